@@ -0,0 +1,81 @@
+//! Analyses over lap files, as JSON.
+
+use anyhow::{bail, Result};
+use clap::{Args, ValueEnum};
+use serde_json::Value;
+use std::path::PathBuf;
+
+use analysis::compare::{compare_laps, head_to_head, CompareOptions};
+use model::Lap;
+
+use crate::files::{find, Input};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Analysis {
+    /// Lap time statistics overall and per stint, and car or track changes.
+    Stats,
+    /// Best, worst and average lap and consistency.
+    Summary,
+    /// Every lap against a reference lap on a distance grid.
+    Compare,
+    /// Two laps against each other: sectors, corners, top speeds and inputs.
+    HeadToHead,
+    /// Per-corner metrics of each lap.
+    Corners,
+}
+
+#[derive(Args)]
+pub struct AnalyzeArgs {
+    analysis: Analysis,
+    #[command(flatten)]
+    input: Input,
+    /// Only this lap (its id or the start of it); repeat for several.
+    #[arg(long = "lap")]
+    laps: Vec<String>,
+    /// Reference lap for `compare`; built from the compared laps when left
+    /// out.
+    #[arg(long)]
+    reference: Option<String>,
+    /// Grid spacing of `compare`, in metres.
+    #[arg(long, default_value_t = 1.0)]
+    step: f64,
+    /// Decimate the series of `compare` to this many points, for charting.
+    #[arg(long)]
+    max_points: Option<usize>,
+    /// Write the JSON to this file instead of printing it.
+    #[arg(long, short)]
+    out: Option<PathBuf>,
+}
+
+pub fn run(args: AnalyzeArgs) -> Result<()> {
+    let all = args.input.load()?;
+    let laps: Vec<Lap> = if args.laps.is_empty() {
+        all.clone()
+    } else {
+        args.laps.iter().map(|id| find(&all, id).cloned()).collect::<Result<_>>()?
+    };
+    let result = match args.analysis {
+        Analysis::Stats => serde_json::to_value(analysis::stats::session_stats(&laps))?,
+        Analysis::Summary => serde_json::to_value(analysis::session_summary(&laps))?,
+        Analysis::Compare => {
+            let reference = args.reference.as_deref().map(|id| find(&all, id).map(|l| l.id)).transpose()?;
+            let opts = CompareOptions { reference, step_m: args.step, max_points: args.max_points, ..Default::default() };
+            serde_json::to_value(compare_laps(&laps, &all, &opts)?)?
+        }
+        Analysis::HeadToHead => {
+            let [a, b] = laps.as_slice() else {
+                bail!("head-to-head compares two laps; pick them with --lap");
+            };
+            serde_json::to_value(head_to_head(a, b))?
+        }
+        Analysis::Corners => Value::Array(
+            laps.iter().map(|l| serde_json::to_value(analysis::corner_metrics(l))).collect::<Result<_, _>>()?,
+        ),
+    };
+    let text = serde_json::to_string_pretty(&result)?;
+    match &args.out {
+        Some(path) => std::fs::write(path, text)?,
+        None => println!("{text}"),
+    }
+    Ok(())
+}
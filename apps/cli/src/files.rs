@@ -0,0 +1,178 @@
+//! Lap files in and out: every format the importers read, written back out
+//! in any export format.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use iox::export::{export_laps, ExportFormat, ExportOptions};
+use iox::import::{dedupe, import_files, CancelHandle, ImportFormat, ImportOptions, KnownLaps};
+use model::Lap;
+
+#[derive(Args)]
+pub struct Input {
+    /// Lap files, in any format Delta imports, or folders of them.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+    /// Format of the files; guessed from each file when left out.
+    #[arg(long, value_parser = snake_case::<ImportFormat>)]
+    pub from: Option<ImportFormat>,
+}
+
+impl Input {
+    /// Laps of every file, in file order, without duplicates. The files are
+    /// read in parallel.
+    pub fn load(&self) -> Result<Vec<Lap>> {
+        let imported = import_files(&self.files, self.from, &ImportOptions::default(), &CancelHandle::default(), |_, _| {})?;
+        if let Some(failed) = imported.files.iter().find(|f| f.error.is_some()) {
+            bail!("importing {}: {}", failed.path.display(), failed.error.as_deref().unwrap_or_default());
+        }
+        let (laps, report) = dedupe(&mut KnownLaps::default(), imported.laps);
+        if report.skipped > 0 {
+            eprintln!("skipped {} duplicate laps", report.skipped);
+        }
+        if laps.is_empty() {
+            bail!("no laps in the given files");
+        }
+        Ok(laps)
+    }
+}
+
+#[derive(Args)]
+pub struct LapsArgs {
+    #[command(flatten)]
+    input: Input,
+    /// Print JSON instead of a table.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct LapRow<'a> {
+    id: Uuid,
+    game: &'a str,
+    car: &'a str,
+    track: &'a str,
+    lap_number: u32,
+    time_ms: u64,
+    points: usize,
+    markers: usize,
+}
+
+pub fn laps(args: LapsArgs) -> Result<()> {
+    let laps = args.input.load()?;
+    let rows: Vec<LapRow> = laps
+        .iter()
+        .map(|l| LapRow {
+            id: l.id,
+            game: &l.meta.game,
+            car: &l.meta.car,
+            track: &l.meta.track,
+            lap_number: l.meta.lap_number,
+            time_ms: l.total_time_ms,
+            points: l.points.len(),
+            markers: l.markers.len(),
+        })
+        .collect();
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    println!("{:<36}  {:<10}  {:<20}  {:<20}  {:>4}  {:>10}  {:>7}", "id", "game", "track", "car", "lap", "time", "markers");
+    for r in rows {
+        println!(
+            "{:<36}  {:<10}  {:<20}  {:<20}  {:>4}  {:>10}  {:>7}",
+            r.id,
+            r.game,
+            r.track,
+            r.car,
+            r.lap_number,
+            fmt_ms(r.time_ms),
+            r.markers
+        );
+    }
+    Ok(())
+}
+
+#[derive(Args)]
+pub struct ConvertArgs {
+    #[command(flatten)]
+    input: Input,
+    /// File to write.
+    #[arg(long, short)]
+    out: PathBuf,
+    /// Format to write (`csv`, `motec_ld`, `parquet`, ...); guessed from the
+    /// extension of `--out` when left out, and the default lap file format
+    /// (`binary`) when it has none.
+    #[arg(long, value_parser = snake_case::<ExportFormat>)]
+    format: Option<ExportFormat>,
+    /// Only this lap (its id or the start of it); repeat for several, in
+    /// the order written.
+    #[arg(long = "lap")]
+    laps: Vec<String>,
+    /// Channels for the tabular formats, e.g. `speed_kph,throttle,brake`.
+    #[arg(long, value_delimiter = ',')]
+    channels: Option<Vec<String>>,
+    /// Driver recorded in MoTeC headers and the metadata sidecar.
+    #[arg(long, default_value = "")]
+    driver: String,
+}
+
+pub fn convert(args: ConvertArgs) -> Result<()> {
+    let laps = args.input.load()?;
+    let format = match args.format {
+        Some(f) => f,
+        None => export_format(&args.out)?,
+    };
+    let lap_ids = if args.laps.is_empty() {
+        None
+    } else {
+        Some(args.laps.iter().map(|id| find(&laps, id).map(|l| l.id)).collect::<Result<_>>()?)
+    };
+    let opts = ExportOptions { lap_ids, channels: args.channels, driver: args.driver, ..Default::default() };
+    let n = export_laps(&laps, &args.out, format, &opts).with_context(|| format!("writing {}", args.out.display()))?;
+    eprintln!("wrote {n} laps to {}", args.out.display());
+    Ok(())
+}
+
+fn export_format(path: &Path) -> Result<ExportFormat> {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    Ok(match ext.as_str() {
+        "" => ExportFormat::Binary,
+        "csv" => ExportFormat::Csv,
+        "ndjson" | "jsonl" => ExportFormat::Ndjson,
+        iox::binary::EXTENSION => ExportFormat::Binary,
+        iox::bundle::EXTENSION => ExportFormat::Session,
+        "ld" => ExportFormat::MotecLd,
+        "vbo" => ExportFormat::Vbo,
+        "gpx" => ExportFormat::Gpx,
+        "kml" => ExportFormat::Kml,
+        "arrow" | "ipc" | "feather" => ExportFormat::ArrowIpc,
+        "parquet" => ExportFormat::Parquet,
+        "h5" | "hdf5" => ExportFormat::Hdf5,
+        _ => bail!("can't tell the format to write from `{}`; pass --format", path.display()),
+    })
+}
+
+/// The lap of `laps` whose id is or starts with `id`.
+pub fn find<'a>(laps: &'a [Lap], id: &str) -> Result<&'a Lap> {
+    let id = id.to_ascii_lowercase();
+    let mut found = laps.iter().filter(|l| l.id.to_string().starts_with(&id) || l.id.simple().to_string().starts_with(&id));
+    match (found.next(), found.next()) {
+        (Some(lap), None) => Ok(lap),
+        (Some(_), Some(_)) => bail!("more than one lap starts with `{id}`; give more of the id"),
+        (None, _) => bail!("no lap `{id}` in the given files"),
+    }
+}
+
+/// Parse a value by its serde name, e.g. `motec_csv`, the names the app and
+/// its API use.
+fn snake_case<T: DeserializeOwned>(s: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(s.into())).map_err(|_| format!("unknown value `{s}`"))
+}
+
+pub fn fmt_ms(ms: u64) -> String {
+    format!("{}:{:06.3}", ms / 60_000, (ms % 60_000) as f64 / 1000.0)
+}
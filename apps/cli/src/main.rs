@@ -0,0 +1,53 @@
+//! Headless Delta: capture a connector to disk, convert lap files and run
+//! analyses without the desktop app, e.g. on a dedicated box capturing every
+//! driver at a league event.
+//!
+//! ```text
+//! delta-cli record f1 --laps laps/ --samples raw.ndjson
+//! delta-cli laps laps/*/*/*.dlap
+//! delta-cli convert laps/spa/2025-06-01/f1_2025-190412.dlap --out spa.ld
+//! delta-cli analyze stats laps/spa/2025-06-01/*.dlap --out stats.json
+//! ```
+
+use clap::{Parser, Subcommand};
+use std::process::ExitCode;
+
+mod analyze;
+mod files;
+mod record;
+
+#[derive(Parser)]
+#[command(name = "delta-cli", version, about = "Capture, convert and analyse telemetry without the desktop app")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Capture a connector until Ctrl-C, saving each lap as it completes.
+    Record(record::RecordArgs),
+    /// List the laps in files.
+    Laps(files::LapsArgs),
+    /// Write laps of any importable format in another format.
+    Convert(files::ConvertArgs),
+    /// Run an analysis over laps and print or write the result as JSON.
+    Analyze(analyze::AnalyzeArgs),
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Record(args) => record::run(args).await,
+        Command::Laps(args) => files::laps(args),
+        Command::Convert(args) => files::convert(args),
+        Command::Analyze(args) => analyze::run(args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
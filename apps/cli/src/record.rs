@@ -0,0 +1,191 @@
+//! Capture from a connector straight to disk: each completed lap saved as it
+//! finishes, the way the app autosaves, and optionally every raw sample.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, ValueEnum};
+use std::{collections::HashMap, future, path::PathBuf, sync::Arc, time::Duration};
+
+use delta_ingest_core::port;
+use delta_ingest_core::record::{RecordFormat, SampleRecorder};
+use delta_ingest_core::{channel, TelemetryRx, TelemetrySource};
+use delta_ingest_f1::{F1Config, F1Source};
+use delta_ingest_gt7::{GT7Config, GT7Source};
+use delta_ingest_remote::{RemoteConfig, RemoteSource};
+use iox::autosave::{Autosave, AutosaveOptions};
+use iox::build::{LapBuilder, ThinOptions};
+
+use crate::files::fmt_ms;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Source {
+    F1,
+    Gt7,
+    /// Le Mans Ultimate; Windows only.
+    Lmu,
+    /// A driver's instance relaying its session.
+    Remote,
+}
+
+#[derive(Args)]
+pub struct RecordArgs {
+    source: Source,
+    /// Port to receive on; the connector's usual one when left out.
+    #[arg(long)]
+    port: Option<u16>,
+    /// Packet format year the F1 game is set to send.
+    #[arg(long, default_value_t = 2025)]
+    f1_format: u16,
+    /// F1 car to follow by index, e.g. the one being spectated; the
+    /// player's when left out.
+    #[arg(long)]
+    car: Option<u8>,
+    /// Address of the PlayStation, for GT7.
+    #[arg(long)]
+    console: Option<String>,
+    /// GT7 heartbeat packet variant: A, B or ~.
+    #[arg(long, default_value_t = 'A')]
+    variant: char,
+    /// Token the driver's relay presents, for remote.
+    #[arg(long, env = "DELTA_RELAY_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// Folder completed laps are saved to, by track and date.
+    #[arg(long, default_value = "laps")]
+    laps: PathBuf,
+    /// Also log every raw sample to this file: CSV for a `.csv` file,
+    /// NDJSON otherwise. An existing log is continued.
+    #[arg(long)]
+    samples: Option<PathBuf>,
+    /// Stop after this many seconds instead of at Ctrl-C.
+    #[arg(long)]
+    duration: Option<f64>,
+    /// Thin points on straights of the saved laps; corners and braking
+    /// zones keep every point.
+    #[arg(long)]
+    thin: bool,
+}
+
+fn source(args: &RecordArgs) -> Result<Arc<dyn TelemetrySource>> {
+    // the connectors bind in the background; catch a taken port here
+    let check_udp = |addr: &str| port::check_udp(addr).map_err(|c| anyhow!("{}. {}", c.message, c.suggestion));
+    Ok(match args.source {
+        Source::F1 => {
+            if let Some(car) = args.car.filter(|&c| c >= delta_ingest_f1::MAX_CARS) {
+                bail!("car index {car} is out of range; F1 has {} cars", delta_ingest_f1::MAX_CARS);
+            }
+            let cfg = F1Config {
+                bind_addr: format!("0.0.0.0:{}", args.port.unwrap_or(20777)),
+                expected_format: args.f1_format,
+                car_index: args.car,
+            };
+            check_udp(&cfg.bind_addr)?;
+            Arc::new(F1Source::new(cfg))
+        }
+        Source::Gt7 => {
+            let Some(console_ip) = args.console.clone() else {
+                bail!("GT7 sends to whoever asks for it; pass the PlayStation's address with --console");
+            };
+            let cfg = GT7Config {
+                bind_addr: format!("0.0.0.0:{}", args.port.unwrap_or(33740)),
+                console_ip,
+                packet_variant: args.variant,
+            };
+            check_udp(&cfg.bind_addr)?;
+            Arc::new(GT7Source::new(cfg))
+        }
+        #[cfg(windows)]
+        Source::Lmu => Arc::new(delta_ingest_lmu::LMUSource::new()),
+        #[cfg(not(windows))]
+        Source::Lmu => bail!("Le Mans Ultimate telemetry is only available on Windows"),
+        Source::Remote => {
+            let Some(token) = args.token.clone().filter(|t| !t.is_empty()) else {
+                bail!("pass the token shared with the driver with --token or DELTA_RELAY_TOKEN");
+            };
+            let cfg = RemoteConfig {
+                bind_addr: format!("0.0.0.0:{}", args.port.unwrap_or(delta_ingest_remote::DEFAULT_PORT)),
+                token,
+            };
+            std::net::TcpListener::bind(&cfg.bind_addr).with_context(|| format!("binding {}", cfg.bind_addr))?;
+            Arc::new(RemoteSource::new(cfg))
+        }
+    })
+}
+
+pub async fn run(args: RecordArgs) -> Result<()> {
+    let source = source(&args)?;
+    let recorder = match &args.samples {
+        Some(path) => {
+            let csv = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+            let format = if csv { RecordFormat::Csv } else { RecordFormat::Ndjson };
+            Some(SampleRecorder::open(path, format).with_context(|| format!("opening {}", path.display()))?)
+        }
+        None => None,
+    };
+    let autosave = Autosave::new(&AutosaveOptions { root: args.laps.clone() });
+
+    let (tx, rx) = channel();
+    let mut task = tokio::spawn(async move { source.run(tx).await });
+    let thin = args.thin.then(ThinOptions::default);
+    let capture = tokio::task::spawn_blocking(move || capture(rx, recorder, autosave, thin));
+    let stop = async {
+        match args.duration {
+            Some(s) => tokio::time::sleep(Duration::from_secs_f64(s.max(0.0))).await,
+            None => future::pending().await,
+        }
+    };
+    eprintln!("recording; laps go to {}. Ctrl-C to stop", args.laps.display());
+    // aborting the source drops its sender, which ends the capture
+    let stopped = tokio::select! {
+        r = &mut task => r?.err(),
+        _ = tokio::signal::ctrl_c() => None,
+        _ = stop => None,
+    };
+    task.abort();
+    let counts = capture.await?;
+    eprintln!("{} samples, {} laps saved", counts.samples, counts.laps);
+    if let Some(e) = stopped {
+        bail!("the connector stopped: {e}");
+    }
+    if counts.samples == 0 {
+        eprintln!("no telemetry arrived; check that the game sends it to this machine");
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Counts {
+    samples: u64,
+    laps: u64,
+}
+
+/// Build and save laps from `rx` until the connector stops.
+fn capture(rx: TelemetryRx, mut recorder: Option<SampleRecorder>, mut autosave: Autosave, thin: Option<ThinOptions>) -> Counts {
+    // by session and car, so a new session starts laps afresh
+    let mut builders: HashMap<(String, String), LapBuilder> = HashMap::new();
+    let mut counts = Counts::default();
+    for s in rx {
+        counts.samples += 1;
+        if let Some(rec) = &mut recorder {
+            if let Err(e) = rec.write(&s) {
+                eprintln!("logging samples to {} failed, stopping: {e:#}", rec.path().display());
+                recorder = None;
+            }
+        }
+        let b = builders
+            .entry((s.session_uid.clone(), s.car_id.clone()))
+            .or_insert_with(|| {
+                let mut b = LapBuilder::for_sample(&s);
+                b.thin = thin.clone();
+                b
+            });
+        let Some(lap) = b.push(&s) else { continue };
+        match autosave.save_lap(&lap) {
+            Ok(path) => {
+                counts.laps += 1;
+                println!("lap {:>3}  {:>10}  {}", lap.meta.lap_number, fmt_ms(lap.total_time_ms), path.display());
+            }
+            // keep capturing; the next lap may save fine
+            Err(e) => eprintln!("saving lap {} failed: {e:#}", lap.meta.lap_number),
+        }
+    }
+    counts
+}
@@ -1,7 +1,49 @@
 // apps/desktop/src-tauri/src/commands.rs
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
 use uuid::Uuid;
 
+use analysis::compare::{Alignment, CompareOptions, Comparison, LapComparison};
+use analysis::live::LiveDeltaState;
+use analysis::stats::{SessionStats, StatsOptions};
+use delta_ingest_f1::{F1Config, F1Source};
+use delta_ingest_gt7::{DiscoveredConsole, GT7Config, GT7Source};
+use delta_ingest_remote::relay::{RelayOptions, RelayStatus};
+use delta_ingest_remote::{Relay, RemoteConfig, RemoteSource};
+use delta_ingest_core::TelemetrySample;
+use delta_ingest_core::diag::{Diagnosis, SampleProbe};
+use delta_server::discord::{DiscordOptions, Notification};
+use delta_server::influx::{InfluxOptions, InfluxStatus};
+use delta_server::pg::{PgOptions, PgStatus};
+use delta_server::mqtt::MqttOptions;
+use delta_server::nats::{NatsOptions, NatsStatus};
+use delta_server::osc::OscOptions;
+use delta_server::serial::{self as serial_out, SerialOptions, SerialPortInfo};
+use delta_server::{ApiServer, DiscordNotifier, InfluxSink, MqttSink, NatsSink, OscSink, PgSink, SerialSink, TelemetryServer};
+use delta_ingest_core::port::{self, PortConflict};
+use delta_ingest_core::record::{RecordFormat, SampleRecorder};
+use iox::autosave::{Autosave, AutosaveOptions};
+use iox::build::ThinOptions;
+use iox::CsvOptions;
+use iox::export::{ExportFormat, ExportOptions};
+use iox::geo::GeoOrigin;
+use iox::svg::SvgOptions;
+use iox::import::{CancelHandle, FileReport, ImportOptions, ImportReport};
+use iox::stream::Progress;
+use iox::workspace::WorkspaceFile;
+use model::{content_hash, ComparisonSet, Lap, LapMarker, LapMeta};
+use storage::{LapQuery, StoredSession, StoredTrackMap};
+use crate::errors::{self, BackgroundError};
+use crate::session::{ring_for, track_map_of, LapHead, LiveState, SessionLaps, SourceInfo, SESSION};
+use crate::recent::{self, RecentItem, RecentKind, RecentList};
+use crate::settings::{self, Settings};
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct LapMetaInput {
     pub id: Uuid,
@@ -10,43 +52,1318 @@ pub struct LapMetaInput {
     pub car: String,
     pub lap_number: u32,
     pub time_ms: u64,
+    #[serde(default)]
+    pub out_lap: bool,
+    #[serde(default)]
+    pub in_lap: bool,
+    /// Source session the lap was recorded in; none for imported laps.
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
+    /// Empty when not known.
+    #[serde(default)]
+    pub driver: String,
+}
+
+/// Error of the commands that bind a UDP port. A port conflict keeps its
+/// details so the UI can say who holds the port and how to forward around it.
+#[derive(Debug, Serialize)]
+#[serde(tag = "error", rename_all = "snake_case")]
+pub enum StartError {
+    Failed { message: String },
+    PortConflict(PortConflict),
+}
+
+impl From<String> for StartError {
+    fn from(message: String) -> Self {
+        StartError::Failed { message }
+    }
+}
+
+impl From<&str> for StartError {
+    fn from(message: &str) -> Self {
+        StartError::Failed { message: message.into() }
+    }
+}
+
+impl From<anyhow::Error> for StartError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<PortConflict>() {
+            Ok(conflict) => StartError::PortConflict(conflict),
+            Err(e) => StartError::Failed { message: format!("{e:#}") },
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn start_f1(port: Option<u16>, format: Option<u16>, car_index: Option<u8>) -> Result<(), StartError> {
+    let s = SESSION.inner.lock().settings.clone();
+    let cfg = F1Config {
+        bind_addr: format!("0.0.0.0:{}", port.unwrap_or(s.f1_port)),
+        expected_format: format.unwrap_or(s.f1_format),
+        car_index: car_index.or(s.f1_car_index),
+    };
+    // the connector binds in the background; catch a taken port here
+    port::check_udp(&cfg.bind_addr).map_err(StartError::PortConflict)?;
+    SESSION.start_source("f1", Arc::new(F1Source::new(cfg)));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn start_gt7(console_ip: Option<String>, variant: Option<String>, bind_port: Option<u16>) -> Result<(), StartError> {
+    let s = SESSION.inner.lock().settings.clone();
+    let console_ip = console_ip.unwrap_or(s.gt7_console_ip);
+    if console_ip.is_empty() {
+        return Err("no PlayStation address set; enter it in settings".into());
+    }
+    let cfg = GT7Config {
+        bind_addr: format!("0.0.0.0:{}", bind_port.unwrap_or(s.gt7_bind_port)),
+        console_ip,
+        packet_variant: variant.unwrap_or(s.gt7_variant).chars().next().unwrap_or('A'),
+    };
+    port::check_udp(&cfg.bind_addr).map_err(StartError::PortConflict)?;
+    SESSION.start_source("gt7", Arc::new(GT7Source::new(cfg)));
+    Ok(())
+}
+
+/// Take in a driver's session relayed from their instance, listening for
+/// their relay on `port` with `token` (the saved ones by default).
+#[tauri::command]
+pub async fn start_remote(port: Option<u16>, token: Option<String>) -> Result<(), StartError> {
+    let s = SESSION.inner.lock().settings.clone();
+    let token = token.unwrap_or(s.remote_token);
+    if token.is_empty() {
+        return Err("no relay token set; create one in settings and share it with the driver".into());
+    }
+    let cfg = RemoteConfig { bind_addr: format!("0.0.0.0:{}", port.unwrap_or(s.remote_port)), token };
+    // the connector binds in the background; catch a taken port here
+    std::net::TcpListener::bind(&cfg.bind_addr).map_err(|e| format!("binding {} failed: {e}", cfg.bind_addr))?;
+    SESSION.start_source("remote", Arc::new(RemoteSource::new(cfg)));
+    Ok(())
+}
+
+/// A fresh random token for relaying, to share with the coach or driver.
+#[tauri::command]
+pub async fn new_relay_token() -> Result<String, String> {
+    Ok(Uuid::new_v4().simple().to_string())
+}
+
+/// GT7 consoles on the local network, found by broadcasting a heartbeat and
+/// listening on the GT7 bind port for `timeout_ms` (default 2 s). Fails while
+/// GT7 telemetry is running, as that holds the port.
+#[tauri::command]
+pub async fn discover_consoles(timeout_ms: Option<u64>) -> Result<Vec<DiscoveredConsole>, StartError> {
+    if SESSION.source_keys().iter().any(|k| k == "gt7") {
+        return Err("stop GT7 telemetry before searching for consoles; it holds the port".into());
+    }
+    let port = SESSION.inner.lock().settings.gt7_bind_port;
+    Ok(delta_ingest_gt7::discover_consoles(port, Duration::from_millis(timeout_ms.unwrap_or(2000))).await?)
+}
+
+#[tauri::command]
+pub async fn start_lmu() -> Result<(), String> {
+    // the shared-memory connector only exists on Windows
+    #[cfg(windows)]
+    {
+        SESSION.start_source("lmu", Arc::new(delta_ingest_lmu::LMUSource::new()));
+        Ok(())
+    }
+    #[cfg(not(windows))]
+    Err("Le Mans Ultimate telemetry is only available on Windows".into())
+}
+
+#[tauri::command]
+pub async fn stop_all() -> Result<(), String> {
+    SESSION.stop_all();
+    Ok(())
+}
+
+/// Stop building laps from source `key`, or from every running source, while
+/// the driver is in the garage or menus. The source keeps its session and
+/// the lap in progress is dropped. Returns the keys paused.
+#[tauri::command]
+pub async fn pause_recording(key: Option<String>) -> Result<Vec<String>, String> {
+    let keys = key.map(|k| vec![k]).unwrap_or_else(|| SESSION.source_keys());
+    let mut inner = SESSION.inner.lock();
+    Ok(keys.into_iter().filter(|k| inner.pause(k)).collect())
+}
+
+/// Build laps from source `key`, or every paused source, again. The lap the
+/// car is on when resuming is partial and not kept. Returns the keys resumed.
+#[tauri::command]
+pub async fn resume_recording(key: Option<String>) -> Result<Vec<String>, String> {
+    let mut inner = SESSION.inner.lock();
+    let keys = key.map(|k| vec![k]).unwrap_or_else(|| inner.paused.iter().cloned().collect());
+    Ok(keys.into_iter().filter(|k| inner.resume(k)).collect())
+}
+
+/// Watch source `key` for `seconds` (default 5) and report packet and sample
+/// rates, how many packets parse, which channels carry data and which
+/// samples fail sanity checks.
+#[tauri::command]
+pub async fn diagnose_source(key: String, seconds: Option<f64>) -> Result<Diagnosis, String> {
+    if !SESSION.source_keys().contains(&key) {
+        return Err(format!("source {key} is not running"));
+    }
+    let counters = SESSION.ingest_counters(&key);
+    let before = counters.as_ref().map(|c| c.snapshot());
+    SESSION.inner.lock().probes.insert(key.clone(), SampleProbe::new());
+    tokio::time::sleep(Duration::from_secs_f64(seconds.unwrap_or(5.0).clamp(0.5, 60.0))).await;
+    let probe = SESSION.inner.lock().probes.remove(&key).ok_or_else(|| format!("source {key} stopped while diagnosing"))?;
+    let counts = counters.zip(before).map(|(c, before)| c.snapshot().since(&before));
+    Ok(probe.report(counts))
+}
+
+/// Stop one connector (`f1`, `gt7`, `lmu`, `remote`), leaving the others running.
+#[tauri::command]
+pub async fn stop_source(key: String) -> Result<(), String> {
+    if SESSION.stop_source(&key) { Ok(()) } else { Err(format!("source {key} is not running")) }
+}
+
+/// Stop and start one connector again with the settings it was started with.
+#[tauri::command]
+pub async fn restart_source(key: String) -> Result<(), String> {
+    if SESSION.restart_source(&key) { Ok(()) } else { Err(format!("source {key} is not running")) }
+}
+
+/// Follow car `index` of the F1 session from now on, e.g. the one being
+/// spectated; the player's when left out. The lap in progress is dropped.
+#[tauri::command]
+pub async fn follow_f1_car(index: Option<u8>) -> Result<(), String> {
+    if let Some(i) = index.filter(|&i| i >= delta_ingest_f1::MAX_CARS) {
+        return Err(format!("car index {i} is out of range; F1 has {} cars", delta_ingest_f1::MAX_CARS));
+    }
+    if SESSION.follow_car("f1", index) { Ok(()) } else { Err("source f1 is not running".into()) }
+}
+
+/// Keys of the running connectors.
+#[tauri::command]
+pub async fn list_sources() -> Result<Vec<SourceInfo>, String> {
+    Ok(SESSION.sources())
 }
 
+/// Recorded sessions in the lap database, newest first.
 #[tauri::command]
-pub async fn start_f1() -> Result<(), String> { Ok(()) }
+pub async fn list_sessions() -> Result<Vec<StoredSession>, String> {
+    let inner = SESSION.inner.lock();
+    let store = inner.store.as_ref().ok_or("lap database unavailable; sessions are only kept there")?;
+    store.sessions().map_err(|e| format!("{e:#}"))
+}
 
+/// Laps in the session, fastest first; only those recorded in source session
+/// `session_id` when given, which also counts as opening that session.
 #[tauri::command]
-pub async fn start_gt7() -> Result<(), String> { Ok(()) }
+pub async fn list_laps(session_id: Option<Uuid>) -> Result<Vec<LapMetaInput>, String> {
+    let mut inner = SESSION.inner.lock();
+    if let Some(id) = session_id {
+        let name = inner.store.as_ref()
+            .and_then(|s| s.sessions().ok())
+            .and_then(|all| all.into_iter().find(|s| s.id == id))
+            .map(|s| s.name)
+            .unwrap_or_else(|| id.simple().to_string());
+        inner.remember_session(id, &name);
+    }
+    let in_session = |l: &LapHead| session_id.is_none() || inner.lap_sessions.get(&l.id) == session_id.as_ref();
+    let mut laps: Vec<LapMetaInput> = inner.lap_heads().filter(in_session).map(|l| LapMetaInput {
+        id: l.id,
+        game: l.meta.game.clone(),
+        track: l.meta.track.clone(),
+        car: l.meta.car.clone(),
+        lap_number: l.meta.lap_number,
+        time_ms: l.total_time_ms,
+        out_lap: l.meta.out_lap,
+        in_lap: l.meta.in_lap,
+        session_id: inner.lap_sessions.get(&l.id).copied(),
+        driver: inner.lap_drivers.get(&l.id).cloned().unwrap_or_default(),
+    }).collect();
+    laps.sort_by_key(|l| l.time_ms);
+    Ok(laps)
+}
 
+/// Lap count, lap time statistics overall and per stint, and car or track
+/// changes of source session `session_id`. Out-laps and in-laps only count
+/// towards the lap times with `include_out_in`.
 #[tauri::command]
-pub async fn start_lmu() -> Result<(), String> { Ok(()) }
+pub async fn session_stats(session_id: Uuid, include_out_in: Option<bool>) -> Result<SessionStats, String> {
+    let laps = {
+        let mut inner = SESSION.inner.lock();
+        let ids: Vec<Uuid> = inner
+            .lap_heads()
+            .filter(|l| inner.lap_sessions.get(&l.id) == Some(&session_id))
+            .map(|l| l.id)
+            .collect();
+        let mut laps = inner.load_laps(Some(&ids))?;
+        // in the order they were driven: recording time where the database
+        // has it, lap number otherwise
+        let recorded: HashMap<Uuid, i64> = inner
+            .store
+            .as_ref()
+            .and_then(|s| s.query(&LapQuery { session_id: Some(session_id), ..Default::default() }).ok())
+            .map(|rows| rows.into_iter().map(|r| (r.id, r.recorded_at)).collect())
+            .unwrap_or_default();
+        laps.sort_by_key(|l| (recorded.get(&l.id).copied().unwrap_or(i64::MAX), l.meta.lap_number));
+        laps
+    };
+    if laps.is_empty() {
+        return Err(format!("session {session_id} has no laps"));
+    }
+    let opts = StatsOptions { include_out_in_laps: include_out_in.unwrap_or(false) };
+    Ok(analysis::stats::session_stats_with(&laps, &opts))
+}
 
+/// Laps cut short by a crash and recovered from the journal at startup.
 #[tauri::command]
-pub async fn stop_all() -> Result<(), String> { Ok(()) }
+pub async fn recovered_laps() -> Result<Vec<Uuid>, String> {
+    Ok(SESSION.inner.lock().recovered.clone())
+}
 
+/// Failures of background work (saving laps, publishing, journaling) so
+/// far, oldest first. New ones arrive as `app://error` events.
 #[tauri::command]
-pub async fn list_laps() -> Result<Vec<LapMetaInput>, String> {
-    Ok(Vec::new())
+pub async fn background_errors() -> Result<Vec<BackgroundError>, String> {
+    Ok(errors::recent())
+}
+
+/// Remove laps from the session and the lap database; returns how many
+/// were found.
+#[tauri::command]
+pub async fn delete_laps(ids: Vec<Uuid>) -> Result<usize, String> {
+    let mut inner = SESSION.inner.lock();
+    // a lap still queued for the database would come back after the delete
+    inner.flush_writes();
+    let mut removed = 0;
+    for id in ids {
+        let mut found = inner.laps.remove(&id).is_some() | inner.stored.remove(&id).is_some();
+        inner.known.remove(id);
+        inner.lap_sessions.remove(&id);
+        inner.lap_drivers.remove(&id);
+        if let Some(store) = &inner.store {
+            found |= store.delete_lap(id).map_err(|e| format!("{e:#}"))?;
+        }
+        removed += found as usize;
+    }
+    Ok(removed)
+}
+
+/// Fields of a lap to change; unset ones are left as they are.
+#[derive(Debug, Default, Deserialize)]
+pub struct LapEdit {
+    pub car: Option<String>,
+    pub track: Option<String>,
+    pub lap_number: Option<u32>,
+    pub driver: Option<String>,
 }
 
+/// Correct a lap's metadata in the session and the lap database.
 #[tauri::command]
-pub async fn analyze_laps(_ids: Vec<Uuid>) -> Result<String, String> {
-    Ok("analysis-ok".into())
+pub async fn edit_lap(lap_id: Uuid, edit: LapEdit) -> Result<LapMetaInput, String> {
+    let mut guard = SESSION.inner.lock();
+    let inner = &mut *guard;
+    let apply = |meta: &mut LapMeta| {
+        if let Some(car) = edit.car { meta.car = car; }
+        if let Some(track) = edit.track { meta.track = track; }
+        if let Some(n) = edit.lap_number { meta.lap_number = n; }
+    };
+    // the content hash covers car and track
+    inner.known.remove(lap_id);
+    let (meta, total_time_ms) = if let Some(lap) = inner.laps.get_mut(&lap_id) {
+        // copies the lap only while an analysis still holds it
+        let lap = Arc::make_mut(lap);
+        apply(&mut lap.meta);
+        inner.known.insert(lap);
+        (lap.meta.clone(), lap.total_time_ms)
+    } else if let Some(l) = inner.stored.get_mut(&lap_id) {
+        apply(&mut l.meta);
+        inner.known.insert_hashed(lap_id, content_hash(&l.meta, l.samples_hash));
+        (l.meta.clone(), l.total_time_ms)
+    } else {
+        return Err(format!("lap {lap_id} not found"));
+    };
+    inner.flush_writes();
+    if let Some(store) = &inner.store {
+        store.update_meta(lap_id, &meta).map_err(|e| format!("{e:#}"))?;
+        if let Some(driver) = &edit.driver {
+            store.set_driver(lap_id, driver).map_err(|e| format!("{e:#}"))?;
+        }
+    } else if edit.driver.is_some() {
+        return Err("lap database unavailable; driver names are only kept there".into());
+    }
+    if let Some(driver) = edit.driver {
+        if let Some(l) = inner.stored.get_mut(&lap_id) {
+            l.driver = driver.clone();
+        }
+        if driver.is_empty() {
+            inner.lap_drivers.remove(&lap_id);
+        } else {
+            inner.lap_drivers.insert(lap_id, driver);
+        }
+    }
+    Ok(LapMetaInput {
+        id: lap_id,
+        lap_number: meta.lap_number,
+        time_ms: total_time_ms,
+        out_lap: meta.out_lap,
+        in_lap: meta.in_lap,
+        session_id: inner.lap_sessions.get(&lap_id).copied(),
+        driver: inner.lap_drivers.get(&lap_id).cloned().unwrap_or_default(),
+        game: meta.game,
+        track: meta.track,
+        car: meta.car,
+    })
 }
 
+/// Replace the tags of a lap.
 #[tauri::command]
-pub async fn build_track_map(_track: String) -> Result<String, String> {
-    Ok("track-map-ok".into())
+pub async fn set_lap_tags(lap_id: Uuid, tags: Vec<String>) -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.flush_writes();
+    let store = inner.store.as_mut().ok_or("lap database unavailable; tags are only kept there")?;
+    let mut tags: Vec<String> = tags.into_iter().map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+    tags.sort();
+    tags.dedup();
+    store.set_tags(lap_id, &tags).map_err(|e| format!("{e:#}"))
 }
 
 #[tauri::command]
-pub async fn import_file(_path: String) -> Result<String, String> {
-    Ok("import-ok".into())
+pub async fn lap_tags(lap_id: Uuid) -> Result<Vec<String>, String> {
+    let inner = SESSION.inner.lock();
+    match &inner.store {
+        Some(store) => store.tags(lap_id).map_err(|e| format!("{e:#}")),
+        None => Ok(Vec::new()),
+    }
 }
 
+/// Running delta to best and predicted lap time, keyed by source.
 #[tauri::command]
-pub async fn export_file(_dest: String) -> Result<String, String> {
-    Ok("export-ok".into())
+pub async fn live_delta() -> Result<HashMap<String, LiveDeltaState>, String> {
+    Ok(SESSION.inner.lock().live_deltas())
+}
+
+/// Latest sample and running lap of every source: current lap time, last
+/// lap, delta to best. The same data arrives as `telemetry://sample` events.
+#[tauri::command]
+pub async fn live_state() -> Result<Vec<LiveState>, String> {
+    Ok(SESSION.inner.lock().live_states())
+}
+
+/// Samples of source `key` from the last `seconds` (60 s when `None`, which
+/// is also the most kept), oldest first, for live strip charts.
+#[tauri::command]
+pub async fn live_history(key: String, seconds: Option<f64>) -> Result<Vec<TelemetrySample>, String> {
+    let seconds = seconds.unwrap_or(60.0);
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(format!("invalid span {seconds}"));
+    }
+    Ok(SESSION.inner.lock().live_history(&key, Duration::from_secs_f64(seconds)))
+}
+
+/// `telemetry://sample` events per second and source (0 turns them off).
+/// `telemetry://lap-completed` is always sent.
+#[tauri::command]
+pub async fn set_live_rate(hz: f64) -> Result<(), String> {
+    if !hz.is_finite() || hz < 0.0 {
+        return Err(format!("invalid rate {hz}"));
+    }
+    SESSION.inner.lock().sample_hz = hz;
+    Ok(())
+}
+
+/// Spins, lockups and crashes detected in a completed lap.
+#[tauri::command]
+pub async fn lap_markers(lap_id: Uuid) -> Result<Vec<LapMarker>, String> {
+    let lap = SESSION.inner.lock().lap(lap_id)?;
+    lap.map(|l| l.markers.clone()).ok_or_else(|| format!("lap {lap_id} not found"))
+}
+
+#[tauri::command]
+pub async fn analyze_laps(
+    lap_ids: Vec<Uuid>,
+    reference_id: Option<Uuid>,
+    alignment: Option<Alignment>,
+    step_m: Option<f64>,
+    max_points: Option<usize>,
+) -> Result<Comparison, String> {
+    // share out so the comparison runs without holding the session lock
+    let (laps, reference) = {
+        let mut inner = SESSION.inner.lock();
+        // no ids: compare the whole session, as before
+        let laps = if lap_ids.is_empty() {
+            inner.load_laps(None)?
+        } else {
+            lap_ids
+                .iter()
+                .map(|&id| inner.lap(id)?.ok_or_else(|| format!("lap {id} not found")))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        // the reference may be a lap outside the comparison
+        let reference: Vec<Arc<Lap>> = match reference_id {
+            Some(id) => inner.lap(id)?.into_iter().collect(),
+            None => Vec::new(),
+        };
+        (laps, reference)
+    };
+    let d = CompareOptions::default();
+    let opts = CompareOptions {
+        reference: reference_id,
+        alignment: alignment.unwrap_or(d.alignment),
+        step_m: step_m.unwrap_or(d.step_m),
+        max_points,
+        ..d
+    };
+    tauri::async_runtime::spawn_blocking(move || analysis::compare::compare_laps(&laps, &reference, &opts))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Lap `b` against lap `a`: sector and corner deltas, top speeds and how
+/// each was driven.
+#[tauri::command]
+pub async fn compare_laps(a: Uuid, b: Uuid) -> Result<LapComparison, String> {
+    let (a, b) = {
+        let mut inner = SESSION.inner.lock();
+        let mut get = |id: Uuid| inner.lap(id)?.ok_or_else(|| format!("lap {id} not found"));
+        (get(a)?, get(b)?)
+    };
+    tauri::async_runtime::spawn_blocking(move || analysis::compare::head_to_head(&a, &b))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Cached map of `track` (in `game` when given), built from the best clean
+/// lap on first use and refined as faster clean laps come in.
+#[tauri::command]
+pub async fn get_track_map(track: String, game: Option<String>) -> Result<Option<StoredTrackMap>, String> {
+    Ok(SESSION.inner.lock().track_map(game.as_deref(), &track))
+}
+
+/// Drop the cached map of `track` and build it again from the best clean
+/// lap in the session.
+#[tauri::command]
+pub async fn build_track_map(track: String, game: Option<String>) -> Result<Option<StoredTrackMap>, String> {
+    let mut inner = SESSION.inner.lock();
+    inner.track_maps.retain(|(g, t), _| !(*t == track && game.as_ref().is_none_or(|game| g == game)));
+    Ok(inner.track_map(game.as_deref(), &track))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProgress {
+    pub path: String,
+    #[serde(flatten)]
+    pub progress: Progress,
+}
+
+/// Import every lap of `path` into the session, skipping laps whose content
+/// is already there. Emits `import-progress` events while reading; stopped by
+/// [`cancel_import`].
+#[tauri::command]
+pub async fn import_file(app: AppHandle, path: String) -> Result<ImportReport, String> {
+    let cancel = CancelHandle::default();
+    let keys = begin_import(std::slice::from_ref(&path), &cancel)?;
+    let p = path.clone();
+    let laps = tauri::async_runtime::spawn_blocking(move || {
+        iox::import::import_laps_with_progress(Path::new(&p), None, &ImportOptions::default(), &cancel, |progress| {
+            let _ = app.emit("import-progress", ImportProgress { path: p.clone(), progress });
+        })
+    })
+    .await;
+    SESSION.inner.lock().imports.retain(|k, _| !keys.contains(k));
+    let laps = laps.map_err(|e| e.to_string())?.map_err(|e| format!("{e:#}"))?;
+    let report = add_laps(laps).await?;
+    SESSION.inner.lock().remember_file(RecentKind::Import, &path);
+    Ok(report)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilesImport {
+    #[serde(flatten)]
+    pub report: ImportReport,
+    pub files: Vec<FileReport>,
+}
+
+/// Import `paths` in parallel, folders included, like [`import_file`]: a
+/// file that fails is reported with its error and the others are still
+/// added. Cancelling any of the paths stops them all.
+#[tauri::command]
+pub async fn import_files(app: AppHandle, paths: Vec<String>) -> Result<FilesImport, String> {
+    let cancel = CancelHandle::default();
+    let keys = begin_import(&paths, &cancel)?;
+    let files: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+    let imported = tauri::async_runtime::spawn_blocking(move || {
+        iox::import::import_files(&files, None, &ImportOptions::default(), &cancel, |path, progress| {
+            let _ = app.emit("import-progress", ImportProgress { path: path.display().to_string(), progress });
+        })
+    })
+    .await;
+    SESSION.inner.lock().imports.retain(|k, _| !keys.contains(k));
+    let imported = imported.map_err(|e| e.to_string())?.map_err(|e| format!("{e:#}"))?;
+    let report = add_laps(imported.laps).await?;
+    let mut inner = SESSION.inner.lock();
+    for path in &paths {
+        inner.remember_file(RecentKind::Import, path);
+    }
+    Ok(FilesImport { report, files: imported.files })
+}
+
+/// Rebuild laps from a pcap/pcapng capture of F1 UDP traffic to `port`
+/// (20777 by default) and add those not already in the session.
+#[tauri::command]
+pub async fn import_f1_capture(path: String, port: Option<u16>) -> Result<ImportReport, String> {
+    let p = path.clone();
+    let laps = tauri::async_runtime::spawn_blocking(move || {
+        delta_ingest_f1::pcap::read_capture(Path::new(&p), Some(port.unwrap_or(20777)))
+            .map(|samples| iox::build::build_laps(&samples))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("{e:#}"))?;
+    let report = add_laps(laps).await?;
+    SESSION.inner.lock().remember_file(RecentKind::Import, &path);
+    Ok(report)
+}
+
+/// Add imported laps to the session and lap database, skipping duplicates.
+/// The session lock is held only to pick out and add the new laps; their
+/// track maps are built on a blocking thread and the database writes are
+/// queued to the writer.
+async fn add_laps(laps: Vec<Lap>) -> Result<ImportReport, String> {
+    let (mapped, report) = {
+        let mut inner = SESSION.inner.lock();
+        let (fresh, report) = iox::import::dedupe(&mut inner.known, laps);
+        let mut mapped: HashMap<(String, String), Arc<Lap>> = HashMap::new();
+        for lap in fresh {
+            let lap = Arc::new(lap);
+            if let Some(writer) = &inner.writer {
+                writer.save_lap(lap.clone(), None);
+            }
+            // only the fastest new lap of each track can give it a better map
+            if inner.improves_track_map(&lap) {
+                let k = (lap.meta.game.clone(), lap.meta.track.clone());
+                if mapped.get(&k).is_none_or(|m| lap.total_time_ms < m.total_time_ms) {
+                    mapped.insert(k, lap.clone());
+                }
+            }
+            inner.laps.insert(lap.id, lap);
+        }
+        (mapped, report)
+    };
+    if !mapped.is_empty() {
+        let maps = tauri::async_runtime::spawn_blocking(move || mapped.values().map(|l| track_map_of(l)).collect::<Vec<_>>())
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut inner = SESSION.inner.lock();
+        for m in maps {
+            inner.keep_track_map(m);
+        }
+    }
+    Ok(report)
+}
+
+/// Register an import of `paths` under their content keys, refusing one
+/// whose content is already being imported under any path.
+fn begin_import(paths: &[String], cancel: &CancelHandle) -> Result<Vec<u64>, String> {
+    let keys: Vec<u64> = paths.iter().map(|p| iox::import::content_key(Path::new(p))).collect();
+    let mut inner = SESSION.inner.lock();
+    if let Some(i) = keys.iter().position(|k| inner.imports.contains_key(k)) {
+        return Err(format!("{} is already being imported", paths[i]));
+    }
+    inner.imports.extend(keys.iter().map(|&k| (k, cancel.clone())));
+    Ok(keys)
+}
+
+/// Stop the import of `path` (or of the same file under another path), if
+/// one is running. Its `import_file` call then fails with "import
+/// cancelled" and nothing is added to the session.
+#[tauri::command]
+pub async fn cancel_import(path: String) -> Result<(), String> {
+    let key = iox::import::content_key(Path::new(&path));
+    if let Some(cancel) = SESSION.inner.lock().imports.get(&key) {
+        cancel.cancel();
+    }
+    Ok(())
+}
+
+/// Export session laps (all, or just `lap_ids`), optionally limited to `channels`
+/// and, for CSV, formatted per `csv`. `driver` and `conditions` go into the
+/// metadata sidecar of CSV and MoTeC exports.
+/// Returns the number of laps written.
+#[tauri::command]
+pub async fn export_file(
+    kind: ExportFormat,
+    path: String,
+    lap_ids: Option<Vec<Uuid>>,
+    channels: Option<Vec<String>>,
+    csv: Option<CsvOptions>,
+    driver: Option<String>,
+    conditions: Option<String>,
+) -> Result<usize, String> {
+    let mut laps = SESSION.inner.lock().load_laps(lap_ids.as_deref())?;
+    laps.sort_by_key(|l| (l.meta.track.clone(), l.meta.lap_number));
+    let opts = ExportOptions {
+        lap_ids,
+        channels,
+        csv: csv.unwrap_or_default(),
+        driver: driver.unwrap_or_default(),
+        conditions: conditions.unwrap_or_default(),
+        ..ExportOptions::default()
+    };
+    let p = path.clone();
+    let n = tauri::async_runtime::spawn_blocking(move || iox::export::export_laps(&laps, Path::new(&p), kind, &opts))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("{e:#}"))?;
+    SESSION.inner.lock().remember_file(RecentKind::Export, &path);
+    Ok(n)
+}
+
+/// Save session laps (all, or just `lap_ids`) in the default lap file
+/// format ([`iox::save_laps`]). Returns the number of laps written.
+#[tauri::command]
+pub async fn save_laps(path: String, lap_ids: Option<Vec<Uuid>>) -> Result<usize, String> {
+    let mut laps = SESSION.inner.lock().load_laps(lap_ids.as_deref())?;
+    laps.sort_by_key(|l| (l.meta.track.clone(), l.meta.lap_number));
+    let p = path.clone();
+    let n = tauri::async_runtime::spawn_blocking(move || {
+        let selected: Vec<Lap> = iox::export::select_laps(&laps, lap_ids.as_deref()).into_iter().cloned().collect();
+        if selected.is_empty() {
+            anyhow::bail!("no laps selected to save");
+        }
+        iox::save_laps(&selected, Path::new(&p)).map(|()| selected.len())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("{e:#}"))?;
+    SESSION.inner.lock().remember_file(RecentKind::Export, &path);
+    Ok(n)
+}
+
+/// Overlay, delta and corner files for `lap_ids` (all laps when `None`) in
+/// `dir`, against `reference_id` or the fastest lap. Returns the files written.
+#[tauri::command]
+pub async fn export_analysis(
+    dir: String,
+    lap_ids: Option<Vec<Uuid>>,
+    reference_id: Option<Uuid>,
+) -> Result<Vec<String>, String> {
+    let mut laps = SESSION.inner.lock().load_laps(lap_ids.as_deref())?;
+    laps.sort_by_key(|l| (l.meta.track.clone(), l.meta.lap_number));
+    let laps: Vec<Lap> =
+        iox::export::select_laps(&laps, lap_ids.as_deref()).into_iter().cloned().collect();
+    let written = iox::charts::export_analysis(&laps, reference_id, Path::new(&dir))
+        .map_err(|e| format!("{e:#}"))?;
+    SESSION.inner.lock().remember_file(RecentKind::Export, &dir);
+    Ok(written.into_iter().map(|p| p.display().to_string()).collect())
+}
+
+/// Track map of lap `lap_id` as GeoJSON, placed on the globe at `origin`
+/// (0°N 0°E when `None`).
+#[tauri::command]
+pub async fn export_track_geojson(lap_id: Uuid, path: String, origin: Option<GeoOrigin>) -> Result<(), String> {
+    let lap = SESSION.inner.lock().lap(lap_id)?.ok_or_else(|| format!("lap {lap_id} not found"))?;
+    let map = analysis::build_track_map(&lap);
+    iox::geojson::export_geojson(&map, Path::new(&path), origin.as_ref()).map_err(|e| format!("{e:#}"))?;
+    SESSION.inner.lock().remember_file(RecentKind::Export, &path);
+    Ok(())
+}
+
+/// Track map of lap `lap_id` as SVG, also written to `path` when given.
+#[tauri::command]
+pub async fn render_track_svg(lap_id: Uuid, opts: Option<SvgOptions>, path: Option<String>) -> Result<String, String> {
+    let lap = SESSION.inner.lock().lap(lap_id)?.ok_or_else(|| format!("lap {lap_id} not found"))?;
+    let svg = iox::svg::track_map_svg(&analysis::build_track_map(&lap), Some(&*lap), &opts.unwrap_or_default());
+    if let Some(path) = path {
+        std::fs::write(&path, &svg).map_err(|e| format!("writing {path}: {e}"))?;
+        SESSION.inner.lock().remember_file(RecentKind::Export, &path);
+    }
+    Ok(svg)
+}
+
+/// Start appending every completed lap under `dir` (track/date folders), or
+/// stop when `dir` is `None`.
+#[tauri::command]
+pub async fn set_autosave(dir: Option<String>) -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.autosave = dir.as_ref().map(|d| Autosave::new(&AutosaveOptions { root: PathBuf::from(d) }));
+    inner.settings.autosave_dir = dir;
+    settings::save(&inner.settings).map_err(|e| format!("{e:#}"))
+}
+
+#[tauri::command]
+pub async fn get_settings() -> Result<Settings, String> {
+    Ok(SESSION.inner.lock().settings.clone())
+}
+
+/// Replace and persist the settings. Running connectors keep their settings
+/// until restarted; the autosave folder applies straight away.
+#[tauri::command]
+pub async fn set_settings(settings: Settings) -> Result<(), String> {
+    let (start_server, start_api, start_mqtt_sink, start_nats_sink, start_relay_sink, start_grpc) = {
+        let mut inner = SESSION.inner.lock();
+        if settings.ring_minutes != inner.settings.ring_minutes {
+            inner.ring = ring_for(settings.ring_minutes);
+        }
+        if settings.autosave_dir != inner.settings.autosave_dir {
+            inner.autosave = settings.autosave_dir.as_ref()
+                .map(|d| Autosave::new(&AutosaveOptions { root: PathBuf::from(d) }));
+        }
+        if settings.thin_laps != inner.settings.thin_laps {
+            for b in inner.builders.values_mut() {
+                b.thin = settings.thin_laps.then(ThinOptions::default);
+            }
+        }
+        if settings.lap_completion != inner.settings.lap_completion {
+            for b in inner.builders.values_mut() {
+                b.completion = settings.lap_completion_for(&b.game);
+            }
+        }
+        let start_server = settings.ws_server
+            && (!inner.settings.ws_server || settings.ws_port != inner.settings.ws_port);
+        if !settings.ws_server {
+            inner.server = None;
+        }
+        let start_api = settings.api_server
+            && (!inner.settings.api_server
+                || settings.api_port != inner.settings.api_port
+                || settings.api_lan != inner.settings.api_lan);
+        if !settings.api_server {
+            inner.api = None;
+        }
+        let start_mqtt_sink = settings.mqtt_enabled
+            && (!inner.settings.mqtt_enabled || settings.mqtt != inner.settings.mqtt);
+        if !settings.mqtt_enabled {
+            inner.mqtt = None;
+        }
+        let start_nats_sink = settings.nats_enabled
+            && (!inner.settings.nats_enabled || settings.nats != inner.settings.nats);
+        if !settings.nats_enabled {
+            inner.nats = None;
+        }
+        let start_relay_sink = settings.relay_enabled
+            && (!inner.settings.relay_enabled || settings.relay != inner.settings.relay);
+        if !settings.relay_enabled {
+            inner.relay = None;
+        }
+        if settings.osc != inner.settings.osc || !settings.osc_enabled {
+            inner.osc = None;
+        }
+        if settings.osc_enabled && inner.osc.is_none() {
+            inner.osc = Some(OscSink::new(&settings.osc).map_err(|e| format!("{e:#}"))?);
+        }
+        if settings.serial != inner.settings.serial || !settings.serial_enabled {
+            inner.serial = None;
+        }
+        if settings.serial_enabled && inner.serial.is_none() {
+            inner.serial = Some(SerialSink::open(&settings.serial).map_err(|e| format!("{e:#}"))?);
+        }
+        if settings.discord != inner.settings.discord || !settings.discord_enabled {
+            inner.discord = None;
+        }
+        if settings.discord_enabled && inner.discord.is_none() {
+            inner.discord = Some(DiscordNotifier::new(&settings.discord).map_err(|e| format!("{e:#}"))?);
+        }
+        if settings.influx != inner.settings.influx || !settings.influx_enabled {
+            inner.influx = None;
+        }
+        if settings.influx_enabled && inner.influx.is_none() {
+            inner.influx = Some(InfluxSink::new(&settings.influx).map_err(|e| format!("{e:#}"))?);
+        }
+        if settings.postgres != inner.settings.postgres || !settings.postgres_enabled {
+            inner.postgres = None;
+        }
+        if settings.postgres_enabled && inner.postgres.is_none() {
+            inner.postgres = Some(PgSink::new(&settings.postgres).map_err(|e| format!("{e:#}"))?);
+        }
+        let start_grpc = settings.grpc_server
+            && (!inner.settings.grpc_server || settings.grpc_port != inner.settings.grpc_port);
+        #[cfg(feature = "grpc")]
+        if !settings.grpc_server {
+            inner.grpc = None;
+        }
+        settings::save(&settings).map_err(|e| format!("{e:#}"))?;
+        inner.settings = settings;
+        (start_server, start_api, start_mqtt_sink, start_nats_sink, start_relay_sink, start_grpc)
+    };
+    if start_server {
+        start_ws_server(None).await?;
+    }
+    if start_api {
+        start_api_server(None).await?;
+    }
+    if start_mqtt_sink {
+        start_mqtt(None).await?;
+    }
+    if start_nats_sink {
+        start_nats(None).await?;
+    }
+    if start_relay_sink {
+        start_relay(None).await?;
+    }
+    if start_grpc {
+        start_grpc_server(None).await?;
+    }
+    Ok(())
+}
+
+/// Where the WebSocket server listens and how many clients it has.
+#[derive(Clone, Debug, Serialize)]
+pub struct WsServerStatus {
+    pub addr: String,
+    pub clients: usize,
+}
+
+/// Start the WebSocket server on `port` (the saved port by default),
+/// replacing a running one, and keep it on across restarts. Clients get
+/// `sample` messages at the live rate and a `lap_completed` message per lap.
+#[tauri::command]
+pub async fn start_ws_server(port: Option<u16>) -> Result<WsServerStatus, String> {
+    let port = {
+        let mut inner = SESSION.inner.lock();
+        // free the port when restarting on the same one
+        inner.server = None;
+        port.unwrap_or(inner.settings.ws_port)
+    };
+    let server = TelemetryServer::bind(&format!("0.0.0.0:{port}")).await.map_err(|e| format!("{e:#}"))?;
+    let status = WsServerStatus { addr: server.local_addr().to_string(), clients: 0 };
+    let mut inner = SESSION.inner.lock();
+    inner.server = Some(server);
+    if !inner.settings.ws_server || inner.settings.ws_port != port {
+        inner.settings.ws_server = true;
+        inner.settings.ws_port = port;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(status)
+}
+
+#[tauri::command]
+pub async fn stop_ws_server() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.server = None;
+    if inner.settings.ws_server {
+        inner.settings.ws_server = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// Start the HTTP API over the laps on `port` (the saved port by default),
+/// replacing a running one, and keep it on across restarts. It listens on
+/// this machine only unless the `api_lan` setting is on. Returns the address.
+#[tauri::command]
+pub async fn start_api_server(port: Option<u16>) -> Result<String, String> {
+    let (port, lan) = {
+        let mut inner = SESSION.inner.lock();
+        inner.api = None;
+        (port.unwrap_or(inner.settings.api_port), inner.settings.api_lan)
+    };
+    let host = if lan { "0.0.0.0" } else { "127.0.0.1" };
+    let api = ApiServer::bind(&format!("{host}:{port}"), Arc::new(SessionLaps)).await.map_err(|e| format!("{e:#}"))?;
+    let addr = api.local_addr().to_string();
+    let mut inner = SESSION.inner.lock();
+    inner.api = Some(api);
+    if !inner.settings.api_server || inner.settings.api_port != port {
+        inner.settings.api_server = true;
+        inner.settings.api_port = port;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(addr)
+}
+
+#[tauri::command]
+pub async fn stop_api_server() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.api = None;
+    if inner.settings.api_server {
+        inner.settings.api_server = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// Address of the HTTP API, if it's running.
+#[tauri::command]
+pub async fn api_server_addr() -> Result<Option<String>, String> {
+    Ok(SESSION.inner.lock().api.as_ref().map(|a| a.local_addr().to_string()))
+}
+
+/// The WebSocket server, if it's running.
+#[tauri::command]
+pub async fn ws_server_status() -> Result<Option<WsServerStatus>, String> {
+    let inner = SESSION.inner.lock();
+    Ok(inner.server.as_ref().map(|s| WsServerStatus { addr: s.local_addr().to_string(), clients: s.clients() }))
+}
+
+/// Whether the MQTT sink is on and why it can't reach the broker, if it can't.
+#[derive(Clone, Debug, Serialize)]
+pub struct MqttStatus {
+    pub broker: String,
+    pub error: Option<String>,
+}
+
+/// Publish live samples and laps to the broker in `options` (the saved
+/// options by default), replacing a running sink, and keep it on across
+/// restarts. Samples go out at the live rate; the sink keeps retrying while
+/// the broker is unreachable.
+#[tauri::command]
+pub async fn start_mqtt(options: Option<MqttOptions>) -> Result<MqttStatus, String> {
+    let mut inner = SESSION.inner.lock();
+    let options = options.unwrap_or_else(|| inner.settings.mqtt.clone());
+    inner.mqtt = None;
+    let sink = MqttSink::connect(&options).map_err(|e| format!("{e:#}"))?;
+    let status = MqttStatus { broker: format!("{}:{}", options.host, options.port), error: None };
+    inner.mqtt = Some(sink);
+    if !inner.settings.mqtt_enabled || inner.settings.mqtt != options {
+        inner.settings.mqtt_enabled = true;
+        inner.settings.mqtt = options;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(status)
+}
+
+#[tauri::command]
+pub async fn stop_mqtt() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.mqtt = None;
+    if inner.settings.mqtt_enabled {
+        inner.settings.mqtt_enabled = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// The MQTT sink, if it's on.
+#[tauri::command]
+pub async fn mqtt_status() -> Result<Option<MqttStatus>, String> {
+    let inner = SESSION.inner.lock();
+    Ok(inner.mqtt.as_ref().map(|m| {
+        let o = m.options();
+        MqttStatus { broker: format!("{}:{}", o.host, o.port), error: m.error() }
+    }))
+}
+
+/// Publish live samples and laps to the NATS server in `options` (the saved
+/// options by default) under the driver's subjects, replacing a running
+/// sink, and keep it on across restarts. Samples go out at the live rate.
+#[tauri::command]
+pub async fn start_nats(options: Option<NatsOptions>) -> Result<NatsStatus, String> {
+    let mut inner = SESSION.inner.lock();
+    let options = options.unwrap_or_else(|| inner.settings.nats.clone());
+    inner.nats = None;
+    let sink = NatsSink::connect(&options).map_err(|e| format!("{e:#}"))?;
+    let status = sink.status();
+    inner.nats = Some(sink);
+    if !inner.settings.nats_enabled || inner.settings.nats != options {
+        inner.settings.nats_enabled = true;
+        inner.settings.nats = options;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(status)
+}
+
+#[tauri::command]
+pub async fn stop_nats() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.nats = None;
+    if inner.settings.nats_enabled {
+        inner.settings.nats_enabled = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// The NATS sink, if it's on.
+#[tauri::command]
+pub async fn nats_status() -> Result<Option<NatsStatus>, String> {
+    Ok(SESSION.inner.lock().nats.as_ref().map(|n| n.status()))
+}
+
+/// Relay every sample to the coach's instance in `options` (the saved
+/// options by default), replacing a running relay, and keep it on across
+/// restarts. Reconnects whenever the link drops.
+#[tauri::command]
+pub async fn start_relay(options: Option<RelayOptions>) -> Result<RelayStatus, String> {
+    let mut inner = SESSION.inner.lock();
+    let options = options.unwrap_or_else(|| inner.settings.relay.clone());
+    inner.relay = None;
+    let relay = Relay::connect(&options).map_err(|e| format!("{e:#}"))?;
+    let status = relay.status();
+    inner.relay = Some(relay);
+    if !inner.settings.relay_enabled || inner.settings.relay != options {
+        inner.settings.relay_enabled = true;
+        inner.settings.relay = options;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(status)
+}
+
+#[tauri::command]
+pub async fn stop_relay() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.relay = None;
+    if inner.settings.relay_enabled {
+        inner.settings.relay_enabled = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// The relay to the coach, if it's on.
+#[tauri::command]
+pub async fn relay_status() -> Result<Option<RelayStatus>, String> {
+    Ok(SESSION.inner.lock().relay.as_ref().map(|r| r.status()))
+}
+
+/// Send the channels mapped in `options` (the saved options by default) as
+/// OSC, replacing a running sink, and keep it on across restarts.
+#[tauri::command]
+pub async fn start_osc(options: Option<OscOptions>) -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    let options = options.unwrap_or_else(|| inner.settings.osc.clone());
+    inner.osc = Some(OscSink::new(&options).map_err(|e| format!("{e:#}"))?);
+    if !inner.settings.osc_enabled || inner.settings.osc != options {
+        inner.settings.osc_enabled = true;
+        inner.settings.osc = options;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_osc() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.osc = None;
+    if inner.settings.osc_enabled {
+        inner.settings.osc_enabled = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// Serial ports on this machine, for picking the dash's.
+#[tauri::command]
+pub async fn list_serial_ports() -> Result<Vec<SerialPortInfo>, String> {
+    serial_out::list_ports().map_err(|e| format!("{e:#}"))
+}
+
+/// Write frames for a DIY dash to the port in `options` (the saved options
+/// by default), replacing a running output, and keep it on across restarts.
+/// Output stops if the port fails, e.g. when the device is unplugged.
+#[tauri::command]
+pub async fn start_serial(options: Option<SerialOptions>) -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    let options = options.unwrap_or_else(|| inner.settings.serial.clone());
+    // the port can only be open once
+    inner.serial = None;
+    inner.serial = Some(SerialSink::open(&options).map_err(|e| format!("{e:#}"))?);
+    if !inner.settings.serial_enabled || inner.settings.serial != options {
+        inner.settings.serial_enabled = true;
+        inner.settings.serial = options;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_serial() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.serial = None;
+    if inner.settings.serial_enabled {
+        inner.settings.serial_enabled = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// Post to the Discord webhook in `options` (the saved options by default)
+/// from now on, and keep posting across restarts.
+#[tauri::command]
+pub async fn start_discord(options: Option<DiscordOptions>) -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    let options = options.unwrap_or_else(|| inner.settings.discord.clone());
+    inner.discord = Some(DiscordNotifier::new(&options).map_err(|e| format!("{e:#}"))?);
+    if !inner.settings.discord_enabled || inner.settings.discord != options {
+        inner.settings.discord_enabled = true;
+        inner.settings.discord = options;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_discord() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.discord = None;
+    if inner.settings.discord_enabled {
+        inner.settings.discord_enabled = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// Post a test message to the webhook in `options`, without saving them.
+#[tauri::command]
+pub async fn test_discord(options: DiscordOptions) -> Result<(), String> {
+    DiscordNotifier::new(&options).map_err(|e| format!("{e:#}"))?.notify(&Notification::Test);
+    Ok(())
+}
+
+/// Write samples and laps to the InfluxDB bucket in `options` (the saved
+/// options by default), replacing a running sink, and keep writing across
+/// restarts.
+#[tauri::command]
+pub async fn start_influx(options: Option<InfluxOptions>) -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    let options = options.unwrap_or_else(|| inner.settings.influx.clone());
+    inner.influx = Some(InfluxSink::new(&options).map_err(|e| format!("{e:#}"))?);
+    if !inner.settings.influx_enabled || inner.settings.influx != options {
+        inner.settings.influx_enabled = true;
+        inner.settings.influx = options;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// Stop writing to InfluxDB; lines already queued are still sent.
+#[tauri::command]
+pub async fn stop_influx() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.influx = None;
+    if inner.settings.influx_enabled {
+        inner.settings.influx_enabled = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// Lines written and dropped by the InfluxDB sink, if it's on.
+#[tauri::command]
+pub async fn influx_status() -> Result<Option<InfluxStatus>, String> {
+    Ok(SESSION.inner.lock().influx.as_ref().map(|i| i.status()))
+}
+
+/// Stream completed laps into the database in `options` (the saved options
+/// by default), replacing a running sink, and keep streaming across
+/// restarts. Connection problems show up in `postgres_status`.
+#[tauri::command]
+pub async fn start_postgres(options: Option<PgOptions>) -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    let options = options.unwrap_or_else(|| inner.settings.postgres.clone());
+    inner.postgres = Some(PgSink::new(&options).map_err(|e| format!("{e:#}"))?);
+    if !inner.settings.postgres_enabled || inner.settings.postgres != options {
+        inner.settings.postgres_enabled = true;
+        inner.settings.postgres = options;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// Stop streaming laps; laps already queued are still written.
+#[tauri::command]
+pub async fn stop_postgres() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.postgres = None;
+    if inner.settings.postgres_enabled {
+        inner.settings.postgres_enabled = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// Connection and laps written of the PostgreSQL sink, if it's on.
+#[tauri::command]
+pub async fn postgres_status() -> Result<Option<PgStatus>, String> {
+    Ok(SESSION.inner.lock().postgres.as_ref().map(|p| p.status()))
+}
+
+/// Start the gRPC service on `port` (the saved port by default), replacing a
+/// running one, and keep it on across restarts. Returns the address. Fails
+/// in builds without the `grpc` feature.
+#[tauri::command]
+pub async fn start_grpc_server(port: Option<u16>) -> Result<String, String> {
+    #[cfg(not(feature = "grpc"))]
+    {
+        let _ = port;
+        Err("this build has no gRPC support".into())
+    }
+    #[cfg(feature = "grpc")]
+    {
+        let port = {
+            let mut inner = SESSION.inner.lock();
+            inner.grpc = None;
+            port.unwrap_or(inner.settings.grpc_port)
+        };
+        let server = delta_server::GrpcServer::bind(&format!("0.0.0.0:{port}"), Arc::new(SessionLaps))
+            .await
+            .map_err(|e| format!("{e:#}"))?;
+        let addr = server.local_addr().to_string();
+        let mut inner = SESSION.inner.lock();
+        inner.grpc = Some(server);
+        if !inner.settings.grpc_server || inner.settings.grpc_port != port {
+            inner.settings.grpc_server = true;
+            inner.settings.grpc_port = port;
+            settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+        }
+        Ok(addr)
+    }
+}
+
+#[tauri::command]
+pub async fn stop_grpc_server() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    #[cfg(feature = "grpc")]
+    {
+        inner.grpc = None;
+    }
+    if inner.settings.grpc_server {
+        inner.settings.grpc_server = false;
+        settings::save(&inner.settings).map_err(|e| format!("{e:#}"))?;
+    }
+    Ok(())
+}
+
+/// Address of the gRPC service, if it's running.
+#[tauri::command]
+pub async fn grpc_server_addr() -> Result<Option<String>, String> {
+    #[cfg(feature = "grpc")]
+    return Ok(SESSION.inner.lock().grpc.as_ref().map(|g| g.local_addr().to_string()));
+    #[cfg(not(feature = "grpc"))]
+    Ok(None)
+}
+
+/// Folder laps are currently autosaved to, if autosave is on.
+#[tauri::command]
+pub async fn autosave_dir() -> Result<Option<String>, String> {
+    Ok(SESSION.inner.lock().autosave.as_ref().map(|a| a.root().display().to_string()))
+}
+
+/// Append every incoming sample to `path` as NDJSON or CSV until stopped.
+#[tauri::command]
+pub async fn start_recording(path: String, format: RecordFormat) -> Result<(), String> {
+    let rec = SampleRecorder::open(Path::new(&path), format).map_err(|e| format!("{e:#}"))?;
+    SESSION.inner.lock().recorder = Some(rec);
+    Ok(())
+}
+
+/// Stop recording; returns how many samples were written.
+#[tauri::command]
+pub async fn stop_recording() -> Result<u64, String> {
+    let rec = SESSION.inner.lock().recorder.take();
+    Ok(rec.map(|r| r.written()).unwrap_or(0))
+}
+
+/// Write the last `minutes` of telemetry from the always-on ring buffer to
+/// `path` (NDJSON unless `format` says otherwise), whether or not a
+/// recording was running. Returns how many samples were written.
+#[tauri::command]
+pub async fn save_last(minutes: f64, path: String, format: Option<RecordFormat>) -> Result<u64, String> {
+    if !minutes.is_finite() || minutes <= 0.0 {
+        return Err(format!("invalid duration {minutes}"));
+    }
+    let mut inner = SESSION.inner.lock();
+    let ring = inner.ring.as_ref().ok_or("background recording is off; set ring_minutes in settings")?;
+    let n = ring
+        .save_last(Duration::from_secs_f64(minutes * 60.0), Path::new(&path), format.unwrap_or(RecordFormat::Ndjson))
+        .map_err(|e| format!("{e:#}"))?;
+    inner.remember_file(RecentKind::Export, &path);
+    Ok(n)
 }
 
 #[tauri::command]
@@ -54,13 +1371,191 @@ pub async fn cars_and_tracks() -> Result<(Vec<String>, Vec<String>), String> {
     Ok((Vec::new(), Vec::new()))
 }
 
+/// Save a named set of laps from any sessions or imports to compare
+/// together, in `workspace` when given. Replaces a set of the same name.
 #[tauri::command]
-pub async fn save_workspace(_name: String) -> Result<(), String> { Ok(()) }
+pub async fn create_comparison_set(
+    name: String,
+    lap_ids: Vec<Uuid>,
+    reference_id: Option<Uuid>,
+    workspace: Option<String>,
+) -> Result<ComparisonSet, String> {
+    let inner = SESSION.inner.lock();
+    if let Some(id) = lap_ids.iter().chain(&reference_id).find(|&&id| !inner.has_lap(id)) {
+        return Err(format!("lap {id} not found"));
+    }
+    let store = inner.store.as_ref().ok_or("lap database unavailable; comparison sets are only kept there")?;
+    let set = ComparisonSet {
+        name,
+        lap_ids,
+        reference_id,
+        created_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0),
+    };
+    store.put_comparison_set(workspace.as_deref().unwrap_or_default(), &set).map_err(|e| format!("{e:#}"))?;
+    Ok(set)
+}
 
 #[tauri::command]
-pub async fn load_workspace(_name: String) -> Result<(), String> { Ok(()) }
+pub async fn list_comparison_sets(workspace: Option<String>) -> Result<Vec<ComparisonSet>, String> {
+    let inner = SESSION.inner.lock();
+    let store = inner.store.as_ref().ok_or("lap database unavailable; comparison sets are only kept there")?;
+    store.comparison_sets(workspace.as_deref().unwrap_or_default()).map_err(|e| format!("{e:#}"))
+}
+
+#[tauri::command]
+pub async fn delete_comparison_set(name: String, workspace: Option<String>) -> Result<bool, String> {
+    let inner = SESSION.inner.lock();
+    let store = inner.store.as_ref().ok_or("lap database unavailable; comparison sets are only kept there")?;
+    store.delete_comparison_set(workspace.as_deref().unwrap_or_default(), &name).map_err(|e| format!("{e:#}"))
+}
+
+/// Run [`analyze_laps`] over the laps of a comparison set.
+#[tauri::command]
+pub async fn compare_set(
+    name: String,
+    workspace: Option<String>,
+    alignment: Option<Alignment>,
+    step_m: Option<f64>,
+    max_points: Option<usize>,
+) -> Result<Comparison, String> {
+    let set = {
+        let inner = SESSION.inner.lock();
+        let store = inner.store.as_ref().ok_or("lap database unavailable; comparison sets are only kept there")?;
+        store
+            .comparison_sets(workspace.as_deref().unwrap_or_default())
+            .map_err(|e| format!("{e:#}"))?
+            .into_iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| format!("comparison set {name} not found"))?
+    };
+    if set.lap_ids.is_empty() {
+        return Err(format!("comparison set {name} has no laps"));
+    }
+    analyze_laps(set.lap_ids, set.reference_id, alignment, step_m, max_points).await
+}
+
+/// Recently imported and exported files and opened sessions, newest first.
+#[tauri::command]
+pub async fn recent_items() -> Result<Vec<RecentItem>, String> {
+    Ok(SESSION.inner.lock().recent.items.clone())
+}
+
+#[tauri::command]
+pub async fn clear_recent() -> Result<(), String> {
+    let mut inner = SESSION.inner.lock();
+    inner.recent = RecentList::default();
+    recent::save(&inner.recent).map_err(|e| format!("{e:#}"))
+}
+
+#[tauri::command]
+pub async fn save_workspace(name: String, payload: serde_json::Value) -> Result<(), String> {
+    SESSION.inner.lock().workspaces.insert(name, payload);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn load_workspace(name: String) -> Result<serde_json::Value, String> {
+    SESSION.inner.lock().workspaces.get(&name).cloned().ok_or_else(|| format!("workspace {name} not found"))
+}
 
 #[tauri::command]
 pub async fn list_workspaces() -> Result<Vec<String>, String> {
-    Ok(Vec::new())
+    let mut names: Vec<String> = SESSION.inner.lock().workspaces.keys().cloned().collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Write the saved workspace `name` and the laps `lap_ids` it refers to into
+/// one `.deltaworkspace` file. Returns the number of laps written.
+#[tauri::command]
+pub async fn export_workspace(name: String, lap_ids: Vec<Uuid>, path: String) -> Result<usize, String> {
+    let ws = {
+        let mut inner = SESSION.inner.lock();
+        let layout = inner.workspaces.get(&name).cloned().ok_or_else(|| format!("workspace {name} not found"))?;
+        let sets = match &inner.store {
+            Some(store) => store.comparison_sets(&name).map_err(|e| format!("{e:#}"))?,
+            None => Vec::new(),
+        };
+        // the laps of the comparison sets go along too
+        let mut ids = lap_ids;
+        for id in sets.iter().flat_map(|s| s.lap_ids.iter().chain(&s.reference_id)) {
+            if !ids.contains(id) {
+                ids.push(*id);
+            }
+        }
+        let laps = ids
+            .iter()
+            .map(|&id| inner.lap(id)?.map(|l| Lap::clone(&l)).ok_or_else(|| format!("lap {id} not found")))
+            .collect::<Result<Vec<_>, _>>()?;
+        WorkspaceFile::new(&name, layout, sets, laps)
+    };
+    let p = path.clone();
+    let n = tauri::async_runtime::spawn_blocking(move || iox::workspace::export_workspace(&ws, Path::new(&p)).map(|_| ws.laps.len()))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("{e:#}"))?;
+    SESSION.inner.lock().remember_file(RecentKind::Export, &path);
+    Ok(n)
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceImport {
+    /// Name it was saved under; suffixed when a workspace of that name exists.
+    pub name: String,
+    pub layout: serde_json::Value,
+    pub lap_ids: Vec<Uuid>,
+    pub report: ImportReport,
+}
+
+/// Read a `.deltaworkspace` file: add its laps to the session and save its
+/// layout as a workspace. Laps already here under another id are not added
+/// again; the layout is pointed at the local copies instead. The file is
+/// opened in place, so laps already in the session are never decoded.
+#[tauri::command]
+pub async fn import_workspace(path: String) -> Result<WorkspaceImport, String> {
+    let p = path.clone();
+    let mapped = tauri::async_runtime::spawn_blocking(move || iox::workspace::open_workspace(Path::new(&p)))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("{e:#}"))?;
+    let known: Vec<bool> = {
+        let inner = SESSION.inner.lock();
+        mapped.laps.heads().iter().map(|h| inner.has_lap(h.id)).collect()
+    };
+    let (manifest, file, total) = (mapped.manifest, mapped.laps, known.len());
+    let laps = tauri::async_runtime::spawn_blocking(move || {
+        (0..file.len()).filter(|&i| !known[i]).map(|i| file.lap(i)).collect::<anyhow::Result<Vec<_>>>()
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("{e:#}"))?;
+    let skipped = total - laps.len();
+    let mut ws = WorkspaceFile { manifest, laps };
+    let remap: HashMap<Uuid, Uuid> = {
+        let inner = SESSION.inner.lock();
+        ws.laps
+            .iter()
+            .filter_map(|l| inner.known.by_hash(l.content_hash()).map(|to| (l.id, to)))
+            .collect()
+    };
+    ws.remap_laps(&remap);
+    let mut report = add_laps(ws.laps).await?;
+    report.skipped += skipped;
+
+    let mut inner = SESSION.inner.lock();
+    let base = ws.manifest.name;
+    let mut name = base.clone();
+    let mut n = 2;
+    while inner.workspaces.contains_key(&name) {
+        name = format!("{base} ({n})");
+        n += 1;
+    }
+    inner.workspaces.insert(name.clone(), ws.manifest.layout.clone());
+    if let Some(store) = &inner.store {
+        for set in &ws.manifest.comparison_sets {
+            store.put_comparison_set(&name, set).map_err(|e| format!("{e:#}"))?;
+        }
+    }
+    inner.remember_file(RecentKind::Import, &path);
+    Ok(WorkspaceImport { name, layout: ws.manifest.layout, lap_ids: ws.manifest.lap_ids, report })
 }
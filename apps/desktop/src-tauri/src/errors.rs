@@ -0,0 +1,61 @@
+//! Failures of work no command is waiting on: saving laps, journaling,
+//! publishing to sinks, background servers. Each is logged to stderr, sent to
+//! the frontend as an `app://error` event and kept for `background_errors`,
+//! so failures from before the window was up aren't lost either.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+pub const ERROR_EVENT: &str = "app://error";
+
+/// Errors kept for `background_errors`; older ones drop off.
+const MAX_KEPT: usize = 50;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BackgroundError {
+    /// What was being done, e.g. "saving lap".
+    pub action: String,
+    pub message: String,
+    /// Unix seconds.
+    pub at: i64,
+}
+
+// set once the app is up; kept apart from the session so errors can be
+// reported with its lock held
+static APP: OnceCell<AppHandle> = OnceCell::new();
+static KEPT: Lazy<Mutex<VecDeque<BackgroundError>>> = Lazy::new(Default::default);
+
+/// Send errors to the frontend as they happen from now on.
+pub fn attach(app: AppHandle) {
+    let _ = APP.set(app);
+}
+
+/// Record that `action` failed with `e`.
+pub fn report(action: &str, e: impl Display) {
+    let err = BackgroundError {
+        action: action.to_string(),
+        message: format!("{e:#}"),
+        at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0),
+    };
+    eprintln!("{} failed: {}", err.action, err.message);
+    if let Some(app) = APP.get() {
+        let _ = app.emit(ERROR_EVENT, &err);
+    }
+    let mut kept = KEPT.lock();
+    if kept.len() == MAX_KEPT {
+        kept.pop_front();
+    }
+    kept.push_back(err);
+}
+
+/// Errors reported so far, oldest first.
+pub fn recent() -> Vec<BackgroundError> {
+    KEPT.lock().iter().cloned().collect()
+}
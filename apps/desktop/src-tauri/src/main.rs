@@ -1,23 +1,105 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 mod session;
+mod settings;
+mod recent;
 mod commands;
+mod errors;
+mod writer;
 
 use commands::{
-    start_f1, start_gt7, start_lmu, stop_all,
-    list_laps, analyze_laps, build_track_map,
-    import_file, export_file,
-    cars_and_tracks,
-    save_workspace, load_workspace, list_workspaces,
+    start_f1, start_gt7, start_lmu, start_remote, new_relay_token, stop_all, stop_source, restart_source, follow_f1_car, list_sources, list_sessions,
+    list_laps, recovered_laps, background_errors, delete_laps, edit_lap, set_lap_tags, lap_tags, live_delta, live_state, live_history, lap_markers, analyze_laps, compare_laps, get_track_map, build_track_map,
+    import_file, import_files, cancel_import, import_f1_capture, export_file, save_laps, export_analysis, export_track_geojson, render_track_svg,
+    set_autosave, autosave_dir, get_settings, set_settings, set_live_rate, start_recording, stop_recording, save_last,
+    cars_and_tracks, recent_items, clear_recent,
+    create_comparison_set, list_comparison_sets, delete_comparison_set, compare_set,
+    session_stats, discover_consoles, pause_recording, resume_recording,
+    diagnose_source, start_ws_server, stop_ws_server, ws_server_status,
+    start_api_server, stop_api_server, api_server_addr, start_mqtt, stop_mqtt, mqtt_status,
+    start_nats, stop_nats, nats_status, start_relay, stop_relay, relay_status,
+    start_osc, stop_osc, list_serial_ports, start_serial, stop_serial, start_discord, stop_discord, test_discord,
+    start_influx, stop_influx, influx_status, start_postgres, stop_postgres, postgres_status,
+    start_grpc_server, stop_grpc_server, grpc_server_addr,
+    save_workspace, load_workspace, list_workspaces, export_workspace, import_workspace,
 };
 
 fn main() {
     tauri::Builder::default()
+        .setup(|app| {
+            errors::attach(app.handle().clone());
+            session::SESSION.inner.lock().app = Some(app.handle().clone());
+            if session::SESSION.inner.lock().settings.ws_server {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_ws_server(None).await { errors::report("starting websocket server", e); }
+                });
+            }
+            if session::SESSION.inner.lock().settings.api_server {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_api_server(None).await { errors::report("starting http api", e); }
+                });
+            }
+            if session::SESSION.inner.lock().settings.mqtt_enabled {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_mqtt(None).await { errors::report("starting mqtt sink", e); }
+                });
+            }
+            if session::SESSION.inner.lock().settings.nats_enabled {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_nats(None).await { errors::report("starting nats sink", e); }
+                });
+            }
+            if session::SESSION.inner.lock().settings.relay_enabled {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_relay(None).await { errors::report("starting relay", e); }
+                });
+            }
+            if session::SESSION.inner.lock().settings.osc_enabled {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_osc(None).await { errors::report("starting osc output", e); }
+                });
+            }
+            if session::SESSION.inner.lock().settings.serial_enabled {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_serial(None).await { errors::report("opening serial output", e); }
+                });
+            }
+            if session::SESSION.inner.lock().settings.discord_enabled {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_discord(None).await { errors::report("starting discord notifications", e); }
+                });
+            }
+            if session::SESSION.inner.lock().settings.influx_enabled {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_influx(None).await { errors::report("starting influxdb writer", e); }
+                });
+            }
+            if session::SESSION.inner.lock().settings.postgres_enabled {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_postgres(None).await { errors::report("starting postgres sink", e); }
+                });
+            }
+            if session::SESSION.inner.lock().settings.grpc_server {
+                tauri::async_runtime::spawn(async {
+                    if let Err(e) = commands::start_grpc_server(None).await { errors::report("starting grpc server", e); }
+                });
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
-            start_f1, start_gt7, start_lmu, stop_all,
-            list_laps, analyze_laps, build_track_map,
-            import_file, export_file,
-            cars_and_tracks,
-            save_workspace, load_workspace, list_workspaces,
+            start_f1, start_gt7, start_lmu, start_remote, new_relay_token, stop_all, stop_source, restart_source, follow_f1_car, list_sources, list_sessions,
+            list_laps, recovered_laps, background_errors, delete_laps, edit_lap, set_lap_tags, lap_tags, live_delta, live_state, live_history, lap_markers, analyze_laps, compare_laps, get_track_map, build_track_map,
+            import_file, import_files, cancel_import, import_f1_capture, export_file, save_laps, export_analysis, export_track_geojson, render_track_svg,
+            set_autosave, autosave_dir, get_settings, set_settings, set_live_rate, start_recording, stop_recording, save_last,
+            cars_and_tracks, recent_items, clear_recent,
+            create_comparison_set, list_comparison_sets, delete_comparison_set, compare_set,
+            session_stats, discover_consoles, pause_recording, resume_recording,
+            diagnose_source, start_ws_server, stop_ws_server, ws_server_status,
+            start_api_server, stop_api_server, api_server_addr, start_mqtt, stop_mqtt, mqtt_status,
+            start_nats, stop_nats, nats_status, start_relay, stop_relay, relay_status,
+            start_osc, stop_osc, list_serial_ports, start_serial, stop_serial, start_discord, stop_discord, test_discord,
+            start_influx, stop_influx, influx_status, start_postgres, stop_postgres, postgres_status,
+            start_grpc_server, stop_grpc_server, grpc_server_addr,
+            save_workspace, load_workspace, list_workspaces, export_workspace, import_workspace,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
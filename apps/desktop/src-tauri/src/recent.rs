@@ -0,0 +1,81 @@
+//! Recently imported and exported files and recently opened sessions, for
+//! the start screen. Kept newest first in `recent.json` next to the settings.
+
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::settings::config_dir;
+
+/// Items kept; older ones drop off the end.
+pub const MAX_ITEMS: usize = 20;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecentKind {
+    Import,
+    Export,
+    Session,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RecentItem {
+    pub kind: RecentKind,
+    /// File path for imports and exports, session id for sessions.
+    pub target: String,
+    /// What to show: the file name, or the session's name.
+    pub label: String,
+    /// Unix seconds.
+    pub at: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct RecentList {
+    pub items: Vec<RecentItem>,
+}
+
+impl RecentList {
+    pub fn file(&mut self, kind: RecentKind, path: &str) {
+        let label = PathBuf::from(path).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| path.into());
+        self.push(kind, path.into(), label);
+    }
+
+    pub fn session(&mut self, id: Uuid, name: &str) {
+        self.push(RecentKind::Session, id.simple().to_string(), name.into());
+    }
+
+    /// Move the item to the front, adding it if new.
+    fn push(&mut self, kind: RecentKind, target: String, label: String) {
+        self.items.retain(|i| !(i.kind == kind && i.target == target));
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+        self.items.insert(0, RecentItem { kind, target, label, at });
+        self.items.truncate(MAX_ITEMS);
+    }
+}
+
+pub fn recent_path() -> PathBuf {
+    config_dir().join("recent.json")
+}
+
+/// The saved list, or an empty one when there is none yet.
+pub fn load() -> Result<RecentList> {
+    let path = recent_path();
+    if !path.exists() {
+        return Ok(RecentList::default());
+    }
+    let text = fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+}
+
+pub fn save(list: &RecentList) -> Result<()> {
+    let path = recent_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, serde_json::to_vec_pretty(list)?).with_context(|| format!("writing {}", path.display()))
+}
@@ -1,149 +1,1013 @@
-use std::{collections::HashMap, thread, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use crossbeam_channel::RecvTimeoutError;
+use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use serde_json::json;
 use uuid::Uuid;
 
 use model::*;
 use delta_ingest_core::{TelemetrySample, TelemetryRx, TelemetrySource, channel, Game as GameId};
+use delta_ingest_core::diag::{IngestCounters, SampleProbe};
+use delta_ingest_remote::Relay;
+use delta_server::discord::Notification;
+use delta_server::influx::Tags;
+use delta_server::pg::PgSession;
+use delta_server::{ApiServer, DiscordNotifier, InfluxSink, LapInfo, LapProvider, LiveView, MqttSink, NatsSink, OscSink, PgSink, SerialSink, SourceMetrics, TelemetryServer};
+use delta_ingest_core::record::{SampleRecorder, SampleRing};
 use analysis as an;
+use an::live::LiveDeltaState;
+use an::stream::StreamState;
+use storage::{LapQuery, LapStore, LapSummary, StoredSession, StoredTrackMap};
+use iox::autosave::{Autosave, AutosaveOptions};
+use iox::build::{LapBuilder, ThinOptions};
+use iox::import::{CancelHandle, KnownLaps};
+use iox::journal::{self, Journal};
+use crate::errors;
+use crate::recent::{self, RecentKind, RecentList};
+use crate::settings::{self, Settings};
+use crate::writer::StoreWriter;
+
+/// Process-wide session shared by the connectors and the Tauri commands.
+pub static SESSION: Lazy<AppSession> = Lazy::new(AppSession::new);
 
 pub struct AppSession {
     pub inner: Mutex<Inner>,
+    // running connectors by key ("f1", "gt7", "lmu", "remote"); kept apart from `inner`,
+    // which the pump threads lock for every sample
+    sources: Mutex<HashMap<String, SourceHandle>>,
+}
+
+pub const SAMPLE_EVENT: &str = "telemetry://sample";
+pub const LAP_EVENT: &str = "telemetry://lap-completed";
+// message types of the same events on the WebSocket server
+const SERVER_SAMPLE: &str = "sample";
+const SERVER_LAP: &str = "lap_completed";
+const DEFAULT_SAMPLE_HZ: f64 = 20.0;
+// how often each source's lap in progress is journaled
+const JOURNAL_INTERVAL: Duration = Duration::from_secs(5);
+// caps the ring buffer's memory: enough for four sources at 60 Hz
+const RING_MAX_HZ: usize = 240;
+// recent samples kept per source for live strip charts
+const HISTORY_WINDOW: Duration = Duration::from_secs(60);
+const HISTORY_MAX_HZ: usize = 120;
+
+/// What a dash or overlay page shows for one source: its latest sample and
+/// the running lap. Sent as the `telemetry://sample` payload.
+#[derive(Clone, Debug, Serialize)]
+pub struct LiveState {
+    pub source: String,
+    pub sample: TelemetrySample,
+    /// Lap number, current lap time, delta to best and predicted time.
+    pub delta: LiveDeltaState,
+    /// Sector splits, top speed and lap time statistics.
+    pub stream: StreamState,
+    pub last_lap_ms: Option<u64>,
+}
+
+/// Sent as `telemetry://lap-completed` the moment a lap rolls over, for the
+/// toast the UI pops when the driver crosses the line.
+#[derive(Serialize)]
+struct LapEvent<'a> {
+    source: &'a str,
+    #[serde(with = "uuid::serde::simple")]
+    id: Uuid,
+    meta: &'a LapMeta,
+    total_time_ms: u64,
+    /// Time in each sector of the track map, in ms.
+    sector_ms: Vec<f64>,
+    /// Session best before this lap; `None` on the first lap.
+    best_ms: Option<u64>,
+    /// Lap time minus `best_ms` (negative = new best).
+    delta_to_best_ms: Option<i64>,
+}
+
+/// What the lap list needs of a lap, loaded or not.
+pub struct LapHead<'a> {
+    pub id: Uuid,
+    pub meta: &'a LapMeta,
+    pub total_time_ms: u64,
+}
+
+/// One run of a source, from start to stop. Its laps are grouped under it in
+/// the lap database.
+#[derive(Clone, Debug, Serialize)]
+pub struct SourceSession {
+    #[serde(with = "uuid::serde::simple")]
+    pub id: Uuid,
+    pub source: String,
+    /// Unix seconds.
+    pub started_at: i64,
+    pub laps: Vec<Uuid>,
+}
+
+/// What `list_sources` reports about a running connector.
+#[derive(Clone, Debug, Serialize)]
+pub struct SourceInfo {
+    pub key: String,
+    pub session: SourceSession,
+    /// Unknown until the source has sent samples.
+    pub game: Option<String>,
+    pub best_ms: Option<u64>,
+    pub paused: bool,
+}
+
+/// Totals of a running source since it started.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SourceCounts {
+    pub samples: u64,
+    /// Samples that arrived while paused.
+    pub paused: u64,
+    /// Samples of laps cut short by the game session changing.
+    pub cut_short: u64,
+    /// Samples thrown away by lap builders since replaced, so the total
+    /// doesn't go back when a session rolls over.
+    pub dropped: u64,
+    pub laps: u64,
+}
+
+/// A running connector: its async task, and the flag that stops the thread
+/// pumping its samples into the session.
+struct SourceHandle {
+    source: Arc<dyn TelemetrySource>,
+    task: tauri::async_runtime::JoinHandle<()>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl SourceHandle {
+    fn stop(self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.task.abort();
+    }
 }
 
 pub struct Inner {
-    pub laps: HashMap<Uuid, Lap>,
+    // laps with their points: recorded or imported this run, or loaded from
+    // the lap database when first needed; see `Inner::lap`
+    pub laps: HashMap<Uuid, Arc<Lap>>,
+    // metadata of the lap database's laps not loaded yet
+    pub stored: HashMap<Uuid, LapSummary>,
+    // ids and content hashes of `laps` and `stored`, for deduplicating
+    // imports; kept in step with them
+    pub known: KnownLaps,
+    // persisted user settings; write back with `settings::save` after changing
+    pub settings: Settings,
+    // recently used files and sessions for the start screen, persisted
+    pub recent: RecentList,
+    // on-disk lap database, for reads and edits; laps and track maps are
+    // written by `writer` on its own connection
+    pub store: Option<LapStore>,
+    pub writer: Option<StoreWriter>,
+    // when set, every completed lap is also appended to a session folder on disk
+    pub autosave: Option<Autosave>,
+    // raw log of every sample from every source, when recording to file
+    pub recorder: Option<SampleRecorder>,
+    // last minutes of samples from every source, for `save_last`
+    pub ring: Option<SampleRing>,
+    // last `HISTORY_WINDOW` of samples per running source, for strip charts
+    pub history: HashMap<String, SampleRing>,
+    // snapshots of laps in progress, recovered after a crash
+    pub journal: Journal,
+    // laps recovered from the journal at startup, tagged "recovered"
+    pub recovered: Vec<Uuid>,
+    // imports in flight, by `iox::import::content_key` of each path, so the
+    // frontend can cancel them and the same file isn't imported twice at once
+    pub imports: HashMap<u64, CancelHandle>,
     pub workspaces: HashMap<String, serde_json::Value>,
     pub running: bool,
     // builders per source/session
     pub builders: HashMap<String, LapBuilder>,
-    // join handles (we only need to drop them when stopping; simplified)
+    // session of each running source, by source key
+    pub sessions: HashMap<String, SourceSession>,
+    // sources whose lap building is paused (garage, menus); their samples
+    // only reach the raw recorder and the ring buffer
+    pub paused: HashSet<String>,
+    // sources being diagnosed, watched sample by sample
+    pub probes: HashMap<String, SampleProbe>,
+    // sample and lap totals per running source, for the metrics endpoint
+    pub counts: HashMap<String, SourceCounts>,
+    // game session each running source last reported, to tell restarts
+    session_uids: HashMap<String, String>,
+    // session every recorded lap belongs to; imported laps have none
+    pub lap_sessions: HashMap<Uuid, Uuid>,
+    // driver of every lap given one; the lap database has the same
+    pub lap_drivers: HashMap<Uuid, String>,
+    // best track map per (game, track), written through to the lap database
+    pub track_maps: HashMap<(String, String), StoredTrackMap>,
+    // live events to the frontend; set once the app is up
+    pub app: Option<AppHandle>,
+    // the same events as JSON to WebSocket clients on the LAN, when enabled
+    pub server: Option<TelemetryServer>,
+    // HTTP API over the laps for scripts and external tools, when enabled
+    pub api: Option<ApiServer>,
+    // samples and laps published to an MQTT broker, when enabled
+    pub mqtt: Option<MqttSink>,
+    // the same, on a NATS server under the driver's subjects, when enabled
+    pub nats: Option<NatsSink>,
+    // every sample relayed to a coach's instance, when enabled
+    pub relay: Option<Relay>,
+    // channels sent as OSC to motion rigs and shakers, when enabled
+    pub osc: Option<OscSink>,
+    // frames for a DIY dash on a serial port, when enabled
+    pub serial: Option<SerialSink>,
+    // personal bests, session summaries and incidents posted to Discord
+    pub discord: Option<DiscordNotifier>,
+    // samples and lap summaries stored in InfluxDB, when enabled
+    pub influx: Option<InfluxSink>,
+    // laps and points streamed into PostgreSQL/TimescaleDB, when enabled
+    pub postgres: Option<PgSink>,
+    // typed samples and laps for gRPC clients, when enabled
+    #[cfg(feature = "grpc")]
+    pub grpc: Option<delta_server::GrpcServer>,
+    // `telemetry://sample` events per second and source; 0 turns them off
+    pub sample_hz: f64,
+    last_sample_event: HashMap<String, Instant>,
 }
 
 impl AppSession {
-    pub fn new() -> Self { Self { inner: Mutex::new(Inner {
-        laps: HashMap::new(),
-        workspaces: HashMap::new(),
-        running: false,
-        builders: HashMap::new(),
-    }) } }
+    pub fn new() -> Self {
+        let mut store = match LapStore::open(&db_path()) {
+            Ok(s) => Some(s),
+            Err(e) => { errors::report("opening the lap database (laps will not persist)", e); None }
+        };
+        let writer = store.as_ref().and_then(|_| {
+            StoreWriter::spawn(&db_path()).map_err(|e| errors::report("starting the lap database writer", e)).ok()
+        });
+        let mut laps = HashMap::new();
+        let recovered = recover_journal(store.as_mut(), &mut laps);
+        // only metadata; points are read when a lap is first needed
+        let summaries = store.as_ref()
+            .and_then(|s| s.query(&LapQuery::default()).map_err(|e| errors::report("loading laps", e)).ok())
+            .unwrap_or_default();
+        // stored hashes, so nothing is rehashed; recovered laps may not be stored
+        let mut known = KnownLaps::default();
+        for l in &summaries {
+            known.insert_hashed(l.id, content_hash(&l.meta, l.samples_hash));
+        }
+        for id in &recovered {
+            if let Some(l) = laps.get(id) { known.insert(l); }
+        }
+        let lap_sessions = summaries
+            .iter()
+            .filter_map(|l| Some((l.id, l.session_id?)))
+            .collect();
+        let lap_drivers = summaries
+            .iter()
+            .filter(|l| !l.driver.is_empty())
+            .map(|l| (l.id, l.driver.clone()))
+            .collect();
+        let stored = summaries
+            .into_iter()
+            .filter(|l| !laps.contains_key(&l.id))
+            .map(|l| (l.id, l))
+            .collect();
+        let track_maps = store.as_ref()
+            .and_then(|s| s.track_maps().map_err(|e| errors::report("loading track maps", e)).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|m| ((m.game.clone(), m.track.clone()), m))
+            .collect();
+        let settings = settings::load().unwrap_or_else(|e| {
+            errors::report("reading settings (using defaults)", e);
+            Settings::default()
+        });
+        let recent = recent::load().unwrap_or_else(|e| {
+            errors::report("reading recent items (starting empty)", e);
+            RecentList::default()
+        });
+        let ring = ring_for(settings.ring_minutes);
+        let autosave = settings.autosave_dir.as_ref()
+            .map(|d| Autosave::new(&AutosaveOptions { root: PathBuf::from(d) }));
+        Self { inner: Mutex::new(Inner {
+            laps,
+            stored,
+            known,
+            settings,
+            recent,
+            store,
+            writer,
+            autosave,
+            recorder: None,
+            ring,
+            history: HashMap::new(),
+            journal: Journal::new(&journal_dir(), JOURNAL_INTERVAL),
+            recovered,
+            imports: HashMap::new(),
+            workspaces: HashMap::new(),
+            running: false,
+            builders: HashMap::new(),
+            sessions: HashMap::new(),
+            paused: HashSet::new(),
+            probes: HashMap::new(),
+            counts: HashMap::new(),
+            session_uids: HashMap::new(),
+            lap_sessions,
+            lap_drivers,
+            track_maps,
+            app: None,
+            server: None,
+            api: None,
+            mqtt: None,
+            nats: None,
+            relay: None,
+            osc: None,
+            serial: None,
+            discord: None,
+            influx: None,
+            postgres: None,
+            #[cfg(feature = "grpc")]
+            grpc: None,
+            sample_hz: DEFAULT_SAMPLE_HZ,
+            last_sample_event: HashMap::new(),
+        }), sources: Mutex::new(HashMap::new()) }
+    }
+
+    /// Run `source` under `key` in a new session, replacing any source
+    /// already running there.
+    pub fn start_source(&'static self, key: &str, source: Arc<dyn TelemetrySource>) {
+        let old = self.sources.lock().remove(key);
+        if let Some(old) = old {
+            old.stop();
+        }
+        self.inner.lock().begin_session(key);
+        let handle = run_source(source, key.to_string(), self);
+        self.sources.lock().insert(key.to_string(), handle);
+    }
+
+    /// Stop the source under `key`; false if none was running.
+    pub fn stop_source(&self, key: &str) -> bool {
+        let Some(h) = self.sources.lock().remove(key) else { return false };
+        h.stop();
+        self.inner.lock().end_session(key);
+        true
+    }
+
+    /// Stop and start again the source under `key` with the same settings;
+    /// false if none was running.
+    pub fn restart_source(&'static self, key: &str) -> bool {
+        let Some(old) = self.sources.lock().remove(key) else { return false };
+        let source = old.source.clone();
+        old.stop();
+        self.start_source(key, source);
+        true
+    }
+
+    pub fn stop_all(&self) {
+        let stopped: Vec<(String, SourceHandle)> = self.sources.lock().drain().collect();
+        let mut inner = self.inner.lock();
+        for (key, h) in stopped {
+            h.stop();
+            inner.end_session(&key);
+        }
+    }
+
+    /// Follow car `index` (the player's for `None`) with the source under
+    /// `key`; false if it isn't running or only sees one car. The lap in
+    /// progress belongs to the car followed so far, so it's dropped.
+    pub fn follow_car(&self, key: &str, index: Option<u8>) -> bool {
+        let followed = self.sources.lock().get(key).is_some_and(|h| h.source.follow_car(index));
+        if followed {
+            if let Some(b) = self.inner.lock().builders.get_mut(key) {
+                b.restart_lap();
+            }
+        }
+        followed
+    }
+
+    /// Packet counters of the source under `key`, if it's running and keeps them.
+    pub fn ingest_counters(&self, key: &str) -> Option<Arc<IngestCounters>> {
+        self.sources.lock().get(key).and_then(|h| h.source.counters())
+    }
+
+    pub fn source_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.sources.lock().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Running sources with their sessions, by key.
+    pub fn sources(&self) -> Vec<SourceInfo> {
+        let keys = self.source_keys();
+        let inner = self.inner.lock();
+        keys.into_iter()
+            .filter_map(|key| {
+                let session = inner.sessions.get(&key)?.clone();
+                let b = inner.builders.get(&key);
+                Some(SourceInfo {
+                    game: b.map(|b| b.game.clone()),
+                    best_ms: b.and_then(|b| b.live.delta().best_ms),
+                    paused: inner.paused.contains(&key),
+                    key,
+                    session,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Track map of `lap`, to keep with [`Inner::keep_track_map`]. Building it
+/// takes a while, so imports do it without the session lock.
+pub fn track_map_of(lap: &Lap) -> StoredTrackMap {
+    StoredTrackMap {
+        game: lap.meta.game.clone(),
+        track: lap.meta.track.clone(),
+        lap_id: lap.id,
+        lap_time_ms: lap.total_time_ms,
+        updated_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0),
+        map: an::build_track_map(lap),
+    }
+}
+
+/// Ring buffer holding `minutes` of samples; `None` for 0.
+pub fn ring_for(minutes: u32) -> Option<SampleRing> {
+    (minutes > 0).then(|| SampleRing::new(Duration::from_secs(minutes as u64 * 60), minutes as usize * 60 * RING_MAX_HZ))
+}
+
+fn db_path() -> PathBuf {
+    data_dir().join("laps.db")
+}
+
+fn journal_dir() -> PathBuf {
+    data_dir().join("journal")
 }
 
-// Build laps out of telemetry samples
-pub struct LapBuilder {
-    pub current: Option<Lap>,
-    pub last: Option<TelemetrySample>,
-    pub start_pos: Option<(f32,f32)>,
-    pub cum_dist: f64,
-    pub last_t_ms: f64,
-    pub track_guess_m: f64,
+fn data_dir() -> PathBuf {
+    dirs_next::data_dir().unwrap_or_else(std::env::temp_dir).join("app.delta")
 }
 
-impl LapBuilder {
-    pub fn new(game: &str, car: &str, track: &str) -> Self {
-        Self { current: Some(new_lap(game, car, track, 1)), last: None, start_pos: None, cum_dist: 0.0, last_t_ms: 0.0, track_guess_m: 0.0 }
+/// Bring laps cut short by a crash back from the journal into `laps` and
+/// the lap database, tagged "recovered". Returns their ids.
+fn recover_journal(mut store: Option<&mut LapStore>, laps: &mut HashMap<Uuid, Arc<Lap>>) -> Vec<Uuid> {
+    let found = match journal::recover(&journal_dir()) {
+        Ok(found) => found,
+        Err(e) => { errors::report("reading the journal", e); return Vec::new(); }
+    };
+    let mut ids = Vec::new();
+    for r in found {
+        if let Some(store) = store.as_deref_mut() {
+            let saved = (|| -> anyhow::Result<()> {
+                // the session is only in the database once it completed a lap
+                let session = match r.session_id {
+                    Some(id) if !store.sessions()?.iter().any(|s| s.id == id) => {
+                        store.upsert_session(&StoredSession {
+                            id,
+                            name: format!("{} {}", r.source, r.lap.meta.track),
+                            game: r.lap.meta.game.clone(),
+                            car: r.lap.meta.car.clone(),
+                            track: r.lap.meta.track.clone(),
+                            started_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0),
+                        })?;
+                        Some(id)
+                    }
+                    other => other,
+                };
+                store.insert_lap(&r.lap, session)?;
+                store.add_tag(r.lap.id, "recovered")
+            })();
+            if let Err(e) = saved { errors::report("saving recovered lap", e); }
+        }
+        ids.push(r.lap.id);
+        laps.insert(r.lap.id, Arc::new(r.lap));
     }
+    ids
 }
 
-fn new_lap(game: &str, car: &str, track: &str, num: u32) -> Lap {
-    Lap {
-        id: Uuid::new_v4(),
-        meta: LapMeta { id: Uuid::new_v4(), game: game.into(), car: car.into(), track: track.into(), lap_number: num },
-        total_time_ms: 0,
-        points: vec![]
+/// The session's laps, as served by the HTTP API.
+pub struct SessionLaps;
+
+impl LapProvider for SessionLaps {
+    fn laps(&self) -> Vec<LapInfo> {
+        let inner = SESSION.inner.lock();
+        inner.lap_heads().map(|l| LapInfo {
+            id: l.id,
+            meta: l.meta.clone(),
+            total_time_ms: l.total_time_ms,
+            session_id: inner.lap_sessions.get(&l.id).copied(),
+        }).collect()
+    }
+
+    fn lap(&self, id: Uuid) -> Option<Arc<Lap>> {
+        SESSION.inner.lock().lap(id).map_err(|e| errors::report("loading lap", e)).ok().flatten()
+    }
+
+    fn live(&self) -> Vec<LiveView> {
+        let inner = SESSION.inner.lock();
+        let mut views: Vec<LiveView> = inner.builders.iter().filter_map(|(key, b)| {
+            let s = b.last.as_ref()?;
+            let d = b.live.delta();
+            Some(LiveView {
+                source: key.clone(),
+                game: b.game.clone(),
+                track: b.track.clone(),
+                lap_number: d.lap_number,
+                lap_time_ms: d.elapsed_ms,
+                delta_ms: d.delta_ms,
+                predicted_ms: d.predicted_ms,
+                best_ms: d.best_ms,
+                last_lap_ms: b.last_lap_ms,
+                speed_kph: (s.speed_mps * 3.6) as f64,
+                gear: s.gear,
+                // the frame laps are built in
+                x: s.world_pos_x as f64,
+                y: s.world_pos_y as f64,
+            })
+        }).collect();
+        views.sort_by(|a, b| a.source.cmp(&b.source));
+        views
+    }
+
+    fn track_map(&self, game: &str, track: &str) -> Option<TrackMap> {
+        SESSION.inner.lock().track_map(Some(game), track).map(|m| m.map)
     }
+
+    fn metrics(&self) -> Vec<SourceMetrics> {
+        SESSION.source_keys().into_iter().map(|key| {
+            let packets = SESSION.ingest_counters(&key).map(|c| c.snapshot());
+            let inner = SESSION.inner.lock();
+            let counts = inner.counts.get(&key).copied().unwrap_or_default();
+            let b = inner.builders.get(&key);
+            SourceMetrics {
+                packets: packets.map(|p| p.packets),
+                bytes: packets.map(|p| p.bytes),
+                parse_errors: packets.map(|p| p.malformed),
+                samples: counts.samples,
+                dropped_samples: counts.paused + counts.cut_short + counts.dropped + b.map_or(0, |b| b.dropped),
+                laps_completed: counts.laps,
+                current_lap_ms: b.filter(|b| b.last.is_some()).map(|b| b.live.delta().elapsed_ms),
+                last_lap_ms: b.and_then(|b| b.last_lap_ms),
+                best_lap_ms: b.and_then(|b| b.live.delta().best_ms),
+                source: key,
+            }
+        }).collect()
+    }
+}
+
+/// Live state of the car `b` builds laps for, once it has sent a sample.
+fn live_state(b: &LapBuilder, source: &str) -> Option<LiveState> {
+    Some(LiveState {
+        source: source.into(),
+        sample: b.last.clone()?,
+        delta: b.live.delta().clone(),
+        stream: b.live.state().clone(),
+        last_lap_ms: b.last_lap_ms,
+    })
 }
 
 impl Inner {
-    pub fn feed_sample(&mut self, key: &str, s: &TelemetrySample) {
-        let (game, car, track) = (format!("{:?}", s.game).to_lowercase(), "Unknown", "Unknown");
-        let b = self.builders.entry(key.to_string()).or_insert_with(|| LapBuilder::new(&game, car, track));
-        // initialise start pos
-        let posx = s.world_pos_x; let posy = s.world_pos_z;
-        if b.start_pos.is_none() && s.speed_mps > 0.1 { b.start_pos = Some((posx, posy)); }
-
-        // compute time and distance
-        let t_ms = s.sim_time_s * 1000.0;
-        let mut lap_dist = s.lap_distance_m as f64;
-        if lap_dist <= 0.0 {
-            if let Some(last) = &b.last {
-                let dx = (s.world_pos_x - last.world_pos_x) as f64;
-                let dy = (s.world_pos_z - last.world_pos_z) as f64;
-                let step = (dx*dx + dy*dy).sqrt();
-                b.cum_dist += step;
+    /// Start a fresh session for `key`: a new session id, and new lap
+    /// builder and live delta so nothing carries over from the last run.
+    fn begin_session(&mut self, key: &str) {
+        self.end_session(key);
+        self.open_session(key);
+    }
+
+    fn open_session(&mut self, key: &str) {
+        let started_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+        self.sessions.insert(key.to_string(), SourceSession { id: Uuid::new_v4(), source: key.to_string(), started_at, laps: Vec::new() });
+    }
+
+    fn end_session(&mut self, key: &str) {
+        self.close_session(key);
+        self.last_sample_event.remove(key);
+        self.paused.remove(key);
+        self.probes.remove(key);
+        self.counts.remove(key);
+        self.session_uids.remove(key);
+        self.history.remove(key);
+    }
+
+    /// `key` moved to another game session (a restart, a new session or
+    /// rejoining): drop the lap in progress and carry on in a new session,
+    /// so laps of the two never run into each other. The source keeps
+    /// running, paused or not.
+    fn roll_session(&mut self, key: &str) {
+        if !self.sessions.contains_key(key) {
+            return;
+        }
+        let cut_short = self.builders.get(key).and_then(|b| b.current.as_ref()).map_or(0, |l| l.points.len() as u64);
+        self.counts.entry(key.to_string()).or_default().cut_short += cut_short;
+        self.close_session(key);
+        self.open_session(key);
+    }
+
+    /// Finish `key`'s session: tell Discord how it went and throw away the
+    /// lap in progress.
+    fn close_session(&mut self, key: &str) {
+        let ended = self.sessions.remove(key);
+        if let (Some(discord), Some(sess)) = (&self.discord, ended) {
+            let laps: Vec<Arc<Lap>> = sess.laps.iter().filter_map(|id| self.laps.get(id).cloned()).collect();
+            if let Some(last) = laps.last() {
+                discord.notify(&Notification::SessionEnd {
+                    source: key.to_string(),
+                    meta: last.meta.clone(),
+                    summary: an::session_summary(&laps),
+                });
             }
-            lap_dist = b.cum_dist;
-        } else {
-            b.cum_dist = lap_dist;
         }
+        if let Some(b) = self.builders.remove(key) {
+            self.counts.entry(key.to_string()).or_default().dropped += b.dropped;
+        }
+        if let Err(e) = self.journal.clear(key) { errors::report("clearing the journal", e); }
+    }
 
-        if let Some(lap) = &mut b.current {
-            lap.points.push(TelemetryPoint {
-                t_ms, lap_distance_m: lap_dist,
-                x: posx as f64, y: posy as f64,
-                speed_kph: (s.speed_mps * 3.6) as f64,
-                throttle: s.throttle as f64,
-                brake: s.brake as f64,
-                gear: s.gear,
-                rpm: s.engine_rpm as f64,
-            });
-            lap.total_time_ms = (t_ms - lap.points.first().map(|p| p.t_ms).unwrap_or(t_ms)) as u64;
+    /// Stop building laps from `key`'s samples, dropping the lap in progress.
+    /// The session carries on; false if `key` isn't running or already paused.
+    pub fn pause(&mut self, key: &str) -> bool {
+        if !self.sessions.contains_key(key) || !self.paused.insert(key.to_string()) {
+            return false;
         }
+        if let Some(b) = self.builders.get_mut(key) {
+            b.restart_lap();
+        }
+        if let Err(e) = self.journal.clear(key) { errors::report("clearing the journal", e); }
+        true
+    }
+
+    /// Build laps from `key`'s samples again. The first lap is partial and
+    /// not kept; laps count from the next time the car crosses the line.
+    pub fn resume(&mut self, key: &str) -> bool {
+        if !self.paused.remove(key) {
+            return false;
+        }
+        if let Some(b) = self.builders.get_mut(key) {
+            b.restart_lap();
+        }
+        true
+    }
 
-        // detect lap end
-        let mut roll = false;
-        // 1) explicit lap number increase
-        if let Some(last) = &b.last {
-            if s.current_lap > last.current_lap && s.current_lap > 0 {
-                roll = true;
+    // anything that takes live samples or lap events
+    fn has_listeners(&self) -> bool {
+        self.app.is_some() || self.server.is_some() || self.mqtt.is_some() || self.nats.is_some()
+            || self.influx.is_some() || self.postgres.is_some() || self.grpc_running()
+    }
+
+    fn grpc_running(&self) -> bool {
+        #[cfg(feature = "grpc")]
+        return self.grpc.is_some();
+        #[cfg(not(feature = "grpc"))]
+        false
+    }
+
+    pub fn feed_sample(&mut self, key: &str, s: &TelemetrySample) {
+        if self.session_uids.get(key).is_none_or(|uid| *uid != s.session_uid)
+            && self.session_uids.insert(key.to_string(), s.session_uid.clone()).is_some()
+        {
+            self.roll_session(key);
+        }
+        if let Some(probe) = self.probes.get_mut(key) {
+            probe.observe(s);
+        }
+        let counts = self.counts.entry(key.to_string()).or_default();
+        counts.samples += 1;
+        if let Some(rec) = &mut self.recorder {
+            if let Err(e) = rec.write(s) {
+                errors::report(&format!("recording to {} (stopped)", rec.path().display()), e);
+                self.recorder = None;
             }
         }
-        // 2) heuristics when no lap numbers: near start pos and elapsed > 15s
-        if !roll {
-            if let (Some(sp), Some(lap)) = (b.start_pos, &b.current) {
-                let dx = (posx - sp.0) as f64; let dy = (posy - sp.1) as f64;
-                let d = (dx*dx + dy*dy).sqrt();
-                let elapsed = t_ms - lap.points.first().map(|p| p.t_ms).unwrap_or(t_ms);
-                if d < 20.0 && elapsed > 15000.0 && s.speed_mps > 1.0 { roll = true; }
+        if let Some(ring) = &mut self.ring {
+            ring.push(s);
+        }
+        match self.history.get_mut(key) {
+            Some(history) => history.push(s),
+            None => {
+                let mut history = SampleRing::new(HISTORY_WINDOW, HISTORY_WINDOW.as_secs() as usize * HISTORY_MAX_HZ);
+                history.push(s);
+                self.history.insert(key.to_string(), history);
             }
         }
-
-        if roll {
-            if let Some(mut finished) = b.current.take() {
-                // sanity: set total time precisely
-                finished.total_time_ms = (t_ms - finished.points.first().map(|p| p.t_ms).unwrap_or(t_ms)) as u64;
-                // normalize lap distance to end value
-                let lastd = finished.points.last().map(|p| p.lap_distance_m).unwrap_or(0.0);
-                if lastd > b.track_guess_m { b.track_guess_m = lastd; }
-                // insert
-                self.laps.insert(finished.id, finished);
-                // new lap
-                let next_num = s.current_lap.max(1);
-                b.current = Some(new_lap(&game, car, track, next_num));
-                b.cum_dist = 0.0;
+        // the rig moves with the car whether laps are being built or not
+        if let Some(osc) = &mut self.osc {
+            osc.send(key, s);
+        }
+        // the coach builds their own laps, so gets every sample
+        if let Some(relay) = &self.relay {
+            relay.send(key, s);
+        }
+        if let Some(serial) = &mut self.serial {
+            let delta = self.builders.get(key).map(|b| b.live.delta());
+            if let Err(e) = serial.send(key, s, delta, self.paused.contains(key)) {
+                errors::report("serial output (stopped)", e);
+                self.serial = None;
             }
         }
+        if self.paused.contains(key) {
+            counts.paused += 1;
+            return;
+        }
+        let b = self.builders.entry(key.to_string()).or_insert_with(|| {
+            let mut b = LapBuilder::for_sample(s);
+            b.thin = self.settings.thin_laps.then(ThinOptions::default);
+            b.completion = self.settings.lap_completion_for(&b.game);
+            b
+        });
+        let best_ms = b.live.delta().best_ms;
+        let finished = b.push(s);
+        if let Some(lap) = &finished {
+            counts.laps += 1;
+            if let Some(serial) = &mut self.serial {
+                serial.lap_completed(key, best_ms.is_none_or(|b| lap.total_time_ms < b));
+            }
+        }
+        let journaled = match (&finished, &b.current) {
+            (Some(_), _) => self.journal.clear(key),
+            (None, Some(lap)) if !lap.points.is_empty() && !b.partial => {
+                self.journal.snapshot(key, self.sessions.get(key).map(|s| s.id), lap).map(|_| ())
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = journaled { errors::report("journaling lap", e); }
+        // gRPC clients get every sample, not just the live rate
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            grpc.publish_sample(key, s, b.live.delta());
+        }
+        let tags = Tags { source: key, game: &b.game, car: &b.car, track: &b.track };
+        if let Some(influx) = &mut self.influx {
+            influx.write_sample(tags, s, b.live.delta());
+        }
+        if self.has_listeners() {
+            let due = self.sample_hz > 0.0
+                && self.last_sample_event.get(key).is_none_or(|t| t.elapsed().as_secs_f64() >= 1.0 / self.sample_hz);
+            if due {
+                self.last_sample_event.insert(key.to_string(), Instant::now());
+                if let Some(state) = live_state(b, key) {
+                    if let Some(app) = &self.app {
+                        let _ = app.emit(SAMPLE_EVENT, &state);
+                    }
+                    if let Some(server) = &self.server {
+                        if let Err(e) = server.publish(SERVER_SAMPLE, &state) { errors::report("websocket publish", e); }
+                    }
+                    if let Some(mqtt) = &self.mqtt {
+                        if let Err(e) = mqtt.publish_sample(key, &state) { errors::report("mqtt publish", e); }
+                    }
+                    if let Some(nats) = &self.nats {
+                        if let Err(e) = nats.publish_sample(key, &state) { errors::report("nats publish", e); }
+                    }
+                }
+            }
+            if let Some(lap) = &finished {
+                // split on the cached map's sectors so splits line up lap to lap
+                let sectors = match self.track_maps.get(&(lap.meta.game.clone(), lap.meta.track.clone())) {
+                    Some(m) => m.map.sectors.clone(),
+                    None => an::build_track_map(lap).sectors,
+                };
+                let event = LapEvent {
+                    source: key,
+                    id: lap.id,
+                    meta: &lap.meta,
+                    total_time_ms: lap.total_time_ms,
+                    sector_ms: an::compare::sector_times(lap, &sectors),
+                    best_ms,
+                    delta_to_best_ms: best_ms.map(|b| lap.total_time_ms as i64 - b as i64),
+                };
+                if let Some(app) = &self.app {
+                    let _ = app.emit(LAP_EVENT, &event);
+                }
+                if let Some(server) = &self.server {
+                    if let Err(e) = server.publish(SERVER_LAP, &event) { errors::report("websocket publish", e); }
+                }
+                if let Some(mqtt) = &self.mqtt {
+                    if let Err(e) = mqtt.publish_lap(key, &event) { errors::report("mqtt publish", e); }
+                }
+                if let Some(nats) = &self.nats {
+                    if let Err(e) = nats.publish_lap(key, &event) { errors::report("nats publish", e); }
+                }
+                if let Some(influx) = &self.influx {
+                    influx.write_lap(tags, lap, &event.sector_ms, best_ms.is_none_or(|b| lap.total_time_ms < b));
+                }
+                if let Some(pg) = &self.postgres {
+                    let session = self.sessions.get(key).map(|s| PgSession { id: s.id, started_at: s.started_at });
+                    pg.write_lap(key, session, lap, &event.sector_ms);
+                }
+                #[cfg(feature = "grpc")]
+                if let Some(grpc) = &self.grpc {
+                    grpc.publish_lap(key, lap, &event.sector_ms, best_ms);
+                }
+            }
+        }
+        if let Some(finished) = finished {
+            let session = self.sessions.get_mut(key).map(|sess| {
+                sess.laps.push(finished.id);
+                sess.clone()
+            });
+            if let Some(sess) = &session {
+                self.lap_sessions.insert(finished.id, sess.id);
+            }
+            let finished = Arc::new(finished);
+            if let Some(writer) = &self.writer {
+                let session = session.map(|sess| StoredSession {
+                    id: sess.id,
+                    name: format!("{} {}", key, finished.meta.track),
+                    game: finished.meta.game.clone(),
+                    car: finished.meta.car.clone(),
+                    track: finished.meta.track.clone(),
+                    started_at: sess.started_at,
+                });
+                writer.save_lap(finished.clone(), session);
+            }
+            if let Some(autosave) = &mut self.autosave {
+                if let Err(e) = autosave.save_lap(&finished) { errors::report("autosaving lap", e); }
+            }
+            if let Some(discord) = &self.discord {
+                self.notify_lap(discord, &finished);
+            }
+            self.offer_track_map(&finished);
+            self.known.insert(&finished);
+            self.laps.insert(finished.id, finished);
+        }
+    }
+
+    /// Tell Discord about `lap`, just completed: whether it beat every
+    /// earlier lap with its car and track, and its incidents. A first lap
+    /// with a car and track has nothing to beat and isn't posted as a best,
+    /// and neither are out-laps and in-laps.
+    fn notify_lap(&self, discord: &DiscordNotifier, lap: &Lap) {
+        let previous_ms = self.lap_heads()
+            .filter(|l| l.meta.game == lap.meta.game && l.meta.car == lap.meta.car && l.meta.track == lap.meta.track)
+            .filter(|l| l.meta.is_flying())
+            .map(|l| l.total_time_ms)
+            .min();
+        if let Some(previous_ms) = previous_ms.filter(|&p| lap.meta.is_flying() && lap.total_time_ms < p) {
+            discord.notify(&Notification::PersonalBest { meta: lap.meta.clone(), time_ms: lap.total_time_ms, previous_ms });
+        }
+        for marker in &lap.markers {
+            discord.notify(&Notification::Incident { meta: lap.meta.clone(), marker: marker.clone() });
+        }
+    }
+
+    /// Note a file just imported or exported in the recent items.
+    pub fn remember_file(&mut self, kind: RecentKind, path: &str) {
+        self.recent.file(kind, path);
+        self.save_recent();
+    }
+
+    pub fn remember_session(&mut self, id: Uuid, name: &str) {
+        self.recent.session(id, name);
+        self.save_recent();
+    }
+
+    fn save_recent(&self) {
+        if let Err(e) = recent::save(&self.recent) { errors::report("saving recent items", e); }
+    }
+
+    /// Id, metadata and time of every lap, whether its points are loaded or not.
+    pub fn lap_heads(&self) -> impl Iterator<Item = LapHead<'_>> {
+        let loaded = self.laps.values().map(|l| LapHead { id: l.id, meta: &l.meta, total_time_ms: l.total_time_ms });
+        let stored = self.stored.values().map(|l| LapHead { id: l.id, meta: &l.meta, total_time_ms: l.total_time_ms });
+        loaded.chain(stored)
+    }
+
+    pub fn has_lap(&self, id: Uuid) -> bool {
+        self.laps.contains_key(&id) || self.stored.contains_key(&id)
+    }
+
+    /// Lap `id` with its points, read from the lap database the first time
+    /// it's needed and kept from then on; `None` if there is no such lap.
+    pub fn lap(&mut self, id: Uuid) -> Result<Option<Arc<Lap>>, String> {
+        if let Some(l) = self.laps.get(&id) {
+            return Ok(Some(l.clone()));
+        }
+        if !self.stored.contains_key(&id) {
+            return Ok(None);
+        }
+        let store = self.store.as_ref().ok_or("lap database unavailable")?;
+        let Some(lap) = store.lap(id).map_err(|e| format!("{e:#}"))? else { return Ok(None) };
+        self.stored.remove(&id);
+        let lap = Arc::new(lap);
+        self.laps.insert(id, lap.clone());
+        Ok(Some(lap))
+    }
+
+    /// Laps `ids`, or every lap when `None`, with their points; unknown ids
+    /// are left out.
+    pub fn load_laps(&mut self, ids: Option<&[Uuid]>) -> Result<Vec<Arc<Lap>>, String> {
+        let ids: Vec<Uuid> = match ids {
+            Some(ids) => ids.to_vec(),
+            None => self.lap_heads().map(|l| l.id).collect(),
+        };
+        let mut laps = Vec::with_capacity(ids.len());
+        for id in ids {
+            laps.extend(self.lap(id)?);
+        }
+        Ok(laps)
+    }
+
+    /// Wait for queued lap database writes, before an edit that must land
+    /// after them.
+    pub fn flush_writes(&self) {
+        if let Some(writer) = &self.writer {
+            writer.flush();
+        }
+    }
+
+    /// Use `lap` for its track's map if [`Inner::improves_track_map`].
+    /// Returns whether the map was replaced.
+    pub fn offer_track_map(&mut self, lap: &Lap) -> bool {
+        self.improves_track_map(lap) && self.keep_track_map(track_map_of(lap))
+    }
+
+    /// Whether `lap` is clean (no spins or off-tracks, not an out-lap or
+    /// in-lap) and faster than the lap its track's cached map came from.
+    pub fn improves_track_map(&self, lap: &Lap) -> bool {
+        if lap.points.len() < 2 || lap.total_time_ms == 0 || !lap.markers.is_empty() || !lap.meta.is_flying() {
+            return false;
+        }
+        let k = (lap.meta.game.clone(), lap.meta.track.clone());
+        self.track_maps.get(&k).is_none_or(|m| m.lap_time_ms > lap.total_time_ms)
+    }
+
+    /// Cache and save `m` unless a map from a lap at least as fast got there
+    /// first; returns whether it was kept.
+    pub fn keep_track_map(&mut self, m: StoredTrackMap) -> bool {
+        let k = (m.game.clone(), m.track.clone());
+        if self.track_maps.get(&k).is_some_and(|old| old.lap_time_ms <= m.lap_time_ms) {
+            return false;
+        }
+        if let Some(writer) = &self.writer {
+            writer.save_track_map(m.clone());
+        }
+        self.track_maps.insert(k, m);
+        true
+    }
+
+    /// Cached map of `track` (in `game`, or the most recently updated of any
+    /// game), built from the best clean session lap when there is none yet.
+    pub fn track_map(&mut self, game: Option<&str>, track: &str) -> Option<StoredTrackMap> {
+        let cached = |maps: &HashMap<(String, String), StoredTrackMap>| {
+            maps.values()
+                .filter(|m| m.track == track && game.is_none_or(|g| m.game == g))
+                .max_by_key(|m| m.updated_at)
+                .cloned()
+        };
+        if let Some(m) = cached(&self.track_maps) {
+            return Some(m);
+        }
+        let mut candidates: Vec<(u64, Uuid)> = self.lap_heads()
+            .filter(|l| l.meta.track == track && game.is_none_or(|g| l.meta.game == g) && l.meta.is_flying())
+            .map(|l| (l.total_time_ms, l.id))
+            .collect();
+        candidates.sort();
+        // markers and points are only known once a lap is loaded
+        let best = candidates.into_iter()
+            .filter_map(|(_, id)| self.lap(id).map_err(|e| errors::report("loading lap", e)).ok().flatten())
+            .find(|l| l.markers.is_empty() && l.points.len() > 1)?;
+        self.offer_track_map(&best);
+        cached(&self.track_maps)
+    }
+
+    /// Latest sample and running lap of every source, by source key.
+    pub fn live_states(&self) -> Vec<LiveState> {
+        let mut states: Vec<LiveState> = self.builders.iter().filter_map(|(k, b)| live_state(b, k)).collect();
+        states.sort_by(|a, b| a.source.cmp(&b.source));
+        states
+    }
+
+    /// `key`'s samples of the last `span` (at most `HISTORY_WINDOW`),
+    /// oldest first; empty for a source that isn't running.
+    pub fn live_history(&self, key: &str, span: Duration) -> Vec<TelemetrySample> {
+        self.history.get(key).map(|h| h.last(span).cloned().collect()).unwrap_or_default()
+    }
 
-        b.last = Some(s.clone());
-        b.last_t_ms = t_ms;
+    /// Latest live delta for every source that has produced samples.
+    pub fn live_deltas(&self) -> HashMap<String, LiveDeltaState> {
+        self.builders.iter().map(|(k, b)| (k.clone(), b.live.delta().clone())).collect()
     }
 }
 
-pub fn run_source<S: TelemetrySource + 'static>(src: S, rx_key: String, sess: &'static AppSession) {
+fn run_source(src: Arc<dyn TelemetrySource>, rx_key: String, sess: &'static AppSession) -> SourceHandle {
     let (tx, rx): (_, TelemetryRx) = channel();
-    tokio::spawn(async move {
-        let _ = src.run(tx).await;
+    let source = src.clone();
+    let key = rx_key.clone();
+    let task = tauri::async_runtime::spawn(async move {
+        if let Err(e) = src.run(tx).await { errors::report(&format!("source {key}"), e); }
     });
+    let cancel = Arc::new(AtomicBool::new(false));
+    let stop = cancel.clone();
     // pump samples into session (blocking thread)
     std::thread::spawn(move || {
-        loop {
-            match rx.recv() {
+        while !stop.load(Ordering::Relaxed) {
+            match rx.recv_timeout(Duration::from_millis(100)) {
                 Ok(sample) => {
                     let mut inner = sess.inner.lock();
+                    // stopped while this one was on its way: the session it
+                    // belongs to has ended, or another source's has begun
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
                     inner.feed_sample(&rx_key, &sample);
                 }
-                Err(_) => { thread::sleep(Duration::from_millis(10)); }
+                Err(RecvTimeoutError::Timeout) => {}
+                // the source task ended and dropped its sender
+                Err(RecvTimeoutError::Disconnected) => break,
             }
         }
     });
+    SourceHandle { source, task, cancel }
 }
@@ -0,0 +1,188 @@
+//! User settings persisted as JSON in the platform config dir
+//! (`~/.config/app.delta/settings.json` on Linux).
+//!
+//! Connectors fall back on these when a start command leaves a value out, so
+//! ports and the console address are set once instead of on every start.
+//! Missing keys take their defaults, so older files keep loading as fields
+//! are added.
+
+use std::{collections::BTreeMap, fs, path::PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use delta_ingest_remote::relay::RelayOptions;
+use delta_server::discord::DiscordOptions;
+use delta_server::influx::InfluxOptions;
+use delta_server::pg::PgOptions;
+use delta_server::mqtt::MqttOptions;
+use delta_server::nats::NatsOptions;
+use delta_server::osc::OscOptions;
+use delta_server::serial::SerialOptions;
+use iox::build::LapCompletion;
+use iox::SpeedUnit;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub f1_port: u16,
+    /// Packet format year the F1 game is set to send.
+    pub f1_format: u16,
+    /// F1 car to follow by index, e.g. when spectating a league race; the
+    /// player's when `None`.
+    pub f1_car_index: Option<u8>,
+    /// Empty until the user enters their console's address.
+    pub gt7_console_ip: String,
+    /// Heartbeat packet variant: "A", "B" or "~".
+    pub gt7_variant: String,
+    pub gt7_bind_port: u16,
+    /// Port a driver's relay connects to when watching them remotely.
+    pub remote_port: u16,
+    /// Token a driver's relay must present; empty until one is set.
+    pub remote_token: String,
+    pub speed_unit: SpeedUnit,
+    /// Folder completed laps are autosaved to; autosave is off when `None`.
+    pub autosave_dir: Option<String>,
+    pub theme: Theme,
+    /// Minutes of telemetry kept in memory for `save_last`; 0 turns it off.
+    pub ring_minutes: u32,
+    /// Thin points on straights of laps as they're recorded; corners and
+    /// braking zones keep every point.
+    pub thin_laps: bool,
+    /// How laps end, per game as laps name it ("f1_2025", "gt7", "lmu");
+    /// games left out go by the lap number.
+    pub lap_completion: BTreeMap<String, LapCompletion>,
+    /// Run the WebSocket server for dashboards and overlays on the LAN;
+    /// started at launch when set.
+    pub ws_server: bool,
+    pub ws_port: u16,
+    /// Run the HTTP API over the laps; started at launch when set.
+    pub api_server: bool,
+    pub api_port: u16,
+    /// Serve the API to the whole LAN instead of only this machine.
+    pub api_lan: bool,
+    /// Publish live samples and laps to an MQTT broker; connected at launch
+    /// when set.
+    pub mqtt_enabled: bool,
+    pub mqtt: MqttOptions,
+    /// Publish live data to a NATS server; connected at launch when set.
+    pub nats_enabled: bool,
+    pub nats: NatsOptions,
+    /// Send channels as OSC to a motion rig or shaker software; on at launch
+    /// when set.
+    pub osc_enabled: bool,
+    pub osc: OscOptions,
+    /// Send frames to a DIY dash on a serial port; opened at launch when set.
+    pub serial_enabled: bool,
+    pub serial: SerialOptions,
+    /// Post personal bests, session summaries and incidents to a Discord
+    /// webhook.
+    pub discord_enabled: bool,
+    pub discord: DiscordOptions,
+    /// Store samples and lap summaries in InfluxDB; on at launch when set.
+    pub influx_enabled: bool,
+    pub influx: InfluxOptions,
+    /// Stream laps and points into PostgreSQL/TimescaleDB; on at launch
+    /// when set.
+    pub postgres_enabled: bool,
+    pub postgres: PgOptions,
+    /// Relay every sample to a coach's instance; reconnected at launch
+    /// when set.
+    pub relay_enabled: bool,
+    pub relay: RelayOptions,
+    /// Run the gRPC service on the LAN; started at launch when set. Only
+    /// builds with the `grpc` feature have it.
+    pub grpc_server: bool,
+    pub grpc_port: u16,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            f1_port: 20777,
+            f1_format: 2025,
+            f1_car_index: None,
+            gt7_console_ip: String::new(),
+            gt7_variant: "A".into(),
+            gt7_bind_port: 33740,
+            remote_port: delta_ingest_remote::DEFAULT_PORT,
+            remote_token: String::new(),
+            speed_unit: SpeedUnit::default(),
+            autosave_dir: None,
+            theme: Theme::default(),
+            ring_minutes: 30,
+            thin_laps: false,
+            lap_completion: BTreeMap::new(),
+            ws_server: false,
+            ws_port: delta_server::ws::DEFAULT_PORT,
+            api_server: false,
+            api_port: delta_server::rest::DEFAULT_PORT,
+            api_lan: false,
+            mqtt_enabled: false,
+            mqtt: MqttOptions::default(),
+            nats_enabled: false,
+            nats: NatsOptions::default(),
+            osc_enabled: false,
+            osc: OscOptions::default(),
+            serial_enabled: false,
+            serial: SerialOptions::default(),
+            discord_enabled: false,
+            discord: DiscordOptions::default(),
+            influx_enabled: false,
+            influx: InfluxOptions::default(),
+            postgres_enabled: false,
+            postgres: PgOptions::default(),
+            relay_enabled: false,
+            relay: RelayOptions::default(),
+            grpc_server: false,
+            // the port gRPC examples use
+            grpc_port: 50051,
+        }
+    }
+}
+
+impl Settings {
+    /// How laps of `game` end.
+    pub fn lap_completion_for(&self, game: &str) -> LapCompletion {
+        self.lap_completion.get(game).cloned().unwrap_or_default()
+    }
+}
+
+/// Folder the app keeps its settings and other small state files in.
+pub fn config_dir() -> PathBuf {
+    dirs_next::config_dir().unwrap_or_else(std::env::temp_dir).join("app.delta")
+}
+
+pub fn settings_path() -> PathBuf {
+    config_dir().join("settings.json")
+}
+
+/// Saved settings, or the defaults when there are none yet.
+pub fn load() -> Result<Settings> {
+    let path = settings_path();
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+    let text = fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+}
+
+pub fn save(settings: &Settings) -> Result<()> {
+    let path = settings_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // write then rename so a crash mid-write keeps the old file
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(settings)?)?;
+    fs::rename(&tmp, &path).with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
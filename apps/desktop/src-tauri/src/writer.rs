@@ -0,0 +1,80 @@
+//! Lap database writes that recording and imports shouldn't wait for. Laps,
+//! their sessions and track maps are queued to a thread with its own
+//! connection, so no database write happens under the session lock.
+
+use std::{path::Path, sync::Arc};
+use anyhow::Result;
+use crossbeam_channel::{bounded, unbounded, Sender};
+
+use model::Lap;
+use storage::{LapStore, StoredSession, StoredTrackMap};
+use crate::errors;
+
+enum Write {
+    Lap { lap: Arc<Lap>, session: Option<StoredSession> },
+    TrackMap(StoredTrackMap),
+    /// Answered once everything queued before it is written.
+    Flush(Sender<()>),
+}
+
+pub struct StoreWriter {
+    tx: Sender<Write>,
+}
+
+impl StoreWriter {
+    /// Start writing to the lap database at `path` on a thread of its own.
+    /// Failed writes go to [`errors::report`].
+    pub fn spawn(path: &Path) -> Result<Self> {
+        let mut store = LapStore::open(path)?;
+        let (tx, rx) = unbounded::<Write>();
+        std::thread::Builder::new().name("lap-store-writer".into()).spawn(move || {
+            for w in rx {
+                match w {
+                    Write::Lap { lap, session } => {
+                        let saved = match &session {
+                            Some(s) => store.upsert_session(s),
+                            None => Ok(()),
+                        };
+                        if let Err(e) = saved.and_then(|_| store.insert_lap(&lap, session.map(|s| s.id))) {
+                            errors::report("saving lap", e);
+                        }
+                    }
+                    Write::TrackMap(m) => {
+                        if let Err(e) = store.put_track_map(&m) {
+                            errors::report("saving track map", e);
+                        }
+                    }
+                    Write::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        })?;
+        Ok(Self { tx })
+    }
+
+    /// Queue `lap` and, for a recorded lap, its session. The session is
+    /// written with every lap so it picks up the car and track once the
+    /// source reports them.
+    pub fn save_lap(&self, lap: Arc<Lap>, session: Option<StoredSession>) {
+        self.send(Write::Lap { lap, session });
+    }
+
+    pub fn save_track_map(&self, m: StoredTrackMap) {
+        self.send(Write::TrackMap(m));
+    }
+
+    /// Wait until everything queued so far is written, so a change made on
+    /// another connection (an edit, a delete) lands after it.
+    pub fn flush(&self) {
+        let (done, wait) = bounded(1);
+        self.send(Write::Flush(done));
+        let _ = wait.recv();
+    }
+
+    fn send(&self, w: Write) {
+        if self.tx.send(w).is_err() {
+            errors::report("saving to the lap database", "the writer thread stopped");
+        }
+    }
+}
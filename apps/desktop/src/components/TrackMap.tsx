@@ -3,10 +3,12 @@ import { useMemo } from 'react'
 type Point = { x: number; y: number }
 type BBox = { minx: number; maxx: number; miny: number; maxy: number }
 type Corner = { index: number; x: number; y: number; label?: string }
+type Lod = { tolerance_m: number; polyline: Point[] }
 
 type TrackMapData = {
   bbox: BBox
   polyline: Point[]
+  lods?: Lod[]
   corners?: Corner[]
 }
 
@@ -50,6 +52,7 @@ export default function TrackMap({
   const {
     bbox: { minx, maxx, miny, maxy },
     polyline,
+    lods = [],
     corners = [],
   } = map as TrackMapData
 
@@ -64,15 +67,21 @@ export default function TrackMap({
   // Flip Y so higher Y plots visually "up"
   const sy = (y: number) => padding + (1 - (y - miny) / spanY) * innerH
 
+  // the coarsest outline that stays within half a pixel of the driven line
+  const metresPerPx = Math.max(spanX / Math.max(1, innerW), spanY / Math.max(1, innerH))
+  const outline = lods
+    .filter((l) => l.tolerance_m <= metresPerPx / 2 && l.polyline.length > 1)
+    .reduce((best, l) => (l.polyline.length < best.length ? l.polyline : best), polyline)
+
   const pathD = useMemo(() => {
-    return polyline
+    return outline
       .map((p, i) => {
         const px = isFiniteNumber(p.x) ? p.x : 0
         const py = isFiniteNumber(p.y) ? p.y : 0
         return `${i ? 'L' : 'M'} ${sx(px)} ${sy(py)}`
       })
       .join(' ')
-  }, [polyline, minx, maxx, miny, maxy, padding, width, height])
+  }, [outline, minx, maxx, miny, maxy, padding, width, height])
 
   return (
     <svg
@@ -1,21 +1,258 @@
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 
-export const startF1 = (port:number=20777, format:number=2025) => invoke('start_f1', { port, format })
-export const startGT7 = (consoleIp:string, variant:string='A', bindPort:number=33740) => invoke('start_gt7', { consoleIp, variant, bindPort })
+// arguments left out come from the saved settings
+export type PortConflict = {
+  error: 'port_conflict', kind: 'in_use'|'permission_denied'|'address_unavailable'|'other',
+  bind_addr: string, port: number, owner: { pid: number, name: string } | null, message: string, suggestion: string,
+}
+/** Rejection of the commands that bind a UDP port. */
+export type StartError = { error: 'failed', message: string } | PortConflict
+/** Message for a StartError, with the suggested fix of a port conflict. */
+export const startErrorText = (e: any, fallback: string) =>
+  e?.error === 'port_conflict' && e.suggestion ? `${e.message}. ${e.suggestion}` : e?.message ?? fallback
+export const startF1 = (port?: number, format?: number, carIndex?: number) => invoke('start_f1', { port, format, carIndex })
+// follow another car of the session, e.g. the one being spectated; the player's when left out
+export const followF1Car = (index?: number) => invoke('follow_f1_car', { index })
+export const startGT7 = (consoleIp?: string, variant?: string, bindPort?: number) => invoke('start_gt7', { consoleIp, variant, bindPort })
+export type DiscoveredConsole = { ip: string, hostname: string | null, latency_ms: number }
+export const discoverConsoles = (timeoutMs?: number) => invoke('discover_consoles', { timeoutMs }) as Promise<DiscoveredConsole[]>
 export const startLMU = () => invoke('start_lmu')
+// a driver's session relayed from their instance; see startRelay for their end
+export const startRemote = (port?: number, token?: string) => invoke('start_remote', { port, token })
+export const newRelayToken = () => invoke('new_relay_token') as Promise<string>
 
 export const stopAll = () => invoke('stop_all')
+export type SourceKey = 'f1'|'gt7'|'lmu'|'remote'
+export const stopSource = (key: SourceKey) => invoke('stop_source', { key })
+export const restartSource = (key: SourceKey) => invoke('restart_source', { key })
+export type Diagnosis = {
+  duration_s: number, packets: number | null, packet_rate_hz: number | null, parse_success: number | null,
+  parse_errors: number | null, avg_parse_us: number | null, samples: number, sample_rate_hz: number,
+  channels: { channel: string, coverage: number }[], validation_failures: { check: string, count: number }[],
+}
+export const diagnoseSource = (key: SourceKey, seconds?: number) => invoke('diagnose_source', { key, seconds }) as Promise<Diagnosis>
+export type WsServerStatus = { addr: string, clients: number }
+export const startWsServer = (port?: number) => invoke('start_ws_server', { port }) as Promise<WsServerStatus>
+export const stopWsServer = () => invoke('stop_ws_server')
+export const wsServerStatus = () => invoke('ws_server_status') as Promise<WsServerStatus | null>
+export const startApiServer = (port?: number) => invoke('start_api_server', { port }) as Promise<string>
+export const stopApiServer = () => invoke('stop_api_server')
+export const apiServerAddr = () => invoke('api_server_addr') as Promise<string | null>
+export type MqttOptions = {
+  host: string, port: number, client_id: string, username: string | null, password: string | null,
+  topic_prefix: string, mode: 'sample'|'channels'|'both', qos: 0|1|2, retain_laps: boolean,
+}
+export type MqttStatus = { broker: string, error: string | null }
+export const startMqtt = (options?: MqttOptions) => invoke('start_mqtt', { options }) as Promise<MqttStatus>
+export const stopMqtt = () => invoke('stop_mqtt')
+export const mqttStatus = () => invoke('mqtt_status') as Promise<MqttStatus | null>
+export type NatsOptions = {
+  url: string, driver: string, subject_prefix: string,
+  token: string | null, username: string | null, password: string | null,
+}
+export type NatsStatus = { server: string, connected: boolean, error: string | null }
+export const startNats = (options?: NatsOptions) => invoke('start_nats', { options }) as Promise<NatsStatus>
+export const stopNats = () => invoke('stop_nats')
+export const natsStatus = () => invoke('nats_status') as Promise<NatsStatus | null>
+export type RelayOptions = { url: string, token: string, source: SourceKey | null }
+export type RelayStatus = { target: string, connected: boolean, samples_sent: number, bytes_sent: number, error: string | null }
+export const startRelay = (options?: RelayOptions) => invoke('start_relay', { options }) as Promise<RelayStatus>
+export const stopRelay = () => invoke('stop_relay')
+export const relayStatus = () => invoke('relay_status') as Promise<RelayStatus | null>
+export type OscChannel = 'speed_kph'|'rpm'|'throttle'|'brake'|'gear'|'long_g'|'lat_g'|'vert_g'|'yaw_rate'|'slip'|'pitch'|'roll'
+export type OscMapping = { channel: OscChannel, address: string, scale: number, rate_hz: number | null }
+export type OscOptions = { target: string, rate_hz: number, source: SourceKey | null, mappings: OscMapping[] }
+export const startOsc = (options?: OscOptions) => invoke('start_osc', { options })
+export const stopOsc = () => invoke('stop_osc')
+export type SerialOptions = { port: string, baud: number, rate_hz: number, shift_at: number, source: SourceKey | null }
+export type SerialPortInfo = { name: string, description: string | null }
+export const listSerialPorts = () => invoke('list_serial_ports') as Promise<SerialPortInfo[]>
+export const startSerial = (options?: SerialOptions) => invoke('start_serial', { options })
+export const stopSerial = () => invoke('stop_serial')
+export type DiscordOptions = {
+  webhook_url: string, username: string, driver: string, personal_best: boolean, session_end: boolean,
+  incidents: ('spin'|'lockup'|'crash')[],
+}
+export const startDiscord = (options?: DiscordOptions) => invoke('start_discord', { options })
+export const stopDiscord = () => invoke('stop_discord')
+export const testDiscord = (options: DiscordOptions) => invoke('test_discord', { options })
+export type InfluxOptions = {
+  url: string, org: string, bucket: string, token: string | null, measurement_prefix: string,
+  sample_hz: number, batch_size: number, flush_ms: number,
+}
+export type InfluxStatus = { written: number, dropped: number, error: string | null }
+export const startInflux = (options?: InfluxOptions) => invoke('start_influx', { options })
+export const stopInflux = () => invoke('stop_influx')
+export const influxStatus = () => invoke('influx_status') as Promise<InfluxStatus | null>
+export type PgOptions = { url: string, driver: string, event: string, timescale: boolean }
+export type PgStatus = { connected: boolean, laps_written: number, pending: number, dropped: number, error: string | null }
+export const startPostgres = (options?: PgOptions) => invoke('start_postgres', { options })
+export const stopPostgres = () => invoke('stop_postgres')
+export const postgresStatus = () => invoke('postgres_status') as Promise<PgStatus | null>
+/** Fails in builds without gRPC support. */
+export const startGrpcServer = (port?: number) => invoke('start_grpc_server', { port }) as Promise<string>
+export const stopGrpcServer = () => invoke('stop_grpc_server')
+export const grpcServerAddr = () => invoke('grpc_server_addr') as Promise<string | null>
+/** OBS browser-source URL of the live overlay on the running HTTP API. */
+export const overlayUrl = (addr: string, source?: SourceKey) =>
+  `http://${addr.replace('0.0.0.0', '127.0.0.1')}/overlay${source ? `?source=${source}` : ''}`
+export const pauseRecording = (key?: SourceKey) => invoke('pause_recording', { key }) as Promise<SourceKey[]>
+export const resumeRecording = (key?: SourceKey) => invoke('resume_recording', { key }) as Promise<SourceKey[]>
+export type SourceSession = { id: string, source: SourceKey, started_at: number, laps: string[] }
+export type SourceInfo = { key: SourceKey, session: SourceSession, game: string | null, best_ms: number | null, paused: boolean }
+export const listSources = () => invoke('list_sources') as Promise<SourceInfo[]>
+export type StoredSession = { id: string, name: string, game: string, car: string, track: string, started_at: number }
+export const listSessions = () => invoke('list_sessions') as Promise<StoredSession[]>
 
-export const listLaps = () => invoke('list_laps') as Promise<any[]>
-export const analyzeLaps = (ids: string[]) => invoke('analyze_laps', { lapIds: ids })
-export const buildTrackMap = (id: string) => invoke('build_track_map', { lapId: id })
-export const getTrackMap = (id: string) => invoke('get_track_map', { lapId: id })
+export const listLaps = (sessionId?: string) => invoke('list_laps', { sessionId }) as Promise<any[]>
+// laps a crash cut short, brought back at startup and tagged 'recovered'
+export const recoveredLaps = () => invoke('recovered_laps') as Promise<string[]>
+// failures of background work (saving laps, publishing, journaling); new ones arrive as events
+export type BackgroundError = { action: string, message: string, at: number }
+export const backgroundErrors = () => invoke('background_errors') as Promise<BackgroundError[]>
+export const onBackgroundError = (cb: (e: BackgroundError) => void) =>
+  listen<BackgroundError>('app://error', e => cb(e.payload))
+export const liveDelta = () => invoke('live_delta') as Promise<Record<string, any>>
+export const lapMarkers = (id: string) => invoke('lap_markers', { lapId: id }) as Promise<any[]>
+export type Alignment = 'distance'|'normalized'
+export type Comparison = {
+  reference_id: string,
+  speed: { distance: number[], series: { lap_id: string, channel: string, values: number[] }[] },
+  deltas: { lap_id: string, delta_ms: number[] }[],
+  corners: Record<string, any>,
+  summary: Record<string, any>,
+}
+// empty ids compare every lap; without a reference the fastest is used
+// points per chart series; a 13 km lap is 13k on the 1 m grid
+export const CHART_POINTS = 2000
+export const analyzeLaps = (ids: string[], referenceId?: string, alignment?: Alignment, stepM?: number, maxPoints: number = CHART_POINTS) =>
+  invoke('analyze_laps', { lapIds: ids, referenceId, alignment, stepM, maxPoints }) as Promise<Comparison>
+export type SectorDelta = { index: number, start_m: number, end_m: number, a_ms: number, b_ms: number, delta_ms: number }
+export type CornerRow = {
+  corner: number, apex_m: number, entry_ms: number, mid_ms: number, exit_ms: number, total_ms: number, min_speed_delta_kph: number,
+  min_speed_a_kph: number, min_speed_b_kph: number,
+  brake_point_a_m: number | null, brake_point_b_m: number | null, throttle_on_a_m: number | null, throttle_on_b_m: number | null,
+}
+export type InputStats = { full_throttle: number, braking: number, coasting: number, avg_throttle: number, brake_applications: number, gear_changes: number }
+export type LapComparison = {
+  a: string, b: string, lap_delta_ms: number, sectors: SectorDelta[], corners: CornerRow[],
+  top_speed_a_kph: number, top_speed_b_kph: number, top_speed_delta_kph: number, inputs_a: InputStats, inputs_b: InputStats,
+}
+// b against a; positive deltas mean b is slower
+export const compareLaps = (a: string, b: string) => invoke('compare_laps', { a, b }) as Promise<LapComparison>
+export type StoredTrackMap = { game: string, track: string, lap_id: string, lap_time_ms: number, updated_at: number, map: any }
+// cached per game and track; buildTrackMap discards the cache and rebuilds it
+export const getTrackMap = (track: string, game?: string) =>
+  invoke('get_track_map', { track, game }) as Promise<StoredTrackMap | null>
+export const buildTrackMap = (track: string, game?: string) =>
+  invoke('build_track_map', { track, game }) as Promise<StoredTrackMap | null>
 
-export const importFile = (path: string) => invoke('import_file', { path })
-export const exportFile = (kind: 'csv'|'ndjson'|'motec_csv', path:string) => invoke('export_file', { kind, path })
+export type LiveDeltaState = { lap_number: number, distance_m: number, elapsed_ms: number, delta_ms?: number, predicted_ms?: number, best_ms?: number }
+export type SectorSplit = { index: number, start_m: number, end_m: number, time_ms: number | null, reference_ms: number | null, delta_ms: number | null }
+export type StreamState = { sector: number | null, splits: SectorSplit[], last_splits: SectorSplit[], top_speed_kph: number, session: LapTimeStats, recent: LapTimeStats }
+export type LiveState = { source: string, sample: Record<string, unknown>, delta: LiveDeltaState, stream: StreamState, last_lap_ms: number | null }
+export type SampleEvent = LiveState
+export const liveState = () => invoke('live_state') as Promise<LiveState[]>
+// a source's samples of the last `seconds` (at most 60), oldest first, for strip charts
+export const liveHistory = (key: SourceKey, seconds?: number) =>
+  invoke('live_history', { key, seconds }) as Promise<Record<string, unknown>[]>
+export type LapEvent = { source: string, id: string, meta: { id: string, game: string, car: string, track: string, lap_number: number }, total_time_ms: number, sector_ms: number[], best_ms: number | null, delta_to_best_ms: number | null }
+export const setLiveRate = (hz: number) => invoke('set_live_rate', { hz })
+export const onSample = (cb: (e: SampleEvent) => void) => listen<SampleEvent>('telemetry://sample', e => cb(e.payload))
+export const onLapCompleted = (cb: (e: LapEvent) => void) => listen<LapEvent>('telemetry://lap-completed', e => cb(e.payload))
+
+export type LapEdit = { car?: string, track?: string, lap_number?: number, driver?: string }
+export const deleteLaps = (ids: string[]) => invoke('delete_laps', { ids }) as Promise<number>
+export const editLap = (lapId: string, edit: LapEdit) => invoke('edit_lap', { lapId, edit })
+export const setLapTags = (lapId: string, tags: string[]) => invoke('set_lap_tags', { lapId, tags })
+export const lapTags = (lapId: string) => invoke('lap_tags', { lapId }) as Promise<string[]>
+
+export type ImportReport = { imported: number, skipped: number }
+export const importFile = (path: string) => invoke('import_file', { path }) as Promise<ImportReport>
+export type FileReport = { path: string, laps: number, error: string | null }
+export type FilesImport = ImportReport & { files: FileReport[] }
+// files are read in parallel; a folder stands for the lap files under it
+export const importFiles = (paths: string[]) => invoke('import_files', { paths }) as Promise<FilesImport>
+export const importF1Capture = (path: string, port?: number) =>
+  invoke('import_f1_capture', { path, port }) as Promise<ImportReport>
+export const cancelImport = (path: string) => invoke('cancel_import', { path })
+export type ImportProgress = { path: string, bytes_read: number, total_bytes: number, laps: number }
+export const onImportProgress = (cb: (p: ImportProgress) => void) =>
+  listen<ImportProgress>('import-progress', e => cb(e.payload))
+export type ExportKind = 'csv'|'ndjson'|'binary'|'session'|'motec_csv'|'motec_ld'|'vbo'|'gpx'|'kml'|'arrow_ipc'|'parquet'|'hdf5'|'corners'|'summary'|'video_overlay'
+export type CsvOptions = { speed_unit?: 'kph'|'mph', delimiter?: string, decimal?: string, unit_row?: boolean }
+export type ExportMeta = { driver?: string, conditions?: string }
+export const exportFile = (kind: ExportKind, path: string, lapIds?: string[], channels?: string[], csv?: CsvOptions, meta?: ExportMeta) =>
+  invoke('export_file', { kind, path, lapIds, channels, csv, ...meta }) as Promise<number>
+// laps in the default lap file format (.dlap)
+export const saveLaps = (path: string, lapIds?: string[]) =>
+  invoke('save_laps', { path, lapIds }) as Promise<number>
+export type GeoOrigin = { lat_deg: number, lon_deg: number, bearing_deg?: number }
+export const exportTrackGeojson = (lapId: string, path: string, origin?: GeoOrigin) =>
+  invoke('export_track_geojson', { lapId, path, origin })
+export type SvgOptions = { width: number, height: number, margin: number, stroke_width: number, heatmap?: boolean, background?: string }
+export const renderTrackSvg = (lapId: string, opts?: SvgOptions, path?: string) =>
+  invoke('render_track_svg', { lapId, opts, path }) as Promise<string>
+export const exportAnalysis = (dir: string, lapIds?: string[], referenceId?: string) =>
+  invoke('export_analysis', { dir, lapIds, referenceId }) as Promise<string[]>
+
+export const setAutosave = (dir: string | null) => invoke('set_autosave', { dir })
+export const autosaveDir = () => invoke('autosave_dir') as Promise<string | null>
+
+// how laps end; settings.lap_completion holds it per game
+export type LapCompletion =
+  | { kind: 'lap_number' }
+  | { kind: 'distance_reset', min_drop_m: number }
+  | { kind: 'position', radius_m: number, min_lap_s: number }
+
+export type Settings = {
+  f1_port: number, f1_format: number, f1_car_index: number | null,
+  gt7_console_ip: string, gt7_variant: string, gt7_bind_port: number, remote_port: number, remote_token: string,
+  speed_unit: 'kph'|'mph', autosave_dir: string | null, theme: 'system'|'light'|'dark', ring_minutes: number, thin_laps: boolean,
+  lap_completion: Record<string, LapCompletion>, ws_server: boolean, ws_port: number,
+  api_server: boolean, api_port: number, api_lan: boolean, mqtt_enabled: boolean, mqtt: MqttOptions,
+  nats_enabled: boolean, nats: NatsOptions, relay_enabled: boolean, relay: RelayOptions,
+  osc_enabled: boolean, osc: OscOptions, serial_enabled: boolean, serial: SerialOptions,
+  discord_enabled: boolean, discord: DiscordOptions, influx_enabled: boolean, influx: InfluxOptions,
+  postgres_enabled: boolean, postgres: PgOptions, grpc_server: boolean, grpc_port: number,
+}
+export const getSettings = () => invoke('get_settings') as Promise<Settings>
+export const setSettings = (settings: Settings) => invoke('set_settings', { settings })
+export const startRecording = (path: string, format: 'ndjson'|'csv') => invoke('start_recording', { path, format })
+export const stopRecording = () => invoke('stop_recording') as Promise<number>
+// last `minutes` of telemetry from the always-on buffer, even without a recording
+export const saveLast = (minutes: number, path: string, format?: 'ndjson'|'csv') =>
+  invoke('save_last', { minutes, path, format }) as Promise<number>
 
 export const carsAndTracks = (game: string) => invoke('cars_and_tracks', { game })
 
 export const saveWorkspace = (name: string, payload: any) => invoke('save_workspace', { name, payload })
 export const loadWorkspace = (name: string) => invoke('load_workspace', { name })
+export type LapTimeStats = { lap_count: number, best_ms: number | null, avg_ms: number | null, std_ms: number | null }
+export type StintStats = LapTimeStats & { index: number, first_lap: number, last_lap: number, car: string }
+export type ConditionChange = { lap_id: string, lap_number: number, field: 'car'|'track', from: string, to: string }
+export type SessionStats = LapTimeStats & { stints: StintStats[], condition_changes: ConditionChange[] }
+// out-laps and in-laps are left out of the lap times unless includeOutIn
+export const sessionStats = (sessionId: string, includeOutIn?: boolean) =>
+  invoke('session_stats', { sessionId, includeOutIn }) as Promise<SessionStats>
+
+export type ComparisonSet = { name: string, lap_ids: string[], reference_id: string | null, created_at: number }
+export const createComparisonSet = (name: string, lapIds: string[], referenceId?: string, workspace?: string) =>
+  invoke('create_comparison_set', { name, lapIds, referenceId, workspace }) as Promise<ComparisonSet>
+export const listComparisonSets = (workspace?: string) =>
+  invoke('list_comparison_sets', { workspace }) as Promise<ComparisonSet[]>
+export const deleteComparisonSet = (name: string, workspace?: string) =>
+  invoke('delete_comparison_set', { name, workspace }) as Promise<boolean>
+export const compareSet = (name: string, workspace?: string, alignment?: Alignment, stepM?: number) =>
+  invoke('compare_set', { name, workspace, alignment, stepM }) as Promise<Comparison>
+
+export type RecentItem = { kind: 'import'|'export'|'session', target: string, label: string, at: number }
+export const recentItems = () => invoke('recent_items') as Promise<RecentItem[]>
+export const clearRecent = () => invoke('clear_recent')
+
 export const listWorkspaces = () => invoke('list_workspaces') as Promise<string[]>
+export const exportWorkspace = (name: string, lapIds: string[], path: string) =>
+  invoke('export_workspace', { name, lapIds, path }) as Promise<number>
+export type WorkspaceImport = { name: string, layout: any, lap_ids: string[], report: ImportReport }
+export const importWorkspace = (path: string) => invoke('import_workspace', { path }) as Promise<WorkspaceImport>
@@ -1,5 +1,6 @@
 import { useCallback, useEffect, useMemo, useState } from 'react'
-import { startF1, startGT7, startLMU, stopAll, listLaps } from '../lib/api'
+import { startF1, followF1Car, startGT7, startLMU, stopAll, listLaps, getSettings, discoverConsoles, startErrorText } from '../lib/api'
+import type { DiscoveredConsole } from '../lib/api'
 
 type F1Format = 2024 | 2025
 type GT7Variant = 'A' | 'B' | '~'
@@ -16,10 +17,13 @@ type Lap = {
 export default function Dashboard() {
   const [port, setPort] = useState<number>(20777)
   const [format, setFormat] = useState<F1Format>(2025)
+  // car index to follow when spectating; the player's when unset
+  const [carIndex, setCarIndex] = useState<number | undefined>(undefined)
   const [consoleIp, setConsoleIp] = useState<string>('192.168.1.100')
   const [variant, setVariant] = useState<GT7Variant>('A')
   const [laps, setLaps] = useState<Lap[]>([])
-  const [busy, setBusy] = useState<'f1' | 'gt7' | 'lmu' | 'stop' | null>(null)
+  const [consoles, setConsoles] = useState<DiscoveredConsole[]>([])
+  const [busy, setBusy] = useState<'f1' | 'follow' | 'gt7' | 'find' | 'lmu' | 'stop' | null>(null)
   const [error, setError] = useState<string | null>(null)
 
   const fmtLap = useCallback((ms: number) => {
@@ -45,28 +49,66 @@ export default function Dashboard() {
     void refresh()
   }, [refresh])
 
+  // prefill the connector fields from the saved settings
+  useEffect(() => {
+    getSettings()
+      .then(s => {
+        setPort(s.f1_port)
+        setFormat(s.f1_format as F1Format)
+        setCarIndex(s.f1_car_index ?? undefined)
+        if (s.gt7_console_ip) setConsoleIp(s.gt7_console_ip)
+        setVariant(s.gt7_variant as GT7Variant)
+      })
+      .catch(() => {})
+  }, [])
+
   const handleStartF1 = useCallback(async () => {
     try {
       setBusy('f1'); setError(null)
-      await startF1(port, format)
+      await startF1(port, format, carIndex)
+    } catch (e: any) {
+      setError(startErrorText(e, 'Failed to start F1'))
+    } finally {
+      setBusy(null)
+    }
+  }, [port, format, carIndex])
+
+  const handleFollowCar = useCallback(async () => {
+    try {
+      setBusy('follow'); setError(null)
+      await followF1Car(carIndex)
     } catch (e: any) {
-      setError(e?.message ?? 'Failed to start F1')
+      setError(typeof e === 'string' ? e : e?.message ?? 'Failed to follow car')
     } finally {
       setBusy(null)
     }
-  }, [port, format])
+  }, [carIndex])
 
   const handleStartGT7 = useCallback(async () => {
     try {
       setBusy('gt7'); setError(null)
       await startGT7(consoleIp.trim(), variant)
     } catch (e: any) {
-      setError(e?.message ?? 'Failed to start GT7')
+      setError(startErrorText(e, 'Failed to start GT7'))
     } finally {
       setBusy(null)
     }
   }, [consoleIp, variant])
 
+  const handleFindConsoles = useCallback(async () => {
+    try {
+      setBusy('find'); setError(null)
+      const found = await discoverConsoles()
+      setConsoles(found)
+      if (found.length > 0) setConsoleIp(found[0].ip)
+      else setError('No PS5 answered; is GT7 running on the same network?')
+    } catch (e: any) {
+      setError(startErrorText(e, 'Failed to search for consoles'))
+    } finally {
+      setBusy(null)
+    }
+  }, [])
+
   const handleStartLMU = useCallback(async () => {
     try {
       setBusy('lmu'); setError(null)
@@ -97,6 +139,11 @@ export default function Dashboard() {
     setPort(Number.isFinite(n) && n > 0 && n <= 65535 ? n : 20777)
   }
 
+  const onCarIndexChange = (v: string) => {
+    const n = Number.parseInt(v, 10)
+    setCarIndex(Number.isFinite(n) && n >= 0 && n < 22 ? n : undefined)
+  }
+
   const onFormatChange = (v: string) => {
     const n = Number.parseInt(v, 10) as F1Format
     setFormat(n === 2024 || n === 2025 ? n : 2025)
@@ -140,15 +187,37 @@ export default function Dashboard() {
                   <option key={yr} value={yr}>{yr}</option>
                 ))}
               </select>
+              <input
+                className="bg-white/5 rounded-xl px-3 py-2 w-20"
+                type="number"
+                inputMode="numeric"
+                min={0}
+                max={21}
+                value={carIndex ?? ''}
+                onChange={(e) => onCarIndexChange(e.target.value)}
+                placeholder="Car"
+                title="Car index to follow when spectating; empty for your own car"
+              />
+            </div>
+            <div className="flex gap-2">
+              <button
+                type="button"
+                className="px-3 py-2 rounded-xl bg-accent/20 hover:bg-accent/30 disabled:opacity-50"
+                onClick={handleStartF1}
+                disabled={busy !== null}
+              >
+                {busy === 'f1' ? 'Starting…' : 'Start F1'}
+              </button>
+              <button
+                type="button"
+                className="px-3 py-2 rounded-xl bg-white/10 hover:bg-white/20 disabled:opacity-50"
+                onClick={handleFollowCar}
+                disabled={busy !== null}
+                title="Switch the running F1 source to this car"
+              >
+                {busy === 'follow' ? 'Switching…' : 'Follow car'}
+              </button>
             </div>
-            <button
-              type="button"
-              className="px-3 py-2 rounded-xl bg-accent/20 hover:bg-accent/30 disabled:opacity-50"
-              onClick={handleStartF1}
-              disabled={busy !== null}
-            >
-              {busy === 'f1' ? 'Starting…' : 'Start F1'}
-            </button>
           </div>
 
           {/* GT7 */}
@@ -162,7 +231,23 @@ export default function Dashboard() {
                 onChange={(e) => setConsoleIp(e.target.value)}
                 placeholder="PS5 IP"
                 inputMode="numeric"
+                list="gt7-consoles"
               />
+              <datalist id="gt7-consoles">
+                {consoles.map(c => (
+                  <option key={c.ip} value={c.ip}>
+                    {`${c.hostname ?? c.ip} (${Math.round(c.latency_ms)} ms)`}
+                  </option>
+                ))}
+              </datalist>
+              <button
+                type="button"
+                className="px-3 py-2 rounded-xl bg-white/5 hover:bg-white/10 disabled:opacity-50"
+                onClick={handleFindConsoles}
+                disabled={busy !== null}
+              >
+                {busy === 'find' ? 'Searching…' : 'Find'}
+              </button>
               <select
                 className="bg-white/5 rounded-xl px-3 py-2"
                 value={variant}
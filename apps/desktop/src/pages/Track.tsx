@@ -20,6 +20,7 @@ type CornerLabel = { index: number; x: number; y: number }
 
 type TrackMapData = {
   polyline: Point2[]
+  lods?: { tolerance_m: number; polyline: Point2[] }[]
   corners: CornerLabel[]
   sectors: Sector[]
   bbox: BBox
@@ -61,12 +62,13 @@ export default function Track() {
     }
   }, [laps, selectedId])
 
-  const loadMap = useCallback(async (lapId: string) => {
+  const loadMap = useCallback(async (lap: Lap) => {
     try {
       setError(null)
       setLoading(true)
-      const res = (await getTrackMap(lapId)) as TrackMapData
-      setMap(res ?? null)
+      // cached per game and track, so every lap of a track shows the same map
+      const res = await getTrackMap(lap.track, lap.game)
+      setMap((res?.map as TrackMapData) ?? null)
     } catch (e: any) {
       setError(e?.message ?? 'Failed to load track map')
       setMap(null)
@@ -75,15 +77,15 @@ export default function Track() {
     }
   }, [])
 
-  useEffect(() => {
-    if (selectedId) void loadMap(selectedId)
-  }, [selectedId, loadMap])
-
   const selectedLap = useMemo(
     () => laps.find((l) => l.id === selectedId) || null,
     [laps, selectedId]
   )
 
+  useEffect(() => {
+    if (selectedLap) void loadMap(selectedLap)
+  }, [selectedLap, loadMap])
+
   const fmtLap = (ms?: number) =>
     Number.isFinite(ms) ? `${(ms! / 1000).toFixed(3)}s` : '-'
 
@@ -0,0 +1,216 @@
+//! Spin, lockup and crash detection over a lap's points.
+
+use model::*;
+
+use crate::math::wrap_angle;
+
+const G: f64 = 9.81;
+
+#[derive(Clone, Debug)]
+pub struct AnomalyConfig {
+    /// Yaw rate (rad/s) above which a rotation is considered a spin.
+    pub spin_yaw_rate: f64,
+    /// How much faster the car must rotate than its path turns.
+    pub spin_ratio: f64,
+    /// Brake input above which decel spikes are checked for lockups.
+    pub lockup_brake: f64,
+    /// Lockup when decel exceeds the lap's typical braking decel by this factor.
+    pub lockup_ratio: f64,
+    /// Decel (g) treated as an impact regardless of inputs.
+    pub crash_decel_g: f64,
+    /// Speed lost (fraction) within `crash_window_ms` that counts as a crash.
+    pub crash_speed_drop: f64,
+    pub crash_window_ms: f64,
+    /// Ignore everything below this speed (pit lane, standing starts).
+    pub min_speed_kph: f64,
+    /// Detections of the same kind closer than this are merged.
+    pub merge_ms: f64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            spin_yaw_rate: 2.0,
+            spin_ratio: 2.5,
+            lockup_brake: 0.5,
+            lockup_ratio: 1.4,
+            crash_decel_g: 10.0,
+            crash_speed_drop: 0.7,
+            crash_window_ms: 1000.0,
+            min_speed_kph: 15.0,
+            merge_ms: 2000.0,
+        }
+    }
+}
+
+/// Whether `lap` reports heading, without which spins can't be told from
+/// corners. Sources without orientation (most CSV imports) leave yaw at 0.
+pub fn spin_detection_available(lap: &Lap) -> bool {
+    lap.points.iter().any(|p| p.yaw != 0.0)
+}
+
+/// Scan a lap and return its incidents ordered by time. Spins are only looked
+/// for when [`spin_detection_available`].
+pub fn detect_anomalies(lap: &Lap, cfg: &AnomalyConfig) -> Vec<LapMarker> {
+    let pts = &lap.points;
+    if pts.len() < 3 {
+        return Vec::new();
+    }
+    let spins = spin_detection_available(lap);
+
+    let mut raw = Vec::new();
+    let decel = decel_series(pts);
+    let typical_brake = median(
+        pts.iter()
+            .zip(&decel)
+            .filter(|(p, &d)| p.brake >= cfg.lockup_brake && d > 0.0)
+            .map(|(_, &d)| d)
+            .collect(),
+    );
+
+    for i in 1..pts.len() - 1 {
+        let (p0, p1, p2) = (&pts[i - 1], &pts[i], &pts[i + 1]);
+        if p1.speed_kph < cfg.min_speed_kph {
+            continue;
+        }
+        let dt = (p2.t_ms - p0.t_ms) / 1000.0;
+        if dt <= 0.0 {
+            continue;
+        }
+
+        // spin: the body rotates much faster than the path it's following
+        if spins {
+            let yaw_rate = wrap_angle(p2.yaw - p0.yaw) / dt;
+            let path_rate = wrap_angle(heading(p1, p2) - heading(p0, p1)) / dt;
+            if yaw_rate.abs() > cfg.spin_yaw_rate && yaw_rate.abs() > cfg.spin_ratio * path_rate.abs() {
+                raw.push(marker(MarkerKind::Spin, p1, yaw_rate.abs()));
+            }
+        }
+
+        // lockup: under braking, decel well above what this lap normally achieves
+        let d = decel[i];
+        if let Some(typ) = typical_brake {
+            if p1.brake >= cfg.lockup_brake && d > typ * cfg.lockup_ratio {
+                raw.push(marker(MarkerKind::Lockup, p1, d / G));
+            }
+        }
+
+        // crash: impact-level decel
+        if d / G > cfg.crash_decel_g {
+            raw.push(marker(MarkerKind::Crash, p1, d / G));
+        }
+    }
+
+    // crash: speed collapse within a short window
+    let mut j = 0;
+    for i in 0..pts.len() {
+        while pts[i].t_ms - pts[j].t_ms > cfg.crash_window_ms {
+            j += 1;
+        }
+        let v0 = pts[j..=i].iter().map(|p| p.speed_kph).fold(0.0, f64::max);
+        let v1 = pts[i].speed_kph;
+        if v0 >= cfg.min_speed_kph * 3.0 && v1 < v0 * (1.0 - cfg.crash_speed_drop) {
+            let dt = ((pts[i].t_ms - pts[j].t_ms) / 1000.0).max(1e-3);
+            raw.push(marker(MarkerKind::Crash, &pts[i], (v0 - v1) / 3.6 / dt / G));
+        }
+    }
+
+    raw.sort_by(|a, b| a.t_ms.partial_cmp(&b.t_ms).unwrap_or(std::cmp::Ordering::Equal));
+    merge(raw, cfg.merge_ms)
+}
+
+fn marker(kind: MarkerKind, p: &TelemetryPoint, severity: f64) -> LapMarker {
+    LapMarker { kind, t_ms: p.t_ms, lap_distance_m: p.lap_distance_m, x: p.x, y: p.y, severity }
+}
+
+/// Collapse bursts of the same kind into a single marker at their peak.
+fn merge(raw: Vec<LapMarker>, merge_ms: f64) -> Vec<LapMarker> {
+    let mut out: Vec<LapMarker> = Vec::new();
+    let mut last_seen: Vec<(MarkerKind, usize, f64)> = Vec::new();
+
+    for m in raw {
+        let open = last_seen.iter_mut().find(|(k, _, t)| *k == m.kind && m.t_ms - *t <= merge_ms);
+        match open {
+            Some((_, idx, t)) => {
+                *t = m.t_ms;
+                if m.severity > out[*idx].severity {
+                    out[*idx] = m;
+                }
+            }
+            None => {
+                last_seen.retain(|(k, _, _)| *k != m.kind);
+                last_seen.push((m.kind, out.len(), m.t_ms));
+                out.push(m);
+            }
+        }
+    }
+    out
+}
+
+/// Longitudinal deceleration (m/s², positive when slowing) per point.
+fn decel_series(pts: &[TelemetryPoint]) -> Vec<f64> {
+    let mut d = vec![0.0; pts.len()];
+    for i in 1..pts.len() {
+        let dt = (pts[i].t_ms - pts[i - 1].t_ms) / 1000.0;
+        if dt > 0.0 {
+            d[i] = (pts[i - 1].speed_kph - pts[i].speed_kph) / 3.6 / dt;
+        }
+    }
+    d
+}
+
+fn heading(a: &TelemetryPoint, b: &TelemetryPoint) -> f64 {
+    (b.y - a.y).atan2(b.x - a.x)
+}
+
+fn median(mut v: Vec<f64>) -> Option<f64> {
+    if v.is_empty() {
+        return None;
+    }
+    v.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    Some(v[v.len() / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, point};
+
+    /// Straight ahead at 100 km/h for 5 s, sampled at 10 Hz, with the car
+    /// rotating a full turn over 1 s in the middle when `spin`.
+    fn straight(spin: bool) -> Lap {
+        let points = (0..50)
+            .map(|i| {
+                let mut p = point(i as f64 * 100.0, i as f64 * 100.0 / 36.0);
+                p.speed_kph = 100.0;
+                p.yaw = if spin { (i.clamp(20, 30) - 20) as f64 * std::f64::consts::TAU / 10.0 } else { 0.0 };
+                p
+            })
+            .collect();
+        lap(5000, points)
+    }
+
+    #[test]
+    fn spin_found_when_yaw_is_reported() {
+        let l = straight(true);
+        assert!(spin_detection_available(&l));
+        let m = detect_anomalies(&l, &AnomalyConfig::default());
+        assert_eq!(m.len(), 1, "{m:?}");
+        assert_eq!(m[0].kind, MarkerKind::Spin);
+        assert!((2000.0..=3000.0).contains(&m[0].t_ms));
+    }
+
+    #[test]
+    fn no_yaw_means_spins_unavailable() {
+        let l = straight(false);
+        assert!(!spin_detection_available(&l));
+        assert!(detect_anomalies(&l, &AnomalyConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn wrap_angle_takes_the_short_way_round() {
+        use std::f64::consts::PI;
+        assert!((wrap_angle(PI - 0.1 - (-PI + 0.1)) - (-0.2)).abs() < 1e-12);
+        assert!((wrap_angle(-3.0 * PI / 2.0) - PI / 2.0).abs() < 1e-12);
+    }
+}
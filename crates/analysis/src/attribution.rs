@@ -0,0 +1,194 @@
+//! Splits the time lost in each corner into entry, mid and exit phases.
+
+use model::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::corner_metrics;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Entry,
+    Mid,
+    Exit,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Phase::Entry => "entry",
+            Phase::Mid => "mid-corner",
+            Phase::Exit => "exit",
+        })
+    }
+}
+
+/// Time lost (positive) or gained (negative) against the reference, per phase.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CornerTimeLoss {
+    pub corner: u32,
+    pub apex_m: f64,
+    pub entry_ms: f64,
+    pub mid_ms: f64,
+    pub exit_ms: f64,
+    pub total_ms: f64,
+    /// Lap minimum speed minus reference minimum speed through the corner.
+    pub min_speed_delta_kph: f64,
+}
+
+impl CornerTimeLoss {
+    /// The phase where the most time went, with its loss in ms.
+    pub fn worst_phase(&self) -> (Phase, f64) {
+        [(Phase::Entry, self.entry_ms), (Phase::Mid, self.mid_ms), (Phase::Exit, self.exit_ms)]
+            .into_iter()
+            .fold((Phase::Entry, f64::NEG_INFINITY), |a, b| if b.1 > a.1 { b } else { a })
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct TimeLossReport {
+    #[serde(with = "uuid::serde::simple")]
+    pub reference_id: Uuid,
+    #[serde(with = "uuid::serde::simple")]
+    pub lap_id: Uuid,
+    pub corners: Vec<CornerTimeLoss>,
+}
+
+impl TimeLossReport {
+    /// Human-readable lines such as "you lose 0.15 s on entry to T4",
+    /// for every corner phase losing more than `min_loss_ms`, worst first.
+    pub fn headlines(&self, min_loss_ms: f64) -> Vec<String> {
+        let mut worst: Vec<(u32, Phase, f64)> = self
+            .corners
+            .iter()
+            .map(|c| {
+                let (p, ms) = c.worst_phase();
+                (c.corner, p, ms)
+            })
+            .filter(|(_, _, ms)| *ms > min_loss_ms)
+            .collect();
+        worst.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        worst
+            .into_iter()
+            .map(|(c, p, ms)| format!("you lose {:.2} s on {} {} T{}", ms / 1000.0, p, preposition(p), c))
+            .collect()
+    }
+}
+
+fn preposition(p: Phase) -> &'static str {
+    match p {
+        Phase::Entry => "to",
+        Phase::Mid => "of",
+        Phase::Exit => "of",
+    }
+}
+
+/// Fraction of the shorter corner half treated as the mid (apex) phase on each side.
+const MID_FRACTION: f64 = 0.25;
+
+/// Attribute `lap`'s time loss against `reference` to the phases of each reference corner.
+pub fn corner_time_loss(reference: &Lap, lap: &Lap) -> TimeLossReport {
+    let corners = corner_metrics(reference).corners;
+    let out = corners
+        .iter()
+        .map(|c| {
+            let half = (c.apex_m - c.start_m).min(c.end_m - c.apex_m).max(0.0);
+            let w = half * MID_FRACTION;
+            let bounds = [c.start_m, c.apex_m - w, c.apex_m + w, c.end_m];
+
+            let t = |lap: &Lap, d: f64| lap.time_at_distance(d).unwrap_or(0.0);
+            let delta: Vec<f64> = bounds.iter().map(|&d| t(lap, d) - t(reference, d)).collect();
+
+            let min_speed = |lap: &Lap| {
+                lap.points_between(c.start_m, c.end_m)
+                    .iter()
+                    .map(|p| p.speed_kph)
+                    .fold(f64::INFINITY, f64::min)
+            };
+            let (lap_min, ref_min) = (min_speed(lap), min_speed(reference));
+            let min_speed_delta_kph = if lap_min.is_finite() && ref_min.is_finite() {
+                lap_min - ref_min
+            } else {
+                0.0
+            };
+
+            CornerTimeLoss {
+                corner: c.index,
+                apex_m: c.apex_m,
+                entry_ms: delta[1] - delta[0],
+                mid_ms: delta[2] - delta[1],
+                exit_ms: delta[3] - delta[2],
+                total_ms: delta[3] - delta[0],
+                min_speed_delta_kph,
+            }
+        })
+        .collect();
+
+    TimeLossReport { reference_id: reference.id, lap_id: lap.id, corners: out }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, point};
+
+    /// 800 m of road, a point every 5 m, turning 120° round points 40, 80
+    /// and 120. Each 5 m takes 100 ms plus `extra_ms(segment)`.
+    fn road(extra_ms: impl Fn(usize) -> f64) -> Lap {
+        let (mut t, mut x, mut y, mut heading) = (0.0, 0.0, 0.0, 0.0_f64);
+        let pts: Vec<TelemetryPoint> = (0..=160)
+            .map(|i| {
+                if i > 0 {
+                    t += 100.0 + extra_ms(i - 1);
+                    x += 5.0 * heading.cos();
+                    y += 5.0 * heading.sin();
+                }
+                // the turns spread over a few points, so each has one sharpest point
+                heading += [40.0, 80.0, 120.0]
+                    .iter()
+                    .map(|&k| 0.59 * (-((i as f64 - k) / 2.0).powi(2)).exp())
+                    .sum::<f64>();
+                TelemetryPoint { x, y, ..point(t, i as f64 * 5.0) }
+            })
+            .collect();
+        let total = pts.last().map_or(0, |p| p.t_ms as u64);
+        lap(total, pts)
+    }
+
+    #[test]
+    fn phases_add_up_to_the_corner_and_corners_to_the_lap() {
+        let reference = road(|_| 0.0);
+        // slow into T1, through T2's apex and out of T3; nothing on the straights
+        let lap = road(|i| match i {
+            25..=34 => 10.0,
+            79..=80 => 60.0,
+            125..=135 => 20.0,
+            _ => 0.0,
+        });
+        let report = corner_time_loss(&reference, &lap);
+        assert_eq!(report.corners.iter().map(|c| c.corner).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!((report.reference_id, report.lap_id), (reference.id, lap.id));
+
+        for c in &report.corners {
+            assert!((c.entry_ms + c.mid_ms + c.exit_ms - c.total_ms).abs() < 1e-9, "{c:?}");
+        }
+        let total: f64 = report.corners.iter().map(|c| c.total_ms).sum();
+        assert_eq!(total, (lap.total_time_ms - reference.total_time_ms) as f64);
+
+        let worst: Vec<Phase> = report.corners.iter().map(|c| c.worst_phase().0).collect();
+        assert_eq!(worst, [Phase::Entry, Phase::Mid, Phase::Exit]);
+        assert_eq!(
+            report.headlines(0.0),
+            ["you lose 0.22 s on exit of T3", "you lose 0.12 s on mid-corner of T2", "you lose 0.10 s on entry to T1"]
+        );
+    }
+
+    #[test]
+    fn the_same_lap_loses_nothing() {
+        let reference = road(|i| (i % 7) as f64);
+        let report = corner_time_loss(&reference, &reference.clone());
+        assert!(report.corners.iter().all(|c| c.total_ms == 0.0 && c.min_speed_delta_kph == 0.0));
+        assert!(report.headlines(0.0).is_empty());
+    }
+}
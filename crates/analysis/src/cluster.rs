@@ -0,0 +1,228 @@
+//! Groups laps by how they were driven (k-means on resampled speed/brake traces).
+
+use model::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::resample::{distance_grid, resample_lap};
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ClusterOptions {
+    pub k: usize,
+    /// Resampling step for the feature vectors (m).
+    pub step_m: f64,
+    pub max_iter: usize,
+    /// Relative weight of the brake trace against the (normalized) speed trace.
+    pub brake_weight: f64,
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        Self { k: 2, step_m: 10.0, max_iter: 50, brake_weight: 1.0 }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LapCluster {
+    #[serde(with = "uuid::serde::simple")]
+    pub lap_id: Uuid,
+    pub cluster: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ClusterSummary {
+    pub cluster: usize,
+    pub size: usize,
+    pub mean_time_ms: f64,
+    pub best_time_ms: u64,
+    /// Share of the lap spent on the brakes.
+    pub brake_fraction: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct LapClusters {
+    pub labels: Vec<LapCluster>,
+    pub clusters: Vec<ClusterSummary>,
+}
+
+/// Cluster laps by driving style. Laps without points are left out.
+pub fn cluster_laps(laps: &[Lap], opts: &ClusterOptions) -> LapClusters {
+    let used: Vec<&Lap> = laps.iter().filter(|l| l.points.len() > 1).collect();
+    if used.is_empty() || opts.k == 0 {
+        return LapClusters::default();
+    }
+    let k = opts.k.min(used.len());
+
+    let common = used
+        .iter()
+        .filter_map(|l| l.points.last().map(|p| p.lap_distance_m))
+        .fold(f64::INFINITY, f64::min);
+    let grid = distance_grid(common, opts.step_m.max(1.0));
+    let resampled: Vec<_> = used.par_iter().map(|l| resample_lap(l, &grid)).collect();
+    let vmax = resampled
+        .iter()
+        .flat_map(|r| r.speed_kph.iter().copied())
+        .fold(1.0_f64, f64::max);
+
+    let features: Vec<Vec<f64>> = resampled
+        .iter()
+        .map(|r| {
+            r.speed_kph
+                .iter()
+                .map(|v| v / vmax)
+                .chain(r.brake.iter().map(|b| b * opts.brake_weight))
+                .collect()
+        })
+        .collect();
+
+    let labels = kmeans(&features, k, opts.max_iter, fastest_index(&used));
+
+    let clusters = (0..k)
+        .map(|c| {
+            let members: Vec<usize> = (0..used.len()).filter(|&i| labels[i] == c).collect();
+            let size = members.len();
+            let mean_time_ms = if size > 0 {
+                members.iter().map(|&i| used[i].total_time_ms as f64).sum::<f64>() / size as f64
+            } else {
+                0.0
+            };
+            let best_time_ms = members.iter().map(|&i| used[i].total_time_ms).min().unwrap_or(0);
+            let brake_fraction = if size > 0 {
+                members
+                    .iter()
+                    .map(|&i| {
+                        let b = &resampled[i].brake;
+                        b.iter().filter(|&&x| x > 0.1).count() as f64 / b.len().max(1) as f64
+                    })
+                    .sum::<f64>()
+                    / size as f64
+            } else {
+                0.0
+            };
+            ClusterSummary { cluster: c, size, mean_time_ms, best_time_ms, brake_fraction }
+        })
+        .collect();
+
+    LapClusters {
+        labels: used
+            .iter()
+            .zip(&labels)
+            .map(|(l, &cluster)| LapCluster { lap_id: l.id, cluster })
+            .collect(),
+        clusters,
+    }
+}
+
+fn fastest_index(laps: &[&Lap]) -> usize {
+    laps.iter()
+        .enumerate()
+        .min_by_key(|(_, l)| l.total_time_ms)
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// Lloyd's k-means with deterministic farthest-point seeding from `seed`.
+fn kmeans(x: &[Vec<f64>], k: usize, max_iter: usize, seed: usize) -> Vec<usize> {
+    let mut centroids = vec![x[seed].clone()];
+    while centroids.len() < k {
+        let far = (0..x.len())
+            .max_by(|&a, &b| {
+                let da = nearest(&x[a], &centroids).1;
+                let db = nearest(&x[b], &centroids).1;
+                da.partial_cmp(&db).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(0);
+        centroids.push(x[far].clone());
+    }
+
+    let mut labels = vec![usize::MAX; x.len()];
+    for _ in 0..max_iter.max(1) {
+        let next: Vec<usize> = x.par_iter().map(|v| nearest(v, &centroids).0).collect();
+        if next == labels {
+            break;
+        }
+        labels = next;
+
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            let members: Vec<&Vec<f64>> =
+                x.iter().zip(&labels).filter(|(_, &l)| l == c).map(|(v, _)| v).collect();
+            if members.is_empty() {
+                continue;
+            }
+            for (j, m) in centroid.iter_mut().enumerate() {
+                *m = members.iter().map(|v| v[j]).sum::<f64>() / members.len() as f64;
+            }
+        }
+    }
+    labels
+}
+
+fn nearest(v: &[f64], centroids: &[Vec<f64>]) -> (usize, f64) {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i, v.iter().zip(c).map(|(a, b)| (a - b) * (a - b)).sum::<f64>()))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .unwrap_or((0, 0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, point};
+
+    /// 1 km at `kph`, braking fully from `brake_m.0` to `brake_m.1`.
+    fn styled(total_ms: u64, kph: f64, brake_m: (f64, f64)) -> Lap {
+        let pts = (0..=100)
+            .map(|i| {
+                let d = i as f64 * 10.0;
+                let braking = (brake_m.0..brake_m.1).contains(&d);
+                TelemetryPoint { speed_kph: kph, brake: if braking { 1.0 } else { 0.0 }, ..point(d * 100.0, d) }
+            })
+            .collect();
+        lap(total_ms, pts)
+    }
+
+    fn laps() -> Vec<Lap> {
+        vec![
+            styled(92_000, 150.0, (300.0, 500.0)),
+            styled(88_000, 200.0, (600.0, 650.0)),
+            styled(91_000, 152.0, (290.0, 500.0)),
+            styled(87_500, 198.0, (600.0, 660.0)),
+            styled(93_000, 149.0, (310.0, 510.0)),
+            styled(88_500, 201.0, (590.0, 650.0)),
+        ]
+    }
+
+    #[test]
+    fn laps_driven_alike_cluster_together_and_the_fastest_seeds_the_first() {
+        let laps = laps();
+        let out = cluster_laps(&laps, &ClusterOptions::default());
+        let labels: Vec<usize> = out.labels.iter().map(|l| l.cluster).collect();
+        assert_eq!(labels, [1, 0, 1, 0, 1, 0]);
+        assert!(out.labels.iter().zip(&laps).all(|(c, l)| c.lap_id == l.id));
+
+        let (fast, slow) = (&out.clusters[0], &out.clusters[1]);
+        assert_eq!((fast.size, fast.best_time_ms, fast.mean_time_ms), (3, 87_500, 88_000.0));
+        assert_eq!((slow.size, slow.best_time_ms, slow.mean_time_ms), (3, 91_000, 92_000.0));
+        assert!(slow.brake_fraction > fast.brake_fraction);
+
+        // no randomness: the same laps cluster the same way every time
+        assert_eq!(cluster_laps(&laps, &ClusterOptions::default()), out);
+    }
+
+    #[test]
+    fn k_is_capped_at_the_laps_with_points() {
+        let mut laps = laps();
+        laps.truncate(1);
+        laps.push(lap(80_000, vec![point(0.0, 0.0)]));
+        let out = cluster_laps(&laps, &ClusterOptions { k: 3, ..Default::default() });
+        assert_eq!(out.labels.len(), 1);
+        assert_eq!(out.clusters.len(), 1);
+        assert_eq!(out.clusters[0].size, 1);
+
+        assert_eq!(cluster_laps(&laps, &ClusterOptions { k: 0, ..Default::default() }), LapClusters::default());
+        assert_eq!(cluster_laps(&[], &ClusterOptions::default()), LapClusters::default());
+    }
+}
@@ -0,0 +1,469 @@
+//! Targeted comparison of chosen laps against a chosen reference, and the
+//! head-to-head breakdown of one lap against another.
+
+use model::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::{Borrow, Cow};
+use uuid::Uuid;
+
+use crate::attribution::{corner_time_loss, CornerTimeLoss};
+use crate::decimate::{lttb, select};
+use crate::reference::{reference_lap, ReferenceMode};
+use crate::resample::{distance_grid, resample_lap};
+use crate::{
+    build_track_map, corner_metrics, session_summary, CornerMetrics, OverlayResult, OverlaySeries, SessionSummary,
+};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Alignment {
+    /// Compare at the same lap distance.
+    #[default]
+    Distance,
+    /// Stretch each lap to the reference's length first, so laps measured
+    /// slightly differently (other lines, distance drift) still line up.
+    Normalized,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CompareOptions {
+    /// Reference lap; built from the compared laps per `reference_mode` when `None`.
+    #[serde(default)]
+    pub reference: Option<Uuid>,
+    #[serde(default)]
+    pub reference_mode: ReferenceMode,
+    #[serde(default)]
+    pub alignment: Alignment,
+    /// Grid spacing in metres.
+    #[serde(default = "default_step")]
+    pub step_m: f64,
+    /// Decimate the speed and delta series to this many points for charting;
+    /// see [`Comparison::decimate`].
+    #[serde(default)]
+    pub max_points: Option<usize>,
+}
+
+fn default_step() -> f64 {
+    1.0
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            reference: None,
+            reference_mode: ReferenceMode::default(),
+            alignment: Alignment::default(),
+            step_m: 1.0,
+            max_points: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LapDelta {
+    #[serde(with = "uuid::serde::simple")]
+    pub lap_id: Uuid,
+    /// Lap time minus reference time at each grid distance (positive = slower).
+    pub delta_ms: Vec<f64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Comparison {
+    #[serde(with = "uuid::serde::simple")]
+    pub reference_id: Uuid,
+    /// Speed of the reference and every compared lap.
+    pub speed: OverlayResult,
+    /// Delta of every compared lap other than the reference.
+    pub deltas: Vec<LapDelta>,
+    pub corners: CornerMetrics,
+    pub summary: SessionSummary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompareError {
+    NoLaps,
+    UnknownReference(Uuid),
+    BadStep(f64),
+}
+
+impl Comparison {
+    /// Keep at most `max_points` grid distances, the same ones for the speed
+    /// of every lap and every delta, so they still chart against
+    /// `speed.distance`.
+    pub fn decimate(&mut self, max_points: usize) {
+        let ys: Vec<&[f64]> = self
+            .speed
+            .series
+            .iter()
+            .map(|s| s.values.as_slice())
+            .chain(self.deltas.iter().map(|d| d.delta_ms.as_slice()))
+            .collect();
+        let keep = lttb(&self.speed.distance, &ys, max_points);
+        self.speed.distance = select(&self.speed.distance, &keep);
+        for s in &mut self.speed.series {
+            s.values = select(&s.values, &keep);
+        }
+        for d in &mut self.deltas {
+            d.delta_ms = select(&d.delta_ms, &keep);
+        }
+    }
+}
+
+impl std::fmt::Display for CompareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompareError::NoLaps => write!(f, "no laps with telemetry to compare"),
+            CompareError::UnknownReference(id) => write!(f, "reference lap {id} not found"),
+            CompareError::BadStep(s) => write!(f, "grid step must be positive, got {s}"),
+        }
+    }
+}
+
+impl std::error::Error for CompareError {}
+
+/// Compare `laps` against the reference picked by `opts`. An explicit
+/// reference is looked up in `candidates`, which may hold laps outside the
+/// comparison (the session best, a friend's lap). Laps may be shared
+/// (`Arc<Lap>`) or borrowed; only the ones a normalized alignment stretches
+/// are copied.
+pub fn compare_laps<L, C>(laps: &[L], candidates: &[C], opts: &CompareOptions) -> Result<Comparison, CompareError>
+where
+    L: Borrow<Lap> + Sync,
+    C: Borrow<Lap>,
+{
+    if opts.step_m.is_nan() || opts.step_m <= 0.0 {
+        return Err(CompareError::BadStep(opts.step_m));
+    }
+    let laps: Vec<&Lap> = laps.iter().map(Borrow::borrow).filter(|l| !l.points.is_empty()).collect();
+    if laps.is_empty() {
+        return Err(CompareError::NoLaps);
+    }
+    let synthetic;
+    let reference: &Lap = match opts.reference {
+        Some(id) => laps
+            .iter()
+            .copied()
+            .chain(candidates.iter().map(Borrow::borrow))
+            .find(|l| l.id == id)
+            .ok_or(CompareError::UnknownReference(id))?,
+        None => {
+            synthetic = reference_lap(&laps, opts.reference_mode, opts.step_m).ok_or(CompareError::NoLaps)?;
+            &synthetic
+        }
+    };
+
+    let ref_len = reference.points.last().map(|p| p.lap_distance_m).unwrap_or(0.0);
+    let grid = distance_grid(ref_len, opts.step_m);
+
+    let r = resample_lap(reference, &grid);
+    let compared: Vec<_> =
+        laps.par_iter().map(|l| (l.id, resample_lap(&aligned(l, ref_len, opts.alignment), &grid))).collect();
+
+    let mut series = vec![OverlaySeries { lap_id: reference.id, channel: "speed".into(), values: r.speed_kph.clone() }];
+    let mut deltas = Vec::new();
+    for (id, c) in compared {
+        if id == reference.id {
+            continue;
+        }
+        deltas.push(LapDelta { lap_id: id, delta_ms: c.t_ms.iter().zip(&r.t_ms).map(|(t, rt)| t - rt).collect() });
+        series.push(OverlaySeries { lap_id: id, channel: "speed".into(), values: c.speed_kph });
+    }
+
+    let mut comparison = Comparison {
+        reference_id: reference.id,
+        speed: OverlayResult { distance: grid, series },
+        deltas,
+        corners: corner_metrics(reference),
+        summary: session_summary(&laps),
+    };
+    if let Some(n) = opts.max_points {
+        comparison.decimate(n);
+    }
+    Ok(comparison)
+}
+
+/// `lap` as compared against a reference `ref_len` long: stretched to it
+/// when normalizing, as is otherwise.
+fn aligned(lap: &Lap, ref_len: f64, alignment: Alignment) -> Cow<'_, Lap> {
+    let len = lap.points.last().map(|p| p.lap_distance_m).unwrap_or(0.0);
+    if alignment != Alignment::Normalized || len <= 0.0 {
+        return Cow::Borrowed(lap);
+    }
+    let mut lap = lap.clone();
+    let k = ref_len / len;
+    for p in &mut lap.points {
+        p.lap_distance_m *= k;
+    }
+    lap.reindex();
+    Cow::Owned(lap)
+}
+
+/// Lap `b` against lap `a`. Deltas are `b` minus `a`: positive time means
+/// `b` is slower, positive distance means `b` is later.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LapComparison {
+    #[serde(with = "uuid::serde::simple")]
+    pub a: Uuid,
+    #[serde(with = "uuid::serde::simple")]
+    pub b: Uuid,
+    pub lap_delta_ms: i64,
+    /// Sectors of `a`'s track map.
+    pub sectors: Vec<SectorDelta>,
+    /// Corners found on `a`.
+    pub corners: Vec<CornerRow>,
+    pub top_speed_a_kph: f64,
+    pub top_speed_b_kph: f64,
+    pub top_speed_delta_kph: f64,
+    pub inputs_a: InputStats,
+    pub inputs_b: InputStats,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SectorDelta {
+    pub index: u32,
+    pub start_m: f64,
+    pub end_m: f64,
+    pub a_ms: f64,
+    pub b_ms: f64,
+    pub delta_ms: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CornerRow {
+    #[serde(flatten)]
+    pub loss: CornerTimeLoss,
+    pub min_speed_a_kph: f64,
+    pub min_speed_b_kph: f64,
+    pub brake_point_a_m: Option<f64>,
+    pub brake_point_b_m: Option<f64>,
+    pub throttle_on_a_m: Option<f64>,
+    pub throttle_on_b_m: Option<f64>,
+}
+
+/// How a lap was driven, as shares of lap time (0..=1) and counts.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct InputStats {
+    pub full_throttle: f64,
+    pub braking: f64,
+    /// Neither on the throttle nor on the brake.
+    pub coasting: f64,
+    pub avg_throttle: f64,
+    /// Times the brake was pressed.
+    pub brake_applications: u32,
+    pub gear_changes: u32,
+}
+
+const FULL_THROTTLE: f64 = 0.98;
+const PEDAL_ON: f64 = 0.05;
+// thresholds `corner_metrics` uses for brake and throttle points
+const BRAKE_POINT: f64 = 0.2;
+const THROTTLE_ON: f64 = 0.6;
+
+/// Time-weighted input shares and pedal and gear counts of `lap`.
+pub fn input_stats(lap: &Lap) -> InputStats {
+    let mut s = InputStats::default();
+    let mut total = 0.0;
+    for w in lap.points.windows(2) {
+        let (p, q) = (&w[0], &w[1]);
+        let dt = (q.t_ms - p.t_ms).max(0.0);
+        total += dt;
+        if p.throttle >= FULL_THROTTLE {
+            s.full_throttle += dt;
+        }
+        if p.brake > PEDAL_ON {
+            s.braking += dt;
+        } else if p.throttle < PEDAL_ON {
+            s.coasting += dt;
+        }
+        s.avg_throttle += p.throttle * dt;
+        if q.brake > PEDAL_ON && p.brake <= PEDAL_ON {
+            s.brake_applications += 1;
+        }
+        if q.gear != p.gear {
+            s.gear_changes += 1;
+        }
+    }
+    if total > 0.0 {
+        for v in [&mut s.full_throttle, &mut s.braking, &mut s.coasting, &mut s.avg_throttle] {
+            *v /= total;
+        }
+    }
+    s
+}
+
+/// Time `lap` spent in each of `sectors`, in ms.
+pub fn sector_times(lap: &Lap, sectors: &[Sector]) -> Vec<f64> {
+    sectors
+        .iter()
+        .map(|sec| {
+            let t = |d: f64| lap.time_at_distance(d).unwrap_or(0.0);
+            t(sec.end_m) - t(sec.start_m)
+        })
+        .collect()
+}
+
+fn in_range(lap: &Lap, from: f64, to: f64) -> impl Iterator<Item = &TelemetryPoint> {
+    lap.points_between(from, to).iter()
+}
+
+/// Break down `b` against `a` sector by sector and corner by corner.
+pub fn head_to_head(a: &Lap, b: &Lap) -> LapComparison {
+    let bounds = build_track_map(a).sectors;
+    let (ta, tb) = (sector_times(a, &bounds), sector_times(b, &bounds));
+    let sectors = bounds
+        .iter()
+        .zip(ta.into_iter().zip(tb))
+        .enumerate()
+        .map(|(i, (sec, (a_ms, b_ms)))| SectorDelta {
+            index: i as u32 + 1,
+            start_m: sec.start_m,
+            end_m: sec.end_m,
+            a_ms,
+            b_ms,
+            delta_ms: b_ms - a_ms,
+        })
+        .collect();
+
+    let metrics = corner_metrics(a).corners;
+    let corners = corner_time_loss(a, b)
+        .corners
+        .into_iter()
+        .zip(&metrics)
+        .map(|(loss, c)| {
+            let min_speed = |lap: &Lap| in_range(lap, c.start_m, c.end_m).map(|p| p.speed_kph).fold(f64::INFINITY, f64::min);
+            let brake = |lap: &Lap| in_range(lap, c.start_m, c.apex_m).find(|p| p.brake > BRAKE_POINT).map(|p| p.lap_distance_m);
+            let throttle =
+                |lap: &Lap| in_range(lap, c.apex_m, c.end_m).find(|p| p.throttle > THROTTLE_ON).map(|p| p.lap_distance_m);
+            CornerRow {
+                loss,
+                min_speed_a_kph: min_speed(a),
+                min_speed_b_kph: min_speed(b),
+                brake_point_a_m: brake(a),
+                brake_point_b_m: brake(b),
+                throttle_on_a_m: throttle(a),
+                throttle_on_b_m: throttle(b),
+            }
+        })
+        .collect();
+
+    let top = |lap: &Lap| lap.points.iter().map(|p| p.speed_kph).fold(0.0, f64::max);
+    let (top_a, top_b) = (top(a), top(b));
+    LapComparison {
+        a: a.id,
+        b: b.id,
+        lap_delta_ms: b.total_time_ms as i64 - a.total_time_ms as i64,
+        sectors,
+        corners,
+        top_speed_a_kph: top_a,
+        top_speed_b_kph: top_b,
+        top_speed_delta_kph: top_b - top_a,
+        inputs_a: input_stats(a),
+        inputs_b: input_stats(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, point};
+
+    /// `total_ms` over `len_m` at an even pace, a point every 10th of it.
+    fn even_lap(total_ms: u64, len_m: f64) -> Lap {
+        let pts = (0..=10).map(|i| point(total_ms as f64 * i as f64 / 10.0, len_m * i as f64 / 10.0)).collect();
+        lap(total_ms, pts)
+    }
+
+    fn against(reference: &Lap) -> CompareOptions {
+        CompareOptions { reference: Some(reference.id), step_m: 100.0, ..Default::default() }
+    }
+
+    #[test]
+    fn deltas_against_an_explicit_reference() {
+        let (reference, slower) = (even_lap(100_000, 1000.0), even_lap(110_000, 1000.0));
+        let laps = [reference.clone(), slower.clone()];
+        let c = compare_laps(&laps, &[] as &[Lap], &against(&reference)).unwrap();
+        assert_eq!(c.reference_id, reference.id);
+        assert_eq!(c.speed.distance.len(), 11);
+        assert_eq!(c.speed.series.iter().map(|s| s.lap_id).collect::<Vec<_>>(), [reference.id, slower.id]);
+        // the reference isn't compared with itself
+        assert_eq!(c.deltas.len(), 1);
+        assert_eq!(c.deltas[0].lap_id, slower.id);
+        assert_eq!((c.deltas[0].delta_ms[0], c.deltas[0].delta_ms[5], c.deltas[0].delta_ms[10]), (0.0, 5_000.0, 10_000.0));
+    }
+
+    #[test]
+    fn the_reference_may_come_from_outside_the_comparison() {
+        let best = even_lap(90_000, 1000.0);
+        let laps = [even_lap(100_000, 1000.0)];
+        let c = compare_laps(&laps, &[&best], &against(&best)).unwrap();
+        assert_eq!(c.reference_id, best.id);
+        assert_eq!(c.deltas[0].delta_ms[10], 10_000.0);
+    }
+
+    #[test]
+    fn normalized_alignment_stretches_to_the_reference() {
+        let reference = even_lap(100_000, 1000.0);
+        // the same pace round a lap measured 10% long
+        let long = even_lap(100_000, 1100.0);
+        let laps = [reference.clone(), long];
+        let by_distance = compare_laps(&laps, &[] as &[Lap], &against(&reference)).unwrap();
+        assert!(by_distance.deltas[0].delta_ms[10] < 0.0);
+
+        let opts = CompareOptions { alignment: Alignment::Normalized, ..against(&reference) };
+        let normalized = compare_laps(&laps, &[] as &[Lap], &opts).unwrap();
+        assert!(normalized.deltas[0].delta_ms.iter().all(|d| d.abs() < 1e-9), "{:?}", normalized.deltas[0]);
+    }
+
+    #[test]
+    fn max_points_decimates_every_series_alike() {
+        let reference = even_lap(100_000, 1000.0);
+        let laps = [reference.clone(), even_lap(105_000, 1000.0), even_lap(110_000, 1000.0)];
+        let opts = CompareOptions { step_m: 1.0, max_points: Some(50), ..against(&reference) };
+        let c = compare_laps(&laps, &[] as &[Lap], &opts).unwrap();
+        assert_eq!(c.speed.distance.len(), 50);
+        assert!(c.speed.series.iter().all(|s| s.values.len() == 50));
+        assert!(c.deltas.iter().all(|d| d.delta_ms.len() == 50));
+        assert_eq!((c.speed.distance[0], c.speed.distance[49]), (0.0, 1000.0));
+    }
+
+    #[test]
+    fn compare_errors() {
+        let l = even_lap(100_000, 1000.0);
+        let none: &[Lap] = &[];
+        let opts = |step_m| CompareOptions { step_m, ..Default::default() };
+        assert_eq!(compare_laps(&[&l], none, &opts(0.0)), Err(CompareError::BadStep(0.0)));
+        assert!(matches!(compare_laps(&[&l], none, &opts(f64::NAN)), Err(CompareError::BadStep(_))));
+        assert_eq!(compare_laps(none, none, &opts(1.0)), Err(CompareError::NoLaps));
+        assert_eq!(compare_laps(&[lap(1, Vec::new())], none, &opts(1.0)), Err(CompareError::NoLaps));
+        let missing = Uuid::new_v4();
+        let opts = CompareOptions { reference: Some(missing), ..Default::default() };
+        assert_eq!(compare_laps(&[l], none, &opts), Err(CompareError::UnknownReference(missing)));
+    }
+
+    #[test]
+    fn input_shares_are_weighted_by_time() {
+        let pedals = [(1.0, 0.0, 3), (1.0, 0.0, 4), (0.0, 1.0, 4), (0.0, 0.0, 3), (0.5, 0.0, 3), (0.0, 0.5, 3)];
+        let pts = pedals
+            .iter()
+            .enumerate()
+            .map(|(i, &(throttle, brake, gear))| TelemetryPoint { throttle, brake, gear, ..point(i as f64 * 1_000.0, 0.0) })
+            .collect();
+        let s = input_stats(&lap(5_000, pts));
+        assert_eq!((s.full_throttle, s.braking, s.coasting), (0.4, 0.2, 0.2));
+        assert_eq!(s.avg_throttle, 0.5);
+        assert_eq!((s.brake_applications, s.gear_changes), (2, 2));
+        assert_eq!(input_stats(&lap(0, Vec::new())), InputStats::default());
+    }
+
+    #[test]
+    fn head_to_head_sectors_add_up_to_the_lap() {
+        let (a, b) = (even_lap(100_000, 1000.0), even_lap(104_000, 1000.0));
+        let h = head_to_head(&a, &b);
+        assert_eq!((h.a, h.b, h.lap_delta_ms), (a.id, b.id, 4_000));
+        assert!(!h.sectors.is_empty());
+        let sum: f64 = h.sectors.iter().map(|s| s.delta_ms).sum();
+        assert!((sum - 4_000.0).abs() < 1e-9);
+        assert_eq!(sector_times(&a, &[Sector { start_m: 200.0, end_m: 500.0 }]), [30_000.0]);
+    }
+}
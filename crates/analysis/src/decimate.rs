@@ -0,0 +1,130 @@
+//! Downsampling of distance series for charting, by
+//! largest-triangle-three-buckets (LTTB): a few thousand points that keep the
+//! peaks and troughs a chart of the full series would show.
+//!
+//! Series on a shared grid are decimated together: one set of grid points is
+//! kept for all of them, picked by their combined triangle areas, so rows of
+//! an overlay stay aligned.
+
+/// Positions in `x` to keep, at most `target` of them, always including the
+/// first and last. Every series in `ys` runs along `x`; each counts relative
+/// to its own range, so kph and ms weigh the same. All positions when `x` has
+/// no more than `target`.
+pub fn lttb(x: &[f64], ys: &[&[f64]], target: usize) -> Vec<usize> {
+    let n = x.len();
+    let target = target.max(3);
+    if n <= target {
+        return (0..n).collect();
+    }
+    let scale: Vec<f64> = ys
+        .iter()
+        .map(|y| {
+            let (lo, hi) = y.iter().filter(|v| v.is_finite()).fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+                (lo.min(v), hi.max(v))
+            });
+            if hi > lo {
+                1.0 / (hi - lo)
+            } else {
+                1.0
+            }
+        })
+        .collect();
+
+    // the first and last points are their own buckets; the rest are split
+    // evenly between the other target - 2
+    let every = (n - 2) as f64 / (target - 2) as f64;
+    let bucket = |i: usize| (i as f64 * every) as usize + 1..(((i + 1) as f64 * every) as usize + 1).min(n - 1);
+
+    let mut keep = Vec::with_capacity(target);
+    keep.push(0);
+    let mut a = 0;
+    let mut avg = vec![0.0; ys.len()];
+    for i in 0..target - 2 {
+        // the third corner is the average of the next bucket
+        let next = if i + 1 < target - 2 { bucket(i + 1) } else { n - 1..n };
+        let len = next.len() as f64;
+        let avg_x = x[next.clone()].iter().sum::<f64>() / len;
+        for (m, y) in avg.iter_mut().zip(ys) {
+            *m = y[next.clone()].iter().sum::<f64>() / len;
+        }
+
+        let mut best = (f64::NEG_INFINITY, a + 1);
+        for j in bucket(i) {
+            let area: f64 = ys
+                .iter()
+                .zip(&avg)
+                .zip(&scale)
+                .map(|((y, &avg_y), &k)| {
+                    let t = ((x[a] - avg_x) * (y[j] - y[a]) - (x[a] - x[j]) * (avg_y - y[a])).abs() * k;
+                    if t.is_nan() {
+                        0.0
+                    } else {
+                        t
+                    }
+                })
+                .sum();
+            if area > best.0 {
+                best = (area, j);
+            }
+        }
+        keep.push(best.1);
+        a = best.1;
+    }
+    keep.push(n - 1);
+    keep
+}
+
+/// `values` at the positions `keep`.
+pub fn select(values: &[f64], keep: &[usize]) -> Vec<f64> {
+    keep.iter().map(|&i| values[i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(n: usize) -> Vec<f64> {
+        (0..n).map(|i| i as f64).collect()
+    }
+
+    #[test]
+    fn keeps_target_increasing_positions_with_the_ends() {
+        let x = grid(1000);
+        let y: Vec<f64> = x.iter().map(|d| (d / 37.0).sin()).collect();
+        for target in [3, 10, 250, 999] {
+            let keep = lttb(&x, &[&y], target);
+            assert_eq!(keep.len(), target);
+            assert_eq!((keep[0], keep[target - 1]), (0, 999));
+            assert!(keep.windows(2).all(|w| w[0] < w[1]), "{keep:?}");
+        }
+    }
+
+    #[test]
+    fn keeps_everything_when_the_target_covers_the_series() {
+        let x = grid(20);
+        let y = vec![1.0; 20];
+        assert_eq!(lttb(&x, &[&y], 20), (0..20).collect::<Vec<_>>());
+        assert_eq!(lttb(&x, &[&y], 500), (0..20).collect::<Vec<_>>());
+        assert_eq!(lttb(&x[..2], &[&y[..2]], 0), [0, 1]);
+    }
+
+    #[test]
+    fn keeps_a_spike() {
+        let x = grid(1000);
+        let mut flat = vec![100.0; 1000];
+        flat[613] = 250.0;
+        let keep = lttb(&x, &[&flat], 20);
+        assert!(keep.contains(&613), "{keep:?}");
+    }
+
+    #[test]
+    fn a_spike_in_any_series_is_kept_for_all() {
+        let x = grid(1000);
+        let flat = vec![0.0; 1000];
+        let mut spiky = vec![5_000.0; 1000];
+        spiky[421] = 5_001.0;
+        let keep = lttb(&x, &[&flat, &spiky], 20);
+        assert!(keep.contains(&421), "{keep:?}");
+        assert_eq!(select(&spiky, &keep).len(), keep.len());
+    }
+}
@@ -1,47 +1,79 @@
 use model::*;
+use rayon::prelude::*;
 use serde_json::{json, Value};
+use std::borrow::Borrow;
+
+pub mod anomaly;
+pub mod attribution;
+pub mod cluster;
+pub mod compare;
+pub mod decimate;
+pub mod live;
+pub mod math;
+pub mod reference;
+pub mod resample;
+pub mod results;
+pub mod stats;
+pub mod stream;
+pub mod trackmap;
+#[cfg(test)]
+mod testutil;
+
+pub use results::{CornerMetrics, DeltaSeries, OverlayResult, OverlaySeries, SessionSummary};
+
+use math::{eval_channels, MathChannel, MathError};
+use stats::StatsOptions;
+use resample::{distance_grid, max_distance, resample_column, resample_lap, ResampledLap};
+
+/// Speed of every lap on a 1 m distance grid.
+pub fn speed_overlay<L: Borrow<Lap> + Sync>(laps: &[L]) -> OverlayResult {
+    let grid = distance_grid(max_distance(laps), 1.0);
+    let series = laps
+        .par_iter()
+        .map(Borrow::borrow)
+        .map(|l: &Lap| OverlaySeries {
+            lap_id: l.id,
+            channel: "speed".into(),
+            values: resample_lap(l, &grid).speed_kph,
+        })
+        .collect();
+    OverlayResult { distance: grid, series }
+}
 
-pub fn overlay_speed_vs_distance(laps: &[Lap]) -> Value {
-    let max_len = laps
-        .iter()
-        .filter_map(|l| l.points.last().map(|p| p.lap_distance_m))
-        .fold(0.0_f64, f64::max);
-
-    let step = 1.0_f64;
-    let mut rows = Vec::new();
-    let mut d = 0.0_f64;
-
-    while d <= max_len {
-        let mut row = serde_json::Map::new();
-        row.insert("distance".into(), json!(d));
-        for lap in laps {
-            let v = sample_speed_at_distance(lap, d);
-            row.insert(format!("speed_{}", lap.id), json!(v));
-        }
-        rows.push(Value::Object(row));
-        d += step;
-    }
+/// User math channels of every lap on a 1 m distance grid, one series per channel and lap.
+pub fn math_overlay(laps: &[Lap], channels: &[MathChannel]) -> Result<OverlayResult, MathError> {
+    let grid = distance_grid(max_distance(laps), 1.0);
+    let per_lap = laps
+        .par_iter()
+        .map(|lap| {
+            let cols = eval_channels(lap, channels)?;
+            Ok(cols
+                .into_iter()
+                .map(|(name, values)| OverlaySeries {
+                    lap_id: lap.id,
+                    channel: name,
+                    values: resample_column(lap, &values, &grid),
+                })
+                .collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<_>, MathError>>()?;
+    Ok(OverlayResult { distance: grid, series: per_lap.into_iter().flatten().collect() })
+}
 
-    Value::Array(rows)
+#[deprecated(note = "use `speed_overlay`")]
+pub fn overlay_speed_vs_distance(laps: &[Lap]) -> Value {
+    speed_overlay(laps).to_rows()
 }
 
-fn sample_speed_at_distance(lap: &Lap, dist: f64) -> f64 {
-    if lap.points.is_empty() {
-        return 0.0;
-    }
-    let mut best = lap.points[0].speed_kph;
-    let mut bd = f64::INFINITY;
-    for p in &lap.points {
-        let dd = (p.lap_distance_m - dist).abs();
-        if dd < bd {
-            bd = dd;
-            best = p.speed_kph;
-        }
-    }
-    best
+/// Best, worst and average lap time and consistency of `laps`, which may be
+/// shared (`Arc<Lap>`) or borrowed. Out-laps and in-laps are left out.
+pub fn session_summary<L: Borrow<Lap>>(laps: &[L]) -> SessionSummary {
+    session_summary_with(laps, &StatsOptions::default())
 }
 
-pub fn lap_summary(laps: &[Lap]) -> Value {
+/// Like [`session_summary`], counting the laps `opts` asks for.
+pub fn session_summary_with<L: Borrow<Lap>>(laps: &[L], opts: &StatsOptions) -> SessionSummary {
+    let laps: Vec<&Lap> = laps.iter().map(Borrow::borrow).filter(|l| opts.counts(l)).collect();
     let best = laps.iter().map(|l| l.total_time_ms).min().unwrap_or(0);
     let worst = laps.iter().map(|l| l.total_time_ms).max().unwrap_or(0);
     let avg = if !laps.is_empty() {
@@ -52,16 +84,22 @@ pub fn lap_summary(laps: &[Lap]) -> Value {
 
     // collect simple 3-way split sector times (ms) across all laps
     let mut sector_times_ms = Vec::new();
-    for l in laps {
+    for l in &laps {
         sector_times_ms.extend(thirds(l).into_iter().map(|x| x as f64));
     }
     let consistency = stddev(&sector_times_ms);
 
+    SessionSummary { lap_count: laps.len(), best_ms: best, worst_ms: worst, avg_ms: avg, consistency }
+}
+
+#[deprecated(note = "use `session_summary`")]
+pub fn lap_summary(laps: &[Lap]) -> Value {
+    let s = session_summary(laps);
     json!({
-        "best_ms": best,
-        "worst_ms": worst,
-        "avg_ms": avg,
-        "consistency": consistency
+        "best_ms": s.best_ms,
+        "worst_ms": s.worst_ms,
+        "avg_ms": s.avg_ms,
+        "consistency": s.consistency
     })
 }
 
@@ -101,84 +139,49 @@ fn stddev(v: &[f64]) -> f64 {
     (var.sqrt()) / 1000.0
 }
 
-pub fn rolling_delta_vs_reference(reference: &Lap, laps: &[Lap]) -> Value {
+/// Mean time delta of `laps` against `reference` on a 1 m grid.
+pub fn delta_series<L: Borrow<Lap> + Sync>(reference: &Lap, laps: &[L]) -> DeltaSeries {
     let max_len = reference
         .points
         .last()
         .map(|p| p.lap_distance_m)
         .unwrap_or(0.0);
 
-    let step = 1.0_f64;
-    let mut rows = Vec::new();
-    let mut d = 0.0_f64;
-
-    while d <= max_len {
-        let t_ref = time_at_distance(reference, d);
-        let mut delta = 0.0_f64;
-        let mut count = 0.0_f64;
-
-        for lap in laps {
-            if lap.id == reference.id {
-                continue;
+    let grid = distance_grid(max_len, 1.0);
+    let t_ref = resample_lap(reference, &grid).t_ms;
+    let others: Vec<ResampledLap> = laps
+        .par_iter()
+        .map(Borrow::borrow)
+        .filter(|l: &&Lap| l.id != reference.id)
+        .map(|l| resample_lap(l, &grid))
+        .collect();
+
+    let delta_ms = (0..grid.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut delta = 0.0_f64;
+            for r in &others {
+                delta += r.t_ms[i] - t_ref[i];
             }
-            let t = time_at_distance(lap, d);
-            delta += t - t_ref;
-            count += 1.0;
-        }
-
-        if count > 0.0 {
-            delta /= count;
-        }
-
-        rows.push(json!({
-            "distance": d,
-            "delta_ms": delta
-        }));
-        d += step;
-    }
+            if !others.is_empty() {
+                delta /= others.len() as f64;
+            }
+            delta
+        })
+        .collect();
 
-    Value::Array(rows)
+    DeltaSeries { reference_id: reference.id, distance: grid, delta_ms }
 }
 
-fn time_at_distance(lap: &Lap, dist: f64) -> f64 {
-    if lap.points.is_empty() {
-        return 0.0;
-    }
-
-    let mut best_t = lap.points.last().map(|p| p.t_ms).unwrap_or(0.0);
-    let mut bd = f64::INFINITY;
-
-    for p in &lap.points {
-        let dd = (p.lap_distance_m - dist).abs();
-        if dd < bd {
-            bd = dd;
-            best_t = p.t_ms;
-        }
-    }
-
-    let t0 = lap.points.first().map(|p| p.t_ms).unwrap_or(0.0);
-    best_t - t0
+#[deprecated(note = "use `delta_series`")]
+pub fn rolling_delta_vs_reference(reference: &Lap, laps: &[Lap]) -> Value {
+    delta_series(reference, laps).to_rows()
 }
 
+/// Outline, corners and sectors of the track `lap` was driven on, in the
+/// lap's own axes: [`trackmap::build_track_map_with`] and default options.
 pub fn build_track_map(lap: &Lap) -> TrackMap {
-    let pl: Vec<Point2> = lap.points.iter().map(|p| Point2 { x: p.x, y: p.y }).collect();
-    let bbox = bbox_of(&pl);
-    let curv = curvature_series(&lap.points);
-    let peaks = peak_indices(&curv, 12, 0.03);
-
-    let mut corners = Vec::new();
-    for (i, idx) in peaks.iter().enumerate() {
-        if let Some(p) = lap.points.get(*idx) {
-            corners.push(CornerLabel {
-                index: (i + 1) as u32,
-                x: p.x,
-                y: p.y,
-            });
-        }
-    }
-
-    let sectors = auto_sectors(lap, &curv, 3);
-    TrackMap { polyline: pl, corners, sectors, bbox }
+    trackmap::build_track_map_with(lap, &trackmap::TrackMapOptions::default())
 }
 
 fn bbox_of(pl: &[Point2]) -> BBox {
@@ -225,16 +228,11 @@ fn curvature_series(points: &[TelemetryPoint]) -> Vec<f64> {
 
     // light smoothing
     let mut s = vec![0.0; n];
-    for i in 0..n {
-        let mut sum = 0.0;
-        let mut cnt = 0.0;
+    for (i, si) in s.iter_mut().enumerate() {
         let from = i.saturating_sub(2);
         let to = (i + 3).min(n);
-        for k in from..to {
-            sum += c[k];
-            cnt += 1.0;
-        }
-        s[i] = if cnt > 0.0 { sum / cnt } else { 0.0 };
+        let w = &c[from..to];
+        *si = if w.is_empty() { 0.0 } else { w.iter().sum::<f64>() / w.len() as f64 };
     }
 
     s
@@ -253,13 +251,7 @@ fn peak_indices(curv: &[f64], window: usize, threshold: f64) -> Vec<usize> {
             continue;
         }
         // v must be a local maximum in the window
-        let mut is_peak = true;
-        for k in (i - window)..(i + window) {
-            if curv[k] > v {
-                is_peak = false;
-                break;
-            }
-        }
+        let is_peak = curv[(i - window)..(i + window)].iter().all(|&k| k <= v);
         if is_peak {
             peaks.push(i);
         }
@@ -296,7 +288,7 @@ fn auto_sectors(lap: &Lap, curv: &[f64], n: usize) -> Vec<Sector> {
     sectors
 }
 
-pub fn per_corner_metrics(reference: &Lap) -> Vec<Value> {
+pub fn corner_metrics(reference: &Lap) -> CornerMetrics {
     let curv = curvature_series(&reference.points);
     let peaks = peak_indices(&curv, 12, 0.03);
     let mut out = Vec::new();
@@ -339,19 +331,29 @@ pub fn per_corner_metrics(reference: &Lap) -> Vec<Value> {
             }
         }
 
-        out.push(json!({
-            "index": i + 1,
-            "start_m": reference.points[start].lap_distance_m,
-            "apex_m": apex.lap_distance_m,
-            "end_m": reference.points[end].lap_distance_m,
-            "x": apex.x, "y": apex.y,
-            "min_speed": min_speed,
-            "entry_speed": entry,
-            "exit_speed": exit,
-            "brake_point_m": brake_m,
-            "throttle_on_m": throt_m
-        }));
+        out.push(Corner {
+            index: (i + 1) as u32,
+            start_m: reference.points[start].lap_distance_m,
+            apex_m: apex.lap_distance_m,
+            end_m: reference.points[end].lap_distance_m,
+            x: apex.x,
+            y: apex.y,
+            min_speed,
+            entry_speed: entry,
+            exit_speed: exit,
+            brake_point_m: brake_m,
+            throttle_on_m: throt_m,
+        });
     }
 
-    out
+    CornerMetrics { lap_id: reference.id, corners: out }
+}
+
+#[deprecated(note = "use `corner_metrics`")]
+pub fn per_corner_metrics(reference: &Lap) -> Vec<Value> {
+    corner_metrics(reference)
+        .corners
+        .iter()
+        .map(|c| serde_json::to_value(c).unwrap_or(Value::Null))
+        .collect()
 }
@@ -0,0 +1,160 @@
+//! Incremental delta-to-best and predicted lap time, fed one point at a time.
+
+use model::*;
+use serde::Serialize;
+
+#[derive(Clone, Serialize, Debug, Default, PartialEq)]
+pub struct LiveDeltaState {
+    pub lap_number: u32,
+    pub distance_m: f64,
+    pub elapsed_ms: f64,
+    /// Current lap time minus the reference lap's time at the same distance (ms).
+    pub delta_ms: Option<f64>,
+    /// Reference lap time plus the current delta (ms).
+    pub predicted_ms: Option<f64>,
+    pub best_ms: Option<u64>,
+}
+
+/// Keeps a distance/time index of the reference (session-best) lap and
+/// compares the running lap against it as points arrive.
+#[derive(Clone, Debug, Default)]
+pub struct LiveDelta {
+    reference: LapIndex,
+    best_ms: Option<u64>,
+    lap_t0: Option<f64>,
+    state: LiveDeltaState,
+}
+
+impl LiveDelta {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Offer a completed lap; it becomes the reference if it beats the current best.
+    pub fn offer_lap(&mut self, lap: &Lap) -> bool {
+        if lap.points.len() < 2 || lap.total_time_ms == 0 {
+            return false;
+        }
+        if self.best_ms.is_some_and(|b| lap.total_time_ms >= b) {
+            return false;
+        }
+        self.set_reference(lap);
+        true
+    }
+
+    /// Replace the reference lap unconditionally.
+    pub fn set_reference(&mut self, lap: &Lap) {
+        self.reference = lap.index().clone();
+        self.best_ms = Some(lap.total_time_ms);
+        self.state.best_ms = self.best_ms;
+    }
+
+    /// Start timing a new lap.
+    pub fn begin_lap(&mut self, lap_number: u32) {
+        self.lap_t0 = None;
+        self.state = LiveDeltaState { lap_number, best_ms: self.best_ms, ..Default::default() };
+    }
+
+    /// Feed the latest point of the running lap and return the updated state.
+    pub fn push(&mut self, p: &TelemetryPoint) -> &LiveDeltaState {
+        let t0 = *self.lap_t0.get_or_insert(p.t_ms);
+        let elapsed = p.t_ms - t0;
+        self.state.distance_m = p.lap_distance_m;
+        self.state.elapsed_ms = elapsed;
+
+        match self.ref_time_at(p.lap_distance_m) {
+            Some(t_ref) => {
+                let delta = elapsed - t_ref;
+                self.state.delta_ms = Some(delta);
+                self.state.predicted_ms = self.best_ms.map(|b| b as f64 + delta);
+            }
+            None => {
+                self.state.delta_ms = None;
+                self.state.predicted_ms = None;
+            }
+        }
+        &self.state
+    }
+
+    pub fn state(&self) -> &LiveDeltaState {
+        &self.state
+    }
+
+    /// Distance/time index of the reference lap; empty without one.
+    pub fn reference(&self) -> &LapIndex {
+        &self.reference
+    }
+
+    fn ref_time_at(&self, dist: f64) -> Option<f64> {
+        let (first, last) = self.reference.range()?;
+        if self.reference.len() < 2 || dist < first || dist > last {
+            return None;
+        }
+        self.reference.time_at_distance(dist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, point};
+
+    /// `total_ms` over 1000 m at an even pace, a point every 100 m.
+    fn even_lap(total_ms: u64) -> Lap {
+        let pts = (0..=10).map(|i| point(total_ms as f64 * i as f64 / 10.0, i as f64 * 100.0)).collect();
+        lap(total_ms, pts)
+    }
+
+    #[test]
+    fn no_delta_without_a_reference() {
+        let mut live = LiveDelta::new();
+        live.begin_lap(1);
+        let s = live.push(&point(1000.0, 50.0));
+        assert_eq!((s.delta_ms, s.predicted_ms, s.best_ms), (None, None, None));
+        assert_eq!(s.distance_m, 50.0);
+    }
+
+    #[test]
+    fn only_a_faster_usable_lap_becomes_the_reference() {
+        let mut live = LiveDelta::new();
+        assert!(live.offer_lap(&even_lap(100_000)));
+        assert!(!live.offer_lap(&even_lap(110_000)));
+        assert!(!live.offer_lap(&even_lap(100_000)));
+        assert!(!live.offer_lap(&lap(90_000, vec![point(0.0, 0.0)])));
+        assert!(!live.offer_lap(&lap(0, even_lap(1).points)));
+        assert!(live.offer_lap(&even_lap(95_000)));
+        assert_eq!(live.state().best_ms, Some(95_000));
+    }
+
+    #[test]
+    fn delta_and_prediction_against_the_reference() {
+        let mut live = LiveDelta::new();
+        live.offer_lap(&even_lap(100_000));
+        live.begin_lap(2);
+        // timing starts at the lap's first point, whatever the clock says
+        live.push(&point(5_000.0, 0.0));
+        let s = live.push(&point(53_000.0, 500.0)).clone();
+        assert_eq!(s.lap_number, 2);
+        assert_eq!(s.elapsed_ms, 48_000.0);
+        assert_eq!(s.delta_ms, Some(-2_000.0));
+        assert_eq!(s.predicted_ms, Some(98_000.0));
+
+        // past the end of the reference there is nothing to compare with
+        let s = live.push(&point(110_000.0, 1_050.0));
+        assert_eq!((s.delta_ms, s.predicted_ms), (None, None));
+    }
+
+    #[test]
+    fn a_new_lap_starts_over_and_keeps_the_best() {
+        let mut live = LiveDelta::new();
+        live.offer_lap(&even_lap(100_000));
+        live.begin_lap(2);
+        live.push(&point(0.0, 0.0));
+        live.push(&point(20_000.0, 200.0));
+        live.begin_lap(3);
+        assert_eq!(live.state(), &LiveDeltaState { lap_number: 3, best_ms: Some(100_000), ..Default::default() });
+        let s = live.push(&point(30_000.0, 0.0));
+        assert_eq!(s.elapsed_ms, 0.0);
+        assert_eq!(s.delta_ms, Some(0.0));
+    }
+}
@@ -0,0 +1,393 @@
+//! User-defined math channels, e.g. `brake*100`, `speed_kph/rpm`, `lat_g/long_g`.
+//!
+//! Expressions support `+ - * / ^`, unary minus, parentheses, numeric literals,
+//! the functions `abs sqrt min max sin cos clamp`, and any channel name from
+//! [`CHANNELS`].
+
+use std::collections::HashMap;
+
+use model::*;
+use serde::{Deserialize, Serialize};
+
+pub use model::wrap_angle;
+
+/// Channel names an expression may reference.
+pub const CHANNELS: &[&str] = &[
+    "t_ms", "t_s", "lap_distance_m", "x", "y", "speed_kph", "speed_mps", "throttle", "brake",
+    "gear", "rpm", "yaw", "long_g", "lat_g",
+];
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MathChannel {
+    pub name: String,
+    pub expr: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MathError {
+    Parse { pos: usize, msg: String },
+    UnknownChannel(String),
+    UnknownFunction(String),
+    Arity { func: String, expected: usize, got: usize },
+}
+
+impl std::fmt::Display for MathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MathError::Parse { pos, msg } => write!(f, "parse error at {pos}: {msg}"),
+            MathError::UnknownChannel(c) => write!(f, "unknown channel `{c}`"),
+            MathError::UnknownFunction(c) => write!(f, "unknown function `{c}`"),
+            MathError::Arity { func, expected, got } => {
+                write!(f, "`{func}` takes {expected} argument(s), got {got}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MathError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Bin(Op, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+impl Expr {
+    pub fn parse(src: &str) -> Result<Expr, MathError> {
+        let mut p = Parser { src: src.as_bytes(), pos: 0 };
+        let e = p.expr()?;
+        p.skip_ws();
+        if p.pos < p.src.len() {
+            return Err(p.err("unexpected trailing input"));
+        }
+        e.check()?;
+        Ok(e)
+    }
+
+    /// Evaluate over every point of a lap.
+    pub fn eval_lap(&self, lap: &Lap) -> Vec<f64> {
+        let mut names = Vec::new();
+        self.vars(&mut names);
+        let cols: HashMap<&str, Vec<f64>> =
+            names.into_iter().map(|n| (n, channel_column(lap, n))).collect();
+        (0..lap.points.len()).map(|i| self.eval(&|n| cols[n][i])).collect()
+    }
+
+    fn eval(&self, var: &dyn Fn(&str) -> f64) -> f64 {
+        match self {
+            Expr::Num(v) => *v,
+            Expr::Var(n) => var(n),
+            Expr::Neg(e) => -e.eval(var),
+            Expr::Bin(op, a, b) => {
+                let (a, b) = (a.eval(var), b.eval(var));
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    // a math channel shouldn't poison a chart with infinities
+                    Op::Div => if b == 0.0 { 0.0 } else { a / b },
+                    Op::Pow => a.powf(b),
+                }
+            }
+            Expr::Call(f, args) => {
+                let a: Vec<f64> = args.iter().map(|e| e.eval(var)).collect();
+                match f.as_str() {
+                    "abs" => a[0].abs(),
+                    "sqrt" => a[0].max(0.0).sqrt(),
+                    "sin" => a[0].sin(),
+                    "cos" => a[0].cos(),
+                    "min" => a[0].min(a[1]),
+                    "max" => a[0].max(a[1]),
+                    "clamp" => a[0].clamp(a[1].min(a[2]), a[1].max(a[2])),
+                    _ => f64::NAN,
+                }
+            }
+        }
+    }
+
+    fn vars<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Num(_) => {}
+            Expr::Var(n) => {
+                if !out.contains(&n.as_str()) {
+                    out.push(n);
+                }
+            }
+            Expr::Neg(e) => e.vars(out),
+            Expr::Bin(_, a, b) => {
+                a.vars(out);
+                b.vars(out);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|e| e.vars(out)),
+        }
+    }
+
+    fn check(&self) -> Result<(), MathError> {
+        match self {
+            Expr::Num(_) => Ok(()),
+            Expr::Var(n) if CHANNELS.contains(&n.as_str()) => Ok(()),
+            Expr::Var(n) => Err(MathError::UnknownChannel(n.clone())),
+            Expr::Neg(e) => e.check(),
+            Expr::Bin(_, a, b) => a.check().and(b.check()),
+            Expr::Call(f, args) => {
+                let expected = match f.as_str() {
+                    "abs" | "sqrt" | "sin" | "cos" => 1,
+                    "min" | "max" => 2,
+                    "clamp" => 3,
+                    _ => return Err(MathError::UnknownFunction(f.clone())),
+                };
+                if args.len() != expected {
+                    return Err(MathError::Arity { func: f.clone(), expected, got: args.len() });
+                }
+                args.iter().try_for_each(|e| e.check())
+            }
+        }
+    }
+}
+
+/// Evaluate a set of math channels over a lap, in order.
+pub fn eval_channels(lap: &Lap, channels: &[MathChannel]) -> Result<Vec<(String, Vec<f64>)>, MathError> {
+    channels
+        .iter()
+        .map(|c| Ok((c.name.clone(), Expr::parse(&c.expr)?.eval_lap(lap))))
+        .collect()
+}
+
+/// One column of raw or derived channel values for a lap.
+pub fn channel_column(lap: &Lap, name: &str) -> Vec<f64> {
+    let pts = &lap.points;
+    match name {
+        "long_g" => long_g(pts),
+        "lat_g" => lat_g(pts),
+        _ => pts
+            .iter()
+            .map(|p| match name {
+                "t_ms" => p.t_ms,
+                "t_s" => p.t_ms / 1000.0,
+                "lap_distance_m" => p.lap_distance_m,
+                "x" => p.x,
+                "y" => p.y,
+                "speed_kph" => p.speed_kph,
+                "speed_mps" => p.speed_kph / 3.6,
+                "throttle" => p.throttle,
+                "brake" => p.brake,
+                "gear" => p.gear as f64,
+                "rpm" => p.rpm,
+                "yaw" => p.yaw,
+                _ => 0.0,
+            })
+            .collect(),
+    }
+}
+
+// longitudinal acceleration in g from the speed trace (central difference)
+fn long_g(pts: &[TelemetryPoint]) -> Vec<f64> {
+    let n = pts.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (i.saturating_sub(1), (i + 1).min(n.saturating_sub(1)));
+            let dt = (pts[b].t_ms - pts[a].t_ms) / 1000.0;
+            if dt > 0.0 {
+                (pts[b].speed_kph - pts[a].speed_kph) / 3.6 / dt / 9.81
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+// lateral acceleration in g: v * heading rate of the driven path
+fn lat_g(pts: &[TelemetryPoint]) -> Vec<f64> {
+    let n = pts.len();
+    (0..n)
+        .map(|i| {
+            if i == 0 || i + 1 >= n {
+                return 0.0;
+            }
+            let (p0, p1, p2) = (&pts[i - 1], &pts[i], &pts[i + 1]);
+            let h1 = (p1.y - p0.y).atan2(p1.x - p0.x);
+            let h2 = (p2.y - p1.y).atan2(p2.x - p1.x);
+            let dh = wrap_angle(h2 - h1);
+            let dt = (p2.t_ms - p0.t_ms) / 2000.0;
+            if dt > 0.0 {
+                p1.speed_kph / 3.6 * dh / dt / 9.81
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn err(&self, msg: &str) -> MathError {
+        MathError::Parse { pos: self.pos, msg: msg.into() }
+    }
+
+    fn skip_ws(&mut self) {
+        while self.pos < self.src.len() && self.src[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_ws();
+        self.src.get(self.pos).copied()
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, MathError> {
+        let mut lhs = self.term()?;
+        while let Some(c @ (b'+' | b'-')) = self.peek() {
+            self.pos += 1;
+            let op = if c == b'+' { Op::Add } else { Op::Sub };
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr, MathError> {
+        let mut lhs = self.unary()?;
+        while let Some(c @ (b'*' | b'/')) = self.peek() {
+            self.pos += 1;
+            let op = if c == b'*' { Op::Mul } else { Op::Div };
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Expr, MathError> {
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    // power := atom ('^' unary)?   (right-associative)
+    fn power(&mut self) -> Result<Expr, MathError> {
+        let base = self.atom()?;
+        if self.peek() == Some(b'^') {
+            self.pos += 1;
+            return Ok(Expr::Bin(Op::Pow, Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr, MathError> {
+        match self.peek() {
+            Some(b'(') => {
+                self.pos += 1;
+                let e = self.expr()?;
+                if self.peek() != Some(b')') {
+                    return Err(self.err("expected `)`"));
+                }
+                self.pos += 1;
+                Ok(e)
+            }
+            Some(c) if c.is_ascii_digit() || c == b'.' => {
+                let start = self.pos;
+                while self.pos < self.src.len()
+                    && (self.src[self.pos].is_ascii_digit() || self.src[self.pos] == b'.')
+                {
+                    self.pos += 1;
+                }
+                let s = std::str::from_utf8(&self.src[start..self.pos]).unwrap_or_default();
+                s.parse().map(Expr::Num).map_err(|_| self.err("invalid number"))
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == b'_' => {
+                let start = self.pos;
+                while self.pos < self.src.len()
+                    && (self.src[self.pos].is_ascii_alphanumeric() || self.src[self.pos] == b'_')
+                {
+                    self.pos += 1;
+                }
+                let name = String::from_utf8_lossy(&self.src[start..self.pos]).into_owned();
+                if self.peek() != Some(b'(') {
+                    return Ok(Expr::Var(name));
+                }
+                self.pos += 1;
+                let mut args = Vec::new();
+                if self.peek() == Some(b')') {
+                    self.pos += 1;
+                    return Ok(Expr::Call(name, args));
+                }
+                loop {
+                    args.push(self.expr()?);
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b')') => {
+                            self.pos += 1;
+                            return Ok(Expr::Call(name, args));
+                        }
+                        _ => return Err(self.err("expected `,` or `)`")),
+                    }
+                }
+            }
+            Some(_) => Err(self.err("unexpected character")),
+            None => Err(self.err("unexpected end of expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, point};
+
+    fn eval(src: &str) -> f64 {
+        Expr::parse(src).unwrap().eval(&|_| f64::NAN)
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(eval("-2 ^ 2"), -4.0);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("8 / 0"), 0.0);
+        assert_eq!(eval("clamp(5, 3, 1) + max(1, min(4, 2)) + abs(-1.5)"), 6.5);
+    }
+
+    #[test]
+    fn evaluates_channels_per_point() {
+        let points = (0..3)
+            .map(|i| TelemetryPoint { brake: i as f64 / 4.0, speed_kph: 36.0, ..point(i as f64 * 100.0, 0.0) })
+            .collect();
+        let l = lap(200, points);
+        let out = eval_channels(&l, &[MathChannel { name: "b".into(), expr: "brake*100 + speed_mps".into() }]).unwrap();
+        assert_eq!(out, vec![("b".to_string(), vec![10.0, 35.0, 60.0])]);
+    }
+
+    #[test]
+    fn rejects_bad_expressions() {
+        assert_eq!(Expr::parse("speed_kmh").unwrap_err(), MathError::UnknownChannel("speed_kmh".into()));
+        assert_eq!(Expr::parse("tan(1)").unwrap_err(), MathError::UnknownFunction("tan".into()));
+        assert_eq!(
+            Expr::parse("min(1)").unwrap_err(),
+            MathError::Arity { func: "min".into(), expected: 2, got: 1 }
+        );
+        assert!(matches!(Expr::parse("1 +"), Err(MathError::Parse { .. })));
+        assert!(matches!(Expr::parse("(1"), Err(MathError::Parse { .. })));
+        assert!(matches!(Expr::parse("1 2"), Err(MathError::Parse { .. })));
+    }
+}
@@ -0,0 +1,151 @@
+//! Reference lap selection, including synthetic "typical" laps.
+
+use model::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use uuid::Uuid;
+
+use crate::resample::{distance_grid, resample_lap, ResampledLap};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceMode {
+    /// The quickest lap as recorded.
+    #[default]
+    Fastest,
+    /// Median of every channel at each distance.
+    Median,
+    /// Mean at each distance, weighted towards quicker laps.
+    Weighted,
+}
+
+/// Exponent applied to `best / lap time` when weighting laps.
+const WEIGHT_EXPONENT: i32 = 8;
+
+/// Build a reference lap from `laps`. Synthetic references are sampled every
+/// `step` metres over the distance all laps cover and get a fresh id with lap number 0.
+pub fn reference_lap<L: Borrow<Lap>>(laps: &[L], mode: ReferenceMode, step: f64) -> Option<Lap> {
+    let laps: Vec<&Lap> = laps.iter().map(Borrow::borrow).collect();
+    let fastest = laps
+        .iter()
+        .copied()
+        .filter(|l| !l.points.is_empty())
+        .min_by_key(|l| l.total_time_ms)?;
+    if mode == ReferenceMode::Fastest || laps.len() == 1 {
+        return Some(fastest.clone());
+    }
+
+    let common = laps
+        .iter()
+        .filter_map(|l| l.points.last().map(|p| p.lap_distance_m))
+        .fold(f64::INFINITY, f64::min);
+    let grid = distance_grid(common, step);
+    if grid.len() < 2 {
+        return Some(fastest.clone());
+    }
+
+    let used: Vec<&Lap> = laps.iter().copied().filter(|l| !l.points.is_empty()).collect();
+    let resampled: Vec<ResampledLap> = used.par_iter().map(|l| resample_lap(l, &grid)).collect();
+    let best = fastest.total_time_ms.max(1) as f64;
+    let weights: Vec<f64> = used
+        .iter()
+        .map(|l| (best / l.total_time_ms.max(1) as f64).powi(WEIGHT_EXPONENT))
+        .collect();
+
+    let combine = |column: &dyn Fn(&ResampledLap) -> f64| -> f64 {
+        let values: Vec<f64> = resampled.iter().map(column).collect();
+        match mode {
+            ReferenceMode::Weighted => weighted_mean(&values, &weights),
+            _ => median(values),
+        }
+    };
+    // headings wrap, so a median or plain mean of laps either side of ±π
+    // points the wrong way; average them as unit vectors instead
+    let combine_angle = |column: &dyn Fn(&ResampledLap) -> f64| -> f64 {
+        let values: Vec<f64> = resampled.iter().map(column).collect();
+        match mode {
+            ReferenceMode::Weighted => circular_mean(&values, &weights),
+            _ => circular_mean(&values, &vec![1.0; values.len()]),
+        }
+    };
+
+    let points = (0..grid.len())
+        .map(|i| TelemetryPoint {
+            t_ms: combine(&|r| r.t_ms[i]),
+            lap_distance_m: grid[i],
+            x: combine(&|r| r.x[i]),
+            y: combine(&|r| r.y[i]),
+            speed_kph: combine(&|r| r.speed_kph[i]),
+            throttle: combine(&|r| r.throttle[i]),
+            brake: combine(&|r| r.brake[i]),
+            gear: combine(&|r| r.gear[i] as f64).round() as i8,
+            rpm: combine(&|r| r.rpm[i]),
+            yaw: combine_angle(&|r| r.yaw[i]),
+        })
+        .collect::<Vec<_>>();
+
+    let total_time_ms = points.last().map(|p| p.t_ms.max(0.0) as u64).unwrap_or(0);
+    let id = Uuid::new_v4();
+    Some(Lap {
+        id,
+        meta: LapMeta { id, lap_number: 0, ..fastest.meta.clone() },
+        total_time_ms,
+        points,
+        markers: Vec::new(),
+        index: Default::default(),
+    })
+}
+
+fn median(mut v: Vec<f64>) -> f64 {
+    if v.is_empty() {
+        return 0.0;
+    }
+    v.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let n = v.len();
+    if n % 2 == 1 {
+        v[n / 2]
+    } else {
+        (v[n / 2 - 1] + v[n / 2]) / 2.0
+    }
+}
+
+fn weighted_mean(v: &[f64], w: &[f64]) -> f64 {
+    let total: f64 = w.iter().sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    v.iter().zip(w).map(|(a, b)| a * b).sum::<f64>() / total
+}
+
+fn circular_mean(v: &[f64], w: &[f64]) -> f64 {
+    let sin = v.iter().zip(w).map(|(a, b)| a.sin() * b).sum::<f64>();
+    let cos = v.iter().zip(w).map(|(a, b)| a.cos() * b).sum::<f64>();
+    sin.atan2(cos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{self, point};
+    use std::f64::consts::PI;
+
+    fn lap(total_time_ms: u64, yaw: f64) -> Lap {
+        let points = (0..=10)
+            .map(|i| TelemetryPoint { yaw, ..point(i as f64 * total_time_ms as f64 / 10.0, i as f64 * 10.0) })
+            .collect();
+        testutil::lap(total_time_ms, points)
+    }
+
+    #[test]
+    fn yaw_averages_across_the_wrap() {
+        // both laps head (nearly) due south, one either side of ±π
+        let laps = [lap(1000, PI - 0.1), lap(1100, -PI + 0.1)];
+        for mode in [ReferenceMode::Median, ReferenceMode::Weighted] {
+            let r = reference_lap(&laps, mode, 10.0).unwrap();
+            for p in &r.points {
+                assert!(p.yaw.abs() > PI - 0.1, "{mode:?}: {}", p.yaw);
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Resampling of lap channels onto a fixed distance grid.
+
+use model::*;
+use serde::Serialize;
+use std::borrow::Borrow;
+use uuid::Uuid;
+
+/// A lap's channels interpolated onto a shared distance grid, stored column-wise.
+#[derive(Clone, Serialize, Debug, Default, PartialEq)]
+pub struct ResampledLap {
+    pub lap_id: Uuid,
+    pub distance: Vec<f64>,
+    /// Elapsed time since the lap's first point (ms).
+    pub t_ms: Vec<f64>,
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub speed_kph: Vec<f64>,
+    pub throttle: Vec<f64>,
+    pub brake: Vec<f64>,
+    pub gear: Vec<i8>,
+    pub rpm: Vec<f64>,
+    pub yaw: Vec<f64>,
+}
+
+/// `0, step, 2*step, ..` up to and including `max_m`.
+pub fn distance_grid(max_m: f64, step: f64) -> Vec<f64> {
+    if step.is_nan() || step <= 0.0 || max_m.is_nan() || max_m < 0.0 {
+        return Vec::new();
+    }
+    let n = (max_m / step + 1e-9).floor() as usize;
+    (0..=n).map(|i| i as f64 * step).collect()
+}
+
+/// Longest recorded distance across laps.
+pub fn max_distance<L: Borrow<Lap>>(laps: &[L]) -> f64 {
+    laps.iter()
+        .filter_map(|l| l.borrow().points.last().map(|p| p.lap_distance_m))
+        .fold(0.0_f64, f64::max)
+}
+
+/// Resample a lap onto `0..=last distance` every `step` metres.
+pub fn resample_by_distance(lap: &Lap, step: f64) -> ResampledLap {
+    let max = lap.points.last().map(|p| p.lap_distance_m).unwrap_or(0.0);
+    resample_lap(lap, &distance_grid(max, step))
+}
+
+/// Resample every channel of a lap onto `grid` (ascending distances).
+/// Values outside the recorded range are held at the first/last sample.
+pub fn resample_lap(lap: &Lap, grid: &[f64]) -> ResampledLap {
+    let t0 = lap.points.first().map(|p| p.t_ms).unwrap_or(0.0);
+    let mut out = ResampledLap {
+        lap_id: lap.id,
+        distance: grid.to_vec(),
+        ..Default::default()
+    };
+    if lap.points.is_empty() {
+        let zeros = vec![0.0; grid.len()];
+        out.t_ms = zeros.clone();
+        out.x = zeros.clone();
+        out.y = zeros.clone();
+        out.speed_kph = zeros.clone();
+        out.throttle = zeros.clone();
+        out.brake = zeros.clone();
+        out.rpm = zeros.clone();
+        out.yaw = zeros;
+        out.gear = vec![0; grid.len()];
+        return out;
+    }
+
+    for p in grid.iter().filter_map(|&d| lap.sample_at_distance(d)) {
+        out.t_ms.push(p.t_ms - t0);
+        out.x.push(p.x);
+        out.y.push(p.y);
+        out.speed_kph.push(p.speed_kph);
+        out.throttle.push(p.throttle);
+        out.brake.push(p.brake);
+        out.rpm.push(p.rpm);
+        out.yaw.push(p.yaw);
+        out.gear.push(p.gear);
+    }
+    out
+}
+
+/// Interpolate an arbitrary per-point column (e.g. a math channel) onto `grid`.
+pub fn resample_column(lap: &Lap, values: &[f64], grid: &[f64]) -> Vec<f64> {
+    if lap.points.is_empty() || values.len() != lap.points.len() {
+        return vec![0.0; grid.len()];
+    }
+    let index = lap.index();
+    grid.iter()
+        .filter_map(|&d| index.bracket(d))
+        .map(|(i, j, f)| values[i] + f * (values[j] - values[i]))
+        .collect()
+}
@@ -0,0 +1,107 @@
+//! Typed outputs of the analysis functions.
+
+use model::Corner;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::decimate::{lttb, select};
+
+/// Channels of several laps on a shared distance grid.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct OverlayResult {
+    pub distance: Vec<f64>,
+    pub series: Vec<OverlaySeries>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct OverlaySeries {
+    #[serde(with = "uuid::serde::simple")]
+    pub lap_id: Uuid,
+    pub channel: String,
+    pub values: Vec<f64>,
+}
+
+impl OverlayResult {
+    /// Keep at most `max_points` distances, the same ones for every series;
+    /// see [`lttb`].
+    pub fn decimate(&mut self, max_points: usize) {
+        let ys: Vec<&[f64]> = self.series.iter().map(|s| s.values.as_slice()).collect();
+        let keep = lttb(&self.distance, &ys, max_points);
+        self.distance = select(&self.distance, &keep);
+        for s in &mut self.series {
+            s.values = select(&s.values, &keep);
+        }
+    }
+
+    /// Row-per-distance JSON (`{distance, <channel>_<lap id>: ..}`) for charting libraries.
+    pub fn to_rows(&self) -> Value {
+        let keys: Vec<String> = self
+            .series
+            .iter()
+            .map(|s| format!("{}_{}", s.channel, s.lap_id))
+            .collect();
+        let rows = self
+            .distance
+            .par_iter()
+            .enumerate()
+            .map(|(i, d)| {
+                let mut row = serde_json::Map::new();
+                row.insert("distance".into(), json!(d));
+                for (key, s) in keys.iter().zip(&self.series) {
+                    row.insert(key.clone(), json!(s.values[i]));
+                }
+                Value::Object(row)
+            })
+            .collect();
+        Value::Array(rows)
+    }
+}
+
+/// Time delta of one or more laps against a reference, by distance.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct DeltaSeries {
+    #[serde(with = "uuid::serde::simple")]
+    pub reference_id: Uuid,
+    pub distance: Vec<f64>,
+    pub delta_ms: Vec<f64>,
+}
+
+impl DeltaSeries {
+    /// Keep at most `max_points` distances; see [`lttb`].
+    pub fn decimate(&mut self, max_points: usize) {
+        let keep = lttb(&self.distance, &[&self.delta_ms], max_points);
+        self.distance = select(&self.distance, &keep);
+        self.delta_ms = select(&self.delta_ms, &keep);
+    }
+
+    /// Row-per-distance JSON (`{distance, delta_ms}`).
+    pub fn to_rows(&self) -> Value {
+        Value::Array(
+            self.distance
+                .iter()
+                .zip(&self.delta_ms)
+                .map(|(d, t)| json!({ "distance": d, "delta_ms": t }))
+                .collect(),
+        )
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct CornerMetrics {
+    #[serde(with = "uuid::serde::simple")]
+    pub lap_id: Uuid,
+    pub corners: Vec<Corner>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct SessionSummary {
+    /// Laps the statistics count.
+    pub lap_count: usize,
+    pub best_ms: u64,
+    pub worst_ms: u64,
+    pub avg_ms: f64,
+    /// Std-dev of sector times, in seconds.
+    pub consistency: f64,
+}
@@ -0,0 +1,226 @@
+//! Session overview: lap time statistics overall and per stint, and where the
+//! car or track changed.
+//!
+//! A stint is a run of laps driven back to back. A new one starts when lap
+//! numbers don't follow on, when the clock jumps (a pause, a pit stop or a
+//! restarted game session) or when the car changes. Sources don't report fuel
+//! or weather, so neither is part of the statistics.
+//!
+//! Out-laps and in-laps are left out of the lap time statistics unless
+//! [`StatsOptions`] asks for them; they still count towards stints.
+
+use model::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use uuid::Uuid;
+
+/// Largest time between the end of one lap and the start of the next that
+/// still counts as the same stint.
+pub const STINT_GAP_MS: f64 = 60_000.0;
+
+/// Which laps lap time statistics count.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct StatsOptions {
+    /// Count out-laps and in-laps too.
+    pub include_out_in_laps: bool,
+}
+
+impl StatsOptions {
+    /// Whether `lap`'s time goes into the statistics.
+    pub fn counts(&self, lap: &Lap) -> bool {
+        self.include_out_in_laps || lap.meta.is_flying()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct LapTimeStats {
+    pub lap_count: usize,
+    pub best_ms: Option<u64>,
+    pub avg_ms: Option<f64>,
+    /// Standard deviation of the lap times.
+    pub std_ms: Option<f64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct StintStats {
+    /// 1-based.
+    pub index: u32,
+    pub first_lap: u32,
+    pub last_lap: u32,
+    pub car: String,
+    #[serde(flatten)]
+    pub times: LapTimeStats,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ConditionChange {
+    /// First lap driven with the new value.
+    #[serde(with = "uuid::serde::simple")]
+    pub lap_id: Uuid,
+    pub lap_number: u32,
+    /// `car` or `track`.
+    pub field: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct SessionStats {
+    #[serde(flatten)]
+    pub times: LapTimeStats,
+    pub stints: Vec<StintStats>,
+    pub condition_changes: Vec<ConditionChange>,
+}
+
+pub fn lap_time_stats(laps: &[&Lap]) -> LapTimeStats {
+    if laps.is_empty() {
+        return LapTimeStats::default();
+    }
+    let n = laps.len() as f64;
+    let avg = laps.iter().map(|l| l.total_time_ms as f64).sum::<f64>() / n;
+    let var = laps.iter().map(|l| (l.total_time_ms as f64 - avg).powi(2)).sum::<f64>() / n;
+    LapTimeStats {
+        lap_count: laps.len(),
+        best_ms: laps.iter().map(|l| l.total_time_ms).min(),
+        avg_ms: Some(avg),
+        std_ms: Some(var.sqrt()),
+    }
+}
+
+/// Whether `next` was driven straight after `prev`.
+fn continues(prev: &Lap, next: &Lap) -> bool {
+    if next.meta.lap_number != prev.meta.lap_number + 1 || next.meta.car != prev.meta.car {
+        return false;
+    }
+    match (prev.points.last(), next.points.first()) {
+        (Some(a), Some(b)) => (0.0..=STINT_GAP_MS).contains(&(b.t_ms - a.t_ms)),
+        _ => true,
+    }
+}
+
+/// Statistics of `laps`, which must be in the order they were driven.
+pub fn session_stats<L: Borrow<Lap>>(laps: &[L]) -> SessionStats {
+    session_stats_with(laps, &StatsOptions::default())
+}
+
+/// Like [`session_stats`], counting the laps `opts` asks for.
+pub fn session_stats_with<L: Borrow<Lap>>(laps: &[L], opts: &StatsOptions) -> SessionStats {
+    let all: Vec<&Lap> = laps.iter().map(Borrow::borrow).collect();
+    let mut stints: Vec<Vec<&Lap>> = Vec::new();
+    let mut changes = Vec::new();
+    for (i, &lap) in all.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|p| all[p]);
+        match stints.last_mut() {
+            Some(stint) if prev.is_some_and(|p| continues(p, lap)) => stint.push(lap),
+            _ => stints.push(vec![lap]),
+        }
+        let Some(prev) = prev else { continue };
+        for (field, from, to) in [("car", &prev.meta.car, &lap.meta.car), ("track", &prev.meta.track, &lap.meta.track)] {
+            if from != to {
+                changes.push(ConditionChange {
+                    lap_id: lap.id,
+                    lap_number: lap.meta.lap_number,
+                    field: field.into(),
+                    from: from.clone(),
+                    to: to.clone(),
+                });
+            }
+        }
+    }
+
+    let timed = |laps: &[&Lap]| -> LapTimeStats {
+        let counted: Vec<&Lap> = laps.iter().copied().filter(|l| opts.counts(l)).collect();
+        lap_time_stats(&counted)
+    };
+    SessionStats {
+        times: timed(&all),
+        stints: stints
+            .iter()
+            .enumerate()
+            .map(|(i, s)| StintStats {
+                index: i as u32 + 1,
+                first_lap: s[0].meta.lap_number,
+                last_lap: s[s.len() - 1].meta.lap_number,
+                car: s[0].meta.car.clone(),
+                times: timed(s),
+            })
+            .collect(),
+        condition_changes: changes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, point};
+
+    /// Lap `n` of `car`, `total_ms` long, starting `start_s` into the session.
+    fn driven(n: u32, car: &str, start_s: f64, total_ms: u64) -> Lap {
+        let t0 = start_s * 1000.0;
+        let mut l = lap(total_ms, vec![point(t0, 0.0), point(t0 + total_ms as f64, 1000.0)]);
+        l.meta.lap_number = n;
+        l.meta.car = car.into();
+        l.meta.track = "spa".into();
+        l
+    }
+
+    #[test]
+    fn lap_times_over_the_laps() {
+        let laps = [driven(1, "a", 0.0, 100_000), driven(2, "a", 100.0, 110_000), driven(3, "a", 210.0, 90_000)];
+        let s = lap_time_stats(&laps.iter().collect::<Vec<_>>());
+        assert_eq!((s.lap_count, s.best_ms, s.avg_ms), (3, Some(90_000), Some(100_000.0)));
+        assert!((s.std_ms.unwrap() - (200.0e6_f64 / 3.0).sqrt()).abs() < 1e-6);
+        assert_eq!(lap_time_stats(&[]), LapTimeStats::default());
+    }
+
+    #[test]
+    fn stints_break_on_gaps_lap_numbers_and_cars() {
+        let laps = [
+            driven(1, "a", 0.0, 100_000),
+            driven(2, "a", 100.0, 100_000),
+            // back from the pits two minutes later
+            driven(3, "a", 320.0, 100_000),
+            // lap numbers start over
+            driven(1, "a", 420.0, 100_000),
+            driven(2, "b", 520.0, 95_000),
+            driven(3, "b", 615.0, 96_000),
+        ];
+        let s = session_stats(&laps);
+        let stints: Vec<_> = s.stints.iter().map(|s| (s.index, s.first_lap, s.last_lap, s.car.as_str(), s.times.lap_count)).collect();
+        assert_eq!(stints, [(1, 1, 2, "a", 2), (2, 3, 3, "a", 1), (3, 1, 1, "a", 1), (4, 2, 3, "b", 2)]);
+        assert_eq!(s.times.lap_count, 6);
+        assert_eq!(s.times.best_ms, Some(95_000));
+
+        assert_eq!(s.condition_changes.len(), 1);
+        let c = &s.condition_changes[0];
+        assert_eq!((c.lap_id, c.lap_number, c.field.as_str(), c.from.as_str(), c.to.as_str()), (laps[4].id, 2, "car", "a", "b"));
+    }
+
+    #[test]
+    fn out_and_in_laps_count_towards_stints_not_times() {
+        let mut laps = vec![driven(1, "a", 0.0, 130_000), driven(2, "a", 130.0, 100_000), driven(3, "a", 230.0, 120_000)];
+        laps[0].meta.out_lap = true;
+        laps[2].meta.in_lap = true;
+
+        let s = session_stats(&laps);
+        assert_eq!(s.stints.len(), 1);
+        assert_eq!((s.stints[0].first_lap, s.stints[0].last_lap), (1, 3));
+        assert_eq!((s.times.lap_count, s.times.avg_ms), (1, Some(100_000.0)));
+
+        let all = session_stats_with(&laps, &StatsOptions { include_out_in_laps: true });
+        assert_eq!((all.times.lap_count, all.times.best_ms), (3, Some(100_000)));
+        assert_eq!(all.stints[0].times, all.times);
+    }
+
+    #[test]
+    fn track_changes_are_reported() {
+        let mut laps = vec![driven(1, "a", 0.0, 100_000), driven(2, "a", 100.0, 100_000)];
+        laps[1].meta.track = "monza".into();
+        let s = session_stats(&laps);
+        assert_eq!(s.stints.len(), 1);
+        let changes: Vec<_> = s.condition_changes.iter().map(|c| (c.field.as_str(), c.from.as_str(), c.to.as_str())).collect();
+        assert_eq!(changes, [("track", "spa", "monza")]);
+        assert_eq!(session_stats::<Lap>(&[]), SessionStats::default());
+    }
+}
@@ -0,0 +1,387 @@
+//! Running results for one car, updated a point at a time as samples arrive:
+//! delta to the best lap, sector splits of the lap in progress and lap time
+//! statistics.
+//!
+//! A point costs a lookup into the reference lap's index and a check for a
+//! sector boundary, so a live view stays responsive with a full grid of cars.
+//! Whole-lap work (the reference's sectors) only happens when a new best
+//! lap is set.
+
+use model::*;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+use crate::build_track_map;
+use crate::live::{LiveDelta, LiveDeltaState};
+use crate::stats::LapTimeStats;
+
+#[derive(Clone, Debug)]
+pub struct StreamOptions {
+    /// Completed laps the recent statistics cover.
+    pub window: usize,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self { window: 5 }
+    }
+}
+
+/// One sector of a lap against the same sector of the reference lap.
+#[derive(Clone, Serialize, Debug, Default, PartialEq)]
+pub struct SectorSplit {
+    /// 1-based.
+    pub index: u32,
+    pub start_m: f64,
+    pub end_m: f64,
+    /// `None` until the car leaves the sector.
+    pub time_ms: Option<f64>,
+    /// `None` without a reference lap.
+    pub reference_ms: Option<f64>,
+    /// `time_ms` minus `reference_ms` (negative = faster).
+    pub delta_ms: Option<f64>,
+}
+
+/// Everything `LapStream` keeps besides the delta.
+#[derive(Clone, Serialize, Debug, Default, PartialEq)]
+pub struct StreamState {
+    /// Sector the car is in, 1-based; `None` without sectors or past the
+    /// last one.
+    pub sector: Option<u32>,
+    /// Sectors of the lap in progress.
+    pub splits: Vec<SectorSplit>,
+    /// Sectors of the last completed lap.
+    pub last_splits: Vec<SectorSplit>,
+    /// Highest speed of the lap in progress.
+    pub top_speed_kph: f64,
+    /// Every lap completed.
+    pub session: LapTimeStats,
+    /// The last `StreamOptions::window` laps completed.
+    pub recent: LapTimeStats,
+}
+
+/// Running delta, splits and statistics of one car. Sectors come from the
+/// reference lap's track map unless set with `set_sectors`.
+#[derive(Clone, Debug)]
+pub struct LapStream {
+    opts: StreamOptions,
+    delta: LiveDelta,
+    sectors: Vec<Sector>,
+    fixed_sectors: bool,
+    // reference lap's time in each sector; empty without one
+    reference_ms: Vec<f64>,
+    // distance and lap time of the last point
+    prev: Option<(f64, f64)>,
+    // lap time at the end of the last sector the car left
+    sector_start_ms: f64,
+    times: RunningStats,
+    recent: VecDeque<u64>,
+    state: StreamState,
+}
+
+impl Default for LapStream {
+    fn default() -> Self {
+        Self::new(StreamOptions::default())
+    }
+}
+
+impl LapStream {
+    pub fn new(opts: StreamOptions) -> Self {
+        Self {
+            opts,
+            delta: LiveDelta::new(),
+            sectors: Vec::new(),
+            fixed_sectors: false,
+            reference_ms: Vec::new(),
+            prev: None,
+            sector_start_ms: 0.0,
+            times: RunningStats::default(),
+            recent: VecDeque::new(),
+            state: StreamState::default(),
+        }
+    }
+
+    /// Split laps on `sectors` (e.g. a saved track map's) instead of the
+    /// reference lap's, from the next lap on.
+    pub fn set_sectors(&mut self, sectors: Vec<Sector>) {
+        self.sectors = sectors;
+        self.fixed_sectors = true;
+        self.reference_ms = match self.delta.state().best_ms {
+            Some(total) => reference_times(&self.sectors, total, |d| self.delta.reference().time_at_distance(d)),
+            None => Vec::new(),
+        };
+    }
+
+    /// Start timing a new lap.
+    pub fn begin_lap(&mut self, lap_number: u32) {
+        self.delta.begin_lap(lap_number);
+        self.prev = None;
+        self.sector_start_ms = 0.0;
+        self.state.top_speed_kph = 0.0;
+        self.state.splits = self
+            .sectors
+            .iter()
+            .enumerate()
+            .map(|(i, s)| SectorSplit {
+                index: i as u32 + 1,
+                start_m: s.start_m,
+                end_m: s.end_m,
+                reference_ms: self.reference_ms.get(i).copied(),
+                ..Default::default()
+            })
+            .collect();
+        self.state.sector = (!self.state.splits.is_empty()).then_some(1);
+    }
+
+    /// Feed the latest point of the running lap.
+    pub fn push(&mut self, p: &TelemetryPoint) -> &StreamState {
+        let (d, elapsed) = {
+            let s = self.delta.push(p);
+            (s.distance_m, s.elapsed_ms)
+        };
+        self.state.top_speed_kph = self.state.top_speed_kph.max(p.speed_kph);
+        if let Some((pd, pe)) = self.prev {
+            // only a boundary crossed between two points counts, so a lap
+            // starting past one doesn't close sectors it never drove
+            while let Some(end) = self.state.sector.and_then(|k| self.state.splits.get(k as usize - 1)).map(|s| s.end_m) {
+                if !(pd < end && d >= end) {
+                    break;
+                }
+                let f = if d > pd { (end - pd) / (d - pd) } else { 1.0 };
+                self.close_sector(pe + f * (elapsed - pe));
+            }
+        }
+        self.prev = Some((d, elapsed));
+        &self.state
+    }
+
+    /// A lap that counts has been completed: close its last sector, update
+    /// the statistics and take it as the reference if it's the best.
+    /// Out-laps and in-laps only close their sectors.
+    pub fn complete_lap(&mut self, lap: &Lap) {
+        if self.state.sector.is_some_and(|k| k as usize == self.state.splits.len()) {
+            self.close_sector(lap.total_time_ms as f64);
+        }
+        self.state.last_splits = std::mem::take(&mut self.state.splits);
+        self.state.sector = None;
+        if !lap.meta.is_flying() {
+            return;
+        }
+
+        self.times.add(lap.total_time_ms);
+        self.recent.push_back(lap.total_time_ms);
+        while self.recent.len() > self.opts.window {
+            self.recent.pop_front();
+        }
+        self.state.session = self.times.stats();
+        let mut recent = RunningStats::default();
+        self.recent.iter().for_each(|&t| recent.add(t));
+        self.state.recent = recent.stats();
+
+        if self.delta.offer_lap(lap) {
+            if !self.fixed_sectors {
+                self.sectors = build_track_map(lap).sectors;
+            }
+            self.reference_ms = reference_times(&self.sectors, lap.total_time_ms, |d| lap.time_at_distance(d));
+        }
+    }
+
+    pub fn delta(&self) -> &LiveDeltaState {
+        self.delta.state()
+    }
+
+    pub fn state(&self) -> &StreamState {
+        &self.state
+    }
+
+    fn close_sector(&mut self, at_ms: f64) {
+        let Some(k) = self.state.sector else { return };
+        let split = &mut self.state.splits[k as usize - 1];
+        let time = at_ms - self.sector_start_ms;
+        split.time_ms = Some(time);
+        split.delta_ms = split.reference_ms.map(|r| time - r);
+        self.sector_start_ms = at_ms;
+        self.state.sector = ((k as usize) < self.state.splits.len()).then_some(k + 1);
+    }
+
+}
+
+/// Reference sector times from the reference lap's time at each sector end.
+/// The last sector ends on the lap time, as the running lap's does.
+fn reference_times(sectors: &[Sector], total_ms: u64, time_at: impl Fn(f64) -> Option<f64>) -> Vec<f64> {
+    let ends = sectors
+        .iter()
+        .enumerate()
+        .map(|(i, s)| if i + 1 == sectors.len() { total_ms as f64 } else { time_at(s.end_m).unwrap_or(0.0) });
+    ends.scan(0.0, |start, end| Some(end - std::mem::replace(start, end))).collect()
+}
+
+/// Lap time count, best, mean and spread, updated one lap at a time.
+#[derive(Clone, Debug, Default)]
+struct RunningStats {
+    n: usize,
+    best: Option<u64>,
+    mean: f64,
+    // sum of squared differences from the mean (Welford)
+    m2: f64,
+}
+
+impl RunningStats {
+    fn add(&mut self, ms: u64) {
+        let x = ms as f64;
+        self.n += 1;
+        self.best = Some(self.best.map_or(ms, |b| b.min(ms)));
+        let d = x - self.mean;
+        self.mean += d / self.n as f64;
+        self.m2 += d * (x - self.mean);
+    }
+
+    fn stats(&self) -> LapTimeStats {
+        if self.n == 0 {
+            return LapTimeStats::default();
+        }
+        LapTimeStats {
+            lap_count: self.n,
+            best_ms: self.best,
+            avg_ms: Some(self.mean),
+            std_ms: Some((self.m2 / self.n as f64).sqrt()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, point};
+
+    /// `total_ms` over 1000 m at an even pace, a point every 100 m.
+    fn even_lap(total_ms: u64) -> Lap {
+        let pts = (0..=10).map(|i| point(total_ms as f64 * i as f64 / 10.0, i as f64 * 100.0)).collect();
+        lap(total_ms, pts)
+    }
+
+    fn thirds() -> Vec<Sector> {
+        vec![
+            Sector { start_m: 0.0, end_m: 400.0 },
+            Sector { start_m: 400.0, end_m: 700.0 },
+            Sector { start_m: 700.0, end_m: 1000.0 },
+        ]
+    }
+
+    /// Drive `l` through the stream as lap `n` and complete it.
+    fn drive(stream: &mut LapStream, n: u32, l: &Lap) {
+        stream.begin_lap(n);
+        l.points.iter().for_each(|p| {
+            stream.push(p);
+        });
+        stream.complete_lap(l);
+    }
+
+    fn reference_splits(stream: &LapStream) -> Vec<Option<f64>> {
+        let mut stream = stream.clone();
+        stream.begin_lap(0);
+        stream.state().splits.iter().map(|s| s.reference_ms).collect()
+    }
+
+    #[test]
+    fn splits_against_the_reference_sectors() {
+        let mut stream = LapStream::default();
+        stream.set_sectors(thirds());
+        drive(&mut stream, 1, &even_lap(100_000));
+
+        stream.begin_lap(2);
+        let l = even_lap(90_000);
+        for p in &l.points[..6] {
+            stream.push(p);
+        }
+        let s = stream.state();
+        assert_eq!(s.sector, Some(2));
+        assert_eq!(s.splits[0].time_ms, Some(36_000.0));
+        assert_eq!(s.splits[0].delta_ms, Some(-4_000.0));
+        assert_eq!(s.splits[1].time_ms, None);
+
+        l.points[6..].iter().for_each(|p| {
+            stream.push(p);
+        });
+        stream.complete_lap(&l);
+        let times: Vec<_> = stream.state().last_splits.iter().map(|s| (s.time_ms, s.delta_ms)).collect();
+        assert_eq!(
+            times,
+            [(Some(36_000.0), Some(-4_000.0)), (Some(27_000.0), Some(-3_000.0)), (Some(27_000.0), Some(-3_000.0))]
+        );
+        assert_eq!(stream.state().sector, None);
+        assert_eq!(stream.delta().best_ms, Some(90_000));
+    }
+
+    #[test]
+    fn last_reference_sector_ends_on_the_lap_time() {
+        // the reference's points stop short of the line, so only the lap
+        // time says when its last sector ended
+        let mut short = even_lap(100_000);
+        short.points.pop();
+
+        let mut before = LapStream::default();
+        before.set_sectors(thirds());
+        drive(&mut before, 1, &short);
+        let mut after = LapStream::default();
+        drive(&mut after, 1, &short);
+        after.set_sectors(thirds());
+
+        let want = vec![Some(40_000.0), Some(30_000.0), Some(30_000.0)];
+        assert_eq!(reference_splits(&before), want);
+        assert_eq!(reference_splits(&after), want);
+    }
+
+    #[test]
+    fn sectors_without_a_reference_have_no_reference_times() {
+        let mut stream = LapStream::default();
+        stream.set_sectors(thirds());
+        assert_eq!(reference_splits(&stream), [None, None, None]);
+    }
+
+    #[test]
+    fn session_and_recent_statistics_count_flying_laps() {
+        let mut stream = LapStream::new(StreamOptions { window: 2 });
+        stream.set_sectors(thirds());
+        let mut out = even_lap(150_000);
+        out.meta.out_lap = true;
+        drive(&mut stream, 1, &out);
+        assert_eq!(stream.state().session, LapTimeStats::default());
+        assert_eq!(stream.delta().best_ms, None);
+
+        for (n, ms) in [(2, 100_000), (3, 110_000), (4, 90_000)] {
+            drive(&mut stream, n, &even_lap(ms));
+        }
+        let s = stream.state();
+        assert_eq!((s.session.lap_count, s.session.best_ms, s.session.avg_ms), (3, Some(90_000), Some(100_000.0)));
+        assert_eq!((s.recent.lap_count, s.recent.best_ms, s.recent.avg_ms), (2, Some(90_000), Some(100_000.0)));
+        assert_eq!(s.recent.std_ms, Some(10_000.0));
+    }
+
+    #[test]
+    fn top_speed_resets_each_lap() {
+        let mut stream = LapStream::default();
+        stream.begin_lap(1);
+        stream.push(&TelemetryPoint { speed_kph: 250.0, ..point(0.0, 0.0) });
+        stream.push(&TelemetryPoint { speed_kph: 200.0, ..point(1_000.0, 50.0) });
+        assert_eq!(stream.state().top_speed_kph, 250.0);
+        stream.begin_lap(2);
+        assert_eq!(stream.state().top_speed_kph, 0.0);
+    }
+
+    #[test]
+    fn running_stats_match_a_direct_calculation() {
+        let times = [92_345, 91_870, 93_002, 91_999, 92_410, 95_120, 91_760];
+        let mut running = RunningStats::default();
+        times.iter().for_each(|&t| running.add(t));
+
+        let n = times.len() as f64;
+        let mean = times.iter().map(|&t| t as f64).sum::<f64>() / n;
+        let var = times.iter().map(|&t| (t as f64 - mean).powi(2)).sum::<f64>() / n;
+        let s = running.stats();
+        assert_eq!((s.lap_count, s.best_ms), (7, Some(91_760)));
+        assert!((s.avg_ms.unwrap() - mean).abs() < 1e-6);
+        assert!((s.std_ms.unwrap() - var.sqrt()).abs() < 1e-6);
+        assert_eq!(RunningStats::default().stats(), LapTimeStats::default());
+    }
+}
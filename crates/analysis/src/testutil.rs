@@ -0,0 +1,39 @@
+//! Laps shared by the tests.
+
+use model::*;
+use uuid::Uuid;
+
+/// A point at `t_ms`, `d` metres round and on the x axis at `d`; the rest zero.
+pub fn point(t_ms: f64, d: f64) -> TelemetryPoint {
+    TelemetryPoint {
+        t_ms,
+        lap_distance_m: d,
+        x: d,
+        y: 0.0,
+        speed_kph: 0.0,
+        throttle: 0.0,
+        brake: 0.0,
+        gear: 0,
+        rpm: 0.0,
+        yaw: 0.0,
+    }
+}
+
+pub fn lap(total_time_ms: u64, points: Vec<TelemetryPoint>) -> Lap {
+    Lap {
+        id: Uuid::new_v4(),
+        meta: LapMeta {
+            id: Uuid::new_v4(),
+            game: "test".into(),
+            car: String::new(),
+            track: String::new(),
+            lap_number: 1,
+            out_lap: false,
+            in_lap: false,
+        },
+        total_time_ms,
+        points,
+        markers: Vec::new(),
+        index: Default::default(),
+    }
+}
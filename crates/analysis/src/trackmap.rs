@@ -0,0 +1,229 @@
+//! Normalization of track maps so maps of the same circuit line up, and
+//! simplification of their outlines.
+
+use model::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{auto_sectors, bbox_of, curvature_series, peak_indices};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    /// Keep the axes the lap was recorded in: north up for laps in the
+    /// common east-north-up frame, the game's own axes for older ones.
+    #[default]
+    #[serde(alias = "north_up")]
+    AsRecorded,
+    /// Rotate so the longest straight runs left to right.
+    StraightHorizontal,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct TrackMapOptions {
+    /// Translate so the lap's first point (start/finish) is at (0, 0).
+    #[serde(default)]
+    pub origin_at_start: bool,
+    #[serde(default)]
+    pub orientation: Orientation,
+    /// Uniformly scale so the map fits a `width x height` box.
+    #[serde(default)]
+    pub fit_to: Option<(f64, f64)>,
+    /// How far (m) the outline may stray from the driven line;
+    /// [`SIMPLIFY_TOLERANCE_M`] when `None`, and 0 keeps every point.
+    #[serde(default)]
+    pub tolerance_m: Option<f64>,
+}
+
+/// Curvature (proxy) below which a point counts as part of a straight.
+const STRAIGHT_CURVATURE: f64 = 0.004;
+
+/// Tolerance (m) of `build_track_map`'s outline: well under a car's width,
+/// so the outline looks the same while straights shrink to a few vertices.
+pub const SIMPLIFY_TOLERANCE_M: f64 = 0.25;
+
+/// Tolerances (m) of the levels of detail in `TrackMap::lods`.
+pub const LOD_TOLERANCES_M: [f64; 3] = [1.0, 4.0, 16.0];
+
+/// Outline, corners and sectors of the track `lap` was driven on, laid out
+/// per `opts` with [`normalize_track_map`]. The outline is simplified to
+/// `opts.tolerance_m`, with coarser levels of detail alongside.
+pub fn build_track_map_with(lap: &Lap, opts: &TrackMapOptions) -> TrackMap {
+    let pl: Vec<Point2> = lap.points.iter().map(|p| Point2 { x: p.x, y: p.y }).collect();
+    let curv = curvature_series(&lap.points);
+    let corners = peak_indices(&curv, 12, 0.03)
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &idx)| lap.points.get(idx).map(|p| CornerLabel { index: (i + 1) as u32, x: p.x, y: p.y }))
+        .collect();
+    let sectors = auto_sectors(lap, &curv, 3);
+    let lods = LOD_TOLERANCES_M
+        .iter()
+        .map(|&tolerance_m| TrackLod { tolerance_m, polyline: simplify_polyline(&pl, tolerance_m) })
+        .collect();
+    let polyline = simplify_polyline(&pl, opts.tolerance_m.unwrap_or(SIMPLIFY_TOLERANCE_M));
+    let mut map = TrackMap { polyline, lods, corners, sectors, bbox: bbox_of(&pl) };
+    normalize_track_map(&mut map, lap, opts);
+    map
+}
+
+/// Translate, rotate and scale a map built from `lap` in place. The levels
+/// of detail keep their tolerances in the source's metres.
+pub fn normalize_track_map(map: &mut TrackMap, lap: &Lap, opts: &TrackMapOptions) {
+    let Some(start) = map.polyline.first().cloned() else {
+        return;
+    };
+
+    let (ox, oy) = if opts.origin_at_start { (start.x, start.y) } else { (0.0, 0.0) };
+    let angle = match opts.orientation {
+        Orientation::AsRecorded => 0.0,
+        Orientation::StraightHorizontal => main_straight_heading(lap).map(|h| -h).unwrap_or(0.0),
+    };
+    let (sin, cos) = angle.sin_cos();
+    let transform = |x: f64, y: f64| {
+        let (dx, dy) = (x - ox, y - oy);
+        (dx * cos - dy * sin, dx * sin + dy * cos)
+    };
+
+    for p in map.polyline.iter_mut().chain(map.lods.iter_mut().flat_map(|l| &mut l.polyline)) {
+        (p.x, p.y) = transform(p.x, p.y);
+    }
+    for c in &mut map.corners {
+        (c.x, c.y) = transform(c.x, c.y);
+    }
+
+    if let Some((w, h)) = opts.fit_to {
+        let b = bbox_of(&map.polyline);
+        let (bw, bh) = (b.maxx - b.minx, b.maxy - b.miny);
+        let s = match (bw > 0.0, bh > 0.0) {
+            (true, true) => (w / bw).min(h / bh),
+            (true, false) => w / bw,
+            (false, true) => h / bh,
+            (false, false) => 1.0,
+        };
+        for p in map.polyline.iter_mut().chain(map.lods.iter_mut().flat_map(|l| &mut l.polyline)) {
+            p.x *= s;
+            p.y *= s;
+        }
+        for c in &mut map.corners {
+            c.x *= s;
+            c.y *= s;
+        }
+    }
+
+    map.bbox = bbox_of(&map.polyline);
+}
+
+/// `points` with as few vertices as keep every dropped point within
+/// `tolerance` of the result (Douglas-Peucker). The first and last points
+/// are always kept.
+pub fn simplify_polyline(points: &[Point2], tolerance: f64) -> Vec<Point2> {
+    let n = points.len();
+    if n < 3 || tolerance.is_nan() || tolerance <= 0.0 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; n];
+    (keep[0], keep[n - 1]) = (true, true);
+    // spans left to split; a stack rather than recursion, as a long lap
+    // can nest deeper than the call stack allows
+    let mut spans = vec![(0, n - 1)];
+    while let Some((a, b)) = spans.pop() {
+        let mut far = None;
+        let mut far_d = tolerance;
+        for (i, p) in points.iter().enumerate().take(b).skip(a + 1) {
+            let d = segment_distance(p, &points[a], &points[b]);
+            if d > far_d {
+                (far, far_d) = (Some(i), d);
+            }
+        }
+        if let Some(i) = far {
+            keep[i] = true;
+            spans.push((a, i));
+            spans.push((i, b));
+        }
+    }
+    points.iter().zip(keep).filter(|(_, k)| *k).map(|(p, _)| p.clone()).collect()
+}
+
+/// Distance from `p` to the segment `a`-`b`.
+fn segment_distance(p: &Point2, a: &Point2, b: &Point2) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 > 0.0 { (((p.x - a.x) * dx + (p.y - a.y) * dy) / len2).clamp(0.0, 1.0) } else { 0.0 };
+    (p.x - (a.x + t * dx)).hypot(p.y - (a.y + t * dy))
+}
+
+/// Heading (radians) of the longest low-curvature stretch of the lap.
+pub fn main_straight_heading(lap: &Lap) -> Option<f64> {
+    let pts = &lap.points;
+    let curv = curvature_series(pts);
+
+    let mut best: Option<(usize, usize)> = None;
+    let mut best_len = 0.0;
+    let mut run_start: Option<usize> = None;
+    for i in 0..=curv.len() {
+        let straight = i < curv.len() && curv[i] < STRAIGHT_CURVATURE;
+        match (straight, run_start) {
+            (true, None) => run_start = Some(i),
+            (false, Some(a)) => {
+                let b = i - 1;
+                let len = (pts[b].x - pts[a].x).hypot(pts[b].y - pts[a].y);
+                if b > a && len > best_len {
+                    best_len = len;
+                    best = Some((a, b));
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let (a, b) = best?;
+    let (dx, dy) = (pts[b].x - pts[a].x, pts[b].y - pts[a].y);
+    if dx == 0.0 && dy == 0.0 {
+        return None;
+    }
+    Some(dy.atan2(dx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, point};
+
+    /// 300 m north from the origin, then 100 m east.
+    fn north_then_east() -> Lap {
+        let points = (0..=40)
+            .map(|i| {
+                let d = i as f64 * 10.0;
+                let mut p = point(d * 50.0, d);
+                (p.x, p.y) = if d <= 300.0 { (0.0, d) } else { (d - 300.0, 300.0) };
+                p
+            })
+            .collect();
+        lap(20_000, points)
+    }
+
+    #[test]
+    fn as_recorded_keeps_the_source_axes() {
+        let l = north_then_east();
+        let map = build_track_map_with(&l, &TrackMapOptions::default());
+        let raw: Vec<Point2> = l.points.iter().map(|p| Point2 { x: p.x, y: p.y }).collect();
+        assert_eq!(map.polyline, simplify_polyline(&raw, SIMPLIFY_TOLERANCE_M));
+        assert_eq!(map, crate::build_track_map(&l));
+        let o: Orientation = serde_json::from_str("\"north_up\"").unwrap();
+        assert_eq!(o, Orientation::AsRecorded);
+    }
+
+    #[test]
+    fn straight_horizontal_lays_the_straight_flat() {
+        let l = north_then_east();
+        let opts = TrackMapOptions {
+            origin_at_start: true,
+            orientation: Orientation::StraightHorizontal,
+            ..Default::default()
+        };
+        let map = build_track_map_with(&l, &opts);
+        let corner = map.polyline.iter().find(|p| (p.x.hypot(p.y) - 300.0).abs() < 1e-6).unwrap();
+        assert!(corner.y.abs() < 1e-6 && corner.x > 0.0, "{corner:?}");
+    }
+}
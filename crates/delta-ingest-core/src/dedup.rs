@@ -0,0 +1,97 @@
+//! Dropping frames a connector has already sent: games that resend a frame
+//! unchanged, or that are polled faster than they update, and UDP frames
+//! that arrive late, after a newer one. Laps built from repeated or
+//! backward timestamps can't be interpolated between them.
+
+use crate::TelemetrySample;
+
+/// Sim time more than this behind the last frame's is the game starting
+/// over (a restart, or a new session under the same id), not a stale frame
+/// (s).
+const RESTART_S: f64 = 1.0;
+
+/// The session and sim time of the last frame one source sent.
+#[derive(Debug, Default)]
+pub struct FrameDedup {
+    last: Option<(String, f64)>,
+}
+
+impl FrameDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `s` is a frame not sent yet: from another session than the
+    /// last one, or later in its sim time. Frame numbers aren't trusted, as
+    /// some games number a resent frame afresh. Remembered if so.
+    pub fn fresh(&mut self, s: &TelemetrySample) -> bool {
+        let stale = self.last.as_ref().is_some_and(|(uid, t)| {
+            *uid == s.session_uid && s.sim_time_s <= *t && *t - s.sim_time_s <= RESTART_S
+        });
+        if !stale {
+            self.last = Some((s.session_uid.clone(), s.sim_time_s));
+        }
+        !stale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Game, Phase};
+
+    fn frame(session: &str, frame: u64, t: f64) -> TelemetrySample {
+        TelemetrySample {
+            game: Game::LMU,
+            car_id: "player:0".into(),
+            session_uid: session.into(),
+            frame,
+            sim_time_s: t,
+            speed_mps: 0.0,
+            throttle: 0.0,
+            brake: 0.0,
+            gear: 0,
+            engine_rpm: 0.0,
+            world_pos_x: 0.0,
+            world_pos_y: 0.0,
+            world_pos_z: 0.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            lap_distance_m: 0.0,
+            current_lap: 0,
+            current_lap_time_s: 0.0,
+            last_lap_time_s: 0.0,
+            best_lap_time_s: 0.0,
+            fuel_l: 0.0,
+            fuel_capacity_l: 0.0,
+            rev_limiter: false,
+            phase: Phase::Driving,
+        }
+    }
+
+    fn fresh(frames: &[(&str, u64, f64)]) -> Vec<bool> {
+        let mut dedup = FrameDedup::new();
+        frames.iter().map(|&(uid, n, t)| dedup.fresh(&frame(uid, n, t))).collect()
+    }
+
+    #[test]
+    fn drops_frames_resent_under_any_number() {
+        let got = fresh(&[("a", 1, 10.0), ("a", 1, 10.0), ("a", 2, 10.0), ("a", 3, 10.02)]);
+        assert_eq!(got, [true, false, false, true]);
+    }
+
+    #[test]
+    fn drops_frames_that_arrive_late() {
+        let got = fresh(&[("a", 1, 10.0), ("a", 3, 10.04), ("a", 2, 10.02), ("a", 4, 10.06)]);
+        assert_eq!(got, [true, true, false, true]);
+    }
+
+    #[test]
+    fn a_new_session_or_a_restart_starts_over() {
+        // another session at the same time, then the game back to the
+        // start of one under the same id
+        let got = fresh(&[("a", 1, 10.0), ("b", 1, 10.0), ("b", 2, 0.5), ("b", 3, 0.52)]);
+        assert_eq!(got, [true, true, true, true]);
+    }
+}
@@ -0,0 +1,219 @@
+//! Ingest diagnostics for "no data showing" reports.
+//!
+//! Packet connectors keep [`IngestCounters`] of what arrives on the socket
+//! and how much of it parses into samples. [`SampleProbe`] watches the
+//! samples themselves for a while: which channels carry data and which
+//! values fail basic sanity checks. [`SampleProbe::report`] puts both into
+//! one [`Diagnosis`].
+
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::TelemetrySample;
+
+/// Running totals a connector updates for every packet it receives.
+#[derive(Debug, Default)]
+pub struct IngestCounters {
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    samples: AtomicU64,
+    malformed: AtomicU64,
+    parse_ns: AtomicU64,
+}
+
+#[derive(Clone, Copy, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct CounterSnapshot {
+    pub packets: u64,
+    pub bytes: u64,
+    /// Packets that parsed, into a sample or, for games that spread a
+    /// sample over several packets (F1), into part of one.
+    pub samples: u64,
+    /// Packets that couldn't be decoded at all.
+    pub malformed: u64,
+    pub parse_ns: u64,
+}
+
+impl IngestCounters {
+    /// Count a packet of `len` bytes that took `parse` to parse, and whether
+    /// it parsed. An F1 packet parses when it is read, though its frame's
+    /// sample is only sent once the next frame starts.
+    pub fn record(&self, len: usize, sample: bool, parse: Duration) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(len as u64, Ordering::Relaxed);
+        if sample {
+            self.samples.fetch_add(1, Ordering::Relaxed);
+        }
+        self.parse_ns.fetch_add(parse.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Count a recorded packet as undecodable: truncated, a bad header or
+    /// failing to decrypt.
+    pub fn record_malformed(&self) {
+        self.malformed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> CounterSnapshot {
+        CounterSnapshot {
+            packets: self.packets.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            samples: self.samples.load(Ordering::Relaxed),
+            malformed: self.malformed.load(Ordering::Relaxed),
+            parse_ns: self.parse_ns.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl CounterSnapshot {
+    /// What was counted between `earlier` and this snapshot.
+    pub fn since(&self, earlier: &CounterSnapshot) -> CounterSnapshot {
+        CounterSnapshot {
+            packets: self.packets.saturating_sub(earlier.packets),
+            bytes: self.bytes.saturating_sub(earlier.bytes),
+            samples: self.samples.saturating_sub(earlier.samples),
+            malformed: self.malformed.saturating_sub(earlier.malformed),
+            parse_ns: self.parse_ns.saturating_sub(earlier.parse_ns),
+        }
+    }
+}
+
+const CHANNELS: [&str; 9] =
+    ["speed", "throttle", "brake", "gear", "rpm", "position", "orientation", "lap_distance", "lap_number"];
+
+// fastest road cars top out well below this; anything above is garbage
+const MAX_SPEED_MPS: f32 = 150.0;
+
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct ChannelCoverage {
+    pub channel: String,
+    /// Share of samples (0..=1) with a non-zero value.
+    pub coverage: f64,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+pub struct ValidationFailure {
+    pub check: String,
+    /// Samples failing it.
+    pub count: u64,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct Diagnosis {
+    pub duration_s: f64,
+    /// Packet figures are `None` for connectors that don't count packets
+    /// (shared memory).
+    pub packets: Option<u64>,
+    pub packet_rate_hz: Option<f64>,
+    /// Share of packets (0..=1) that parsed.
+    pub parse_success: Option<f64>,
+    pub parse_errors: Option<u64>,
+    pub avg_parse_us: Option<f64>,
+    pub samples: u64,
+    pub sample_rate_hz: f64,
+    pub channels: Vec<ChannelCoverage>,
+    pub validation_failures: Vec<ValidationFailure>,
+}
+
+/// Watches the samples of one source.
+#[derive(Debug)]
+pub struct SampleProbe {
+    started: Instant,
+    samples: u64,
+    present: [u64; CHANNELS.len()],
+    failures: BTreeMap<&'static str, u64>,
+    last: Option<(u64, f64)>,
+}
+
+impl Default for SampleProbe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SampleProbe {
+    pub fn new() -> Self {
+        Self { started: Instant::now(), samples: 0, present: [0; CHANNELS.len()], failures: BTreeMap::new(), last: None }
+    }
+
+    pub fn observe(&mut self, s: &TelemetrySample) {
+        self.samples += 1;
+        let present = [
+            s.speed_mps != 0.0,
+            s.throttle != 0.0,
+            s.brake != 0.0,
+            s.gear != 0,
+            s.engine_rpm != 0.0,
+            s.world_pos_x != 0.0 || s.world_pos_y != 0.0 || s.world_pos_z != 0.0,
+            s.yaw != 0.0 || s.pitch != 0.0 || s.roll != 0.0,
+            s.lap_distance_m != 0.0,
+            s.current_lap != 0,
+        ];
+        for (n, p) in self.present.iter_mut().zip(present) {
+            *n += p as u64;
+        }
+
+        let floats = [
+            s.speed_mps,
+            s.throttle,
+            s.brake,
+            s.engine_rpm,
+            s.world_pos_x,
+            s.world_pos_y,
+            s.world_pos_z,
+            s.yaw,
+            s.pitch,
+            s.roll,
+            s.lap_distance_m,
+            s.current_lap_time_s,
+            s.last_lap_time_s,
+            s.best_lap_time_s,
+            s.fuel_l,
+            s.fuel_capacity_l,
+        ];
+        let checks = [
+            ("non-finite value", floats.iter().any(|v| !v.is_finite()) || !s.sim_time_s.is_finite()),
+            ("pedal outside 0..1", ![s.throttle, s.brake].iter().all(|p| (0.0..=1.0).contains(p))),
+            ("speed out of range", !(0.0..=MAX_SPEED_MPS).contains(&s.speed_mps)),
+            ("gear out of range", !(-1..=10).contains(&s.gear)),
+            ("negative rpm", s.engine_rpm < 0.0),
+            ("frame went backwards", self.last.is_some_and(|(f, _)| s.frame < f)),
+            ("time went backwards", self.last.is_some_and(|(_, t)| s.sim_time_s < t)),
+        ];
+        for (check, failed) in checks {
+            if failed {
+                *self.failures.entry(check).or_default() += 1;
+            }
+        }
+        self.last = Some((s.frame, s.sim_time_s));
+    }
+
+    /// Findings so far, with the packet `counts` the connector kept over the
+    /// same time, if it keeps any.
+    pub fn report(&self, counts: Option<CounterSnapshot>) -> Diagnosis {
+        let secs = self.started.elapsed().as_secs_f64().max(1e-3);
+        let share = |n: u64, of: u64| if of > 0 { n as f64 / of as f64 } else { 0.0 };
+        Diagnosis {
+            duration_s: secs,
+            packets: counts.map(|c| c.packets),
+            packet_rate_hz: counts.map(|c| c.packets as f64 / secs),
+            parse_success: counts.map(|c| share(c.samples, c.packets)),
+            parse_errors: counts.map(|c| c.malformed),
+            avg_parse_us: counts.filter(|c| c.packets > 0).map(|c| c.parse_ns as f64 / c.packets as f64 / 1000.0),
+            samples: self.samples,
+            sample_rate_hz: self.samples as f64 / secs,
+            channels: CHANNELS
+                .iter()
+                .zip(self.present)
+                .map(|(c, n)| ChannelCoverage { channel: c.to_string(), coverage: share(n, self.samples) })
+                .collect(),
+            validation_failures: self
+                .failures
+                .iter()
+                .map(|(c, n)| ValidationFailure { check: c.to_string(), count: *n })
+                .collect(),
+        }
+    }
+}
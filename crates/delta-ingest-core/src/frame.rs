@@ -0,0 +1,74 @@
+//! The world frame every connector reports in, whatever the game's own axes,
+//! so a circuit's map comes out the same way round from every game:
+//!
+//! - `world_pos_x` east, `world_pos_y` north, `world_pos_z` up (m),
+//!   right-handed. "North" is the game's own, not the real circuit's.
+//! - `yaw` the heading, radians clockwise from north (+Y) in 0..2π, as a
+//!   compass reads and as GPS imports store it.
+//!
+//! Pitch and roll are passed through as the game reports them.
+
+use std::f32::consts::{PI, TAU};
+
+use crate::{Game, TelemetrySample};
+
+/// A game axis, or its opposite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+    NegX,
+    NegY,
+    NegZ,
+}
+
+impl Axis {
+    fn pick(self, [x, y, z]: [f32; 3]) -> f32 {
+        match self {
+            Axis::X => x,
+            Axis::Y => y,
+            Axis::Z => z,
+            Axis::NegX => -x,
+            Axis::NegY => -y,
+            Axis::NegZ => -z,
+        }
+    }
+}
+
+/// Where a game's axes point in the common frame, and how its yaw turns
+/// into a heading: `yaw_offset + yaw_sign * yaw`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Axes {
+    pub east: Axis,
+    pub north: Axis,
+    pub up: Axis,
+    pub yaw_sign: f32,
+    pub yaw_offset: f32,
+}
+
+impl Game {
+    pub fn axes(self) -> Axes {
+        match self {
+            // right-handed, Y up, +Z towards the bottom of a top-down map;
+            // yaw turns +Z towards +X
+            Game::F1_2024 | Game::F1_2025 | Game::GT7 => {
+                Axes { east: Axis::X, north: Axis::NegZ, up: Axis::Y, yaw_sign: -1.0, yaw_offset: PI }
+            }
+            // rF2's left-handed frame: Y up, a car facing -Z has +X on its
+            // left; yaw is the heading already
+            Game::LMU => Axes { east: Axis::NegX, north: Axis::NegZ, up: Axis::Y, yaw_sign: 1.0, yaw_offset: 0.0 },
+        }
+    }
+}
+
+/// Move `s`'s position and heading from its game's axes into the common
+/// frame. Connectors call it once on each sample they make.
+pub fn to_common(s: &mut TelemetrySample) {
+    let a = s.game.axes();
+    let native = [s.world_pos_x, s.world_pos_y, s.world_pos_z];
+    s.world_pos_x = a.east.pick(native);
+    s.world_pos_y = a.north.pick(native);
+    s.world_pos_z = a.up.pick(native);
+    s.yaw = (a.yaw_offset + a.yaw_sign * s.yaw).rem_euclid(TAU);
+}
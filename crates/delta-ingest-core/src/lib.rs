@@ -2,6 +2,13 @@
 
 use serde::{Deserialize, Serialize};
 use async_trait::async_trait;
+use std::sync::Arc;
+
+pub mod dedup;
+pub mod diag;
+pub mod frame;
+pub mod port;
+pub mod record;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Game {
@@ -26,7 +33,8 @@ pub struct TelemetrySample {
     pub gear: i8,        // -1..8 etc.
     pub engine_rpm: f32,
 
-    // world pose (right-handed, meters)
+    // world pose in the common frame (see `frame`): x east, y north, z up
+    // (meters), yaw clockwise from north
     pub world_pos_x: f32,
     pub world_pos_y: f32,
     pub world_pos_z: f32,
@@ -39,6 +47,37 @@ pub struct TelemetrySample {
     pub current_lap: u32,
     pub current_lap_time_s: f32,
     pub last_lap_time_s: f32,
+
+    // car state; 0 where the game doesn't send it
+    #[serde(default)]
+    pub best_lap_time_s: f32,
+    #[serde(default)]
+    pub fuel_l: f32,
+    #[serde(default)]
+    pub fuel_capacity_l: f32,
+    #[serde(default)]
+    pub rev_limiter: bool,
+
+    // what the game says the car is doing; `Driving` where it doesn't say
+    #[serde(default)]
+    pub phase: Phase,
+}
+
+/// What the car is doing, as far as the game says. Laps are only built
+/// while it's `Driving`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    #[default]
+    Driving,
+    /// The game is paused; the lap carries on after.
+    Paused,
+    /// In the game's menus or watching a replay.
+    Menu,
+    /// In the garage or pit box, before driving out.
+    Garage,
+    /// On the formation lap before a race.
+    Formation,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,6 +104,17 @@ pub type TelemetryRx = crossbeam_channel::Receiver<TelemetrySample>;
 #[async_trait]
 pub trait TelemetrySource: Send + Sync {
     async fn run(&self, tx: TelemetryTx) -> Result<(), IngestError>;
+
+    /// Packet counters, for connectors that receive packets.
+    fn counters(&self) -> Option<Arc<diag::IngestCounters>> {
+        None
+    }
+
+    /// Follow car `index` of the session instead of the player's (`None`),
+    /// for connectors that see every car; false when this one doesn't.
+    fn follow_car(&self, _index: Option<u8>) -> bool {
+        false
+    }
 }
 
 /// Create an unbounded telemetry channel.
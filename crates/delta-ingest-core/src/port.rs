@@ -0,0 +1,211 @@
+//! Why a UDP connector can't bind its port, in terms the user can act on.
+//!
+//! Games send telemetry to one fixed port (20777 for F1, 33740 for GT7), so
+//! the usual failure is another program already receiving there: SimHub, a
+//! dashboard app or a second copy of Delta. [`check_udp`] tries the bind up
+//! front and, when it fails, looks up which process owns the port (from
+//! `/proc` on Linux, `lsof` on macOS, `netstat` on Windows) and suggests how
+//! to share the telemetry instead of fighting over the port.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// Another program is bound to the port.
+    InUse,
+    /// Binding needs privileges the app doesn't have.
+    PermissionDenied,
+    /// The address isn't one of this machine's.
+    AddressUnavailable,
+    Other,
+}
+
+/// The process holding a port.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct PortOwner {
+    pub pid: u32,
+    pub name: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("{message}")]
+pub struct PortConflict {
+    pub kind: ConflictKind,
+    pub bind_addr: String,
+    pub port: u16,
+    /// When it could be found out.
+    pub owner: Option<PortOwner>,
+    pub message: String,
+    pub suggestion: String,
+}
+
+/// Try binding `bind_addr` the way a connector would and explain a failure.
+/// A port held by this very process (a connector being restarted) is fine.
+pub fn check_udp(bind_addr: &str) -> Result<(), PortConflict> {
+    match UdpSocket::bind(bind_addr) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let conflict = diagnose(bind_addr, &e);
+            if conflict.owner.as_ref().is_some_and(|o| o.pid == std::process::id()) {
+                return Ok(());
+            }
+            Err(conflict)
+        }
+    }
+}
+
+/// Explain the bind error `err` for `bind_addr`.
+pub fn diagnose(bind_addr: &str, err: &io::Error) -> PortConflict {
+    let addr: Option<SocketAddr> = bind_addr.to_socket_addrs().ok().and_then(|mut a| a.next());
+    let port = addr.map(|a| a.port()).unwrap_or(0);
+    let alt = port.checked_add(1).unwrap_or(port - 1);
+    let (kind, owner, message, suggestion) = match err.kind() {
+        io::ErrorKind::AddrInUse => {
+            let owner = udp_port_owner(port);
+            let (message, suggestion) = match &owner {
+                Some(o) if is_simhub(&o.name) => (
+                    format!("UDP port {port} is in use by SimHub"),
+                    format!(
+                        "Let SimHub pass the telemetry on: in SimHub's settings for this game, turn on UDP \
+                         forwarding to 127.0.0.1:{alt}, then set Delta's port to {alt}."
+                    ),
+                ),
+                Some(o) if is_self(&o.name) => (
+                    format!("UDP port {port} is in use by another Delta window (pid {})", o.pid),
+                    "Stop telemetry in the other window or close it; only one copy of Delta can receive \
+                     on a port."
+                        .into(),
+                ),
+                Some(o) => (
+                    format!("UDP port {port} is in use by {} (pid {})", o.name, o.pid),
+                    forward_hint(port, alt),
+                ),
+                None => (format!("UDP port {port} is in use by another program"), forward_hint(port, alt)),
+            };
+            (ConflictKind::InUse, owner, message, suggestion)
+        }
+        io::ErrorKind::PermissionDenied => (
+            ConflictKind::PermissionDenied,
+            None,
+            format!("not allowed to bind UDP port {port}"),
+            "Ports below 1024 need administrator rights; pick a port above 1024 in both the game and Delta."
+                .into(),
+        ),
+        io::ErrorKind::AddrNotAvailable => (
+            ConflictKind::AddressUnavailable,
+            None,
+            format!("{bind_addr} is not an address of this computer"),
+            format!("Bind to 0.0.0.0:{port} to receive on every network interface."),
+        ),
+        _ => (ConflictKind::Other, None, format!("binding {bind_addr} failed: {err}"), String::new()),
+    };
+    PortConflict { kind, bind_addr: bind_addr.into(), port, owner, message, suggestion }
+}
+
+fn forward_hint(port: u16, alt: u16) -> String {
+    format!(
+        "Close the other program, or share the telemetry: set the game to send to port {alt} and Delta to \
+         listen on {alt}, or turn on the other program's UDP forwarding to 127.0.0.1:{alt}. Games with \
+         several UDP outputs can also send to both {port} and {alt}."
+    )
+}
+
+fn is_simhub(name: &str) -> bool {
+    name.to_lowercase().contains("simhub")
+}
+
+fn is_self(name: &str) -> bool {
+    let me = std::env::current_exe().ok().and_then(|p| p.file_stem().map(|s| s.to_string_lossy().to_lowercase()));
+    let name = name.to_lowercase();
+    let name = name.strip_suffix(".exe").unwrap_or(&name);
+    me.is_some_and(|me| me == name)
+}
+
+/// The process bound to UDP `port`, when the platform lets us find out.
+pub fn udp_port_owner(port: u16) -> Option<PortOwner> {
+    owner_impl(port)
+}
+
+#[cfg(target_os = "linux")]
+fn owner_impl(port: u16) -> Option<PortOwner> {
+    use std::fs;
+    // socket inodes bound to the port, from the kernel's socket tables
+    let inodes: Vec<String> = ["/proc/net/udp", "/proc/net/udp6"]
+        .iter()
+        .filter_map(|t| fs::read_to_string(t).ok())
+        .flat_map(|text| {
+            text.lines()
+                .skip(1)
+                .filter_map(|line| {
+                    let cols: Vec<&str> = line.split_whitespace().collect();
+                    let local_port = cols.get(1)?.rsplit_once(':')?.1;
+                    if u16::from_str_radix(local_port, 16).ok()? != port {
+                        return None;
+                    }
+                    cols.get(9).map(|s| s.to_string())
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    if inodes.is_empty() {
+        return None;
+    }
+    let targets: Vec<String> = inodes.iter().map(|i| format!("socket:[{i}]")).collect();
+    for entry in fs::read_dir("/proc").ok()?.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse::<u32>().ok()) else { continue };
+        // other users' processes can't be read; skip them
+        let Ok(fds) = fs::read_dir(entry.path().join("fd")) else { continue };
+        let owns = fds.flatten().any(|fd| {
+            fs::read_link(fd.path()).is_ok_and(|l| targets.iter().any(|t| l.as_os_str() == t.as_str()))
+        });
+        if owns {
+            let name = fs::read_to_string(entry.path().join("comm")).unwrap_or_default().trim().to_string();
+            return Some(PortOwner { pid, name });
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "macos")]
+fn owner_impl(port: u16) -> Option<PortOwner> {
+    // -F pc prints fields one per line: p<pid>, c<command>
+    let out = std::process::Command::new("lsof").args(["-nP", &format!("-iUDP:{port}"), "-F", "pc"]).output().ok()?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    let pid = text.lines().find_map(|l| l.strip_prefix('p'))?.parse().ok()?;
+    let name = text.lines().find_map(|l| l.strip_prefix('c')).unwrap_or_default().to_string();
+    Some(PortOwner { pid, name })
+}
+
+#[cfg(windows)]
+fn owner_impl(port: u16) -> Option<PortOwner> {
+    use std::process::Command;
+    // "  UDP    0.0.0.0:20777    *:*    1234"
+    let out = Command::new("netstat").args(["-ano", "-p", "UDP"]).output().ok()?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    let pid: u32 = text.lines().find_map(|line| {
+        let cols: Vec<&str> = line.split_whitespace().collect();
+        let local_port = cols.get(1)?.rsplit_once(':')?.1;
+        if local_port.parse::<u16>().ok()? != port {
+            return None;
+        }
+        cols.last()?.parse().ok()
+    })?;
+    // "\"SimHubWPF.exe\",\"1234\",..."
+    let out = Command::new("tasklist").args(["/FI", &format!("PID eq {pid}"), "/FO", "CSV", "/NH"]).output().ok()?;
+    let name = String::from_utf8_lossy(&out.stdout)
+        .split(',')
+        .next()
+        .map(|s| s.trim().trim_matches('"').to_string())
+        .unwrap_or_default();
+    Some(PortOwner { pid, name })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn owner_impl(_port: u16) -> Option<PortOwner> {
+    None
+}
@@ -0,0 +1,149 @@
+//! Raw sample log: every [`TelemetrySample`] a connector sends, appended to
+//! an NDJSON or CSV file as it arrives, without any lap building.
+//!
+//! [`SampleRing`] keeps the same samples in memory for a bounded time
+//! instead, so a stint can be written out after the fact.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::TelemetrySample;
+
+/// Samples buffered between flushes; about 4 s at 60 Hz.
+const FLUSH_EVERY: u64 = 256;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordFormat {
+    Ndjson,
+    Csv,
+}
+
+enum Sink {
+    Ndjson(BufWriter<File>),
+    Csv(Box<csv::Writer<File>>),
+}
+
+/// Appends samples to a file. Re-opening an existing log continues it; a CSV
+/// header is only written to a new or empty file.
+pub struct SampleRecorder {
+    path: PathBuf,
+    sink: Sink,
+    written: u64,
+}
+
+impl SampleRecorder {
+    pub fn open(path: &Path, format: RecordFormat) -> Result<Self> {
+        let f = OpenOptions::new().create(true).append(true).open(path)?;
+        let fresh = f.metadata()?.len() == 0;
+        let sink = match format {
+            RecordFormat::Ndjson => Sink::Ndjson(BufWriter::new(f)),
+            RecordFormat::Csv => Sink::Csv(Box::new(csv::WriterBuilder::new().has_headers(fresh).from_writer(f))),
+        };
+        Ok(Self { path: path.to_path_buf(), sink, written: 0 })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Samples written since the recorder was opened.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn write(&mut self, s: &TelemetrySample) -> Result<()> {
+        match &mut self.sink {
+            Sink::Ndjson(w) => {
+                serde_json::to_writer(&mut *w, s)?;
+                w.write_all(b"\n")?;
+            }
+            Sink::Csv(w) => w.serialize(s)?,
+        }
+        self.written += 1;
+        if self.written.is_multiple_of(FLUSH_EVERY) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        match &mut self.sink {
+            Sink::Ndjson(w) => w.flush()?,
+            Sink::Csv(w) => w.flush()?,
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SampleRecorder {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// The most recent samples, at most `window` old and `max_samples` many;
+/// older ones are dropped as new ones arrive.
+pub struct SampleRing {
+    window: Duration,
+    max_samples: usize,
+    buf: VecDeque<(Instant, TelemetrySample)>,
+}
+
+impl SampleRing {
+    pub fn new(window: Duration, max_samples: usize) -> Self {
+        Self { window, max_samples, buf: VecDeque::new() }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    pub fn push(&mut self, s: &TelemetrySample) {
+        let now = Instant::now();
+        self.buf.push_back((now, s.clone()));
+        while self.buf.len() > self.max_samples
+            || self.buf.front().is_some_and(|(t, _)| now.duration_since(*t) > self.window)
+        {
+            self.buf.pop_front();
+        }
+    }
+
+    /// Samples received in the last `span`, oldest first.
+    pub fn last(&self, span: Duration) -> impl Iterator<Item = &TelemetrySample> {
+        let now = Instant::now();
+        let start = self.buf.partition_point(|(t, _)| now.duration_since(*t) > span);
+        self.buf.range(start..).map(|(_, s)| s)
+    }
+
+    /// Write the samples of the last `span` to a new log at `path`,
+    /// replacing any file there. Returns how many were written.
+    pub fn save_last(&self, span: Duration, path: &Path, format: RecordFormat) -> Result<u64> {
+        File::create(path)?;
+        let mut rec = SampleRecorder::open(path, format)?;
+        for s in self.last(span) {
+            rec.write(s)?;
+        }
+        rec.flush()?;
+        Ok(rec.written())
+    }
+}
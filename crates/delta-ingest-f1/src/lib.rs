@@ -1,8 +1,14 @@
-use anyhow::Context;
-use byteorder::{LittleEndian, ReadBytesExt};
 use delta_ingest_core::{*, Game as GameId};
-use std::io::Cursor;
+use delta_ingest_core::diag::IngestCounters;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
+use tokio::time;
+use zerocopy::little_endian::{F32, I16, U16, U32, U64};
+use zerocopy::{FromBytes, Immutable, KnownLayout, Unaligned};
+
+pub mod pcap;
 
 #[derive(Clone, Debug)]
 pub struct F1Config {
@@ -10,20 +16,36 @@ pub struct F1Config {
     pub bind_addr: String,
     /// 2024 or 2025 (currently advisory; packets are accepted cross-year)
     pub expected_format: u16,
+    /// Car to follow, by its index in the game's per-car arrays, e.g. the
+    /// one a spectator is watching; the player's when `None`.
+    pub car_index: Option<u8>,
 }
 
 impl Default for F1Config {
     fn default() -> Self {
-        Self { bind_addr: "0.0.0.0:20777".into(), expected_format: 2025 }
+        Self { bind_addr: "0.0.0.0:20777".into(), expected_format: 2025, car_index: None }
     }
 }
 
+// `F1Source::car` when following the player's car
+const PLAYER_CAR: u16 = u16::MAX;
+
+// with no packet for this long the game has stopped sending, so the frame
+// held back is sent as it is
+const STREAM_IDLE: Duration = Duration::from_millis(500);
+
 pub struct F1Source {
     cfg: F1Config,
+    counters: Arc<IngestCounters>,
+    // car followed, changed while running by `follow_car`
+    car: AtomicU16,
 }
 
 impl F1Source {
-    pub fn new(cfg: F1Config) -> Self { Self { cfg } }
+    pub fn new(cfg: F1Config) -> Self {
+        let car = AtomicU16::new(cfg.car_index.map_or(PLAYER_CAR, u16::from));
+        Self { cfg, counters: Arc::default(), car }
+    }
 }
 
 #[async_trait::async_trait]
@@ -31,21 +53,41 @@ impl TelemetrySource for F1Source {
     async fn run(&self, tx: TelemetryTx) -> Result<(), IngestError> {
         let socket = UdpSocket::bind(&self.cfg.bind_addr)
             .await
-            .with_context(|| format!("bind {}", self.cfg.bind_addr))?;
+            .map_err(|e| IngestError::Other(port::diagnose(&self.cfg.bind_addr, &e).into()))?;
 
         let mut buf = vec![0u8; 2048];
+        let mut decoder = F1Decoder::default();
 
         loop {
-            let (len, _peer) = socket
-                .recv_from(&mut buf)
-                .await
-                .with_context(|| "recv_from UDP socket")?;
+            let recv = match time::timeout(STREAM_IDLE, socket.recv_from(&mut buf)).await {
+                Ok(recv) => recv,
+                // the game stopped sending: its last frame is complete
+                Err(_) => {
+                    if decoder.flush().is_some_and(|sample| tx.send(sample).is_err()) {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let len = match recv {
+                Ok((len, _peer)) => len,
+                Err(e) => {
+                    if let Some(sample) = decoder.flush() {
+                        let _ = tx.send(sample);
+                    }
+                    return Err(anyhow::Error::new(e).context("recv_from UDP socket").into());
+                }
+            };
 
-            if len < 32 {
-                continue;
+            let started = Instant::now();
+            decoder.follow(u8::try_from(self.car.load(Ordering::Relaxed)).ok());
+            let parsed = decoder.parse_packet(&buf[..len]);
+            // a packet is read whether or not it completes a frame's sample
+            self.counters.record(len, decoder.decoded(), started.elapsed());
+            if !decoder.decoded() && malformed(&buf[..len]) {
+                self.counters.record_malformed();
             }
-
-            if let Some(sample) = parse_packet(&buf[..len], self.cfg.expected_format) {
+            if let Some(sample) = parsed {
                 // crossbeam Sender is synchronous; if the receiver is gone, stop gracefully
                 if tx.send(sample).is_err() {
                     break;
@@ -55,56 +97,146 @@ impl TelemetrySource for F1Source {
 
         Ok(())
     }
+
+    fn counters(&self) -> Option<Arc<IngestCounters>> {
+        Some(self.counters.clone())
+    }
+
+    fn follow_car(&self, index: Option<u8>) -> bool {
+        self.car.store(index.map_or(PLAYER_CAR, u16::from), Ordering::Relaxed);
+        true
+    }
 }
 
-#[derive(Debug)]
+// Wire layouts of the 2024 spec (2025 keeps them), read in place from the
+// datagram: every field is little-endian and unaligned, so a view over any
+// byte offset is valid. Per-car entries follow the header in car order, so
+// car `i`'s starts `i * size_of::<Entry>()` past it.
+
+#[repr(C)]
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
 struct PacketHeader {
-    packet_format: u16, // 2024/2025
+    packet_format: U16, // 2024/2025
     game_year: u8,
     game_major: u8,
     game_minor: u8,
     packet_version: u8,
     packet_id: u8,
-    session_uid: u64,
-    session_time: f32,
-    frame_identifier: u32,
-    overall_frame_identifier: u32,
+    session_uid: U64,
+    session_time: F32,
+    frame_identifier: U32,
+    overall_frame_identifier: U32,
     player_car_index: u8,
     secondary_player_car_index: u8,
 }
 
-fn read_header(mut c: Cursor<&[u8]>) -> Option<PacketHeader> {
-    let pf = c.read_u16::<LittleEndian>().ok()?;
-    let game_year = c.read_u8().ok()?;
-    let game_major = c.read_u8().ok()?;
-    let game_minor = c.read_u8().ok()?;
-    let packet_version = c.read_u8().ok()?;
-    let packet_id = c.read_u8().ok()?;
-    let session_uid = c.read_u64::<LittleEndian>().ok()?;
-    let session_time = c.read_f32::<LittleEndian>().ok()?;
-    let frame_identifier = c.read_u32::<LittleEndian>().ok()?;
-    let overall_frame_identifier = c.read_u32::<LittleEndian>().ok()?;
-    let player_car_index = c.read_u8().ok()?;
-    let secondary_player_car_index = c.read_u8().ok()?;
-
-    Some(PacketHeader {
-        packet_format: pf,
-        game_year,
-        game_major,
-        game_minor,
-        packet_version,
-        packet_id,
-        session_uid,
-        session_time,
-        frame_identifier,
-        overall_frame_identifier,
-        player_car_index,
-        secondary_player_car_index,
-    })
-}
-
-// Packet IDs (Codemasters/EA spec). We only need Motion (0), LapData (2), CarTelemetry (6).
+/// A car's entry in the motion packet.
+#[repr(C, packed)]
+#[derive(FromBytes, KnownLayout, Immutable, Unaligned)]
+struct CarMotion {
+    world_pos: [F32; 3],
+    _world_velocity: [F32; 3],
+    // normalised forward and right vectors
+    _world_forward_dir: [I16; 3],
+    _world_right_dir: [I16; 3],
+    _g_force: [F32; 3],
+    yaw: F32,
+    pitch: F32,
+    roll: F32,
+}
+
+/// A car's entry in the lap data packet.
+#[repr(C, packed)]
+#[derive(FromBytes, KnownLayout, Immutable, Unaligned)]
+struct CarLap {
+    last_lap_time_ms: U32,
+    current_lap_time_ms: U32,
+    // sector 1 and 2 times, gaps to the car in front and the leader
+    _splits: [u8; 12],
+    lap_distance: F32,
+    _total_distance: F32,
+    _safety_car_delta: F32,
+    _car_position: u8,
+    current_lap_num: u8,
+    // pit status through grid position
+    _status: [u8; 10],
+    // 0 in the garage, 1 flying lap, 2 in-lap, 3 out-lap, 4 on track
+    driver_status: u8,
+    _result_status: u8,
+    _pit_lane_timer_active: u8,
+    _pit_lane_time_ms: U16,
+    _pit_stop_timer_ms: U16,
+    _pit_stop_should_serve_pen: u8,
+    _speed_trap_fastest_speed: F32,
+    _speed_trap_fastest_lap: u8,
+}
+
+/// The start of the session packet, up to the safety car status.
+#[repr(C)]
+#[derive(FromBytes, KnownLayout, Immutable, Unaligned)]
+struct Session {
+    _skip: [u8; 14],
+    game_paused: u8,
+    // spectating, marshal zones
+    _skip2: [u8; 109],
+    // 0 none, 1 full, 2 virtual, 3 formation lap
+    safety_car_status: u8,
+}
+
+const DRIVER_IN_GARAGE: u8 = 0;
+const SAFETY_CAR_FORMATION: u8 = 3;
+
+/// A car's entry in the car telemetry packet.
+#[repr(C, packed)]
+#[derive(FromBytes, KnownLayout, Immutable, Unaligned)]
+struct CarTelemetry {
+    speed_kph: U16,
+    throttle: F32,
+    _steer: F32,
+    brake: F32,
+    _clutch: u8,
+    gear: i8,
+    rpm: U16,
+    _drs: u8,
+    _rev_lights: [u8; 3],
+    // brake, tyre and engine temperatures
+    _temperatures: [u8; 18],
+    _tyre_pressures: [F32; 4],
+    _surface_type: [u8; 4],
+}
+
+/// Too short for any packet, or with a header no F1 game sends: an unknown
+/// packet format or id.
+fn malformed(buf: &[u8]) -> bool {
+    buf.len() < 32
+        || read_header(buf).is_none_or(|h| !(2018..2100).contains(&h.packet_format.get()) || h.packet_id > 15)
+}
+
+fn read_header(buf: &[u8]) -> Option<&PacketHeader> {
+    PacketHeader::ref_from_prefix(buf).ok().map(|(h, _)| h)
+}
+
+/// `T` at `start` in `buf`, or `None` if the packet ends before it does.
+fn view<T: FromBytes + KnownLayout + Immutable>(buf: &[u8], start: usize) -> Option<&T> {
+    T::ref_from_prefix(buf.get(start..)?).ok().map(|(v, _)| v)
+}
+
+/// Cars in the game's per-car arrays.
+pub const MAX_CARS: u8 = 22;
+
+const _: () = {
+    assert!(size_of::<PacketHeader>() == 29);
+    assert!(size_of::<CarMotion>() == 60);
+    assert!(size_of::<CarLap>() == 57);
+    assert!(size_of::<CarTelemetry>() == 60);
+    assert!(std::mem::offset_of!(CarLap, current_lap_num) == 33);
+    assert!(std::mem::offset_of!(CarLap, driver_status) == 44);
+    assert!(std::mem::offset_of!(CarTelemetry, gear) == 15);
+};
+
+// Packet IDs (Codemasters/EA spec). We only need Motion (0), Session (1), LapData (2), CarTelemetry (6).
 const PACKET_MOTION: u8 = 0;
+const PACKET_SESSION: u8 = 1;
 const PACKET_LAPDATA: u8 = 2;
 const PACKET_CAR_TELEMETRY: u8 = 6;
 
@@ -126,97 +258,125 @@ struct PlayerState {
     current_lap: u32,
     current_lap_time_s: f32,
     last_lap_time_s: f32,
+    in_garage: bool,
+    paused: bool,
+    formation: bool,
     frame: u64,
 }
 
-fn parse_packet(buf: &[u8], _expected_format: u16) -> Option<TelemetrySample> {
-    let hdr = read_header(Cursor::new(buf))?;
-    // If packet_format doesn't match expected, still accept for cross-year convenience
+/// Decodes F1 UDP packets into samples, combining the followed car's latest
+/// motion, lap and telemetry values across packets. The game sends several
+/// packets per frame, so a frame's sample is held until the next frame
+/// starts and sent once, with everything its packets carried; packets of
+/// a frame already sent add nothing. State is per decoder, so each source
+/// or replay owns its own.
+#[derive(Default)]
+pub struct F1Decoder {
+    st: PlayerState,
+    // the player's when `None`
+    car: Option<u8>,
+    // sample of the frame in progress
+    pending: Option<TelemetrySample>,
+    // whether the last packet was read
+    decoded: bool,
+}
+
+impl F1Decoder {
+    /// Follow car `index` from the next packet on; the player's for `None`.
+    /// Values kept from the car followed so far are dropped.
+    pub fn follow(&mut self, index: Option<u8>) {
+        if index != self.car {
+            self.car = index;
+            self.st = PlayerState::default();
+            self.pending = None;
+        }
+    }
+
 
-    use std::sync::OnceLock;
-    static STATE: OnceLock<std::sync::Mutex<PlayerState>> = OnceLock::new();
-    let state = STATE.get_or_init(|| std::sync::Mutex::new(PlayerState::default()));
-    let mut st = state.lock().ok()?; // lock mutex for thread safety
+    /// The sample of the last frame, once `buf` starts the next; `None`
+    /// until then, or if it isn't an F1 packet.
+    pub fn parse_packet(&mut self, buf: &[u8]) -> Option<TelemetrySample> {
+        self.decoded = false;
+        if buf.len() < 32 {
+            return None;
+        }
+        let sample = decode(buf, &mut self.st, self.car)?;
+        self.decoded = true;
+        match &self.pending {
+            Some(p) if p.session_uid == sample.session_uid && sample.frame < p.frame => None,
+            Some(p) if p.session_uid == sample.session_uid && sample.frame == p.frame => {
+                self.pending = Some(sample);
+                None
+            }
+            _ => self.pending.replace(sample),
+        }
+    }
+
+    /// Whether the last packet given to [`Self::parse_packet`] was read as
+    /// an F1 packet, whether or not it completed a sample.
+    pub fn decoded(&self) -> bool {
+        self.decoded
+    }
+
+    /// The sample of the frame in progress, at the end of the packets.
+    pub fn flush(&mut self) -> Option<TelemetrySample> {
+        self.pending.take()
+    }
+}
+
+fn decode(buf: &[u8], st: &mut PlayerState, car: Option<u8>) -> Option<TelemetrySample> {
+    let hdr = read_header(buf)?;
+    // If packet_format doesn't match expected, still accept for cross-year convenience
 
+    let base = size_of::<PacketHeader>();
+    let idx = car.unwrap_or(hdr.player_car_index) as usize;
     match hdr.packet_id {
         PACKET_MOTION => {
-            // Layout as per spec: 22 cars of MotionData; read player's by index
-            let base = 24; // header size up to secondary player index
-            let idx = hdr.player_car_index as usize;
-            // Per-car MotionData payload size varies by year; guard aggressively.
-            let per_car = 1464usize; // conservative size for recent years
-            let start = base + idx * per_car;
-
-            if buf.len() >= start + 64 {
-                let mut c = Cursor::new(&buf[start..start + 64]);
-                st.world_pos_x = c.read_f32::<LittleEndian>().unwrap_or(st.world_pos_x);
-                st.world_pos_y = c.read_f32::<LittleEndian>().unwrap_or(st.world_pos_y);
-                st.world_pos_z = c.read_f32::<LittleEndian>().unwrap_or(st.world_pos_z);
-
-                // skip 7 f32s (velocities/angles) to orientation (approx offsets 36..48)
-                let _ = c.read_f32::<LittleEndian>();
-                let _ = c.read_f32::<LittleEndian>();
-                let _ = c.read_f32::<LittleEndian>();
-                let _ = c.read_f32::<LittleEndian>();
-                let _ = c.read_f32::<LittleEndian>();
-                let _ = c.read_f32::<LittleEndian>();
-                let _ = c.read_f32::<LittleEndian>();
-
-                st.yaw = c.read_f32::<LittleEndian>().unwrap_or(st.yaw);
-                st.pitch = c.read_f32::<LittleEndian>().unwrap_or(st.pitch);
-                st.roll = c.read_f32::<LittleEndian>().unwrap_or(st.roll);
+            if let Some(m) = view::<CarMotion>(buf, base + idx * size_of::<CarMotion>()) {
+                [st.world_pos_x, st.world_pos_y, st.world_pos_z] = m.world_pos.map(|v| v.get());
+                st.yaw = m.yaw.get();
+                st.pitch = m.pitch.get();
+                st.roll = m.roll.get();
             }
         }
-        PACKET_LAPDATA => {
-            // LapData: 22 cars entries; read player's lap metrics
-            let base = 24;
-            let idx = hdr.player_car_index as usize;
-
-            // Use conservative offsets used here: lap distance @0x14 (f32), current @0x20, last @0x24
-            let start = base + idx * 51; // rough stride; we guard by length below
-            if buf.len() >= start + 0x28 {
-                let mut c = Cursor::new(&buf[start + 0x14..start + 0x28]);
-                st.lap_distance = c.read_f32::<LittleEndian>().unwrap_or(st.lap_distance);
-                st.current_lap_time_s =
-                    c.read_f32::<LittleEndian>().unwrap_or(st.current_lap_time_s);
-                st.last_lap_time_s = c.read_f32::<LittleEndian>().unwrap_or(st.last_lap_time_s);
+        PACKET_SESSION => {
+            if let Some(v) = view::<Session>(buf, base) {
+                st.paused = v.game_paused != 0;
+                st.formation = v.safety_car_status == SAFETY_CAR_FORMATION;
             }
-
-            // Current lap number—treat as u8 fallback
-            let lap_num_off = start + 0x10;
-            if buf.len() > lap_num_off {
-                st.current_lap = buf[lap_num_off] as u32;
+        }
+        PACKET_LAPDATA => {
+            if let Some(l) = view::<CarLap>(buf, base + idx * size_of::<CarLap>()) {
+                st.lap_distance = l.lap_distance.get();
+                st.current_lap = l.current_lap_num as u32;
+                st.current_lap_time_s = l.current_lap_time_ms.get() as f32 / 1000.0;
+                st.last_lap_time_s = l.last_lap_time_ms.get() as f32 / 1000.0;
+                st.in_garage = l.driver_status == DRIVER_IN_GARAGE;
             }
         }
         PACKET_CAR_TELEMETRY => {
-            // CarTelemetry: 22 cars; read speed, throttle, brake, gear, rpm
-            let base = 24;
-            let idx = hdr.player_car_index as usize;
-            let start = base + idx * 58; // rough stride; guard length
-
-            if buf.len() >= start + 20 {
-                let mut c = Cursor::new(&buf[start..]);
-                let speed_kph = c.read_u16::<LittleEndian>().unwrap_or(0) as f32;
-                st.speed_mps = speed_kph / 3.6;
-                st.throttle = c.read_u8().unwrap_or(0) as f32 / 255.0;
-                let _steer = c.read_i8().unwrap_or(0);
-                st.brake = c.read_u8().unwrap_or(0) as f32 / 255.0;
-                let _clutch = c.read_u8().unwrap_or(0);
-                st.gear = c.read_i8().unwrap_or(st.gear);
-                st.rpm = c.read_u16::<LittleEndian>().unwrap_or(0) as f32;
+            if let Some(t) = view::<CarTelemetry>(buf, base + idx * size_of::<CarTelemetry>()) {
+                st.speed_mps = t.speed_kph.get() as f32 / 3.6;
+                st.throttle = t.throttle.get();
+                st.brake = t.brake.get();
+                st.gear = t.gear;
+                st.rpm = t.rpm.get() as f32;
             }
         }
         _ => {}
     }
 
-    st.frame = hdr.overall_frame_identifier as u64;
+    st.frame = hdr.overall_frame_identifier.get() as u64;
 
-    Some(TelemetrySample {
-        game: if hdr.packet_format >= 2025 { GameId::F1_2025 } else { GameId::F1_2024 },
-        car_id: format!("player:{}", hdr.player_car_index),
-        session_uid: format!("{}", hdr.session_uid),
+    let mut sample = TelemetrySample {
+        game: if hdr.packet_format.get() >= 2025 { GameId::F1_2025 } else { GameId::F1_2024 },
+        car_id: match car {
+            Some(i) => format!("car:{i}"),
+            None => format!("player:{}", hdr.player_car_index),
+        },
+        session_uid: format!("{}", hdr.session_uid.get()),
         frame: st.frame,
-        sim_time_s: hdr.session_time as f64,
+        sim_time_s: hdr.session_time.get() as f64,
 
         speed_mps: st.speed_mps,
         throttle: st.throttle,
@@ -235,5 +395,244 @@ fn parse_packet(buf: &[u8], _expected_format: u16) -> Option<TelemetrySample> {
         current_lap: st.current_lap,
         current_lap_time_s: st.current_lap_time_s,
         last_lap_time_s: st.last_lap_time_s,
-    })
+        best_lap_time_s: 0.0,
+        fuel_l: 0.0,
+        fuel_capacity_l: 0.0,
+        rev_limiter: false,
+
+        phase: if st.paused {
+            Phase::Paused
+        } else if st.in_garage {
+            Phase::Garage
+        } else if st.formation {
+            Phase::Formation
+        } else {
+            Phase::Driving
+        },
+    };
+    frame::to_common(&mut sample);
+    Some(sample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A packet of `len` bytes with a 2024 header for `id`, frame `frame`,
+    /// with the player in car 0.
+    fn packet(id: u8, frame: u32, len: usize) -> Vec<u8> {
+        let mut b = vec![0u8; len];
+        b[0..2].copy_from_slice(&2024u16.to_le_bytes());
+        b[2] = 24;
+        b[3] = 1;
+        b[5] = 1;
+        b[6] = id;
+        b[7..15].copy_from_slice(&0x1234_5678_9abc_def0u64.to_le_bytes());
+        b[15..19].copy_from_slice(&12.5f32.to_le_bytes());
+        b[19..23].copy_from_slice(&frame.to_le_bytes());
+        b[23..27].copy_from_slice(&frame.to_le_bytes());
+        b
+    }
+
+    fn put(b: &mut [u8], at: usize, v: &[u8]) {
+        b[at..at + v.len()].copy_from_slice(v);
+    }
+
+    /// A motion packet with car `i` at (i, 2i, 3i) and yawed 0.1 * i.
+    pub(crate) fn motion(frame: u32) -> Vec<u8> {
+        let mut b = packet(PACKET_MOTION, frame, 29 + 22 * 60);
+        for i in 0..22 {
+            let car = 29 + i * 60;
+            for (k, v) in [i as f32, 2.0 * i as f32, 3.0 * i as f32].into_iter().enumerate() {
+                put(&mut b, car + k * 4, &v.to_le_bytes());
+            }
+            put(&mut b, car + 12, &[0x55; 36]); // velocity, directions, g-forces
+            put(&mut b, car + 48, &(0.1 * i as f32).to_le_bytes());
+            put(&mut b, car + 52, &0.01f32.to_le_bytes());
+            put(&mut b, car + 56, &(-0.02f32).to_le_bytes());
+        }
+        b
+    }
+
+    /// A lap data packet with car `i` on lap `i + 1`, `100 * i` m round it.
+    pub(crate) fn lap_data(frame: u32) -> Vec<u8> {
+        let mut b = packet(PACKET_LAPDATA, frame, 29 + 22 * 57 + 2);
+        for i in 0..22 {
+            let car = 29 + i * 57;
+            put(&mut b, car, &(90_000 + i as u32).to_le_bytes()); // last lap ms
+            put(&mut b, car + 4, &(30_500 + i as u32).to_le_bytes()); // current lap ms
+            put(&mut b, car + 8, &[0x77; 12]); // sectors and gaps
+            put(&mut b, car + 20, &(100.0 * i as f32).to_le_bytes()); // lap distance
+            put(&mut b, car + 24, &(5000.0 + i as f32).to_le_bytes()); // total distance
+            put(&mut b, car + 28, &(-1.5f32).to_le_bytes()); // safety car delta
+            b[car + 32] = 22 - i as u8; // position
+            b[car + 33] = i as u8 + 1; // lap number
+            b[car + 44] = 4; // driver status: on track
+            b[car + 56] = 9; // speed trap lap
+        }
+        b
+    }
+
+    /// A car telemetry packet with car `i` at `100 + i` km/h in gear `i % 8 + 1`.
+    pub(crate) fn telemetry(frame: u32) -> Vec<u8> {
+        let mut b = packet(PACKET_CAR_TELEMETRY, frame, 29 + 22 * 60 + 3);
+        for i in 0..22 {
+            let car = 29 + i * 60;
+            put(&mut b, car, &(100 + i as u16).to_le_bytes());
+            put(&mut b, car + 2, &0.75f32.to_le_bytes()); // throttle
+            put(&mut b, car + 6, &(-0.25f32).to_le_bytes()); // steer
+            put(&mut b, car + 10, &0.5f32.to_le_bytes()); // brake
+            b[car + 15] = (i % 8) as u8 + 1;
+            put(&mut b, car + 16, &(10_000 + i as u16).to_le_bytes());
+            put(&mut b, car + 22, &[0x66; 18]); // temperatures
+            put(&mut b, car + 40, &23.5f32.to_le_bytes()); // tyre pressure
+        }
+        b
+    }
+
+    #[test]
+    fn decodes_the_player_car_from_each_packet() {
+        let mut st = PlayerState::default();
+        for b in [motion(7), lap_data(7), telemetry(7)] {
+            decode(&b, &mut st, None).unwrap();
+        }
+        assert_eq!((st.world_pos_x, st.world_pos_y, st.world_pos_z), (0.0, 0.0, 0.0));
+        assert_eq!((st.yaw, st.pitch, st.roll), (0.0, 0.01, -0.02));
+        assert_eq!(st.current_lap, 1);
+        assert_eq!(st.lap_distance, 0.0);
+        assert_eq!(st.current_lap_time_s, 30.5);
+        assert_eq!(st.last_lap_time_s, 90.0);
+        assert!(!st.in_garage);
+        assert!((st.speed_mps - 100.0 / 3.6).abs() < 1e-4);
+        assert_eq!((st.throttle, st.brake), (0.75, 0.5));
+        assert_eq!(st.gear, 1);
+        assert_eq!(st.rpm, 10_000.0);
+    }
+
+    #[test]
+    fn last_car_entry_fits_the_packet() {
+        let mut st = PlayerState::default();
+        let last = MAX_CARS - 1;
+        for b in [motion(7), lap_data(7), telemetry(7)] {
+            decode(&b, &mut st, Some(last)).unwrap();
+        }
+        assert_eq!(st.world_pos_x, 21.0);
+        assert_eq!(st.current_lap, 22);
+        assert_eq!(st.lap_distance, 2100.0);
+        assert_eq!(st.rpm, 10_021.0);
+    }
+
+    #[test]
+    fn follows_a_car_other_than_the_players() {
+        let mut d = F1Decoder::default();
+        d.follow(Some(5));
+        for b in [motion(7), lap_data(7), telemetry(7)] {
+            assert!(d.parse_packet(&b).is_none());
+        }
+        let s = d.parse_packet(&motion(8)).unwrap();
+        assert!(d.decoded());
+        assert_eq!(s.car_id, "car:5");
+        assert_eq!(s.frame, 7);
+        assert_eq!(s.current_lap, 6);
+        assert_eq!(s.lap_distance_m, 500.0);
+        assert_eq!(s.current_lap_time_s, 30.505);
+        assert_eq!(s.last_lap_time_s, 90.005);
+        assert_eq!(s.gear, 6);
+        assert_eq!(s.engine_rpm, 10_005.0);
+        assert!((s.speed_mps - 105.0 / 3.6).abs() < 1e-4);
+        // game (5, 10, 15) is east 5, north -15, up 10
+        assert_eq!((s.world_pos_x, s.world_pos_y, s.world_pos_z), (5.0, -15.0, 10.0));
+
+        // switching back drops car 5's values
+        d.follow(None);
+        assert!(d.flush().is_none());
+        d.parse_packet(&lap_data(9));
+        let s = d.flush().unwrap();
+        assert_eq!(s.car_id, "player:0");
+        assert_eq!(s.current_lap, 1);
+        assert_eq!(s.engine_rpm, 0.0);
+    }
+
+    #[test]
+    fn every_packet_of_a_frame_decodes_but_one_sample_is_sent() {
+        let mut d = F1Decoder::default();
+        let mut samples = Vec::new();
+        for frame in 1..=3 {
+            for b in [motion(frame), lap_data(frame), telemetry(frame)] {
+                samples.extend(d.parse_packet(&b));
+                assert!(d.decoded());
+            }
+        }
+        // a resent packet of an earlier frame is read but adds nothing
+        assert!(d.parse_packet(&lap_data(2)).is_none());
+        assert!(d.decoded());
+        assert!(d.parse_packet(&[0u8; 8]).is_none());
+        assert!(!d.decoded());
+
+        samples.extend(d.flush());
+        assert_eq!(samples.iter().map(|s| s.frame).collect::<Vec<_>>(), [1, 2, 3]);
+        assert!(d.flush().is_none());
+    }
+
+    #[test]
+    fn interleaved_replays_keep_their_own_state() {
+        let session = |mut b: Vec<u8>, uid: u64| {
+            b[7..15].copy_from_slice(&uid.to_le_bytes());
+            b
+        };
+        let stream = |uid: u64| -> Vec<Vec<u8>> {
+            (1..=2).flat_map(|f| [motion(f), lap_data(f), telemetry(f)]).map(|b| session(b, uid)).collect()
+        };
+        // one replay follows the player, the other car 3
+        let (mut a, mut b) = (F1Decoder::default(), F1Decoder::default());
+        b.follow(Some(3));
+        let (mut out_a, mut out_b) = (Vec::new(), Vec::new());
+        for (pa, pb) in stream(1).iter().zip(stream(2).iter()) {
+            out_a.extend(a.parse_packet(pa));
+            out_b.extend(b.parse_packet(pb));
+        }
+        out_a.extend(a.flush());
+        out_b.extend(b.flush());
+
+        assert_eq!(out_a.len(), 2);
+        assert_eq!(out_b.len(), 2);
+        for s in &out_a {
+            assert_eq!((s.session_uid.as_str(), s.car_id.as_str(), s.current_lap), ("1", "player:0", 1));
+        }
+        for s in &out_b {
+            assert_eq!((s.session_uid.as_str(), s.car_id.as_str(), s.current_lap), ("2", "car:3", 4));
+        }
+    }
+
+    #[test]
+    fn session_reads_pause_and_safety_car_past_the_header() {
+        // 2024 session packet, laid out as the spec has it from byte 29
+        let mut b = packet(PACKET_SESSION, 100, 753);
+        let body = 29;
+        b[body + 3] = 57; // total laps
+        b[body + 4..body + 6].copy_from_slice(&5412u16.to_le_bytes()); // track length
+        b[body + 9..body + 11].copy_from_slice(&3541u16.to_le_bytes()); // time left
+        b[body + 11..body + 13].copy_from_slice(&3600u16.to_le_bytes()); // duration
+        b[body + 13] = 80; // pit speed limit
+        b[body + 14] = 0; // game paused
+        b[body + 18] = 21; // marshal zones
+        for z in 0..21 {
+            let at = body + 19 + z * 5;
+            b[at..at + 4].copy_from_slice(&(z as f32 / 21.0).to_le_bytes());
+            b[at + 4] = 1;
+        }
+        b[body + 124] = SAFETY_CAR_FORMATION;
+
+        let mut st = PlayerState::default();
+        let s = decode(&b, &mut st, None).unwrap();
+        assert!(!st.paused);
+        assert!(st.formation);
+        assert_eq!(s.phase, Phase::Formation);
+
+        b[body + 14] = 1;
+        b[body + 124] = 0;
+        decode(&b, &mut st, None).unwrap();
+        assert!(st.paused);
+        assert!(!st.formation);
+    }
 }
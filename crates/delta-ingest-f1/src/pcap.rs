@@ -0,0 +1,309 @@
+//! Replay F1 UDP traffic from a Wireshark/tcpdump capture.
+//!
+//! Reads classic pcap and pcapng files (Ethernet, loopback, raw IP and Linux
+//! cooked captures), pulls out the UDP payloads sent to the telemetry port and
+//! runs them through [`F1Decoder`] in capture order.
+
+use anyhow::{anyhow, bail, Result};
+use std::path::Path;
+
+use delta_ingest_core::TelemetrySample;
+
+use crate::F1Decoder;
+
+// link-layer types (https://www.tcpdump.org/linktypes.html)
+const LINK_NULL: u32 = 0;
+const LINK_ETHERNET: u32 = 1;
+const LINK_RAW: u32 = 101;
+const LINK_LINUX_SLL: u32 = 113;
+const LINK_LOOP: u32 = 108;
+const LINK_IPV4: u32 = 228;
+const LINK_IPV6: u32 = 229;
+const LINK_LINUX_SLL2: u32 = 276;
+
+const PCAPNG_SHB: u32 = 0x0A0D_0D0A;
+const PCAPNG_IDB: u32 = 1;
+const PCAPNG_SPB: u32 = 3;
+const PCAPNG_EPB: u32 = 6;
+
+/// Samples from every F1 packet in the capture at `path` sent to `port`
+/// (any UDP port when `None`).
+pub fn read_capture(path: &Path, port: Option<u16>) -> Result<Vec<TelemetrySample>> {
+    let data = std::fs::read(path)?;
+    let mut decoder = F1Decoder::default();
+    let mut samples = Vec::new();
+    for_each_frame(&data, |link, frame| {
+        if let Some(payload) = udp_payload(link, frame, port) {
+            samples.extend(decoder.parse_packet(payload));
+        }
+    })?;
+    samples.extend(decoder.flush());
+    if samples.is_empty() {
+        bail!("no F1 telemetry packets found in {}", path.display());
+    }
+    Ok(samples)
+}
+
+/// Calls `f` with the link type and bytes of every captured frame.
+fn for_each_frame(data: &[u8], mut f: impl FnMut(u32, &[u8])) -> Result<()> {
+    let magic = data.get(..4).ok_or_else(|| anyhow!("not a capture file: too short"))?;
+    match magic {
+        [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => pcap_frames(data, false, &mut f),
+        [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => pcap_frames(data, true, &mut f),
+        [0x0a, 0x0d, 0x0d, 0x0a] => pcapng_frames(data, &mut f),
+        _ => bail!("not a pcap or pcapng capture"),
+    }
+}
+
+fn u16_at(b: &[u8], at: usize, be: bool) -> Option<u16> {
+    let v: [u8; 2] = b.get(at..at + 2)?.try_into().ok()?;
+    Some(if be { u16::from_be_bytes(v) } else { u16::from_le_bytes(v) })
+}
+
+fn u32_at(b: &[u8], at: usize, be: bool) -> Option<u32> {
+    let v: [u8; 4] = b.get(at..at + 4)?.try_into().ok()?;
+    Some(if be { u32::from_be_bytes(v) } else { u32::from_le_bytes(v) })
+}
+
+fn pcap_frames(data: &[u8], be: bool, f: &mut impl FnMut(u32, &[u8])) -> Result<()> {
+    // upper bits may carry FCS flags
+    let link = u32_at(data, 20, be).ok_or_else(|| anyhow!("truncated pcap header"))? & 0xffff;
+    let mut at = 24;
+    // a truncated last record (capture cut off mid-write) just ends the file
+    while let Some(len) = u32_at(data, at + 8, be) {
+        let start = at + 16;
+        let Some(frame) = data.get(start..start + len as usize) else { break };
+        f(link, frame);
+        at = start + len as usize;
+    }
+    Ok(())
+}
+
+fn pcapng_frames(data: &[u8], f: &mut impl FnMut(u32, &[u8])) -> Result<()> {
+    let mut be = false;
+    let mut links: Vec<u32> = Vec::new();
+    let mut at = 0;
+    while at + 12 <= data.len() {
+        let kind = u32_at(data, at, be).unwrap_or(0);
+        if kind == PCAPNG_SHB {
+            // byte-order magic decides the endianness of this section
+            be = match data.get(at + 8..at + 12) {
+                Some([0x1a, 0x2b, 0x3c, 0x4d]) => true,
+                Some([0x4d, 0x3c, 0x2b, 0x1a]) => false,
+                _ => bail!("bad pcapng section header"),
+            };
+            links.clear();
+        }
+        let len = u32_at(data, at + 4, be).unwrap_or(0) as usize;
+        if len < 12 || at + len > data.len() {
+            break;
+        }
+        let body = &data[at + 8..at + len - 4];
+        match kind {
+            PCAPNG_IDB => links.push(u16_at(body, 0, be).unwrap_or(0) as u32),
+            PCAPNG_EPB => {
+                if let (Some(iface), Some(cap)) = (u32_at(body, 0, be), u32_at(body, 12, be)) {
+                    if let (Some(&link), Some(frame)) = (links.get(iface as usize), body.get(20..20 + cap as usize)) {
+                        f(link, frame);
+                    }
+                }
+            }
+            PCAPNG_SPB => {
+                if let (Some(&link), Some(orig)) = (links.first(), u32_at(body, 0, be)) {
+                    let cap = (orig as usize).min(body.len() - 4);
+                    f(link, &body[4..4 + cap]);
+                }
+            }
+            _ => {}
+        }
+        at += len;
+    }
+    Ok(())
+}
+
+/// UDP payload of a captured frame, if it is an unfragmented IPv4/IPv6 UDP
+/// datagram to `port`.
+fn udp_payload(link: u32, frame: &[u8], port: Option<u16>) -> Option<&[u8]> {
+    let ip = match link {
+        LINK_ETHERNET => {
+            let mut at = 12;
+            // skip 802.1Q / QinQ tags
+            while matches!(u16_at(frame, at, true)?, 0x8100 | 0x88a8) {
+                at += 4;
+            }
+            frame.get(at + 2..)?
+        }
+        LINK_NULL | LINK_LOOP => frame.get(4..)?,
+        LINK_RAW | LINK_IPV4 | LINK_IPV6 => frame,
+        LINK_LINUX_SLL => frame.get(16..)?,
+        LINK_LINUX_SLL2 => frame.get(20..)?,
+        _ => return None,
+    };
+    let udp = match ip.first()? >> 4 {
+        4 => {
+            let ihl = (ip[0] & 0x0f) as usize * 4;
+            let frag = u16_at(ip, 6, true)?;
+            if *ip.get(9)? != 17 || frag & 0x3fff != 0 {
+                return None;
+            }
+            let total = (u16_at(ip, 2, true)? as usize).min(ip.len());
+            ip.get(ihl..total)?
+        }
+        6 => {
+            if *ip.get(6)? != 17 {
+                return None;
+            }
+            let total = (40 + u16_at(ip, 4, true)? as usize).min(ip.len());
+            ip.get(40..total)?
+        }
+        _ => return None,
+    };
+    let dst = u16_at(udp, 2, true)?;
+    if port.is_some_and(|p| p != dst) {
+        return None;
+    }
+    let len = (u16_at(udp, 4, true)? as usize).min(udp.len());
+    udp.get(8..len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{lap_data, motion, telemetry};
+
+    const F1_PORT: u16 = 20777;
+
+    /// An Ethernet frame holding an IPv4 datagram of protocol `proto` to
+    /// `port`; UDP (17) gets a UDP header in front of `payload`.
+    fn ethernet(proto: u8, port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut udp = Vec::new();
+        udp.extend(50_000u16.to_be_bytes());
+        udp.extend(port.to_be_bytes());
+        udp.extend((8 + payload.len() as u16).to_be_bytes());
+        udp.extend([0, 0]);
+        udp.extend(payload);
+
+        let mut f = vec![0xff; 12];
+        f.extend([0x08, 0x00]);
+        f.extend([0x45, 0]);
+        f.extend((20 + udp.len() as u16).to_be_bytes());
+        f.extend([0, 0, 0, 0, 64, proto, 0, 0]);
+        f.extend([192, 168, 1, 20, 192, 168, 1, 10]);
+        f.extend(udp);
+        f
+    }
+
+    /// Frames 1 to 3 of F1 traffic, with a packet to another port and a TCP
+    /// segment mixed in.
+    fn frames() -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        for frame in 1..=3 {
+            for b in [motion(frame), lap_data(frame), telemetry(frame)] {
+                frames.push(ethernet(17, F1_PORT, &b));
+            }
+            frames.push(ethernet(17, 5000, &motion(9)));
+            frames.push(ethernet(6, F1_PORT, &[0; 40]));
+        }
+        frames
+    }
+
+    fn pcap(frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut b = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        b.extend([0; 8]);
+        b.extend(65_535u32.to_le_bytes());
+        b.extend(LINK_ETHERNET.to_le_bytes());
+        for (i, f) in frames.iter().enumerate() {
+            b.extend((i as u32).to_le_bytes());
+            b.extend(0u32.to_le_bytes());
+            b.extend((f.len() as u32).to_le_bytes());
+            b.extend((f.len() as u32).to_le_bytes());
+            b.extend(f);
+        }
+        b
+    }
+
+    fn pcapng(frames: &[Vec<u8>]) -> Vec<u8> {
+        let block = |kind: u32, body: &[u8]| {
+            let len = (12 + body.len().div_ceil(4) * 4) as u32;
+            let mut b = Vec::new();
+            b.extend(kind.to_le_bytes());
+            b.extend(len.to_le_bytes());
+            b.extend(body);
+            b.resize(len as usize - 4, 0);
+            b.extend(len.to_le_bytes());
+            b
+        };
+        let mut shb = vec![0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0];
+        shb.extend((-1i64).to_le_bytes());
+        let mut idb = (LINK_ETHERNET as u16).to_le_bytes().to_vec();
+        idb.extend([0, 0]);
+        idb.extend(65_535u32.to_le_bytes());
+
+        let mut b = block(PCAPNG_SHB, &shb);
+        b.extend(block(PCAPNG_IDB, &idb));
+        for f in frames {
+            let mut epb = vec![0; 12];
+            epb.extend((f.len() as u32).to_le_bytes());
+            epb.extend((f.len() as u32).to_le_bytes());
+            epb.extend(f);
+            b.extend(block(PCAPNG_EPB, &epb));
+        }
+        b
+    }
+
+    /// `bytes` written to a file removed on drop.
+    struct Capture(std::path::PathBuf);
+
+    impl Capture {
+        fn new(name: &str, bytes: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("delta-f1-{}-{name}", std::process::id()));
+            std::fs::write(&path, bytes).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for Capture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn replays_f1_packets_from_a_pcap() {
+        let file = Capture::new("a.pcap", &pcap(&frames()));
+        let samples = read_capture(&file.0, Some(F1_PORT)).unwrap();
+        assert_eq!(samples.iter().map(|s| s.frame).collect::<Vec<_>>(), [1, 2, 3]);
+        let s = &samples[0];
+        assert_eq!(s.current_lap, 1);
+        assert_eq!(s.engine_rpm, 10_000.0);
+        assert!((s.speed_mps - 100.0 / 3.6).abs() < 1e-4);
+    }
+
+    #[test]
+    fn pcapng_gives_the_same_samples() {
+        let frames = frames();
+        let classic = Capture::new("b.pcap", &pcap(&frames));
+        let ng = Capture::new("b.pcapng", &pcapng(&frames));
+        let a = read_capture(&classic.0, Some(F1_PORT)).unwrap();
+        let b = read_capture(&ng.0, Some(F1_PORT)).unwrap();
+        assert_eq!(a.iter().map(|s| s.frame).collect::<Vec<_>>(), b.iter().map(|s| s.frame).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn a_capture_cut_off_mid_record_keeps_what_came_before() {
+        let mut bytes = pcap(&frames());
+        bytes.truncate(bytes.len() - 10);
+        let file = Capture::new("c.pcap", &bytes);
+        let samples = read_capture(&file.0, Some(F1_PORT)).unwrap();
+        assert_eq!(samples.iter().map(|s| s.frame).collect::<Vec<_>>(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn refuses_files_that_are_not_captures() {
+        let file = Capture::new("d.pcap", b"t_ms,speed\n0,1\n");
+        assert!(read_capture(&file.0, None).is_err());
+        // a capture with no F1 traffic on the port
+        let empty = Capture::new("e.pcap", &pcap(&[ethernet(17, F1_PORT, &[1, 2, 3])]));
+        assert!(read_capture(&empty.0, Some(F1_PORT)).is_err());
+    }
+}
@@ -0,0 +1,470 @@
+//! Lap distance for GT7, whose packets don't carry it, and the lap count
+//! where they don't have that either.
+//!
+//! Laps roll over when the game's lap counter goes up. The start line is a
+//! gate across the track where it first did, square to the way the car was
+//! going, which places each crossing between the samples either side, so
+//! the lap time and the distance into the new lap come out exact. Each lap
+//! counted narrows down where the game's line is.
+//!
+//! Without a lap counter, the gate goes where the car first gets moving,
+//! and passing through it the same way at least [`MIN_LAP_M`] after the
+//! last time starts the next lap.
+//!
+//! Until a lap has been driven gate to gate, distance is integrated from
+//! speed. That lap's path is then kept as the track, and on later laps the
+//! distance is where the car projects onto it, so every lap is measured on
+//! the same scale whatever line it takes and overlays and deltas line up.
+//! Off the kept path (the pit lane, a spin) it's integrated again from
+//! where the projection left off.
+
+use delta_ingest_core::TelemetrySample;
+
+/// Laps shorter than this don't count, so stopping on the gate or reversing
+/// through it doesn't count laps (m).
+pub const MIN_LAP_M: f64 = 500.0;
+/// How far either side of the start position the gate reaches (m).
+const GATE_HALF_WIDTH_M: f64 = 25.0;
+/// Below this the car isn't moving enough to tell a direction from (m/s).
+const MOVING_MPS: f64 = 3.0;
+/// Spacing of the kept track path (m).
+const PATH_STEP_M: f64 = 2.0;
+/// Further than this from the kept path, the car is off it (m).
+const OFF_PATH_M: f64 = 15.0;
+/// Path points searched ahead of and behind the last match.
+const SEARCH_AHEAD: usize = 100;
+const SEARCH_BEHIND: usize = 10;
+/// Gaps in the samples longer than this (a pause, a replay) add no
+/// distance (s).
+const MAX_GAP_S: f64 = 1.0;
+
+/// Start line gate: a point and the unit direction of travel through it.
+#[derive(Clone, Copy, Debug)]
+struct Gate {
+    at: (f64, f64),
+    dir: (f64, f64),
+}
+
+impl Gate {
+    /// Signed distance of `p` past the gate, and how far to the side of it.
+    fn locate(&self, p: (f64, f64)) -> (f64, f64) {
+        let (dx, dy) = (p.0 - self.at.0, p.1 - self.at.1);
+        (dx * self.dir.0 + dy * self.dir.1, (dx * self.dir.1 - dy * self.dir.0).abs())
+    }
+
+    /// How far from `prev` to `pos` (0..=1) the car went through the gate
+    /// the right way, if it did.
+    fn crossing(&self, prev: (f64, f64), pos: (f64, f64)) -> Option<f64> {
+        let (before, _) = self.locate(prev);
+        let (after, side) = self.locate(pos);
+        (before < 0.0 && after >= 0.0 && side <= GATE_HALF_WIDTH_M).then(|| before / (before - after))
+    }
+
+    /// How far from `prev` to `pos` (0..=1) the car went through the gate,
+    /// the game having counted a lap between them. The line is somewhere on
+    /// that stretch, so a gate off it moves onto its nearer end; over a few
+    /// laps it closes in on the line.
+    fn counted(&mut self, prev: (f64, f64), pos: (f64, f64)) -> f64 {
+        let (before, _) = self.locate(prev);
+        let (after, _) = self.locate(pos);
+        let shift = if after < 0.0 {
+            after
+        } else if before > 0.0 {
+            before
+        } else if after > before {
+            return -before / (after - before);
+        } else {
+            return 1.0;
+        };
+        self.at = (self.at.0 + shift * self.dir.0, self.at.1 + shift * self.dir.1);
+        if shift < 0.0 { 1.0 } else { 0.0 }
+    }
+}
+
+/// One point of the kept track path.
+#[derive(Clone, Copy, Debug)]
+struct PathPoint {
+    x: f64,
+    y: f64,
+    distance_m: f64,
+}
+
+/// Fills in the lap distance and lap times of GT7 samples, and the lap
+/// number where the packets don't have it; see the module docs.
+#[derive(Debug, Default)]
+pub struct LapTracker {
+    gate: Option<Gate>,
+    // position, time and speed of the last sample
+    last: Option<((f64, f64), f64, f64)>,
+    lap: u32,
+    distance_m: f64,
+    lap_start_s: f64,
+    last_lap_s: f64,
+    // the lap in progress started on the line and has no gaps
+    full_lap: bool,
+    // the packets carry the game's lap counter
+    counted: bool,
+    // path of the lap in progress, until the track is kept
+    path: Vec<PathPoint>,
+    track: Vec<PathPoint>,
+    // position in `track` of the last match; `None` off it
+    hint: Option<usize>,
+}
+
+impl LapTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Length of the kept track path, once a lap has been driven gate to
+    /// gate (m).
+    pub fn track_length_m(&self) -> Option<f64> {
+        self.track.last().map(|p| p.distance_m)
+    }
+
+    /// Set `s`'s lap distance and lap time, and its lap number and last lap
+    /// time unless the game counts laps itself.
+    pub fn update(&mut self, s: &mut TelemetrySample) {
+        let pos = (s.world_pos_x as f64, s.world_pos_y as f64);
+        let t = s.sim_time_s;
+        let speed = s.speed_mps as f64;
+
+        if s.current_lap > 0 && !self.counted {
+            // laps so far were counted on a gate of ours, which needn't be
+            // on the game's line
+            self.counted = true;
+            self.lap = s.current_lap;
+            self.gate = None;
+            self.full_lap = false;
+            self.path.clear();
+            self.track.clear();
+            self.hint = None;
+        }
+        let counter = self.counted.then_some(s.current_lap);
+
+        match self.last {
+            None => self.lap_start_s = t,
+            // the game started over, a new session or back to the menu,
+            // likely after a gap in the packets
+            Some(_) if counter.is_some_and(|n| n < self.lap) => {
+                self.lap = s.current_lap;
+                self.distance_m = 0.0;
+                self.lap_start_s = t;
+                self.full_lap = false;
+                self.path.clear();
+                self.hint = None;
+            }
+            Some((prev, t0, v0)) => {
+                let dt = t - t0;
+                if dt > 0.0 && dt <= MAX_GAP_S {
+                    self.advance(prev, pos, t0, dt, (v0 + speed) / 2.0 * dt, counter);
+                } else {
+                    // the path has a hole in it; don't keep it as the track
+                    self.full_lap = false;
+                }
+            }
+        }
+        self.last = Some((pos, t, speed));
+
+        s.current_lap = self.lap.max(1);
+        s.lap_distance_m = self.distance_m as f32;
+        s.current_lap_time_s = (t - self.lap_start_s) as f32;
+        if !self.counted {
+            s.last_lap_time_s = self.last_lap_s as f32;
+        }
+    }
+
+    /// Move on from `prev` to `pos`, `travelled` metres in `dt` seconds,
+    /// with the game's lap `counter` if it counts laps.
+    fn advance(&mut self, prev: (f64, f64), pos: (f64, f64), t0: f64, dt: f64, travelled: f64, counter: Option<u32>) {
+        let (dx, dy) = (pos.0 - prev.0, pos.1 - prev.1);
+        let step = dx.hypot(dy);
+        let dir = (step > 0.0).then(|| (dx / step, dy / step));
+        let crossing = match (counter, self.gate) {
+            // the game counted a lap; the gate only places it between the
+            // samples, and starts halfway between the first two counted
+            (Some(n), Some(mut gate)) if n > self.lap => {
+                let f = gate.counted(prev, pos);
+                self.gate = Some(gate);
+                Some(f)
+            }
+            (Some(n), None) if n > self.lap => {
+                self.gate = dir.map(|dir| Gate { at: ((prev.0 + pos.0) / 2.0, (prev.1 + pos.1) / 2.0), dir });
+                Some(if self.gate.is_some() { 0.5 } else { 1.0 })
+            }
+            (Some(_), _) => None,
+            (None, Some(gate)) => gate.crossing(prev, pos).filter(|_| self.distance_m + travelled >= MIN_LAP_M),
+            (None, None) => {
+                self.distance_m += travelled;
+                if let Some(dir) = dir.filter(|_| travelled / dt >= MOVING_MPS) {
+                    // the car is under way: the gate goes here and the first
+                    // lap starts on it
+                    self.gate = Some(Gate { at: pos, dir });
+                    self.lap = 1;
+                    self.distance_m = 0.0;
+                    self.lap_start_s = t0 + dt;
+                    self.full_lap = true;
+                    self.record(pos);
+                }
+                return;
+            }
+        };
+
+        if let Some(f) = crossing {
+            let crossed_s = t0 + f * dt;
+            let at = (prev.0 + f * dx, prev.1 + f * dy);
+            self.distance_m += f * travelled;
+            self.finish_lap(at, crossed_s, counter.unwrap_or(self.lap + 1));
+            self.distance_m = (1.0 - f) * travelled;
+            self.hint = (!self.track.is_empty()).then_some(0);
+            self.record(pos);
+            return;
+        }
+
+        self.distance_m = match self.project(pos) {
+            Some(d) => d,
+            None => self.distance_m + travelled,
+        };
+        self.record(pos);
+    }
+
+    /// Close the lap in progress on the line at `at`, crossed at
+    /// `crossed_s`, and start lap `next`.
+    fn finish_lap(&mut self, at: (f64, f64), crossed_s: f64, next: u32) {
+        if self.track.is_empty() && self.full_lap {
+            self.path.push(PathPoint { x: at.0, y: at.1, distance_m: self.distance_m });
+            self.track = std::mem::take(&mut self.path);
+        }
+        self.path.clear();
+        self.lap = next;
+        self.last_lap_s = crossed_s - self.lap_start_s;
+        self.lap_start_s = crossed_s;
+        self.full_lap = true;
+        if self.track.is_empty() {
+            self.path.push(PathPoint { x: at.0, y: at.1, distance_m: 0.0 });
+        }
+    }
+
+    /// Add `pos` to the path of the lap in progress while there's no track
+    /// kept yet.
+    fn record(&mut self, pos: (f64, f64)) {
+        if !self.track.is_empty() {
+            return;
+        }
+        let far_enough = self.path.last().is_none_or(|p| self.distance_m - p.distance_m >= PATH_STEP_M);
+        if far_enough {
+            self.path.push(PathPoint { x: pos.0, y: pos.1, distance_m: self.distance_m });
+        }
+    }
+
+    /// Distance along the kept track at the point nearest `pos`, searched
+    /// around the last match; `None` without a track or off it.
+    fn project(&mut self, pos: (f64, f64)) -> Option<f64> {
+        if self.track.len() < 2 {
+            return None;
+        }
+        let (from, to) = match self.hint {
+            Some(k) => (k.saturating_sub(SEARCH_BEHIND), (k + SEARCH_AHEAD).min(self.track.len() - 1)),
+            // lost: search the whole track
+            None => (0, self.track.len() - 1),
+        };
+        let mut best: Option<(f64, usize, f64)> = None;
+        for i in from..to {
+            let (a, b) = (self.track[i], self.track[i + 1]);
+            let (sx, sy) = (b.x - a.x, b.y - a.y);
+            let len2 = sx * sx + sy * sy;
+            let f = if len2 > 0.0 { (((pos.0 - a.x) * sx + (pos.1 - a.y) * sy) / len2).clamp(0.0, 1.0) } else { 0.0 };
+            let off = (a.x + f * sx - pos.0).hypot(a.y + f * sy - pos.1);
+            if best.is_none_or(|(o, _, _)| off < o) {
+                best = Some((off, i, a.distance_m + f * (b.distance_m - a.distance_m)));
+            }
+        }
+        let (off, i, d) = best?;
+        if off > OFF_PATH_M {
+            self.hint = None;
+            return None;
+        }
+        self.hint = Some(i);
+        Some(d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use delta_ingest_core::{Game, Phase};
+
+    const LAP_M: f64 = 1000.0;
+    const HZ: f64 = 60.0;
+    /// 47 m/s, so no sample lands on the line.
+    const V: f64 = 47.0;
+
+    /// Where the car is `m` round a 1 km circle, the line at `m` = 0.
+    fn at(m: f64) -> (f64, f64) {
+        let r = LAP_M / std::f64::consts::TAU;
+        let a = m / r;
+        (r * a.sin(), r * (1.0 - a.cos()))
+    }
+
+    fn sample(t: f64, m: f64, v: f64, counted: bool) -> TelemetrySample {
+        let (x, y) = at(m);
+        TelemetrySample {
+            game: Game::GT7,
+            car_id: "player:0".into(),
+            session_uid: "gt7".into(),
+            frame: (t * HZ).round() as u64,
+            sim_time_s: t,
+            speed_mps: v as f32,
+            throttle: 1.0,
+            brake: 0.0,
+            gear: 4,
+            engine_rpm: 7000.0,
+            world_pos_x: x as f32,
+            world_pos_y: y as f32,
+            world_pos_z: 0.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            lap_distance_m: 0.0,
+            current_lap: if counted { (m / LAP_M).floor() as u32 + 1 } else { 0 },
+            current_lap_time_s: 0.0,
+            last_lap_time_s: 0.0,
+            best_lap_time_s: 0.0,
+            fuel_l: 0.0,
+            fuel_capacity_l: 0.0,
+            rev_limiter: false,
+            phase: Phase::Driving,
+        }
+    }
+
+    /// `n` samples at `V` from `m0`, the first at `t0`; with the game's
+    /// lap counter if `counted`.
+    fn drive(t0: f64, m0: f64, n: usize, counted: bool) -> Vec<TelemetrySample> {
+        (0..n).map(|i| sample(t0 + i as f64 / HZ, m0 + V * i as f64 / HZ, V, counted)).collect()
+    }
+
+    fn track(tracker: &mut LapTracker, samples: &mut [TelemetrySample]) {
+        samples.iter_mut().for_each(|s| tracker.update(s));
+    }
+
+    /// Samples `m` apart along the circle from each (sample, `m`) pair.
+    fn with_m(samples: &[TelemetrySample], m0: f64) -> impl Iterator<Item = (&TelemetrySample, f64)> {
+        samples.iter().enumerate().map(move |(i, s)| (s, m0 + V * i as f64 / HZ))
+    }
+
+    #[test]
+    fn without_a_counter_laps_start_where_the_car_got_moving() {
+        // a second stood still 100 m round, then 3.5 laps
+        let mut still: Vec<_> = (0..60).map(|i| sample(i as f64 / HZ, 100.0, 0.0, false)).collect();
+        let mut going = drive(1.0, 100.0, (3.5 * LAP_M / V * HZ) as usize, false);
+        let mut tracker = LapTracker::new();
+        track(&mut tracker, &mut still);
+        track(&mut tracker, &mut going);
+        assert!(still.iter().all(|s| s.current_lap == 1 && s.lap_distance_m == 0.0));
+
+        // the gate is at the first sample moving
+        let gate_m = 100.0 + V / HZ;
+        for (s, m) in with_m(&going, 100.0).skip(1) {
+            let into = m - gate_m;
+            assert_eq!(s.current_lap, (into / LAP_M).floor() as u32 + 1, "{m}");
+            // lap 1 integrated from speed, the rest projected onto its path
+            assert!((s.lap_distance_m as f64 - into.rem_euclid(LAP_M)).abs() < 0.05, "{m}: {}", s.lap_distance_m);
+            assert!((s.current_lap_time_s as f64 - into.rem_euclid(LAP_M) / V).abs() < 1e-3, "{m}");
+        }
+        // each crossing interpolated between the samples either side
+        assert!((going.last().unwrap().last_lap_time_s as f64 - LAP_M / V).abs() < 1e-3);
+        assert!((tracker.track_length_m().unwrap() - LAP_M).abs() < 0.05);
+    }
+
+    #[test]
+    fn laps_follow_the_game_counter_onto_its_line() {
+        let mut samples = drive(0.0, 100.0, (3.5 * LAP_M / V * HZ) as usize, true);
+        let mut tracker = LapTracker::new();
+        track(&mut tracker, &mut samples);
+        // the first count puts the gate within half a step of the line and
+        // later ones close in; the lap from 100 m isn't whole
+        let half_step = V / HZ / 2.0;
+        for (s, m) in with_m(&samples, 100.0).filter(|(_, m)| *m >= LAP_M) {
+            assert_eq!(s.current_lap, (m / LAP_M).floor() as u32 + 1);
+            assert!((s.lap_distance_m as f64 - m % LAP_M).abs() <= half_step + 0.05, "{m}: {}", s.lap_distance_m);
+        }
+        // lap 2 went gate to gate, so its path is kept for lap 3
+        assert!((tracker.track_length_m().unwrap() - LAP_M).abs() <= half_step);
+        // off the line by the same amount on lap 3, projected, as on lap 2
+        let off: Vec<f64> = with_m(&samples, 100.0)
+            .filter(|(_, m)| (LAP_M + 50.0..3.0 * LAP_M - 50.0).contains(m) && (50.0..950.0).contains(&(m % LAP_M)))
+            .map(|(s, m)| s.lap_distance_m as f64 - m % LAP_M)
+            .collect();
+        let (lo, hi) = off.iter().fold((f64::MAX, f64::MIN), |(lo, hi), &o| (lo.min(o), hi.max(o)));
+        assert!(hi - lo < 0.05, "{lo} to {hi}");
+    }
+
+    #[test]
+    fn reversing_through_the_gate_counts_no_lap() {
+        let mut tracker = LapTracker::new();
+        let mut t = 0.0;
+        let mut m = 0.0;
+        let mut step = |tracker: &mut LapTracker, dm: f64| {
+            t += 1.0 / HZ;
+            m += dm;
+            let mut s = sample(t, m, dm.abs() * HZ, false);
+            tracker.update(&mut s);
+            s
+        };
+        // 20 m on past the gate, back 40 m through it and forward again
+        let forward: Vec<_> = (0..30).map(|_| step(&mut tracker, 1.0)).collect();
+        let back: Vec<_> = (0..40).map(|_| step(&mut tracker, -1.0)).collect();
+        let again: Vec<_> = (0..60).map(|_| step(&mut tracker, 1.0)).collect();
+        assert!(forward.iter().chain(&back).chain(&again).all(|s| s.current_lap == 1));
+        // then a whole lap does count
+        let round: Vec<_> = (0..(LAP_M as usize)).map(|_| step(&mut tracker, 1.0)).collect();
+        assert_eq!(round.last().unwrap().current_lap, 2);
+    }
+
+    #[test]
+    fn off_the_kept_path_distance_is_integrated() {
+        let mut tracker = LapTracker::new();
+        // lap 2 is the first counted from the line to the line, and kept;
+        // then part of lap 3 is off it
+        let n = (3.0 * LAP_M / V * HZ) as usize;
+        let mut samples = drive(0.0, 0.0, n, true);
+        let off = |m: f64| (2.0 * LAP_M + 300.0..2.0 * LAP_M + 400.0).contains(&m);
+        for (i, s) in samples.iter_mut().enumerate() {
+            let m = V * i as f64 / HZ;
+            if off(m) {
+                // some 50 m wide of the track, and going half as fast as
+                // the positions say
+                let (x, y) = at(m);
+                (s.world_pos_x, s.world_pos_y) = ((x * 1.2) as f32, (y * 1.2) as f32);
+                s.speed_mps = (V / 2.0) as f32;
+            }
+        }
+        track(&mut tracker, &mut samples);
+        assert!(tracker.track_length_m().is_some());
+
+        let ms: Vec<f64> = (0..n).map(|i| V * i as f64 / HZ).collect();
+        for i in 1..n {
+            let (s, prev) = (&samples[i], &samples[i - 1]);
+            if off(ms[i]) && off(ms[i - 1]) {
+                let travelled = (s.speed_mps + prev.speed_mps) as f64 / 2.0 / HZ;
+                assert!(((s.lap_distance_m - prev.lap_distance_m) as f64 - travelled).abs() < 1e-3, "{}", ms[i]);
+            } else if ms[i] > 2.0 * LAP_M + 400.0 {
+                // back on it, projected again
+                assert!((s.lap_distance_m as f64 - ms[i] % LAP_M).abs() < 0.5, "{}: {}", ms[i], s.lap_distance_m);
+            }
+        }
+    }
+
+    #[test]
+    fn the_counter_starting_over_starts_the_lap_over() {
+        let mut tracker = LapTracker::new();
+        let mut samples = drive(0.0, 0.0, (2.5 * LAP_M / V * HZ) as usize, true);
+        track(&mut tracker, &mut samples);
+        assert_eq!(samples.last().unwrap().current_lap, 3);
+
+        // a new session a few seconds later, on lap 1 again part way round
+        let mut s = sample(60.0, 300.0, V, true);
+        tracker.update(&mut s);
+        assert_eq!((s.current_lap, s.lap_distance_m, s.current_lap_time_s), (1, 0.0, 0.0));
+    }
+}
@@ -1,12 +1,23 @@
 use anyhow::Context;
 use tokio::{net::UdpSocket, time};
-use std::time::Duration;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::Serialize;
 
 use delta_ingest_core::{*, Game as GameId};
+use delta_ingest_core::dedup::FrameDedup;
+use delta_ingest_core::diag::IngestCounters;
 use salsa20::cipher::{KeyIvInit, StreamCipher};
 use salsa20::Salsa20;
-use byteorder::{LittleEndian, ReadBytesExt};
-use std::io::Cursor;
+use std::mem::{offset_of, size_of};
+use zerocopy::little_endian::{F32, I16, I32, U16, U32};
+use zerocopy::{FromBytes, Immutable, KnownLayout, Unaligned};
+
+pub mod lap;
+
+use lap::LapTracker;
 
 #[derive(Clone, Debug)]
 pub struct GT7Config {
@@ -28,18 +39,21 @@ impl Default for GT7Config {
     }
 }
 
-pub struct GT7Source { cfg: GT7Config }
-impl GT7Source { pub fn new(cfg: GT7Config) -> Self { Self { cfg } } }
+/// Port the console takes heartbeats on.
+const CONSOLE_PORT: u16 = 33740;
+
+pub struct GT7Source { cfg: GT7Config, counters: Arc<IngestCounters> }
+impl GT7Source { pub fn new(cfg: GT7Config) -> Self { Self { cfg, counters: Arc::default() } } }
 
 #[async_trait::async_trait]
 impl TelemetrySource for GT7Source {
     async fn run(&self, tx: TelemetryTx) -> Result<(), IngestError> {
         let socket = UdpSocket::bind(&self.cfg.bind_addr)
             .await
-            .with_context(|| format!("bind {}", self.cfg.bind_addr))?;
+            .map_err(|e| IngestError::Other(port::diagnose(&self.cfg.bind_addr, &e).into()))?;
 
         // We "connect" the UDP socket so send()/recv() go to/from this peer by default.
-        socket.connect((&*self.cfg.console_ip, 33740))
+        socket.connect((&*self.cfg.console_ip, CONSOLE_PORT))
             .await
             .with_context(|| format!("connect {}", self.cfg.console_ip))?;
 
@@ -52,6 +66,8 @@ impl TelemetrySource for GT7Source {
         hb_interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
 
         let mut buf = vec![0u8; 2048];
+        let mut laps = LapTracker::new();
+        let mut dedup = FrameDedup::new();
 
         loop {
             tokio::select! {
@@ -61,7 +77,19 @@ impl TelemetrySource for GT7Source {
                 recv = socket.recv(&mut buf) => {
                     match recv {
                         Ok(len) => {
-                            if let Some(sample) = decrypt_and_parse(&buf[..len], variant) {
+                            let started = Instant::now();
+                            let parsed = decrypt_and_parse(&buf[..len], variant);
+                            self.counters.record(len, parsed.is_some(), started.elapsed());
+                            // every GT7 packet carries a sample, so any miss is a bad packet
+                            if parsed.is_none() {
+                                self.counters.record_malformed();
+                            }
+                            // a resent or late packet adds nothing
+                            if let Some(mut sample) = parsed.filter(|s| dedup.fresh(s)) {
+                                // a replay or the menus don't count towards laps
+                                if sample.phase != Phase::Menu {
+                                    laps.update(&mut sample);
+                                }
                                 if tx.send(sample).is_err() {
                                     // receiver dropped; time to stop
                                     break;
@@ -79,6 +107,54 @@ impl TelemetrySource for GT7Source {
 
         Ok(())
     }
+
+    fn counters(&self) -> Option<Arc<IngestCounters>> {
+        Some(self.counters.clone())
+    }
+}
+
+/// A console that answered a discovery heartbeat.
+#[derive(Clone, Debug, Serialize)]
+pub struct DiscoveredConsole {
+    pub ip: String,
+    /// Reverse DNS name, when the network has one.
+    pub hostname: Option<String>,
+    /// From the heartbeat to the first telemetry packet.
+    pub latency_ms: f64,
+}
+
+/// Broadcast a heartbeat on the local network and collect the consoles that
+/// start sending telemetry to `bind_port` within `wait`, fastest first.
+/// Fails when `bind_port` is taken, e.g. by a running [`GT7Source`].
+pub async fn discover_consoles(bind_port: u16, wait: Duration) -> anyhow::Result<Vec<DiscoveredConsole>> {
+    let addr = format!("0.0.0.0:{bind_port}");
+    let socket = UdpSocket::bind(&addr).await.map_err(|e| port::diagnose(&addr, &e))?;
+    socket.set_broadcast(true)?;
+    let variant = normalise_variant('A');
+    let sent = Instant::now();
+    socket.send_to(&[variant as u8], (Ipv4Addr::BROADCAST, CONSOLE_PORT)).await.context("broadcast heartbeat")?;
+
+    let mut found: HashMap<IpAddr, f64> = HashMap::new();
+    let mut buf = vec![0u8; 2048];
+    let deadline = time::Instant::now() + wait;
+    while let Ok(recv) = time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, from) = recv?;
+        // anything else on the port is not a console
+        if decrypt_and_parse(&buf[..len], variant).is_some() {
+            found.entry(from.ip()).or_insert_with(|| sent.elapsed().as_secs_f64() * 1000.0);
+        }
+    }
+
+    let mut consoles = Vec::with_capacity(found.len());
+    for (ip, latency_ms) in found {
+        let hostname = tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&ip).ok())
+            .await
+            .unwrap_or_default()
+            .filter(|h| *h != ip.to_string());
+        consoles.push(DiscoveredConsole { ip: ip.to_string(), hostname, latency_ms });
+    }
+    consoles.sort_by(|a, b| a.latency_ms.total_cmp(&b.latency_ms));
+    Ok(consoles)
 }
 
 #[inline]
@@ -89,87 +165,269 @@ fn normalise_variant(v: char) -> char {
     }
 }
 
-// Encryption per community docs: Salsa20 with fixed key string and per-packet nonce
-// bytes (0x40..0x47) whose first 4 bytes are XOR'd with a variant-specific constant.
-fn decrypt_and_parse(pkt: &[u8], variant: char) -> Option<TelemetrySample> {
-    // Header needs at least up to nonce at 0x40..0x47 and some payload.
-    if pkt.len() < 0x48 { return None; }
+// Key: the first 32 bytes of "Simulator Interface Packet GT7 ver 0.0"
+const KEY: [u8; 32] = *b"Simulator Interface Packet GT7 v";
+
+/// Largest packet taken, more than any variant sends (the '~' packet is
+/// 344 bytes).
+const MAX_PACKET: usize = 0x200;
+
+/// Smallest packet taken: variant 'A', which the others extend.
+const MIN_PACKET: usize = size_of::<Packet>();
+
+/// "0S7G" at the start of every decrypted packet.
+const MAGIC: u32 = 0x4737_5330;
+
+/// Start of the nonce, sent in the clear over what decrypts to garbage.
+const IV_AT: usize = 0x40;
 
-    // Key (32 bytes) — "Simulator Interface Packet GT7 ver 0.0" (padded/truncated)
-    let mut key = [0u8; 32];
-    let key_str = b"Simulator Interface Packet GT7 ver 0.0";
-    let copy_len = key_str.len().min(key.len());
-    key[..copy_len].copy_from_slice(&key_str[..copy_len]);
+/// GT7 sends a packet every frame, 60 times a second.
+const PACKET_HZ: f64 = 60.0;
+
+/// The decrypted 'A' packet, as documented by the community
+/// (<https://github.com/Nenkai/PDTools>); every field is little-endian
+/// and unaligned, so the view is valid at any offset.
+#[repr(C, packed)]
+#[derive(FromBytes, KnownLayout, Immutable, Unaligned)]
+struct Packet {
+    magic: U32,
+    pos: [F32; 3],
+    _velocity: [F32; 3],
+    // vector part of the car's rotation quaternion, `rotation_w` its scalar
+    rotation: [F32; 3],
+    rotation_w: F32,
+    _angular_velocity: [F32; 3],
+    _body_height: F32,
+    engine_rpm: F32,
+    _iv: [u8; 4],
+    // litres, or charge for electric cars
+    fuel: F32,
+    fuel_capacity: F32,
+    speed_mps: F32,
+    _boost: F32,
+    _oil_pressure: F32,
+    _water_temp: F32,
+    _oil_temp: F32,
+    _tyre_temps: [F32; 4],
+    // counts up by one a packet
+    packet_id: I32,
+    current_lap: I16,
+    _total_laps: I16,
+    // lap times are -1 until there is one
+    best_lap_ms: I32,
+    last_lap_ms: I32,
+    _time_of_day_ms: I32,
+    _start_position: I16,
+    _cars: I16,
+    _min_alert_rpm: I16,
+    _max_alert_rpm: I16,
+    _max_speed: I16,
+    flags: U16,
+    // current gear in the low nibble, suggested in the high
+    gears: u8,
+    throttle: u8,
+    brake: u8,
+    _unknown: u8,
+    _road_plane: [F32; 4],
+    _wheel_rps: [F32; 4],
+    _tyre_radius: [F32; 4],
+    _suspension_height: [F32; 4],
+    _unknown2: [F32; 8],
+    _clutch: [F32; 3],
+    _transmission_top_speed: F32,
+    _gear_ratios: [F32; 8],
+    _car_code: I32,
+}
+
+const _: () = {
+    assert!(size_of::<Packet>() == 0x128);
+    assert!(offset_of!(Packet, engine_rpm) == 0x3C);
+    assert!(offset_of!(Packet, fuel) == 0x44);
+    assert!(offset_of!(Packet, fuel_capacity) == 0x48);
+    assert!(offset_of!(Packet, speed_mps) == 0x4C);
+    assert!(offset_of!(Packet, packet_id) == 0x70);
+    assert!(offset_of!(Packet, current_lap) == 0x74);
+    assert!(offset_of!(Packet, best_lap_ms) == 0x78);
+    assert!(offset_of!(Packet, last_lap_ms) == 0x7C);
+    assert!(offset_of!(Packet, flags) == 0x8E);
+    assert!(offset_of!(Packet, gears) == 0x90);
+};
+
+// Bits of `Packet::flags`
+const FLAG_ON_TRACK: u16 = 1 << 0;
+const FLAG_PAUSED: u16 = 1 << 1;
+const FLAG_LOADING: u16 = 1 << 2;
+// set while the rev limiter warning flashes
+const FLAG_REV_LIMITER: u16 = 1 << 5;
+
+/// What the nonce's first word is XORed with for each variant.
+fn iv_mask(variant: char) -> u32 {
+    match variant {
+        'B' => 0xDEAD_BEEF,
+        '~' => 0x55FA_BB4F,
+        _ => 0xDEAD_BEAF,
+    }
+}
 
-    // Nonce (8 bytes) at 0x40..0x47; first 4 bytes XORed with variant constant
+/// The whole packet decrypted in place, or `None` if it isn't one.
+/// Salsa20 with a fixed key; the nonce is the word at `IV_AT` XORed with
+/// the variant's mask, then the word itself.
+fn decrypt(pkt: &[u8], variant: char, buf: &mut [u8; MAX_PACKET]) -> Option<usize> {
+    if pkt.len() < MIN_PACKET || pkt.len() > MAX_PACKET {
+        return None;
+    }
+    let iv: [u8; 4] = pkt[IV_AT..IV_AT + 4].try_into().ok()?;
     let mut nonce = [0u8; 8];
-    nonce.copy_from_slice(&pkt[0x40..0x48]);
-    let xconst: u32 = match variant {
-        'A' => 0xDEAD_BEAF, // community value for A (placeholder if you have the exact one)
-        'B' => 0xDEAD_BEEF, // community value for B
-        _   => 0x545F_4C7E, // "~" fallback placeholder
-    };
-    let mut first4 = u32::from_le_bytes([nonce[0], nonce[1], nonce[2], nonce[3]]);
-    first4 ^= xconst;
-    nonce[0..4].copy_from_slice(&first4.to_le_bytes());
-
-    // Decrypt payload after 0x48
-    if pkt.len() <= 0x48 { return None; }
-    let mut payload = pkt[0x48..].to_vec();
-
-    // Salsa20 uses 32-byte key + 8-byte nonce
-    let mut cipher = Salsa20::new((&key).into(), (&nonce).into());
-    cipher.apply_keystream(&mut payload);
-
-    // Packet A structure (approx 296 bytes). Read a minimal set with bounds checks.
-    if payload.len() < 0x60 { return None; }
-    let mut c = Cursor::new(&payload);
-
-    let _seq   = c.read_u32::<LittleEndian>().ok()?;
-    let _magic = c.read_u32::<LittleEndian>().ok()?;
-    let time_ms = c.read_u32::<LittleEndian>().ok()?;
-    let _unknown = c.read_u32::<LittleEndian>().ok()?; // skip
-
-    // Positions and orientation (x,y,z,yaw,pitch,roll)
-    let pos_x = c.read_f32::<LittleEndian>().ok()?;
-    let pos_y = c.read_f32::<LittleEndian>().ok()?;
-    let pos_z = c.read_f32::<LittleEndian>().ok()?;
-    let yaw   = c.read_f32::<LittleEndian>().ok()?;
-    let pitch = c.read_f32::<LittleEndian>().ok()?;
-    let roll  = c.read_f32::<LittleEndian>().ok()?;
-
-    // Dynamics block starting at 0x40
-    const DYN_OFF: usize = 0x40;
-    if payload.len() < DYN_OFF + 0x14 { return None; }
-    let mut d = Cursor::new(&payload[DYN_OFF..]);
-    let speed_kmh = d.read_f32::<LittleEndian>().ok()?;
-    let engine_rpm = d.read_f32::<LittleEndian>().ok()?;
-    let throttle = d.read_f32::<LittleEndian>().ok()?;
-    let brake    = d.read_f32::<LittleEndian>().ok()?;
-    let gear_i32 = d.read_i32::<LittleEndian>().ok()?;
-
-    Some(TelemetrySample {
+    nonce[..4].copy_from_slice(&(u32::from_le_bytes(iv) ^ iv_mask(variant)).to_le_bytes());
+    nonce[4..].copy_from_slice(&iv);
+
+    let mut cipher = Salsa20::new((&KEY).into(), (&nonce).into());
+    cipher.apply_keystream_b2b(pkt, &mut buf[..pkt.len()]).ok()?;
+    let (magic, _) = U32::ref_from_prefix(&buf[..]).ok()?;
+    (magic.get() == MAGIC).then_some(pkt.len())
+}
+
+/// Pitch, heading and roll (radians) of the unit quaternion
+/// `x, y, z, w` in GT7's Y-up frame, taken yaw first.
+fn euler([x, y, z]: [f32; 3], w: f32) -> (f32, f32, f32) {
+    let pitch = (2.0 * (w * x - y * z)).clamp(-1.0, 1.0).asin();
+    let yaw = (2.0 * (w * y + x * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+    let roll = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (x * x + z * z));
+    (pitch, yaw, roll)
+}
+
+fn decrypt_and_parse(pkt: &[u8], variant: char) -> Option<TelemetrySample> {
+    let mut buf = [0u8; MAX_PACKET];
+    let len = decrypt(pkt, variant, &mut buf)?;
+    let (p, _) = Packet::ref_from_prefix(&buf[..len]).ok()?;
+    let packet_id = p.packet_id.get().max(0) as u64;
+    let [pos_x, pos_y, pos_z] = p.pos.map(|v| v.get());
+    let (pitch, yaw, roll) = euler(p.rotation.map(|v| v.get()), p.rotation_w.get());
+    let flags = p.flags.get();
+
+    let mut sample = TelemetrySample {
         game: GameId::GT7,
         car_id: "player:0".into(),
         session_uid: "gt7".into(),
-        frame: time_ms as u64,
-        sim_time_s: (time_ms as f64) / 1000.0,
+        frame: packet_id,
+        sim_time_s: packet_id as f64 / PACKET_HZ,
 
-        speed_mps: speed_kmh / 3.6,
-        throttle,
-        brake,
-        gear: gear_i32 as i8,
-        engine_rpm,
+        speed_mps: p.speed_mps.get(),
+        throttle: p.throttle as f32 / 255.0,
+        brake: p.brake as f32 / 255.0,
+        gear: (p.gears & 0x0F) as i8,
+        engine_rpm: p.engine_rpm.get(),
 
         world_pos_x: pos_x,
         world_pos_y: pos_y,
         world_pos_z: pos_z,
         yaw, pitch, roll,
 
-        // Not present in this packet; can be derived in a higher layer if needed.
+        // Not present in this packet; the source fills them in with a LapTracker.
         lap_distance_m: 0.0,
-        current_lap: 0,
         current_lap_time_s: 0.0,
-        last_lap_time_s: 0.0,
-    })
+        current_lap: p.current_lap.get().max(0) as u32,
+        last_lap_time_s: lap_time_s(p.last_lap_ms.get()),
+        best_lap_time_s: lap_time_s(p.best_lap_ms.get()),
+
+        fuel_l: p.fuel.get(),
+        fuel_capacity_l: p.fuel_capacity.get(),
+        rev_limiter: flags & FLAG_REV_LIMITER != 0,
+
+        phase: if flags & FLAG_PAUSED != 0 {
+            Phase::Paused
+        } else if flags & FLAG_LOADING != 0 || flags & FLAG_ON_TRACK == 0 {
+            Phase::Menu
+        } else {
+            Phase::Driving
+        },
+    };
+    frame::to_common(&mut sample);
+    Some(sample)
+}
+
+/// Seconds from a packet lap time, 0 for none.
+fn lap_time_s(ms: i32) -> f32 {
+    if ms > 0 { ms as f32 / 1000.0 } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(b: &mut [u8], at: usize, v: &[u8]) {
+        b[at..at + v.len()].copy_from_slice(v);
+    }
+
+    /// An 'A' packet as the console sends it: `plain` encrypted with the
+    /// nonce from `iv`, then `iv` written over its slot in the clear.
+    fn encrypt(plain: &[u8], iv: u32) -> Vec<u8> {
+        let mut nonce = [0u8; 8];
+        nonce[..4].copy_from_slice(&(iv ^ iv_mask('A')).to_le_bytes());
+        nonce[4..].copy_from_slice(&iv.to_le_bytes());
+        let mut pkt = plain.to_vec();
+        Salsa20::new((&KEY).into(), (&nonce).into()).apply_keystream(&mut pkt);
+        put(&mut pkt, IV_AT, &iv.to_le_bytes());
+        pkt
+    }
+
+    fn plain() -> Vec<u8> {
+        let mut b = vec![0u8; 0x128];
+        put(&mut b, 0x00, &MAGIC.to_le_bytes());
+        put(&mut b, 0x04, &10.0f32.to_le_bytes());
+        put(&mut b, 0x08, &2.0f32.to_le_bytes());
+        put(&mut b, 0x0C, &(-30.0f32).to_le_bytes());
+        put(&mut b, 0x28, &1.0f32.to_le_bytes()); // no rotation
+        put(&mut b, 0x3C, &6500.0f32.to_le_bytes());
+        put(&mut b, 0x44, &42.5f32.to_le_bytes());
+        put(&mut b, 0x48, &100.0f32.to_le_bytes());
+        put(&mut b, 0x4C, &50.0f32.to_le_bytes());
+        put(&mut b, 0x70, &600i32.to_le_bytes());
+        put(&mut b, 0x74, &3i16.to_le_bytes());
+        put(&mut b, 0x76, &5i16.to_le_bytes());
+        put(&mut b, 0x78, &95_123i32.to_le_bytes());
+        put(&mut b, 0x7C, &96_500i32.to_le_bytes());
+        put(&mut b, 0x8E, &(FLAG_ON_TRACK | FLAG_REV_LIMITER).to_le_bytes());
+        b[0x90] = 0x54; // 4th, 5th suggested
+        b[0x91] = 255;
+        b[0x92] = 51;
+        b
+    }
+
+    #[test]
+    fn decrypts_and_reads_a_packet() {
+        let pkt = encrypt(&plain(), 0x1234_5678);
+        let s = decrypt_and_parse(&pkt, 'A').unwrap();
+        assert_eq!(s.frame, 600);
+        assert_eq!(s.sim_time_s, 10.0);
+        assert_eq!(s.speed_mps, 50.0);
+        assert_eq!(s.engine_rpm, 6500.0);
+        assert_eq!(s.gear, 4);
+        assert_eq!((s.throttle, s.brake), (1.0, 0.2));
+        assert_eq!(s.current_lap, 3);
+        assert_eq!(s.best_lap_time_s, 95.123);
+        assert_eq!(s.last_lap_time_s, 96.5);
+        assert_eq!((s.fuel_l, s.fuel_capacity_l), (42.5, 100.0));
+        assert!(s.rev_limiter);
+        assert_eq!(s.phase, Phase::Driving);
+        // game (10, 2, -30) is east 10, north 30, up 2
+        assert_eq!((s.world_pos_x, s.world_pos_y, s.world_pos_z), (10.0, 30.0, 2.0));
+    }
+
+    #[test]
+    fn reads_pause_and_menu_flags() {
+        let mut b = plain();
+        put(&mut b, 0x8E, &(FLAG_ON_TRACK | FLAG_PAUSED).to_le_bytes());
+        assert_eq!(decrypt_and_parse(&encrypt(&b, 7), 'A').unwrap().phase, Phase::Paused);
+        put(&mut b, 0x8E, &0u16.to_le_bytes());
+        assert_eq!(decrypt_and_parse(&encrypt(&b, 7), 'A').unwrap().phase, Phase::Menu);
+    }
+
+    #[test]
+    fn rejects_a_packet_that_does_not_decrypt() {
+        let pkt = encrypt(&plain(), 0x1234_5678);
+        // the wrong variant's nonce decrypts to garbage
+        assert!(decrypt_and_parse(&pkt, 'B').is_none());
+        assert!(decrypt_and_parse(&pkt[..0x100], 'A').is_none());
+    }
 }
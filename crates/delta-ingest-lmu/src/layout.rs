@@ -0,0 +1,450 @@
+//! rF2SharedMemoryMapPlugin's telemetry buffer, and its scoring buffer up
+//! to the vehicles, field for field as `rF2State.h` declares them. The plugin builds with
+//! `#pragma pack(push, 4)`, so doubles sit on 4-byte boundaries; `long` is
+//! 32 bits on Windows.
+//!
+//! Every field is kept, used or not, so the ones read land where the plugin
+//! writes them. The `const` checks below pin the size and the offsets a
+//! mistake would most likely shift, and fail the build on every platform
+//! if the layout drifts.
+
+#![allow(non_snake_case, dead_code)]
+
+use std::mem::{offset_of, size_of};
+
+/// Vehicles the plugin's buffers have room for.
+pub const MAX_MAPPED_VEHICLES: usize = 128;
+
+#[repr(C, packed(4))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RF2Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[repr(C, packed(4))]
+#[derive(Clone, Copy)]
+pub struct RF2Wheel {
+    pub mSuspensionDeflection: f64,
+    pub mRideHeight: f64,
+    pub mSuspForce: f64,
+    pub mBrakeTemp: f64,
+    pub mBrakePressure: f64,
+    pub mRotation: f64,
+    pub mLateralPatchVel: f64,
+    pub mLongitudinalPatchVel: f64,
+    pub mLateralGroundVel: f64,
+    pub mLongitudinalGroundVel: f64,
+    pub mCamber: f64,
+    pub mLateralForce: f64,
+    pub mLongitudinalForce: f64,
+    pub mTireLoad: f64,
+    pub mGripFract: f64,
+    pub mPressure: f64,
+    pub mTemperature: [f64; 3],
+    pub mWear: f64,
+    pub mTerrainName: [u8; 16],
+    pub mSurfaceType: u8,
+    pub mFlat: u8,
+    pub mDetached: u8,
+    pub mStaticUndeflectedRadius: u8,
+    pub mVerticalTireDeflection: f64,
+    pub mWheelYLocation: f64,
+    pub mToe: f64,
+    pub mTireCarcassTemperature: f64,
+    pub mTireInnerLayerTemperature: [f64; 3],
+    pub mExpansion: [u8; 24],
+}
+
+#[repr(C, packed(4))]
+#[derive(Clone, Copy)]
+pub struct RF2VehicleTelemetry {
+    // time
+    pub mID: i32,
+    pub mDeltaTime: f64,
+    pub mElapsedTime: f64,
+    pub mLapNumber: i32,
+    pub mLapStartET: f64,
+    pub mVehicleName: [u8; 64],
+    pub mTrackName: [u8; 64],
+
+    // position and derivatives
+    pub mPos: RF2Vec3,
+    pub mLocalVel: RF2Vec3,
+    pub mLocalAccel: RF2Vec3,
+
+    // orientation (rows of the rotation matrix) and derivatives
+    pub mOri: [RF2Vec3; 3],
+    pub mLocalRot: RF2Vec3,
+    pub mLocalRotAccel: RF2Vec3,
+
+    // vehicle status
+    pub mGear: i32,
+    pub mEngineRPM: f64,
+    pub mEngineWaterTemp: f64,
+    pub mEngineOilTemp: f64,
+    pub mClutchRPM: f64,
+
+    // driver input
+    pub mUnfilteredThrottle: f64,
+    pub mUnfilteredBrake: f64,
+    pub mUnfilteredSteering: f64,
+    pub mUnfilteredClutch: f64,
+
+    // filtered input
+    pub mFilteredThrottle: f64,
+    pub mFilteredBrake: f64,
+    pub mFilteredSteering: f64,
+    pub mFilteredClutch: f64,
+
+    pub mSteeringShaftTorque: f64,
+    pub mFront3rdDeflection: f64,
+    pub mRear3rdDeflection: f64,
+
+    // aerodynamics
+    pub mFrontWingHeight: f64,
+    pub mFrontRideHeight: f64,
+    pub mRearRideHeight: f64,
+    pub mDrag: f64,
+    pub mFrontDownforce: f64,
+    pub mRearDownforce: f64,
+
+    // state and damage
+    pub mFuel: f64,
+    pub mEngineMaxRPM: f64,
+    pub mScheduledStops: u8,
+    pub mOverheating: u8,
+    pub mDetached: u8,
+    pub mHeadlights: u8,
+    pub mDentSeverity: [u8; 8],
+    pub mLastImpactET: f64,
+    pub mLastImpactMagnitude: f64,
+    pub mLastImpactPos: RF2Vec3,
+
+    // expanded
+    pub mEngineTorque: f64,
+    pub mCurrentSector: i32,
+    pub mSpeedLimiter: u8,
+    pub mMaxGears: u8,
+    pub mFrontTireCompoundIndex: u8,
+    pub mRearTireCompoundIndex: u8,
+    pub mFuelCapacity: f64,
+    pub mFrontFlapActivated: u8,
+    pub mRearFlapActivated: u8,
+    pub mRearFlapLegalStatus: u8,
+    pub mIgnitionStarter: u8,
+    pub mFrontTireCompoundName: [u8; 18],
+    pub mRearTireCompoundName: [u8; 18],
+    pub mSpeedLimiterAvailable: u8,
+    pub mAntiStallActivated: u8,
+    pub mUnused: [u8; 2],
+    pub mVisualSteeringWheelRange: f32,
+    pub mRearBrakeBias: f64,
+    pub mTurboBoostPressure: f64,
+    pub mPhysicsToGraphicsOffset: [f32; 3],
+    pub mPhysicalSteeringWheelRange: f32,
+    pub mExpansion: [u8; 152],
+
+    // kept last in the plugin so it can grow
+    pub mWheels: [RF2Wheel; 4],
+}
+
+/// The buffer's header and its first vehicle; the rest of the
+/// [`MAX_MAPPED_VEHICLES`] follow, one `RF2VehicleTelemetry` each.
+#[repr(C, packed(4))]
+#[derive(Clone, Copy)]
+pub struct RF2Telemetry {
+    // equal when the plugin isn't part way through writing the buffer
+    pub mVersionUpdateBegin: u32,
+    pub mVersionUpdateEnd: u32,
+    pub mBytesUpdatedHint: i32,
+    pub mNumVehicles: i32,
+    pub mVehicle: RF2VehicleTelemetry,
+}
+
+/// Bytes of the telemetry buffer with room for every vehicle.
+pub const TELEMETRY_BUFFER_SIZE: usize =
+    size_of::<RF2Telemetry>() + (MAX_MAPPED_VEHICLES - 1) * size_of::<RF2VehicleTelemetry>();
+
+#[repr(C, packed(4))]
+#[derive(Clone, Copy)]
+pub struct RF2ScoringInfo {
+    pub mTrackName: [u8; 64],
+    pub mSession: i32,
+    pub mCurrentET: f64,
+    pub mEndET: f64,
+    pub mMaxLaps: i32,
+    pub mLapDist: f64,
+    // the plugin's results stream pointer
+    pub pointer1: [u8; 8],
+    pub mNumVehicles: i32,
+
+    pub mGamePhase: u8,
+    pub mYellowFlagState: i8,
+    pub mSectorFlag: [i8; 3],
+    pub mStartLight: u8,
+    pub mNumRedLights: u8,
+    pub mInRealtime: u8,
+    pub mPlayerName: [u8; 32],
+    pub mPlrFileName: [u8; 64],
+
+    // weather
+    pub mDarkCloud: f64,
+    pub mRaining: f64,
+    pub mAmbientTemp: f64,
+    pub mTrackTemp: f64,
+    pub mWind: RF2Vec3,
+    pub mMinPathWetness: f64,
+    pub mMaxPathWetness: f64,
+
+    // multiplayer
+    pub mGameMode: u8,
+    pub mIsPasswordProtected: u8,
+    pub mServerPort: u16,
+    pub mServerPublicIP: u32,
+    pub mMaxPlayers: i32,
+    pub mServerName: [u8; 32],
+    pub mStartET: f32,
+
+    pub mAvgPathWetness: f64,
+    pub mExpansion: [u8; 200],
+    // the plugin's vehicle scoring pointer
+    pub pointer2: [u8; 8],
+}
+
+#[repr(C, packed(4))]
+#[derive(Clone, Copy)]
+pub struct RF2VehicleScoring {
+    pub mID: i32,
+    pub mDriverName: [u8; 32],
+    pub mVehicleName: [u8; 64],
+    pub mTotalLaps: i16,
+    pub mSector: i8,
+    pub mFinishStatus: i8,
+    pub mLapDist: f64,
+    pub mPathLateral: f64,
+    pub mTrackEdge: f64,
+
+    // lap and sector times, -1 where there are none yet
+    pub mBestSector1: f64,
+    pub mBestSector2: f64,
+    pub mBestLapTime: f64,
+    pub mLastSector1: f64,
+    pub mLastSector2: f64,
+    pub mLastLapTime: f64,
+    pub mCurSector1: f64,
+    pub mCurSector2: f64,
+
+    pub mNumPitstops: i16,
+    pub mNumPenalties: i16,
+    pub mIsPlayer: u8,
+    pub mControl: i8,
+    pub mInPits: u8,
+    pub mPlace: u8,
+    pub mVehicleClass: [u8; 32],
+
+    pub mTimeBehindNext: f64,
+    pub mLapsBehindNext: i32,
+    pub mTimeBehindLeader: f64,
+    pub mLapsBehindLeader: i32,
+    pub mLapStartET: f64,
+
+    // position and derivatives, at scoring rate
+    pub mPos: RF2Vec3,
+    pub mLocalVel: RF2Vec3,
+    pub mLocalAccel: RF2Vec3,
+    pub mOri: [RF2Vec3; 3],
+    pub mLocalRot: RF2Vec3,
+    pub mLocalRotAccel: RF2Vec3,
+
+    pub mHeadlights: u8,
+    pub mPitState: u8,
+    pub mServerScored: u8,
+    pub mIndividualPhase: u8,
+    pub mQualification: i32,
+    pub mTimeIntoLap: f64,
+    pub mEstimatedLapTime: f64,
+    pub mPitGroup: [u8; 24],
+    pub mFlag: u8,
+    pub mUnderYellow: u8,
+    pub mCountLapFlag: u8,
+    pub mInGarageStall: u8,
+    pub mUpgradePack: [u8; 16],
+    pub mPitLapDist: f32,
+    pub mBestLapSector1: f32,
+    pub mBestLapSector2: f32,
+    pub mExpansion: [u8; 48],
+}
+
+#[repr(C, packed(4))]
+#[derive(Clone, Copy)]
+pub struct RF2Scoring {
+    pub mVersionUpdateBegin: u32,
+    pub mVersionUpdateEnd: u32,
+    pub mBytesUpdatedHint: i32,
+    pub mScoringInfo: RF2ScoringInfo,
+    pub mVehicles: [RF2VehicleScoring; MAX_MAPPED_VEHICLES],
+}
+
+const _: () = {
+    assert!(size_of::<RF2Vec3>() == 24);
+    assert!(size_of::<RF2Wheel>() == 260);
+    assert!(offset_of!(RF2Wheel, mTerrainName) == 160);
+    assert!(offset_of!(RF2Wheel, mVerticalTireDeflection) == 180);
+
+    assert!(size_of::<RF2VehicleTelemetry>() == 1888);
+    assert!(offset_of!(RF2VehicleTelemetry, mDeltaTime) == 4);
+    assert!(offset_of!(RF2VehicleTelemetry, mLapNumber) == 20);
+    assert!(offset_of!(RF2VehicleTelemetry, mLapStartET) == 24);
+    assert!(offset_of!(RF2VehicleTelemetry, mPos) == 160);
+    assert!(offset_of!(RF2VehicleTelemetry, mLocalVel) == 184);
+    assert!(offset_of!(RF2VehicleTelemetry, mOri) == 232);
+    assert!(offset_of!(RF2VehicleTelemetry, mGear) == 352);
+    assert!(offset_of!(RF2VehicleTelemetry, mEngineRPM) == 356);
+    assert!(offset_of!(RF2VehicleTelemetry, mUnfilteredThrottle) == 388);
+    assert!(offset_of!(RF2VehicleTelemetry, mFuel) == 524);
+    assert!(offset_of!(RF2VehicleTelemetry, mLastImpactET) == 552);
+    assert!(offset_of!(RF2VehicleTelemetry, mFuelCapacity) == 608);
+    assert!(offset_of!(RF2VehicleTelemetry, mRearBrakeBias) == 664);
+    assert!(offset_of!(RF2VehicleTelemetry, mExpansion) == 696);
+    assert!(offset_of!(RF2VehicleTelemetry, mWheels) == 848);
+
+    assert!(size_of::<RF2ScoringInfo>() == 548);
+    assert!(offset_of!(RF2ScoringInfo, mLapDist) == 88);
+    assert!(offset_of!(RF2ScoringInfo, mNumVehicles) == 104);
+    assert!(offset_of!(RF2ScoringInfo, mDarkCloud) == 212);
+    assert!(offset_of!(RF2ScoringInfo, mServerPort) == 286);
+    assert!(offset_of!(RF2ScoringInfo, mAvgPathWetness) == 332);
+
+    assert!(size_of::<RF2VehicleScoring>() == 584);
+    assert!(offset_of!(RF2VehicleScoring, mLapDist) == 104);
+    assert!(offset_of!(RF2VehicleScoring, mBestLapTime) == 144);
+    assert!(offset_of!(RF2VehicleScoring, mLastLapTime) == 168);
+    assert!(offset_of!(RF2VehicleScoring, mIsPlayer) == 196);
+    assert!(offset_of!(RF2VehicleScoring, mTimeBehindNext) == 232);
+    assert!(offset_of!(RF2VehicleScoring, mPos) == 264);
+    assert!(offset_of!(RF2VehicleScoring, mQualification) == 460);
+    assert!(offset_of!(RF2VehicleScoring, mPitLapDist) == 524);
+
+    assert!(offset_of!(RF2Scoring, mScoringInfo) == 12);
+    assert!(offset_of!(RF2Scoring, mVehicles) == 560);
+
+    assert!(offset_of!(RF2Telemetry, mNumVehicles) == 12);
+    assert!(offset_of!(RF2Telemetry, mVehicle) == 16);
+    assert!(size_of::<RF2Telemetry>() == 16 + 1888);
+};
+
+impl RF2Scoring {
+    /// The player's vehicle, if the game is showing one and the plugin
+    /// isn't part way through writing the buffer.
+    pub fn player(&self) -> Option<RF2VehicleScoring> {
+        if { self.mVersionUpdateBegin } != { self.mVersionUpdateEnd } {
+            return None;
+        }
+        let n = ({ self.mScoringInfo.mNumVehicles }.max(0) as usize).min(MAX_MAPPED_VEHICLES);
+        let vehicles = self.mVehicles;
+        vehicles[..n].iter().find(|v| v.mIsPlayer != 0).copied()
+    }
+}
+
+/// Seconds from a scoring lap time, 0 for none.
+pub fn lap_time_s(t: f64) -> f32 {
+    if t > 0.0 { t as f32 } else { 0.0 }
+}
+
+/// Where vehicle `i` starts in the telemetry buffer.
+pub const fn telemetry_vehicle_offset(i: usize) -> usize {
+    offset_of!(RF2Telemetry, mVehicle) + i * size_of::<RF2VehicleTelemetry>()
+}
+
+impl RF2VehicleTelemetry {
+    /// Heading, pitch and roll (radians) from the rotation matrix.
+    pub fn yaw_pitch_roll(&self) -> (f64, f64, f64) {
+        let [x, y, z] = self.mOri;
+        let yaw = z.x.atan2(z.z);
+        let pitch = (-y.z).atan2(x.z.hypot(z.z));
+        let roll = y.x.atan2(x.x.hypot(z.x));
+        (yaw, pitch, roll)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(b: &mut [u8], at: usize, v: &[u8]) {
+        b[at..at + v.len()].copy_from_slice(v);
+    }
+
+    /// `T` read from the start of `b`, as the source reads the mapping.
+    fn read<T: Copy>(b: &[u8]) -> T {
+        assert!(b.len() >= size_of::<T>());
+        unsafe { std::ptr::read_unaligned(b.as_ptr() as *const T) }
+    }
+
+    /// A scoring buffer with three vehicles, the player's (id 7) second,
+    /// written at the offsets `rF2State.h` gives.
+    fn scoring() -> Vec<u8> {
+        let mut b = vec![0u8; size_of::<RF2Scoring>()];
+        put(&mut b, 0, &3u32.to_le_bytes());
+        put(&mut b, 4, &3u32.to_le_bytes());
+        put(&mut b, 12, b"Circuit de la Sarthe");
+        put(&mut b, 12 + 88, &13_626.0f64.to_le_bytes()); // track length
+        put(&mut b, 12 + 104, &3i32.to_le_bytes());
+        for (i, id) in [4i32, 7, 9].into_iter().enumerate() {
+            let v = 560 + i * 584;
+            put(&mut b, v, &id.to_le_bytes());
+            put(&mut b, v + 104, &(1000.0 * (i + 1) as f64).to_le_bytes()); // lap distance
+            put(&mut b, v + 144, &(-1.0f64).to_le_bytes()); // best lap
+            put(&mut b, v + 168, &(200.0 + i as f64).to_le_bytes()); // last lap
+        }
+        b[560 + 584 + 196] = 1;
+        b
+    }
+
+    #[test]
+    fn finds_the_player_in_the_scoring_buffer() {
+        let s: RF2Scoring = read(&scoring());
+        assert_eq!(&s.mScoringInfo.mTrackName[..20], b"Circuit de la Sarthe");
+        assert_eq!({ s.mScoringInfo.mLapDist }, 13_626.0);
+
+        let p = s.player().unwrap();
+        assert_eq!({ p.mID }, 7);
+        assert_eq!({ p.mLapDist }, 2000.0);
+        assert_eq!(lap_time_s(p.mLastLapTime), 201.0);
+        assert_eq!(lap_time_s(p.mBestLapTime), 0.0);
+    }
+
+    #[test]
+    fn no_player_while_the_buffer_is_written_or_past_the_vehicle_count() {
+        let mut b = scoring();
+        put(&mut b, 4, &2u32.to_le_bytes());
+        assert!(read::<RF2Scoring>(&b).player().is_none());
+
+        let mut b = scoring();
+        put(&mut b, 12 + 104, &1i32.to_le_bytes());
+        assert!(read::<RF2Scoring>(&b).player().is_none());
+    }
+
+    #[test]
+    fn reads_a_vehicle_past_the_first_in_the_telemetry_buffer() {
+        let mut b = vec![0u8; TELEMETRY_BUFFER_SIZE];
+        put(&mut b, 12, &2i32.to_le_bytes());
+        let v = 16 + 1888;
+        put(&mut b, v, &7i32.to_le_bytes());
+        put(&mut b, v + 20, &3i32.to_le_bytes()); // lap number
+        put(&mut b, v + 160, &(-52.5f64).to_le_bytes()); // x
+        put(&mut b, v + 352, &4i32.to_le_bytes()); // gear
+        put(&mut b, v + 388, &0.8f64.to_le_bytes()); // throttle
+        put(&mut b, v + 524, &61.0f64.to_le_bytes()); // fuel
+
+        let t: RF2VehicleTelemetry = read(&b[telemetry_vehicle_offset(1)..]);
+        assert_eq!({ t.mID }, 7);
+        assert_eq!({ t.mLapNumber }, 3);
+        assert_eq!({ t.mPos.x }, -52.5);
+        assert_eq!({ t.mGear }, 4);
+        assert_eq!({ t.mUnfilteredThrottle }, 0.8);
+        assert_eq!({ t.mFuel }, 61.0);
+    }
+}
@@ -0,0 +1,190 @@
+use windows::Win32::Foundation::*;
+use windows::Win32::System::Memory::*;
+
+use std::ffi::CString;
+use std::mem::size_of;
+
+use delta_ingest_core::dedup::FrameDedup;
+use delta_ingest_core::*;
+use tokio::time::{self, Duration};
+
+use crate::layout::{
+    lap_time_s, telemetry_vehicle_offset, RF2Scoring, RF2Telemetry, RF2VehicleTelemetry, MAX_MAPPED_VEHICLES,
+    TELEMETRY_BUFFER_SIZE,
+};
+
+struct SharedMemoryMapping {
+    view: *mut std::ffi::c_void,
+    handle: HANDLE,
+}
+
+impl Drop for SharedMemoryMapping {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.view.is_null() {
+                UnmapViewOfFile(self.view);
+            }
+            if !self.handle.is_invalid() {
+                // Ignore failure on close
+                let _ = CloseHandle(self.handle);
+            }
+        }
+    }
+}
+
+impl SharedMemoryMapping {
+    fn new(name: &str, size: usize) -> Result<Self, IngestError> {
+        unsafe {
+            let name_c =
+                CString::new(name).map_err(|_| IngestError::Msg("Invalid shared memory name".into()))?;
+
+            // Open the already-created mapping from the plugin (read-only)
+            let handle = OpenFileMappingA(FILE_MAP_READ.0, BOOL(0), PCSTR(name_c.as_ptr() as _));
+            if handle.is_invalid() {
+                return Err(IngestError::Msg(
+                    "LMU/rF2 Telemetry mapping not found. Ensure rF2SharedMemoryMapPlugin is installed".into(),
+                ));
+            }
+
+            // Map only the size we need
+            let view = MapViewOfFile(
+                handle,
+                FILE_MAP_READ.0,
+                0,
+                0,
+                size,
+            );
+            if view.is_null() {
+                let _ = CloseHandle(handle);
+                return Err(IngestError::Msg("Failed to map view of shared memory".into()));
+            }
+
+            Ok(Self { view, handle })
+        }
+    }
+
+    /// A copy of the `T` at `offset` in the view.
+    ///
+    /// # Safety
+    /// `offset + size_of::<T>()` must be within the size mapped.
+    unsafe fn read<T: Copy>(&self, offset: usize) -> T {
+        // read_volatile: the plugin writes the buffer under us
+        std::ptr::read_volatile((self.view as *const u8).add(offset) as *const T)
+    }
+}
+
+/// Names of shared memory buffers created by rF2SharedMemoryMapPlugin.
+/// Telemetry has the physics at 50 Hz; scoring says which vehicle is the
+/// player's and has its lap distance and lap times.
+const SM_TELEMETRY: &str = "$rFactor2SMMP_Telemetry$";
+const SM_SCORING: &str = "$rFactor2SMMP_Scoring$";
+
+impl RF2VehicleTelemetry {
+    fn validate(&self) -> bool {
+        let v = self;
+        // Physics sanity
+        let vel = v.mLocalVel;
+        let ok_vel = |v: f64| v.is_finite() && (-1000.0..=1000.0).contains(&v);
+        if !ok_vel(vel.x) || !ok_vel(vel.y) || !ok_vel(vel.z) {
+            return false;
+        }
+        // Controls sanity
+        if !(0.0..=1.0).contains(&{ v.mUnfilteredThrottle })
+            || !(0.0..=1.0).contains(&{ v.mUnfilteredBrake })
+            || !(-1..=8).contains(&{ v.mGear })
+        {
+            return false;
+        }
+        true
+    }
+}
+
+pub struct LMUSource;
+impl LMUSource {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait::async_trait]
+impl TelemetrySource for LMUSource {
+    async fn run(&self, tx: TelemetryTx) -> Result<(), IngestError> {
+        // Open the shared memory mappings (RAII)
+        let telemetry = SharedMemoryMapping::new(SM_TELEMETRY, TELEMETRY_BUFFER_SIZE)?;
+        let scoring = SharedMemoryMapping::new(SM_SCORING, size_of::<RF2Scoring>())?;
+
+        // 50 Hz loop
+        const FRAME_INTERVAL: Duration = Duration::from_millis(20);
+        let mut ticker = time::interval(FRAME_INTERVAL);
+        // Prevent catch-up storm if the loop stalls
+        ticker.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+        // polled faster than the game updates, the same frame comes round again
+        let mut dedup = FrameDedup::new();
+
+        loop {
+            // Read a snapshot of each buffer from the mapped region; read_volatile
+            // avoids UB as the plugin writes them under us.
+            let score: RF2Scoring = unsafe { scoring.read(0) };
+            let header: RF2Telemetry = unsafe { telemetry.read(0) };
+            let vehicles = ({ header.mNumVehicles }.max(0) as usize).min(MAX_MAPPED_VEHICLES);
+
+            // the telemetry vehicle with the scoring player's id; none in the
+            // menus, or while either buffer is part way through being written
+            let player = score.player().filter(|_| { header.mVersionUpdateBegin } == { header.mVersionUpdateEnd });
+            let found = player.and_then(|p| {
+                (0..vehicles)
+                    .map(|i| unsafe { telemetry.read::<RF2VehicleTelemetry>(telemetry_vehicle_offset(i)) })
+                    .find(|v| { v.mID } == { p.mID })
+                    .map(|v| (p, v))
+            });
+
+            if let Some((p, v)) = found.filter(|(_, v)| v.validate()) {
+                let (vel, pos) = (v.mLocalVel, v.mPos);
+                // Derive speed magnitude from local velocity
+                let speed_mps = (vel.x.powi(2) + vel.y.powi(2) + vel.z.powi(2)).sqrt();
+                let (yaw, pitch, roll) = v.yaw_pitch_roll();
+
+                let mut sample = TelemetrySample {
+                    game: Game::LMU,
+                    car_id: "player:0".to_string(),
+                    session_uid: "lmu".to_string(),
+                    frame: (v.mElapsedTime * 1000.0) as u64,
+                    sim_time_s: v.mElapsedTime,
+                    speed_mps: speed_mps as f32,
+                    throttle: v.mUnfilteredThrottle as f32,
+                    brake: v.mUnfilteredBrake as f32,
+                    gear: v.mGear as i8,
+                    engine_rpm: v.mEngineRPM as f32,
+                    world_pos_x: pos.x as f32,
+                    world_pos_y: pos.y as f32,
+                    world_pos_z: pos.z as f32,
+                    yaw: yaw as f32,
+                    pitch: pitch as f32,
+                    roll: roll as f32,
+                    // scoring updates at 5 Hz, so lap distance steps between its updates
+                    lap_distance_m: p.mLapDist as f32,
+                    current_lap: v.mLapNumber.max(0) as u32,
+                    current_lap_time_s: (v.mElapsedTime - v.mLapStartET).max(0.0) as f32,
+                    last_lap_time_s: lap_time_s(p.mLastLapTime),
+                    best_lap_time_s: lap_time_s(p.mBestLapTime),
+                    fuel_l: v.mFuel as f32,
+                    fuel_capacity_l: v.mFuelCapacity as f32,
+                    rev_limiter: false,
+                    // garage and session state are in the scoring buffer
+                    phase: Phase::Driving,
+                };
+                frame::to_common(&mut sample);
+
+                // If receiver is gone, stop gracefully
+                if dedup.fresh(&sample) && tx.send(sample).is_err() {
+                    break;
+                }
+            }
+
+            // Wait for next tick (non-blocking)
+            ticker.tick().await;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+//! Live telemetry relayed between Delta instances, so a coach can watch a
+//! driver's session from anywhere.
+//!
+//! The driver's instance runs a [`Relay`], which connects out to the coach's
+//! instance over WebSocket and streams every sample it ingests. The coach's
+//! instance runs a [`RemoteSource`], which takes them in like any other
+//! connector: laps, deltas and analysis are built on the coach's side.
+//!
+//! Protocol:
+//! - the relay opens a WebSocket (`ws://` or, behind a TLS proxy, `wss://`)
+//!   with `Authorization: Bearer <token>`; the source answers 401 to any
+//!   other token
+//! - each binary message is a batch of samples: a version byte
+//!   ([`VERSION`]), then the samples as a postcard `Vec<TelemetrySample>`,
+//!   zstd-compressed
+//! - the source takes one relay at a time; a new one replaces the old, so
+//!   a driver reconnecting after a network drop doesn't wait for the old
+//!   connection to time out
+
+use anyhow::{bail, Context, Result};
+use futures_util::StreamExt;
+use std::{
+    future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::{header::AUTHORIZATION, StatusCode},
+        Message,
+    },
+    WebSocketStream,
+};
+
+use delta_ingest_core::diag::IngestCounters;
+use delta_ingest_core::*;
+
+pub mod relay;
+pub use relay::Relay;
+
+pub const DEFAULT_PORT: u16 = 47800;
+// bumped whenever TelemetrySample changes
+pub const VERSION: u8 = 3;
+
+// largest batch accepted once decompressed, far above a second of samples
+const MAX_BATCH: usize = 16 << 20;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Clone, Debug)]
+pub struct RemoteConfig {
+    /// Where relays connect, e.g. `0.0.0.0:47800`.
+    pub bind_addr: String,
+    /// Shared with the driver; relays presenting anything else are refused.
+    pub token: String,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self { bind_addr: format!("0.0.0.0:{DEFAULT_PORT}"), token: String::new() }
+    }
+}
+
+pub struct RemoteSource {
+    cfg: RemoteConfig,
+    counters: Arc<IngestCounters>,
+}
+
+impl RemoteSource {
+    pub fn new(cfg: RemoteConfig) -> Self {
+        Self { cfg, counters: Arc::default() }
+    }
+}
+
+type Socket = WebSocketStream<TcpStream>;
+
+#[async_trait::async_trait]
+impl TelemetrySource for RemoteSource {
+    async fn run(&self, tx: TelemetryTx) -> Result<(), IngestError> {
+        if self.cfg.token.is_empty() {
+            return Err(IngestError::Msg("no relay token set".into()));
+        }
+        let listener = TcpListener::bind(&self.cfg.bind_addr)
+            .await
+            .with_context(|| format!("bind {}", self.cfg.bind_addr))?;
+        let mut relay: Option<Socket> = None;
+        // handshakes run in tasks of their own, so a connection that stalls
+        // holds up neither the relay being read nor the next to connect
+        let (done, mut handshaken) = mpsc::unbounded_channel::<Socket>();
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let Ok((stream, peer)) = accepted else { continue };
+                    let (token, done) = (self.cfg.token.clone(), done.clone());
+                    tokio::spawn(async move {
+                        match tokio::time::timeout(HANDSHAKE_TIMEOUT, accept(stream, &token)).await {
+                            Ok(Ok(ws)) => { let _ = done.send(ws); }
+                            Ok(Err(e)) => eprintln!("relay from {peer} refused: {e:#}"),
+                            Err(_) => eprintln!("relay from {peer} timed out"),
+                        }
+                    });
+                }
+                // replaces (and so closes) the relay before it
+                Some(ws) = handshaken.recv() => relay = Some(ws),
+                msg = next(&mut relay) => match msg {
+                    Some(Ok(Message::Binary(frame))) => {
+                        let started = Instant::now();
+                        let batch = decode(&frame);
+                        self.counters.record(frame.len(), batch.as_ref().is_ok_and(|b| !b.is_empty()), started.elapsed());
+                        match batch {
+                            Ok(batch) => {
+                                for s in batch {
+                                    if tx.send(s).is_err() {
+                                        // receiver dropped; time to stop
+                                        return Ok(());
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                self.counters.record_malformed();
+                                eprintln!("bad relay frame: {e:#}");
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => relay = None,
+                    // pings are answered by tungstenite itself
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    }
+
+    fn counters(&self) -> Option<Arc<IngestCounters>> {
+        Some(self.counters.clone())
+    }
+}
+
+/// Next message of the relay; never resolves while there is none.
+async fn next(relay: &mut Option<Socket>) -> Option<Result<Message, tokio_tungstenite::tungstenite::Error>> {
+    match relay {
+        Some(ws) => ws.next().await,
+        None => future::pending().await,
+    }
+}
+
+// the handshake callback's error type is tungstenite's, large or not
+#[allow(clippy::result_large_err)]
+async fn accept(stream: TcpStream, token: &str) -> Result<Socket> {
+    let expected = format!("Bearer {token}");
+    let check = |req: &Request, resp: Response| {
+        let given = req.headers().get(AUTHORIZATION).map(|v| v.as_bytes()).unwrap_or_default();
+        if constant_time_eq(given, expected.as_bytes()) {
+            Ok(resp)
+        } else {
+            let mut refused = ErrorResponse::new(Some("bad relay token".into()));
+            *refused.status_mut() = StatusCode::UNAUTHORIZED;
+            Err(refused)
+        }
+    };
+    Ok(tokio_tungstenite::accept_hdr_async(stream, check).await?)
+}
+
+/// Compare without returning early, so timing doesn't reveal how much of a
+/// guessed token is right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// One batch as a relay message.
+pub fn encode(batch: &[TelemetrySample]) -> Result<Vec<u8>> {
+    let raw = postcard::to_stdvec(batch)?;
+    let mut frame = vec![VERSION];
+    frame.extend(zstd::bulk::compress(&raw, ZSTD_LEVEL)?);
+    Ok(frame)
+}
+
+pub fn decode(frame: &[u8]) -> Result<Vec<TelemetrySample>> {
+    match frame.split_first() {
+        Some((&VERSION, body)) => {
+            let raw = zstd::bulk::decompress(body, MAX_BATCH).context("decompress")?;
+            Ok(postcard::from_bytes(&raw)?)
+        }
+        Some((v, _)) => bail!("relay protocol version {v} isn't supported; update Delta on both ends"),
+        None => bail!("empty frame"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use relay::RelayOptions;
+
+    fn sample(frame: u64) -> TelemetrySample {
+        TelemetrySample {
+            game: Game::GT7,
+            car_id: "car".into(),
+            session_uid: "1".into(),
+            frame,
+            sim_time_s: frame as f64 / 60.0,
+            speed_mps: 50.0,
+            throttle: 1.0,
+            brake: 0.0,
+            gear: 4,
+            engine_rpm: 7000.0,
+            world_pos_x: 1.0,
+            world_pos_y: 2.0,
+            world_pos_z: 0.0,
+            yaw: 0.5,
+            pitch: 0.0,
+            roll: 0.0,
+            lap_distance_m: frame as f32,
+            current_lap: 2,
+            current_lap_time_s: 12.5,
+            last_lap_time_s: 90.0,
+            best_lap_time_s: 0.0,
+            fuel_l: 0.0,
+            fuel_capacity_l: 0.0,
+            rev_limiter: false,
+            phase: Phase::Driving,
+        }
+    }
+
+    #[test]
+    fn batches_round_trip() {
+        let batch = vec![sample(1), sample(2)];
+        let back = decode(&encode(&batch).unwrap()).unwrap();
+        assert_eq!(back.iter().map(|s| s.frame).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(back[1].lap_distance_m, 2.0);
+        assert!(decode(&[VERSION + 1, 0]).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_stalled_connection_does_not_hold_up_a_relay() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let source = RemoteSource::new(RemoteConfig { bind_addr: addr.to_string(), token: "secret".into() });
+        let (tx, rx) = channel();
+        tokio::spawn(async move { source.run(tx).await });
+
+        // connects but never sends its handshake
+        let stalled = loop {
+            match TcpStream::connect(addr).await {
+                Ok(s) => break s,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        let relay = Relay::connect(&RelayOptions {
+            url: format!("ws://{addr}"),
+            token: "secret".into(),
+            source: None,
+        })
+        .unwrap();
+
+        // well inside the stalled handshake's timeout
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT / 2;
+        let got = loop {
+            relay.send("gt7", &sample(7));
+            if let Ok(s) = rx.try_recv() {
+                break Some(s);
+            }
+            if Instant::now() > deadline {
+                break None;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        };
+        assert_eq!(got.map(|s| s.frame), Some(7));
+        drop(stalled);
+    }
+}
@@ -0,0 +1,162 @@
+//! The driver's end: samples batched, compressed and sent to a coach's
+//! [`RemoteSource`](crate::RemoteSource).
+
+use anyhow::{bail, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, http::header::AUTHORIZATION, Message};
+
+use delta_ingest_core::TelemetrySample;
+
+/// Samples queued for sending before the relay starts dropping them.
+const QUEUE: usize = 4096;
+/// How long samples are gathered into one message. Short enough that the
+/// coach sees them live, long enough for compression to pay off.
+const BATCH: Duration = Duration::from_millis(50);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct RelayOptions {
+    /// The coach's instance, e.g. `ws://coach.example.com:47800`.
+    pub url: String,
+    pub token: String,
+    /// Only relay this source's samples; every source when unset.
+    pub source: Option<String>,
+}
+
+#[derive(Clone, Serialize, Debug, Default, PartialEq)]
+pub struct RelayStatus {
+    pub target: String,
+    pub connected: bool,
+    pub samples_sent: u64,
+    /// Compressed bytes sent, for judging the upload it takes.
+    pub bytes_sent: u64,
+    /// Last connection problem; cleared once connected.
+    pub error: Option<String>,
+}
+
+/// Relays samples to one coach, reconnecting whenever the link drops;
+/// disconnects when dropped. Samples taken while disconnected are dropped:
+/// the coach watches live, so late samples are of no use.
+pub struct Relay {
+    opts: RelayOptions,
+    tx: mpsc::Sender<TelemetrySample>,
+    status: Arc<Mutex<RelayStatus>>,
+    task: JoinHandle<()>,
+}
+
+impl Relay {
+    /// Start relaying to `opts.url`. Must be called inside a tokio runtime.
+    pub fn connect(opts: &RelayOptions) -> Result<Self> {
+        if !opts.url.starts_with("ws://") && !opts.url.starts_with("wss://") {
+            bail!("the coach's address should start with ws:// or wss://");
+        }
+        if opts.token.is_empty() {
+            bail!("no relay token set");
+        }
+        let (tx, rx) = mpsc::channel(QUEUE);
+        let status = Arc::new(Mutex::new(RelayStatus { target: opts.url.clone(), ..Default::default() }));
+        let task = tokio::spawn(run(opts.clone(), rx, status.clone()));
+        Ok(Self { opts: opts.clone(), tx, status, task })
+    }
+
+    pub fn options(&self) -> &RelayOptions {
+        &self.opts
+    }
+
+    pub fn status(&self) -> RelayStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Queue a sample of `source` for the coach.
+    pub fn send(&self, source: &str, s: &TelemetrySample) {
+        if self.opts.source.as_deref().is_some_and(|want| want != source) {
+            return;
+        }
+        // full while the coach is away or the upload can't keep up
+        let _ = self.tx.try_send(s.clone());
+    }
+}
+
+impl Drop for Relay {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run(opts: RelayOptions, mut rx: mpsc::Receiver<TelemetrySample>, status: Arc<Mutex<RelayStatus>>) {
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        let result = match open(&opts).await {
+            Ok(ws) => {
+                {
+                    let mut s = status.lock().unwrap();
+                    s.connected = true;
+                    s.error = None;
+                }
+                backoff = Duration::from_secs(1);
+                pump(ws, &mut rx, &status).await
+            }
+            Err(e) => Err(e),
+        };
+        {
+            let mut s = status.lock().unwrap();
+            s.connected = false;
+            if let Err(e) = result {
+                s.error = Some(format!("{e:#}"));
+            }
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        // stale by the time the link is back
+        while rx.try_recv().is_ok() {}
+    }
+}
+
+type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn open(opts: &RelayOptions) -> Result<Socket> {
+    let mut req = opts.url.as_str().into_client_request()?;
+    req.headers_mut().insert(AUTHORIZATION, format!("Bearer {}", opts.token).parse()?);
+    let (ws, _) = tokio_tungstenite::connect_async(req).await?;
+    Ok(ws)
+}
+
+/// Send batches until the link drops.
+async fn pump(mut ws: Socket, rx: &mut mpsc::Receiver<TelemetrySample>, status: &Mutex<RelayStatus>) -> Result<()> {
+    let mut tick = tokio::time::interval(BATCH);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut batch = Vec::new();
+    loop {
+        tokio::select! {
+            s = rx.recv() => match s {
+                Some(s) => batch.push(s),
+                // the relay was dropped
+                None => return Ok(()),
+            },
+            _ = tick.tick() => {
+                if batch.is_empty() {
+                    continue;
+                }
+                let frame = crate::encode(&batch)?;
+                let len = frame.len();
+                ws.send(Message::Binary(frame)).await?;
+                let mut s = status.lock().unwrap();
+                s.samples_sent += batch.len() as u64;
+                s.bytes_sent += len as u64;
+                batch.clear();
+            }
+            msg = ws.next() => match msg {
+                Some(Ok(Message::Close(_))) | None => bail!("the coach closed the connection"),
+                Some(Err(e)) => return Err(e.into()),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
@@ -0,0 +1,62 @@
+/*
+ * C API of Delta's lap loading and analysis, built from crates/ffi as
+ * libdelta (delta.dll, libdelta.so or libdelta.dylib, plus a static
+ * library).
+ *
+ * Results are JSON written into a caller-provided buffer with a terminating
+ * NUL. Each call returns the JSON's length in bytes without the NUL and
+ * writes nothing when it does not fit, so call once with out = NULL and
+ * cap = 0 for the size, then again with a buffer of at least size + 1.
+ * Failures return NULL or -1; delta_last_error has the message, per thread.
+ *
+ * A DeltaLaps is immutable once loaded and may be shared between threads.
+ */
+
+#ifndef DELTA_H
+#define DELTA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Only changes when an existing function does; compare with
+ * delta_abi_version() at startup. */
+#define DELTA_ABI_VERSION 1
+
+typedef struct DeltaLaps DeltaLaps;
+
+uint32_t delta_abi_version(void);
+
+/* Laps from a .dlap lap file, a .deltasession bundle or NDJSON, told apart
+ * by their first bytes. The data is copied; free the result with
+ * delta_laps_free. */
+DeltaLaps *delta_laps_load(const uint8_t *data, size_t len);
+void delta_laps_free(DeltaLaps *laps);
+size_t delta_laps_count(const DeltaLaps *laps);
+
+/* [{index, id, game, car, track, lap_number, time_ms, points}] in load
+ * order; index is what the functions below take. */
+int64_t delta_laps_info(const DeltaLaps *laps, char *out, size_t cap);
+
+/* {reference_id, distance, delta_ms}: time delta of lap against reference
+ * on a 1 m distance grid. */
+int64_t delta_delta_series(const DeltaLaps *laps, size_t reference, size_t lap, char *out, size_t cap);
+
+/* {lap_id, corners}: entry, apex and exit speeds, brake points and
+ * throttle-on points of each corner. */
+int64_t delta_corner_metrics(const DeltaLaps *laps, size_t lap, char *out, size_t cap);
+
+/* Best, worst and average lap time and consistency of every lap. */
+int64_t delta_session_summary(const DeltaLaps *laps, char *out, size_t cap);
+
+/* Message of the last failure on this thread, written like the results. */
+int64_t delta_last_error(char *out, size_t cap);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* DELTA_H */
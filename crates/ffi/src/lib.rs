@@ -0,0 +1,221 @@
+//! C API over lap loading and analysis, so overlay tools written in C, C++
+//! or C# can run Delta's analysis in-process. The declarations are in
+//! `include/delta.h`.
+//!
+//! Conventions:
+//! - laps are loaded from a buffer into an opaque `DeltaLaps`, which is
+//!   immutable afterwards and may be shared between threads
+//! - results are JSON, the same as the app's API sends, written into a
+//!   caller-provided buffer with a terminating NUL; each call returns the
+//!   JSON's length in bytes without the NUL, and writes nothing when the
+//!   buffer is too small, so a call with `out = NULL, cap = 0` asks for the
+//!   size to allocate
+//! - failures return NULL or -1; `delta_last_error` has the message, per
+//!   thread
+//! - `DELTA_ABI_VERSION` only changes when an existing function does
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    ffi::c_char,
+    io::{BufRead, Cursor},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use iox::binary::{BinaryReader, MAGIC};
+use model::Lap;
+
+pub const DELTA_ABI_VERSION: u32 = 1;
+
+// zip local file header, which session bundles start with
+const ZIP_MAGIC: &[u8; 4] = b"PK\x03\x04";
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Laps loaded by `delta_laps_load`; opaque to C.
+pub struct DeltaLaps {
+    laps: Vec<Lap>,
+}
+
+#[derive(Serialize)]
+struct LapInfo<'a> {
+    index: usize,
+    id: String,
+    game: &'a str,
+    car: &'a str,
+    track: &'a str,
+    lap_number: u32,
+    time_ms: u64,
+    points: usize,
+}
+
+#[no_mangle]
+pub extern "C" fn delta_abi_version() -> u32 {
+    DELTA_ABI_VERSION
+}
+
+/// Load laps from `len` bytes at `data`: a `.dlap` lap file, a
+/// `.deltasession` bundle or NDJSON, told apart by their first bytes.
+/// Returns NULL on failure.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn delta_laps_load(data: *const u8, len: usize) -> *mut DeltaLaps {
+    guard(ptr::null_mut(), || {
+        if data.is_null() {
+            bail!("no data");
+        }
+        let bytes = slice::from_raw_parts(data, len);
+        let laps = load(bytes)?;
+        Ok(Box::into_raw(Box::new(DeltaLaps { laps })))
+    })
+}
+
+fn load(bytes: &[u8]) -> Result<Vec<Lap>> {
+    if bytes.starts_with(MAGIC) {
+        BinaryReader::new(bytes)?.collect()
+    } else if bytes.starts_with(ZIP_MAGIC) {
+        Ok(iox::bundle::read_bundle(Cursor::new(bytes))?.laps)
+    } else {
+        bytes
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.as_ref().is_ok_and(|l| !l.trim().is_empty()))
+            .map(|(i, line)| serde_json::from_str(&line?).map_err(|e| anyhow!("line {}: {e}", i + 1)))
+            .collect()
+    }
+}
+
+/// # Safety
+/// `laps` must come from `delta_laps_load` and not be used afterwards. NULL
+/// is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn delta_laps_free(laps: *mut DeltaLaps) {
+    if !laps.is_null() {
+        drop(Box::from_raw(laps));
+    }
+}
+
+/// # Safety
+/// `laps` must come from `delta_laps_load`.
+#[no_mangle]
+pub unsafe extern "C" fn delta_laps_count(laps: *const DeltaLaps) -> usize {
+    laps.as_ref().map_or(0, |l| l.laps.len())
+}
+
+/// Metadata of every lap as a JSON array, in load order; the `index` of
+/// each is what the analysis functions take.
+///
+/// # Safety
+/// `laps` must come from `delta_laps_load`; `out` must be NULL or point to
+/// `cap` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn delta_laps_info(laps: *const DeltaLaps, out: *mut c_char, cap: usize) -> i64 {
+    guard(-1, || {
+        let info: Vec<LapInfo> = get(laps)?
+            .laps
+            .iter()
+            .enumerate()
+            .map(|(index, l)| LapInfo {
+                index,
+                id: l.id.simple().to_string(),
+                game: &l.meta.game,
+                car: &l.meta.car,
+                track: &l.meta.track,
+                lap_number: l.meta.lap_number,
+                time_ms: l.total_time_ms,
+                points: l.points.len(),
+            })
+            .collect();
+        write_json(&info, out, cap)
+    })
+}
+
+/// Time delta of lap `lap` against lap `reference` by distance, on a 1 m
+/// grid: `{reference_id, distance, delta_ms}`.
+///
+/// # Safety
+/// As for `delta_laps_info`.
+#[no_mangle]
+pub unsafe extern "C" fn delta_delta_series(
+    laps: *const DeltaLaps,
+    reference: usize,
+    lap: usize,
+    out: *mut c_char,
+    cap: usize,
+) -> i64 {
+    guard(-1, || {
+        let laps = get(laps)?;
+        let (reference, lap) = (nth(laps, reference)?, nth(laps, lap)?);
+        write_json(&analysis::delta_series(reference, slice::from_ref(lap)), out, cap)
+    })
+}
+
+/// Corners of lap `lap` with their speeds, brake points and throttle-on
+/// points: `{lap_id, corners}`.
+///
+/// # Safety
+/// As for `delta_laps_info`.
+#[no_mangle]
+pub unsafe extern "C" fn delta_corner_metrics(laps: *const DeltaLaps, lap: usize, out: *mut c_char, cap: usize) -> i64 {
+    guard(-1, || write_json(&analysis::corner_metrics(nth(get(laps)?, lap)?), out, cap))
+}
+
+/// Best, worst and average lap time and consistency of every lap.
+///
+/// # Safety
+/// As for `delta_laps_info`.
+#[no_mangle]
+pub unsafe extern "C" fn delta_session_summary(laps: *const DeltaLaps, out: *mut c_char, cap: usize) -> i64 {
+    guard(-1, || write_json(&analysis::session_summary(&get(laps)?.laps), out, cap))
+}
+
+/// Message of the last failure on this thread, like the results: returns
+/// its length and writes it when it fits. 0 when nothing has failed.
+///
+/// # Safety
+/// `out` must be NULL or point to `cap` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn delta_last_error(out: *mut c_char, cap: usize) -> i64 {
+    LAST_ERROR.with(|e| write_bytes(e.borrow().as_bytes(), out, cap))
+}
+
+unsafe fn get<'a>(laps: *const DeltaLaps) -> Result<&'a DeltaLaps> {
+    laps.as_ref().ok_or_else(|| anyhow!("no laps"))
+}
+
+fn nth(laps: &DeltaLaps, index: usize) -> Result<&Lap> {
+    laps.laps.get(index).ok_or_else(|| anyhow!("lap index {index} out of range; {} laps loaded", laps.laps.len()))
+}
+
+unsafe fn write_json<T: Serialize>(value: &T, out: *mut c_char, cap: usize) -> Result<i64> {
+    Ok(write_bytes(&serde_json::to_vec(value)?, out, cap))
+}
+
+unsafe fn write_bytes(bytes: &[u8], out: *mut c_char, cap: usize) -> i64 {
+    if !out.is_null() && bytes.len() < cap {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), out.cast(), bytes.len());
+        *out.add(bytes.len()) = 0;
+    }
+    bytes.len() as i64
+}
+
+/// Run `f`, turning an error or a panic into `failed` and the message for
+/// `delta_last_error`; unwinding into C is undefined behaviour.
+fn guard<T>(failed: T, f: impl FnOnce() -> Result<T>) -> T {
+    let message = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(v)) => return v,
+        Ok(Err(e)) => format!("{e:#}"),
+        Err(panic) => {
+            let what = panic.downcast_ref::<&str>().copied().or(panic.downcast_ref::<String>().map(String::as_str));
+            format!("internal error: {}", what.unwrap_or("panic"))
+        }
+    };
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+    failed
+}
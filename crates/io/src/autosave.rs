@@ -0,0 +1,82 @@
+//! Append every completed lap to disk as soon as it is finished.
+//!
+//! Laps go to `<root>/<track>/<YYYY-MM-DD>/<game>-<HHMMSS>.dlap` (UTC), one
+//! [binary](crate::binary) file per game and track for the lifetime of an
+//! [`Autosave`], each lap appended as its own frame so nothing already
+//! written is touched again and a crash loses at most the lap in progress.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use model::*;
+
+use crate::binary::EXTENSION;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct AutosaveOptions {
+    pub root: PathBuf,
+}
+
+pub struct Autosave {
+    root: PathBuf,
+    started: time::OffsetDateTime,
+    /// File per (game, track), fixed on first use.
+    files: HashMap<(String, String), PathBuf>,
+}
+
+impl Autosave {
+    pub fn new(opts: &AutosaveOptions) -> Self {
+        Self { root: opts.root.clone(), started: time::OffsetDateTime::now_utc(), files: HashMap::new() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// File `lap` is (or will be) saved to.
+    pub fn path_for(&mut self, lap: &Lap) -> PathBuf {
+        let key = (lap.meta.game.clone(), lap.meta.track.clone());
+        let (root, t) = (&self.root, self.started);
+        self.files
+            .entry(key)
+            .or_insert_with(|| {
+                root.join(file_component(&lap.meta.track))
+                    .join(format!("{}-{:02}-{:02}", t.year(), u8::from(t.month()), t.day()))
+                    .join(format!(
+                        "{}-{:02}{:02}{:02}.{EXTENSION}",
+                        file_component(&lap.meta.game),
+                        t.hour(),
+                        t.minute(),
+                        t.second()
+                    ))
+            })
+            .clone()
+    }
+
+    /// Append `lap` to its session file, creating folders as needed.
+    pub fn save_lap(&mut self, lap: &Lap) -> Result<PathBuf> {
+        let path = self.path_for(lap);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        crate::append_laps(std::slice::from_ref(lap), &path)?;
+        Ok(path)
+    }
+}
+
+/// `name` made safe to use as a single path component.
+fn file_component(name: &str) -> String {
+    let s: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | ' ' | '.') { c } else { '_' })
+        .collect();
+    match s.trim_matches('.') {
+        "" => "unknown".into(),
+        s => s.into(),
+    }
+}
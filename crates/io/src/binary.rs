@@ -0,0 +1,413 @@
+//! Compact binary lap files (`.dlap`).
+//!
+//! Layout: the 4-byte magic `DLAP`, a one-byte format version, then one or
+//! more zstd frames. The decompressed stream is a sequence of records, each a
+//! little-endian `u32` length followed by a postcard-encoded [`Lap`]. Every
+//! lap is written as its own frame, so a file can be appended to lap by lap
+//! and still be read in one pass.
+//!
+//! Version 1 laps have no out-lap and in-lap flags in their metadata; they
+//! read back as neither. Appending to a version 1 file rewrites it as the
+//! current version first.
+//!
+//! [`MappedLaps`] opens a file in place instead: it only decodes the start of
+//! each frame for the lap's metadata, and a lap's points when asked for.
+
+use anyhow::{anyhow, bail, Context, Result};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Write},
+    ops::Range,
+    path::Path,
+};
+use uuid::Uuid;
+
+use model::*;
+
+pub const MAGIC: &[u8; 4] = b"DLAP";
+pub const VERSION: u8 = 2;
+pub const EXTENSION: &str = "dlap";
+
+const LEVEL: i32 = 3;
+
+/// Writes laps to a `.dlap` stream.
+pub struct BinaryWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> BinaryWriter<W> {
+    /// Start a new file, writing the header.
+    pub fn new(mut inner: W) -> Result<Self> {
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[VERSION])?;
+        Ok(Self { inner })
+    }
+
+    /// Continue a stream whose header has already been written.
+    pub fn append(inner: W) -> Self {
+        Self { inner }
+    }
+
+    pub fn write_lap(&mut self, lap: &Lap) -> Result<()> {
+        let body = postcard::to_stdvec(lap)?;
+        let mut enc = zstd::Encoder::new(&mut self.inner, LEVEL)?;
+        enc.write_all(&(body.len() as u32).to_le_bytes())?;
+        enc.write_all(&body)?;
+        enc.finish()?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Reads laps back from a `.dlap` stream.
+pub struct BinaryReader<R: Read> {
+    dec: zstd::Decoder<'static, BufReader<R>>,
+    version: u8,
+}
+
+impl<R: Read> BinaryReader<R> {
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut header = [0u8; 5];
+        inner.read_exact(&mut header).map_err(|_| anyhow!("not a lap file: too short"))?;
+        if &header[..4] != MAGIC {
+            bail!("not a lap file: bad magic");
+        }
+        if header[4] > VERSION {
+            bail!("lap file version {} is newer than supported {VERSION}", header[4]);
+        }
+        Ok(Self { dec: zstd::Decoder::new(inner)?, version: header[4] })
+    }
+
+    /// Format version of the file.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn read_lap(&mut self) -> Result<Option<Lap>> {
+        let mut len = [0u8; 4];
+        match self.dec.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut body = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.dec.read_exact(&mut body)?;
+        Ok(Some(decode_lap(&body, self.version)?))
+    }
+}
+
+impl<R: Read> Iterator for BinaryReader<R> {
+    type Item = Result<Lap>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_lap().transpose()
+    }
+}
+
+pub fn export_binary(laps: &[Lap], path: &Path) -> Result<()> {
+    let mut w = BinaryWriter::new(BufWriter::new(File::create(path)?))?;
+    for l in laps {
+        w.write_lap(l)?;
+    }
+    w.finish()?;
+    Ok(())
+}
+
+/// Append laps to `path`, creating the file (with header) if needed.
+pub fn append_binary(laps: &[Lap], path: &Path) -> Result<()> {
+    if file_version(path)?.is_some_and(|v| v < VERSION) {
+        let mut all = import_binary(path)?;
+        all.extend_from_slice(laps);
+        return export_binary(&all, path);
+    }
+    let f = OpenOptions::new().create(true).append(true).open(path)?;
+    let fresh = f.metadata()?.len() == 0;
+    let f = BufWriter::new(f);
+    let mut w = if fresh { BinaryWriter::new(f)? } else { BinaryWriter::append(f) };
+    for l in laps {
+        w.write_lap(l)?;
+    }
+    w.finish()?;
+    Ok(())
+}
+
+pub fn import_binary(path: &Path) -> Result<Vec<Lap>> {
+    BinaryReader::new(File::open(path)?)?.collect()
+}
+
+/// Format version of the lap file at `path`; `None` when it's missing or
+/// doesn't have a header yet.
+fn file_version(path: &Path) -> Result<Option<u8>> {
+    let mut header = [0u8; 5];
+    match File::open(path) {
+        Ok(mut f) => Ok((f.read(&mut header)? == 5 && &header[..4] == MAGIC).then_some(header[4])),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Whether the file at `path` starts with the lap file magic.
+pub fn is_binary(path: &Path) -> Result<bool> {
+    let mut head = [0u8; 4];
+    let n = File::open(path)?.read(&mut head)?;
+    Ok(n == 4 && &head == MAGIC)
+}
+
+/// Decompressed bytes read for a lap's metadata; enough for any real names.
+const HEAD_BYTES: u64 = 512;
+
+/// A lap's metadata as stored ahead of its points.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LapHead {
+    #[serde(with = "uuid::serde::simple")]
+    pub id: Uuid,
+    pub meta: LapMeta,
+    pub total_time_ms: u64,
+    /// Number of points; postcard writes a `Vec`'s length as a `usize`
+    /// before its items, so this reads it without the points.
+    pub points: usize,
+}
+
+enum Bytes {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+/// Laps of a `.dlap` file, memory-mapped, with only their metadata decoded.
+/// Opening touches the start of every frame; [`MappedLaps::lap`] decodes
+/// one lap in full.
+pub struct MappedLaps {
+    bytes: Bytes,
+    version: u8,
+    // zstd frame of each lap, in file order
+    frames: Vec<Range<usize>>,
+    heads: Vec<LapHead>,
+}
+
+impl MappedLaps {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: lap files are only ever appended to, never truncated or
+        // rewritten in place while open
+        let map = unsafe { Mmap::map(&file)? };
+        let len = map.len();
+        Self::new(Bytes::Mapped(map), 0..len)
+    }
+
+    /// The lap file at `range` of the file at `path`, e.g. an uncompressed
+    /// entry of a zip.
+    pub fn open_range(path: &Path, range: Range<usize>) -> Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: as for `open`
+        let map = unsafe { Mmap::map(&file)? };
+        if range.end > map.len() {
+            bail!("lap data runs past the end of the file");
+        }
+        Self::new(Bytes::Mapped(map), range)
+    }
+
+    /// A lap file already in memory.
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Self> {
+        let len = bytes.len();
+        Self::new(Bytes::Owned(bytes), 0..len)
+    }
+
+    fn new(bytes: Bytes, range: Range<usize>) -> Result<Self> {
+        let mut laps = Self { bytes, version: VERSION, frames: Vec::new(), heads: Vec::new() };
+        let data = &laps.data()[range.clone()];
+        if data.len() < 5 || &data[..4] != MAGIC {
+            bail!("not a lap file: bad magic");
+        }
+        if data[4] > VERSION {
+            bail!("lap file version {} is newer than supported {VERSION}", data[4]);
+        }
+        let version = data[4];
+        let mut frames = Vec::new();
+        let mut pos = 5;
+        while pos < data.len() {
+            let n = zstd::zstd_safe::find_frame_compressed_size(&data[pos..])
+                .map_err(|_| anyhow!("lap {}: damaged frame", frames.len() + 1))?;
+            frames.push(range.start + pos..range.start + pos + n);
+            pos += n;
+        }
+        let heads = frames
+            .iter()
+            .enumerate()
+            .map(|(i, f)| read_head(&laps.data()[f.clone()], version).with_context(|| format!("lap {}", i + 1)))
+            .collect::<Result<_>>()?;
+        laps.version = version;
+        laps.frames = frames;
+        laps.heads = heads;
+        Ok(laps)
+    }
+
+    fn data(&self) -> &[u8] {
+        match &self.bytes {
+            Bytes::Mapped(m) => m,
+            Bytes::Owned(v) => v,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heads.is_empty()
+    }
+
+    /// Metadata of every lap, in file order.
+    pub fn heads(&self) -> &[LapHead] {
+        &self.heads
+    }
+
+    /// Position of the lap with id `id`.
+    pub fn position(&self, id: Uuid) -> Option<usize> {
+        self.heads.iter().position(|h| h.id == id)
+    }
+
+    /// Lap `i` with its points and markers, decoded from the file.
+    pub fn lap(&self, i: usize) -> Result<Lap> {
+        let frame = self.frames.get(i).ok_or_else(|| anyhow!("lap index {i} out of range; {} laps", self.len()))?;
+        let body = zstd::decode_all(&self.data()[frame.clone()])?;
+        let record = body.get(4..).ok_or_else(|| anyhow!("lap {}: truncated record", i + 1))?;
+        decode_lap(record, self.version)
+    }
+}
+
+/// `LapMeta` as version 1 wrote it, without the out-lap and in-lap flags.
+#[derive(Deserialize)]
+struct LapMetaV1 {
+    #[serde(with = "uuid::serde::simple")]
+    id: Uuid,
+    game: String,
+    car: String,
+    track: String,
+    lap_number: u32,
+}
+
+impl From<LapMetaV1> for LapMeta {
+    fn from(m: LapMetaV1) -> Self {
+        LapMeta { id: m.id, game: m.game, car: m.car, track: m.track, lap_number: m.lap_number, out_lap: false, in_lap: false }
+    }
+}
+
+#[derive(Deserialize)]
+struct LapV1 {
+    #[serde(with = "uuid::serde::simple")]
+    id: Uuid,
+    meta: LapMetaV1,
+    total_time_ms: u64,
+    points: Vec<TelemetryPoint>,
+    markers: Vec<LapMarker>,
+}
+
+#[derive(Deserialize)]
+struct LapHeadV1 {
+    #[serde(with = "uuid::serde::simple")]
+    id: Uuid,
+    meta: LapMetaV1,
+    total_time_ms: u64,
+    points: usize,
+}
+
+/// A lap record written by format `version`.
+fn decode_lap(record: &[u8], version: u8) -> Result<Lap> {
+    if version >= 2 {
+        return Ok(postcard::from_bytes(record)?);
+    }
+    let l: LapV1 = postcard::from_bytes(record)?;
+    Ok(Lap {
+        id: l.id,
+        meta: l.meta.into(),
+        total_time_ms: l.total_time_ms,
+        points: l.points,
+        markers: l.markers,
+        index: Default::default(),
+    })
+}
+
+/// Metadata at the start of a lap record written by format `version`.
+fn take_head(record: &[u8], version: u8) -> postcard::Result<LapHead> {
+    if version >= 2 {
+        return Ok(postcard::take_from_bytes::<LapHead>(record)?.0);
+    }
+    let (h, _) = postcard::take_from_bytes::<LapHeadV1>(record)?;
+    Ok(LapHead { id: h.id, meta: h.meta.into(), total_time_ms: h.total_time_ms, points: h.points })
+}
+
+/// Metadata of the lap in `frame`, from as little of it as decompresses to
+/// the record's start.
+fn read_head(frame: &[u8], version: u8) -> Result<LapHead> {
+    let mut start = Vec::new();
+    zstd::Decoder::with_buffer(frame)?.take(4 + HEAD_BYTES).read_to_end(&mut start)?;
+    match start.get(4..).map(|r| take_head(r, version)) {
+        Some(Ok(head)) => Ok(head),
+        // longer than usual names; decode the whole record
+        _ => {
+            let body = zstd::decode_all(frame)?;
+            Ok(take_head(body.get(4..).unwrap_or_default(), version)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, TempPath};
+
+    #[test]
+    fn saved_laps_load_back_and_map_in_place() {
+        let laps = vec![lap(1, 40), lap(2, 300), lap(3, 0)];
+        let path = TempPath::new(EXTENSION);
+        crate::save_laps(&laps, &path).unwrap();
+        assert!(is_binary(&path).unwrap());
+        assert_eq!(crate::load_laps(&path).unwrap(), laps);
+
+        let mapped = MappedLaps::open(&path).unwrap();
+        assert_eq!(mapped.len(), 3);
+        for (i, l) in laps.iter().enumerate() {
+            let head = &mapped.heads()[i];
+            assert_eq!((head.id, &head.meta, head.total_time_ms, head.points), (l.id, &l.meta, l.total_time_ms, l.points.len()));
+            assert_eq!(&mapped.lap(i).unwrap(), l);
+        }
+        assert_eq!(mapped.position(laps[1].id), Some(1));
+        assert!(mapped.lap(3).is_err());
+    }
+
+    #[test]
+    fn appended_laps_read_back_in_order() {
+        let laps = vec![lap(1, 20), lap(2, 30), lap(3, 25)];
+        let path = TempPath::new(EXTENSION);
+        for l in &laps {
+            crate::append_laps(std::slice::from_ref(l), &path).unwrap();
+        }
+        assert_eq!(import_binary(&path).unwrap(), laps);
+        assert_eq!(MappedLaps::from_vec(std::fs::read(&*path).unwrap()).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn long_names_still_give_a_head() {
+        let mut l = lap(1, 10);
+        l.meta.track = "x".repeat(2 * HEAD_BYTES as usize);
+        let path = TempPath::new(EXTENSION);
+        crate::save_laps(std::slice::from_ref(&l), &path).unwrap();
+        assert_eq!(MappedLaps::open(&path).unwrap().heads()[0].meta, l.meta);
+    }
+
+    #[test]
+    fn load_falls_back_to_ndjson() {
+        let laps = vec![lap(1, 10)];
+        let path = TempPath::new("ndjson");
+        crate::export_ndjson(&laps, &path).unwrap();
+        assert!(!is_binary(&path).unwrap());
+        assert_eq!(crate::load_laps(&path).unwrap(), laps);
+        assert!(MappedLaps::open(&path).is_err());
+    }
+}
@@ -0,0 +1,884 @@
+//! Laps built out of live telemetry samples, as the connectors send them.
+//!
+//! How a lap's end is told is set per builder with [`LapCompletion`]: by
+//! default when the game's lap number goes up or, for samples without lap
+//! numbers, when the car passes back within 20 m of where it started after
+//! at least 15 s. Where that start area misfires (a track crossing near
+//! its pit straight), laps can end on the game's lap distance dropping
+//! back instead, or on the start position with a tighter radius or a
+//! longer time. The line falls between two samples, so the crossing is
+//! interpolated between them from the game's time into the new lap or,
+//! without one, its lap distance: the finished lap ends on a point at the
+//! crossing and the next starts on the same point at distance 0. Each
+//! finished lap gets its anomaly markers.
+//!
+//! Sources don't say when the car is in the pits, so out-laps and in-laps
+//! are told from the driving: a lap that starts from a standstill or part
+//! way round, or stops for a while in its first half, is an out-lap; one
+//! that stops in its second half, or ends at a steady pit lane speed, is an
+//! in-lap. A standing start counts as an out-lap too.
+//!
+//! Samples only count while the game says the car is being driven: paused
+//! ones are skipped, and the garage, the menus or a formation lap throw
+//! the lap in progress away, the next starting when driving does. Where
+//! the game doesn't say, a lap that hasn't moved off yet (the garage, the
+//! grid) only keeps the last moment it stood still, so waiting doesn't
+//! count towards it.
+//!
+//! Time going backwards is a rewind, like an F1 flashback: the lap in
+//! progress is cut back to the points before the time rewound to and
+//! carries on from there. Rewinding back across the line throws it away
+//! instead, as partial; the lap rewound into was already finished.
+//!
+//! A lap joined part way round is partial: its time and distance only cover
+//! what was seen of it, so it's dropped instead of returned, and kept out of
+//! the live statistics and reference. That's the lap in progress when the
+//! first sample already has the car moving, or time or distance into a lap
+//! the game counts, and the lap the game's counter turns up in well after
+//! it started.
+//!
+//! With [`ThinOptions`] set, points on straights are thinned out as the lap
+//! is built: a point is only dropped when the points either side of it
+//! reproduce it by interpolation, within the tolerances. Corners, braking
+//! zones and gear changes keep every point, so analysis over a thinned lap
+//! comes out nearly the same at a fraction of the size. The live delta
+//! still sees every sample.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use analysis::math::wrap_angle;
+use analysis::stream::LapStream;
+use delta_ingest_core::{Phase, TelemetrySample};
+use model::*;
+
+/// Below this the car counts as stopped (km/h).
+const STOPPED_KPH: f64 = 5.0;
+/// Stopped for this long is a pit box or the garage rather than a slow
+/// corner (ms).
+const STOP_MS: f64 = 2000.0;
+/// A lap whose first point is further round than this started part way (m).
+const OUT_LAP_START_M: f64 = 200.0;
+/// Pit lane speed limits are below this (km/h).
+const PIT_LIMIT_KPH: f64 = 100.0;
+/// Held within `LIMITER_SPREAD_KPH` for this long up to the line, the car
+/// is on the pit lane limiter (ms).
+const PIT_LANE_MS: f64 = 5000.0;
+const LIMITER_SPREAD_KPH: f64 = 3.0;
+
+/// Default start area of [`LapCompletion::Position`], used for samples
+/// without lap numbers (m, s).
+const START_RADIUS_M: f64 = 20.0;
+const MIN_LAP_S: f64 = 15.0;
+
+/// How the end of a lap is told from the samples; see the module docs.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LapCompletion {
+    /// The game's lap number goes up; samples without one go by the start
+    /// position with the default radius and time.
+    #[default]
+    LapNumber,
+    /// The game's lap distance drops back by more than `min_drop_m`.
+    DistanceReset { min_drop_m: f64 },
+    /// The car passes back within `radius_m` of where it first got moving,
+    /// at least `min_lap_s` into the lap.
+    Position { radius_m: f64, min_lap_s: f64 },
+}
+
+/// Which points thinning keeps; see the module docs.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct ThinOptions {
+    /// Longest stretch without a kept point (m).
+    pub max_gap_m: f64,
+    /// Turns tighter than this keep every point (m).
+    pub corner_radius_m: f64,
+    /// How far a dropped point may be from the line between the kept ones
+    /// (m).
+    pub position_m: f64,
+    /// Interpolation error allowed for the time, which deltas are read
+    /// from (ms).
+    pub time_ms: f64,
+    /// Interpolation error allowed for speed (km/h).
+    pub speed_kph: f64,
+    /// Interpolation error allowed for throttle and brake, and the brake
+    /// pressure that counts as braking (0..1).
+    pub pedal: f64,
+}
+
+impl Default for ThinOptions {
+    fn default() -> Self {
+        Self { max_gap_m: 25.0, corner_radius_m: 300.0, position_m: 0.05, time_ms: 1.0, speed_kph: 0.5, pedal: 0.02 }
+    }
+}
+
+/// Builds laps out of one car's telemetry samples.
+pub struct LapBuilder {
+    pub game: String,
+    pub car: String,
+    pub track: String,
+    pub current: Option<Lap>,
+    pub last: Option<TelemetrySample>,
+    pub start_pos: Option<(f32,f32)>,
+    pub cum_dist: f64,
+    pub last_t_ms: f64,
+    pub track_guess_m: f64,
+    // running delta to this source's best lap, splits and lap time stats
+    pub live: LapStream,
+    pub last_lap_ms: Option<u64>,
+    // the lap in progress started mid-lap (joined late, or after a
+    // resume); it's dropped instead of returned when it completes
+    pub partial: bool,
+    // the car was last in the garage, so the next lap starts from it
+    // rather than part way round
+    left_garage: bool,
+    // samples of laps thrown away
+    pub dropped: u64,
+    /// Thin points on straights; every point is kept when `None`.
+    pub thin: Option<ThinOptions>,
+    thinning: Thinning,
+    /// How the end of a lap is told.
+    pub completion: LapCompletion,
+}
+
+/// Where thinning the lap in progress is at.
+#[derive(Default)]
+struct Thinning {
+    // points since the last kept one, not kept so far
+    held: Vec<TelemetryPoint>,
+    // direction of the last step between points
+    heading: Option<f64>,
+}
+
+impl LapBuilder {
+    pub fn new(game: &str, car: &str, track: &str) -> Self {
+        let mut live = LapStream::default();
+        live.begin_lap(1);
+        Self {
+            game: game.into(), car: car.into(), track: track.into(),
+            current: Some(new_lap(game, car, track, 1)), last: None, start_pos: None, cum_dist: 0.0, last_t_ms: 0.0, track_guess_m: 0.0, live, last_lap_ms: None, partial: false, left_garage: false, dropped: 0,
+            thin: None, thinning: Thinning::default(), completion: LapCompletion::default(),
+        }
+    }
+
+    /// Builder for samples of `s`'s game, car and track not yet known.
+    pub fn for_sample(s: &TelemetrySample) -> Self {
+        Self::new(&format!("{:?}", s.game).to_lowercase(), "Unknown", "Unknown")
+    }
+
+    /// Add a sample; returns the lap it completes, if any.
+    pub fn push(&mut self, s: &TelemetrySample) -> Option<Lap> {
+        match s.phase {
+            Phase::Driving => {}
+            Phase::Paused => return None,
+            phase => {
+                if self.last.is_some() {
+                    self.drop_lap();
+                    self.partial = false;
+                }
+                self.left_garage = phase == Phase::Garage;
+                return None;
+            }
+        }
+        if self.last.is_none() && !std::mem::take(&mut self.left_garage) && joined_mid_lap(s) {
+            self.partial = true;
+        }
+        if let Some(last) = self.last.as_ref().filter(|l| s.sim_time_s < l.sim_time_s) {
+            if s.current_lap < last.current_lap {
+                self.restart_lap();
+            } else {
+                self.rewind(s.sim_time_s * 1000.0);
+            }
+        }
+        // initialise start pos
+        let posx = s.world_pos_x; let posy = s.world_pos_y;
+        if self.start_pos.is_none() && s.speed_mps > 0.1 { self.start_pos = Some((posx, posy)); }
+
+        // compute time and distance
+        let t_ms = s.sim_time_s * 1000.0;
+        let measured = s.lap_distance_m > 0.0;
+        let mut step = 0.0;
+        if let Some(last) = &self.last {
+            let dx = (s.world_pos_x - last.world_pos_x) as f64;
+            let dy = (s.world_pos_y - last.world_pos_y) as f64;
+            step = (dx*dx + dy*dy).sqrt();
+        }
+        let lap_dist = if measured { s.lap_distance_m as f64 } else { self.cum_dist + step };
+        self.cum_dist = lap_dist;
+
+        let mut point = TelemetryPoint {
+            t_ms, lap_distance_m: lap_dist,
+            x: posx as f64, y: posy as f64,
+            speed_kph: (s.speed_mps * 3.6) as f64,
+            throttle: s.throttle as f64,
+            brake: s.brake as f64,
+            gear: s.gear,
+            rpm: s.engine_rpm as f64,
+            yaw: s.yaw as f64,
+        };
+
+        if !self.lap_ended(s, t_ms) {
+            if let Some(lap) = &mut self.current {
+                // yet to move off: start over from this sample
+                let waiting = |p: &TelemetryPoint| p.speed_kph < STOPPED_KPH;
+                if waiting(&point) && !lap.points.is_empty() && lap.points.iter().chain(&self.thinning.held).all(waiting) {
+                    self.dropped += lap.points.len() as u64;
+                    lap.points.clear();
+                    self.thinning = Thinning::default();
+                    self.live.begin_lap(lap.meta.lap_number);
+                }
+            }
+            self.live.push(&point);
+            if let Some(lap) = &mut self.current {
+                self.thinning.add(lap, point, self.thin.as_ref());
+            }
+            self.last = Some(s.clone());
+            self.last_t_ms = t_ms;
+            return None;
+        }
+
+        // the game's lap counter only just turned up, further into the lap
+        // than the last sample: the car wasn't seen crossing the line, so
+        // neither side of it is a whole lap
+        let counter_late = self.last.as_ref().is_some_and(|l| {
+            l.current_lap == 0 && s.current_lap_time_s as f64 * 1000.0 > t_ms - l.sim_time_s * 1000.0
+        });
+        if counter_late {
+            self.partial = true;
+        }
+
+        let mut done = None;
+        if let Some(mut finished) = self.current.take() {
+            // the line was crossed between the last sample and this one:
+            // the lap ends on a point interpolated at the crossing and the
+            // next starts on the same point, at distance 0
+            let prev = self.thinning.held.last().or(finished.points.last()).cloned().unwrap_or_else(|| point.clone());
+            let f = crossing(&prev, &point, s, measured, step);
+            let mut end = lerp_point(&prev, &point, f);
+            end.lap_distance_m = prev.lap_distance_m + f * step;
+            self.live.push(&end);
+            // the lap ends where the car got to, kept or not
+            finished.points.extend(std::mem::take(&mut self.thinning).held.pop());
+            finished.points.push(end.clone());
+            finished.total_time_ms = (end.t_ms - finished.points.first().map(|p| p.t_ms).unwrap_or(end.t_ms)).round() as u64;
+            // normalize lap distance to end value
+            if end.lap_distance_m > self.track_guess_m { self.track_guess_m = end.lap_distance_m; }
+            finished.markers = analysis::anomaly::detect_anomalies(&finished, &analysis::anomaly::AnomalyConfig::default());
+            flag_out_in(&mut finished);
+            if !self.partial {
+                self.live.complete_lap(&finished);
+                self.last_lap_ms = Some(finished.total_time_ms);
+            }
+            // new lap
+            let next_num = if s.current_lap > 0 { s.current_lap } else { finished.meta.lap_number + 1 };
+            let mut lap = new_lap(&self.game, &self.car, &self.track, next_num);
+            self.live.begin_lap(next_num);
+            let start = TelemetryPoint { lap_distance_m: 0.0, ..end };
+            self.live.push(&start);
+            self.thinning.add(&mut lap, start, self.thin.as_ref());
+            self.cum_dist = (1.0 - f) * step;
+            if !measured {
+                point.lap_distance_m = self.cum_dist;
+            }
+            if point.t_ms > lap.points[0].t_ms {
+                self.live.push(&point);
+                self.thinning.add(&mut lap, point, self.thin.as_ref());
+            }
+            self.current = Some(lap);
+            if std::mem::take(&mut self.partial) {
+                self.dropped += finished.points.len() as u64;
+            } else {
+                done = Some(finished);
+            }
+            self.partial = counter_late;
+        }
+
+        self.last = Some(s.clone());
+        self.last_t_ms = t_ms;
+        done
+    }
+
+    /// Throw away the lap in progress and start over from the next sample,
+    /// marking the new lap partial since it starts wherever the car is.
+    pub fn restart_lap(&mut self) {
+        self.drop_lap();
+        self.partial = true;
+    }
+
+    /// Whether `s`, at `t_ms`, is past the end of the lap in progress by
+    /// `completion`.
+    fn lap_ended(&self, s: &TelemetrySample, t_ms: f64) -> bool {
+        let Some(last) = &self.last else { return false };
+        match self.completion {
+            LapCompletion::LapNumber if s.current_lap > 0 => s.current_lap > last.current_lap,
+            LapCompletion::LapNumber => self.back_at_start(s, t_ms, START_RADIUS_M, MIN_LAP_S),
+            LapCompletion::DistanceReset { min_drop_m } => (last.lap_distance_m - s.lap_distance_m) as f64 > min_drop_m,
+            LapCompletion::Position { radius_m, min_lap_s } => self.back_at_start(s, t_ms, radius_m, min_lap_s),
+        }
+    }
+
+    /// Whether `s` has the car moving within `radius_m` of where it
+    /// started, at least `min_lap_s` into the lap.
+    fn back_at_start(&self, s: &TelemetrySample, t_ms: f64, radius_m: f64, min_lap_s: f64) -> bool {
+        let (Some(sp), Some(lap)) = (self.start_pos, &self.current) else { return false };
+        let d = ((s.world_pos_x - sp.0) as f64).hypot((s.world_pos_y - sp.1) as f64);
+        let elapsed = t_ms - lap.points.first().map(|p| p.t_ms).unwrap_or(t_ms);
+        d < radius_m && elapsed > min_lap_s * 1000.0 && s.speed_mps > 1.0
+    }
+
+    /// Cut the lap in progress back to its points before `t_ms`, to carry
+    /// on from a sample there. Throws it away as partial when that leaves
+    /// none.
+    fn rewind(&mut self, t_ms: f64) {
+        let Some(lap) = &mut self.current else { return };
+        lap.points.extend(std::mem::take(&mut self.thinning).held);
+        let before = lap.points.len();
+        lap.points.retain(|p| p.t_ms < t_ms);
+        self.dropped += (before - lap.points.len()) as u64;
+        if lap.points.is_empty() {
+            self.restart_lap();
+            return;
+        }
+        let (first, end) = (&lap.points[0], &lap.points[lap.points.len() - 1]);
+        lap.total_time_ms = (end.t_ms - first.t_ms).round() as u64;
+        // the next step is measured from where the car was rewound to
+        self.cum_dist = end.lap_distance_m;
+        self.last = None;
+        self.live.begin_lap(lap.meta.lap_number);
+        for p in &lap.points {
+            self.live.push(p);
+        }
+    }
+
+    /// Throw away the lap in progress; the next sample starts it over.
+    fn drop_lap(&mut self) {
+        let num = self.current.as_ref().map(|l| l.meta.lap_number).unwrap_or(1);
+        self.dropped += self.current.as_ref().map_or(0, |l| l.points.len() as u64);
+        self.current = Some(new_lap(&self.game, &self.car, &self.track, num));
+        self.live.begin_lap(num);
+        self.thinning = Thinning::default();
+        self.last = None;
+        self.cum_dist = 0.0;
+    }
+}
+
+impl Thinning {
+    /// Add `p` to the lap in progress, thinned with `opts` if set.
+    fn add(&mut self, lap: &mut Lap, p: TelemetryPoint, opts: Option<&ThinOptions>) {
+        let t_ms = p.t_ms;
+        match opts {
+            Some(opts) => self.push(&mut lap.points, p, opts),
+            None => lap.points.push(p),
+        }
+        lap.total_time_ms = (t_ms - lap.points.first().map(|p| p.t_ms).unwrap_or(t_ms)).round() as u64;
+    }
+
+    /// Add `p` to `points`, keeping only the points interpolation can't
+    /// stand in for under `opts`.
+    fn push(&mut self, points: &mut Vec<TelemetryPoint>, p: TelemetryPoint, opts: &ThinOptions) {
+        let Some(kept) = points.last() else {
+            points.push(p);
+            return;
+        };
+        let prev = self.held.last().unwrap_or(kept);
+        let (dx, dy) = (p.x - prev.x, p.y - prev.y);
+        let step = dx.hypot(dy);
+        // too short a step to tell a direction from
+        let heading = (step > 0.01).then(|| dy.atan2(dx));
+        let cornering = match (heading, self.heading) {
+            (Some(h), Some(h0)) => wrap_angle(h - h0).abs() / step > 1.0 / opts.corner_radius_m,
+            _ => false,
+        };
+        if heading.is_some() {
+            self.heading = heading;
+        }
+
+        let braking = p.brake > opts.pedal || kept.brake > opts.pedal;
+        if cornering || braking || p.gear != kept.gear {
+            points.extend(self.held.pop());
+            self.held.clear();
+            points.push(p);
+            return;
+        }
+        let fits = p.lap_distance_m - kept.lap_distance_m <= opts.max_gap_m
+            && self.held.iter().all(|h| interpolates(kept, &p, h, opts));
+        if !fits {
+            // the last held point still fitted the line to it; keep that
+            points.extend(self.held.pop());
+            self.held.clear();
+        }
+        self.held.push(p);
+    }
+}
+
+/// Whether `s`, the first sample seen, finds the car already into a lap the
+/// game counts: moving, or with time or distance into it.
+fn joined_mid_lap(s: &TelemetrySample) -> bool {
+    s.current_lap > 0
+        && (s.speed_mps as f64 * 3.6 >= STOPPED_KPH
+            || s.current_lap_time_s > 0.0
+            || s.lap_distance_m as f64 > OUT_LAP_START_M)
+}
+
+/// Set `lap`'s out-lap and in-lap flags; see the module docs.
+fn flag_out_in(lap: &mut Lap) {
+    let points = &lap.points;
+    let (Some(first), Some(last)) = (points.first(), points.last()) else { return };
+    // the last point can be past the line, so go by the furthest one
+    let start_m = first.lap_distance_m;
+    let half_m = start_m + (points.iter().map(|p| p.lap_distance_m).fold(start_m, f64::max) - start_m) / 2.0;
+    let mut out_lap = first.speed_kph < STOPPED_KPH || start_m > OUT_LAP_START_M;
+    let mut in_lap = false;
+
+    let mut stopped: Option<&TelemetryPoint> = None;
+    for p in points {
+        if p.speed_kph >= STOPPED_KPH {
+            stopped = None;
+            continue;
+        }
+        let since = *stopped.get_or_insert(p);
+        if p.t_ms - since.t_ms >= STOP_MS {
+            if since.lap_distance_m < half_m {
+                out_lap = true;
+            } else {
+                in_lap = true;
+            }
+        }
+    }
+
+    if last.t_ms - first.t_ms > PIT_LANE_MS {
+        let (lo, hi) = points
+            .iter()
+            .rev()
+            .take_while(|p| last.t_ms - p.t_ms <= PIT_LANE_MS)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| (lo.min(p.speed_kph), hi.max(p.speed_kph)));
+        in_lap |= lo >= STOPPED_KPH && hi < PIT_LIMIT_KPH && hi - lo <= LIMITER_SPREAD_KPH;
+    }
+    lap.meta.out_lap = out_lap;
+    lap.meta.in_lap = in_lap;
+}
+
+/// How far from `prev` to `p` (0..=1) the car crossed the line: by the
+/// game's time into the new lap when it has one, else by how far past the
+/// line its lap distance puts `p` over the `step` between them. On `p`
+/// itself when neither tells.
+fn crossing(prev: &TelemetryPoint, p: &TelemetryPoint, s: &TelemetrySample, measured: bool, step: f64) -> f64 {
+    let dt = p.t_ms - prev.t_ms;
+    let into_ms = s.current_lap_time_s as f64 * 1000.0;
+    if dt > 0.0 && into_ms > 0.0 && into_ms < dt {
+        1.0 - into_ms / dt
+    } else if measured && p.lap_distance_m < prev.lap_distance_m && p.lap_distance_m < step {
+        1.0 - p.lap_distance_m / step
+    } else {
+        1.0
+    }
+}
+
+/// The point `f` (0..=1) of the way from `a` to `b`: gear from the nearer
+/// one, heading the short way round.
+fn lerp_point(a: &TelemetryPoint, b: &TelemetryPoint, f: f64) -> TelemetryPoint {
+    let lerp = |u: f64, v: f64| u + f * (v - u);
+    TelemetryPoint {
+        t_ms: lerp(a.t_ms, b.t_ms),
+        lap_distance_m: lerp(a.lap_distance_m, b.lap_distance_m),
+        x: lerp(a.x, b.x),
+        y: lerp(a.y, b.y),
+        speed_kph: lerp(a.speed_kph, b.speed_kph),
+        throttle: lerp(a.throttle, b.throttle),
+        brake: lerp(a.brake, b.brake),
+        gear: if f < 0.5 { a.gear } else { b.gear },
+        rpm: lerp(a.rpm, b.rpm),
+        yaw: a.yaw + f * wrap_angle(b.yaw - a.yaw),
+    }
+}
+
+/// Whether `h` is within `opts` of the line from `a` to `b`.
+fn interpolates(a: &TelemetryPoint, b: &TelemetryPoint, h: &TelemetryPoint, opts: &ThinOptions) -> bool {
+    let span = b.lap_distance_m - a.lap_distance_m;
+    let f = if span > 0.0 { ((h.lap_distance_m - a.lap_distance_m) / span).clamp(0.0, 1.0) } else { 0.0 };
+    let off = |u: f64, v: f64, w: f64| (u + f * (v - u) - w).abs();
+    off(a.x, b.x, h.x).hypot(off(a.y, b.y, h.y)) <= opts.position_m
+        && off(a.t_ms, b.t_ms, h.t_ms) <= opts.time_ms
+        && off(a.speed_kph, b.speed_kph, h.speed_kph) <= opts.speed_kph
+        && off(a.throttle, b.throttle, h.throttle) <= opts.pedal
+        && off(a.brake, b.brake, h.brake) <= opts.pedal
+}
+
+fn new_lap(game: &str, car: &str, track: &str, num: u32) -> Lap {
+    Lap {
+        id: Uuid::new_v4(),
+        meta: LapMeta { id: Uuid::new_v4(), game: game.into(), car: car.into(), track: track.into(), lap_number: num, out_lap: false, in_lap: false },
+        total_time_ms: 0,
+        points: vec![],
+        markers: vec![],
+        index: Default::default(),
+    }
+}
+
+/// Laps completed in `samples`, built per session and car, in the order they
+/// were finished. The lap each car is on at the end is left out.
+pub fn build_laps<'a>(samples: impl IntoIterator<Item = &'a TelemetrySample>) -> Vec<Lap> {
+    let mut builders: HashMap<(&str, &str), LapBuilder> = HashMap::new();
+    let mut laps = Vec::new();
+    for s in samples {
+        let b = builders.entry((&s.session_uid, &s.car_id)).or_insert_with(|| LapBuilder::for_sample(s));
+        laps.extend(b.push(s));
+    }
+    laps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use delta_ingest_core::Game;
+
+    const LAP_M: f64 = 1000.0;
+    const STEP_S: f64 = 0.1;
+
+    /// Samples every 100 ms from `t0_s` to before `t1_s` of a car going
+    /// round a 1 km circle, starting `d0_m` into lap 1 at `kph(t_s, m)`,
+    /// `m` being how far it has gone in all. Lap numbers, lap times and lap
+    /// distances are what the game would send.
+    fn drive(t0_s: f64, t1_s: f64, d0_m: f64, kph: impl Fn(f64, f64) -> f64) -> Vec<TelemetrySample> {
+        let r = LAP_M / std::f64::consts::TAU;
+        let (mut m, mut lap_start_s) = (d0_m, t0_s);
+        let mut out = Vec::new();
+        for i in 0.. {
+            let t = t0_s + i as f64 * STEP_S;
+            if t >= t1_s {
+                break;
+            }
+            let v = kph(t, m) / 3.6;
+            let (lap, d) = ((m / LAP_M) as u32 + 1, m % LAP_M);
+            let a = d / r;
+            out.push(TelemetrySample {
+                game: Game::F1_2024,
+                car_id: "0".into(),
+                session_uid: "s".into(),
+                frame: i,
+                sim_time_s: t,
+                speed_mps: v as f32,
+                throttle: 1.0,
+                brake: 0.0,
+                gear: 6,
+                engine_rpm: 10000.0,
+                world_pos_x: (r * a.sin()) as f32,
+                world_pos_y: (r * (1.0 - a.cos())) as f32,
+                world_pos_z: 0.0,
+                yaw: a as f32,
+                pitch: 0.0,
+                roll: 0.0,
+                lap_distance_m: d as f32,
+                current_lap: lap,
+                current_lap_time_s: (t - lap_start_s) as f32,
+                last_lap_time_s: 0.0,
+                best_lap_time_s: 0.0,
+                fuel_l: 0.0,
+                fuel_capacity_l: 0.0,
+                rev_limiter: false,
+                phase: Phase::Driving,
+            });
+            let next = m + v * STEP_S;
+            if (next / LAP_M) as u32 > (m / LAP_M) as u32 {
+                // when the line was crossed on the way to the next sample
+                lap_start_s = t + ((next / LAP_M).floor() * LAP_M - m) / v;
+            }
+            m = next;
+        }
+        out
+    }
+
+    /// 180 km/h, a 20 s lap. Starting 1 m in puts each line crossing 20 ms
+    /// after 30 ms past a sample: at 20.03 s, 40.03 s, ...
+    fn flying(t1_s: f64) -> Vec<TelemetrySample> {
+        drive(0.05, t1_s, 1.0, |_, _| 180.0)
+    }
+
+    fn push_all<'a>(b: &mut LapBuilder, samples: impl IntoIterator<Item = &'a TelemetrySample>) -> Vec<Lap> {
+        samples.into_iter().filter_map(|s| b.push(s)).collect()
+    }
+
+    fn builder() -> LapBuilder {
+        LapBuilder::new("f1_2024", "car", "track")
+    }
+
+    fn close(a: f64, b: f64, tol: f64) -> bool {
+        (a - b).abs() < tol
+    }
+
+    /// Checks the lap ending at `end_ms` closes on the same point the next
+    /// one opens on, at the line.
+    fn assert_rollover(finished: &Lap, next: &Lap, start_ms: f64, end_ms: f64) {
+        let (first, end) = (&finished.points[0], finished.points.last().unwrap());
+        assert!(close(first.t_ms, start_ms, 0.01) && first.lap_distance_m == 0.0, "{first:?}");
+        assert!(close(end.t_ms, end_ms, 0.01), "{end:?}");
+        assert!(close(end.lap_distance_m, LAP_M, 0.01), "{end:?}");
+        assert_eq!(finished.total_time_ms, (end_ms - start_ms).round() as u64);
+
+        let start = &next.points[0];
+        assert_eq!((start.t_ms, start.x, start.y, start.lap_distance_m), (end.t_ms, end.x, end.y, 0.0));
+        // then on to the first sample past the line
+        let after = &next.points[1];
+        assert!(close(after.t_ms, end_ms + 20.0, 0.01) && close(after.lap_distance_m, 1.0, 1e-3), "{after:?}");
+    }
+
+    #[test]
+    fn a_lap_ends_where_the_line_was_crossed_by_the_lap_time() {
+        let mut b = builder();
+        let laps = push_all(&mut b, &flying(45.0));
+        // lap 1 was joined part way round
+        assert_eq!(laps.iter().map(|l| l.meta.lap_number).collect::<Vec<_>>(), [2]);
+        assert_rollover(&laps[0], b.current.as_ref().unwrap(), 20_030.0, 40_030.0);
+        assert_eq!(b.last_lap_ms, Some(20_000));
+    }
+
+    #[test]
+    fn without_a_lap_time_the_crossing_goes_by_lap_distance() {
+        let mut samples = flying(45.0);
+        samples.iter_mut().for_each(|s| s.current_lap_time_s = 0.0);
+        let mut b = builder();
+        let laps = push_all(&mut b, &samples);
+        assert_eq!(laps.len(), 1);
+        assert_rollover(&laps[0], b.current.as_ref().unwrap(), 20_030.0, 40_030.0);
+    }
+
+    #[test]
+    fn a_rewind_cuts_the_lap_back_and_it_carries_on() {
+        let samples = flying(65.0);
+        // to 29.95 s into lap 2, then a flashback to 25.05 s
+        let (ahead, rewound) = (&samples[..300], &samples[250..]);
+        let mut b = builder();
+        assert!(push_all(&mut b, ahead).is_empty());
+        let dropped = b.dropped;
+        assert!(b.push(&rewound[0]).is_none());
+        let lap = b.current.as_ref().unwrap();
+        assert_eq!(lap.points.last().unwrap().t_ms, 25_050.0);
+        assert!(lap.points.iter().rev().skip(1).all(|p| p.t_ms < 25_050.0));
+        assert_eq!(b.dropped - dropped, 50, "the points from 25.05 s to 29.95 s");
+
+        let laps = push_all(&mut b, &rewound[1..]);
+        assert_eq!(laps.iter().map(|l| l.meta.lap_number).collect::<Vec<_>>(), [2, 3]);
+        assert!(laps[0].points.windows(2).all(|w| w[0].t_ms < w[1].t_ms));
+        // as if the rewound stretch was only driven once
+        let straight = push_all(&mut builder(), &samples);
+        assert_eq!(laps[0].points, straight[0].points);
+        assert_eq!(laps[0].total_time_ms, 20_000);
+    }
+
+    #[test]
+    fn a_lap_joined_part_way_round_is_dropped() {
+        let mut b = builder();
+        // on at 400 m into lap 1, which is done 12 s later
+        let laps = push_all(&mut b, &drive(0.05, 25.0, 400.0, |_, _| 180.0));
+        assert!(laps.is_empty());
+        assert!(b.dropped >= 120, "{}", b.dropped);
+        assert_eq!(b.last_lap_ms, None);
+        assert!(!b.partial, "lap 2 was seen from the line");
+
+        // from the grid, lap 1 is whole
+        let mut b = builder();
+        let laps = push_all(&mut b, &drive(0.0, 40.0, 0.0, |t, _| if t < 1.0 { 0.0 } else { 180.0 }));
+        assert_eq!(laps.iter().map(|l| l.meta.lap_number).collect::<Vec<_>>(), [1]);
+        // from the last moment it stood still
+        assert_eq!(laps[0].points[0].t_ms, 900.0);
+    }
+
+    #[test]
+    fn a_restarted_lap_is_dropped() {
+        let samples = flying(65.0);
+        let mut b = builder();
+        push_all(&mut b, &samples[..300]);
+        // say, a resume after a while with no samples
+        b.restart_lap();
+        let laps = push_all(&mut b, &samples[300..]);
+        assert_eq!(laps.iter().map(|l| l.meta.lap_number).collect::<Vec<_>>(), [3]);
+        assert_eq!(laps[0].total_time_ms, 20_000);
+    }
+
+    #[test]
+    fn pit_exit_flying_lap_and_pit_entry() {
+        // 3 s in the pit box 100 m in, then flat out until the last 200 m
+        // of lap 3, taken at the pit lane limit
+        let kph = |t: f64, m: f64| match (t, m) {
+            (t, _) if t < 3.0 => 0.0,
+            (_, m) if m > 2800.0 => 60.0,
+            _ => 180.0,
+        };
+        let laps = push_all(&mut builder(), &drive(0.0, 75.0, 100.0, kph));
+        let flags: Vec<_> = laps.iter().map(|l| (l.meta.lap_number, l.meta.out_lap, l.meta.in_lap)).collect();
+        assert_eq!(flags, [(1, true, false), (2, false, false), (3, false, true)]);
+    }
+
+    #[test]
+    fn stopping_late_in_a_lap_makes_it_an_in_lap() {
+        // off the grid, then 3 s stood still 650 m into lap 2
+        let kph = |t: f64, _| if t < 1.0 || (34.0..37.0).contains(&t) { 0.0 } else { 180.0 };
+        let laps = push_all(&mut builder(), &drive(0.0, 50.0, 0.0, kph));
+        let flags: Vec<_> = laps.iter().map(|l| (l.meta.lap_number, l.meta.out_lap, l.meta.in_lap)).collect();
+        // a standing start counts as an out-lap
+        assert_eq!(flags, [(1, true, false), (2, false, true)]);
+    }
+
+    fn numbers(laps: &[Lap]) -> Vec<u32> {
+        laps.iter().map(|l| l.meta.lap_number).collect()
+    }
+
+    #[test]
+    fn laps_end_on_the_lap_number() {
+        let mut samples = flying(65.0);
+        let laps = push_all(&mut builder(), &samples);
+        assert_eq!(numbers(&laps), [2, 3]);
+        // the lap distance dropping back doesn't end one by itself
+        samples.iter_mut().for_each(|s| s.current_lap = 1);
+        assert!(push_all(&mut builder(), &samples).is_empty());
+    }
+
+    #[test]
+    fn laps_end_on_the_lap_distance_dropping_back() {
+        let mut samples = flying(65.0);
+        for s in &mut samples {
+            s.current_lap = 0;
+        }
+        // a glitch 500 m into lap 2 that doesn't drop far enough
+        samples[300].lap_distance_m -= 300.0;
+        let mut b = builder();
+        b.completion = LapCompletion::DistanceReset { min_drop_m: 500.0 };
+        let laps = push_all(&mut b, &samples);
+        // without lap numbers the first lap can't be told apart from a whole one
+        assert_eq!(numbers(&laps), [1, 2, 3]);
+        assert_eq!(laps.iter().map(|l| l.total_time_ms).collect::<Vec<_>>(), [19_980, 20_000, 20_000]);
+        assert_rollover(&laps[1], &laps[2], 20_030.0, 40_030.0);
+    }
+
+    #[test]
+    fn laps_end_back_at_the_start_position() {
+        // nothing but the car's position to go by
+        let mut samples = flying(65.0);
+        for s in &mut samples {
+            (s.current_lap, s.current_lap_time_s, s.lap_distance_m) = (0, 0.0, 0.0);
+        }
+        let with = |radius_m, min_lap_s| {
+            let mut b = builder();
+            b.completion = LapCompletion::Position { radius_m, min_lap_s };
+            push_all(&mut b, &samples)
+        };
+        // within 30 m of where it started 0.6 s before each line crossing
+        let laps = with(30.0, 10.0);
+        assert_eq!(numbers(&laps), [1, 2, 3]);
+        assert_eq!(laps.iter().map(|l| l.total_time_ms).collect::<Vec<_>>(), [19_400, 20_000, 20_000]);
+        assert!(close(laps[1].points.last().unwrap().lap_distance_m, LAP_M, 1.0));
+        // without the time guard, each sample still in the start area
+        // past the line counts another lap
+        assert!(with(30.0, 0.0).len() > 3);
+    }
+
+    #[test]
+    fn thinning_keeps_braking_zones_and_corners() {
+        // 500 m east, braking from 200 m to 220 m, a 30 m radius left
+        // hander and on north: 5 m a sample
+        let arc_m = 30.0 * std::f64::consts::FRAC_PI_2;
+        let at = |d: f64| match d {
+            d if d < 500.0 => (d, 0.0),
+            d if d < 500.0 + arc_m => {
+                let a = (d - 500.0) / 30.0;
+                (500.0 + 30.0 * a.sin(), 30.0 * (1.0 - a.cos()))
+            }
+            d => (530.0, 30.0 + d - 500.0 - arc_m),
+        };
+        let mut samples = flying(17.0);
+        for s in &mut samples {
+            let d = s.lap_distance_m as f64;
+            let (x, y) = at(d);
+            (s.world_pos_x, s.world_pos_y) = (x as f32, y as f32);
+            s.brake = if (200.0..220.0).contains(&d) { 0.8 } else { 0.0 };
+        }
+
+        let mut b = builder();
+        b.thin = Some(ThinOptions::default());
+        push_all(&mut b, &samples);
+        let kept: Vec<TelemetryPoint> = b.current.unwrap().points.into_iter().chain(b.thinning.held).collect();
+        let is_kept = |s: &TelemetrySample| kept.iter().any(|p| p.t_ms == s.sim_time_s * 1000.0);
+
+        assert!(kept.len() < samples.len() / 2, "{} of {}", kept.len(), samples.len());
+        assert!(samples.iter().filter(|s| s.brake > 0.0).all(is_kept));
+        let corner = samples.iter().filter(|s| (505.0..495.0 + arc_m).contains(&(s.lap_distance_m as f64)));
+        assert_eq!(corner.clone().count(), 8);
+        assert!(corner.clone().all(is_kept));
+        // and still no more than 25 m between points on the straights
+        assert!(kept.windows(2).all(|w| w[1].lap_distance_m - w[0].lap_distance_m <= 25.0));
+    }
+
+    fn with_phase(samples: &mut [TelemetrySample], phase: Phase, during: std::ops::Range<f64>) {
+        for s in samples.iter_mut().filter(|s| during.contains(&s.sim_time_s)) {
+            s.phase = phase;
+        }
+    }
+
+    #[test]
+    fn samples_off_track_never_start_or_extend_a_lap() {
+        // a formation lap across the line, then racing from 25 s
+        let mut samples = flying(65.0);
+        with_phase(&mut samples, Phase::Formation, 0.0..25.0);
+        let mut b = builder();
+        let (formation, racing) = samples.split_at(250);
+        assert!(push_all(&mut b, formation).is_empty());
+        assert!(b.current.as_ref().unwrap().points.is_empty());
+        // lap 2 was already under way when the race started
+        assert_eq!(numbers(&push_all(&mut b, racing)), [3]);
+
+        // the garage, then out of the pit box at 3 s
+        let mut samples = drive(0.0, 45.0, 100.0, |t, _| if t < 3.0 { 0.0 } else { 180.0 });
+        with_phase(&mut samples, Phase::Garage, 0.0..2.0);
+        let mut b = builder();
+        assert!(push_all(&mut b, &samples[..20]).is_empty());
+        assert!(b.current.as_ref().unwrap().points.is_empty());
+        let laps = push_all(&mut b, &samples[20..]);
+        // not joined part way, though the lap timer has been running
+        assert_eq!(numbers(&laps), [1, 2]);
+        assert!(laps[0].meta.out_lap && laps[0].points[0].t_ms >= 2000.0);
+
+        // menus part way round throw the lap away
+        let mut samples = flying(65.0);
+        with_phase(&mut samples, Phase::Menu, 30.0..30.5);
+        assert_eq!(numbers(&push_all(&mut builder(), &samples)), [3]);
+    }
+
+    #[test]
+    fn a_pause_leaves_the_lap_as_it_was() {
+        let mut samples = flying(65.0);
+        with_phase(&mut samples, Phase::Paused, 30.0..32.0);
+        let laps = push_all(&mut builder(), &samples);
+        assert_eq!(numbers(&laps), [2, 3]);
+        assert!(!laps[0].points.iter().any(|p| (30_000.0..32_000.0).contains(&p.t_ms)));
+        assert_eq!(laps[0].total_time_ms, 20_000);
+    }
+
+    #[test]
+    fn importing_builds_the_laps_recording_does() {
+        // two cars interleaved, and a new session for the second half
+        let mut other = drive(0.05, 125.0, 1.0, |_, _| 150.0);
+        other.iter_mut().for_each(|s| s.car_id = "1".into());
+        let mut samples: Vec<TelemetrySample> = flying(125.0).into_iter().zip(other).flat_map(|(a, b)| [a, b]).collect();
+        let half = samples.len() / 2;
+        samples[half..].iter_mut().for_each(|s| s.session_uid = "s2".into());
+
+        // as the recorder does: a builder per session and car
+        let mut builders: HashMap<(String, String), LapBuilder> = HashMap::new();
+        let mut live = Vec::new();
+        for s in &samples {
+            let b = builders.entry((s.session_uid.clone(), s.car_id.clone())).or_insert_with(|| LapBuilder::for_sample(s));
+            live.extend(b.push(s));
+        }
+
+        let imported = build_laps(&samples);
+        assert_eq!(imported.len(), live.len());
+        assert_eq!(imported.len(), 7);
+        for (a, b) in imported.iter().zip(&live) {
+            assert_eq!(a.meta.lap_number, b.meta.lap_number);
+            assert_eq!((&a.meta.game, a.meta.out_lap, a.meta.in_lap), (&b.meta.game, b.meta.out_lap, b.meta.in_lap));
+            assert_eq!((a.total_time_ms, &a.points, &a.markers), (b.total_time_ms, &b.points, &b.markers));
+        }
+    }
+}
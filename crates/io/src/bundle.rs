@@ -0,0 +1,156 @@
+//! Session bundles (`.deltasession`): one zip holding everything needed to
+//! pick a session up on another machine.
+//!
+//! Entries:
+//! - `manifest.json` — [`BundleManifest`]
+//! - `laps.dlap` — every lap in the [binary](crate::binary) format
+//! - `track_map.json` — map built from the fastest lap, if any
+//! - `summary.json` — [`SessionSummary`]
+//! - `corners.json` — [`CornerMetrics`] of the fastest lap, if any
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, Write},
+    path::Path,
+};
+use uuid::Uuid;
+use zip::{result::ZipError, write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use analysis::{CornerMetrics, SessionSummary};
+use model::*;
+
+use crate::binary::{BinaryReader, BinaryWriter};
+use crate::gpx::iso8601;
+
+pub const EXTENSION: &str = "deltasession";
+pub const VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const LAPS: &str = "laps.dlap";
+const TRACK_MAP: &str = "track_map.json";
+const SUMMARY: &str = "summary.json";
+const CORNERS: &str = "corners.json";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BundleManifest {
+    pub version: u32,
+    /// UTC time the bundle was written, ISO 8601.
+    pub created: String,
+    pub lap_count: usize,
+    pub games: Vec<String>,
+    pub cars: Vec<String>,
+    pub tracks: Vec<String>,
+    /// Lap the track map and corner metrics were built from.
+    #[serde(default)]
+    pub reference_lap: Option<Uuid>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionBundle {
+    pub manifest: BundleManifest,
+    pub laps: Vec<Lap>,
+    pub track_map: Option<TrackMap>,
+    pub summary: SessionSummary,
+    pub corners: Option<CornerMetrics>,
+}
+
+impl SessionBundle {
+    /// Bundle `laps` with their analysis, using the fastest lap as reference.
+    pub fn new(laps: Vec<Lap>) -> Self {
+        let reference = laps.iter().filter(|l| !l.points.is_empty()).min_by_key(|l| l.total_time_ms);
+        let track_map = reference.map(analysis::build_track_map);
+        let corners = reference.map(analysis::corner_metrics);
+        let manifest = BundleManifest {
+            version: VERSION,
+            created: iso8601(time::OffsetDateTime::now_utc()),
+            lap_count: laps.len(),
+            games: distinct(&laps, |m| &m.game),
+            cars: distinct(&laps, |m| &m.car),
+            tracks: distinct(&laps, |m| &m.track),
+            reference_lap: reference.map(|l| l.id),
+        };
+        let summary = analysis::session_summary(&laps);
+        Self { manifest, laps, track_map, summary, corners }
+    }
+}
+
+fn distinct(laps: &[Lap], f: impl Fn(&LapMeta) -> &String) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for l in laps {
+        let v = f(&l.meta);
+        if !out.contains(v) {
+            out.push(v.clone());
+        }
+    }
+    out
+}
+
+pub fn write_bundle<W: Write + Seek>(bundle: &SessionBundle, inner: W) -> Result<W> {
+    let mut zip = ZipWriter::new(inner);
+    let deflate = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // lap frames are zstd already
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    zip.start_file(MANIFEST, deflate)?;
+    serde_json::to_writer_pretty(&mut zip, &bundle.manifest)?;
+
+    zip.start_file(LAPS, stored)?;
+    let mut w = BinaryWriter::new(&mut zip)?;
+    for l in &bundle.laps {
+        w.write_lap(l)?;
+    }
+    w.finish()?;
+
+    if let Some(map) = &bundle.track_map {
+        zip.start_file(TRACK_MAP, deflate)?;
+        serde_json::to_writer(&mut zip, map)?;
+    }
+    zip.start_file(SUMMARY, deflate)?;
+    serde_json::to_writer_pretty(&mut zip, &bundle.summary)?;
+    if let Some(corners) = &bundle.corners {
+        zip.start_file(CORNERS, deflate)?;
+        serde_json::to_writer(&mut zip, corners)?;
+    }
+    Ok(zip.finish()?)
+}
+
+pub fn read_bundle<R: Read + Seek>(inner: R) -> Result<SessionBundle> {
+    let mut zip = ZipArchive::new(inner).context("not a session bundle")?;
+    let manifest: BundleManifest =
+        json_entry(&mut zip, MANIFEST)?.ok_or_else(|| anyhow!("session bundle has no {MANIFEST}"))?;
+    if manifest.version > VERSION {
+        bail!("session bundle version {} is newer than supported {VERSION}", manifest.version);
+    }
+    let laps = {
+        let entry = zip.by_name(LAPS).map_err(|_| anyhow!("session bundle has no {LAPS}"))?;
+        BinaryReader::new(entry)?.collect::<Result<Vec<_>>>().context(LAPS)?
+    };
+    let track_map = json_entry(&mut zip, TRACK_MAP)?;
+    let summary = match json_entry(&mut zip, SUMMARY)? {
+        Some(s) => s,
+        None => analysis::session_summary(&laps),
+    };
+    let corners = json_entry(&mut zip, CORNERS)?;
+    Ok(SessionBundle { manifest, laps, track_map, summary, corners })
+}
+
+/// The JSON entry `name`, or `None` if the archive does not have it.
+pub(crate) fn json_entry<R: Read + Seek, T: DeserializeOwned>(zip: &mut ZipArchive<R>, name: &str) -> Result<Option<T>> {
+    match zip.by_name(name) {
+        Ok(entry) => Ok(Some(serde_json::from_reader(entry).context(name.to_string())?)),
+        Err(ZipError::FileNotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn export_bundle(laps: &[Lap], path: &Path) -> Result<()> {
+    let bundle = SessionBundle::new(laps.to_vec());
+    write_bundle(&bundle, BufWriter::new(File::create(path)?))?.flush()?;
+    Ok(())
+}
+
+pub fn import_bundle(path: &Path) -> Result<SessionBundle> {
+    read_bundle(BufReader::new(File::open(path)?))
+}
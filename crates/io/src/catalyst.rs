@@ -0,0 +1,375 @@
+//! Garmin Catalyst session import. Catalyst exports sessions as FIT activity
+//! files: GPS `record` messages plus one `lap` message per timed lap.
+//!
+//! Only the parts of FIT needed for that are decoded (definition and data
+//! messages, compressed timestamps, developer fields skipped). Records carry
+//! whole-second timestamps, so several samples within one second are spread
+//! evenly across it. Catalyst records no pedal inputs, so throttle and brake
+//! stay at 0; heading comes from the driven path.
+
+use anyhow::{anyhow, bail, Result};
+use std::{collections::HashMap, f64::consts::TAU, path::Path};
+use uuid::Uuid;
+
+use model::*;
+
+use crate::geo::GeoOrigin;
+
+const MSG_LAP: u16 = 19;
+const MSG_RECORD: u16 = 20;
+const FIELD_TIMESTAMP: u8 = 253;
+
+const SEMICIRCLE_DEG: f64 = 180.0 / 2_147_483_648.0;
+
+#[derive(Clone, Copy, Default)]
+struct Record {
+    t_s: u32,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    distance_m: Option<f64>,
+    speed_mps: Option<f64>,
+}
+
+#[derive(Clone, Copy)]
+struct LapSpan {
+    start_s: u32,
+    end_s: u32,
+    /// `total_elapsed_time`, the lap time Catalyst shows.
+    elapsed_ms: Option<u64>,
+}
+
+/// Import a Catalyst `.fit` export. `track` names the laps (Catalyst does not
+/// store it in the file); the file name is used when `None`.
+pub fn import_catalyst(path: &Path, track: Option<&str>, origin: Option<&GeoOrigin>) -> Result<Vec<Lap>> {
+    let bytes = std::fs::read(path)?;
+    let (records, spans) = decode_fit(&bytes)?;
+    if records.is_empty() {
+        bail!("FIT file has no GPS records");
+    }
+    let track = match track {
+        Some(t) => t.to_string(),
+        None => path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default(),
+    };
+    let origin = origin.copied().unwrap_or_else(|| {
+        records
+            .iter()
+            .find_map(|r| Some(GeoOrigin::new(r.lat?, r.lon?)))
+            .unwrap_or_default()
+    });
+    let times = sub_second_times(&records);
+
+    // without lap messages the whole recording is one lap
+    let spans = if spans.is_empty() {
+        vec![LapSpan { start_s: records[0].t_s, end_s: records[records.len() - 1].t_s, elapsed_ms: None }]
+    } else {
+        spans
+    };
+
+    let mut laps = Vec::new();
+    for span in spans {
+        let idx: Vec<usize> =
+            (0..records.len()).filter(|&i| (span.start_s..=span.end_s).contains(&records[i].t_s)).collect();
+        if idx.len() < 2 {
+            continue;
+        }
+        let mut lap = new_lap(&track, laps.len() as u32 + 1);
+        let d0 = records[idx[0]].distance_m;
+        let t0 = times[idx[0]];
+        let mut cum = 0.0;
+        for &i in &idx {
+            let r = &records[i];
+            let (x, y) = match (r.lat, r.lon) {
+                (Some(la), Some(lo)) => origin.to_local(la, lo),
+                _ => lap.points.last().map(|p| (p.x, p.y)).unwrap_or((0.0, 0.0)),
+            };
+            let prev = lap.points.last();
+            if let Some(p) = prev {
+                cum += (x - p.x).hypot(y - p.y);
+            }
+            // clockwise from north (+y), like every connector's yaw
+            let yaw = match prev {
+                Some(p) if (x - p.x).hypot(y - p.y) > 0.05 => (x - p.x).atan2(y - p.y).rem_euclid(TAU),
+                Some(p) => p.yaw,
+                None => 0.0,
+            };
+            lap.points.push(TelemetryPoint {
+                t_ms: (times[i] - t0) * 1000.0,
+                lap_distance_m: match (r.distance_m, d0) {
+                    (Some(d), Some(d0)) => d - d0,
+                    _ => cum,
+                },
+                x,
+                y,
+                speed_kph: r.speed_mps.unwrap_or(0.0) * 3.6,
+                throttle: 0.0,
+                brake: 0.0,
+                gear: 0,
+                rpm: 0.0,
+                yaw,
+            });
+        }
+        lap.total_time_ms = span
+            .elapsed_ms
+            .unwrap_or_else(|| lap.points.last().map(|p| p.t_ms.round() as u64).unwrap_or(0));
+        laps.push(lap);
+    }
+    Ok(laps)
+}
+
+/// Record times in seconds, with runs sharing a whole second spread across it.
+fn sub_second_times(records: &[Record]) -> Vec<f64> {
+    let mut out = Vec::with_capacity(records.len());
+    let mut i = 0;
+    while i < records.len() {
+        let n = records[i..].iter().take_while(|r| r.t_s == records[i].t_s).count();
+        out.extend((0..n).map(|k| records[i].t_s as f64 + k as f64 / n as f64));
+        i += n;
+    }
+    out
+}
+
+struct Definition {
+    global: u16,
+    big_endian: bool,
+    /// (field number, size, base type)
+    fields: Vec<(u8, u8, u8)>,
+    dev_size: usize,
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let s = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| anyhow!("FIT file truncated at byte {}", self.pos))?;
+        self.pos += n;
+        Ok(s)
+    }
+
+    /// Integer value of every valid field of a data message, keyed by field number.
+    fn fields(&mut self, def: &Definition) -> Result<Vec<(u8, i64)>> {
+        let mut out = Vec::with_capacity(def.fields.len());
+        for &(num, size, base) in &def.fields {
+            let raw = self.take(size as usize)?;
+            if let Some(v) = int_value(raw, base, def.big_endian) {
+                out.push((num, v));
+            }
+        }
+        self.take(def.dev_size)?;
+        Ok(out)
+    }
+}
+
+fn decode_fit(bytes: &[u8]) -> Result<(Vec<Record>, Vec<LapSpan>)> {
+    let header_len = *bytes.first().ok_or_else(|| anyhow!("empty file"))? as usize;
+    if bytes.len() < 12 || header_len < 12 || &bytes[8..12] != b".FIT" {
+        bail!("not a FIT file");
+    }
+    let data_len = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+    let end = (header_len + data_len).min(bytes.len());
+    let mut cur = Cursor { bytes: &bytes[..end], pos: header_len };
+
+    let mut defs: HashMap<u8, Definition> = HashMap::new();
+    let mut last_ts = 0u32;
+    let mut records = Vec::new();
+    let mut spans = Vec::new();
+
+    while cur.pos < end {
+        let h = cur.take(1)?[0];
+        if h & 0x80 != 0 {
+            // compressed timestamp header: 5-bit rolling offset from the last timestamp
+            let offset = (h & 0x1f) as u32;
+            last_ts = last_ts.wrapping_add(offset.wrapping_sub(last_ts & 0x1f) & 0x1f);
+            let local = (h >> 5) & 0x03;
+            let def = defs.get(&local).ok_or_else(|| anyhow!("undefined local message {local}"))?;
+            let values = cur.fields(def)?;
+            collect(def.global, &values, last_ts, &mut records, &mut spans);
+            continue;
+        }
+        let local = h & 0x0f;
+        if h & 0x40 != 0 {
+            let fixed = cur.take(5)?;
+            let big_endian = fixed[1] == 1;
+            let global = if big_endian {
+                u16::from_be_bytes([fixed[2], fixed[3]])
+            } else {
+                u16::from_le_bytes([fixed[2], fixed[3]])
+            };
+            let fields = cur.take(fixed[4] as usize * 3)?.chunks_exact(3).map(|f| (f[0], f[1], f[2])).collect();
+            let dev_size = if h & 0x20 != 0 {
+                let n = cur.take(1)?[0] as usize;
+                cur.take(n * 3)?.chunks_exact(3).map(|f| f[1] as usize).sum()
+            } else {
+                0
+            };
+            defs.insert(local, Definition { global, big_endian, fields, dev_size });
+        } else {
+            let def = defs.get(&local).ok_or_else(|| anyhow!("undefined local message {local}"))?;
+            let values = cur.fields(def)?;
+            if let Some(ts) = field(&values, FIELD_TIMESTAMP) {
+                last_ts = ts as u32;
+            }
+            collect(def.global, &values, last_ts, &mut records, &mut spans);
+        }
+    }
+    Ok((records, spans))
+}
+
+/// Decode a scalar integer field; `None` for other types, arrays and the
+/// base type's invalid value.
+fn int_value(raw: &[u8], base: u8, big_endian: bool) -> Option<i64> {
+    // (size, signed, invalid raw value)
+    let (size, signed, invalid) = match base & 0x1f {
+        0x00 | 0x02 | 0x0d => (1, false, 0xff),
+        0x0a => (1, false, 0),
+        0x01 => (1, true, 0x7f),
+        0x03 => (2, true, 0x7fff),
+        0x04 => (2, false, 0xffff),
+        0x0b => (2, false, 0),
+        0x05 => (4, true, 0x7fff_ffff),
+        0x06 => (4, false, 0xffff_ffff),
+        0x0c => (4, false, 0),
+        _ => return None,
+    };
+    if raw.len() != size {
+        return None;
+    }
+    let mut buf = [0u8; 8];
+    buf[..size].copy_from_slice(raw);
+    if big_endian {
+        buf[..size].reverse();
+    }
+    let u = u64::from_le_bytes(buf);
+    if u == invalid {
+        return None;
+    }
+    let shift = 64 - 8 * size as u32;
+    Some(if signed { ((u << shift) as i64) >> shift } else { u as i64 })
+}
+
+fn field(values: &[(u8, i64)], num: u8) -> Option<i64> {
+    values.iter().find(|(n, _)| *n == num).map(|(_, v)| *v)
+}
+
+fn collect(global: u16, values: &[(u8, i64)], ts: u32, records: &mut Vec<Record>, spans: &mut Vec<LapSpan>) {
+    match global {
+        MSG_RECORD => records.push(Record {
+            t_s: ts,
+            lat: field(values, 0).map(|v| v as f64 * SEMICIRCLE_DEG),
+            lon: field(values, 1).map(|v| v as f64 * SEMICIRCLE_DEG),
+            distance_m: field(values, 5).map(|v| v as f64 / 100.0),
+            // enhanced_speed when present, plain speed otherwise
+            speed_mps: field(values, 73).or(field(values, 6)).map(|v| v as f64 / 1000.0),
+        }),
+        MSG_LAP => {
+            if let Some(start) = field(values, 2) {
+                spans.push(LapSpan {
+                    start_s: start as u32,
+                    end_s: ts.max(start as u32),
+                    elapsed_ms: field(values, 7).map(|v| v as u64),
+                });
+            }
+        }
+        _ => {}
+    }
+}
+
+fn new_lap(track: &str, lap_number: u32) -> Lap {
+    Lap {
+        id: Uuid::new_v4(),
+        meta: LapMeta {
+            id: Uuid::new_v4(),
+            game: "catalyst".into(),
+            car: "Unknown".into(),
+            track: track.into(),
+            lap_number,
+            out_lap: false,
+            in_lap: false,
+        },
+        total_time_ms: 0,
+        points: Vec::new(),
+        markers: Vec::new(),
+        index: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    const DEG_SEMICIRCLE: f64 = 2_147_483_648.0 / 180.0;
+
+    /// A FIT file of 1 Hz records driving north (or east) at 20 m/s
+    /// for 10 s, in one lap message of 9.5 s.
+    fn fit(east: bool) -> Vec<u8> {
+        let mut data = Vec::new();
+        // local 0: record (timestamp, lat, lon, speed)
+        data.extend([0x40, 0, 0]);
+        data.extend(MSG_RECORD.to_le_bytes());
+        data.extend([4, FIELD_TIMESTAMP, 4, 0x86, 0, 4, 0x85, 1, 4, 0x85, 6, 2, 0x84]);
+        // local 1: lap (timestamp, start_time, total_elapsed_time)
+        data.extend([0x41, 0, 0]);
+        data.extend(MSG_LAP.to_le_bytes());
+        data.extend([3, FIELD_TIMESTAMP, 4, 0x86, 2, 4, 0x86, 7, 4, 0x86]);
+
+        let (lat0, lon0) = (52.0_f64, 1.0);
+        for t in 0..10u32 {
+            // 20 m in degrees, near enough at this latitude
+            let step = 20.0 * t as f64 / 111_320.0;
+            let (lat, lon) = if east { (lat0, lon0 + step / lat0.to_radians().cos()) } else { (lat0 + step, lon0) };
+            data.push(0x00);
+            data.extend((1000 + t).to_le_bytes());
+            data.extend(((lat * DEG_SEMICIRCLE) as i32).to_le_bytes());
+            data.extend(((lon * DEG_SEMICIRCLE) as i32).to_le_bytes());
+            data.extend(20_000u16.to_le_bytes());
+        }
+        data.push(0x01);
+        data.extend(1009u32.to_le_bytes());
+        data.extend(1000u32.to_le_bytes());
+        data.extend(9500u32.to_le_bytes());
+
+        let mut file = vec![12, 0x20];
+        file.extend(2100u16.to_le_bytes());
+        file.extend((data.len() as u32).to_le_bytes());
+        file.extend(b".FIT");
+        file.extend(data);
+        file
+    }
+
+    fn import(east: bool) -> Lap {
+        let path = TempPath::new("fit");
+        std::fs::write(&*path, fit(east)).unwrap();
+        let mut laps = import_catalyst(&path, Some("Snetterton"), None).unwrap();
+        assert_eq!(laps.len(), 1);
+        laps.remove(0)
+    }
+
+    #[test]
+    fn decodes_records_and_lap_time() {
+        let lap = import(false);
+        assert_eq!(lap.meta.track, "Snetterton");
+        assert_eq!(lap.total_time_ms, 9500);
+        assert_eq!(lap.points.len(), 10);
+        let last = lap.points.last().unwrap();
+        assert!((last.t_ms - 9000.0).abs() < 1e-9);
+        assert!((last.y - 180.0).abs() < 0.5, "{}", last.y);
+        assert!((last.lap_distance_m - 180.0).abs() < 0.5);
+        assert!(lap.points.iter().all(|p| (p.speed_kph - 72.0).abs() < 1e-9));
+        // no pedals in the file, so none made up
+        assert!(lap.points.iter().all(|p| p.throttle == 0.0 && p.brake == 0.0));
+    }
+
+    #[test]
+    fn yaw_is_clockwise_from_north() {
+        let north = import(false);
+        assert!(north.points[1..].iter().all(|p| p.yaw < 1e-3 || p.yaw > TAU - 1e-3), "{:?}", north.points[1].yaw);
+        let east = import(true);
+        assert!(east.points[1..].iter().all(|p| (p.yaw - TAU / 4.0).abs() < 1e-3), "{:?}", east.points[1].yaw);
+    }
+}
@@ -0,0 +1,227 @@
+//! Chart-ready analysis results as plain files, for spreadsheets, Grafana or
+//! notebooks. One call writes, side by side as JSON and CSV:
+//! - `overlay` — speed of every lap by distance, one column per lap
+//! - `delta` — time delta of every lap to the reference by distance
+//! - `corners` — corner metrics of every lap, one row per corner
+//!
+//! [`export_corners_csv`] and [`export_summary_csv`] write just the corner
+//! table or the per-session lap time summary.
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+use analysis::{delta_series, session_summary, speed_overlay, OverlayResult};
+use model::*;
+
+/// Delta of every non-reference lap on the reference lap's 1 m grid.
+#[derive(Clone, Serialize, Debug, Default, PartialEq)]
+pub struct DeltaRibbon {
+    #[serde(with = "uuid::serde::simple")]
+    pub reference_id: Uuid,
+    pub distance: Vec<f64>,
+    pub laps: Vec<(Uuid, Vec<f64>)>,
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct CornerRow {
+    #[serde(with = "uuid::serde::simple")]
+    pub lap_id: Uuid,
+    pub lap_number: u32,
+    #[serde(flatten)]
+    pub corner: Corner,
+}
+
+/// Per-lap time delta to `reference` (positive = slower).
+pub fn delta_ribbon(reference: &Lap, laps: &[Lap]) -> DeltaRibbon {
+    let mut ribbon = DeltaRibbon { reference_id: reference.id, ..DeltaRibbon::default() };
+    for l in laps.iter().filter(|l| l.id != reference.id) {
+        let d = delta_series(reference, std::slice::from_ref(l));
+        ribbon.distance = d.distance;
+        ribbon.laps.push((l.id, d.delta_ms));
+    }
+    ribbon
+}
+
+pub fn corner_rows(laps: &[Lap]) -> Vec<CornerRow> {
+    laps.iter()
+        .flat_map(|l| {
+            analysis::corner_metrics(l)
+                .corners
+                .into_iter()
+                .map(|corner| CornerRow { lap_id: l.id, lap_number: l.meta.lap_number, corner })
+        })
+        .collect()
+}
+
+/// Write overlay, delta and corner files for `laps` into `dir` against
+/// `reference` (the fastest lap when `None`); returns the files written.
+pub fn export_analysis(laps: &[Lap], reference: Option<Uuid>, dir: &Path) -> Result<Vec<PathBuf>> {
+    let reference = match reference {
+        Some(id) => match laps.iter().find(|l| l.id == id) {
+            Some(l) => l,
+            None => bail!("reference lap {id} is not among the exported laps"),
+        },
+        None => match laps.iter().filter(|l| !l.points.is_empty()).min_by_key(|l| l.total_time_ms) {
+            Some(l) => l,
+            None => bail!("no laps with telemetry to analyse"),
+        },
+    };
+    std::fs::create_dir_all(dir)?;
+
+    let overlay = speed_overlay(laps);
+    let ribbon = delta_ribbon(reference, laps);
+    let corners = corner_rows(laps);
+
+    let mut written = Vec::new();
+    let mut out = |name: &str| {
+        let p = dir.join(name);
+        written.push(p.clone());
+        p
+    };
+    let overlay_cols = overlay_columns(&overlay, laps);
+    write_json(&out("overlay.json"), &json_rows(&overlay.distance, &overlay_cols))?;
+    write_columns(&out("overlay.csv"), &overlay.distance, &overlay_cols)?;
+    let ribbon_cols = ribbon_columns(&ribbon, laps);
+    write_json(&out("delta.json"), &json_rows(&ribbon.distance, &ribbon_cols))?;
+    write_columns(&out("delta.csv"), &ribbon.distance, &ribbon_cols)?;
+    write_json(&out("corners.json"), &corners)?;
+    write_corners_csv(&out("corners.csv"), &corners)?;
+    Ok(written)
+}
+
+/// Corner metrics of every lap, one row per corner per lap.
+pub fn export_corners_csv(laps: &[Lap], path: &Path) -> Result<()> {
+    write_corners_csv(path, &corner_rows(laps))
+}
+
+/// Lap time summary (best, worst, mean, sector consistency) with one row per
+/// game, car and track combination in `laps`.
+pub fn export_summary_csv(laps: &[Lap], path: &Path) -> Result<()> {
+    // sessions in order of first appearance
+    let mut sessions: Vec<(&LapMeta, Vec<Lap>)> = Vec::new();
+    for l in laps {
+        let same = |m: &LapMeta| m.game == l.meta.game && m.car == l.meta.car && m.track == l.meta.track;
+        match sessions.iter_mut().find(|(m, _)| same(m)) {
+            Some((_, v)) => v.push(l.clone()),
+            None => sessions.push((&l.meta, vec![l.clone()])),
+        }
+    }
+
+    let mut w = csv::Writer::from_path(path)?;
+    w.write_record(["game", "car", "track", "lap_count", "best_ms", "worst_ms", "avg_ms", "consistency_s"])?;
+    for (meta, laps) in &sessions {
+        let s = session_summary(laps);
+        w.write_record([
+            meta.game.clone(),
+            meta.car.clone(),
+            meta.track.clone(),
+            s.lap_count.to_string(),
+            s.best_ms.to_string(),
+            s.worst_ms.to_string(),
+            s.avg_ms.to_string(),
+            s.consistency.to_string(),
+        ])?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Column name of a lap: its number, with the id appended when numbers repeat.
+fn lap_key(lap_id: Uuid, laps: &[Lap]) -> String {
+    let Some(lap) = laps.iter().find(|l| l.id == lap_id) else {
+        return lap_id.simple().to_string();
+    };
+    let n = lap.meta.lap_number;
+    if laps.iter().filter(|l| l.meta.lap_number == n).count() > 1 {
+        format!("lap{n}_{}", lap_id.simple())
+    } else {
+        format!("lap{n}")
+    }
+}
+
+fn overlay_columns<'a>(o: &'a OverlayResult, laps: &[Lap]) -> (Vec<String>, Vec<&'a Vec<f64>>) {
+    let keys = o.series.iter().map(|s| format!("{}_{}", s.channel, lap_key(s.lap_id, laps))).collect();
+    (keys, o.series.iter().map(|s| &s.values).collect())
+}
+
+fn ribbon_columns<'a>(r: &'a DeltaRibbon, laps: &[Lap]) -> (Vec<String>, Vec<&'a Vec<f64>>) {
+    let keys = r.laps.iter().map(|(id, _)| format!("delta_ms_{}", lap_key(*id, laps))).collect();
+    (keys, r.laps.iter().map(|(_, v)| v).collect())
+}
+
+/// Row-per-distance JSON, keyed like the CSV columns.
+fn json_rows(distance: &[f64], (keys, columns): &(Vec<String>, Vec<&Vec<f64>>)) -> Value {
+    Value::Array(
+        distance
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                let mut row = serde_json::Map::new();
+                row.insert("distance_m".into(), json!(d));
+                for (key, c) in keys.iter().zip(columns) {
+                    row.insert(key.clone(), json!(c[i]));
+                }
+                Value::Object(row)
+            })
+            .collect(),
+    )
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut w, value)?;
+    w.flush()?;
+    Ok(())
+}
+
+fn write_columns(path: &Path, distance: &[f64], (keys, columns): &(Vec<String>, Vec<&Vec<f64>>)) -> Result<()> {
+    let mut w = csv::Writer::from_path(path)?;
+    let mut header = vec!["distance_m"];
+    header.extend(keys.iter().map(String::as_str));
+    w.write_record(&header)?;
+    for (i, d) in distance.iter().enumerate() {
+        let mut rec = vec![d.to_string()];
+        rec.extend(columns.iter().map(|c| c[i].to_string()));
+        w.write_record(&rec)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+fn write_corners_csv(path: &Path, rows: &[CornerRow]) -> Result<()> {
+    let mut w = csv::Writer::from_path(path)?;
+    w.write_record([
+        "lap_id",
+        "lap_number",
+        "corner",
+        "start_m",
+        "apex_m",
+        "end_m",
+        "x",
+        "y",
+        "min_speed",
+        "entry_speed",
+        "exit_speed",
+        "brake_point_m",
+        "throttle_on_m",
+    ])?;
+    for r in rows {
+        let c = &r.corner;
+        let mut rec = vec![r.lap_id.simple().to_string(), r.lap_number.to_string(), c.index.to_string()];
+        rec.extend(
+            [c.start_m, c.apex_m, c.end_m, c.x, c.y, c.min_speed, c.entry_speed, c.exit_speed, c.brake_point_m, c.throttle_on_m]
+                .iter()
+                .map(f64::to_string),
+        );
+        w.write_record(&rec)?;
+    }
+    w.flush()?;
+    Ok(())
+}
@@ -0,0 +1,198 @@
+//! Laps as Arrow record batches: one row per telemetry point with the lap's
+//! metadata repeated on every row.
+
+use anyhow::{anyhow, Context, Result};
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Float64Type, Int8Type, UInt32Type, UInt64Type},
+    ArrayRef, Float64Array, Int8Array, RecordBatch, StringArray, UInt32Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use uuid::Uuid;
+
+use model::*;
+
+/// Per-point columns after the lap metadata, in file order.
+pub const POINT_COLUMNS: &[&str] =
+    &["t_ms", "lap_distance_m", "x", "y", "speed_kph", "throttle", "brake", "gear", "rpm", "yaw"];
+
+fn meta_fields() -> Vec<Field> {
+    vec![
+        Field::new("lap_id", DataType::Utf8, false),
+        Field::new("meta_id", DataType::Utf8, false),
+        Field::new("game", DataType::Utf8, false),
+        Field::new("car", DataType::Utf8, false),
+        Field::new("track", DataType::Utf8, false),
+        Field::new("lap_number", DataType::UInt32, false),
+        Field::new("total_time_ms", DataType::UInt64, false),
+    ]
+}
+
+fn point_field(name: &str) -> Field {
+    let ty = if name == "gear" { DataType::Int8 } else { DataType::Float64 };
+    Field::new(name, ty, false)
+}
+
+pub fn laps_schema() -> SchemaRef {
+    schema_for(POINT_COLUMNS)
+}
+
+/// Metadata columns plus the given point columns (`t_ms` is always kept).
+pub fn schema_for(columns: &[&str]) -> SchemaRef {
+    let mut fields = meta_fields();
+    fields.extend(
+        POINT_COLUMNS
+            .iter()
+            .filter(|c| **c == "t_ms" || columns.contains(c))
+            .map(|c| point_field(c)),
+    );
+    Arc::new(Schema::new(fields))
+}
+
+pub fn laps_to_batch(laps: &[Lap]) -> Result<RecordBatch> {
+    laps_to_batch_with(laps, POINT_COLUMNS)
+}
+
+/// Like [`laps_to_batch`] with only the listed point columns.
+pub fn laps_to_batch_with(laps: &[Lap], columns: &[&str]) -> Result<RecordBatch> {
+    let flat: Vec<(&Lap, &TelemetryPoint)> =
+        laps.iter().flat_map(|l| l.points.iter().map(move |p| (l, p))).collect();
+    let rows = || flat.iter().copied();
+    let text = |f: fn(&Lap) -> String| -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(rows().map(|(l, _)| f(l))))
+    };
+    let float = |f: fn(&TelemetryPoint) -> f64| -> ArrayRef {
+        Arc::new(Float64Array::from_iter_values(rows().map(|(_, p)| f(p))))
+    };
+
+    let schema = schema_for(columns);
+    let mut arrays: Vec<ArrayRef> = vec![
+        text(|l| l.id.simple().to_string()),
+        text(|l| l.meta.id.simple().to_string()),
+        text(|l| l.meta.game.clone()),
+        text(|l| l.meta.car.clone()),
+        text(|l| l.meta.track.clone()),
+        Arc::new(UInt32Array::from_iter_values(rows().map(|(l, _)| l.meta.lap_number))),
+        Arc::new(UInt64Array::from_iter_values(rows().map(|(l, _)| l.total_time_ms))),
+    ];
+    for field in schema.fields().iter().skip(arrays.len()) {
+        arrays.push(match field.name().as_str() {
+            "t_ms" => float(|p| p.t_ms),
+            "lap_distance_m" => float(|p| p.lap_distance_m),
+            "x" => float(|p| p.x),
+            "y" => float(|p| p.y),
+            "speed_kph" => float(|p| p.speed_kph),
+            "throttle" => float(|p| p.throttle),
+            "brake" => float(|p| p.brake),
+            "gear" => Arc::new(Int8Array::from_iter_values(rows().map(|(_, p)| p.gear))),
+            "rpm" => float(|p| p.rpm),
+            _ => float(|p| p.yaw),
+        });
+    }
+    Ok(RecordBatch::try_new(schema, arrays)?)
+}
+
+/// Append the laps in `batch` to `laps`. Rows of the lap currently at the end
+/// of `laps` continue it, so laps split across batches are joined back up.
+pub fn batch_to_laps(batch: &RecordBatch, laps: &mut Vec<Lap>) -> Result<()> {
+    let col = |name: &str| {
+        batch
+            .column_by_name(name)
+            .ok_or_else(|| anyhow!("missing column `{name}`"))
+    };
+    let text = |name: &str| -> Result<&StringArray> {
+        col(name)?.as_string_opt::<i32>().ok_or_else(|| anyhow!("column `{name}` is not utf8"))
+    };
+    let float = |name: &str| -> Result<&Float64Array> {
+        col(name)?
+            .as_primitive_opt::<Float64Type>()
+            .ok_or_else(|| anyhow!("column `{name}` is not float64"))
+    };
+
+    let lap_id = text("lap_id")?;
+    let meta_id = text("meta_id")?;
+    let game = text("game")?;
+    let car = text("car")?;
+    let track = text("track")?;
+    let lap_number = col("lap_number")?
+        .as_primitive_opt::<UInt32Type>()
+        .ok_or_else(|| anyhow!("column `lap_number` is not uint32"))?;
+    let total = col("total_time_ms")?
+        .as_primitive_opt::<UInt64Type>()
+        .ok_or_else(|| anyhow!("column `total_time_ms` is not uint64"))?;
+    let t_ms = float("t_ms")?;
+    // exports may carry a subset of channels; missing ones read as 0
+    let gear = col("gear").ok().and_then(|c| c.as_primitive_opt::<Int8Type>());
+    let opt = |name: &str| float(name).ok();
+    let (dist, x, y) = (opt("lap_distance_m"), opt("x"), opt("y"));
+    let (speed, throttle, brake, rpm, yaw) =
+        (opt("speed_kph"), opt("throttle"), opt("brake"), opt("rpm"), opt("yaw"));
+    let at = |c: Option<&Float64Array>, i: usize| c.map(|c| c.value(i)).unwrap_or(0.0);
+
+    for i in 0..batch.num_rows() {
+        let id = Uuid::parse_str(lap_id.value(i)).with_context(|| format!("row {i}: lap_id"))?;
+        if laps.last().map(|l| l.id) != Some(id) {
+            laps.push(Lap {
+                id,
+                meta: LapMeta {
+                    id: Uuid::parse_str(meta_id.value(i)).with_context(|| format!("row {i}: meta_id"))?,
+                    game: game.value(i).to_string(),
+                    car: car.value(i).to_string(),
+                    track: track.value(i).to_string(),
+                    lap_number: lap_number.value(i),
+                    out_lap: false,
+                    in_lap: false,
+                },
+                total_time_ms: total.value(i),
+                points: Vec::new(),
+                markers: Vec::new(),
+                index: Default::default(),
+            });
+        }
+        if let Some(l) = laps.last_mut() {
+            l.points.push(TelemetryPoint {
+                t_ms: t_ms.value(i),
+                lap_distance_m: at(dist, i),
+                x: at(x, i),
+                y: at(y, i),
+                speed_kph: at(speed, i),
+                throttle: at(throttle, i),
+                brake: at(brake, i),
+                gear: gear.map(|c| c.value(i)).unwrap_or(0),
+                rpm: at(rpm, i),
+                yaw: at(yaw, i),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::lap;
+
+    #[test]
+    fn a_lap_split_across_batches_is_joined_back_up() {
+        let mut laps = vec![lap(2, 30), lap(3, 20)];
+        for l in &mut laps {
+            l.markers.clear();
+        }
+        let batch = laps_to_batch(&laps).unwrap();
+        let mut back = Vec::new();
+        for (from, len) in [(0, 12), (12, 25), (37, 13)] {
+            batch_to_laps(&batch.slice(from, len), &mut back).unwrap();
+        }
+        assert_eq!(back, laps);
+    }
+
+    #[test]
+    fn missing_metadata_is_an_error() {
+        let batch = laps_to_batch(&[lap(2, 3)]).unwrap();
+        let without = batch.project(&(1..batch.num_columns()).collect::<Vec<_>>()).unwrap();
+        let err = batch_to_laps(&without, &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("lap_id"), "{err}");
+    }
+}
@@ -0,0 +1,252 @@
+//! Import profiles for CSV logs from other tools: which column feeds which
+//! channel, in what unit, and how the file is delimited. Profiles are plain
+//! serde structs so they can be saved next to the user's settings as JSON.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use uuid::Uuid;
+
+use model::*;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    Game,
+    Car,
+    Track,
+    LapNumber,
+    Time,
+    LapDistance,
+    X,
+    Y,
+    Speed,
+    Throttle,
+    Brake,
+    Gear,
+    Rpm,
+    Yaw,
+}
+
+/// Unit a column is recorded in; converted to the channel's native unit
+/// (ms, m, km/h, 0..1 pedals, radians).
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    /// Already in the channel's native unit.
+    #[default]
+    Native,
+    Seconds,
+    Milliseconds,
+    Metres,
+    Kilometres,
+    Feet,
+    Miles,
+    Kph,
+    Mps,
+    Mph,
+    Percent,
+    Fraction,
+    Degrees,
+    Radians,
+}
+
+impl Unit {
+    fn scale(self) -> f64 {
+        match self {
+            Unit::Native | Unit::Milliseconds | Unit::Metres | Unit::Kph | Unit::Fraction | Unit::Radians => 1.0,
+            Unit::Seconds | Unit::Kilometres => 1000.0,
+            Unit::Feet => 0.3048,
+            Unit::Miles => 1609.344,
+            Unit::Mps => 3.6,
+            Unit::Mph => 1.609_344,
+            Unit::Percent => 0.01,
+            Unit::Degrees => std::f64::consts::PI / 180.0,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ColumnMapping {
+    /// Header name, matched case-insensitively.
+    pub column: String,
+    pub channel: Channel,
+    #[serde(default)]
+    pub unit: Unit,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CsvProfile {
+    pub name: String,
+    #[serde(default = "comma")]
+    pub delimiter: char,
+    #[serde(default = "dot")]
+    pub decimal: char,
+    /// Lines to skip before the header row.
+    #[serde(default)]
+    pub skip_rows: usize,
+    pub columns: Vec<ColumnMapping>,
+    /// Used when the file has no game/car/track column.
+    #[serde(default)]
+    pub game: Option<String>,
+    #[serde(default)]
+    pub car: Option<String>,
+    #[serde(default)]
+    pub track: Option<String>,
+}
+
+pub(crate) fn comma() -> char {
+    ','
+}
+
+pub(crate) fn dot() -> char {
+    '.'
+}
+
+impl Default for CsvProfile {
+    /// The layout written by [`crate::export_csv`].
+    fn default() -> Self {
+        let col = |column: &str, channel| ColumnMapping { column: column.into(), channel, unit: Unit::Native };
+        Self {
+            name: "Delta CSV".into(),
+            delimiter: ',',
+            decimal: '.',
+            skip_rows: 0,
+            columns: vec![
+                col("game", Channel::Game),
+                col("car", Channel::Car),
+                col("track", Channel::Track),
+                col("lap_number", Channel::LapNumber),
+                col("t_ms", Channel::Time),
+                col("lap_distance_m", Channel::LapDistance),
+                col("x", Channel::X),
+                col("y", Channel::Y),
+                col("speed_kph", Channel::Speed),
+                col("throttle", Channel::Throttle),
+                col("brake", Channel::Brake),
+                col("gear", Channel::Gear),
+                col("rpm", Channel::Rpm),
+                col("yaw", Channel::Yaw),
+            ],
+            game: None,
+            car: None,
+            track: None,
+        }
+    }
+}
+
+impl CsvProfile {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Import a CSV using `profile`. Laps split on the lap-number column (one lap
+/// if there is none); missing lap distance is integrated from position.
+pub fn import_csv_with_profile(path: &Path, profile: &CsvProfile) -> Result<Vec<Lap>> {
+    if !profile.delimiter.is_ascii() {
+        bail!("delimiter must be an ASCII character");
+    }
+    let text = std::fs::read_to_string(path)?;
+    let body: String = text.lines().skip(profile.skip_rows).collect::<Vec<_>>().join("\n");
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(profile.delimiter as u8)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes());
+
+    let headers: Vec<String> = rdr.headers()?.iter().map(|h| h.to_ascii_lowercase()).collect();
+    // (column index, unit) per mapped channel
+    let mut cols: Vec<(Channel, usize, Unit)> = Vec::new();
+    for m in &profile.columns {
+        let Some(i) = headers.iter().position(|h| *h == m.column.to_ascii_lowercase()) else {
+            continue;
+        };
+        cols.push((m.channel, i, m.unit));
+    }
+    let find = |c: Channel| cols.iter().find(|(ch, ..)| *ch == c).map(|&(_, i, u)| (i, u));
+    if find(Channel::Time).is_none() {
+        bail!("profile `{}` maps no column present in the file to time", profile.name);
+    }
+
+    let num = |rec: &csv::StringRecord, c: Channel, row: usize| -> Result<Option<f64>> {
+        let Some((i, unit)) = find(c) else { return Ok(None) };
+        let raw = rec.get(i).unwrap_or("");
+        if raw.is_empty() {
+            return Ok(None);
+        }
+        let v: f64 = if profile.decimal == '.' { raw.parse() } else { raw.replace(profile.decimal, ".").parse() }
+            .with_context(|| format!("row {row}: `{raw}` in column {} is not a number", headers[i]))?;
+        Ok(Some(v * unit.scale()))
+    };
+    let text_col = |rec: &csv::StringRecord, c: Channel, fixed: &Option<String>| {
+        find(c)
+            .and_then(|(i, _)| rec.get(i))
+            .map(str::to_string)
+            .or_else(|| fixed.clone())
+            .unwrap_or_else(|| "Unknown".into())
+    };
+
+    let mut laps: Vec<Lap> = Vec::new();
+    for (row, rec) in rdr.records().enumerate() {
+        let rec = rec?;
+        let row = row + profile.skip_rows + 2;
+        let t_ms = num(&rec, Channel::Time, row)?.ok_or_else(|| anyhow!("row {row}: no time"))?;
+        let lap_number = num(&rec, Channel::LapNumber, row)?.unwrap_or(1.0) as u32;
+        if laps.last().map(|l| l.meta.lap_number) != Some(lap_number) {
+            laps.push(Lap {
+                id: Uuid::new_v4(),
+                meta: LapMeta {
+                    id: Uuid::new_v4(),
+                    game: text_col(&rec, Channel::Game, &profile.game),
+                    car: text_col(&rec, Channel::Car, &profile.car),
+                    track: text_col(&rec, Channel::Track, &profile.track),
+                    lap_number,
+                    out_lap: false,
+                    in_lap: false,
+                },
+                total_time_ms: 0,
+                points: Vec::new(),
+                markers: Vec::new(),
+                index: Default::default(),
+            });
+        }
+        let point = TelemetryPoint {
+            t_ms,
+            lap_distance_m: num(&rec, Channel::LapDistance, row)?.unwrap_or(f64::NAN),
+            x: num(&rec, Channel::X, row)?.unwrap_or(0.0),
+            y: num(&rec, Channel::Y, row)?.unwrap_or(0.0),
+            speed_kph: num(&rec, Channel::Speed, row)?.unwrap_or(0.0),
+            throttle: num(&rec, Channel::Throttle, row)?.unwrap_or(0.0),
+            brake: num(&rec, Channel::Brake, row)?.unwrap_or(0.0),
+            gear: num(&rec, Channel::Gear, row)?.unwrap_or(0.0) as i8,
+            rpm: num(&rec, Channel::Rpm, row)?.unwrap_or(0.0),
+            yaw: num(&rec, Channel::Yaw, row)?.unwrap_or(0.0),
+        };
+        if let Some(l) = laps.last_mut() {
+            l.points.push(point);
+        }
+    }
+
+    for lap in &mut laps {
+        let pts = &mut lap.points;
+        if pts.iter().any(|p| p.lap_distance_m.is_nan()) {
+            let mut cum = 0.0;
+            for i in 0..pts.len() {
+                if i > 0 {
+                    cum += (pts[i].x - pts[i - 1].x).hypot(pts[i].y - pts[i - 1].y);
+                }
+                pts[i].lap_distance_m = cum;
+            }
+        }
+        if let (Some(a), Some(b)) = (pts.first(), pts.last()) {
+            lap.total_time_ms = (b.t_ms - a.t_ms).max(0.0).round() as u64;
+        }
+    }
+    Ok(laps)
+}
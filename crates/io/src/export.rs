@@ -0,0 +1,132 @@
+//! One entry point over every exporter, with lap and channel selection.
+//! CSV, MoTeC and video overlay exports also get a [metadata
+//! sidecar](crate::sidecar).
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{borrow::Borrow, path::Path};
+use uuid::Uuid;
+
+use model::*;
+
+use crate::geo::GeoOrigin;
+use crate::CsvOptions;
+use crate::motec::{self, MotecOptions};
+use crate::overlay::OverlayOptions;
+use crate::sidecar::{write_sidecar, Sidecar};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    Binary,
+    Session,
+    MotecCsv,
+    MotecLd,
+    Vbo,
+    Gpx,
+    Kml,
+    ArrowIpc,
+    Parquet,
+    Hdf5,
+    /// Corner metrics table, one row per corner per lap.
+    Corners,
+    /// Lap time summary, one row per game/car/track.
+    Summary,
+    /// RaceRender/DashWare CSV for video overlays.
+    VideoOverlay,
+}
+
+/// Point channels that can be selected for tabular formats.
+pub const EXPORT_CHANNELS: &[&str] =
+    &["lap_distance_m", "x", "y", "speed_kph", "throttle", "brake", "gear", "rpm", "yaw"];
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ExportOptions {
+    /// Laps to export, in this order; every lap when `None`.
+    #[serde(default)]
+    pub lap_ids: Option<Vec<Uuid>>,
+    /// Channels to write (see [`EXPORT_CHANNELS`]); all when `None`. Applies to
+    /// CSV, MoTeC and Arrow/Parquet. NDJSON, binary, HDF5 and session bundles
+    /// always hold whole laps, and the map formats only carry position.
+    #[serde(default)]
+    pub channels: Option<Vec<String>>,
+    /// Geo-reference for VBO/GPX/KML and video overlays.
+    #[serde(default)]
+    pub origin: Option<GeoOrigin>,
+    /// Recorded in the MoTeC header and the metadata sidecar.
+    #[serde(default)]
+    pub driver: String,
+    /// Free-text session conditions for the metadata sidecar.
+    #[serde(default)]
+    pub conditions: String,
+    /// Units and number formatting for CSV; the speed unit also applies to
+    /// video overlays.
+    #[serde(default)]
+    pub csv: CsvOptions,
+}
+
+/// The laps `ids` picks out of `laps`, in the order of `ids`, or all of them.
+/// `laps` may be shared (`Arc<Lap>`) or owned.
+pub fn select_laps<'a, L: Borrow<Lap>>(laps: &'a [L], ids: Option<&[Uuid]>) -> Vec<&'a Lap> {
+    let all = laps.iter().map(Borrow::borrow);
+    match ids {
+        None => all.collect(),
+        Some(ids) => ids.iter().filter_map(|id| all.clone().find(|l| l.id == *id)).collect(),
+    }
+}
+
+/// Export the selected laps of `laps` to `path`; returns how many were written.
+/// Only the selected laps are copied for the exporters.
+pub fn export_laps<L: Borrow<Lap>>(laps: &[L], path: &Path, format: ExportFormat, opts: &ExportOptions) -> Result<usize> {
+    let selected: Vec<Lap> = select_laps(laps, opts.lap_ids.as_deref()).into_iter().cloned().collect();
+    if selected.is_empty() {
+        bail!("no laps selected for export");
+    }
+    let channels: Vec<&str> = match &opts.channels {
+        Some(c) => {
+            if let Some(bad) = c.iter().find(|c| !EXPORT_CHANNELS.contains(&c.as_str())) {
+                bail!("unknown channel `{bad}`");
+            }
+            c.iter().map(String::as_str).collect()
+        }
+        None => EXPORT_CHANNELS.to_vec(),
+    };
+    let motec_opts =
+        MotecOptions { channels: opts.channels.clone(), driver: opts.driver.clone(), ..MotecOptions::default() };
+    let origin = opts.origin.as_ref();
+
+    match format {
+        ExportFormat::Csv if opts.channels.is_some() => {
+            crate::export_csv_columns(&selected, &channels, path, &opts.csv)?
+        }
+        ExportFormat::Csv => crate::export_csv_with(&selected, path, &opts.csv)?,
+        ExportFormat::Ndjson => crate::export_ndjson(&selected, path)?,
+        ExportFormat::Binary => crate::save_laps(&selected, path)?,
+        ExportFormat::Session => crate::bundle::export_bundle(&selected, path)?,
+        ExportFormat::MotecCsv => motec::export_motec_csv(&selected, path, &motec_opts)?,
+        ExportFormat::MotecLd => motec::export_motec_ld(&selected, path, &motec_opts)?,
+        ExportFormat::Vbo => crate::vbo::export_vbo(&selected, path, origin)?,
+        ExportFormat::Gpx => crate::gpx::export_gpx(&selected, path, origin)?,
+        ExportFormat::Kml => crate::gpx::export_kml(&selected, path, origin)?,
+        ExportFormat::VideoOverlay => {
+            let o = OverlayOptions { speed_unit: opts.csv.speed_unit, origin: opts.origin };
+            crate::overlay::export_overlay_csv(&selected, path, &o)?
+        }
+        ExportFormat::Corners => crate::charts::export_corners_csv(&selected, path)?,
+        ExportFormat::Summary => crate::charts::export_summary_csv(&selected, path)?,
+        #[cfg(feature = "arrow")]
+        ExportFormat::ArrowIpc => crate::ipc::export_arrow_ipc_with(&selected, path, &channels)?,
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet => crate::parquet::export_parquet_with(&selected, path, &channels)?,
+        #[cfg(feature = "hdf5")]
+        ExportFormat::Hdf5 => crate::hdf5::export_hdf5(&selected, path)?,
+        #[allow(unreachable_patterns)]
+        other => bail!("{other:?} export is not enabled in this build"),
+    }
+    if matches!(format, ExportFormat::Csv | ExportFormat::MotecCsv | ExportFormat::MotecLd | ExportFormat::VideoOverlay) {
+        write_sidecar(path, &Sidecar::new(&selected, &opts.driver, &opts.conditions))?;
+    }
+    Ok(selected.len())
+}
@@ -0,0 +1,52 @@
+//! Geo-referencing between the local track frame (metres, `x` east / `y`
+//! north once rotated by `bearing_deg`) and WGS84 latitude/longitude.
+//!
+//! Uses a local tangent-plane approximation, which is well under a
+//! centimetre off across the few kilometres a circuit spans.
+
+use serde::{Deserialize, Serialize};
+
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct GeoOrigin {
+    /// Latitude of the local origin (degrees).
+    pub lat_deg: f64,
+    /// Longitude of the local origin (degrees).
+    pub lon_deg: f64,
+    /// Compass bearing of the local +y axis (degrees clockwise from north).
+    #[serde(default)]
+    pub bearing_deg: f64,
+}
+
+impl GeoOrigin {
+    pub fn new(lat_deg: f64, lon_deg: f64) -> Self {
+        Self { lat_deg, lon_deg, bearing_deg: 0.0 }
+    }
+
+    /// Local metres to `(lat, lon)` in degrees.
+    pub fn to_lat_lon(&self, x: f64, y: f64) -> (f64, f64) {
+        let (s, c) = self.bearing_deg.to_radians().sin_cos();
+        let east = x * c + y * s;
+        let north = -x * s + y * c;
+        let lat = self.lat_deg + (north / EARTH_RADIUS_M).to_degrees();
+        let lon = self.lon_deg + (east / (EARTH_RADIUS_M * self.lat_deg.to_radians().cos())).to_degrees();
+        (lat, lon)
+    }
+
+    /// `(lat, lon)` in degrees to local metres.
+    pub fn to_local(&self, lat: f64, lon: f64) -> (f64, f64) {
+        let north = (lat - self.lat_deg).to_radians() * EARTH_RADIUS_M;
+        let east = (lon - self.lon_deg).to_radians() * EARTH_RADIUS_M * self.lat_deg.to_radians().cos();
+        let (s, c) = self.bearing_deg.to_radians().sin_cos();
+        (east * c - north * s, east * s + north * c)
+    }
+}
+
+/// Great-circle distance between two positions (metres).
+pub fn haversine_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (la1, lo1) = (a.0.to_radians(), a.1.to_radians());
+    let (la2, lo2) = (b.0.to_radians(), b.1.to_radians());
+    let h = ((la2 - la1) / 2.0).sin().powi(2) + la1.cos() * la2.cos() * ((lo2 - lo1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().asin()
+}
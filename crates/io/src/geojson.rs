@@ -0,0 +1,86 @@
+//! GeoJSON track maps for web mapping libraries (Leaflet, Mapbox, deck.gl)
+//! and broadcast overlays.
+//!
+//! One `FeatureCollection`: the racing line as a `LineString`, each sector
+//! as a `LineString` cut from it by distance, and each corner as a labelled
+//! `Point`. Every feature has a `kind` property (`track`, `sector`, `corner`)
+//! to style by. Coordinates are placed through a [`GeoOrigin`]; without one
+//! the map is pinned at 0°N 0°E, still fine for plain-canvas rendering.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use model::*;
+
+use crate::geo::GeoOrigin;
+
+pub fn track_map_geojson(map: &TrackMap, origin: Option<&GeoOrigin>) -> Value {
+    let o = origin.copied().unwrap_or_default();
+    let coord = |p: &Point2| {
+        let (lat, lon) = o.to_lat_lon(p.x, p.y);
+        json!([lon, lat])
+    };
+
+    let mut features = vec![json!({
+        "type": "Feature",
+        "properties": { "kind": "track" },
+        "geometry": {
+            "type": "LineString",
+            "coordinates": map.polyline.iter().map(coord).collect::<Vec<_>>(),
+        },
+    })];
+
+    // sectors are lap distances; measure them along the polyline
+    let dist = path_distances(&map.polyline);
+    for (i, s) in map.sectors.iter().enumerate() {
+        let coords: Vec<Value> = map
+            .polyline
+            .iter()
+            .zip(&dist)
+            .filter(|(_, &d)| d >= s.start_m && d <= s.end_m)
+            .map(|(p, _)| coord(p))
+            .collect();
+        features.push(json!({
+            "type": "Feature",
+            "properties": { "kind": "sector", "index": i + 1, "start_m": s.start_m, "end_m": s.end_m },
+            "geometry": { "type": "LineString", "coordinates": coords },
+        }));
+    }
+
+    for c in &map.corners {
+        features.push(json!({
+            "type": "Feature",
+            "properties": { "kind": "corner", "index": c.index, "label": format!("T{}", c.index) },
+            "geometry": { "type": "Point", "coordinates": coord(&Point2 { x: c.x, y: c.y }) },
+        }));
+    }
+
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+/// Distance along `line` at each of its points.
+pub(crate) fn path_distances(line: &[Point2]) -> Vec<f64> {
+    let mut d = 0.0;
+    line.iter()
+        .enumerate()
+        .map(|(i, p)| {
+            if i > 0 {
+                let q = &line[i - 1];
+                d += (p.x - q.x).hypot(p.y - q.y);
+            }
+            d
+        })
+        .collect()
+}
+
+pub fn export_geojson(map: &TrackMap, path: &Path, origin: Option<&GeoOrigin>) -> Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut w, &track_map_geojson(map, origin))?;
+    w.flush()?;
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! GPX tracks and KML line strings of driven laps, for Google Earth and GIS
+//! tools. Local coordinates are placed on the globe through a [`GeoOrigin`];
+//! without one they are pinned at 0°N 0°E and the origin is noted in the file.
+
+use anyhow::Result;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use model::*;
+
+use crate::geo::GeoOrigin;
+
+/// One GPX `<trk>` per lap. Point times count from the moment of export.
+pub fn export_gpx(laps: &[Lap], path: &Path, origin: Option<&GeoOrigin>) -> Result<()> {
+    let o = origin.copied().unwrap_or_default();
+    let mut w = BufWriter::new(File::create(path)?);
+    let start = time::OffsetDateTime::now_utc();
+
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        w,
+        r#"<gpx version="1.1" creator="Delta" xmlns="http://www.topografix.com/GPX/1/1">"#
+    )?;
+    writeln!(w, "  <metadata><desc>{}</desc></metadata>", esc(&origin_note(&o, origin.is_some())))?;
+    let mut offset_ms = 0.0;
+    for lap in laps {
+        writeln!(w, "  <trk>")?;
+        writeln!(w, "    <name>{}</name>", esc(&lap_name(lap)))?;
+        writeln!(w, "    <number>{}</number>", lap.meta.lap_number)?;
+        writeln!(w, "    <trkseg>")?;
+        let t0 = lap.points.first().map(|p| p.t_ms).unwrap_or(0.0);
+        for p in &lap.points {
+            let (lat, lon) = o.to_lat_lon(p.x, p.y);
+            let at = start + time::Duration::milliseconds((offset_ms + p.t_ms - t0) as i64);
+            writeln!(
+                w,
+                r#"      <trkpt lat="{lat:.8}" lon="{lon:.8}"><ele>0</ele><time>{}</time></trkpt>"#,
+                iso8601(at)
+            )?;
+        }
+        writeln!(w, "    </trkseg>")?;
+        writeln!(w, "  </trk>")?;
+        offset_ms += lap.total_time_ms as f64;
+    }
+    writeln!(w, "</gpx>")?;
+    w.flush()?;
+    Ok(())
+}
+
+/// One KML `<Placemark>` line string per lap.
+pub fn export_kml(laps: &[Lap], path: &Path, origin: Option<&GeoOrigin>) -> Result<()> {
+    let o = origin.copied().unwrap_or_default();
+    let mut w = BufWriter::new(File::create(path)?);
+
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(w, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+    writeln!(w, "<Document>")?;
+    let title = laps.first().map(|l| l.meta.track.as_str()).unwrap_or("Delta laps");
+    writeln!(w, "  <name>{}</name>", esc(title))?;
+    writeln!(w, "  <description>{}</description>", esc(&origin_note(&o, origin.is_some())))?;
+    writeln!(
+        w,
+        "  <Style id=\"lap\"><LineStyle><color>ff0080ff</color><width>3</width></LineStyle></Style>"
+    )?;
+    for lap in laps {
+        writeln!(w, "  <Placemark>")?;
+        writeln!(w, "    <name>{}</name>", esc(&lap_name(lap)))?;
+        writeln!(w, "    <styleUrl>#lap</styleUrl>")?;
+        writeln!(w, "    <LineString><tessellate>1</tessellate><coordinates>")?;
+        for p in &lap.points {
+            let (lat, lon) = o.to_lat_lon(p.x, p.y);
+            writeln!(w, "      {lon:.8},{lat:.8},0")?;
+        }
+        writeln!(w, "    </coordinates></LineString>")?;
+        writeln!(w, "  </Placemark>")?;
+    }
+    writeln!(w, "</Document>")?;
+    writeln!(w, "</kml>")?;
+    w.flush()?;
+    Ok(())
+}
+
+fn lap_name(lap: &Lap) -> String {
+    let ms = lap.total_time_ms;
+    format!(
+        "{} {} lap {} ({}:{:02}.{:03})",
+        lap.meta.track,
+        lap.meta.car,
+        lap.meta.lap_number,
+        ms / 60000,
+        (ms / 1000) % 60,
+        ms % 1000
+    )
+}
+
+fn origin_note(o: &GeoOrigin, declared: bool) -> String {
+    if declared {
+        format!("Local track coordinates placed at {:.7}, {:.7} (bearing {:.1})", o.lat_deg, o.lon_deg, o.bearing_deg)
+    } else {
+        "Local track coordinates in metres around 0, 0 (no geo-reference set)".into()
+    }
+}
+
+pub(crate) fn iso8601(t: time::OffsetDateTime) -> String {
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        t.year(),
+        u8::from(t.month()),
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second(),
+        t.millisecond()
+    )
+}
+
+pub(crate) fn esc(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
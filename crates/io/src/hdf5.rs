@@ -0,0 +1,80 @@
+//! HDF5 export for MATLAB, h5py and other engineering tooling.
+//!
+//! Layout: one group per session (laps sharing game, car and track) holding
+//! one group per lap, each with a 1-D dataset per channel:
+//!
+//! ```text
+//! /session_0            attrs: game, car, track
+//!   /lap_003            attrs: lap_id, lap_number, total_time_ms
+//!     t_ms, lap_distance_m, x, y, speed_kph, ...   attr: unit
+//! ```
+
+use anyhow::Result;
+use hdf5::types::VarLenUnicode;
+use std::path::Path;
+
+use model::*;
+
+use crate::channel_unit;
+
+/// Channels written per lap, in order.
+pub const HDF5_CHANNELS: &[&str] =
+    &["t_ms", "lap_distance_m", "x", "y", "speed_kph", "throttle", "brake", "gear", "rpm", "yaw"];
+
+pub fn export_hdf5(laps: &[Lap], path: &Path) -> Result<()> {
+    let file = hdf5::File::create(path)?;
+    text_attr(&file, "creator", "Delta")?;
+
+    // sessions in order of first appearance
+    let mut sessions: Vec<(&LapMeta, Vec<&Lap>)> = Vec::new();
+    for l in laps {
+        let same = |m: &LapMeta| m.game == l.meta.game && m.car == l.meta.car && m.track == l.meta.track;
+        match sessions.iter_mut().find(|(m, _)| same(m)) {
+            Some((_, v)) => v.push(l),
+            None => sessions.push((&l.meta, vec![l])),
+        }
+    }
+
+    for (i, (meta, laps)) in sessions.iter().enumerate() {
+        let session = file.create_group(&format!("session_{i}"))?;
+        text_attr(&session, "game", &meta.game)?;
+        text_attr(&session, "car", &meta.car)?;
+        text_attr(&session, "track", &meta.track)?;
+
+        let mut used: Vec<String> = Vec::new();
+        for lap in laps {
+            let mut name = format!("lap_{:03}", lap.meta.lap_number);
+            // repeated lap numbers (several stints) get a suffix
+            let base = name.clone();
+            let mut n = 1;
+            while used.contains(&name) {
+                name = format!("{base}_{n}");
+                n += 1;
+            }
+            used.push(name.clone());
+
+            let g = session.create_group(&name)?;
+            text_attr(&g, "lap_id", &lap.id.simple().to_string())?;
+            g.new_attr::<u32>().create("lap_number")?.write_scalar(&lap.meta.lap_number)?;
+            g.new_attr::<u64>().create("total_time_ms")?.write_scalar(&lap.total_time_ms)?;
+
+            for &ch in HDF5_CHANNELS {
+                let ds = if ch == "gear" {
+                    let v: Vec<i8> = lap.points.iter().map(|p| p.gear).collect();
+                    g.new_dataset_builder().with_data(v.as_slice()).create(ch)?
+                } else {
+                    let v = analysis::math::channel_column(lap, ch);
+                    g.new_dataset_builder().with_data(v.as_slice()).create(ch)?
+                };
+                text_attr(&ds, "unit", channel_unit(ch))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn text_attr(loc: &hdf5::Location, name: &str, value: &str) -> Result<()> {
+    let v: VarLenUnicode = value.parse()?;
+    loc.new_attr::<VarLenUnicode>().create(name)?.write_scalar(&v)?;
+    Ok(())
+}
@@ -0,0 +1,445 @@
+//! One entry point over every importer, with duplicate-lap detection.
+//!
+//! NDJSON and binary files keep lap ids, as do CSV and MoTeC exports with a
+//! [metadata sidecar](crate::sidecar); the rest regenerate them, so
+//! duplicates are found by id or by content: a
+//! [`Lap::content_hash`] over the samples, coarse enough that a CSV round trip
+//! hashes the same as the original.
+
+use anyhow::{bail, Result};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use model::*;
+use uuid::Uuid;
+
+use crate::geo::GeoOrigin;
+use crate::laptimer::LapTimerApp;
+use crate::sidecar::read_sidecar;
+use crate::stream::Progress;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    Csv,
+    Ndjson,
+    Binary,
+    Session,
+    MotecCsv,
+    Vbo,
+    RaceChrono,
+    Catalyst,
+    HarrysLapTimer,
+    TrackAddict,
+    ArrowIpc,
+    Parquet,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ImportOptions {
+    /// Geo-reference for GPS formats; the first fix when `None`.
+    #[serde(default)]
+    pub origin: Option<GeoOrigin>,
+}
+
+/// Guess the format of `path` from its extension and, for CSV, its first lines.
+pub fn detect_format(path: &Path) -> Result<ImportFormat> {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    Ok(match ext.as_str() {
+        "ndjson" | "jsonl" => ImportFormat::Ndjson,
+        crate::binary::EXTENSION => ImportFormat::Binary,
+        crate::bundle::EXTENSION => ImportFormat::Session,
+        "vbo" => ImportFormat::Vbo,
+        "fit" => ImportFormat::Catalyst,
+        "arrow" | "ipc" | "feather" => ImportFormat::ArrowIpc,
+        "parquet" => ImportFormat::Parquet,
+        "csv" | "txt" => sniff_csv(path)?,
+        _ if crate::binary::is_binary(path)? => ImportFormat::Binary,
+        _ => bail!("unrecognised file type `{}`", path.display()),
+    })
+}
+
+fn sniff_csv(path: &Path) -> Result<ImportFormat> {
+    let mut head = Vec::new();
+    std::fs::File::open(path)?.take(8192).read_to_end(&mut head)?;
+    let head = String::from_utf8_lossy(&head).to_lowercase();
+    Ok(if head.contains("motec csv file") {
+        ImportFormat::MotecCsv
+    } else if head.contains("racechrono") {
+        ImportFormat::RaceChrono
+    } else if head.contains("racerender") || head.contains("trackaddict") {
+        ImportFormat::TrackAddict
+    } else if head.contains("harry") || head.contains("lap #") {
+        ImportFormat::HarrysLapTimer
+    } else {
+        ImportFormat::Csv
+    })
+}
+
+/// Read every lap of `path`, detecting the format when `format` is `None`.
+pub fn import_laps(path: &Path, format: Option<ImportFormat>, opts: &ImportOptions) -> Result<Vec<Lap>> {
+    import_laps_with_progress(path, format, opts, &CancelHandle::default(), |_| {})
+}
+
+/// Shared flag to stop an import from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Cancelled.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Error returned by an import stopped through its [`CancelHandle`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("import cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Like [`import_laps`], calling `on_progress` as laps are read and stopping
+/// with [`Cancelled`] once `cancel` is set. CSV, NDJSON and binary files are
+/// streamed and report after every lap; other formats are read whole and
+/// report once done.
+pub fn import_laps_with_progress(
+    path: &Path,
+    format: Option<ImportFormat>,
+    opts: &ImportOptions,
+    cancel: &CancelHandle,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<Vec<Lap>> {
+    let format = match format {
+        Some(f) => f,
+        None => detect_format(path)?,
+    };
+    cancel.check()?;
+
+    macro_rules! stream {
+        ($laps:expr) => {{
+            let mut it = $laps;
+            let mut laps = Vec::new();
+            while let Some(lap) = it.next() {
+                laps.push(lap?);
+                on_progress(it.progress());
+                cancel.check()?;
+            }
+            laps
+        }};
+    }
+    let mut laps = match format {
+        ImportFormat::Csv => stream!(crate::stream::stream_csv(path)?),
+        ImportFormat::Ndjson => stream!(crate::stream::stream_ndjson(path)?),
+        ImportFormat::Binary => stream!(crate::stream::stream_binary(path)?),
+        _ => {
+            let laps = read_whole(path, format, opts)?;
+            let total_bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            on_progress(Progress { bytes_read: total_bytes, total_bytes, laps: laps.len() });
+            laps
+        }
+    };
+    cancel.check()?;
+    if let Some(sidecar) = read_sidecar(path)? {
+        sidecar.apply(&mut laps);
+    }
+    Ok(laps)
+}
+
+/// How one file of [`import_files`] went.
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub laps: usize,
+    /// Why the file couldn't be imported; its laps are left out.
+    pub error: Option<String>,
+}
+
+/// Laps of every file of [`import_files`], in file order and not yet
+/// deduplicated, and a report per file.
+#[derive(Clone, Debug, Default)]
+pub struct MultiImport {
+    pub laps: Vec<Lap>,
+    pub files: Vec<FileReport>,
+}
+
+/// Import `paths` in parallel, a folder standing for the files in it and
+/// below with an importable extension. A file that fails is reported and the
+/// others still import; only cancelling fails the whole import.
+/// `on_progress` is called from the worker threads, with the file it's for.
+pub fn import_files(
+    paths: &[PathBuf],
+    format: Option<ImportFormat>,
+    opts: &ImportOptions,
+    cancel: &CancelHandle,
+    on_progress: impl Fn(&Path, Progress) + Sync,
+) -> Result<MultiImport> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            files.extend(importable_files(path)?);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    let results: Vec<(PathBuf, Result<Vec<Lap>>)> = files
+        .into_par_iter()
+        .map(|path| {
+            let laps = import_laps_with_progress(&path, format, opts, cancel, |p| on_progress(&path, p));
+            (path, laps)
+        })
+        .collect();
+    cancel.check()?;
+
+    let mut out = MultiImport::default();
+    for (path, laps) in results {
+        let report = match laps {
+            Ok(laps) => {
+                let report = FileReport { path, laps: laps.len(), error: None };
+                out.laps.extend(laps);
+                report
+            }
+            Err(e) => FileReport { path, laps: 0, error: Some(format!("{e:#}")) },
+        };
+        out.files.push(report);
+    }
+    Ok(out)
+}
+
+/// Bytes read from each end of a file for [`content_key`].
+const KEY_SAMPLE: u64 = 64 * 1024;
+
+/// Key identifying an import by what it reads rather than where from: a hash
+/// of a file's size and its first and last 64 KiB, so the same file under two
+/// paths is one import. Folders, and files that can't be read, hash their path.
+pub fn content_key(path: &Path) -> u64 {
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    match file_sample(path) {
+        Ok(sample) => sample.hash(&mut h),
+        Err(_) => path.hash(&mut h),
+    }
+    h.finish()
+}
+
+fn file_sample(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut f = std::fs::File::open(path)?;
+    let meta = f.metadata()?;
+    if !meta.is_file() {
+        return Err(std::io::ErrorKind::InvalidInput.into());
+    }
+    let len = meta.len();
+    let mut sample = len.to_le_bytes().to_vec();
+    (&mut f).take(KEY_SAMPLE).read_to_end(&mut sample)?;
+    if len > 2 * KEY_SAMPLE {
+        f.seek(SeekFrom::End(-(KEY_SAMPLE as i64)))?;
+        f.take(KEY_SAMPLE).read_to_end(&mut sample)?;
+    }
+    Ok(sample)
+}
+
+// extensions `detect_format` knows
+const IMPORTABLE: &[&str] = &[
+    "ndjson", "jsonl", crate::binary::EXTENSION, crate::bundle::EXTENSION, "vbo", "fit", "arrow", "ipc", "feather", "parquet",
+    "csv", "txt",
+];
+
+/// Files under `dir` with an importable extension, sorted by path.
+fn importable_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(importable_files(&path)?);
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| IMPORTABLE.contains(&e.to_ascii_lowercase().as_str()))
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn read_whole(path: &Path, format: ImportFormat, opts: &ImportOptions) -> Result<Vec<Lap>> {
+    let origin = opts.origin.as_ref();
+    Ok(match format {
+        ImportFormat::Csv => crate::import_csv(path)?,
+        ImportFormat::Ndjson => crate::import_ndjson(path)?,
+        ImportFormat::Binary => crate::load_laps(path)?,
+        ImportFormat::Session => crate::bundle::import_bundle(path)?.laps,
+        ImportFormat::MotecCsv => crate::motec::import_motec_csv(path)?,
+        ImportFormat::Vbo => crate::vbo::import_vbo(path, origin)?,
+        ImportFormat::RaceChrono => crate::racechrono::import_racechrono(path, origin)?,
+        ImportFormat::Catalyst => crate::catalyst::import_catalyst(path, None, origin)?,
+        ImportFormat::HarrysLapTimer => crate::laptimer::import_laptimer(path, LapTimerApp::HarrysLapTimer, origin)?,
+        ImportFormat::TrackAddict => crate::laptimer::import_laptimer(path, LapTimerApp::TrackAddict, origin)?,
+        #[cfg(feature = "arrow")]
+        ImportFormat::ArrowIpc => crate::ipc::import_arrow_ipc(path)?,
+        #[cfg(feature = "parquet")]
+        ImportFormat::Parquet => crate::parquet::import_parquet(path)?,
+        #[allow(unreachable_patterns)]
+        other => bail!("{other:?} import is not enabled in this build"),
+    })
+}
+
+/// Ids and content hashes of the laps already in a session. Kept up to
+/// date as laps come and go, so an import only hashes the laps it brings.
+#[derive(Clone, Debug, Default)]
+pub struct KnownLaps {
+    ids: HashSet<Uuid>,
+    by_hash: HashMap<u64, Uuid>,
+}
+
+impl KnownLaps {
+    pub fn insert(&mut self, lap: &Lap) {
+        self.insert_hashed(lap.id, lap.content_hash());
+    }
+
+    /// Add a lap by id and [`Lap::content_hash`], e.g. one whose points
+    /// aren't loaded.
+    pub fn insert_hashed(&mut self, id: Uuid, hash: u64) {
+        self.ids.insert(id);
+        self.by_hash.entry(hash).or_insert(id);
+    }
+
+    pub fn remove(&mut self, id: Uuid) {
+        if self.ids.remove(&id) {
+            self.by_hash.retain(|_, v| *v != id);
+        }
+    }
+
+    pub fn contains_id(&self, id: Uuid) -> bool {
+        self.ids.contains(&id)
+    }
+
+    /// The known lap with this content hash, if any.
+    pub fn by_hash(&self, hash: u64) -> Option<Uuid> {
+        self.by_hash.get(&hash).copied()
+    }
+}
+
+impl<'a> FromIterator<&'a Lap> for KnownLaps {
+    fn from_iter<I: IntoIterator<Item = &'a Lap>>(laps: I) -> Self {
+        let mut known = Self::default();
+        laps.into_iter().for_each(|l| known.insert(l));
+        known
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Laps already present (or repeated within the file) and left out.
+    pub skipped: usize,
+}
+
+/// Drop laps of `incoming` whose id or content matches a lap of `known` or
+/// an earlier lap of `incoming`. The laps kept are added to `known`.
+pub fn dedupe(known: &mut KnownLaps, incoming: Vec<Lap>) -> (Vec<Lap>, ImportReport) {
+    let total = incoming.len();
+    let fresh: Vec<Lap> = incoming
+        .into_iter()
+        .filter(|l| {
+            let hash = l.content_hash();
+            let new = !known.contains_id(l.id) && known.by_hash(hash).is_none();
+            // record repeats too, so later ones match by either id or content
+            known.insert_hashed(l.id, hash);
+            new
+        })
+        .collect();
+    let report = ImportReport { imported: fresh.len(), skipped: total - fresh.len() };
+    (fresh, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, TempPath};
+
+    #[test]
+    fn dedupe_matches_csv_copies_by_content() {
+        let laps = vec![lap(1, 30), lap(2, 30)];
+        let mut known: KnownLaps = laps.iter().collect();
+
+        let path = TempPath::new("csv");
+        crate::export_csv(&laps, &path).unwrap();
+        let mut incoming = import_laps(&path, None, &ImportOptions::default()).unwrap();
+        assert!(incoming.iter().all(|l| !known.contains_id(l.id)));
+        // lap numbers aren't content, so the samples have to differ
+        incoming.push(lap(3, 31));
+        incoming.push(incoming[2].clone());
+
+        let (fresh, report) = dedupe(&mut known, incoming);
+        assert_eq!(report, ImportReport { imported: 1, skipped: 3 });
+        assert_eq!(fresh[0].meta.lap_number, 3);
+        assert_eq!(known.by_hash(fresh[0].content_hash()), Some(fresh[0].id));
+
+        known.remove(laps[0].id);
+        assert_eq!(known.by_hash(laps[0].content_hash()), None);
+    }
+
+    #[test]
+    fn content_key_follows_content_not_path() {
+        let (a, b, c) = (TempPath::new("ndjson"), TempPath::new("ndjson"), TempPath::new("ndjson"));
+        crate::export_ndjson(&[lap(1, 5000)], &a).unwrap();
+        std::fs::copy(&*a, &*b).unwrap();
+        crate::export_ndjson(&[lap(2, 5000)], &c).unwrap();
+        assert_eq!(content_key(&a), content_key(&b));
+        assert_ne!(content_key(&a), content_key(&c));
+    }
+
+    #[test]
+    fn progress_counts_only_laps_read() {
+        let path = TempPath::new("ndjson");
+        crate::export_ndjson(&[lap(1, 10), lap(2, 10)], &path).unwrap();
+        let mut text = std::fs::read_to_string(&*path).unwrap();
+        text.push_str("{\"not\": \"a lap\"}\n");
+        std::fs::write(&*path, text).unwrap();
+
+        let mut it = crate::stream::stream_ndjson(&path).unwrap();
+        let results: Vec<bool> = it.by_ref().map(|l| l.is_ok()).collect();
+        assert_eq!(results, [true, true, false]);
+        assert_eq!(it.progress().laps, 2);
+
+        let path = TempPath::new(crate::binary::EXTENSION);
+        crate::binary::export_binary(&[lap(1, 200), lap(2, 200)], &path).unwrap();
+        // garble the second lap's frame
+        let mut bytes = std::fs::read(&*path).unwrap();
+        let n = bytes.len();
+        bytes[n - 80..n - 40].iter_mut().for_each(|b| *b ^= 0xa5);
+        std::fs::write(&*path, &bytes).unwrap();
+
+        let mut it = crate::stream::stream_binary(&path).unwrap();
+        let results: Vec<bool> = it.by_ref().take(2).map(|l| l.is_ok()).collect();
+        assert_eq!(results, [true, false]);
+        assert_eq!(it.progress().laps, 1);
+    }
+}
@@ -0,0 +1,56 @@
+//! Arrow IPC file (Feather v2) export, readable straight into pyarrow, polars
+//! or apache-arrow in JS without a parse step.
+
+use anyhow::Result;
+use std::{fs::File, io::BufWriter, path::Path};
+
+use arrow_ipc::{reader::FileReader, writer::FileWriter};
+
+use model::*;
+
+use crate::columnar::{batch_to_laps, laps_to_batch_with, schema_for, POINT_COLUMNS};
+
+pub fn export_arrow_ipc(laps: &[Lap], path: &Path) -> Result<()> {
+    export_arrow_ipc_with(laps, path, POINT_COLUMNS)
+}
+
+/// Arrow IPC with only the listed point columns (see [`POINT_COLUMNS`]).
+pub fn export_arrow_ipc_with(laps: &[Lap], path: &Path, columns: &[&str]) -> Result<()> {
+    let schema = schema_for(columns);
+    let mut w = FileWriter::try_new(BufWriter::new(File::create(path)?), &schema)?;
+    // one record batch per lap, so readers can memory-map and pick laps individually
+    for lap in laps {
+        w.write(&laps_to_batch_with(std::slice::from_ref(lap), columns)?)?;
+    }
+    w.finish()?;
+    Ok(())
+}
+
+pub fn import_arrow_ipc(path: &Path) -> Result<Vec<Lap>> {
+    let reader = FileReader::try_new(File::open(path)?, None)?;
+    let mut laps = Vec::new();
+    for batch in reader {
+        batch_to_laps(&batch?, &mut laps)?;
+    }
+    Ok(laps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, TempPath};
+
+    #[test]
+    fn round_trip_keeps_every_point() {
+        // markers and out/in-lap flags have no column
+        let mut laps = vec![lap(2, 40), lap(3, 0), lap(4, 25)];
+        for l in &mut laps {
+            l.markers.clear();
+        }
+        let path = TempPath::new("arrow");
+        export_arrow_ipc(&laps, &path).unwrap();
+        // a lap without points has no rows
+        laps.remove(1);
+        assert_eq!(import_arrow_ipc(&path).unwrap(), laps);
+    }
+}
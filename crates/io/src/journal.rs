@@ -0,0 +1,122 @@
+//! Crash journal for laps still being driven.
+//!
+//! Completed laps are saved as soon as they finish; the lap in progress only
+//! lives in memory. [`Journal`] snapshots it to `<dir>/<source>_<session>.dlap`
+//! every few seconds, writing a temporary file and renaming it over the last
+//! snapshot so a crash leaves either the old or the new one, never half of
+//! one. The snapshot is removed once the lap completes or the source stops,
+//! so whatever [`recover`] finds on the next launch was cut short by a crash.
+
+use anyhow::{Context, Result};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+use model::*;
+
+use crate::binary::{import_binary, BinaryWriter, EXTENSION};
+
+/// Session part of the file name of laps recorded outside a session.
+const NO_SESSION: &str = "none";
+
+pub struct Journal {
+    dir: PathBuf,
+    interval: Duration,
+    last: HashMap<String, Instant>,
+}
+
+/// A lap read back from the journal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveredLap {
+    pub source: String,
+    pub session_id: Option<Uuid>,
+    pub lap: Lap,
+}
+
+impl Journal {
+    /// Journal into `dir`, snapshotting each source at most once per `interval`.
+    pub fn new(dir: &Path, interval: Duration) -> Self {
+        Self { dir: dir.to_path_buf(), interval, last: HashMap::new() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Snapshot `source`'s lap in progress if the last snapshot is older than
+    /// the interval. Returns whether one was written.
+    pub fn snapshot(&mut self, source: &str, session_id: Option<Uuid>, lap: &Lap) -> Result<bool> {
+        if self.last.get(source).is_some_and(|t| t.elapsed() < self.interval) {
+            return Ok(false);
+        }
+        self.last.insert(source.to_string(), Instant::now());
+        fs::create_dir_all(&self.dir)?;
+        let session = session_id.map(|id| id.simple().to_string()).unwrap_or_else(|| NO_SESSION.into());
+        let path = self.dir.join(format!("{source}_{session}.{EXTENSION}"));
+        let tmp = path.with_extension("tmp");
+        let mut w = BinaryWriter::new(BufWriter::new(File::create(&tmp)?))?;
+        w.write_lap(lap)?;
+        w.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        // the snapshot of an earlier session of this source is stale now
+        self.remove(source, Some(&path))?;
+        fs::rename(&tmp, &path).with_context(|| format!("writing {}", path.display()))?;
+        Ok(true)
+    }
+
+    /// Drop `source`'s snapshot: its lap completed or it stopped.
+    pub fn clear(&mut self, source: &str) -> Result<()> {
+        self.last.remove(source);
+        self.remove(source, None)
+    }
+
+    fn remove(&self, source: &str, keep: Option<&Path>) -> Result<()> {
+        for (path, s, _) in snapshots(&self.dir)? {
+            if s == source && Some(path.as_path()) != keep {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Snapshot files in `dir` with the source and session parsed from their names.
+fn snapshots(dir: &Path) -> Result<Vec<(PathBuf, String, Option<Uuid>)>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut out = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != EXTENSION) {
+            continue;
+        }
+        let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+        let Some((source, session)) = stem.rsplit_once('_') else { continue };
+        let session_id = if session == NO_SESSION { None } else { Uuid::parse_str(session).ok() };
+        out.push((path.clone(), source.to_string(), session_id));
+    }
+    Ok(out)
+}
+
+/// Laps left in the journal at `dir` by a crash. The snapshots are removed
+/// once read; unreadable ones are skipped and left in place.
+pub fn recover(dir: &Path) -> Result<Vec<RecoveredLap>> {
+    let mut out = Vec::new();
+    for (path, source, session_id) in snapshots(dir)? {
+        let Ok(laps) = import_binary(&path) else { continue };
+        out.extend(laps.into_iter().map(|lap| RecoveredLap { source: source.clone(), session_id, lap }));
+        fs::remove_file(&path)?;
+    }
+    // leftovers of a crash mid-snapshot
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        if entry.path().extension().is_some_and(|e| e == "tmp") {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    Ok(out)
+}
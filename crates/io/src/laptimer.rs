@@ -0,0 +1,294 @@
+//! CSV exports of phone lap-timer apps: Harry's LapTimer and TrackAddict.
+//!
+//! Both write optional preamble lines (`# Key: value` for TrackAddict, free
+//! text for HLT), one header row with unit-suffixed names (`Speed (MPH)`,
+//! `Throttle Position (%) *`) and one row per sample with a lap counter.
+//! Lap 0 is the out-lap before the first line crossing and is skipped. As
+//! with [RaceChrono](crate::racechrono), pedals missing from the log are
+//! estimated from longitudinal acceleration.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use uuid::Uuid;
+
+use model::*;
+
+use crate::geo::GeoOrigin;
+use crate::racechrono::{FULL_BRAKE_G, FULL_THROTTLE_G};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LapTimerApp {
+    HarrysLapTimer,
+    TrackAddict,
+}
+
+impl LapTimerApp {
+    fn game(self) -> &'static str {
+        match self {
+            LapTimerApp::HarrysLapTimer => "harrys_laptimer",
+            LapTimerApp::TrackAddict => "trackaddict",
+        }
+    }
+
+    fn lap_names(self) -> &'static [&'static str] {
+        match self {
+            LapTimerApp::HarrysLapTimer => &["lap #", "lapno", "lap number", "lap"],
+            LapTimerApp::TrackAddict => &["lap"],
+        }
+    }
+}
+
+pub fn import_harrys_laptimer(path: &Path, origin: Option<&GeoOrigin>) -> Result<Vec<Lap>> {
+    import_laptimer(path, LapTimerApp::HarrysLapTimer, origin)
+}
+
+pub fn import_trackaddict(path: &Path, origin: Option<&GeoOrigin>) -> Result<Vec<Lap>> {
+    import_laptimer(path, LapTimerApp::TrackAddict, origin)
+}
+
+/// Header name without the OBD marker, lower-cased, split into name and unit:
+/// `Speed (MPH) *` becomes `("speed", "mph")`.
+fn split_header(h: &str) -> (String, String) {
+    let h = h.trim().trim_end_matches('*').trim().to_lowercase();
+    match (h.rfind('('), h.ends_with(')')) {
+        (Some(i), true) => (h[..i].trim().to_string(), h[i + 1..h.len() - 1].trim().to_string()),
+        _ => (h, String::new()),
+    }
+}
+
+pub fn import_laptimer(path: &Path, app: LapTimerApp, origin: Option<&GeoOrigin>) -> Result<Vec<Lap>> {
+    let mut rdr = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_path(path)?;
+    let records: Vec<csv::StringRecord> = rdr.records().collect::<Result<_, _>>()?;
+
+    let (mut track, mut car) = (String::new(), "Unknown".to_string());
+    let mut header_at = None;
+    for (i, r) in records.iter().enumerate() {
+        let first = r.get(0).unwrap_or("").trim();
+        if let Some(kv) = first.strip_prefix('#') {
+            if let Some((k, v)) = kv.split_once(':') {
+                match k.trim().to_lowercase().as_str() {
+                    "track" | "track name" => track = v.trim().to_string(),
+                    "vehicle" | "car" => car = v.trim().to_string(),
+                    _ => {}
+                }
+            }
+            continue;
+        }
+        if r.iter().any(|h| split_header(h).0 == "time") {
+            header_at = Some(i);
+            break;
+        }
+    }
+    let header_at = header_at.ok_or_else(|| anyhow!("no lap timer header row found"))?;
+    let headers: Vec<(String, String)> = records[header_at].iter().map(split_header).collect();
+    let data: Vec<Vec<Option<f64>>> = records[header_at + 1..]
+        .iter()
+        .filter(|r| r.get(0).and_then(|v| v.trim().parse::<f64>().ok()).is_some())
+        .map(|r| r.iter().map(|v| v.trim().parse::<f64>().ok()).collect())
+        .collect();
+
+    let find = |names: &[&str]| names.iter().find_map(|n| headers.iter().position(|(h, _)| h == n));
+    let unit = |c: Option<usize>| c.map(|i| headers[i].1.as_str()).unwrap_or("");
+
+    let time = find(&["time"]).ok_or_else(|| anyhow!("no time column"))?;
+    let lap_col = find(app.lap_names()).ok_or_else(|| anyhow!("no lap column"))?;
+    let lat = find(&["latitude", "lat"]);
+    let lon = find(&["longitude", "lon", "long"]);
+    // several speed columns in different units are common; prefer metric
+    let speed = headers
+        .iter()
+        .position(|(h, u)| h == "speed" && (u == "kph" || u == "km/h"))
+        .or(find(&["speed", "gps speed"]));
+    let speed_to_kph = match unit(speed) {
+        "mph" => 1.609_344,
+        "m/s" => 3.6,
+        "knots" | "kn" => 1.852,
+        _ => 1.0,
+    };
+    let heading = find(&["heading", "course"]);
+    let dist = find(&["distance", "lap distance", "trip distance"]);
+    let dist_to_m = match unit(dist) {
+        "km" => 1000.0,
+        "mi" | "miles" => 1609.344,
+        "ft" => 0.3048,
+        _ => 1.0,
+    };
+    let long_acc = match app {
+        // TrackAddict: Accel Y is along the direction of travel in the car-mount frame
+        LapTimerApp::TrackAddict => find(&["accel y", "longitudinal acceleration"]),
+        LapTimerApp::HarrysLapTimer => find(&["longitudinal acceleration", "accel lon", "g long", "accel y"]),
+    };
+    let throttle = find(&["throttle position", "throttle", "accelerator pedal position"]);
+    let brake = find(&["brake position", "brake", "brake pressure"]);
+    let rpm = find(&["rpm", "engine speed", "engine rpm"]);
+    let gear = find(&["gear"]);
+
+    let get = |r: &[Option<f64>], c: Option<usize>| c.and_then(|i| r.get(i).copied().flatten());
+    let origin = origin.copied().unwrap_or_else(|| {
+        data.iter()
+            .find_map(|r| Some(GeoOrigin::new(get(r, lat)?, get(r, lon)?)))
+            .unwrap_or_default()
+    });
+
+    let mut laps: Vec<Lap> = Vec::new();
+    let mut starts_s = Vec::new();
+    let mut d0 = 0.0;
+    for r in &data {
+        let Some(num) = get(r, Some(lap_col)).map(|v| v as u32) else { continue };
+        if num == 0 {
+            continue;
+        }
+        let Some(t_s) = get(r, Some(time)) else { continue };
+        if laps.last().map(|l| l.meta.lap_number) != Some(num) {
+            laps.push(new_lap(app, &track, &car, num));
+            starts_s.push(t_s);
+            d0 = get(r, dist).unwrap_or(0.0) * dist_to_m;
+        }
+        let (x, y) = match (get(r, lat), get(r, lon)) {
+            (Some(la), Some(lo)) => origin.to_local(la, lo),
+            _ => (0.0, 0.0),
+        };
+        let acc = get(r, long_acc);
+        let (Some(lap), Some(t0)) = (laps.last_mut(), starts_s.last()) else { continue };
+        lap.points.push(TelemetryPoint {
+            t_ms: (t_s - t0) * 1000.0,
+            lap_distance_m: get(r, dist).map(|d| d * dist_to_m - d0).unwrap_or(0.0),
+            x,
+            y,
+            speed_kph: get(r, speed).unwrap_or(0.0) * speed_to_kph,
+            throttle: get(r, throttle)
+                .map(|v| v / 100.0)
+                .or(acc.map(|g| (g / FULL_THROTTLE_G).clamp(0.0, 1.0)))
+                .unwrap_or(0.0),
+            brake: get(r, brake)
+                .map(|v| v / 100.0)
+                .or(acc.map(|g| (-g / FULL_BRAKE_G).clamp(0.0, 1.0)))
+                .unwrap_or(0.0),
+            gear: get(r, gear).unwrap_or(0.0) as i8,
+            rpm: get(r, rpm).unwrap_or(0.0),
+            yaw: get(r, heading).map(|b| (b - origin.bearing_deg).to_radians()).unwrap_or(0.0),
+        });
+    }
+
+    if dist.is_none() {
+        for lap in &mut laps {
+            let mut cum = 0.0;
+            for i in 1..lap.points.len() {
+                let (a, b) = (&lap.points[i - 1], &lap.points[i]);
+                cum += (b.x - a.x).hypot(b.y - a.y);
+                lap.points[i].lap_distance_m = cum;
+            }
+        }
+    }
+    // a lap runs until the next one starts; the last one until recording stopped
+    for i in 0..laps.len() {
+        let next = laps.get(i + 1).filter(|n| n.meta.lap_number == laps[i].meta.lap_number + 1);
+        let ms = match next {
+            Some(_) => (starts_s[i + 1] - starts_s[i]) * 1000.0,
+            None => laps[i].points.last().map(|p| p.t_ms).unwrap_or(0.0),
+        };
+        laps[i].total_time_ms = ms.max(0.0).round() as u64;
+    }
+    Ok(laps)
+}
+
+fn new_lap(app: LapTimerApp, track: &str, car: &str, lap_number: u32) -> Lap {
+    Lap {
+        id: Uuid::new_v4(),
+        meta: LapMeta {
+            id: Uuid::new_v4(),
+            game: app.game().into(),
+            car: car.into(),
+            track: track.into(),
+            lap_number,
+            out_lap: false,
+            in_lap: false,
+        },
+        total_time_ms: 0,
+        points: Vec::new(),
+        markers: Vec::new(),
+        index: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    fn import(app: LapTimerApp, text: &str) -> Result<Vec<Lap>> {
+        let path = TempPath::new("csv");
+        std::fs::write(&*path, text).unwrap();
+        import_laptimer(&path, app, None)
+    }
+
+    /// TrackAddict: `#` preamble, speed in mph, OBD pedals, no distance column.
+    const TRACKADDICT: &str = "\
+# RaceRender Data: TrackAddict 4.9.2 on iOS
+# Vehicle: Mazda MX-5
+# Track: Lydden Hill
+Time,UTC Time,Lap,Latitude,Longitude,Speed (MPH),Heading,Accel Y,Throttle Position (%) *,Engine Speed (RPM) *
+0.0,1700000000.0,0,51.1770,1.1990,30,0,0.0,50,3000
+1.0,1700000001.0,1,51.1770,1.1990,40,0,0.2,80,4000
+2.0,1700000002.0,1,51.1771,1.1990,50,0,-0.6,0,5000
+3.0,1700000003.0,1,51.1772,1.1990,60,90,0.0,100,6000
+4.5,1700000004.5,2,51.1773,1.1990,60,90,0.0,100,6000
+5.0,1700000005.0,2,51.1774,1.1990,60,90,0.0,100,6000
+";
+
+    #[test]
+    fn decodes_a_trackaddict_export() {
+        let laps = import(LapTimerApp::TrackAddict, TRACKADDICT).unwrap();
+        // lap 0 is the out-lap
+        assert_eq!(laps.iter().map(|l| l.meta.lap_number).collect::<Vec<_>>(), [1, 2]);
+        let lap = &laps[0];
+        assert_eq!((lap.meta.game.as_str(), lap.meta.car.as_str(), lap.meta.track.as_str()), ("trackaddict", "Mazda MX-5", "Lydden Hill"));
+        // runs until lap 2 starts; the last lap until recording stopped
+        assert_eq!((lap.total_time_ms, laps[1].total_time_ms), (3500, 500));
+        assert_eq!(lap.points.iter().map(|p| p.t_ms).collect::<Vec<_>>(), [0.0, 1000.0, 2000.0]);
+
+        let p = &lap.points[0];
+        assert!((p.speed_kph - 64.37376).abs() < 1e-9);
+        assert!((p.throttle - 0.8).abs() < 1e-9 && p.rpm == 4000.0);
+        // throttle was logged, brake wasn't: that one comes from Accel Y
+        assert!((lap.points[1].brake - 0.6).abs() < 1e-9);
+        // distance worked out from the GPS track, 0.0001° of latitude a step
+        assert_eq!(p.lap_distance_m, 0.0);
+        assert!((lap.points[2].lap_distance_m - 22.24).abs() < 0.01, "{}", lap.points[2].lap_distance_m);
+        assert!((lap.points[2].yaw - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+    }
+
+    /// Harry's LapTimer: free-text preamble, speed in two units, distance in km.
+    const HARRYS: &str = "\
+Harry's LapTimer Petrolhead Edition
+Exported session
+Time (s),Lap #,Distance (km),Speed (mph),Speed (kph),Latitude,Longitude,Course,Longitudinal Acceleration (g)
+10.0,1,1.000,62.1,100,48.0000,11.0000,180,0.2
+10.5,1,1.010,62.1,100,48.0001,11.0000,180,-0.5
+11.0,1,1.025,62.1,100,48.0002,11.0000,180,0.0
+";
+
+    #[test]
+    fn decodes_a_harrys_laptimer_export() {
+        let laps = import(LapTimerApp::HarrysLapTimer, HARRYS).unwrap();
+        assert_eq!(laps.len(), 1);
+        let lap = &laps[0];
+        assert_eq!((lap.meta.game.as_str(), lap.meta.car.as_str()), ("harrys_laptimer", "Unknown"));
+        assert_eq!(lap.total_time_ms, 1000);
+        // metric speed column preferred; distance rebased to the lap start
+        assert!(lap.points.iter().all(|p| p.speed_kph == 100.0));
+        let d: Vec<f64> = lap.points.iter().map(|p| p.lap_distance_m).collect();
+        assert!((d[1] - 10.0).abs() < 1e-9 && (d[2] - 25.0).abs() < 1e-9, "{d:?}");
+        // no pedals logged: estimated from longitudinal g
+        assert!((lap.points[0].throttle - 0.5).abs() < 1e-9);
+        assert!((lap.points[1].brake - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn needs_a_header_with_time_and_lap() {
+        assert!(import(LapTimerApp::TrackAddict, "# Track: Nowhere\n1,2,3\n").is_err());
+        assert!(import(LapTimerApp::TrackAddict, "Time,Speed (MPH)\n0.0,30\n").is_err());
+    }
+}
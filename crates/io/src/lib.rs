@@ -1,86 +1,182 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::BufRead, path::Path};
+use std::{fs::File, path::Path};
 use uuid::Uuid;
 use std::io::Write;
 
 use model::*; // assumes Lap, LapMeta, TelemetryPoint, etc. are in this crate
+use analysis::math::{channel_column, eval_channels, MathChannel};
+
+pub mod autosave;
+pub mod binary;
+pub mod build;
+pub mod bundle;
+pub mod catalyst;
+pub mod charts;
+pub mod csv_profile;
+pub mod export;
+pub mod geo;
+pub mod geojson;
+pub mod gpx;
+pub mod import;
+pub mod journal;
+pub mod laptimer;
+pub mod motec;
+pub mod overlay;
+pub mod racechrono;
+pub mod sidecar;
+pub mod stream;
+pub mod svg;
+pub mod vbo;
+pub mod workspace;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+#[cfg(feature = "arrow")]
+pub mod ipc;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+#[cfg(test)]
+mod testutil;
 
 pub fn import_csv(path: &Path) -> Result<Vec<Lap>> {
-    let mut rdr = csv::Reader::from_path(path)?;
-    let mut laps = Vec::<Lap>::new();
-    let mut current: Option<Lap> = None;
-
-    for rec in rdr.deserialize() {
-        let r: CsvRow = rec?;
-
-        // start a new lap when lap_number changes
-        if current.as_ref().map(|l| l.meta.lap_number) != Some(r.lap_number) {
-            if let Some(l) = current.take() {
-                laps.push(l);
-            }
-            current = Some(new_lap(&r));
+    stream::stream_csv(path)?.collect()
+}
+
+/// Point columns of the default CSV layout, after game/car/track/lap_number.
+pub const CSV_COLUMNS: &[&str] =
+    &["t_ms", "lap_distance_m", "x", "y", "speed_kph", "throttle", "brake", "gear", "rpm"];
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedUnit {
+    #[default]
+    Kph,
+    Mph,
+}
+
+/// How CSV exports are formatted. The defaults give the layout
+/// [`import_csv`] reads back.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CsvOptions {
+    /// Unit of the speed column, which is renamed `speed_mph` for mph.
+    #[serde(default)]
+    pub speed_unit: SpeedUnit,
+    #[serde(default = "csv_profile::comma")]
+    pub delimiter: char,
+    /// Decimal separator; `,` for most European locales.
+    #[serde(default = "csv_profile::dot")]
+    pub decimal: char,
+    /// Write a second header row with each column's unit.
+    #[serde(default)]
+    pub unit_row: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self { speed_unit: SpeedUnit::Kph, delimiter: ',', decimal: '.', unit_row: false }
+    }
+}
+
+impl CsvOptions {
+    fn writer(&self, path: &Path) -> Result<csv::Writer<File>> {
+        if !self.delimiter.is_ascii() {
+            anyhow::bail!("delimiter must be an ASCII character");
+        }
+        Ok(csv::WriterBuilder::new().delimiter(self.delimiter as u8).from_path(path)?)
+    }
+
+    fn number(&self, v: f64) -> String {
+        let s = v.to_string();
+        if self.decimal == '.' {
+            s
+        } else {
+            s.replace('.', self.decimal.encode_utf8(&mut [0; 4]))
         }
+    }
 
-        if let Some(l) = &mut current {
-            l.points.push(TelemetryPoint {
-                t_ms: r.t_ms,
-                lap_distance_m: r.lap_distance_m,
-                x: r.x,
-                y: r.y,
-                speed_kph: r.speed_kph,
-                throttle: r.throttle,
-                brake: r.brake,
-                gear: r.gear,
-                rpm: r.rpm,
-            });
-            l.total_time_ms = r.t_ms as u64;
+    /// Header name, unit and value scale of a channel column.
+    fn column(&self, name: &str) -> (String, &'static str, f64) {
+        match (name, self.speed_unit) {
+            ("speed_kph", SpeedUnit::Mph) => ("speed_mph".into(), "mph", 1.0 / 1.609_344),
+            _ => (name.into(), channel_unit(name), 1.0),
         }
     }
+}
 
-    if let Some(l) = current.take() {
-        laps.push(l);
+pub(crate) fn channel_unit(name: &str) -> &'static str {
+    match name {
+        "t_ms" => "ms",
+        "t_s" => "s",
+        "lap_distance_m" | "x" | "y" => "m",
+        "speed_kph" => "km/h",
+        "speed_mps" => "m/s",
+        "throttle" | "brake" => "0-1",
+        "rpm" => "rpm",
+        "yaw" => "rad",
+        "long_g" | "lat_g" => "g",
+        _ => "",
     }
-    Ok(laps)
 }
 
 pub fn export_csv(laps: &[Lap], path: &Path) -> Result<()> {
-    let mut w = csv::Writer::from_path(path)?;
-
-    // Write proper header row (column names)
-    w.write_record(&[
-        "game",
-        "car",
-        "track",
-        "lap_number",
-        "t_ms",
-        "lap_distance_m",
-        "x",
-        "y",
-        "speed_kph",
-        "throttle",
-        "brake",
-        "gear",
-        "rpm",
-    ])?;
+    export_csv_with(laps, path, &CsvOptions::default())
+}
+
+/// The default layout ([`CSV_COLUMNS`]) formatted per `opts`.
+pub fn export_csv_with(laps: &[Lap], path: &Path, opts: &CsvOptions) -> Result<()> {
+    export_csv_columns(laps, CSV_COLUMNS, path, opts)
+}
+
+/// Same layout as [`export_csv`], with one extra column per math channel.
+pub fn export_csv_with_channels(laps: &[Lap], channels: &[MathChannel], path: &Path) -> Result<()> {
+    write_csv(laps, CSV_COLUMNS, channels, path, &CsvOptions::default())
+}
+
+/// Lap metadata and `t_ms` followed by only the listed channels (any name in
+/// [`analysis::math::CHANNELS`]).
+pub fn export_csv_columns(laps: &[Lap], columns: &[&str], path: &Path, opts: &CsvOptions) -> Result<()> {
+    write_csv(laps, columns, &[], path, opts)
+}
+
+/// Lap metadata, `t_ms`, `columns`, then one column per math channel.
+fn write_csv(
+    laps: &[Lap],
+    columns: &[&str],
+    channels: &[MathChannel],
+    path: &Path,
+    opts: &CsvOptions,
+) -> Result<()> {
+    let mut w = opts.writer(path)?;
+    let mut columns: Vec<&str> = columns.iter().copied().filter(|c| *c != "t_ms").collect();
+    columns.insert(0, "t_ms");
+    let cols: Vec<(String, &str, f64)> = columns.iter().map(|c| opts.column(c)).collect();
+
+    let mut header = vec!["game", "car", "track", "lap_number"];
+    header.extend(cols.iter().map(|(name, ..)| name.as_str()));
+    header.extend(channels.iter().map(|c| c.name.as_str()));
+    w.write_record(&header)?;
+    if opts.unit_row {
+        let mut units = vec![""; 4];
+        units.extend(cols.iter().map(|(_, unit, _)| *unit));
+        units.extend(channels.iter().map(|_| ""));
+        w.write_record(&units)?;
+    }
 
     for l in laps {
-        for p in &l.points {
-            w.serialize(CsvRow {
-                game: l.meta.game.clone(),
-                car: l.meta.car.clone(),
-                track: l.meta.track.clone(),
-                lap_number: l.meta.lap_number,
-                t_ms: p.t_ms,
-                lap_distance_m: p.lap_distance_m,
-                x: p.x,
-                y: p.y,
-                speed_kph: p.speed_kph,
-                throttle: p.throttle,
-                brake: p.brake,
-                gear: p.gear,
-                rpm: p.rpm,
-            })?;
+        let values: Vec<Vec<f64>> = columns.iter().map(|c| channel_column(l, c)).collect();
+        let extra = eval_channels(l, channels)?;
+        for i in 0..l.points.len() {
+            let mut rec = vec![
+                l.meta.game.clone(),
+                l.meta.car.clone(),
+                l.meta.track.clone(),
+                l.meta.lap_number.to_string(),
+            ];
+            rec.extend(values.iter().zip(&cols).map(|(v, (_, _, scale))| opts.number(v[i] * scale)));
+            rec.extend(extra.iter().map(|(_, v)| opts.number(v[i])));
+            w.write_record(&rec)?;
         }
     }
     w.flush()?;
@@ -88,19 +184,17 @@ pub fn export_csv(laps: &[Lap], path: &Path) -> Result<()> {
 }
 
 pub fn import_ndjson(path: &Path) -> Result<Vec<Lap>> {
-    let f = File::open(path)?;
-    let rdr = std::io::BufReader::new(f);
-    let mut laps = Vec::new();
-
-    for line in rdr.lines() {
-        let s = line?;
-        let l: Lap = serde_json::from_str(&s)?;
-        laps.push(l);
-    }
-    Ok(laps)
+    stream::stream_ndjson(path)?.collect()
 }
 
+/// One lap per line, ids, metadata and every sample kept exactly, so
+/// [`import_ndjson`] reads back equal laps. JSON has no NaN or infinity, so
+/// laps holding them are refused before anything is written; [`binary`]
+/// keeps them.
 pub fn export_ndjson(laps: &[Lap], path: &Path) -> Result<()> {
+    for l in laps {
+        check_finite(l)?;
+    }
     let f = File::create(path)?;
     let mut w = std::io::BufWriter::new(f);
     for l in laps {
@@ -111,49 +205,46 @@ pub fn export_ndjson(laps: &[Lap], path: &Path) -> Result<()> {
     Ok(())
 }
 
-pub fn export_motec_csv(laps: &[Lap], path: &Path) -> Result<()> {
-    let mut w = csv::Writer::from_path(path)?;
-    w.write_record(&[
-        "Time",
-        "LapDistance",
-        "X",
-        "Y",
-        "Speed",
-        "Throttle",
-        "Brake",
-        "Gear",
-        "RPM",
-        "LapNumber",
-        "Track",
-        "Car",
-        "Game",
-    ])?;
-
-    for l in laps {
-        let t0 = l.points.first().map(|p| p.t_ms).unwrap_or(0.0);
-        for p in &l.points {
-            w.write_record(&[
-                format!("{:.6}", (p.t_ms - t0) / 1000.0),
-                format!("{:.3}", p.lap_distance_m),
-                format!("{:.4}", p.x),
-                format!("{:.4}", p.y),
-                format!("{:.3}", p.speed_kph),
-                format!("{:.3}", p.throttle),
-                format!("{:.3}", p.brake),
-                format!("{}", p.gear),
-                format!("{:.1}", p.rpm),
-                format!("{}", l.meta.lap_number),
-                l.meta.track.clone(),
-                l.meta.car.clone(),
-                l.meta.game.clone(),
-            ])?;
+/// Fails on the first NaN or infinite value in `l`, which JSON can't hold.
+fn check_finite(l: &Lap) -> Result<()> {
+    for (i, p) in l.points.iter().enumerate() {
+        let values = [p.t_ms, p.lap_distance_m, p.x, p.y, p.speed_kph, p.throttle, p.brake, p.rpm, p.yaw];
+        if values.iter().any(|v| !v.is_finite()) {
+            anyhow::bail!("lap {} point {i} has a NaN or infinite value; export it as binary instead", l.meta.lap_number);
+        }
+    }
+    for m in &l.markers {
+        if ![m.t_ms, m.lap_distance_m, m.x, m.y, m.severity].iter().all(|v| v.is_finite()) {
+            anyhow::bail!("lap {} has a marker with a NaN or infinite value; export it as binary instead", l.meta.lap_number);
         }
     }
-
-    w.flush()?;
     Ok(())
 }
 
+/// Save laps in the default on-disk format ([`binary`]).
+pub fn save_laps(laps: &[Lap], path: &Path) -> Result<()> {
+    binary::export_binary(laps, path)
+}
+
+/// Append laps to a file in the default format, creating it if needed.
+pub fn append_laps(laps: &[Lap], path: &Path) -> Result<()> {
+    binary::append_binary(laps, path)
+}
+
+/// Load laps saved by [`save_laps`], falling back to NDJSON for older files.
+pub fn load_laps(path: &Path) -> Result<Vec<Lap>> {
+    if binary::is_binary(path)? {
+        binary::import_binary(path)
+    } else {
+        import_ndjson(path)
+    }
+}
+
+/// MoTeC i2 CSV at 50 Hz; see [`motec::export_motec_csv`] for options.
+pub fn export_motec_csv(laps: &[Lap], path: &Path) -> Result<()> {
+    motec::export_motec_csv(laps, path, &motec::MotecOptions::default())
+}
+
 fn new_lap(r: &CsvRow) -> Lap {
     Lap {
         id: Uuid::new_v4(), // change to `Uuid::new_v4().to_string()` if your model expects String
@@ -163,9 +254,13 @@ fn new_lap(r: &CsvRow) -> Lap {
             car: r.car.clone(),
             track: r.track.clone(),
             lap_number: r.lap_number,
+            out_lap: false,
+            in_lap: false,
         },
         total_time_ms: 0,
         points: Vec::new(),
+        markers: Vec::new(),
+        index: Default::default(),
     }
 }
 
@@ -185,3 +280,52 @@ struct CsvRow {
     gear: i8,
     rpm: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, TempPath};
+
+    #[test]
+    fn ndjson_round_trip_is_exact() {
+        let laps = vec![lap(1, 50), lap(2, 80), lap(3, 0)];
+        let path = TempPath::new("ndjson");
+        export_ndjson(&laps, &path).unwrap();
+        assert_eq!(import_ndjson(&path).unwrap(), laps);
+    }
+
+    #[test]
+    fn binary_round_trip_is_exact() {
+        let mut laps = vec![lap(1, 50), lap(2, 80)];
+        // binary keeps what JSON can't
+        laps[1].points[3].rpm = f64::INFINITY;
+        let path = TempPath::new(binary::EXTENSION);
+        binary::export_binary(&laps, &path).unwrap();
+        assert_eq!(binary::import_binary(&path).unwrap(), laps);
+    }
+
+    #[test]
+    fn ndjson_refuses_non_finite_values() {
+        let mut laps = vec![lap(1, 10)];
+        laps[0].points[4].speed_kph = f64::NAN;
+        let path = TempPath::new("ndjson");
+        assert!(export_ndjson(&laps, &path).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn csv_with_channels_adds_columns_to_the_default_layout() {
+        let laps = vec![lap(1, 20)];
+        let channels = [MathChannel { name: "brake_pct".into(), expr: "brake*100".into() }];
+        let path = TempPath::new("csv");
+        export_csv_with_channels(&laps, &channels, &path).unwrap();
+
+        let text = std::fs::read_to_string(&*path).unwrap();
+        let header = text.lines().next().unwrap();
+        assert_eq!(header, format!("game,car,track,lap_number,{},brake_pct", CSV_COLUMNS.join(",")));
+        let row: Vec<&str> = text.lines().nth(2).unwrap().split(',').collect();
+        let p = &laps[0].points[1];
+        assert_eq!(row[4].parse::<f64>().unwrap(), p.t_ms);
+        assert_eq!(row.last().unwrap().parse::<f64>().unwrap(), p.brake * 100.0);
+    }
+}
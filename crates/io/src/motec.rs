@@ -0,0 +1,692 @@
+//! MoTeC i2 export: native logs (`.ld` data file plus `.ldx` lap beacons) and
+//! i2-style CSV with its metadata header block. Also imports i2 CSV exports,
+//! including the ones AC/ACC users make from their game logs.
+//!
+//! The `.ld` layout follows the community-documented format: a fixed 1762-byte
+//! header, event/venue/vehicle blocks, a linked list of 124-byte channel
+//! descriptors and one contiguous block of little-endian `f32` samples per channel.
+
+use anyhow::{anyhow, Result};
+use std::{fs, io::Write, path::Path};
+use uuid::Uuid;
+
+use model::*;
+
+#[derive(Clone, Debug)]
+pub struct MotecOptions {
+    /// Fixed sample rate the laps are resampled to.
+    pub freq_hz: u16,
+    pub driver: String,
+    pub comment: String,
+    /// Model channels to write (`speed_kph`, `throttle`, ...); all when `None`.
+    /// Lap number is always written.
+    pub channels: Option<Vec<String>>,
+}
+
+impl Default for MotecOptions {
+    fn default() -> Self {
+        Self { freq_hz: 50, driver: String::new(), comment: String::new(), channels: None }
+    }
+}
+
+const HEADER_LEN: u32 = 1762;
+const EVENT_LEN: u32 = 1154;
+const VENUE_LEN: u32 = 1100;
+const VEHICLE_LEN: u32 = 260;
+const CHAN_LEN: u32 = 124;
+
+struct Channel {
+    name: &'static str,
+    short: &'static str,
+    unit: &'static str,
+    /// Model channel the values come from.
+    field: &'static str,
+    get: fn(&Lap, &TelemetryPoint) -> f64,
+}
+
+const CHANNELS: &[Channel] = &[
+    Channel { name: "Ground Speed", short: "Speed", unit: "km/h", field: "speed_kph", get: |_, p| p.speed_kph },
+    Channel { name: "Throttle Pos", short: "Thr", unit: "%", field: "throttle", get: |_, p| p.throttle * 100.0 },
+    Channel { name: "Brake Pos", short: "Brk", unit: "%", field: "brake", get: |_, p| p.brake * 100.0 },
+    Channel { name: "Gear", short: "Gear", unit: "", field: "gear", get: |_, p| p.gear as f64 },
+    Channel { name: "Engine RPM", short: "RPM", unit: "rpm", field: "rpm", get: |_, p| p.rpm },
+    Channel { name: "Lap Distance", short: "LapDist", unit: "m", field: "lap_distance_m", get: |_, p| p.lap_distance_m },
+    Channel { name: "Lap Number", short: "Lap", unit: "", field: "lap_number", get: |l, _| l.meta.lap_number as f64 },
+    Channel { name: "Pos X", short: "PosX", unit: "m", field: "x", get: |_, p| p.x },
+    Channel { name: "Pos Y", short: "PosY", unit: "m", field: "y", get: |_, p| p.y },
+];
+
+fn selected_channels(opts: &MotecOptions) -> Vec<&'static Channel> {
+    CHANNELS
+        .iter()
+        .filter(|c| {
+            c.field == "lap_number"
+                || opts.channels.as_ref().is_none_or(|sel| sel.iter().any(|s| s == c.field))
+        })
+        .collect()
+}
+
+/// Laps resampled back-to-back onto a fixed rate: one column per channel,
+/// the index into the input laps of each sample, and the log time (s) at
+/// which each lap ends.
+struct FixedRate {
+    columns: Vec<Vec<f32>>,
+    lap_of: Vec<usize>,
+    lap_ends_s: Vec<f64>,
+}
+
+fn resample_fixed_rate(laps: &[Lap], freq: u16, chans: &[&Channel]) -> FixedRate {
+    let dt_ms = 1000.0 / freq as f64;
+    let mut columns: Vec<Vec<f32>> = vec![Vec::new(); chans.len()];
+    let mut lap_of = Vec::new();
+    let mut lap_ends_s = Vec::new();
+    for (li, lap) in laps.iter().enumerate() {
+        let (Some(first), Some(last)) = (lap.points.first(), lap.points.last()) else {
+            continue;
+        };
+        let mut k = 0usize;
+        let mut t = first.t_ms;
+        while t <= last.t_ms {
+            while k + 1 < lap.points.len() && lap.points[k + 1].t_ms < t {
+                k += 1;
+            }
+            let a = &lap.points[k];
+            let b = &lap.points[(k + 1).min(lap.points.len() - 1)];
+            let f = if b.t_ms > a.t_ms { ((t - a.t_ms) / (b.t_ms - a.t_ms)).clamp(0.0, 1.0) } else { 0.0 };
+            for (col, ch) in columns.iter_mut().zip(chans) {
+                let (va, vb) = ((ch.get)(lap, a), (ch.get)(lap, b));
+                col.push((va + f * (vb - va)) as f32);
+            }
+            lap_of.push(li);
+            t += dt_ms;
+        }
+        lap_ends_s.push(columns[0].len() as f64 / freq as f64);
+    }
+    FixedRate { columns, lap_of, lap_ends_s }
+}
+
+pub(crate) fn log_date_time() -> (String, String) {
+    let now = time::OffsetDateTime::now_utc();
+    (
+        format!("{:02}/{:02}/{}", now.day(), u8::from(now.month()), now.year()),
+        format!("{:02}:{:02}:{:02}", now.hour(), now.minute(), now.second()),
+    )
+}
+
+fn lap_time_str(ms: u64) -> String {
+    format!("{}:{:02}.{:03}", ms / 60000, (ms / 1000) % 60, ms % 1000)
+}
+
+/// Write `laps` back-to-back into `path` (.ld) and the matching `.ldx` beside it.
+pub fn export_motec_ld(laps: &[Lap], path: &Path, opts: &MotecOptions) -> Result<()> {
+    let freq = opts.freq_hz.max(1);
+    let chans = selected_channels(opts);
+    let FixedRate { columns, lap_ends_s, .. } = resample_fixed_rate(laps, freq, &chans);
+    let n_samples = columns[0].len() as u32;
+
+    let first = laps.first().map(|l| &l.meta);
+    let venue = first.map(|m| m.track.as_str()).unwrap_or("");
+    let vehicle = first.map(|m| m.car.as_str()).unwrap_or("");
+    let event = first.map(|m| format!("{} {}", m.game, m.track)).unwrap_or_default();
+
+    let event_ptr = HEADER_LEN;
+    let venue_ptr = event_ptr + EVENT_LEN;
+    let vehicle_ptr = venue_ptr + VENUE_LEN;
+    let meta_ptr = vehicle_ptr + VEHICLE_LEN;
+    let data_ptr = meta_ptr + CHAN_LEN * chans.len() as u32;
+
+    let (date, clock) = log_date_time();
+
+    let mut b = Buf::default();
+    // header
+    b.u32(0x40);
+    b.pad(4);
+    b.u32(meta_ptr);
+    b.u32(data_ptr);
+    b.pad(20);
+    b.u32(event_ptr);
+    b.pad(24);
+    b.u16(1);
+    b.u16(0x4240);
+    b.u16(0xf);
+    b.u32(0x1f44);
+    b.str("ADL", 8);
+    b.u16(420);
+    b.u16(0xadb0);
+    b.u32(chans.len() as u32);
+    b.pad(4);
+    b.str(&date, 16);
+    b.pad(16);
+    b.str(&clock, 16);
+    b.pad(16);
+    b.str(&opts.driver, 64);
+    b.str(vehicle, 64);
+    b.pad(64);
+    b.str(venue, 64);
+    b.pad(64);
+    b.pad(1024);
+    b.u32(0xc81a4);
+    b.pad(66);
+    b.str(&opts.comment, 64);
+    b.pad(126);
+    debug_assert_eq!(b.0.len() as u32, event_ptr);
+
+    // event -> venue -> vehicle
+    b.str(&event, 64);
+    b.str("", 64);
+    b.str(&opts.comment, 1024);
+    b.u16(venue_ptr as u16);
+    b.str(venue, 64);
+    b.pad(1034);
+    b.u16(vehicle_ptr as u16);
+    b.str(vehicle, 64);
+    b.pad(128);
+    b.u32(0);
+    b.str("", 32);
+    b.str("", 32);
+    debug_assert_eq!(b.0.len() as u32, meta_ptr);
+
+    // channel descriptors, doubly linked
+    for (i, ch) in chans.iter().enumerate() {
+        let i = i as u32;
+        let this = meta_ptr + i * CHAN_LEN;
+        let prev = if i == 0 { 0 } else { this - CHAN_LEN };
+        let next = if i + 1 == chans.len() as u32 { 0 } else { this + CHAN_LEN };
+        b.u32(prev);
+        b.u32(next);
+        b.u32(data_ptr + i * n_samples * 4);
+        b.u32(n_samples);
+        b.u16(0x2ee1 + i as u16);
+        b.u16(0x07); // float
+        b.u16(4); // 4 bytes
+        b.u16(freq);
+        b.i16(0); // shift
+        b.i16(1); // mul
+        b.i16(1); // scale
+        b.i16(0); // decimal places
+        b.str(ch.name, 32);
+        b.str(ch.short, 8);
+        b.str(ch.unit, 12);
+        b.pad(40);
+    }
+    debug_assert_eq!(b.0.len() as u32, data_ptr);
+
+    for col in &columns {
+        for v in col {
+            b.0.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    fs::write(path, &b.0)?;
+
+    fs::write(path.with_extension("ldx"), ldx(laps, &lap_ends_s))?;
+    Ok(())
+}
+
+/// Lap beacons and session details for i2's lap table.
+fn ldx(laps: &[Lap], lap_ends_s: &[f64]) -> String {
+    let mut markers = String::new();
+    // the final lap end is the end of the log, not a beacon
+    for (i, t) in lap_ends_s.iter().take(lap_ends_s.len().saturating_sub(1)).enumerate() {
+        markers.push_str(&format!(
+            "     <Marker Version=\"100\" ClassName=\"BCN\" Name=\"Manual.{}\" Flags=\"77\" Time=\"{:.3}\"/>\n",
+            i + 1,
+            t * 1e6
+        ));
+    }
+
+    // lap numbers in i2 count the laps in the log, so laps without points,
+    // which the log leaves out, don't count
+    let written: Vec<&Lap> = laps.iter().filter(|l| !l.points.is_empty()).collect();
+    let fastest = written
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| l.total_time_ms > 0)
+        .min_by_key(|(_, l)| l.total_time_ms);
+    let fastest_time = fastest.map(|(_, l)| lap_time_str(l.total_time_ms)).unwrap_or_default();
+    let fastest_lap = fastest.map(|(i, _)| i + 1).unwrap_or(0);
+
+    let total = lap_ends_s.len();
+    let mut x = String::new();
+    x.push_str("<?xml version=\"1.0\"?>\n");
+    x.push_str("<LDXFile Locale=\"English_United Kingdom.1252\" DefaultLocale=\"C\" Version=\"1.6\">\n");
+    x.push_str(" <Layers>\n  <Layer>\n   <MarkerBlock>\n");
+    x.push_str("    <MarkerGroup Name=\"Beacons\" Index=\"3\">\n");
+    x.push_str(&markers);
+    x.push_str("    </MarkerGroup>\n   </MarkerBlock>\n   <RangeBlock/>\n  </Layer>\n");
+    x.push_str("  <Details>\n");
+    x.push_str(&format!("   <String Id=\"Total Laps\" Value=\"{total}\"/>\n"));
+    x.push_str(&format!("   <String Id=\"Fastest Time\" Value=\"{fastest_time}\"/>\n"));
+    x.push_str(&format!("   <String Id=\"Fastest Lap\" Value=\"{fastest_lap}\"/>\n"));
+    x.push_str("  </Details>\n </Layers>\n</LDXFile>\n");
+    x
+}
+
+/// i2 CSV: metadata block (venue, vehicle, driver, rate, beacons, lap times),
+/// then channel names, units and fixed-rate samples. Each sample also carries
+/// its lap's track, car and game, which the header block only holds once.
+pub fn export_motec_csv(laps: &[Lap], path: &Path, opts: &MotecOptions) -> Result<()> {
+    let freq = opts.freq_hz.max(1);
+    let chans = selected_channels(opts);
+    let FixedRate { columns, lap_of, lap_ends_s } = resample_fixed_rate(laps, freq, &chans);
+    let n = columns[0].len();
+    let duration = n as f64 / freq as f64;
+
+    let first = laps.first().map(|l| &l.meta);
+    let venue = first.map(|m| m.track.as_str()).unwrap_or("");
+    let vehicle = first.map(|m| m.car.as_str()).unwrap_or("");
+    let session = first.map(|m| m.game.as_str()).unwrap_or("");
+    let end_distance = laps
+        .last()
+        .and_then(|l| l.points.last())
+        .map(|p| p.lap_distance_m)
+        .unwrap_or(0.0);
+    let (date, clock) = log_date_time();
+
+    let mut w = std::io::BufWriter::new(fs::File::create(path)?);
+
+    let dur = format!("{duration:.3}");
+    let rate = freq.to_string();
+    let end_d = format!("{end_distance:.1}");
+    row(&mut w, &["Format", "MoTeC CSV File", "", "", "Workbook", ""])?;
+    row(&mut w, &["Venue", venue, "", "", "Worksheet", ""])?;
+    row(&mut w, &["Vehicle", vehicle, "", "", "Vehicle Desc", ""])?;
+    row(&mut w, &["Driver", &opts.driver, "", "", "Engine ID", ""])?;
+    row(&mut w, &["Device", "ADL"])?;
+    row(&mut w, &["Comment", &opts.comment, "", "", "Session", session])?;
+    row(&mut w, &["Log Date", &date, "", "", "Origin Time", "0.000", "s"])?;
+    row(&mut w, &["Log Time", &clock, "", "", "Start Time", "0.000", "s"])?;
+    row(&mut w, &["Sample Rate", &rate, "Hz", "", "End Time", &dur, "s"])?;
+    row(&mut w, &["Duration", &dur, "s", "", "Start Distance", "0", "m"])?;
+    row(&mut w, &["Range", "entire outing", "", "", "End Distance", &end_d, "m"])?;
+
+    // beacons sit at every lap boundary except the end of the log
+    let mut beacons = vec!["Beacon Markers".to_string()];
+    beacons.extend(
+        lap_ends_s
+            .iter()
+            .take(lap_ends_s.len().saturating_sub(1))
+            .map(|t| format!("{t:.3}")),
+    );
+    row(&mut w, &beacons)?;
+    let mut segments = vec!["Segment Times".to_string()];
+    segments.extend(laps.iter().filter(|l| !l.points.is_empty()).map(|l| lap_time_str(l.total_time_ms)));
+    row(&mut w, &segments)?;
+    writeln!(w)?;
+
+    let mut names = vec!["Time"];
+    names.extend(chans.iter().map(|c| c.name));
+    names.extend(TEXT_COLUMNS);
+    row(&mut w, &names)?;
+    let mut units = vec!["s"];
+    units.extend(chans.iter().map(|c| c.unit));
+    units.extend(TEXT_COLUMNS.map(|_| ""));
+    row(&mut w, &units)?;
+    writeln!(w)?;
+
+    for i in 0..n {
+        let mut rec = vec![format!("{:.3}", i as f64 / freq as f64)];
+        rec.extend(columns.iter().map(|c| format!("{:.3}", c[i])));
+        let m = &laps[lap_of[i]].meta;
+        rec.extend([m.track.clone(), m.car.clone(), m.game.clone()]);
+        row(&mut w, &rec)?;
+    }
+
+    w.flush()?;
+    Ok(())
+}
+
+/// Per-sample text columns after the channels.
+const TEXT_COLUMNS: [&str; 3] = ["Track", "Car", "Game"];
+
+/// One fully-quoted CSV line, the way i2 writes them.
+fn row<W: Write, S: AsRef<str>>(w: &mut W, fields: &[S]) -> Result<()> {
+    let line: Vec<String> = fields
+        .iter()
+        .map(|f| format!("\"{}\"", f.as_ref().replace('"', "\"\"")))
+        .collect();
+    writeln!(w, "{}", line.join(","))?;
+    Ok(())
+}
+
+/// Channel names seen in i2 CSV exports (ours, AC and ACC), matched
+/// case-insensitively; the first present wins.
+const SPEED: &[&str] = &["Ground Speed", "SPEED", "Speed", "Vehicle Speed"];
+const THROTTLE: &[&str] = &["Throttle Pos", "THROTTLE", "Throttle"];
+const BRAKE: &[&str] = &["Brake Pos", "BRAKE", "Brake"];
+const GEAR: &[&str] = &["Gear", "GEAR"];
+const RPM: &[&str] = &["Engine RPM", "RPMS", "RPM"];
+const LAP_DIST: &[&str] = &["Lap Distance", "LAP_DIST", "Distance"];
+const LAP_NUMBER: &[&str] = &["Lap Number", "LAP_NUMBER", "Lap"];
+const BEACON: &[&str] = &["LAP_BEACON", "Lap Beacon", "Beacon"];
+const POS_X: &[&str] = &["Pos X", "Car Coord X", "CAR_COORD_X", "World Position X"];
+// AC/ACC are y-up, so the ground plane is x/z
+const POS_Y: &[&str] = &["Pos Y", "Car Coord Z", "CAR_COORD_Z", "World Position Z"];
+const HEADING: &[&str] = &["Heading", "CAR_YAW", "Yaw"];
+
+/// Import an i2 CSV export. Laps are split on a lap-number channel, else on
+/// the beacon channel, else on the `Beacon Markers` header times.
+pub fn import_motec_csv(path: &Path) -> Result<Vec<Lap>> {
+    let mut rdr = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_path(path)?;
+    let records: Vec<csv::StringRecord> = rdr.records().collect::<Result<_, _>>()?;
+
+    let meta = |key: &str| {
+        records
+            .iter()
+            .find(|r| r.get(0) == Some(key))
+            .and_then(|r| r.get(1))
+            .unwrap_or("")
+            .trim()
+            .to_string()
+    };
+    let (venue, vehicle) = (meta("Venue"), meta("Vehicle"));
+    let beacon_times: Vec<f64> = records
+        .iter()
+        .find(|r| r.get(0) == Some("Beacon Markers"))
+        .map(|r| r.iter().skip(1).filter_map(|v| v.trim().parse().ok()).collect())
+        .unwrap_or_default();
+
+    let header_at = records
+        .iter()
+        .position(|r| r.get(0) == Some("Time"))
+        .ok_or_else(|| anyhow!("no MoTeC channel header row"))?;
+    let names: Vec<String> = records[header_at].iter().map(|s| s.trim().to_ascii_lowercase()).collect();
+    let units: Vec<String> = records
+        .get(header_at + 1)
+        .map(|r| r.iter().map(|s| s.trim().to_ascii_lowercase()).collect())
+        .unwrap_or_default();
+    let rows: Vec<&csv::StringRecord> = records[header_at + 1..]
+        .iter()
+        .filter(|r| r.get(0).and_then(|v| v.trim().parse::<f64>().ok()).is_some())
+        .collect();
+    let data: Vec<Vec<f64>> = rows
+        .iter()
+        .map(|r| r.iter().map(|v| v.trim().parse().unwrap_or(0.0)).collect())
+        .collect();
+
+    let find = |aliases: &[&str]| {
+        aliases.iter().find_map(|a| names.iter().position(|n| *n == a.to_ascii_lowercase()))
+    };
+    let unit = |c: Option<usize>| c.and_then(|i| units.get(i)).map(String::as_str).unwrap_or("");
+    let get = |r: &[f64], c: Option<usize>| c.and_then(|i| r.get(i).copied());
+
+    let (speed, throttle, brake) = (find(SPEED), find(THROTTLE), find(BRAKE));
+    let (gear, rpm, dist) = (find(GEAR), find(RPM), find(LAP_DIST));
+    let (lap_col, beacon) = (find(LAP_NUMBER), find(BEACON));
+    let (px, py, heading) = (find(POS_X), find(POS_Y), find(HEADING));
+    // our own exports carry track, car and game per sample
+    let [track_col, car_col, game_col] = TEXT_COLUMNS.map(|c| find(&[c]));
+    let text = |r: &csv::StringRecord, c: Option<usize>, or: &str| {
+        c.and_then(|i| r.get(i)).map(str::trim).filter(|v| !v.is_empty()).unwrap_or(or).to_string()
+    };
+
+    let speed_scale = match unit(speed) {
+        "m/s" => 3.6,
+        "mph" => 1.609_344,
+        _ => 1.0,
+    };
+    // pedals are usually %, but some exports leave them as 0..1
+    let pedal_scale = |c: Option<usize>| {
+        let max = data.iter().filter_map(|r| get(r, c)).fold(0.0_f64, f64::max);
+        if unit(c) == "%" || max > 1.5 { 0.01 } else { 1.0 }
+    };
+    let (t_scale, b_scale) = (pedal_scale(throttle), pedal_scale(brake));
+    let heading_scale = if unit(heading).starts_with("deg") { 1f64.to_radians() } else { 1.0 };
+    // a distance channel that never resets is session distance, not lap distance
+    let cumulative = dist.is_some()
+        && data.windows(2).all(|w| get(&w[1], dist) >= get(&w[0], dist).map(|d| d - 1.0));
+
+    let mut laps: Vec<Lap> = Vec::new();
+    let mut lap_num = 0u32;
+    let mut next_beacon = 0;
+    let mut prev_beacon = 0.0;
+    for (r, rec) in data.iter().zip(&rows) {
+        let t_s = r[0];
+        let boundary = match (lap_col, beacon) {
+            (Some(_), _) => {
+                let n = get(r, lap_col).unwrap_or(0.0) as u32;
+                let changed = laps.is_empty() || n != lap_num;
+                lap_num = n;
+                changed
+            }
+            (None, Some(_)) => {
+                let b = get(r, beacon).unwrap_or(0.0);
+                let hit = b != prev_beacon && b != 0.0;
+                prev_beacon = b;
+                hit
+            }
+            _ => {
+                let hit = beacon_times.get(next_beacon).is_some_and(|&bt| t_s >= bt);
+                if hit {
+                    next_beacon += 1;
+                }
+                hit
+            }
+        };
+        if lap_col.is_none() && boundary {
+            lap_num += 1;
+        }
+        if boundary || laps.is_empty() {
+            laps.push(Lap {
+                id: Uuid::new_v4(),
+                meta: LapMeta {
+                    id: Uuid::new_v4(),
+                    game: text(rec, game_col, "motec"),
+                    car: text(rec, car_col, &vehicle),
+                    track: text(rec, track_col, &venue),
+                    lap_number: lap_num,
+                    out_lap: false,
+                    in_lap: false,
+                },
+                total_time_ms: 0,
+                points: Vec::new(),
+                markers: Vec::new(),
+                index: Default::default(),
+            });
+        }
+        let Some(lap) = laps.last_mut() else { continue };
+        lap.points.push(TelemetryPoint {
+            t_ms: t_s * 1000.0,
+            lap_distance_m: get(r, dist).unwrap_or(f64::NAN),
+            x: get(r, px).unwrap_or(0.0),
+            y: get(r, py).unwrap_or(0.0),
+            speed_kph: get(r, speed).unwrap_or(0.0) * speed_scale,
+            throttle: get(r, throttle).unwrap_or(0.0) * t_scale,
+            brake: get(r, brake).unwrap_or(0.0) * b_scale,
+            gear: get(r, gear).unwrap_or(0.0) as i8,
+            rpm: get(r, rpm).unwrap_or(0.0),
+            yaw: get(r, heading).unwrap_or(0.0) * heading_scale,
+        });
+    }
+
+    laps.retain(|l| !l.points.is_empty());
+    for lap in &mut laps {
+        let pts = &mut lap.points;
+        if pts[0].lap_distance_m.is_nan() {
+            // no distance channel: integrate position
+            let mut cum = 0.0;
+            pts[0].lap_distance_m = 0.0;
+            for i in 1..pts.len() {
+                cum += (pts[i].x - pts[i - 1].x).hypot(pts[i].y - pts[i - 1].y);
+                pts[i].lap_distance_m = cum;
+            }
+        } else if cumulative {
+            let d0 = pts[0].lap_distance_m;
+            pts.iter_mut().for_each(|p| p.lap_distance_m -= d0);
+        }
+        // log time to time into the lap
+        let t0 = pts[0].t_ms;
+        pts.iter_mut().for_each(|p| p.t_ms -= t0);
+        lap.total_time_ms = pts[pts.len() - 1].t_ms.max(0.0).round() as u64;
+    }
+    Ok(laps)
+}
+
+#[derive(Default)]
+struct Buf(Vec<u8>);
+
+impl Buf {
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+    fn i16(&mut self, v: i16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+    fn pad(&mut self, n: usize) {
+        self.0.resize(self.0.len() + n, 0);
+    }
+    /// Fixed-width, zero-padded (and truncated) ASCII field.
+    fn str(&mut self, s: &str, width: usize) {
+        let bytes = s.as_bytes();
+        let n = bytes.len().min(width);
+        self.0.extend_from_slice(&bytes[..n]);
+        self.pad(width - n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, TempPath};
+
+    fn u32_at(b: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(b[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn ld_header_points_at_channels_and_samples() {
+        let laps = vec![lap(1, 20), lap(2, 20)];
+        let path = TempPath::new("ld");
+        let opts = MotecOptions { channels: Some(vec!["speed_kph".into()]), ..MotecOptions::default() };
+        export_motec_ld(&laps, &path, &opts).unwrap();
+        let _ldx = TempPath(path.with_extension("ldx"));
+        let b = fs::read(&*path).unwrap();
+
+        let (meta_ptr, data_ptr) = (u32_at(&b, 8), u32_at(&b, 12));
+        assert_eq!(meta_ptr, HEADER_LEN + EVENT_LEN + VENUE_LEN + VEHICLE_LEN);
+        // speed plus the lap number, which is always written
+        assert_eq!(data_ptr, meta_ptr + 2 * CHAN_LEN);
+
+        let chan = meta_ptr as usize;
+        assert_eq!(&b[chan + 32..chan + 44], b"Ground Speed");
+        let n = u32_at(&b, chan + 12) as usize;
+        assert_eq!(u32_at(&b, chan + 8), data_ptr);
+        assert_eq!(b.len(), data_ptr as usize + 2 * n * 4);
+        let first = f32::from_le_bytes(b[data_ptr as usize..data_ptr as usize + 4].try_into().unwrap());
+        assert_eq!(first, laps[0].points[0].speed_kph as f32);
+    }
+
+    #[test]
+    fn ldx_fastest_lap_counts_written_laps_only() {
+        let mut slow = lap(2, 20);
+        slow.total_time_ms += 1000;
+        // lap 1 has no points, so the log holds `slow` then `fast`
+        let laps = vec![lap(1, 0), slow, lap(3, 20)];
+        let path = TempPath::new("ld");
+        export_motec_ld(&laps, &path, &MotecOptions::default()).unwrap();
+        let ldx = TempPath(path.with_extension("ldx"));
+        let x = fs::read_to_string(&*ldx).unwrap();
+
+        assert!(x.contains("<String Id=\"Total Laps\" Value=\"2\"/>"), "{x}");
+        assert!(x.contains("<String Id=\"Fastest Lap\" Value=\"2\"/>"), "{x}");
+        assert_eq!(x.matches("ClassName=\"BCN\"").count(), 1);
+    }
+
+    #[test]
+    fn csv_round_trip_keeps_laps_and_metadata() {
+        let mut second = lap(2, 30);
+        second.meta.car = "other car".into();
+        let laps = vec![lap(1, 30), second];
+        let path = TempPath::new("csv");
+        export_motec_csv(&laps, &path, &MotecOptions::default()).unwrap();
+
+        let text = fs::read_to_string(&*path).unwrap();
+        assert!(text.contains("\"Time\",\"Ground Speed\""));
+        assert!(text.contains("\"Track\",\"Car\",\"Game\""));
+
+        let back = import_motec_csv(&path).unwrap();
+        assert_eq!(back.len(), 2);
+        for (a, b) in laps.iter().zip(&back) {
+            assert_eq!(b.meta.lap_number, a.meta.lap_number);
+            assert_eq!(b.meta.track, a.meta.track);
+            assert_eq!(b.meta.car, a.meta.car);
+            assert_eq!(b.meta.game, a.meta.game);
+            assert!((b.points[0].speed_kph - a.points[0].speed_kph).abs() < 1e-3);
+            // 50 Hz, so every fifth sample lands on one of the 10 Hz points
+            assert!((b.points[5].lap_distance_m - a.points[1].lap_distance_m).abs() < 1e-3);
+        }
+    }
+
+    fn import(text: &str) -> Result<Vec<Lap>> {
+        let path = TempPath::new("csv");
+        fs::write(&*path, text).unwrap();
+        import_motec_csv(&path)
+    }
+
+    /// What AC's MoTeC plugin log looks like once exported from i2: the
+    /// game's own channel names, y-up coordinates, no lap-number channel,
+    /// laps only in the `Beacon Markers` header.
+    const AC_EXPORT: &str = "\
+\"Format\",\"MoTeC CSV File\"
+\"Venue\",\"ks_vallelunga\"
+\"Vehicle\",\"ks_mazda_mx5_cup\"
+\"Sample Rate\",\"2\",\"Hz\"
+\"Beacon Markers\",\"1.0\"
+
+\"Time\",\"SPEED\",\"THROTTLE\",\"BRAKE\",\"GEAR\",\"RPMS\",\"CAR_COORD_X\",\"CAR_COORD_Y\",\"CAR_COORD_Z\",\"CAR_YAW\"
+\"s\",\"m/s\",\"%\",\"%\",\"\",\"rpm\",\"m\",\"m\",\"m\",\"deg\"
+\"0.0\",\"20\",\"100\",\"0\",\"3\",\"6000\",\"0\",\"5\",\"0\",\"0\"
+\"0.5\",\"21\",\"100\",\"0\",\"3\",\"6100\",\"0\",\"5\",\"10\",\"0\"
+\"1.0\",\"22\",\"0\",\"80\",\"3\",\"6200\",\"0\",\"5\",\"20\",\"0\"
+\"1.5\",\"15\",\"0\",\"0\",\"2\",\"5000\",\"6\",\"5\",\"28\",\"90\"
+\"2.0\",\"16\",\"50\",\"0\",\"2\",\"5200\",\"12\",\"5\",\"36\",\"90\"
+";
+
+    #[test]
+    fn imports_an_assetto_corsa_export() {
+        let laps = import(AC_EXPORT).unwrap();
+        // the samples before the first beacon are the out-lap, lap 0
+        assert_eq!(laps.iter().map(|l| l.meta.lap_number).collect::<Vec<_>>(), [0, 1]);
+        let lap = &laps[1];
+        assert_eq!((lap.meta.track.as_str(), lap.meta.car.as_str()), ("ks_vallelunga", "ks_mazda_mx5_cup"));
+        assert_eq!(lap.meta.game, "motec");
+        assert_eq!(lap.total_time_ms, 1000);
+        assert_eq!(lap.points.iter().map(|p| p.t_ms).collect::<Vec<_>>(), [0.0, 500.0, 1000.0]);
+
+        let p = &lap.points[0];
+        assert!((p.speed_kph - 79.2).abs() < 1e-9);
+        assert_eq!((p.throttle, p.brake, p.gear, p.rpm), (0.0, 0.8, 3, 6200.0));
+        // ground plane is x/z; no distance channel, so it's integrated from there
+        assert_eq!((lap.points[1].x, lap.points[1].y), (6.0, 28.0));
+        assert_eq!(lap.points.iter().map(|p| p.lap_distance_m).collect::<Vec<_>>(), [0.0, 10.0, 20.0]);
+        assert!((lap.points[1].yaw - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+    }
+
+    /// ACC exports with a beacon channel and a distance that runs on across laps.
+    #[test]
+    fn splits_acc_laps_on_the_beacon_channel() {
+        let text = "\
+\"Venue\",\"monza\"
+\"Time\",\"Speed\",\"Throttle\",\"Brake\",\"Distance\",\"LAP_BEACON\"
+\"s\",\"km/h\",\"\",\"\",\"m\",\"\"
+\"10.0\",\"100\",\"1.0\",\"0\",\"500\",\"0\"
+\"10.5\",\"100\",\"1.0\",\"0\",\"514\",\"1\"
+\"11.0\",\"100\",\"0.5\",\"0.2\",\"528\",\"1\"
+\"11.5\",\"100\",\"1.0\",\"0\",\"542\",\"2\"
+";
+        let laps = import(text).unwrap();
+        assert_eq!(laps.iter().map(|l| l.meta.lap_number).collect::<Vec<_>>(), [0, 1, 2]);
+        let lap = &laps[1];
+        assert_eq!(lap.points.iter().map(|p| p.lap_distance_m).collect::<Vec<_>>(), [0.0, 14.0]);
+        assert_eq!(lap.total_time_ms, 500);
+        // pedals already 0..1
+        assert_eq!((lap.points[1].throttle, lap.points[1].brake), (0.5, 0.2));
+    }
+
+    #[test]
+    fn needs_a_channel_header() {
+        assert!(import("\"Venue\",\"monza\"\n\"1.0\",\"2.0\"\n").is_err());
+    }
+}
@@ -0,0 +1,80 @@
+//! Time-based CSV for video overlay tools (RaceRender, DashWare).
+//!
+//! Written in the TrackAddict layout both tools ship a data profile for: a
+//! `# RaceRender Data` preamble, unit-suffixed headers (`Speed (MPH)`,
+//! `Throttle Position (%)`) and one row per sample with the time in seconds
+//! running on across laps, so the whole file syncs against one video. Local
+//! coordinates are placed on the globe through a [`GeoOrigin`], as for GPX.
+//! [`import_trackaddict`](crate::laptimer::import_trackaddict) reads it back.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use model::*;
+
+use crate::geo::GeoOrigin;
+use crate::SpeedUnit;
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct OverlayOptions {
+    #[serde(default)]
+    pub speed_unit: SpeedUnit,
+    #[serde(default)]
+    pub origin: Option<GeoOrigin>,
+}
+
+pub fn export_overlay_csv(laps: &[Lap], path: &Path, opts: &OverlayOptions) -> Result<()> {
+    let o = opts.origin.unwrap_or_default();
+    let mut w = csv::WriterBuilder::new().flexible(true).from_path(path)?;
+
+    w.write_record(["# RaceRender Data: Delta"])?;
+    if let Some(l) = laps.first() {
+        w.write_record([format!("# Track: {}", l.meta.track)])?;
+        w.write_record([format!("# Vehicle: {}", l.meta.car)])?;
+    }
+    let (speed_col, to_unit) = match opts.speed_unit {
+        SpeedUnit::Kph => ("Speed (KPH)", 1.0),
+        SpeedUnit::Mph => ("Speed (MPH)", 1.0 / 1.609_344),
+    };
+    w.write_record([
+        "Time",
+        "Lap",
+        "Lap Time",
+        "Latitude",
+        "Longitude",
+        speed_col,
+        "Heading",
+        "Distance (m)",
+        "Throttle Position (%)",
+        "Brake Position (%)",
+        "Gear",
+        "Engine Speed (RPM)",
+    ])?;
+
+    let mut offset_ms = 0.0;
+    for lap in laps {
+        let t0 = lap.points.first().map(|p| p.t_ms).unwrap_or(0.0);
+        for p in &lap.points {
+            let (lat, lon) = o.to_lat_lon(p.x, p.y);
+            let heading = (p.yaw.to_degrees() + o.bearing_deg).rem_euclid(360.0);
+            w.write_record([
+                format!("{:.3}", (offset_ms + p.t_ms - t0) / 1000.0),
+                lap.meta.lap_number.to_string(),
+                format!("{:.3}", (p.t_ms - t0) / 1000.0),
+                format!("{lat:.8}"),
+                format!("{lon:.8}"),
+                format!("{:.2}", p.speed_kph * to_unit),
+                format!("{heading:.1}"),
+                format!("{:.2}", p.lap_distance_m),
+                format!("{:.1}", p.throttle * 100.0),
+                format!("{:.1}", p.brake * 100.0),
+                p.gear.to_string(),
+                format!("{:.0}", p.rpm),
+            ])?;
+        }
+        offset_ms += lap.total_time_ms as f64;
+    }
+    w.flush()?;
+    Ok(())
+}
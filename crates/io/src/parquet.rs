@@ -0,0 +1,73 @@
+//! Parquet import/export, one row per telemetry point (see [`crate::columnar`]).
+
+use anyhow::Result;
+use std::{fs::File, path::Path};
+
+use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter};
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use model::*;
+
+use crate::columnar::{batch_to_laps, laps_to_batch_with, schema_for, POINT_COLUMNS};
+
+pub fn export_parquet(laps: &[Lap], path: &Path) -> Result<()> {
+    export_parquet_with(laps, path, POINT_COLUMNS)
+}
+
+/// Parquet with only the listed point columns (see [`POINT_COLUMNS`]).
+pub fn export_parquet_with(laps: &[Lap], path: &Path, columns: &[&str]) -> Result<()> {
+    let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let schema = schema_for(columns);
+    let mut w = ArrowWriter::try_new(File::create(path)?, schema, Some(props))?;
+    // one batch per lap keeps memory bounded for long sessions
+    for lap in laps {
+        w.write(&laps_to_batch_with(std::slice::from_ref(lap), columns)?)?;
+    }
+    w.close()?;
+    Ok(())
+}
+
+pub fn import_parquet(path: &Path) -> Result<Vec<Lap>> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+    let mut laps = Vec::new();
+    for batch in reader {
+        batch_to_laps(&batch?, &mut laps)?;
+    }
+    Ok(laps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, TempPath};
+
+    /// Laps as the columnar layout keeps them: no markers or out/in-lap flags.
+    fn laps() -> Vec<Lap> {
+        let mut laps = vec![lap(2, 40), lap(3, 25)];
+        for l in &mut laps {
+            l.markers.clear();
+        }
+        laps
+    }
+
+    #[test]
+    fn round_trip_keeps_every_point() {
+        let laps = laps();
+        let path = TempPath::new("parquet");
+        export_parquet(&laps, &path).unwrap();
+        assert_eq!(import_parquet(&path).unwrap(), laps);
+    }
+
+    #[test]
+    fn columns_left_out_read_back_as_zero() {
+        let laps = laps();
+        let path = TempPath::new("parquet");
+        export_parquet_with(&laps, &path, &["speed_kph"]).unwrap();
+        let back = import_parquet(&path).unwrap();
+        assert_eq!(back.len(), 2);
+        let (a, b) = (&laps[1].points[7], &back[1].points[7]);
+        assert_eq!((b.t_ms, b.speed_kph), (a.t_ms, a.speed_kph));
+        assert_eq!((b.x, b.rpm, b.gear), (0.0, 0.0, 0));
+    }
+}
@@ -0,0 +1,220 @@
+//! RaceChrono CSV (format 3) import, for phone/GPS track-day sessions.
+//!
+//! The export starts with `key,value` session lines, then a display-name
+//! header (`Time (s),...`), a channel-id header (`timestamp,...`), units and
+//! sources, then data. Channels can appear several times (one per source);
+//! the first column with data wins. Real cars rarely log pedals, so without
+//! throttle/brake channels they are estimated from longitudinal acceleration.
+
+use anyhow::{anyhow, bail, Result};
+use std::path::Path;
+use uuid::Uuid;
+
+use model::*;
+
+use crate::geo::GeoOrigin;
+
+/// Longitudinal g treated as full brake / full throttle in the estimate.
+pub(crate) const FULL_BRAKE_G: f64 = 1.0;
+pub(crate) const FULL_THROTTLE_G: f64 = 0.4;
+
+pub fn import_racechrono(path: &Path, origin: Option<&GeoOrigin>) -> Result<Vec<Lap>> {
+    let mut rdr = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_path(path)?;
+    let records: Vec<csv::StringRecord> = rdr.records().collect::<Result<_, _>>()?;
+
+    let mut track = String::new();
+    let mut header_at = None;
+    for (i, r) in records.iter().enumerate() {
+        match r.get(0).map(str::trim) {
+            Some("Format") if r.get(1).map(str::trim) != Some("3") => {
+                bail!("unsupported RaceChrono CSV format {:?}", r.get(1).unwrap_or(""))
+            }
+            Some("Track name") => track = r.get(1).unwrap_or("").trim().to_string(),
+            Some("Time (s)") | Some("timestamp") => {
+                header_at = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let header_at = header_at.ok_or_else(|| anyhow!("no RaceChrono channel header found"))?;
+    let display = &records[header_at];
+    let ids = records.get(header_at + 1).filter(|r| r.get(0) == Some("timestamp"));
+    let data: Vec<Vec<Option<f64>>> = records[header_at + 1..]
+        .iter()
+        .filter(|r| r.get(0).and_then(|v| v.trim().parse::<f64>().ok()).is_some())
+        .map(|r| r.iter().map(|v| v.trim().parse::<f64>().ok()).collect())
+        .collect();
+
+    // first column matching any of `names` (channel id or display name) that has data
+    let find = |names: &[&str]| -> Option<usize> {
+        let matches = |i: usize| {
+            names.iter().any(|n| {
+                ids.and_then(|r| r.get(i)).map(str::trim) == Some(*n)
+                    || display.get(i).map(str::trim) == Some(*n)
+            })
+        };
+        let cols: Vec<usize> = (0..display.len()).filter(|&i| matches(i)).collect();
+        cols.iter()
+            .copied()
+            .find(|&i| data.iter().any(|r| r.get(i).copied().flatten().is_some()))
+            .or(cols.first().copied())
+    };
+    let time = find(&["timestamp", "Time (s)"]).ok_or_else(|| anyhow!("no timestamp column"))?;
+    let lap_col = find(&["lap_number", "Lap #"]);
+    let dist = find(&["distance_traveled", "distance", "Distance (m)"]);
+    let speed = find(&["speed", "Speed (m/s)"]);
+    let bearing = find(&["bearing", "Bearing (deg)"]);
+    let lat = find(&["latitude", "Latitude (deg)"]);
+    let lon = find(&["longitude", "Longitude (deg)"]);
+    let long_acc = find(&["longitudinal_acc", "Longitudinal acceleration (G)", "y_acc", "Y acceleration (G)"]);
+    let throttle = find(&["throttle_pos", "accelerator_pos", "Throttle position (%)", "Accelerator pedal position (%)"]);
+    let brake = find(&["brake_pos", "Brake position (%)", "Brake pressure (%)"]);
+    let rpm = find(&["rpm", "engine_rpm", "Engine RPM (rpm)"]);
+    let gear = find(&["gear", "Gear"]);
+
+    let get = |r: &[Option<f64>], c: Option<usize>| c.and_then(|i| r.get(i).copied().flatten());
+    let origin = origin.copied().unwrap_or_else(|| {
+        data.iter()
+            .find_map(|r| Some(GeoOrigin::new(get(r, lat)?, get(r, lon)?)))
+            .unwrap_or_default()
+    });
+
+    let mut laps: Vec<Lap> = Vec::new();
+    let mut d0 = 0.0;
+    for r in &data {
+        // rows outside a timed lap (out-laps, pits) have no lap number
+        let Some(num) = (match lap_col {
+            Some(_) => get(r, lap_col).map(|v| v as u32),
+            None => Some(1),
+        }) else {
+            continue;
+        };
+        let Some(t_s) = get(r, Some(time)) else { continue };
+        if laps.last().map(|l| l.meta.lap_number) != Some(num) {
+            laps.push(new_lap(&track, num));
+            d0 = get(r, dist).unwrap_or(0.0);
+        }
+        let (x, y) = match (get(r, lat), get(r, lon)) {
+            (Some(la), Some(lo)) => origin.to_local(la, lo),
+            _ => (0.0, 0.0),
+        };
+        let acc = get(r, long_acc);
+        let Some(lap) = laps.last_mut() else { continue };
+        lap.points.push(TelemetryPoint {
+            t_ms: t_s * 1000.0,
+            lap_distance_m: get(r, dist).map(|d| d - d0).unwrap_or(0.0),
+            x,
+            y,
+            speed_kph: get(r, speed).unwrap_or(0.0) * 3.6,
+            throttle: get(r, throttle)
+                .map(|v| v / 100.0)
+                .or(acc.map(|g| (g / FULL_THROTTLE_G).clamp(0.0, 1.0)))
+                .unwrap_or(0.0),
+            brake: get(r, brake)
+                .map(|v| v / 100.0)
+                .or(acc.map(|g| (-g / FULL_BRAKE_G).clamp(0.0, 1.0)))
+                .unwrap_or(0.0),
+            gear: get(r, gear).unwrap_or(0.0) as i8,
+            rpm: get(r, rpm).unwrap_or(0.0),
+            yaw: get(r, bearing).map(|b| (b - origin.bearing_deg).to_radians()).unwrap_or(0.0),
+        });
+    }
+
+    // fill in distance from positions when the file has none
+    if dist.is_none() {
+        for lap in &mut laps {
+            let mut cum = 0.0;
+            for i in 1..lap.points.len() {
+                let (a, b) = (&lap.points[i - 1], &lap.points[i]);
+                cum += (b.x - a.x).hypot(b.y - a.y);
+                lap.points[i].lap_distance_m = cum;
+            }
+        }
+    }
+    for lap in &mut laps {
+        if let (Some(a), Some(b)) = (lap.points.first(), lap.points.last()) {
+            lap.total_time_ms = (b.t_ms - a.t_ms).max(0.0).round() as u64;
+        }
+    }
+    Ok(laps)
+}
+
+fn new_lap(track: &str, lap_number: u32) -> Lap {
+    Lap {
+        id: Uuid::new_v4(),
+        meta: LapMeta {
+            id: Uuid::new_v4(),
+            game: "racechrono".into(),
+            car: "Unknown".into(),
+            track: track.into(),
+            lap_number,
+            out_lap: false,
+            in_lap: false,
+        },
+        total_time_ms: 0,
+        points: Vec::new(),
+        markers: Vec::new(),
+        index: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    /// A cut-down RaceChrono v8 export: an out-lap row, then laps 1 and 2.
+    /// Speed is logged twice; the GPS column is empty, so the OBD one is used.
+    const SESSION: &str = "\
+This file is created using RaceChrono v8.1.0 ( http://www.racechrono.com/ ).
+Format,3
+Session title,\"Track day\"
+Track name,Brands Hatch Indy
+Created,01/06/2024,10:00
+
+Time (s),Lap #,Distance (m),Speed (m/s),Latitude (deg),Longitude (deg),Bearing (deg),Longitudinal acceleration (G),Speed (m/s)
+timestamp,lap_number,distance_traveled,speed,latitude,longitude,bearing,longitudinal_acc,speed
+unix time,,m,m/s,deg,deg,deg,G,m/s
+,,,gps,gps,gps,gps,calc,obd
+1700000000.0,,0,,51.3560,0.2610,0,0.0,10
+1700000001.0,1,10,,51.3561,0.2610,0,0.3,20
+1700000002.0,1,30,,51.3562,0.2610,0,-0.5,25
+1700000003.0,1,55,,51.3563,0.2610,0,0.0,30
+1700000004.0,2,80,,51.3564,0.2610,90,0.0,30
+1700000005.0,2,110,,51.3565,0.2610,90,0.0,30
+";
+
+    fn import(text: &str) -> Result<Vec<Lap>> {
+        let path = TempPath::new("csv");
+        std::fs::write(&*path, text).unwrap();
+        import_racechrono(&path, None)
+    }
+
+    #[test]
+    fn decodes_a_session_export() {
+        let laps = import(SESSION).unwrap();
+        assert_eq!(laps.iter().map(|l| l.meta.lap_number).collect::<Vec<_>>(), [1, 2]);
+        let lap = &laps[0];
+        assert_eq!(lap.meta.track, "Brands Hatch Indy");
+        assert_eq!(lap.total_time_ms, 2000);
+        assert_eq!(lap.points.iter().map(|p| p.lap_distance_m).collect::<Vec<_>>(), [0.0, 20.0, 45.0]);
+
+        let (p0, p1) = (&lap.points[0], &lap.points[1]);
+        assert_eq!(p0.speed_kph, 72.0);
+        // no pedal channels: estimated from longitudinal g
+        assert!((p0.throttle - 0.75).abs() < 1e-9 && p0.brake == 0.0);
+        assert!(p1.throttle == 0.0 && (p1.brake - 0.5).abs() < 1e-9);
+        // 0.0001° of latitude north of the first fix
+        assert!(p0.x.abs() < 1e-6);
+        assert!((p0.y - 11.12).abs() < 0.01, "{}", p0.y);
+        assert!((laps[1].points[0].yaw - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn refuses_other_formats() {
+        let err = import(&SESSION.replace("Format,3", "Format,2")).unwrap_err();
+        assert!(err.to_string().contains("format"), "{err}");
+        assert!(import("Format,3\n1,2,3\n").is_err());
+    }
+}
@@ -0,0 +1,104 @@
+//! JSON metadata written next to exports that can't hold it themselves.
+//!
+//! CSV and MoTeC files carry samples but lose lap ids, exact lap times and
+//! most of the session context. Exporting `lap.csv` also writes
+//! `lap.csv.meta.json` ([`Sidecar`]); [`import_laps`](crate::import::import_laps)
+//! picks it up and restores ids, metadata and lap times of the laps it reads.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+use model::*;
+
+use crate::gpx::iso8601;
+
+pub const SUFFIX: &str = ".meta.json";
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Sidecar {
+    /// UTC time of export, ISO 8601.
+    pub exported: String,
+    #[serde(default)]
+    pub driver: String,
+    /// Free text: weather, temperatures, tyres, setup notes.
+    #[serde(default)]
+    pub conditions: String,
+    /// Laps in the order they appear in the data file.
+    pub laps: Vec<SidecarLap>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SidecarLap {
+    #[serde(with = "uuid::serde::simple")]
+    pub id: Uuid,
+    pub meta: LapMeta,
+    pub total_time_ms: u64,
+}
+
+impl Sidecar {
+    pub fn new(laps: &[Lap], driver: &str, conditions: &str) -> Self {
+        Self {
+            exported: iso8601(time::OffsetDateTime::now_utc()),
+            driver: driver.into(),
+            conditions: conditions.into(),
+            laps: laps
+                .iter()
+                .map(|l| SidecarLap { id: l.id, meta: l.meta.clone(), total_time_ms: l.total_time_ms })
+                .collect(),
+        }
+    }
+
+    /// Restore ids, metadata and lap times of `laps` read back from the data
+    /// file: position by position when the lap counts agree, otherwise by
+    /// lap number.
+    pub fn apply(&self, laps: &mut [Lap]) {
+        let by_position = self.laps.len() == laps.len();
+        let mut used = vec![false; self.laps.len()];
+        for (i, lap) in laps.iter_mut().enumerate() {
+            let at = if by_position {
+                Some(i)
+            } else {
+                (0..self.laps.len()).find(|&j| !used[j] && self.laps[j].meta.lap_number == lap.meta.lap_number)
+            };
+            if let Some(j) = at {
+                used[j] = true;
+                let s = &self.laps[j];
+                lap.id = s.id;
+                lap.meta = s.meta.clone();
+                lap.total_time_ms = s.total_time_ms;
+            }
+        }
+    }
+}
+
+/// Path of the sidecar for the data file at `path`.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name: OsString = path.file_name().unwrap_or_default().to_owned();
+    name.push(SUFFIX);
+    path.with_file_name(name)
+}
+
+pub fn write_sidecar(path: &Path, sidecar: &Sidecar) -> Result<()> {
+    let mut w = BufWriter::new(File::create(sidecar_path(path))?);
+    serde_json::to_writer_pretty(&mut w, sidecar)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// The sidecar of `path`, if there is one.
+pub fn read_sidecar(path: &Path) -> Result<Option<Sidecar>> {
+    let p = sidecar_path(path);
+    if !p.exists() {
+        return Ok(None);
+    }
+    let r = BufReader::new(File::open(&p)?);
+    let s = serde_json::from_reader(r).with_context(|| format!("reading {}", p.display()))?;
+    Ok(Some(s))
+}
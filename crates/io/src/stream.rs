@@ -0,0 +1,197 @@
+//! Lap-at-a-time import for files too big to load whole.
+//!
+//! [`stream_csv`], [`stream_ndjson`] and [`stream_binary`] return iterators
+//! that hold at most one lap in memory and report how far through the file
+//! they are.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use model::*;
+
+use crate::{new_lap, CsvRow};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Progress {
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+    pub laps: usize,
+}
+
+impl Progress {
+    /// 0..=1; 1 for empty files.
+    pub fn fraction(&self) -> f64 {
+        if self.total_bytes == 0 {
+            1.0
+        } else {
+            (self.bytes_read as f64 / self.total_bytes as f64).min(1.0)
+        }
+    }
+}
+
+/// File reader that counts bytes as they are consumed.
+struct Counting<R> {
+    inner: R,
+    read: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+fn open_counting(path: &Path) -> Result<(Counting<File>, Arc<AtomicU64>, u64)> {
+    let f = File::open(path)?;
+    let total = f.metadata()?.len();
+    let read = Arc::new(AtomicU64::new(0));
+    Ok((Counting { inner: f, read: read.clone() }, read, total))
+}
+
+/// Laps from a CSV in the [`crate::export_csv`] layout, one at a time.
+pub struct CsvLaps {
+    rows: csv::DeserializeRecordsIntoIter<Counting<File>, CsvRow>,
+    current: Option<Lap>,
+    read: Arc<AtomicU64>,
+    total: u64,
+    laps: usize,
+}
+
+pub fn stream_csv(path: &Path) -> Result<CsvLaps> {
+    let (rdr, read, total) = open_counting(path)?;
+    Ok(CsvLaps {
+        rows: csv::Reader::from_reader(rdr).into_deserialize(),
+        current: None,
+        read,
+        total,
+        laps: 0,
+    })
+}
+
+impl CsvLaps {
+    pub fn progress(&self) -> Progress {
+        Progress { bytes_read: self.read.load(Ordering::Relaxed), total_bytes: self.total, laps: self.laps }
+    }
+}
+
+impl Iterator for CsvLaps {
+    type Item = Result<Lap>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let r: CsvRow = match self.rows.next() {
+                Some(Ok(r)) => r,
+                Some(Err(e)) => return Some(Err(e.into())),
+                None => {
+                    let last = self.current.take();
+                    self.laps += last.is_some() as usize;
+                    return last.map(Ok);
+                }
+            };
+
+            // a new lap starts when lap_number changes
+            let mut finished = None;
+            if self.current.as_ref().map(|l| l.meta.lap_number) != Some(r.lap_number) {
+                finished = self.current.replace(new_lap(&r));
+            }
+            if let Some(l) = &mut self.current {
+                l.points.push(TelemetryPoint {
+                    t_ms: r.t_ms,
+                    lap_distance_m: r.lap_distance_m,
+                    x: r.x,
+                    y: r.y,
+                    speed_kph: r.speed_kph,
+                    throttle: r.throttle,
+                    brake: r.brake,
+                    gear: r.gear,
+                    rpm: r.rpm,
+                    yaw: 0.0,
+                });
+                l.total_time_ms = r.t_ms as u64;
+            }
+            if let Some(l) = finished {
+                self.laps += 1;
+                return Some(Ok(l));
+            }
+        }
+    }
+}
+
+/// Laps from an NDJSON file (one lap per line), one at a time.
+pub struct NdjsonLaps {
+    lines: std::io::Lines<BufReader<Counting<File>>>,
+    read: Arc<AtomicU64>,
+    total: u64,
+    laps: usize,
+}
+
+pub fn stream_ndjson(path: &Path) -> Result<NdjsonLaps> {
+    let (rdr, read, total) = open_counting(path)?;
+    Ok(NdjsonLaps { lines: BufReader::new(rdr).lines(), read, total, laps: 0 })
+}
+
+impl NdjsonLaps {
+    pub fn progress(&self) -> Progress {
+        Progress { bytes_read: self.read.load(Ordering::Relaxed), total_bytes: self.total, laps: self.laps }
+    }
+}
+
+impl Iterator for NdjsonLaps {
+    type Item = Result<Lap>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(l) => l,
+                Err(e) => return Some(Err(e.into())),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let lap = serde_json::from_str(&line).map_err(Into::into);
+            // only laps actually read count towards progress
+            self.laps += lap.is_ok() as usize;
+            return Some(lap);
+        }
+    }
+}
+
+/// Laps from a [binary](crate::binary) file, one at a time.
+pub struct BinaryLaps {
+    reader: crate::binary::BinaryReader<Counting<File>>,
+    read: Arc<AtomicU64>,
+    total: u64,
+    laps: usize,
+}
+
+pub fn stream_binary(path: &Path) -> Result<BinaryLaps> {
+    let (rdr, read, total) = open_counting(path)?;
+    Ok(BinaryLaps { reader: crate::binary::BinaryReader::new(rdr)?, read, total, laps: 0 })
+}
+
+impl BinaryLaps {
+    pub fn progress(&self) -> Progress {
+        Progress { bytes_read: self.read.load(Ordering::Relaxed), total_bytes: self.total, laps: self.laps }
+    }
+}
+
+impl Iterator for BinaryLaps {
+    type Item = Result<Lap>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lap = self.reader.read_lap().transpose()?;
+        self.laps += lap.is_ok() as usize;
+        Some(lap)
+    }
+}
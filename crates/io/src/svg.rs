@@ -0,0 +1,149 @@
+//! Standalone SVG images of track maps for setup sheets and race reports.
+//!
+//! The racing line is drawn per sector in alternating colours, or, given the
+//! lap the map was built from, as a speed heatmap (blue slow, red fast).
+//! Corners get numbered labels and the start/finish line a marker. North is
+//! up; the map is scaled to fit the image with a margin.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write as _, path::Path};
+
+use model::*;
+
+use crate::geojson::path_distances;
+
+const SECTOR_COLOURS: &[&str] = &["#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#42d4f4"];
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SvgOptions {
+    pub width: f64,
+    pub height: f64,
+    /// Margin around the map, in pixels.
+    pub margin: f64,
+    pub stroke_width: f64,
+    /// Colour the line by speed instead of by sector (needs the lap).
+    #[serde(default)]
+    pub heatmap: bool,
+    #[serde(default = "default_background")]
+    pub background: String,
+}
+
+fn default_background() -> String {
+    "#ffffff".into()
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self { width: 800.0, height: 600.0, margin: 40.0, stroke_width: 4.0, heatmap: false, background: default_background() }
+    }
+}
+
+/// Render `map` to SVG. `lap` is the lap it was built from, used for the
+/// speed heatmap; ignored unless `opts.heatmap` is set. Its points must be in
+/// the map's frame, so pass maps that haven't been normalized.
+pub fn track_map_svg(map: &TrackMap, lap: Option<&Lap>, opts: &SvgOptions) -> String {
+    let b = &map.bbox;
+    let (bw, bh) = ((b.maxx - b.minx).max(1e-9), (b.maxy - b.miny).max(1e-9));
+    let s = ((opts.width - 2.0 * opts.margin) / bw).min((opts.height - 2.0 * opts.margin) / bh).max(0.0);
+    // centre the map and flip y so north is up
+    let (ox, oy) = ((opts.width - bw * s) / 2.0, (opts.height - bh * s) / 2.0);
+    let px = |x: f64, y: f64| (ox + (x - b.minx) * s, oy + (b.maxy - y) * s);
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = opts.width,
+        h = opts.height
+    );
+    let _ = writeln!(svg, r#"  <rect width="100%" height="100%" fill="{}"/>"#, esc(&opts.background));
+    let line = |svg: &mut String, pts: &[(f64, f64)], colour: &str| {
+        if pts.len() < 2 {
+            return;
+        }
+        let d: Vec<String> = pts.iter().map(|&(x, y)| format!("{x:.1},{y:.1}")).collect();
+        let _ = writeln!(
+            svg,
+            r#"  <polyline points="{}" fill="none" stroke="{colour}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+            d.join(" "),
+            opts.stroke_width
+        );
+    };
+
+    match lap.filter(|l| opts.heatmap && l.points.len() > 1) {
+        Some(lap) => {
+            let (lo, hi) = lap
+                .points
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| (lo.min(p.speed_kph), hi.max(p.speed_kph)));
+            for w in lap.points.windows(2) {
+                let f = if hi > lo { ((w[0].speed_kph + w[1].speed_kph) / 2.0 - lo) / (hi - lo) } else { 0.5 };
+                line(&mut svg, &[px(w[0].x, w[0].y), px(w[1].x, w[1].y)], &heat(f));
+            }
+        }
+        None => {
+            let pts: Vec<(f64, f64)> = map.polyline.iter().map(|p| px(p.x, p.y)).collect();
+            if map.sectors.is_empty() {
+                line(&mut svg, &pts, SECTOR_COLOURS[0]);
+            } else {
+                let dist = path_distances(&map.polyline);
+                for (i, sec) in map.sectors.iter().enumerate() {
+                    // include the next point so sectors join up
+                    let idx: Vec<usize> = (0..pts.len()).filter(|&k| dist[k] >= sec.start_m && dist[k] <= sec.end_m).collect();
+                    if let (Some(&a), Some(&z)) = (idx.first(), idx.last()) {
+                        let z = (z + 1).min(pts.len() - 1);
+                        line(&mut svg, &pts[a..=z], SECTOR_COLOURS[i % SECTOR_COLOURS.len()]);
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(p) = map.polyline.first() {
+        let (x, y) = px(p.x, p.y);
+        let _ = writeln!(svg, r##"  <circle cx="{x:.1}" cy="{y:.1}" r="6" fill="#000000"/>"##);
+        let _ = writeln!(
+            svg,
+            r##"  <text x="{:.1}" y="{:.1}" font-family="sans-serif" font-size="12" fill="#000000">S/F</text>"##,
+            x + 9.0,
+            y - 9.0
+        );
+    }
+    for c in &map.corners {
+        let (x, y) = px(c.x, c.y);
+        let _ = writeln!(
+            svg,
+            r##"  <circle cx="{x:.1}" cy="{y:.1}" r="9" fill="#ffffff" stroke="#333333" stroke-width="1.5"/>"##
+        );
+        let _ = writeln!(
+            svg,
+            r##"  <text x="{x:.1}" y="{y:.1}" font-family="sans-serif" font-size="10" text-anchor="middle" dominant-baseline="central" fill="#333333">{}</text>"##,
+            c.index
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub fn export_svg(map: &TrackMap, lap: Option<&Lap>, path: &Path, opts: &SvgOptions) -> Result<()> {
+    std::fs::write(path, track_map_svg(map, lap, opts))?;
+    Ok(())
+}
+
+/// Blue → green → red for `f` in 0..=1.
+fn heat(f: f64) -> String {
+    let f = f.clamp(0.0, 1.0);
+    let (r, g, b) = if f < 0.5 {
+        let t = f * 2.0;
+        (0.0, t, 1.0 - t)
+    } else {
+        let t = (f - 0.5) * 2.0;
+        (t, 1.0 - t, 0.0)
+    };
+    format!("#{:02x}{:02x}{:02x}", (r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
+
+fn esc(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('"', "&quot;")
+}
@@ -0,0 +1,75 @@
+//! Laps and scratch files shared by the tests.
+
+use std::path::PathBuf;
+
+use model::*;
+use uuid::Uuid;
+
+/// A lap of `n` points 10 m and 100 ms apart, with values awkward enough
+/// that a lossy round trip shows: fractions that don't print short, a
+/// gear change and a marker.
+pub fn lap(lap_number: u32, n: usize) -> Lap {
+    let points: Vec<TelemetryPoint> = (0..n)
+        .map(|i| {
+            let f = i as f64;
+            TelemetryPoint {
+                t_ms: f * 100.0 + 1.0 / 3.0,
+                lap_distance_m: f * 10.0,
+                x: 100.0 + f * 7.123_456_789,
+                y: -50.0 - f * 0.1,
+                speed_kph: 150.0 + f / 7.0,
+                throttle: (f / n as f64).min(1.0),
+                brake: 1.0 - (f / n as f64).min(1.0),
+                gear: 3 + (i * 2 / n.max(1)) as i8,
+                rpm: 9000.0 + f,
+                yaw: 0.01 * f,
+            }
+        })
+        .collect();
+    Lap {
+        id: Uuid::new_v4(),
+        meta: LapMeta {
+            id: Uuid::new_v4(),
+            game: "F1_2024".into(),
+            car: "player:0".into(),
+            track: "Monza".into(),
+            lap_number,
+            out_lap: lap_number == 1,
+            in_lap: false,
+        },
+        total_time_ms: points.last().map_or(0, |p| p.t_ms as u64 + 7),
+        markers: vec![LapMarker {
+            kind: MarkerKind::Lockup,
+            t_ms: 250.5,
+            lap_distance_m: 25.25,
+            x: 1.5,
+            y: -2.5,
+            severity: 1.125,
+        }],
+        points,
+        index: Default::default(),
+    }
+}
+
+/// A path in the temp directory no other test uses; removed on drop.
+pub struct TempPath(pub PathBuf);
+
+impl TempPath {
+    pub fn new(ext: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("delta-io-test-{}.{ext}", Uuid::new_v4().simple())))
+    }
+}
+
+impl std::ops::Deref for TempPath {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,287 @@
+//! Racelogic VBOX `.vbo` files (Circuit Tools, VBOX Test Suite).
+//!
+//! Positions are written as VBO latitude/longitude (arc-minutes, longitude
+//! positive west) through a [`GeoOrigin`]. Without one the local frame is
+//! pinned at 0°N 0°E, so the track still draws to scale; the raw local
+//! coordinates also go out as `posx`/`posy` so our own import is exact.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+use uuid::Uuid;
+
+use model::*;
+
+use crate::geo::{haversine_m, GeoOrigin};
+use crate::motec::log_date_time;
+
+/// (column name, `[header]` description, unit)
+const COLUMNS: &[(&str, &str, &str)] = &[
+    ("sats", "satellites", ""),
+    ("time", "time", ""),
+    ("lat", "latitude", ""),
+    ("long", "longitude", ""),
+    ("velocity", "velocity kmh", "kmh"),
+    ("heading", "heading", "deg"),
+    ("height", "height", "m"),
+    ("lap", "lap number", ""),
+    ("lapdist", "lap distance", "m"),
+    ("throttle", "throttle", "%"),
+    ("brake", "brake", "%"),
+    ("gear", "gear", ""),
+    ("rpm", "engine rpm", "rpm"),
+    ("posx", "local x", "m"),
+    ("posy", "local y", "m"),
+];
+
+pub fn export_vbo(laps: &[Lap], path: &Path, origin: Option<&GeoOrigin>) -> Result<()> {
+    let origin = origin.copied().unwrap_or_default();
+    let mut w = BufWriter::new(File::create(path)?);
+    let (date, time) = log_date_time();
+
+    writeln!(w, "File created on {date} @ {time}")?;
+    writeln!(w)?;
+    writeln!(w, "[header]")?;
+    for (_, desc, _) in COLUMNS {
+        writeln!(w, "{desc}")?;
+    }
+    writeln!(w)?;
+    writeln!(w, "[channel units]")?;
+    for (_, _, unit) in COLUMNS {
+        writeln!(w, "{unit}")?;
+    }
+    writeln!(w)?;
+    writeln!(w, "[comments]")?;
+    if let Some(l) = laps.first() {
+        writeln!(w, "Exported by Delta: {} / {} / {}", l.meta.game, l.meta.car, l.meta.track)?;
+    }
+    writeln!(w, "Origin {:.7} {:.7} bearing {:.2}", origin.lat_deg, origin.lon_deg, origin.bearing_deg)?;
+    writeln!(w)?;
+    writeln!(w, "[column names]")?;
+    writeln!(w, "{}", COLUMNS.iter().map(|c| c.0).collect::<Vec<_>>().join(" "))?;
+    writeln!(w)?;
+    writeln!(w, "[data]")?;
+
+    // laps follow each other on one clock
+    let mut offset_s = 0.0;
+    for lap in laps {
+        let t0 = lap.points.first().map(|p| p.t_ms).unwrap_or(0.0);
+        for p in &lap.points {
+            let (lat, lon) = origin.to_lat_lon(p.x, p.y);
+            let heading = (origin.bearing_deg + p.yaw.to_degrees()).rem_euclid(360.0);
+            writeln!(
+                w,
+                "{:03} {} {:+012.5} {:+012.5} {:07.3} {:06.2} {:+09.2} {} {:.2} {:.1} {:.1} {} {:.0} {:.3} {:.3}",
+                8,
+                vbo_time(offset_s + (p.t_ms - t0) / 1000.0),
+                lat * 60.0,
+                -lon * 60.0,
+                p.speed_kph.max(0.0),
+                heading,
+                0.0,
+                lap.meta.lap_number,
+                p.lap_distance_m,
+                p.throttle * 100.0,
+                p.brake * 100.0,
+                p.gear,
+                p.rpm,
+                p.x,
+                p.y,
+            )?;
+        }
+        offset_s += lap.total_time_ms as f64 / 1000.0;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Read a `.vbo` file. Positions come from `posx`/`posy` when present,
+/// otherwise from lat/long relative to `origin` (or the first fix). Files with
+/// a lap column are split on it; anything else becomes a single lap.
+pub fn import_vbo(path: &Path, origin: Option<&GeoOrigin>) -> Result<Vec<Lap>> {
+    let rdr = BufReader::new(File::open(path)?);
+    let mut section = String::new();
+    let mut names: Vec<String> = Vec::new();
+    let mut rows: Vec<Vec<f64>> = Vec::new();
+
+    for (n, line) in rdr.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.starts_with('[') && line.ends_with(']') {
+            section = line.to_ascii_lowercase();
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        match section.as_str() {
+            "[column names]" => names = line.split_whitespace().map(|s| s.to_ascii_lowercase()).collect(),
+            "[data]" => {
+                let row = line
+                    .split_whitespace()
+                    .map(|v| v.parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .with_context(|| format!("line {}: bad number", n + 1))?;
+                rows.push(row);
+            }
+            _ => {}
+        }
+    }
+    if names.is_empty() {
+        bail!("no [column names] section");
+    }
+    let idx: HashMap<&str, usize> = names.iter().enumerate().map(|(i, s)| (s.as_str(), i)).collect();
+    let col = |names: &[&str]| names.iter().find_map(|n| idx.get(n).copied());
+    let time = col(&["time"]).ok_or_else(|| anyhow!("no time column"))?;
+    let (lat, long) = (col(&["lat", "latitude"]), col(&["long", "longitude"]));
+    let (posx, posy) = (col(&["posx"]), col(&["posy"]));
+    let speed = col(&["velocity", "speed"]);
+    let heading = col(&["heading"]);
+    let lap_col = col(&["lap", "lapnumber", "lap_number"]);
+    let dist = col(&["lapdist"]);
+    let throttle = col(&["throttle"]);
+    let brake = col(&["brake"]);
+    let gear = col(&["gear"]);
+    let rpm = col(&["rpm", "engine_rpm"]);
+
+    let get = |r: &[f64], c: Option<usize>| c.and_then(|i| r.get(i).copied());
+    let latlon = |r: &[f64]| match (get(r, lat), get(r, long)) {
+        (Some(la), Some(lo)) => Some((la / 60.0, -lo / 60.0)),
+        _ => None,
+    };
+    let origin = origin.copied().unwrap_or_else(|| {
+        rows.iter()
+            .find_map(|r| latlon(r))
+            .map(|(la, lo)| GeoOrigin::new(la, lo))
+            .unwrap_or_default()
+    });
+
+    let mut laps: Vec<Lap> = Vec::new();
+    let mut prev_t = 0.0;
+    let mut day_s = 0.0;
+    let mut prev_fix: Option<(f64, f64)> = None;
+    let mut cum = 0.0;
+    for r in &rows {
+        // a truncated or hand-edited row without a time can't be placed
+        let Some(&time) = r.get(time) else { continue };
+        // time of day wraps at midnight
+        let mut t = parse_vbo_time(time) + day_s;
+        if t + 43_200.0 < prev_t {
+            day_s += 86_400.0;
+            t += 86_400.0;
+        }
+        prev_t = t;
+
+        let num = get(r, lap_col).map(|v| v as u32).unwrap_or(1);
+        if laps.last().map(|l| l.meta.lap_number) != Some(num) {
+            laps.push(new_lap(path, num));
+            cum = 0.0;
+        }
+        let fix = latlon(r);
+        if let (Some(a), Some(b)) = (prev_fix, fix) {
+            cum += haversine_m(a, b);
+        }
+        prev_fix = fix.or(prev_fix);
+
+        let (x, y) = match (get(r, posx), get(r, posy), fix) {
+            (Some(x), Some(y), _) => (x, y),
+            (_, _, Some((la, lo))) => origin.to_local(la, lo),
+            _ => (0.0, 0.0),
+        };
+        let Some(lap) = laps.last_mut() else { continue };
+        lap.points.push(TelemetryPoint {
+            t_ms: t * 1000.0,
+            lap_distance_m: get(r, dist).unwrap_or(cum),
+            x,
+            y,
+            speed_kph: get(r, speed).unwrap_or(0.0),
+            throttle: get(r, throttle).map(|v| v / 100.0).unwrap_or(0.0),
+            brake: get(r, brake).map(|v| v / 100.0).unwrap_or(0.0),
+            gear: get(r, gear).unwrap_or(0.0) as i8,
+            rpm: get(r, rpm).unwrap_or(0.0),
+            yaw: get(r, heading).map(|h| (h - origin.bearing_deg).to_radians()).unwrap_or(0.0),
+        });
+    }
+    for lap in &mut laps {
+        if let (Some(a), Some(b)) = (lap.points.first(), lap.points.last()) {
+            lap.total_time_ms = (b.t_ms - a.t_ms).max(0.0).round() as u64;
+        }
+    }
+    Ok(laps)
+}
+
+fn new_lap(path: &Path, lap_number: u32) -> Lap {
+    let track = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    Lap {
+        id: Uuid::new_v4(),
+        meta: LapMeta { id: Uuid::new_v4(), game: "vbox".into(), car: "Unknown".into(), track, lap_number, out_lap: false, in_lap: false },
+        total_time_ms: 0,
+        points: Vec::new(),
+        markers: Vec::new(),
+        index: Default::default(),
+    }
+}
+
+/// Seconds of day as VBO `HHMMSS.SS`.
+fn vbo_time(s: f64) -> String {
+    let cs = (s.max(0.0) * 100.0).round() as u64 % (86_400 * 100);
+    let (h, m, sec) = (cs / 360_000, (cs / 6000) % 60, (cs % 6000) as f64 / 100.0);
+    format!("{h:02}{m:02}{sec:05.2}")
+}
+
+fn parse_vbo_time(v: f64) -> f64 {
+    let h = (v / 10_000.0).floor();
+    let m = ((v - h * 10_000.0) / 100.0).floor();
+    h * 3600.0 + m * 60.0 + (v - h * 10_000.0 - m * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{lap, TempPath};
+
+    #[test]
+    fn export_reads_back() {
+        let laps = vec![lap(1, 30), lap(2, 40)];
+        let path = TempPath::new("vbo");
+        export_vbo(&laps, &path, Some(&GeoOrigin::new(45.62, 9.28))).unwrap();
+        let back = import_vbo(&path, None).unwrap();
+        assert_eq!(back.len(), 2);
+        for (a, b) in laps.iter().zip(&back) {
+            assert_eq!(a.meta.lap_number, b.meta.lap_number);
+            assert_eq!(a.points.len(), b.points.len());
+            for (p, q) in a.points.iter().zip(&b.points) {
+                // posx/posy to the millimetre, times to the centisecond
+                assert!((p.x - q.x).abs() < 1e-3 && (p.y - q.y).abs() < 1e-3);
+                assert!((p.speed_kph - q.speed_kph).abs() < 1e-3);
+                assert!((p.rpm - q.rpm).abs() <= 0.5);
+                assert_eq!(p.gear, q.gear);
+            }
+            let t0 = (a.points[0].t_ms, b.points[0].t_ms);
+            let (p, q) = (a.points.last().unwrap(), b.points.last().unwrap());
+            assert!(((p.t_ms - t0.0) - (q.t_ms - t0.1)).abs() <= 10.0);
+        }
+    }
+
+    #[test]
+    fn short_rows_are_skipped() {
+        let path = TempPath::new("vbo");
+        std::fs::write(
+            &*path,
+            "[column names]\nsats time lat long velocity\n\n[data]\n\
+             008 120000.00 +02737.20000 -00556.80000 100.000\n\
+             008\n\
+             008 120000.10 +02737.20010 -00556.80010 101.000\n",
+        )
+        .unwrap();
+        let laps = import_vbo(&path, None).unwrap();
+        assert_eq!(laps.len(), 1);
+        let speeds: Vec<f64> = laps[0].points.iter().map(|p| p.speed_kph).collect();
+        assert_eq!(speeds, [100.0, 101.0]);
+        assert_eq!(laps[0].total_time_ms, 100);
+    }
+}
@@ -0,0 +1,174 @@
+//! Workspace files (`.deltaworkspace`): an analysis setup together with the
+//! laps it looks at, for sharing between teammates.
+//!
+//! A zip like a [session bundle](crate::bundle), with entries:
+//! - `workspace.json` — [`WorkspaceManifest`]: name, layout, comparison sets
+//!   and lap ids
+//! - `laps.dlap` — the referenced laps in the [binary](crate::binary) format
+//!
+//! The layout is whatever JSON the frontend saves; it is carried as is, apart
+//! from lap ids rewritten by [`WorkspaceFile::remap_laps`].
+//!
+//! [`open_workspace`] reads only the manifest and the laps' metadata, and
+//! leaves the laps in the file until they're needed.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, Write},
+    path::Path,
+};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use model::*;
+
+use crate::binary::{BinaryReader, BinaryWriter, MappedLaps};
+use crate::bundle::json_entry;
+use crate::gpx::iso8601;
+
+pub const EXTENSION: &str = "deltaworkspace";
+pub const VERSION: u32 = 1;
+
+const MANIFEST: &str = "workspace.json";
+const LAPS: &str = "laps.dlap";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct WorkspaceManifest {
+    pub version: u32,
+    /// UTC time the file was written, ISO 8601.
+    pub created: String,
+    pub name: String,
+    pub layout: Value,
+    /// Laps the layout and comparison sets refer to, all of them in `laps.dlap`.
+    pub lap_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub comparison_sets: Vec<ComparisonSet>,
+}
+
+/// A workspace file opened in place by [`open_workspace`].
+pub struct MappedWorkspace {
+    pub manifest: WorkspaceManifest,
+    pub laps: MappedLaps,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkspaceFile {
+    pub manifest: WorkspaceManifest,
+    pub laps: Vec<Lap>,
+}
+
+impl WorkspaceFile {
+    pub fn new(name: &str, layout: Value, comparison_sets: Vec<ComparisonSet>, laps: Vec<Lap>) -> Self {
+        let manifest = WorkspaceManifest {
+            version: VERSION,
+            created: iso8601(time::OffsetDateTime::now_utc()),
+            name: name.into(),
+            layout,
+            lap_ids: laps.iter().map(|l| l.id).collect(),
+            comparison_sets,
+        };
+        Self { manifest, laps }
+    }
+
+    /// Point the workspace at other lap ids, for laps the importing machine
+    /// already has under a different id. Ids inside the layout are found as
+    /// any string holding a mapped id, and rewritten in the same format.
+    pub fn remap_laps(&mut self, map: &HashMap<Uuid, Uuid>) {
+        for id in &mut self.manifest.lap_ids {
+            if let Some(to) = map.get(id) {
+                *id = *to;
+            }
+        }
+        for set in &mut self.manifest.comparison_sets {
+            for id in set.lap_ids.iter_mut().chain(&mut set.reference_id) {
+                if let Some(to) = map.get(id) {
+                    *id = *to;
+                }
+            }
+        }
+        for l in &mut self.laps {
+            if let Some(to) = map.get(&l.id) {
+                l.id = *to;
+            }
+        }
+        remap_value(&mut self.manifest.layout, map);
+    }
+}
+
+fn remap_value(v: &mut Value, map: &HashMap<Uuid, Uuid>) {
+    match v {
+        Value::String(s) => {
+            if let Some(to) = Uuid::parse_str(s).ok().and_then(|id| map.get(&id)) {
+                *s = if s.contains('-') { to.hyphenated().to_string() } else { to.simple().to_string() };
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|i| remap_value(i, map)),
+        Value::Object(fields) => fields.values_mut().for_each(|f| remap_value(f, map)),
+        _ => {}
+    }
+}
+
+pub fn write_workspace<W: Write + Seek>(ws: &WorkspaceFile, inner: W) -> Result<W> {
+    let mut zip = ZipWriter::new(inner);
+    zip.start_file(MANIFEST, SimpleFileOptions::default().compression_method(CompressionMethod::Deflated))?;
+    serde_json::to_writer_pretty(&mut zip, &ws.manifest)?;
+
+    // lap frames are zstd already
+    zip.start_file(LAPS, SimpleFileOptions::default().compression_method(CompressionMethod::Stored))?;
+    let mut w = BinaryWriter::new(&mut zip)?;
+    for l in &ws.laps {
+        w.write_lap(l)?;
+    }
+    w.finish()?;
+    Ok(zip.finish()?)
+}
+
+pub fn read_workspace<R: Read + Seek>(inner: R) -> Result<WorkspaceFile> {
+    let mut zip = ZipArchive::new(inner).context("not a workspace file")?;
+    let manifest: WorkspaceManifest =
+        json_entry(&mut zip, MANIFEST)?.ok_or_else(|| anyhow!("workspace file has no {MANIFEST}"))?;
+    if manifest.version > VERSION {
+        bail!("workspace file version {} is newer than supported {VERSION}", manifest.version);
+    }
+    let laps = {
+        let entry = zip.by_name(LAPS).map_err(|_| anyhow!("workspace file has no {LAPS}"))?;
+        BinaryReader::new(entry)?.collect::<Result<Vec<_>>>().context(LAPS)?
+    };
+    Ok(WorkspaceFile { manifest, laps })
+}
+
+/// The manifest of the workspace file at `path`, with its laps mapped from
+/// the file rather than read.
+pub fn open_workspace(path: &Path) -> Result<MappedWorkspace> {
+    let mut zip = ZipArchive::new(BufReader::new(File::open(path)?)).context("not a workspace file")?;
+    let manifest: WorkspaceManifest =
+        json_entry(&mut zip, MANIFEST)?.ok_or_else(|| anyhow!("workspace file has no {MANIFEST}"))?;
+    if manifest.version > VERSION {
+        bail!("workspace file version {} is newer than supported {VERSION}", manifest.version);
+    }
+    let mut entry = zip.by_name(LAPS).map_err(|_| anyhow!("workspace file has no {LAPS}"))?;
+    // written stored, but other tools may have compressed it
+    let laps = if entry.compression() == CompressionMethod::Stored {
+        let start = entry.data_start() as usize;
+        MappedLaps::open_range(path, start..start + entry.size() as usize)
+    } else {
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut bytes)?;
+        MappedLaps::from_vec(bytes)
+    }
+    .context(LAPS)?;
+    Ok(MappedWorkspace { manifest, laps })
+}
+
+pub fn export_workspace(ws: &WorkspaceFile, path: &Path) -> Result<()> {
+    write_workspace(ws, BufWriter::new(File::create(path)?))?.flush()?;
+    Ok(())
+}
+
+pub fn import_workspace(path: &Path) -> Result<WorkspaceFile> {
+    read_workspace(BufReader::new(File::open(path)?))
+}
@@ -0,0 +1,60 @@
+//! Content hashes of laps, for telling the same lap apart from a copy that
+//! has been through an export and re-import under new ids.
+
+use crate::{Lap, LapMeta};
+
+impl Lap {
+    /// Content hash independent of the lap's ids: see [`content_hash`].
+    pub fn content_hash(&self) -> u64 {
+        content_hash(&self.meta, self.samples_hash())
+    }
+
+    /// Hash of every sample: time relative to the first, position, speed,
+    /// pedals, gear and rpm. Lap time and yaw are left out since the CSV
+    /// layout does not carry them.
+    pub fn samples_hash(&self) -> u64 {
+        let mut h = Fnv::default();
+        let t0 = self.points.first().map(|p| p.t_ms).unwrap_or(0.0);
+        // compared at f32 precision, which absorbs the last-bit differences of
+        // decimal text round trips without rounding at decimal boundaries
+        let q = |v: f64| (v as f32).to_bits().to_le_bytes();
+        for p in &self.points {
+            for v in [p.t_ms - t0, p.x, p.y, p.speed_kph, p.throttle, p.brake, p.rpm] {
+                h.write(&q(v));
+            }
+            h.write(&[p.gear as u8]);
+        }
+        h.0
+    }
+}
+
+/// Content hash of a lap from its game, car and track and its
+/// [`Lap::samples_hash`], so it can be recomputed from stored metadata
+/// without the points.
+pub fn content_hash(meta: &LapMeta, samples_hash: u64) -> u64 {
+    let mut h = Fnv::default();
+    for s in [&meta.game, &meta.car, &meta.track] {
+        h.write(s.as_bytes());
+        h.write(&[0]);
+    }
+    h.write(&samples_hash.to_le_bytes());
+    h.0
+}
+
+/// 64-bit FNV-1a; stable across builds, unlike `DefaultHasher`.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
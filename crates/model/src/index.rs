@@ -0,0 +1,246 @@
+//! Distance lookups over a lap's points.
+
+use std::{fmt, ops::Range, sync::OnceLock};
+
+use crate::{Lap, TelemetryPoint};
+
+/// A lap's points in increasing distance with the time since the first point
+/// at each, for O(log n) lookups by distance. Points that don't advance
+/// distance are left out so the trace stays monotonic.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LapIndex {
+    /// Strictly increasing (m).
+    pub distance: Vec<f64>,
+    /// Time since the lap's first point at each distance (ms).
+    pub elapsed_ms: Vec<f64>,
+    /// Position of each indexed point in the lap's `points`.
+    pub point: Vec<usize>,
+}
+
+impl LapIndex {
+    pub fn new(points: &[TelemetryPoint]) -> Self {
+        let t0 = points.first().map(|p| p.t_ms).unwrap_or(0.0);
+        let mut index = Self::default();
+        for (i, p) in points.iter().enumerate() {
+            if index.distance.last().is_some_and(|&d| p.lap_distance_m <= d) {
+                continue;
+            }
+            index.distance.push(p.lap_distance_m);
+            index.elapsed_ms.push(p.t_ms - t0);
+            index.point.push(i);
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.distance.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.distance.is_empty()
+    }
+
+    /// First and last indexed distance.
+    pub fn range(&self) -> Option<(f64, f64)> {
+        Some((*self.distance.first()?, *self.distance.last()?))
+    }
+
+    /// The indexed points either side of `distance_m` and how far between
+    /// them it lies (0..=1); the first or last one alone outside the range.
+    fn locate(&self, distance_m: f64) -> Option<(usize, usize, f64)> {
+        let last = self.len().checked_sub(1)?;
+        let k = self.distance.partition_point(|&d| d < distance_m);
+        Some(match k {
+            0 => (0, 0, 0.0),
+            k if k > last => (last, last, 0.0),
+            k => {
+                let (d0, d1) = (self.distance[k - 1], self.distance[k]);
+                (k - 1, k, ((distance_m - d0) / (d1 - d0)).clamp(0.0, 1.0))
+            }
+        })
+    }
+
+    /// Positions in the lap's `points` of the two points straddling
+    /// `distance_m`, and the blend factor between them. Held at the first or
+    /// last point outside the recorded range; `None` without points.
+    pub fn bracket(&self, distance_m: f64) -> Option<(usize, usize, f64)> {
+        self.locate(distance_m).map(|(i, j, f)| (self.point[i], self.point[j], f))
+    }
+
+    /// Time since the lap's first point at `distance_m` (ms), interpolated.
+    /// Held at the first or last point outside the recorded range.
+    pub fn time_at_distance(&self, distance_m: f64) -> Option<f64> {
+        let (i, j, f) = self.locate(distance_m)?;
+        Some(self.elapsed_ms[i] + f * (self.elapsed_ms[j] - self.elapsed_ms[i]))
+    }
+
+    /// Positions in the lap's `points` of the stretch from `from_m` to `to_m`.
+    pub fn span(&self, from_m: f64, to_m: f64, points: usize) -> Range<usize> {
+        let start = self.distance.partition_point(|&d| d < from_m);
+        let end = self.distance.partition_point(|&d| d <= to_m);
+        if start >= end {
+            return 0..0;
+        }
+        // up to the next indexed point, so the ones that didn't advance
+        // distance come along
+        self.point[start]..self.point.get(end).copied().unwrap_or(points)
+    }
+}
+
+/// A lap's [`LapIndex`], built on first use. Clones start empty, so editing
+/// the points of a cloned lap can't leave it stale, and it never affects
+/// whether two laps are equal.
+#[derive(Default)]
+pub struct LapIndexCache(OnceLock<LapIndex>);
+
+impl Clone for LapIndexCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for LapIndexCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for LapIndexCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.0.get().is_some() { "LapIndexCache(built)" } else { "LapIndexCache(empty)" })
+    }
+}
+
+impl Lap {
+    /// Distance index of `points`, built on the first lookup. Call
+    /// [`Lap::reindex`] after editing the points of a lap in place.
+    pub fn index(&self) -> &LapIndex {
+        self.index.0.get_or_init(|| LapIndex::new(&self.points))
+    }
+
+    /// Drop the cached index, e.g. after editing `points`.
+    pub fn reindex(&mut self) {
+        self.index = LapIndexCache::default();
+    }
+
+    /// The lap at `distance_m`, interpolated between the points either side:
+    /// gear from the nearer one, heading the short way round. Held at the
+    /// first or last point outside the recorded range; `None` without points.
+    pub fn sample_at_distance(&self, distance_m: f64) -> Option<TelemetryPoint> {
+        let (i, j, f) = self.index().bracket(distance_m)?;
+        let (a, b) = (&self.points[i], &self.points[j]);
+        let lerp = |u: f64, v: f64| u + f * (v - u);
+        Some(TelemetryPoint {
+            t_ms: lerp(a.t_ms, b.t_ms),
+            lap_distance_m: lerp(a.lap_distance_m, b.lap_distance_m),
+            x: lerp(a.x, b.x),
+            y: lerp(a.y, b.y),
+            speed_kph: lerp(a.speed_kph, b.speed_kph),
+            throttle: lerp(a.throttle, b.throttle),
+            brake: lerp(a.brake, b.brake),
+            gear: if f < 0.5 { a.gear } else { b.gear },
+            rpm: lerp(a.rpm, b.rpm),
+            yaw: a.yaw + f * wrap_angle(b.yaw - a.yaw),
+        })
+    }
+
+    /// Time since the lap's first point at `distance_m` (ms); see
+    /// [`LapIndex::time_at_distance`].
+    pub fn time_at_distance(&self, distance_m: f64) -> Option<f64> {
+        self.index().time_at_distance(distance_m)
+    }
+
+    /// The points from `from_m` to `to_m`, in recorded order.
+    pub fn points_between(&self, from_m: f64, to_m: f64) -> &[TelemetryPoint] {
+        &self.points[self.index().span(from_m, to_m, self.points.len())]
+    }
+}
+
+/// `a` wrapped into `[-π, π)`, e.g. the signed difference of two headings.
+pub fn wrap_angle(a: f64) -> f64 {
+    (a + std::f64::consts::PI).rem_euclid(std::f64::consts::TAU) - std::f64::consts::PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(t_ms: f64, d: f64) -> TelemetryPoint {
+        TelemetryPoint {
+            t_ms,
+            lap_distance_m: d,
+            x: d,
+            y: 0.0,
+            speed_kph: 0.0,
+            throttle: 0.0,
+            brake: 0.0,
+            gear: 0,
+            rpm: 0.0,
+            yaw: 0.0,
+        }
+    }
+
+    /// Points at 100 m, 200 m, 200 m (stationary), 300 m, 1 s apart from
+    /// t = 5 s.
+    fn index() -> LapIndex {
+        LapIndex::new(&[point(5_000.0, 100.0), point(6_000.0, 200.0), point(7_000.0, 200.0), point(8_000.0, 300.0)])
+    }
+
+    #[test]
+    fn points_that_dont_advance_are_left_out() {
+        let index = index();
+        assert_eq!(index.distance, [100.0, 200.0, 300.0]);
+        assert_eq!(index.elapsed_ms, [0.0, 1_000.0, 3_000.0]);
+        assert_eq!(index.point, [0, 1, 3]);
+        assert_eq!(index.range(), Some((100.0, 300.0)));
+    }
+
+    #[test]
+    fn lookups_hold_at_the_ends() {
+        let index = index();
+        assert_eq!(index.time_at_distance(0.0), Some(0.0));
+        assert_eq!(index.bracket(0.0), Some((0, 0, 0.0)));
+        assert_eq!(index.time_at_distance(1_000.0), Some(3_000.0));
+        assert_eq!(index.bracket(1_000.0), Some((3, 3, 0.0)));
+    }
+
+    #[test]
+    fn lookups_interpolate_inside() {
+        let index = index();
+        assert_eq!(index.time_at_distance(150.0), Some(500.0));
+        assert_eq!(index.bracket(150.0), Some((0, 1, 0.5)));
+        // the stationary point is skipped, so 250 m is between 200 m and 300 m
+        assert_eq!(index.time_at_distance(250.0), Some(2_000.0));
+        assert_eq!(index.bracket(250.0), Some((1, 3, 0.5)));
+        assert_eq!(index.time_at_distance(200.0), Some(1_000.0));
+    }
+
+    #[test]
+    fn lookups_need_points() {
+        let empty = LapIndex::new(&[]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.range(), None);
+        assert_eq!(empty.time_at_distance(10.0), None);
+        assert_eq!(empty.bracket(10.0), None);
+        assert_eq!(empty.span(0.0, 10.0, 0), 0..0);
+    }
+
+    #[test]
+    fn span_brings_the_points_that_dont_advance() {
+        let index = index();
+        assert_eq!(index.span(150.0, 250.0, 4), 1..3);
+        assert_eq!(index.span(200.0, 300.0, 4), 1..4);
+        assert_eq!(index.span(0.0, 1_000.0, 4), 0..4);
+        assert_eq!(index.span(0.0, 50.0, 4), 0..0);
+        assert_eq!(index.span(400.0, 500.0, 4), 0..0);
+        assert_eq!(index.span(250.0, 150.0, 4), 0..0);
+    }
+
+    #[test]
+    fn wrap_angle_takes_the_short_way_round() {
+        use std::f64::consts::PI;
+        assert!((wrap_angle(PI - 0.1 - (-PI + 0.1)) - (-0.2)).abs() < 1e-12);
+        assert!((wrap_angle(-3.0 * PI / 2.0) - PI / 2.0).abs() < 1e-12);
+        assert_eq!(wrap_angle(0.5), 0.5);
+    }
+}
@@ -1,6 +1,12 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+mod hash;
+mod index;
+
+pub use hash::content_hash;
+pub use index::{wrap_angle, LapIndex, LapIndexCache};
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct TelemetryPoint {
     pub t_ms: f64,
@@ -12,6 +18,9 @@ pub struct TelemetryPoint {
     pub brake: f64,
     pub gear: i8,
     pub rpm: f64,
+    /// Heading in radians; 0 when the source doesn't report orientation.
+    #[serde(default)]
+    pub yaw: f64,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
@@ -22,6 +31,19 @@ pub struct LapMeta {
     pub car: String,
     pub track: String,
     pub lap_number: u32,
+    /// Started from the pits or garage, or part way round.
+    #[serde(default)]
+    pub out_lap: bool,
+    /// Ended in the pits.
+    #[serde(default)]
+    pub in_lap: bool,
+}
+
+impl LapMeta {
+    /// Neither an out-lap nor an in-lap, so the lap time means something.
+    pub fn is_flying(&self) -> bool {
+        !self.out_lap && !self.in_lap
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
@@ -32,6 +54,31 @@ pub struct Lap {
     pub total_time_ms: u64,
     #[serde(default)]
     pub points: Vec<TelemetryPoint>,
+    #[serde(default)]
+    pub markers: Vec<LapMarker>,
+    /// Distance lookups over `points`; see [`Lap::index`].
+    #[serde(skip)]
+    pub index: LapIndexCache,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkerKind {
+    Spin,
+    Lockup,
+    Crash,
+}
+
+/// An incident found in a lap's data, positioned so the UI can jump to it.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LapMarker {
+    pub kind: MarkerKind,
+    pub t_ms: f64,
+    pub lap_distance_m: f64,
+    pub x: f64,
+    pub y: f64,
+    /// Peak value of the triggering signal (rad/s for spins, g for lockups/crashes).
+    pub severity: f64,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
@@ -53,6 +100,9 @@ pub struct Corner {
 pub struct TrackMap {
     #[serde(default)]
     pub polyline: Vec<Point2>,
+    /// Coarser outlines for drawing at small sizes, finest first.
+    #[serde(default)]
+    pub lods: Vec<TrackLod>,
     #[serde(default)]
     pub corners: Vec<CornerLabel>,
     #[serde(default)]
@@ -60,6 +110,14 @@ pub struct TrackMap {
     pub bbox: BBox,
 }
 
+/// The track outline simplified so no point of the driven line is more than
+/// `tolerance_m` away from it.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct TrackLod {
+    pub tolerance_m: f64,
+    pub polyline: Vec<Point2>,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Sector {
     pub start_m: f64,
@@ -86,3 +144,15 @@ pub struct Point2 {
     pub x: f64,
     pub y: f64,
 }
+
+/// Laps picked from any sessions or imports to be compared together.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ComparisonSet {
+    pub name: String,
+    pub lap_ids: Vec<Uuid>,
+    /// Lap the others are compared against; the fastest when `None`.
+    #[serde(default)]
+    pub reference_id: Option<Uuid>,
+    /// Unix seconds.
+    pub created_at: i64,
+}
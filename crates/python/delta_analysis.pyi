@@ -0,0 +1,26 @@
+from os import PathLike
+from typing import Any
+
+MATH_CHANNELS: list[str]
+
+class Lap:
+    id: str
+    game: str
+    car: str
+    track: str
+    lap_number: int
+    out_lap: bool
+    in_lap: bool
+    time_ms: int
+    markers: list[dict[str, Any]]
+    def points(self) -> dict[str, list[float]]: ...
+    def to_dict(self) -> dict[str, Any]: ...
+    def __len__(self) -> int: ...
+
+def load_laps(path: str | PathLike[str], format: str | None = None) -> list[Lap]: ...
+def speed_overlay(laps: list[Lap]) -> dict[str, Any]: ...
+def math_overlay(laps: list[Lap], channels: dict[str, str]) -> dict[str, Any]: ...
+def delta_series(reference: Lap, laps: list[Lap]) -> dict[str, Any]: ...
+def corner_metrics(lap: Lap) -> dict[str, Any]: ...
+def session_summary(laps: list[Lap]) -> dict[str, Any]: ...
+def session_stats(laps: list[Lap]) -> dict[str, Any]: ...
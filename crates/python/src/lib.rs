@@ -0,0 +1,244 @@
+//! Python bindings: lap loading and the analysis functions, so studies in a
+//! notebook run Delta's exact algorithms over exported sessions.
+//!
+//! ```python
+//! import delta_analysis as da
+//! import pandas as pd
+//!
+//! laps = da.load_laps("spa.dlap")
+//! best = min(laps, key=lambda l: l.time_ms)
+//! delta = da.delta_series(best, laps)
+//! df = pd.DataFrame(best.points())
+//! ```
+//!
+//! Results come back as the same dicts and lists the app's API sends as
+//! JSON; lap ids are in their simple (undashed) form throughout.
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use serde_json::Value;
+use std::{collections::BTreeMap, path::PathBuf};
+
+use analysis::math::MathChannel;
+use iox::import::{import_laps, ImportFormat, ImportOptions};
+use model::Lap;
+
+/// One lap: its metadata, telemetry points and incident markers.
+#[pyclass(name = "Lap", module = "delta_analysis", frozen)]
+#[derive(Clone)]
+struct PyLap(Lap);
+
+#[pymethods]
+impl PyLap {
+    #[getter]
+    fn id(&self) -> String {
+        self.0.id.simple().to_string()
+    }
+
+    #[getter]
+    fn game(&self) -> &str {
+        &self.0.meta.game
+    }
+
+    #[getter]
+    fn car(&self) -> &str {
+        &self.0.meta.car
+    }
+
+    #[getter]
+    fn track(&self) -> &str {
+        &self.0.meta.track
+    }
+
+    #[getter]
+    fn lap_number(&self) -> u32 {
+        self.0.meta.lap_number
+    }
+
+    /// Started from the pits or garage, or part way round.
+    #[getter]
+    fn out_lap(&self) -> bool {
+        self.0.meta.out_lap
+    }
+
+    /// Ended in the pits.
+    #[getter]
+    fn in_lap(&self) -> bool {
+        self.0.meta.in_lap
+    }
+
+    #[getter]
+    fn time_ms(&self) -> u64 {
+        self.0.total_time_ms
+    }
+
+    /// Spins, lockups and crashes found in the lap, as dicts.
+    #[getter]
+    fn markers<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_py(py, &json(&self.0.markers)?)
+    }
+
+    /// Telemetry as columns, one list per channel, ready for
+    /// `pandas.DataFrame(lap.points())`.
+    fn points<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let p = &self.0.points;
+        let columns = PyDict::new(py);
+        columns.set_item("t_ms", p.iter().map(|p| p.t_ms).collect::<Vec<_>>())?;
+        columns.set_item("lap_distance_m", p.iter().map(|p| p.lap_distance_m).collect::<Vec<_>>())?;
+        columns.set_item("x", p.iter().map(|p| p.x).collect::<Vec<_>>())?;
+        columns.set_item("y", p.iter().map(|p| p.y).collect::<Vec<_>>())?;
+        columns.set_item("speed_kph", p.iter().map(|p| p.speed_kph).collect::<Vec<_>>())?;
+        columns.set_item("throttle", p.iter().map(|p| p.throttle).collect::<Vec<_>>())?;
+        columns.set_item("brake", p.iter().map(|p| p.brake).collect::<Vec<_>>())?;
+        columns.set_item("gear", p.iter().map(|p| p.gear).collect::<Vec<_>>())?;
+        columns.set_item("rpm", p.iter().map(|p| p.rpm).collect::<Vec<_>>())?;
+        columns.set_item("yaw", p.iter().map(|p| p.yaw).collect::<Vec<_>>())?;
+        Ok(columns)
+    }
+
+    /// The whole lap as the dict NDJSON files hold.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_py(py, &json(&self.0)?)
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.points.len()
+    }
+
+    fn __repr__(&self) -> String {
+        let (m, ms) = (&self.0.meta, self.0.total_time_ms);
+        format!(
+            "Lap({} {} {} lap {}, {}:{:06.3})",
+            m.game,
+            m.track,
+            m.car,
+            m.lap_number,
+            ms / 60_000,
+            (ms % 60_000) as f64 / 1000.0
+        )
+    }
+}
+
+/// Laps of a file in any format Delta imports. `format` (e.g. `"motec_csv"`)
+/// is guessed from the file when left out.
+#[pyfunction]
+#[pyo3(signature = (path, format=None))]
+fn load_laps(py: Python<'_>, path: PathBuf, format: Option<String>) -> PyResult<Vec<PyLap>> {
+    let format: Option<ImportFormat> = format
+        .map(|f| serde_json::from_value(Value::String(f.clone())).map_err(|_| PyValueError::new_err(format!("unknown format `{f}`"))))
+        .transpose()?;
+    let laps = py
+        .allow_threads(|| import_laps(&path, format, &ImportOptions::default()))
+        .map_err(|e| PyRuntimeError::new_err(format!("{}: {e:#}", path.display())))?;
+    Ok(laps.into_iter().map(PyLap).collect())
+}
+
+/// Speed of every lap on a shared 1 m distance grid, decimated to
+/// `max_points` when given.
+#[pyfunction]
+#[pyo3(signature = (laps, max_points=None))]
+fn speed_overlay<'py>(py: Python<'py>, laps: Vec<PyLap>, max_points: Option<usize>) -> PyResult<Bound<'py, PyAny>> {
+    let laps = unwrap(laps);
+    let overlay = py.allow_threads(|| {
+        let mut overlay = analysis::speed_overlay(&laps);
+        if let Some(n) = max_points {
+            overlay.decimate(n);
+        }
+        overlay
+    });
+    to_py(py, &json(&overlay)?)
+}
+
+/// Math channels of every lap on a shared 1 m distance grid; `channels`
+/// maps names to expressions such as `{"brake_pct": "brake*100"}`.
+#[pyfunction]
+fn math_overlay<'py>(py: Python<'py>, laps: Vec<PyLap>, channels: BTreeMap<String, String>) -> PyResult<Bound<'py, PyAny>> {
+    let laps = unwrap(laps);
+    let channels: Vec<MathChannel> = channels.into_iter().map(|(name, expr)| MathChannel { name, expr }).collect();
+    let overlay = py
+        .allow_threads(|| analysis::math_overlay(&laps, &channels))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    to_py(py, &json(&overlay)?)
+}
+
+/// Mean time delta of `laps` against `reference` on a 1 m grid, decimated
+/// to `max_points` when given.
+#[pyfunction]
+#[pyo3(signature = (reference, laps, max_points=None))]
+fn delta_series<'py>(py: Python<'py>, reference: PyLap, laps: Vec<PyLap>, max_points: Option<usize>) -> PyResult<Bound<'py, PyAny>> {
+    let laps = unwrap(laps);
+    let delta = py.allow_threads(|| {
+        let mut delta = analysis::delta_series(&reference.0, &laps);
+        if let Some(n) = max_points {
+            delta.decimate(n);
+        }
+        delta
+    });
+    to_py(py, &json(&delta)?)
+}
+
+/// Corners of `lap` with their entry, apex and exit speeds, brake points and
+/// throttle-on points.
+#[pyfunction]
+fn corner_metrics<'py>(py: Python<'py>, lap: PyLap) -> PyResult<Bound<'py, PyAny>> {
+    let corners = py.allow_threads(|| analysis::corner_metrics(&lap.0));
+    to_py(py, &json(&corners)?)
+}
+
+/// Best, worst and average lap time and sector consistency of `laps`.
+#[pyfunction]
+fn session_summary<'py>(py: Python<'py>, laps: Vec<PyLap>) -> PyResult<Bound<'py, PyAny>> {
+    to_py(py, &json(&analysis::session_summary(&unwrap(laps)))?)
+}
+
+/// Lap time statistics overall and per stint, and car or track changes;
+/// `laps` in the order they were driven.
+#[pyfunction]
+fn session_stats<'py>(py: Python<'py>, laps: Vec<PyLap>) -> PyResult<Bound<'py, PyAny>> {
+    to_py(py, &json(&analysis::stats::session_stats(&unwrap(laps)))?)
+}
+
+#[pymodule]
+fn delta_analysis(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyLap>()?;
+    m.add_function(wrap_pyfunction!(load_laps, m)?)?;
+    m.add_function(wrap_pyfunction!(speed_overlay, m)?)?;
+    m.add_function(wrap_pyfunction!(math_overlay, m)?)?;
+    m.add_function(wrap_pyfunction!(delta_series, m)?)?;
+    m.add_function(wrap_pyfunction!(corner_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(session_summary, m)?)?;
+    m.add_function(wrap_pyfunction!(session_stats, m)?)?;
+    m.add("MATH_CHANNELS", analysis::math::CHANNELS.to_vec())?;
+    Ok(())
+}
+
+fn unwrap(laps: Vec<PyLap>) -> Vec<Lap> {
+    laps.into_iter().map(|l| l.0).collect()
+}
+
+fn json<T: serde::Serialize>(value: &T) -> PyResult<Value> {
+    serde_json::to_value(value).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+}
+
+/// `v` as plain Python objects: dicts, lists, numbers, strings and `None`.
+fn to_py<'py>(py: Python<'py>, v: &Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match v {
+        Value::Null => py.None().into_bound(py),
+        Value::Bool(b) => b.into_pyobject(py)?.to_owned().into_any(),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.into_pyobject(py)?.into_any(),
+            (_, Some(u)) => u.into_pyobject(py)?.into_any(),
+            _ => n.as_f64().unwrap_or(f64::NAN).into_pyobject(py)?.into_any(),
+        },
+        Value::String(s) => s.into_pyobject(py)?.into_any(),
+        Value::Array(items) => PyList::new(py, items.iter().map(|v| to_py(py, v)).collect::<PyResult<Vec<_>>>()?)?.into_any(),
+        Value::Object(map) => {
+            let d = PyDict::new(py);
+            for (k, v) in map {
+                d.set_item(k, to_py(py, v)?)?;
+            }
+            d.into_any()
+        }
+    })
+}
@@ -0,0 +1,22 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc::compile().expect("compiling proto/delta.proto");
+}
+
+#[cfg(feature = "grpc")]
+mod grpc {
+    use protobuf::Message as _;
+    use std::error::Error;
+
+    const PROTO: &str = "proto/delta.proto";
+
+    pub fn compile() -> Result<(), Box<dyn Error>> {
+        println!("cargo:rerun-if-changed={PROTO}");
+        // protobuf-parse gives rust-protobuf descriptors; prost wants its own,
+        // so go through the wire format
+        let set = protobuf_parse::Parser::new().pure().include("proto").input(PROTO).file_descriptor_set()?;
+        let fds = <prost_types::FileDescriptorSet as prost::Message>::decode(set.write_to_bytes()?.as_slice())?;
+        tonic_build::compile_fds(fds)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+//! Posts to a Discord webhook when something worth telling the team
+//! happens: a personal best, the summary of a finished session, or an
+//! incident (spin, crash) found in a lap.
+//!
+//! Posting happens on a thread of its own so ingest never waits on the
+//! network. Rate-limited posts are retried after the wait Discord asks for.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+use analysis::SessionSummary;
+use model::{LapMarker, LapMeta, MarkerKind};
+
+const ATTEMPTS: u32 = 3;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// embed colours
+const GREEN: u32 = 0x3ddc84;
+const BLUE: u32 = 0x4f8cff;
+const RED: u32 = 0xff5c5c;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct DiscordOptions {
+    /// `https://discord.com/api/webhooks/<id>/<token>`
+    pub webhook_url: String,
+    /// Name the posts appear under.
+    pub username: String,
+    /// Driver named in the posts; left out when empty.
+    pub driver: String,
+    pub personal_best: bool,
+    pub session_end: bool,
+    /// Incident kinds posted; lockups are left out by default as every
+    /// heavy stop can look like one.
+    pub incidents: Vec<MarkerKind>,
+}
+
+impl Default for DiscordOptions {
+    fn default() -> Self {
+        Self {
+            webhook_url: String::new(),
+            username: "Delta".into(),
+            driver: String::new(),
+            personal_best: true,
+            session_end: true,
+            incidents: vec![MarkerKind::Spin, MarkerKind::Crash],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Notification {
+    /// A lap faster than every earlier one with the same car and track.
+    PersonalBest { meta: LapMeta, time_ms: u64, previous_ms: u64 },
+    /// A source stopped after recording laps; `meta` is its last lap's.
+    SessionEnd { source: String, meta: LapMeta, summary: SessionSummary },
+    Incident { meta: LapMeta, marker: LapMarker },
+    /// Checks the webhook works.
+    Test,
+}
+
+/// Sends notifications to one webhook.
+pub struct DiscordNotifier {
+    opts: DiscordOptions,
+    tx: Sender<Value>,
+}
+
+impl DiscordNotifier {
+    pub fn new(opts: &DiscordOptions) -> Result<Self> {
+        if !opts.webhook_url.starts_with("https://") {
+            bail!("the webhook URL should start with https://");
+        }
+        let (tx, rx) = mpsc::channel::<Value>();
+        let url = opts.webhook_url.clone();
+        // ends when the notifier (and with it the sender) is dropped
+        thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+            for body in rx {
+                if let Err(e) = post(&agent, &url, &body) {
+                    eprintln!("discord webhook failed: {e:#}");
+                }
+            }
+        });
+        Ok(Self { opts: opts.clone(), tx })
+    }
+
+    pub fn options(&self) -> &DiscordOptions {
+        &self.opts
+    }
+
+    /// Queue `n` for posting if the options ask for its kind.
+    pub fn notify(&self, n: &Notification) {
+        let wanted = match n {
+            Notification::PersonalBest { .. } => self.opts.personal_best,
+            Notification::SessionEnd { .. } => self.opts.session_end,
+            Notification::Incident { marker, .. } => self.opts.incidents.contains(&marker.kind),
+            Notification::Test => true,
+        };
+        if wanted {
+            // only fails once the posting thread is gone, and then there's
+            // nobody to post anyway
+            let _ = self.tx.send(self.message(n));
+        }
+    }
+
+    fn message(&self, n: &Notification) -> Value {
+        let who = if self.opts.driver.is_empty() { String::new() } else { format!("{} — ", self.opts.driver) };
+        let embed = match n {
+            Notification::PersonalBest { meta, time_ms, previous_ms } => json!({
+                "title": format!("{who}new personal best"),
+                "description": format!("**{}** at {} in the {}", fmt_ms(*time_ms), meta.track, meta.car),
+                "color": GREEN,
+                "fields": [
+                    field("Previous", fmt_ms(*previous_ms)),
+                    field("Gain", format!("{:.3}s", (*previous_ms as f64 - *time_ms as f64) / 1000.0)),
+                    field("Game", meta.game.clone()),
+                ],
+            }),
+            Notification::SessionEnd { source, meta, summary } => json!({
+                "title": format!("{who}session finished"),
+                "description": format!("{} laps at {} in the {} ({source})", summary.lap_count, meta.track, meta.car),
+                "color": BLUE,
+                "fields": [
+                    field("Best", fmt_ms(summary.best_ms)),
+                    field("Average", fmt_ms(summary.avg_ms.round() as u64)),
+                    field("Worst", fmt_ms(summary.worst_ms)),
+                    field("Consistency", format!("±{:.3}s", summary.consistency)),
+                ],
+            }),
+            Notification::Incident { meta, marker } => json!({
+                "title": format!("{who}{}", match marker.kind {
+                    MarkerKind::Spin => "spin",
+                    MarkerKind::Lockup => "lockup",
+                    MarkerKind::Crash => "crash",
+                }),
+                "description": format!(
+                    "Lap {} at {}, {:.0} m into the lap",
+                    meta.lap_number, meta.track, marker.lap_distance_m
+                ),
+                "color": RED,
+                "fields": [field("Car", meta.car.clone())],
+            }),
+            Notification::Test => json!({
+                "title": "Delta is connected",
+                "description": "Notifications will show up here.",
+                "color": BLUE,
+            }),
+        };
+        json!({ "username": self.opts.username, "embeds": [embed] })
+    }
+}
+
+fn field(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "inline": true })
+}
+
+fn fmt_ms(ms: u64) -> String {
+    format!("{}:{:06.3}", ms / 60_000, (ms % 60_000) as f64 / 1000.0)
+}
+
+fn post(agent: &ureq::Agent, url: &str, body: &Value) -> Result<()> {
+    for _ in 0..ATTEMPTS {
+        match agent.post(url).send_json(body) {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(429, resp)) => {
+                // Discord says how long to back off, in seconds
+                let wait = resp
+                    .into_json::<Value>()
+                    .ok()
+                    .and_then(|v| v["retry_after"].as_f64())
+                    .unwrap_or(1.0);
+                thread::sleep(Duration::from_secs_f64(wait.clamp(0.0, 60.0)));
+            }
+            Err(ureq::Error::Status(code, resp)) => {
+                let text = resp.into_string().unwrap_or_default();
+                bail!("webhook answered {code}: {text}");
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    bail!("still rate limited after {ATTEMPTS} attempts")
+}
@@ -0,0 +1,295 @@
+//! gRPC service (`delta.v1.Telemetry`, see `proto/delta.proto`) streaming
+//! live samples and laps at full rate and serving laps and analysis as typed
+//! messages. Enabled by the `grpc` feature.
+//!
+//! Streams never hold up the app: a client that falls behind misses
+//! messages rather than queueing them.
+
+// tonic's handlers all return its (large) `Status`; boxing it here would
+// only mean unboxing it again
+#![allow(clippy::result_large_err)]
+
+use anyhow::{Context, Result};
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+use tokio::{net::TcpListener, sync::broadcast, task::JoinHandle};
+use tokio_stream::{
+    wrappers::{BroadcastStream, TcpListenerStream},
+    Stream, StreamExt,
+};
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use analysis::live::LiveDeltaState;
+use analysis::{DeltaSeries, SessionSummary};
+use delta_ingest_core::TelemetrySample;
+use model::{Lap, LapMeta};
+
+use crate::rest::{LapInfo, LapProvider};
+
+pub mod proto {
+    tonic::include_proto!("delta.v1");
+}
+
+use proto::telemetry_server::{Telemetry, TelemetryServer};
+
+pub const DEFAULT_PORT: u16 = 50051;
+
+// messages buffered per client before a slow one starts missing them
+const BUFFER: usize = 1024;
+
+/// A running gRPC server; stops when dropped.
+pub struct GrpcServer {
+    addr: SocketAddr,
+    samples: broadcast::Sender<proto::Sample>,
+    laps: broadcast::Sender<proto::LapCompleted>,
+    task: JoinHandle<()>,
+}
+
+impl GrpcServer {
+    /// Listen on `addr` (`0.0.0.0:50051` for the whole LAN). Must be called
+    /// inside a tokio runtime.
+    pub async fn bind(addr: &str, laps: Arc<dyn LapProvider>) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await.with_context(|| format!("bind {addr}"))?;
+        let addr = listener.local_addr()?;
+        let (samples, _) = broadcast::channel(BUFFER);
+        let (lap_events, _) = broadcast::channel(BUFFER);
+        let service = Service { laps, samples: samples.clone(), lap_events: lap_events.clone() };
+        let task = tokio::spawn(async move {
+            // only returns on listener errors; the server just stops then
+            let _ = tonic::transport::Server::builder()
+                .add_service(TelemetryServer::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await;
+        });
+        Ok(Self { addr, samples, laps: lap_events, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Clients streaming samples or laps.
+    pub fn clients(&self) -> usize {
+        self.samples.receiver_count() + self.laps.receiver_count()
+    }
+
+    /// Send a sample of `source` to the clients streaming samples.
+    pub fn publish_sample(&self, source: &str, s: &TelemetrySample, delta: &LiveDeltaState) {
+        if self.samples.receiver_count() > 0 {
+            let _ = self.samples.send(proto::Sample::new(source, s, delta));
+        }
+    }
+
+    /// Send a completed lap of `source` to the clients streaming laps.
+    pub fn publish_lap(&self, source: &str, lap: &Lap, sector_ms: &[f64], best_ms: Option<u64>) {
+        if self.laps.receiver_count() > 0 {
+            let _ = self.laps.send(proto::LapCompleted {
+                source: source.into(),
+                id: simple(lap.id),
+                meta: Some((&lap.meta).into()),
+                total_time_ms: lap.total_time_ms,
+                sector_ms: sector_ms.to_vec(),
+                best_ms,
+                delta_to_best_ms: best_ms.map(|b| lap.total_time_ms as i64 - b as i64),
+            });
+        }
+    }
+}
+
+impl Drop for GrpcServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Service {
+    laps: Arc<dyn LapProvider>,
+    samples: broadcast::Sender<proto::Sample>,
+    lap_events: broadcast::Sender<proto::LapCompleted>,
+}
+
+type Streamed<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// Messages of `rx` from `source` (any source when empty), skipping those
+/// missed while lagging.
+fn subscribe<T>(rx: broadcast::Receiver<T>, source: String, source_of: fn(&T) -> &str) -> Streamed<T>
+where
+    T: Clone + Send + 'static,
+{
+    Box::pin(
+        BroadcastStream::new(rx)
+            .filter_map(|m| m.ok())
+            .filter(move |m| source.is_empty() || source_of(m) == source)
+            .map(Ok),
+    )
+}
+
+fn simple(id: Uuid) -> String {
+    id.simple().to_string()
+}
+
+fn parse_id(s: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(s.trim()).map_err(|_| Status::invalid_argument(format!("bad lap id {s:?}")))
+}
+
+impl Service {
+    fn load(&self, id: &str) -> Result<Arc<Lap>, Status> {
+        let id = parse_id(id)?;
+        self.laps.lap(id).ok_or_else(|| Status::not_found(format!("lap {id} not found")))
+    }
+
+    fn load_list(&self, ids: &[String]) -> Result<Vec<Arc<Lap>>, Status> {
+        if ids.is_empty() {
+            return Err(Status::invalid_argument("no laps given"));
+        }
+        ids.iter().map(|id| self.load(id)).collect()
+    }
+}
+
+/// Run CPU-heavy analysis off the async workers.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<Response<T>, Status> {
+    tokio::task::spawn_blocking(f).await.map(Response::new).map_err(|e| Status::internal(e.to_string()))
+}
+
+#[tonic::async_trait]
+impl Telemetry for Service {
+    type StreamSamplesStream = Streamed<proto::Sample>;
+    type StreamLapsStream = Streamed<proto::LapCompleted>;
+
+    async fn stream_samples(
+        &self,
+        req: Request<proto::StreamRequest>,
+    ) -> Result<Response<Self::StreamSamplesStream>, Status> {
+        Ok(Response::new(subscribe(self.samples.subscribe(), req.into_inner().source, |s| &s.source)))
+    }
+
+    async fn stream_laps(&self, req: Request<proto::StreamRequest>) -> Result<Response<Self::StreamLapsStream>, Status> {
+        Ok(Response::new(subscribe(self.lap_events.subscribe(), req.into_inner().source, |l| &l.source)))
+    }
+
+    async fn list_laps(&self, req: Request<proto::LapFilter>) -> Result<Response<proto::LapList>, Status> {
+        let f = req.into_inner();
+        let matches = |want: &str, have: &str| want.is_empty() || want.eq_ignore_ascii_case(have);
+        let mut laps: Vec<LapInfo> = self
+            .laps
+            .laps()
+            .into_iter()
+            .filter(|l| matches(&f.game, &l.meta.game) && matches(&f.car, &l.meta.car) && matches(&f.track, &l.meta.track))
+            .collect();
+        laps.sort_by_key(|l| l.total_time_ms);
+        Ok(Response::new(proto::LapList { laps: laps.into_iter().map(Into::into).collect() }))
+    }
+
+    async fn get_lap(&self, req: Request<proto::LapId>) -> Result<Response<proto::Lap>, Status> {
+        self.load(&req.into_inner().id).map(|l| Response::new((&*l).into()))
+    }
+
+    async fn summary(&self, req: Request<proto::LapIds>) -> Result<Response<proto::SessionSummary>, Status> {
+        let laps = self.load_list(&req.into_inner().ids)?;
+        Ok(Response::new(analysis::session_summary(&laps).into()))
+    }
+
+    async fn delta(&self, req: Request<proto::DeltaRequest>) -> Result<Response<proto::DeltaSeries>, Status> {
+        let req = req.into_inner();
+        let reference = self.load(&req.reference)?;
+        let laps = self.load_list(&req.laps)?;
+        blocking(move || analysis::delta_series(&reference, &laps).into()).await
+    }
+}
+
+impl proto::Sample {
+    pub fn new(source: &str, s: &TelemetrySample, delta: &LiveDeltaState) -> Self {
+        Self {
+            source: source.into(),
+            game: format!("{:?}", s.game).to_lowercase(),
+            frame: s.frame,
+            sim_time_s: s.sim_time_s,
+            lap_number: s.current_lap,
+            lap_distance_m: s.lap_distance_m as f64,
+            lap_time_ms: delta.elapsed_ms,
+            speed_kph: (s.speed_mps * 3.6) as f64,
+            throttle: s.throttle as f64,
+            brake: s.brake as f64,
+            gear: s.gear as i32,
+            rpm: s.engine_rpm as f64,
+            x: s.world_pos_x as f64,
+            y: s.world_pos_y as f64,
+            z: s.world_pos_z as f64,
+            yaw: s.yaw as f64,
+            pitch: s.pitch as f64,
+            roll: s.roll as f64,
+            delta_ms: delta.delta_ms,
+            predicted_ms: delta.predicted_ms,
+            best_ms: delta.best_ms,
+        }
+    }
+}
+
+impl From<&LapMeta> for proto::LapMeta {
+    fn from(m: &LapMeta) -> Self {
+        Self {
+            id: simple(m.id),
+            game: m.game.clone(),
+            car: m.car.clone(),
+            track: m.track.clone(),
+            lap_number: m.lap_number,
+            out_lap: m.out_lap,
+            in_lap: m.in_lap,
+        }
+    }
+}
+
+impl From<LapInfo> for proto::LapInfo {
+    fn from(l: LapInfo) -> Self {
+        Self {
+            id: simple(l.id),
+            meta: Some((&l.meta).into()),
+            total_time_ms: l.total_time_ms,
+            session_id: l.session_id.map(simple),
+        }
+    }
+}
+
+impl From<&Lap> for proto::Lap {
+    fn from(l: &Lap) -> Self {
+        Self {
+            id: simple(l.id),
+            meta: Some((&l.meta).into()),
+            total_time_ms: l.total_time_ms,
+            points: l
+                .points
+                .iter()
+                .map(|p| proto::TelemetryPoint {
+                    t_ms: p.t_ms,
+                    lap_distance_m: p.lap_distance_m,
+                    x: p.x,
+                    y: p.y,
+                    speed_kph: p.speed_kph,
+                    throttle: p.throttle,
+                    brake: p.brake,
+                    gear: p.gear as i32,
+                    rpm: p.rpm,
+                    yaw: p.yaw,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<SessionSummary> for proto::SessionSummary {
+    fn from(s: SessionSummary) -> Self {
+        Self {
+            lap_count: s.lap_count as u64,
+            best_ms: s.best_ms,
+            worst_ms: s.worst_ms,
+            avg_ms: s.avg_ms,
+            consistency: s.consistency,
+        }
+    }
+}
+
+impl From<DeltaSeries> for proto::DeltaSeries {
+    fn from(d: DeltaSeries) -> Self {
+        Self { reference_id: simple(d.reference_id), distance: d.distance, delta_ms: d.delta_ms }
+    }
+}
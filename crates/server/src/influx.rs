@@ -0,0 +1,428 @@
+//! Samples and lap summaries written to InfluxDB in line protocol, for
+//! keeping every session in an existing Influx/Grafana stack.
+//!
+//! Writes go through the v2 write API (`/api/v2/write`), which InfluxDB 2,
+//! 3 and 1.8+ all accept. Two measurements are written, tagged by `source`,
+//! `game`, `car` and `track`:
+//! - `<prefix>_sample`: speed, pedals, gear, rpm, position, lap and delta
+//! - `<prefix>_lap`: lap time, sector times and whether it was a best
+//!
+//! Lines are batched on a thread of their own. A failed batch is retried
+//! with growing waits; while Influx is away lines queue up to a limit, then
+//! the oldest are dropped.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use analysis::live::LiveDeltaState;
+use delta_ingest_core::TelemetrySample;
+use model::Lap;
+
+// lines held while Influx can't be reached, about a minute of samples at
+// full rate from a few sources
+const MAX_PENDING: usize = 200_000;
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct InfluxOptions {
+    /// Server root, e.g. `http://localhost:8086`.
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    /// API token; left out for servers without auth.
+    pub token: Option<String>,
+    /// Measurements are `<prefix>_sample` and `<prefix>_lap`.
+    pub measurement_prefix: String,
+    /// Samples written per second and source; 0 writes every sample.
+    pub sample_hz: f64,
+    /// Lines per write request.
+    pub batch_size: usize,
+    /// Longest a line waits before its batch is written.
+    pub flush_ms: u64,
+}
+
+impl Default for InfluxOptions {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:8086".into(),
+            org: String::new(),
+            bucket: "delta".into(),
+            token: None,
+            measurement_prefix: "delta".into(),
+            sample_hz: 20.0,
+            batch_size: 5000,
+            flush_ms: 1000,
+        }
+    }
+}
+
+/// What a sample or lap is tagged with.
+#[derive(Clone, Copy, Debug)]
+pub struct Tags<'a> {
+    pub source: &'a str,
+    pub game: &'a str,
+    pub car: &'a str,
+    pub track: &'a str,
+}
+
+#[derive(Clone, Serialize, Debug, Default, PartialEq)]
+pub struct InfluxStatus {
+    pub written: u64,
+    /// Lines dropped because Influx was away too long or rejected them.
+    pub dropped: u64,
+    /// Last write error; cleared by the next successful write.
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct Shared {
+    written: AtomicU64,
+    dropped: AtomicU64,
+    error: Mutex<Option<String>>,
+}
+
+/// Writes lines to one bucket; what's queued is flushed when dropped.
+pub struct InfluxSink {
+    opts: InfluxOptions,
+    tx: SyncSender<String>,
+    shared: Arc<Shared>,
+    last_sample: HashMap<String, Instant>,
+}
+
+impl InfluxSink {
+    pub fn new(opts: &InfluxOptions) -> Result<Self> {
+        if !opts.url.starts_with("http://") && !opts.url.starts_with("https://") {
+            bail!("the InfluxDB URL should start with http:// or https://");
+        }
+        if opts.bucket.is_empty() {
+            bail!("no InfluxDB bucket given");
+        }
+        let (tx, rx) = mpsc::sync_channel(MAX_PENDING);
+        let shared = Arc::new(Shared::default());
+        let writer = Writer::new(opts, shared.clone());
+        thread::spawn(move || writer.run(rx));
+        Ok(Self { opts: opts.clone(), tx, shared, last_sample: HashMap::new() })
+    }
+
+    pub fn options(&self) -> &InfluxOptions {
+        &self.opts
+    }
+
+    pub fn status(&self) -> InfluxStatus {
+        InfluxStatus {
+            written: self.shared.written.load(Ordering::Relaxed),
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+            error: self.shared.error.lock().unwrap().clone(),
+        }
+    }
+
+    /// Queue a sample, unless one of the same source went out less than
+    /// `1 / sample_hz` ago.
+    pub fn write_sample(&mut self, tags: Tags, s: &TelemetrySample, delta: &LiveDeltaState) {
+        let now = Instant::now();
+        if self.opts.sample_hz > 0.0 {
+            let due = self
+                .last_sample
+                .get(tags.source)
+                .is_none_or(|t| now.duration_since(*t).as_secs_f64() >= 1.0 / self.opts.sample_hz);
+            if !due {
+                return;
+            }
+            self.last_sample.insert(tags.source.to_string(), now);
+        }
+        self.queue(sample_line(&self.opts.measurement_prefix, tags, s, delta));
+    }
+
+    /// Queue the summary of a completed lap; `best` when it's the session's
+    /// best so far.
+    pub fn write_lap(&self, tags: Tags, lap: &Lap, sector_ms: &[f64], best: bool) {
+        self.queue(lap_line(&self.opts.measurement_prefix, tags, lap, sector_ms, best));
+    }
+
+    fn queue(&self, mut line: String) {
+        let ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let _ = write!(line, " {ms}");
+        // full only when Influx has been away for a long time
+        if self.tx.try_send(line).is_err() {
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A `<prefix>_sample` line, without its timestamp.
+fn sample_line(prefix: &str, tags: Tags, s: &TelemetrySample, delta: &LiveDeltaState) -> String {
+    let mut line = head(prefix, "sample", tags);
+    let _ = write!(
+        line,
+        " speed_kph={},throttle={},brake={},gear={}i,rpm={},x={},y={},z={},lap={}i,lap_distance_m={},lap_time_ms={}",
+        s.speed_mps as f64 * 3.6,
+        s.throttle,
+        s.brake,
+        s.gear,
+        s.engine_rpm,
+        s.world_pos_x,
+        s.world_pos_y,
+        s.world_pos_z,
+        s.current_lap,
+        s.lap_distance_m,
+        delta.elapsed_ms,
+    );
+    if let Some(d) = delta.delta_ms {
+        let _ = write!(line, ",delta_ms={d}");
+    }
+    line
+}
+
+/// A `<prefix>_lap` line, without its timestamp.
+fn lap_line(prefix: &str, tags: Tags, lap: &Lap, sector_ms: &[f64], best: bool) -> String {
+    let mut line = head(prefix, "lap", tags);
+    let _ = write!(
+        line,
+        " lap_number={}i,time_ms={}i,best={best},id=\"{}\"",
+        lap.meta.lap_number,
+        lap.total_time_ms,
+        lap.id.simple()
+    );
+    for (i, t) in sector_ms.iter().enumerate() {
+        let _ = write!(line, ",sector_{}_ms={t}", i + 1);
+    }
+    line
+}
+
+/// Measurement and tag set of a line.
+fn head(prefix: &str, kind: &str, tags: Tags) -> String {
+    let mut line = escape(&format!("{prefix}_{kind}"), ", ");
+    for (k, v) in [("source", tags.source), ("game", tags.game), ("car", tags.car), ("track", tags.track)] {
+        // Influx rejects empty tag values
+        if !v.is_empty() {
+            let _ = write!(line, ",{k}={}", escape(v, ",= "));
+        }
+    }
+    line
+}
+
+/// Backslash-escape `special` characters (and backslashes) of a
+/// measurement, tag key or tag value.
+fn escape(s: &str, special: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || special.contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+struct Writer {
+    agent: ureq::Agent,
+    url: String,
+    token: Option<String>,
+    batch_size: usize,
+    flush: Duration,
+    shared: Arc<Shared>,
+}
+
+impl Writer {
+    fn new(opts: &InfluxOptions, shared: Arc<Shared>) -> Self {
+        let url = format!("{}/api/v2/write", opts.url.trim_end_matches('/'));
+        Self {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            url: format!("{url}?precision=ms&org={}&bucket={}", query(&opts.org), query(&opts.bucket)),
+            token: opts.token.clone().filter(|t| !t.is_empty()),
+            batch_size: opts.batch_size.max(1),
+            flush: Duration::from_millis(opts.flush_ms.max(10)),
+            shared,
+        }
+    }
+
+    fn run(self, rx: mpsc::Receiver<String>) {
+        let mut pending: VecDeque<String> = VecDeque::new();
+        let mut last_flush = Instant::now();
+        let mut backoff = Duration::ZERO;
+        let mut retry_at = Instant::now();
+        loop {
+            match rx.recv_timeout(self.flush) {
+                Ok(line) => {
+                    pending.push_back(line);
+                    // take what else is waiting without blocking
+                    pending.extend(rx.try_iter().take(self.batch_size));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                // the sink is gone: one go at what's left, then stop
+                Err(RecvTimeoutError::Disconnected) => {
+                    while !pending.is_empty() && self.write(&mut pending) {}
+                    return;
+                }
+            }
+            if pending.len() > MAX_PENDING {
+                let over = pending.len() - MAX_PENDING;
+                pending.drain(..over);
+                self.shared.dropped.fetch_add(over as u64, Ordering::Relaxed);
+            }
+            let due = pending.len() >= self.batch_size || last_flush.elapsed() >= self.flush;
+            if due && !pending.is_empty() && Instant::now() >= retry_at {
+                last_flush = Instant::now();
+                if self.write(&mut pending) {
+                    backoff = Duration::ZERO;
+                } else {
+                    backoff = (backoff * 2).clamp(Duration::from_secs(1), MAX_BACKOFF);
+                    retry_at = Instant::now() + backoff;
+                }
+            }
+        }
+    }
+
+    /// Write the oldest batch of `pending`; false when it should be retried.
+    fn write(&self, pending: &mut VecDeque<String>) -> bool {
+        let n = pending.len().min(self.batch_size);
+        let body = pending.iter().take(n).map(String::as_str).collect::<Vec<_>>().join("\n");
+        let (error, retry) = match self.post(&body) {
+            Ok(()) => {
+                self.shared.written.fetch_add(n as u64, Ordering::Relaxed);
+                (None, false)
+            }
+            // resending data Influx refused won't help
+            Err(Failure::Rejected(e)) => {
+                self.shared.dropped.fetch_add(n as u64, Ordering::Relaxed);
+                (Some(e), false)
+            }
+            Err(Failure::Retry(e)) => (Some(e), true),
+        };
+        *self.shared.error.lock().unwrap() = error;
+        if !retry {
+            pending.drain(..n);
+        }
+        !retry
+    }
+
+    fn post(&self, body: &str) -> Result<(), Failure> {
+        let mut req = self.agent.post(&self.url).set("Content-Type", "text/plain; charset=utf-8");
+        if let Some(token) = &self.token {
+            req = req.set("Authorization", &format!("Token {token}"));
+        }
+        match req.send_string(body) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, resp)) => {
+                let text = resp.into_string().unwrap_or_default();
+                let e = match text.trim() {
+                    "" => format!("InfluxDB answered {code}"),
+                    text => format!("InfluxDB answered {code}: {text}"),
+                };
+                // rate limits and server trouble pass; bad data and auth don't
+                if code == 429 || code >= 500 { Err(Failure::Retry(e)) } else { Err(Failure::Rejected(e)) }
+            }
+            Err(e) => Err(Failure::Retry(e.to_string())),
+        }
+    }
+}
+
+enum Failure {
+    Retry(String),
+    Rejected(String),
+}
+
+/// Percent-encode a query value.
+fn query(v: &str) -> String {
+    v.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use delta_ingest_core::{Game, Phase};
+    use model::LapMeta;
+    use uuid::Uuid;
+
+    const TAGS: Tags = Tags { source: "f1", game: "f1_2024", car: "Red Bull", track: "Spa, Belgium" };
+
+    fn sample() -> TelemetrySample {
+        TelemetrySample {
+            game: Game::F1_2024,
+            car_id: "player:0".into(),
+            session_uid: "s".into(),
+            frame: 1,
+            sim_time_s: 12.5,
+            speed_mps: 50.0,
+            throttle: 0.5,
+            brake: 0.0,
+            gear: 7,
+            engine_rpm: 11000.0,
+            world_pos_x: 1.5,
+            world_pos_y: -2.0,
+            world_pos_z: 0.25,
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            lap_distance_m: 640.0,
+            current_lap: 3,
+            current_lap_time_s: 12.5,
+            last_lap_time_s: 0.0,
+            best_lap_time_s: 0.0,
+            fuel_l: 0.0,
+            fuel_capacity_l: 0.0,
+            rev_limiter: false,
+            phase: Phase::Driving,
+        }
+    }
+
+    #[test]
+    fn escapes_measurements_and_tags() {
+        let tags = Tags { source: "a=b", game: "", car: r"c\d", track: "Spa, Belgium" };
+        assert_eq!(head("my delta", "lap", tags), r"my\ delta_lap,source=a\=b,car=c\\d,track=Spa\,\ Belgium");
+    }
+
+    #[test]
+    fn writes_sample_fields() {
+        let delta = LiveDeltaState { elapsed_ms: 12500.0, ..Default::default() };
+        assert_eq!(
+            sample_line("delta", TAGS, &sample(), &delta),
+            r"delta_sample,source=f1,game=f1_2024,car=Red\ Bull,track=Spa\,\ Belgium speed_kph=180,throttle=0.5,brake=0,gear=7i,rpm=11000,x=1.5,y=-2,z=0.25,lap=3i,lap_distance_m=640,lap_time_ms=12500"
+        );
+        // the delta only once there's a reference
+        let delta = LiveDeltaState { delta_ms: Some(-125.5), ..delta };
+        assert!(sample_line("delta", TAGS, &sample(), &delta).ends_with(",lap_time_ms=12500,delta_ms=-125.5"));
+    }
+
+    #[test]
+    fn writes_lap_fields() {
+        let id = Uuid::new_v4();
+        let lap = Lap {
+            id,
+            meta: LapMeta {
+                id,
+                game: "f1_2024".into(),
+                car: "Red Bull".into(),
+                track: "Spa".into(),
+                lap_number: 4,
+                out_lap: false,
+                in_lap: false,
+            },
+            total_time_ms: 105_250,
+            points: Vec::new(),
+            markers: Vec::new(),
+            index: Default::default(),
+        };
+        let line = lap_line("delta", TAGS, &lap, &[30_100.5, 45_000.0], true);
+        let fields = line.split_once(" lap_number").unwrap().1;
+        assert_eq!(fields, format!("=4i,time_ms=105250i,best=true,id=\"{}\",sector_1_ms=30100.5,sector_2_ms=45000", id.simple()));
+    }
+}
@@ -0,0 +1,35 @@
+//! Access to a running Delta instance for tools and hardware outside the
+//! app: a WebSocket feed of live data ([`ws`]), an HTTP API over recorded
+//! laps and their analysis ([`rest`]) with Prometheus metrics ([`metrics`]),
+//! and live data published to an MQTT broker ([`mqtt`]) or NATS
+//! ([`nats`]), sent as OSC to motion rigs ([`osc`]) or framed over a serial
+//! port to DIY dashboards ([`serial`]). Samples and laps can be stored in InfluxDB ([`influx`]) or
+//! PostgreSQL/TimescaleDB ([`pg`]), and notable events posted to Discord
+//! ([`discord`]). With the `grpc` feature, a gRPC service streams typed
+//! samples and laps ([`grpc`]).
+
+pub mod discord;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod influx;
+pub mod metrics;
+pub mod mqtt;
+pub mod nats;
+pub mod osc;
+pub mod pg;
+pub mod rest;
+pub mod serial;
+pub mod ws;
+
+pub use discord::DiscordNotifier;
+#[cfg(feature = "grpc")]
+pub use grpc::GrpcServer;
+pub use influx::InfluxSink;
+pub use metrics::SourceMetrics;
+pub use mqtt::MqttSink;
+pub use nats::NatsSink;
+pub use osc::OscSink;
+pub use pg::PgSink;
+pub use rest::{ApiServer, LapInfo, LapProvider, LiveView};
+pub use serial::SerialSink;
+pub use ws::TelemetryServer;
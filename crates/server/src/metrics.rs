@@ -0,0 +1,157 @@
+//! Ingest and session figures in the Prometheus text format, served on
+//! `GET /metrics` by the HTTP API for scraping into Grafana.
+//!
+//! Everything is labelled by `source`. Totals are counters, so rates come
+//! from PromQL, e.g. `rate(delta_packets_total[1m])` for packets per second.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Figures of one running source.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct SourceMetrics {
+    pub source: String,
+    /// Packet figures are `None` for connectors that don't count packets
+    /// (shared memory).
+    pub packets: Option<u64>,
+    pub bytes: Option<u64>,
+    /// Packets that couldn't be decoded.
+    pub parse_errors: Option<u64>,
+    /// Samples the app received.
+    pub samples: u64,
+    /// Samples that didn't go into a lap: received while paused or part of a
+    /// lap thrown away.
+    pub dropped_samples: u64,
+    pub laps_completed: u64,
+    pub current_lap_ms: Option<f64>,
+    pub last_lap_ms: Option<u64>,
+    pub best_lap_ms: Option<u64>,
+}
+
+struct Family {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+    value: fn(&SourceMetrics) -> Option<f64>,
+}
+
+const FAMILIES: &[Family] = &[
+    Family {
+        name: "delta_packets_total",
+        kind: "counter",
+        help: "UDP packets received.",
+        value: |m| m.packets.map(|n| n as f64),
+    },
+    Family {
+        name: "delta_packet_bytes_total",
+        kind: "counter",
+        help: "Bytes of UDP packets received.",
+        value: |m| m.bytes.map(|n| n as f64),
+    },
+    Family {
+        name: "delta_parse_errors_total",
+        kind: "counter",
+        help: "Packets that couldn't be decoded.",
+        value: |m| m.parse_errors.map(|n| n as f64),
+    },
+    Family {
+        name: "delta_samples_total",
+        kind: "counter",
+        help: "Telemetry samples received.",
+        value: |m| Some(m.samples as f64),
+    },
+    Family {
+        name: "delta_dropped_samples_total",
+        kind: "counter",
+        help: "Samples not recorded into a lap (paused or partial laps).",
+        value: |m| Some(m.dropped_samples as f64),
+    },
+    Family {
+        name: "delta_laps_completed_total",
+        kind: "counter",
+        help: "Laps completed.",
+        value: |m| Some(m.laps_completed as f64),
+    },
+    Family {
+        name: "delta_current_lap_seconds",
+        kind: "gauge",
+        help: "Time into the lap in progress.",
+        value: |m| m.current_lap_ms.map(|ms| ms / 1000.0),
+    },
+    Family {
+        name: "delta_last_lap_seconds",
+        kind: "gauge",
+        help: "Time of the last completed lap.",
+        value: |m| m.last_lap_ms.map(|ms| ms as f64 / 1000.0),
+    },
+    Family {
+        name: "delta_best_lap_seconds",
+        kind: "gauge",
+        help: "Best lap time of the session.",
+        value: |m| m.best_lap_ms.map(|ms| ms as f64 / 1000.0),
+    },
+];
+
+/// `sources` in the Prometheus text exposition format. Figures a source
+/// doesn't have are left out rather than reported as 0.
+pub fn render(sources: &[SourceMetrics]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# HELP delta_sources Sources running.\n# TYPE delta_sources gauge\ndelta_sources {}", sources.len());
+    for f in FAMILIES {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", f.name, f.help, f.name, f.kind);
+        for m in sources {
+            if let Some(v) = (f.value)(m) {
+                let _ = writeln!(out, "{}{{source=\"{}\"}} {v}", f.name, escape(&m.source));
+            }
+        }
+    }
+    out
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', r"\\").replace('"', "\\\"").replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_each_family_per_source() {
+        let udp = SourceMetrics {
+            source: "f1".into(),
+            packets: Some(1200),
+            bytes: Some(1_500_000),
+            parse_errors: Some(2),
+            samples: 600,
+            dropped_samples: 40,
+            laps_completed: 3,
+            current_lap_ms: Some(12_345.0),
+            last_lap_ms: Some(81_250),
+            best_lap_ms: None,
+        };
+        let shm = SourceMetrics { source: "lmu \"shm\"".into(), samples: 10, ..Default::default() };
+        let text = render(&[udp, shm]);
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[..3], ["# HELP delta_sources Sources running.", "# TYPE delta_sources gauge", "delta_sources 2"]);
+        for line in [
+            "# TYPE delta_packets_total counter",
+            "delta_packets_total{source=\"f1\"} 1200",
+            "delta_dropped_samples_total{source=\"f1\"} 40",
+            "delta_samples_total{source=\"lmu \\\"shm\\\"\"} 10",
+            "# TYPE delta_current_lap_seconds gauge",
+            "delta_current_lap_seconds{source=\"f1\"} 12.345",
+            "delta_last_lap_seconds{source=\"f1\"} 81.25",
+        ] {
+            assert!(lines.contains(&line), "{line} missing from\n{text}");
+        }
+        // figures a source doesn't have are left out, not 0
+        assert!(!text.contains("delta_packets_total{source=\"lmu"));
+        assert!(!text.contains("delta_best_lap_seconds{"));
+        // a family's samples follow its TYPE line
+        let at = |l: &str| lines.iter().position(|x| *x == l).unwrap();
+        assert!(at("# TYPE delta_samples_total counter") < at("delta_samples_total{source=\"f1\"} 600"));
+        assert!(at("delta_samples_total{source=\"f1\"} 600") < at("# TYPE delta_dropped_samples_total counter"));
+    }
+}
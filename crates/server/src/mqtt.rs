@@ -0,0 +1,213 @@
+//! Live telemetry published to an MQTT broker, for home-automation and
+//! rig-control setups and dashboards like Node-RED.
+//!
+//! Topics are `<prefix>/<source>/...`, the source kept to one topic level:
+//! - `sample` — each sample as JSON ([`MqttMode::Sample`])
+//! - `sample/<field>[/<field>..]` — each value of the sample on its own
+//!   topic as plain text, e.g. `delta/f1/sample/speed_kph` ([`MqttMode::Channels`])
+//! - `lap` — a summary of each completed lap as JSON, retained when
+//!   [`MqttOptions::retain_laps`] is set
+//!
+//! Publishing never blocks: when the broker can't keep up, messages are
+//! dropped.
+
+use anyhow::Result;
+use rumqttc::{AsyncClient, Event, MqttOptions as ClientOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+
+/// Messages queued for the broker before publishing starts dropping them.
+const QUEUE: usize = 1024;
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MqttMode {
+    /// Whole samples as JSON on one topic.
+    #[default]
+    Sample,
+    /// One topic per value.
+    Channels,
+    Both,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct MqttOptions {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic_prefix: String,
+    pub mode: MqttMode,
+    /// 0, 1 or 2.
+    pub qos: u8,
+    /// Keep the last lap on the broker for clients that connect later.
+    pub retain_laps: bool,
+}
+
+impl Default for MqttOptions {
+    fn default() -> Self {
+        Self {
+            host: "localhost".into(),
+            port: 1883,
+            client_id: "delta".into(),
+            username: None,
+            password: None,
+            topic_prefix: "delta".into(),
+            mode: MqttMode::default(),
+            qos: 0,
+            retain_laps: true,
+        }
+    }
+}
+
+/// Connection to a broker; disconnects when dropped.
+pub struct MqttSink {
+    client: AsyncClient,
+    opts: MqttOptions,
+    qos: QoS,
+    // last connection error; cleared once connected
+    error: Arc<Mutex<Option<String>>>,
+    task: JoinHandle<()>,
+}
+
+impl MqttSink {
+    /// Connect to the broker in `opts`, retrying in the background until it
+    /// answers. Must be called inside a tokio runtime.
+    pub fn connect(opts: &MqttOptions) -> Result<Self> {
+        let mut co = ClientOptions::new(&opts.client_id, &opts.host, opts.port);
+        co.set_keep_alive(Duration::from_secs(15));
+        if let Some(user) = &opts.username {
+            co.set_credentials(user, opts.password.as_deref().unwrap_or_default());
+        }
+        let qos = match opts.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            q => anyhow::bail!("MQTT QoS must be 0, 1 or 2, got {q}"),
+        };
+        let (client, mut events) = AsyncClient::new(co, QUEUE);
+        let error = Arc::new(Mutex::new(None));
+        let last_error = error.clone();
+        // the event loop does the actual network work, reconnecting as needed
+        let task = tokio::spawn(async move {
+            loop {
+                match events.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => *last_error.lock().unwrap() = None,
+                    Ok(_) => {}
+                    Err(e) => {
+                        *last_error.lock().unwrap() = Some(e.to_string());
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        });
+        Ok(Self { client, opts: opts.clone(), qos, error, task })
+    }
+
+    pub fn options(&self) -> &MqttOptions {
+        &self.opts
+    }
+
+    /// Why the broker can't be reached, while it can't.
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+
+    /// Publish a sample of `source` per the configured mode.
+    pub fn publish_sample<T: Serialize>(&self, source: &str, sample: &T) -> Result<()> {
+        let value = serde_json::to_value(sample)?;
+        let topic = topic(&self.opts.topic_prefix, source, "sample");
+        if matches!(self.opts.mode, MqttMode::Sample | MqttMode::Both) {
+            self.send(&topic, value.to_string(), false);
+        }
+        if matches!(self.opts.mode, MqttMode::Channels | MqttMode::Both) {
+            let mut leaves = Vec::new();
+            flatten(&topic, &value, &mut leaves);
+            for (topic, text) in leaves {
+                self.send(&topic, text, false);
+            }
+        }
+        Ok(())
+    }
+
+    /// Publish a completed lap of `source` as JSON.
+    pub fn publish_lap<T: Serialize>(&self, source: &str, lap: &T) -> Result<()> {
+        let topic = topic(&self.opts.topic_prefix, source, "lap");
+        self.send(&topic, serde_json::to_string(lap)?, self.opts.retain_laps);
+        Ok(())
+    }
+
+    fn send(&self, topic: &str, payload: String, retain: bool) {
+        // a full queue means the broker is away or slow; drop rather than block
+        let _ = self.client.try_publish(topic, self.qos, retain, payload);
+    }
+}
+
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        let _ = self.client.try_disconnect();
+        self.task.abort();
+    }
+}
+
+/// `<prefix>/<source>/<kind>`, with the characters of `source` a topic
+/// level can't hold (a separator, wildcards) replaced by `_`.
+fn topic(prefix: &str, source: &str, kind: &str) -> String {
+    let level: String = source.chars().map(|c| if matches!(c, '/' | '+' | '#') { '_' } else { c }).collect();
+    format!("{prefix}/{level}/{kind}")
+}
+
+/// Leaf values of `v` as `(topic, text)`, objects nesting into sub-topics.
+/// Arrays and nulls are skipped; strings are sent without quotes.
+fn flatten(topic: &str, v: &Value, out: &mut Vec<(String, String)>) {
+    match v {
+        Value::Object(fields) => {
+            for (k, f) in fields {
+                flatten(&format!("{topic}/{k}"), f, out);
+            }
+        }
+        Value::String(s) => out.push((topic.to_string(), s.clone())),
+        Value::Number(_) | Value::Bool(_) => out.push((topic.to_string(), v.to_string())),
+        Value::Array(_) | Value::Null => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topics_keep_the_source_to_one_level() {
+        assert_eq!(topic("delta", "f1", "sample"), "delta/f1/sample");
+        assert_eq!(topic("rig/delta", "udp/20777+#", "lap"), "rig/delta/udp_20777__/lap");
+    }
+
+    #[test]
+    fn channels_nest_into_sub_topics() {
+        let v = serde_json::json!({
+            "speed_kph": 201.5,
+            "car": "player:0",
+            "delta": { "ms": -12.0, "best": null },
+            "sectors": [1, 2],
+            "paused": false,
+        });
+        let mut out = Vec::new();
+        flatten("delta/f1/sample", &v, &mut out);
+        out.sort();
+        let want = [
+            ("delta/f1/sample/car", "player:0"),
+            ("delta/f1/sample/delta/ms", "-12.0"),
+            ("delta/f1/sample/paused", "false"),
+            ("delta/f1/sample/speed_kph", "201.5"),
+        ];
+        assert_eq!(out, want.map(|(t, v)| (t.to_string(), v.to_string())));
+    }
+}
@@ -0,0 +1,169 @@
+//! Live telemetry published to a NATS server, for teams that already run
+//! NATS as the bus between driver rigs and the pit wall.
+//!
+//! Subjects are `<prefix>.<driver>.<source>.<kind>`, e.g.
+//! `delta.alex.f1.sample`, so the pit wall can follow one driver
+//! (`delta.alex.>`) or every lap of the event (`delta.*.*.lap`):
+//! - `sample` — each sample as JSON, at the live rate
+//! - `lap` — a summary of each completed lap as JSON
+//!
+//! Publishing never blocks: while the server is away or slow, messages are
+//! dropped.
+
+use anyhow::{bail, Result};
+use async_nats::{ConnectOptions, Event};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+/// Messages queued for the server before publishing starts dropping them.
+const QUEUE: usize = 1024;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct NatsOptions {
+    /// `nats://host:4222`; several servers of a cluster separated by commas.
+    pub url: String,
+    /// Driver the subjects are published under.
+    pub driver: String,
+    pub subject_prefix: String,
+    pub token: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for NatsOptions {
+    fn default() -> Self {
+        Self {
+            url: "nats://localhost:4222".into(),
+            driver: "driver".into(),
+            subject_prefix: "delta".into(),
+            token: None,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct NatsStatus {
+    pub server: String,
+    pub connected: bool,
+    /// Last connection problem; cleared once connected.
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    connected: bool,
+    error: Option<String>,
+}
+
+/// Connection to a NATS server; closes when dropped.
+pub struct NatsSink {
+    opts: NatsOptions,
+    // `<prefix>.<driver>`, checked and cleaned up
+    base: String,
+    tx: mpsc::Sender<(String, String)>,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl NatsSink {
+    /// Connect to the servers in `opts`, retrying in the background until
+    /// one answers. Must be called inside a tokio runtime.
+    pub fn connect(opts: &NatsOptions) -> Result<Self> {
+        let prefix = opts.subject_prefix.trim_matches('.');
+        if prefix.is_empty() || !prefix.split('.').all(|t| !t.is_empty() && t.chars().all(subject_char)) {
+            bail!("{:?} isn't a valid NATS subject prefix", opts.subject_prefix);
+        }
+        let base = format!("{prefix}.{}", token(&opts.driver));
+        let state = Arc::new(Mutex::new(State::default()));
+        let events = state.clone();
+        let mut co = ConnectOptions::new()
+            .name("delta")
+            .retry_on_initial_connect()
+            .event_callback(move |e| {
+                let events = events.clone();
+                async move {
+                    let mut s = events.lock().unwrap();
+                    match e {
+                        Event::Connected => *s = State { connected: true, error: None },
+                        Event::Disconnected => s.connected = false,
+                        Event::ServerError(e) => s.error = Some(format!("server error: {e}")),
+                        Event::ClientError(e) => s.error = Some(format!("client error: {e}")),
+                        _ => {}
+                    }
+                }
+            });
+        if let Some(token) = opts.token.clone().filter(|t| !t.is_empty()) {
+            co = co.token(token);
+        }
+        if let Some(user) = opts.username.clone().filter(|u| !u.is_empty()) {
+            co = co.user_and_password(user, opts.password.clone().unwrap_or_default());
+        }
+        let url = opts.url.clone();
+        let (tx, mut rx) = mpsc::channel::<(String, String)>(QUEUE);
+        let failed = state.clone();
+        let task = tokio::spawn(async move {
+            // with retry_on_initial_connect this only fails on a bad address
+            let client = match co.connect(url.as_str()).await {
+                Ok(c) => c,
+                Err(e) => {
+                    failed.lock().unwrap().error = Some(e.to_string());
+                    return;
+                }
+            };
+            while let Some((subject, payload)) = rx.recv().await {
+                // fails only once the client is closed, i.e. never while we
+                // hold it; a lost connection buffers and reconnects instead
+                let _ = client.publish(subject, payload.into()).await;
+            }
+        });
+        Ok(Self { opts: opts.clone(), base, tx, state, task })
+    }
+
+    pub fn options(&self) -> &NatsOptions {
+        &self.opts
+    }
+
+    pub fn status(&self) -> NatsStatus {
+        let s = self.state.lock().unwrap();
+        NatsStatus { server: self.opts.url.clone(), connected: s.connected, error: s.error.clone() }
+    }
+
+    /// Publish a sample of `source` as JSON.
+    pub fn publish_sample<T: Serialize>(&self, source: &str, sample: &T) -> Result<()> {
+        self.send(source, "sample", serde_json::to_string(sample)?);
+        Ok(())
+    }
+
+    /// Publish a completed lap of `source` as JSON.
+    pub fn publish_lap<T: Serialize>(&self, source: &str, lap: &T) -> Result<()> {
+        self.send(source, "lap", serde_json::to_string(lap)?);
+        Ok(())
+    }
+
+    fn send(&self, source: &str, kind: &str, payload: String) {
+        let subject = format!("{}.{}.{kind}", self.base, token(source));
+        // a full queue means the server is away or slow; drop rather than block
+        let _ = self.tx.try_send((subject, payload));
+    }
+}
+
+impl Drop for NatsSink {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn subject_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '.' | '*' | '>')
+}
+
+/// `s` as a single subject token: lower case, with characters subjects
+/// can't hold replaced by `_`.
+fn token(s: &str) -> String {
+    let t: String = s.trim().to_lowercase().chars().map(|c| if subject_char(c) { c } else { '_' }).collect();
+    if t.is_empty() { "_".into() } else { t }
+}
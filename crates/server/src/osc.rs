@@ -0,0 +1,245 @@
+//! Live telemetry as OSC messages over UDP, for motion platforms and
+//! bass-shaker software (SimHub-style rigs, SimTools, custom controllers).
+//!
+//! Each [`OscMapping`] sends one channel as a single float to its own OSC
+//! address at its own rate. Accelerations, yaw rate and slip aren't reported
+//! by every game, so they are derived from consecutive samples of a source.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+use analysis::math::wrap_angle;
+use delta_ingest_core::TelemetrySample;
+
+const G: f64 = 9.81;
+// derived channels are differences of noisy samples; smooth them over
+// about this long
+const SMOOTHING_S: f64 = 0.05;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum OscChannel {
+    SpeedKph,
+    Rpm,
+    Throttle,
+    Brake,
+    Gear,
+    /// Acceleration along the direction of travel, in g (braking negative).
+    LongG,
+    /// Sideways acceleration from the turn of the driven path, in g.
+    LatG,
+    /// Vertical acceleration, in g (bumps, kerbs, crests).
+    VertG,
+    /// Rotation of the car body, rad/s.
+    YawRate,
+    /// How fast the body rotates relative to the path it follows, rad/s:
+    /// near 0 with grip, large in slides and spins.
+    Slip,
+    Pitch,
+    Roll,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct OscMapping {
+    pub channel: OscChannel,
+    /// OSC address, e.g. `/rig/surge`.
+    pub address: String,
+    /// Multiplies the value before sending.
+    #[serde(default = "one")]
+    pub scale: f32,
+    /// Messages per second; the sink's rate when unset.
+    #[serde(default)]
+    pub rate_hz: Option<f64>,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct OscOptions {
+    /// Where messages go, `host:port`.
+    pub target: String,
+    /// Messages per second per mapping.
+    pub rate_hz: f64,
+    /// Only send this source's samples; every source when unset.
+    pub source: Option<String>,
+    pub mappings: Vec<OscMapping>,
+}
+
+impl Default for OscOptions {
+    fn default() -> Self {
+        let map = |channel, address: &str| OscMapping { channel, address: address.into(), scale: 1.0, rate_hz: None };
+        Self {
+            target: "127.0.0.1:9000".into(),
+            rate_hz: 60.0,
+            source: None,
+            mappings: vec![
+                map(OscChannel::LongG, "/delta/long_g"),
+                map(OscChannel::LatG, "/delta/lat_g"),
+                map(OscChannel::VertG, "/delta/vert_g"),
+                map(OscChannel::SpeedKph, "/delta/speed"),
+                map(OscChannel::Rpm, "/delta/rpm"),
+                map(OscChannel::Slip, "/delta/slip"),
+            ],
+        }
+    }
+}
+
+/// Sends mapped channels to one OSC receiver.
+pub struct OscSink {
+    opts: OscOptions,
+    socket: UdpSocket,
+    sources: HashMap<String, SourceState>,
+}
+
+#[derive(Default)]
+struct SourceState {
+    motion: Motion,
+    // last send per mapping
+    sent: Vec<Option<Instant>>,
+}
+
+impl OscSink {
+    pub fn new(opts: &OscOptions) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").context("bind OSC socket")?;
+        socket.connect(&opts.target).with_context(|| format!("OSC target {}", opts.target))?;
+        // a receiver that isn't listening must not hold up ingest
+        socket.set_nonblocking(true)?;
+        Ok(Self { opts: opts.clone(), socket, sources: HashMap::new() })
+    }
+
+    pub fn options(&self) -> &OscOptions {
+        &self.opts
+    }
+
+    /// Take a sample of `source`, sending the mappings that are due.
+    pub fn send(&mut self, source: &str, s: &TelemetrySample) {
+        if self.opts.source.as_deref().is_some_and(|want| want != source) {
+            return;
+        }
+        let state = self.sources.entry(source.to_string()).or_default();
+        state.motion.update(s);
+        state.sent.resize(self.opts.mappings.len(), None);
+        let now = Instant::now();
+        for (m, sent) in self.opts.mappings.iter().zip(&mut state.sent) {
+            let hz = m.rate_hz.unwrap_or(self.opts.rate_hz);
+            let due = hz > 0.0 && sent.is_none_or(|t| now.duration_since(t) >= Duration::from_secs_f64(1.0 / hz));
+            if !due {
+                continue;
+            }
+            *sent = Some(now);
+            let value = state.motion.value(m.channel, s) as f32 * m.scale;
+            // dropped when the receiver is away; the next one replaces it anyway
+            let _ = self.socket.send(&message(&m.address, value));
+        }
+    }
+}
+
+/// Motion derived from consecutive samples.
+#[derive(Default)]
+struct Motion {
+    last: Option<TelemetrySample>,
+    heading: Option<f64>,
+    vert_speed: Option<f64>,
+    long_g: f64,
+    lat_g: f64,
+    vert_g: f64,
+    yaw_rate: f64,
+    slip: f64,
+}
+
+impl Motion {
+    fn update(&mut self, s: &TelemetrySample) {
+        let Some(last) = self.last.replace(s.clone()) else { return };
+        let dt = s.sim_time_s - last.sim_time_s;
+        // repeated or reordered samples carry no motion
+        if dt <= 0.0 {
+            return;
+        }
+        let v = s.speed_mps as f64;
+        let (dx, dy) = ((s.world_pos_x - last.world_pos_x) as f64, (s.world_pos_y - last.world_pos_y) as f64);
+        let vert_speed = (s.world_pos_z - last.world_pos_z) as f64 / dt;
+        let yaw_rate = wrap_angle((s.yaw - last.yaw) as f64) / dt;
+        // heading of the driven path, clockwise from north like yaw;
+        // undefined when (nearly) standing still
+        let heading = (dx.hypot(dy) > 1e-3).then(|| dx.atan2(dy));
+        let path_rate = match (self.heading, heading) {
+            (Some(h0), Some(h1)) => wrap_angle(h1 - h0) / dt,
+            _ => 0.0,
+        };
+
+        let k = (dt / SMOOTHING_S).min(1.0);
+        let smooth = |old: f64, new: f64| old + k * (new - old);
+        self.long_g = smooth(self.long_g, (v - last.speed_mps as f64) / dt / G);
+        self.lat_g = smooth(self.lat_g, v * path_rate / G);
+        if let Some(last_vert) = self.vert_speed {
+            self.vert_g = smooth(self.vert_g, (vert_speed - last_vert) / dt / G);
+        }
+        self.yaw_rate = smooth(self.yaw_rate, yaw_rate);
+        self.slip = smooth(self.slip, yaw_rate - path_rate);
+        self.vert_speed = Some(vert_speed);
+        if heading.is_some() {
+            self.heading = heading;
+        }
+    }
+
+    fn value(&self, channel: OscChannel, s: &TelemetrySample) -> f64 {
+        match channel {
+            OscChannel::SpeedKph => s.speed_mps as f64 * 3.6,
+            OscChannel::Rpm => s.engine_rpm as f64,
+            OscChannel::Throttle => s.throttle as f64,
+            OscChannel::Brake => s.brake as f64,
+            OscChannel::Gear => s.gear as f64,
+            OscChannel::LongG => self.long_g,
+            OscChannel::LatG => self.lat_g,
+            OscChannel::VertG => self.vert_g,
+            OscChannel::YawRate => self.yaw_rate,
+            OscChannel::Slip => self.slip,
+            OscChannel::Pitch => s.pitch as f64,
+            OscChannel::Roll => s.roll as f64,
+        }
+    }
+}
+
+/// An OSC message with one float argument.
+fn message(address: &str, value: f32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(address.len() + 12);
+    pad_str(&mut out, address);
+    pad_str(&mut out, ",f");
+    out.extend_from_slice(&value.to_be_bytes());
+    out
+}
+
+/// OSC strings end in a NUL and are padded with more to a multiple of 4.
+fn pad_str(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(s.as_bytes());
+    out.resize(out.len() + 4 - s.len() % 4, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_a_float_message() {
+        let msg = message("/rig/surge", -0.5);
+        let mut want = b"/rig/surge\0\0,f\0\0".to_vec();
+        want.extend_from_slice(&[0xBF, 0x00, 0x00, 0x00]);
+        assert_eq!(msg, want);
+    }
+
+    #[test]
+    fn strings_always_end_in_a_nul() {
+        // a multiple of 4 long still gets a NUL, then padding
+        assert_eq!(message("/abc", 1.0)[..8], *b"/abc\0\0\0\0");
+        assert_eq!(message("/ab", 1.0)[..4], *b"/ab\0");
+        assert_eq!(message("/rig/heave/x", 1.0).len(), 24);
+    }
+}
@@ -0,0 +1,423 @@
+//! Read-only HTTP API over the laps of a running Delta instance, for
+//! scripts and external tools.
+//!
+//! All responses are JSON; errors are `{"error": "<message>"}` with a 4xx
+//! status. Lap ids are accepted with or without hyphens.
+//!
+//! - `GET /laps[?game=&car=&track=]` — every lap without its points, fastest first
+//! - `GET /laps/{id}` — one lap with its points and markers
+//! - `GET /analysis/overlay?laps=<id>,<id>[&max_points=]` — speed of the laps by distance
+//! - `GET /analysis/delta?reference=<id>&laps=<id>,<id>[&max_points=]` — time delta to the reference
+//!
+//!   `max_points` decimates the series for charting (see [`analysis::decimate`]).
+//! - `GET /analysis/summary?laps=<id>,<id>` — best, worst, average, consistency
+//! - `GET /analysis/compare?a=<id>&b=<id>` — sector and corner breakdown of `b` against `a`
+//! - `GET /trackmap/{id}` — track map built from a lap
+//! - `GET /live` — running sources: lap, delta and position
+//! - `GET /live/trackmap?source=<key>` — track map of the track a source is on
+//! - `GET /overlay[?source=<key>]` — HTML overlay of the live data, made to be
+//!   added to OBS as a browser source
+//! - `GET /metrics` — ingest and session figures for Prometheus (plain text,
+//!   see [`crate::metrics`])
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::{net::TcpListener, task::JoinHandle};
+use uuid::Uuid;
+
+use analysis::compare::{head_to_head, LapComparison};
+use analysis::{DeltaSeries, OverlayResult, SessionSummary};
+use model::*;
+
+use crate::metrics::{self, SourceMetrics};
+
+pub const DEFAULT_PORT: u16 = 8766;
+
+/// Where the API gets its laps from.
+pub trait LapProvider: Send + Sync + 'static {
+    /// Every lap, without points.
+    fn laps(&self) -> Vec<LapInfo>;
+    /// Shared, so serving a lap doesn't copy its points.
+    fn lap(&self, id: Uuid) -> Option<Arc<Lap>>;
+
+    /// Running sources; none for providers without live data.
+    fn live(&self) -> Vec<LiveView> {
+        Vec::new()
+    }
+
+    fn track_map(&self, _game: &str, _track: &str) -> Option<TrackMap> {
+        None
+    }
+
+    fn metrics(&self) -> Vec<SourceMetrics> {
+        Vec::new()
+    }
+}
+
+/// What the overlay shows of a running source.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LiveView {
+    pub source: String,
+    pub game: String,
+    pub track: String,
+    pub lap_number: u32,
+    pub lap_time_ms: f64,
+    /// Against the session best at the same distance; `None` until there is one.
+    pub delta_ms: Option<f64>,
+    pub predicted_ms: Option<f64>,
+    pub best_ms: Option<u64>,
+    pub last_lap_ms: Option<u64>,
+    pub speed_kph: f64,
+    pub gear: i8,
+    /// Position in the frame of the track maps.
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LapInfo {
+    #[serde(with = "uuid::serde::simple")]
+    pub id: Uuid,
+    pub meta: LapMeta,
+    pub total_time_ms: u64,
+    /// Source session the lap was recorded in; none for imported laps.
+    pub session_id: Option<Uuid>,
+}
+
+/// A running API server; stops when dropped.
+pub struct ApiServer {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl ApiServer {
+    /// Listen on `addr` (`127.0.0.1:8766` for this machine only). Must be
+    /// called inside a tokio runtime.
+    pub async fn bind(addr: &str, laps: Arc<dyn LapProvider>) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await.with_context(|| format!("bind {addr}"))?;
+        let addr = listener.local_addr()?;
+        let app = router(laps);
+        let task = tokio::spawn(async move {
+            // only returns on listener errors; the server just stops then
+            let _ = axum::serve(listener, app).await;
+        });
+        Ok(Self { addr, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+type Laps = Arc<dyn LapProvider>;
+
+pub fn router(laps: Laps) -> Router {
+    Router::new()
+        .route("/laps", get(list_laps))
+        .route("/laps/{id}", get(get_lap))
+        .route("/analysis/overlay", get(overlay))
+        .route("/analysis/delta", get(delta))
+        .route("/analysis/summary", get(summary))
+        .route("/analysis/compare", get(compare))
+        .route("/trackmap/{id}", get(track_map))
+        .route("/live", get(live))
+        .route("/live/trackmap", get(live_track_map))
+        .route("/overlay", get(overlay_page))
+        .route("/metrics", get(metrics_text))
+        .with_state(laps)
+}
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+fn parse_id(s: &str) -> Result<Uuid, ApiError> {
+    Uuid::parse_str(s.trim()).map_err(|_| ApiError(StatusCode::BAD_REQUEST, format!("bad lap id {s:?}")))
+}
+
+fn not_found(id: Uuid) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, format!("lap {id} not found"))
+}
+
+/// Providers may lock the app's session or read the lap database, so laps
+/// are loaded off the async workers too.
+async fn load(laps: &Laps, id: &str) -> Result<Arc<Lap>, ApiError> {
+    let id = parse_id(id)?;
+    let laps = laps.clone();
+    off_workers(move || laps.lap(id)).await?.ok_or_else(|| not_found(id))
+}
+
+/// Laps of a comma-separated id list.
+async fn load_list(laps: &Laps, ids: &str) -> Result<Vec<Arc<Lap>>, ApiError> {
+    let ids: Vec<Uuid> = ids.split(',').filter(|s| !s.trim().is_empty()).map(parse_id).collect::<Result<_, _>>()?;
+    if ids.is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "no laps given".into()));
+    }
+    let laps = laps.clone();
+    off_workers(move || ids.into_iter().map(|id| laps.lap(id).ok_or_else(|| not_found(id))).collect()).await?
+}
+
+/// Run blocking work off the async workers.
+async fn off_workers<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T, ApiError> {
+    tokio::task::spawn_blocking(f).await.map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Run CPU-heavy analysis off the async workers.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> ApiResult<T> {
+    off_workers(f).await.map(Json)
+}
+
+#[derive(Deserialize)]
+struct LapFilter {
+    game: Option<String>,
+    car: Option<String>,
+    track: Option<String>,
+}
+
+async fn list_laps(State(laps): State<Laps>, Query(f): Query<LapFilter>) -> ApiResult<Vec<LapInfo>> {
+    blocking(move || {
+        let matches = |want: &Option<String>, have: &str| want.as_deref().is_none_or(|w| w.eq_ignore_ascii_case(have));
+        let mut list: Vec<LapInfo> = laps
+            .laps()
+            .into_iter()
+            .filter(|l| matches(&f.game, &l.meta.game) && matches(&f.car, &l.meta.car) && matches(&f.track, &l.meta.track))
+            .collect();
+        list.sort_by_key(|l| l.total_time_ms);
+        list
+    })
+    .await
+}
+
+async fn get_lap(State(laps): State<Laps>, Path(id): Path<String>) -> ApiResult<Arc<Lap>> {
+    load(&laps, &id).await.map(Json)
+}
+
+#[derive(Deserialize)]
+struct LapList {
+    laps: String,
+}
+
+#[derive(Deserialize)]
+struct OverlayQuery {
+    laps: String,
+    /// Decimate to this many points.
+    max_points: Option<usize>,
+}
+
+async fn overlay(State(laps): State<Laps>, Query(q): Query<OverlayQuery>) -> ApiResult<OverlayResult> {
+    let list = load_list(&laps, &q.laps).await?;
+    blocking(move || {
+        let mut overlay = analysis::speed_overlay(&list);
+        if let Some(n) = q.max_points {
+            overlay.decimate(n);
+        }
+        overlay
+    })
+    .await
+}
+
+async fn summary(State(laps): State<Laps>, Query(q): Query<LapList>) -> ApiResult<SessionSummary> {
+    let list = load_list(&laps, &q.laps).await?;
+    Ok(Json(analysis::session_summary(&list)))
+}
+
+#[derive(Deserialize)]
+struct DeltaQuery {
+    reference: String,
+    laps: String,
+    /// Decimate to this many points.
+    max_points: Option<usize>,
+}
+
+async fn delta(State(laps): State<Laps>, Query(q): Query<DeltaQuery>) -> ApiResult<DeltaSeries> {
+    let reference = load(&laps, &q.reference).await?;
+    let list = load_list(&laps, &q.laps).await?;
+    blocking(move || {
+        let mut delta = analysis::delta_series(&reference, &list);
+        if let Some(n) = q.max_points {
+            delta.decimate(n);
+        }
+        delta
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+struct CompareQuery {
+    a: String,
+    b: String,
+}
+
+async fn compare(State(laps): State<Laps>, Query(q): Query<CompareQuery>) -> ApiResult<LapComparison> {
+    let (a, b) = (load(&laps, &q.a).await?, load(&laps, &q.b).await?);
+    blocking(move || head_to_head(&a, &b)).await
+}
+
+async fn track_map(State(laps): State<Laps>, Path(id): Path<String>) -> ApiResult<TrackMap> {
+    let lap = load(&laps, &id).await?;
+    blocking(move || analysis::build_track_map(&lap)).await
+}
+
+async fn live(State(laps): State<Laps>) -> Json<Vec<LiveView>> {
+    Json(laps.live())
+}
+
+#[derive(Deserialize)]
+struct SourceQuery {
+    source: String,
+}
+
+async fn live_track_map(State(laps): State<Laps>, Query(q): Query<SourceQuery>) -> ApiResult<TrackMap> {
+    // finding the map may build it from a lap read from the lap database
+    off_workers(move || {
+        let view = laps
+            .live()
+            .into_iter()
+            .find(|v| v.source == q.source)
+            .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("source {} is not running", q.source)))?;
+        laps.track_map(&view.game, &view.track)
+            .map(Json)
+            .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("no track map of {} yet", view.track)))
+    })
+    .await?
+}
+
+const OVERLAY: &str = include_str!("overlay.html");
+
+async fn overlay_page() -> Html<&'static str> {
+    Html(OVERLAY)
+}
+
+async fn metrics_text(State(laps): State<Laps>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics::render(&laps.metrics()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    struct Stub(Vec<Arc<Lap>>);
+
+    impl LapProvider for Stub {
+        fn laps(&self) -> Vec<LapInfo> {
+            self.0
+                .iter()
+                .map(|l| LapInfo { id: l.id, meta: l.meta.clone(), total_time_ms: l.total_time_ms, session_id: None })
+                .collect()
+        }
+
+        fn lap(&self, id: Uuid) -> Option<Arc<Lap>> {
+            self.0.iter().find(|l| l.id == id).cloned()
+        }
+    }
+
+    fn lap(track: &str, total_time_ms: u64) -> Arc<Lap> {
+        let points = (0..3)
+            .map(|i| TelemetryPoint {
+                t_ms: i as f64 * 100.0,
+                lap_distance_m: i as f64 * 5.0,
+                x: i as f64 * 5.0,
+                y: 0.0,
+                speed_kph: 180.0,
+                throttle: 1.0,
+                brake: 0.0,
+                gear: 6,
+                rpm: 11000.0,
+                yaw: 0.0,
+            })
+            .collect();
+        Arc::new(Lap {
+            id: Uuid::new_v4(),
+            meta: LapMeta {
+                id: Uuid::new_v4(),
+                game: "f1_2024".into(),
+                car: "player:0".into(),
+                track: track.into(),
+                lap_number: 2,
+                out_lap: false,
+                in_lap: false,
+            },
+            total_time_ms,
+            points,
+            markers: Vec::new(),
+            index: Default::default(),
+        })
+    }
+
+    async fn get(app: &Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        let res = app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+        let status = res.status();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn lists_laps_fastest_first() {
+        let laps = vec![lap("Monza", 82_000), lap("Spa", 105_000), lap("Monza", 81_500)];
+        let app = router(Arc::new(Stub(laps.clone())));
+
+        let (status, body) = get(&app, "/laps").await;
+        assert_eq!(status, StatusCode::OK);
+        let list: Vec<LapInfo> = serde_json::from_value(body).unwrap();
+        assert_eq!(list.iter().map(|l| l.id).collect::<Vec<_>>(), [laps[2].id, laps[0].id, laps[1].id]);
+
+        let (_, body) = get(&app, "/laps?track=monza").await;
+        let list: Vec<LapInfo> = serde_json::from_value(body).unwrap();
+        assert_eq!(list.iter().map(|l| l.total_time_ms).collect::<Vec<_>>(), [81_500, 82_000]);
+    }
+
+    #[tokio::test]
+    async fn fetches_a_lap_by_id() {
+        let laps = vec![lap("Monza", 82_000), lap("Spa", 105_000)];
+        let app = router(Arc::new(Stub(laps.clone())));
+        let want = serde_json::to_value(&*laps[1]).unwrap();
+        // with or without hyphens
+        for id in [laps[1].id.simple().to_string(), laps[1].id.hyphenated().to_string()] {
+            let (status, body) = get(&app, &format!("/laps/{id}")).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, want);
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_and_malformed_ids_are_errors() {
+        let app = router(Arc::new(Stub(vec![lap("Monza", 82_000)])));
+        let missing = Uuid::new_v4();
+        let (status, body) = get(&app, &format!("/laps/{missing}")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], format!("lap {missing} not found"));
+
+        let (status, body) = get(&app, "/laps/not-a-lap").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("bad lap id"));
+
+        let (status, _) = get(&app, &format!("/analysis/summary?laps={missing}")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        // nothing running
+        let (status, _) = get(&app, "/live/trackmap?source=f1").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,259 @@
+//! Live data over a serial/USB port for DIY dashboards and shift lights
+//! (Arduino, ESP32 and the like).
+//!
+//! Every frame is 16 bytes, multi-byte values little-endian:
+//!
+//! | byte  | value                                                        |
+//! |-------|--------------------------------------------------------------|
+//! | 0     | start byte `0xD7`                                            |
+//! | 1     | protocol version, 1                                          |
+//! | 2-3   | speed, km/h × 10 (u16)                                       |
+//! | 4     | gear (i8: -1 reverse, 0 neutral)                             |
+//! | 5-6   | rpm (u16)                                                    |
+//! | 7-8   | highest rpm seen from the source, for shift lights (u16)     |
+//! | 9-10  | delta to the session best, ms (i16; `-32768` when none)      |
+//! | 11    | throttle, 0-255                                              |
+//! | 12    | brake, 0-255                                                 |
+//! | 13    | lap number (u8, wraps)                                       |
+//! | 14    | flags, see [`flags`]                                         |
+//! | 15    | XOR of bytes 1-14                                            |
+//!
+//! A reader syncs by waiting for `0xD7` and dropping frames whose checksum
+//! doesn't match.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use analysis::live::LiveDeltaState;
+use delta_ingest_core::TelemetrySample;
+
+pub const START: u8 = 0xD7;
+pub const VERSION: u8 = 1;
+pub const FRAME_LEN: usize = 16;
+
+/// Bits of the flags byte.
+pub mod flags {
+    /// The delta bytes hold a delta.
+    pub const DELTA: u8 = 1 << 0;
+    /// Rpm is past the shift point.
+    pub const SHIFT: u8 = 1 << 1;
+    /// A lap was just completed (held for a second).
+    pub const LAP: u8 = 1 << 2;
+    /// The lap just completed was a new session best (held for a second).
+    pub const BEST: u8 = 1 << 3;
+    /// Lap recording is paused.
+    pub const PAUSED: u8 = 1 << 4;
+}
+
+// how long lap flags stay set, so a slow reader still sees them
+const FLASH: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct SerialOptions {
+    /// `COM3`, `/dev/ttyUSB0`, `/dev/cu.usbserial-…`
+    pub port: String,
+    pub baud: u32,
+    /// Frames per second.
+    pub rate_hz: f64,
+    /// Share of the highest rpm seen at which the shift flag comes on.
+    pub shift_at: f64,
+    /// Only send this source's data; every source when unset.
+    pub source: Option<String>,
+}
+
+impl Default for SerialOptions {
+    fn default() -> Self {
+        Self { port: String::new(), baud: 115_200, rate_hz: 30.0, shift_at: 0.95, source: None }
+    }
+}
+
+/// A serial port found on this machine.
+#[derive(Clone, Serialize, Debug)]
+pub struct SerialPortInfo {
+    pub name: String,
+    /// USB product or manufacturer, when known.
+    pub description: Option<String>,
+}
+
+pub fn list_ports() -> Result<Vec<SerialPortInfo>> {
+    let ports = serialport::available_ports().context("list serial ports")?;
+    Ok(ports
+        .into_iter()
+        .map(|p| SerialPortInfo {
+            name: p.port_name,
+            description: match p.port_type {
+                serialport::SerialPortType::UsbPort(usb) => usb.product.or(usb.manufacturer),
+                _ => None,
+            },
+        })
+        .collect())
+}
+
+/// An open port frames are written to.
+pub struct SerialSink {
+    opts: SerialOptions,
+    port: Box<dyn serialport::SerialPort>,
+    sources: HashMap<String, SourceState>,
+}
+
+#[derive(Default)]
+struct SourceState {
+    max_rpm: f32,
+    sent: Option<Instant>,
+    lap_until: Option<Instant>,
+    best_until: Option<Instant>,
+}
+
+impl SerialSink {
+    pub fn open(opts: &SerialOptions) -> Result<Self> {
+        let port = serialport::new(&opts.port, opts.baud)
+            // a stalled device must not hold up ingest for long
+            .timeout(Duration::from_millis(20))
+            .open()
+            .with_context(|| format!("open serial port {}", opts.port))?;
+        Ok(Self { opts: opts.clone(), port, sources: HashMap::new() })
+    }
+
+    pub fn options(&self) -> &SerialOptions {
+        &self.opts
+    }
+
+    /// Raise the lap flags of `source` for the next second.
+    pub fn lap_completed(&mut self, source: &str, best: bool) {
+        let until = Some(Instant::now() + FLASH);
+        let state = self.sources.entry(source.to_string()).or_default();
+        state.lap_until = until;
+        if best {
+            state.best_until = until;
+        }
+    }
+
+    /// Take a sample of `source`, writing a frame when one is due. Fails
+    /// when the port can't be written, e.g. the device was unplugged.
+    pub fn send(&mut self, source: &str, s: &TelemetrySample, delta: Option<&LiveDeltaState>, paused: bool) -> Result<()> {
+        if self.opts.source.as_deref().is_some_and(|want| want != source) {
+            return Ok(());
+        }
+        let state = self.sources.entry(source.to_string()).or_default();
+        state.max_rpm = state.max_rpm.max(s.engine_rpm);
+        let now = Instant::now();
+        let due = self.opts.rate_hz > 0.0
+            && state.sent.is_none_or(|t| now.duration_since(t) >= Duration::from_secs_f64(1.0 / self.opts.rate_hz));
+        if !due {
+            return Ok(());
+        }
+        state.sent = Some(now);
+
+        let delta_ms = delta.and_then(|d| d.delta_ms);
+        let mut f = 0;
+        let mut set = |bit, on| {
+            if on {
+                f |= bit;
+            }
+        };
+        set(flags::DELTA, delta_ms.is_some());
+        set(flags::SHIFT, state.max_rpm > 0.0 && s.engine_rpm as f64 >= state.max_rpm as f64 * self.opts.shift_at);
+        set(flags::LAP, state.lap_until.is_some_and(|t| now < t));
+        set(flags::BEST, state.best_until.is_some_and(|t| now < t));
+        set(flags::PAUSED, paused);
+
+        let frame = Frame {
+            speed_kph: s.speed_mps * 3.6,
+            gear: s.gear,
+            rpm: s.engine_rpm,
+            max_rpm: state.max_rpm,
+            delta_ms,
+            throttle: s.throttle,
+            brake: s.brake,
+            lap: s.current_lap,
+            flags: f,
+        };
+        self.port.write_all(&frame.encode()).with_context(|| format!("write to {}", self.opts.port))
+    }
+}
+
+struct Frame {
+    speed_kph: f32,
+    gear: i8,
+    rpm: f32,
+    max_rpm: f32,
+    delta_ms: Option<f64>,
+    throttle: f32,
+    brake: f32,
+    lap: u32,
+    flags: u8,
+}
+
+impl Frame {
+    fn encode(&self) -> [u8; FRAME_LEN] {
+        // `as` saturates, which is what a dash wants for out-of-range values
+        let unit = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let mut b = [0u8; FRAME_LEN];
+        b[0] = START;
+        b[1] = VERSION;
+        b[2..4].copy_from_slice(&((self.speed_kph * 10.0).round() as u16).to_le_bytes());
+        b[4] = self.gear as u8;
+        b[5..7].copy_from_slice(&(self.rpm.round() as u16).to_le_bytes());
+        b[7..9].copy_from_slice(&(self.max_rpm.round() as u16).to_le_bytes());
+        // i16::MIN is "none", so real deltas stop one short of it
+        let delta = self.delta_ms.map_or(i16::MIN, |d| d.round().max(-32767.0) as i16);
+        b[9..11].copy_from_slice(&delta.to_le_bytes());
+        b[11] = unit(self.throttle);
+        b[12] = unit(self.brake);
+        b[13] = self.lap as u8;
+        b[14] = self.flags;
+        b[15] = b[1..15].iter().fold(0, |x, v| x ^ v);
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> Frame {
+        Frame {
+            speed_kph: 287.46,
+            gear: 7,
+            rpm: 11_250.4,
+            max_rpm: 12_000.0,
+            delta_ms: Some(-123.6),
+            throttle: 1.0,
+            brake: 0.2,
+            lap: 3,
+            flags: flags::DELTA | flags::LAP,
+        }
+    }
+
+    #[test]
+    fn lays_out_a_frame() {
+        let b = frame().encode();
+        assert_eq!(b[..2], [START, VERSION]);
+        assert_eq!(u16::from_le_bytes([b[2], b[3]]), 2875);
+        assert_eq!(b[4], 7);
+        assert_eq!(u16::from_le_bytes([b[5], b[6]]), 11_250);
+        assert_eq!(u16::from_le_bytes([b[7], b[8]]), 12_000);
+        assert_eq!(i16::from_le_bytes([b[9], b[10]]), -124);
+        assert_eq!(b[11..15], [255, 51, 3, flags::DELTA | flags::LAP]);
+        assert_eq!(b[15], b[1..15].iter().fold(0, |x, v| x ^ v));
+    }
+
+    #[test]
+    fn out_of_range_values_saturate() {
+        let b = Frame { gear: -1, delta_ms: None, throttle: 1.5, brake: -0.5, lap: 300, ..frame() }.encode();
+        assert_eq!(b[4], 0xFF);
+        assert_eq!(i16::from_le_bytes([b[9], b[10]]), i16::MIN);
+        assert_eq!(b[11..14], [255, 0, 44]);
+        // a real delta never reads as none
+        let b = Frame { delta_ms: Some(-40_000.0), ..frame() }.encode();
+        assert_eq!(i16::from_le_bytes([b[9], b[10]]), -32767);
+        let b = Frame { delta_ms: Some(40_000.0), ..frame() }.encode();
+        assert_eq!(i16::from_le_bytes([b[9], b[10]]), i16::MAX);
+    }
+}
@@ -0,0 +1,122 @@
+//! WebSocket feed of live Delta data on the LAN for custom dashboards,
+//! tablets and stream overlays.
+//!
+//! Every connected client gets every message as a JSON text frame:
+//! `{"type": "<kind>", "data": <payload>}`. The server only broadcasts;
+//! whatever clients send is ignored apart from close frames. A client that
+//! falls behind skips messages rather than slowing the others down.
+
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::broadcast::{self, error::RecvError},
+    task::JoinHandle,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+pub const DEFAULT_PORT: u16 = 8765;
+
+/// Messages buffered per client before a slow one starts skipping; about
+/// four seconds of samples at the default live rate.
+const BACKLOG: usize = 256;
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    data: &'a T,
+}
+
+/// A running server; stops when dropped.
+pub struct TelemetryServer {
+    addr: SocketAddr,
+    tx: broadcast::Sender<Arc<str>>,
+    clients: Arc<AtomicUsize>,
+    task: JoinHandle<()>,
+}
+
+impl TelemetryServer {
+    /// Listen on `addr` (`0.0.0.0:8765` for the whole LAN). Must be called
+    /// inside a tokio runtime.
+    pub async fn bind(addr: &str) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await.with_context(|| format!("bind {addr}"))?;
+        let addr = listener.local_addr()?;
+        let (tx, _) = broadcast::channel(BACKLOG);
+        let clients = Arc::new(AtomicUsize::new(0));
+        let task = tokio::spawn(accept_loop(listener, tx.clone(), clients.clone()));
+        Ok(Self { addr, tx, clients, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Clients connected right now.
+    pub fn clients(&self) -> usize {
+        self.clients.load(Ordering::Relaxed)
+    }
+
+    /// Send `data` to every connected client as a `kind` message. Cheap when
+    /// nobody is connected.
+    pub fn publish<T: Serialize>(&self, kind: &str, data: &T) -> Result<()> {
+        if self.tx.receiver_count() == 0 {
+            return Ok(());
+        }
+        let text = serde_json::to_string(&Envelope { kind, data })?;
+        // only fails when every client left in the meantime
+        let _ = self.tx.send(text.into());
+        Ok(())
+    }
+}
+
+impl Drop for TelemetryServer {
+    fn drop(&mut self) {
+        // client tasks end once the sender is gone
+        self.task.abort();
+    }
+}
+
+async fn accept_loop(listener: TcpListener, tx: broadcast::Sender<Arc<str>>, clients: Arc<AtomicUsize>) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else { continue };
+        let rx = tx.subscribe();
+        let clients = clients.clone();
+        tokio::spawn(async move {
+            clients.fetch_add(1, Ordering::Relaxed);
+            // a failed handshake or a dropped connection just ends the client
+            let _ = serve_client(stream, rx).await;
+            clients.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+async fn serve_client(stream: TcpStream, mut rx: broadcast::Receiver<Arc<str>>) -> Result<()> {
+    let _ = stream.set_nodelay(true);
+    let ws = tokio_tungstenite::accept_async(stream).await?;
+    let (mut sink, mut incoming) = ws.split();
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Ok(text) => sink.send(Message::text(text.as_ref())).await?,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            msg = incoming.next() => match msg {
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            },
+        }
+    }
+    let _ = sink.close().await;
+    Ok(())
+}
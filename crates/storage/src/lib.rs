@@ -0,0 +1,621 @@
+//! Persistent lap database backed by SQLite.
+//!
+//! Laps, their points, sessions, tags, comparison sets and cached track maps
+//! live in one file. Metadata queries
+//! (by track, car, game, date or tag) only touch the indexed `laps` table;
+//! points are read when a lap is actually loaded.
+
+use anyhow::{anyhow, Result};
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+use model::*;
+
+const SCHEMA_VERSION: i32 = 6;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id          TEXT PRIMARY KEY,
+    name        TEXT NOT NULL,
+    game        TEXT NOT NULL,
+    car         TEXT NOT NULL,
+    track       TEXT NOT NULL,
+    started_at  INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS laps (
+    id            TEXT PRIMARY KEY,
+    meta_id       TEXT NOT NULL,
+    session_id    TEXT REFERENCES sessions(id) ON DELETE SET NULL,
+    game          TEXT NOT NULL,
+    car           TEXT NOT NULL,
+    track         TEXT NOT NULL,
+    lap_number    INTEGER NOT NULL,
+    total_time_ms INTEGER NOT NULL,
+    recorded_at   INTEGER NOT NULL,
+    markers       TEXT NOT NULL DEFAULT '[]',
+    driver        TEXT NOT NULL DEFAULT '',
+    out_lap       INTEGER NOT NULL DEFAULT 0,
+    in_lap        INTEGER NOT NULL DEFAULT 0,
+    samples_hash  INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS laps_track ON laps(track, total_time_ms);
+CREATE INDEX IF NOT EXISTS laps_car ON laps(car);
+CREATE INDEX IF NOT EXISTS laps_recorded ON laps(recorded_at);
+CREATE INDEX IF NOT EXISTS laps_session ON laps(session_id);
+CREATE TABLE IF NOT EXISTS points (
+    lap_id         TEXT NOT NULL REFERENCES laps(id) ON DELETE CASCADE,
+    seq            INTEGER NOT NULL,
+    t_ms           REAL NOT NULL,
+    lap_distance_m REAL NOT NULL,
+    x              REAL NOT NULL,
+    y              REAL NOT NULL,
+    speed_kph      REAL NOT NULL,
+    throttle       REAL NOT NULL,
+    brake          REAL NOT NULL,
+    gear           INTEGER NOT NULL,
+    rpm            REAL NOT NULL,
+    yaw            REAL NOT NULL,
+    PRIMARY KEY (lap_id, seq)
+) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS tags (
+    lap_id TEXT NOT NULL REFERENCES laps(id) ON DELETE CASCADE,
+    tag    TEXT NOT NULL,
+    PRIMARY KEY (lap_id, tag)
+);
+CREATE INDEX IF NOT EXISTS tags_tag ON tags(tag);
+CREATE TABLE IF NOT EXISTS track_maps (
+    game        TEXT NOT NULL,
+    track       TEXT NOT NULL,
+    lap_id      TEXT NOT NULL,
+    lap_time_ms INTEGER NOT NULL,
+    updated_at  INTEGER NOT NULL,
+    map         TEXT NOT NULL,
+    PRIMARY KEY (game, track)
+);
+CREATE TABLE IF NOT EXISTS comparison_sets (
+    workspace    TEXT NOT NULL,
+    name         TEXT NOT NULL,
+    lap_ids      TEXT NOT NULL,
+    reference_id TEXT,
+    created_at   INTEGER NOT NULL,
+    PRIMARY KEY (workspace, name)
+);
+";
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct StoredSession {
+    pub id: Uuid,
+    pub name: String,
+    pub game: String,
+    pub car: String,
+    pub track: String,
+    /// Unix seconds.
+    pub started_at: i64,
+}
+
+/// Track map kept for a game and track, and the lap it was built from. The
+/// lap may since have been deleted.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct StoredTrackMap {
+    pub game: String,
+    pub track: String,
+    pub lap_id: Uuid,
+    pub lap_time_ms: u64,
+    /// Unix seconds.
+    pub updated_at: i64,
+    pub map: TrackMap,
+}
+
+/// A lap's metadata without its points.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LapSummary {
+    pub id: Uuid,
+    pub meta: LapMeta,
+    pub total_time_ms: u64,
+    pub session_id: Option<Uuid>,
+    /// Unix seconds.
+    pub recorded_at: i64,
+    /// Empty when not known.
+    pub driver: String,
+    pub tags: Vec<String>,
+    /// [`Lap::samples_hash`] of the stored points; with `meta` it gives the
+    /// lap's [`Lap::content_hash`] without loading them.
+    pub samples_hash: u64,
+}
+
+/// Filters for [`LapStore::query`]; unset fields match everything.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct LapQuery {
+    pub game: Option<String>,
+    pub car: Option<String>,
+    pub track: Option<String>,
+    pub session_id: Option<Uuid>,
+    pub tag: Option<String>,
+    /// Recorded at or after (unix seconds).
+    pub since: Option<i64>,
+    /// Recorded before (unix seconds).
+    pub until: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+pub struct LapStore {
+    conn: Connection,
+}
+
+impl LapStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")?;
+        let version: i32 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(anyhow!("lap database schema v{version} is newer than supported v{SCHEMA_VERSION}"));
+        }
+        conn.execute_batch(SCHEMA)?;
+        if version == 1 {
+            // v2: driver name per lap
+            conn.execute_batch("ALTER TABLE laps ADD COLUMN driver TEXT NOT NULL DEFAULT ''")?;
+        }
+        // v3 and v4 only added the track_maps and comparison_sets tables, created above
+        if (1..5).contains(&version) {
+            // v5: out-lap and in-lap flags
+            conn.execute_batch(
+                "ALTER TABLE laps ADD COLUMN out_lap INTEGER NOT NULL DEFAULT 0;
+                 ALTER TABLE laps ADD COLUMN in_lap INTEGER NOT NULL DEFAULT 0;",
+            )?;
+        }
+        let backfill = (1..6).contains(&version);
+        if backfill {
+            // v6: hash of each lap's points
+            conn.execute_batch("ALTER TABLE laps ADD COLUMN samples_hash INTEGER NOT NULL DEFAULT 0")?;
+        }
+        let store = Self { conn };
+        if backfill {
+            for s in store.query(&LapQuery::default())? {
+                if let Some(lap) = store.lap(s.id)? {
+                    store.conn.execute(
+                        "UPDATE laps SET samples_hash = ?2 WHERE id = ?1",
+                        params![key(&s.id), lap.samples_hash() as i64],
+                    )?;
+                }
+            }
+        }
+        store.conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(store)
+    }
+
+    pub fn upsert_session(&self, s: &StoredSession) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO sessions (id, name, game, car, track, started_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(id) DO UPDATE SET name = ?2, game = ?3, car = ?4, track = ?5, started_at = ?6",
+            params![key(&s.id), s.name, s.game, s.car, s.track, s.started_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn sessions(&self) -> Result<Vec<StoredSession>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, name, game, car, track, started_at FROM sessions ORDER BY started_at DESC")?;
+        let rows = stmt.query_map([], |r| {
+            Ok(StoredSession {
+                id: uuid_col(r, 0)?,
+                name: r.get(1)?,
+                game: r.get(2)?,
+                car: r.get(3)?,
+                track: r.get(4)?,
+                started_at: r.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Insert or replace a lap with all of its points.
+    pub fn insert_lap(&mut self, lap: &Lap, session_id: Option<Uuid>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        let id = key(&lap.id);
+        // keep the original recording time and tags when a lap is re-saved
+        let recorded_at: i64 = tx
+            .query_row("SELECT recorded_at FROM laps WHERE id = ?1", [&id], |r| r.get(0))
+            .optional()?
+            .unwrap_or_else(now);
+        tx.execute(
+            "INSERT INTO laps (id, meta_id, session_id, game, car, track, lap_number, total_time_ms, recorded_at, markers, out_lap, in_lap, samples_hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+             ON CONFLICT(id) DO UPDATE SET meta_id = ?2, session_id = ?3, game = ?4, car = ?5, track = ?6,
+                 lap_number = ?7, total_time_ms = ?8, markers = ?10, out_lap = ?11, in_lap = ?12, samples_hash = ?13",
+            params![
+                id,
+                key(&lap.meta.id),
+                session_id.as_ref().map(key),
+                lap.meta.game,
+                lap.meta.car,
+                lap.meta.track,
+                lap.meta.lap_number,
+                lap.total_time_ms as i64,
+                recorded_at,
+                serde_json::to_string(&lap.markers)?,
+                lap.meta.out_lap,
+                lap.meta.in_lap,
+                lap.samples_hash() as i64,
+            ],
+        )?;
+        tx.execute("DELETE FROM points WHERE lap_id = ?1", [&id])?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO points (lap_id, seq, t_ms, lap_distance_m, x, y, speed_kph, throttle, brake, gear, rpm, yaw)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            )?;
+            for (seq, p) in lap.points.iter().enumerate() {
+                stmt.execute(params![
+                    id,
+                    seq as i64,
+                    p.t_ms,
+                    p.lap_distance_m,
+                    p.x,
+                    p.y,
+                    p.speed_kph,
+                    p.throttle,
+                    p.brake,
+                    p.gear,
+                    p.rpm,
+                    p.yaw
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn lap(&self, id: Uuid) -> Result<Option<Lap>> {
+        let Some(summary) = self.summary(id)? else {
+            return Ok(None);
+        };
+        let markers: String =
+            self.conn.query_row("SELECT markers FROM laps WHERE id = ?1", [key(&id)], |r| r.get(0))?;
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT t_ms, lap_distance_m, x, y, speed_kph, throttle, brake, gear, rpm, yaw
+             FROM points WHERE lap_id = ?1 ORDER BY seq",
+        )?;
+        let points = stmt
+            .query_map([key(&id)], |r| {
+                Ok(TelemetryPoint {
+                    t_ms: r.get(0)?,
+                    lap_distance_m: r.get(1)?,
+                    x: r.get(2)?,
+                    y: r.get(3)?,
+                    speed_kph: r.get(4)?,
+                    throttle: r.get(5)?,
+                    brake: r.get(6)?,
+                    gear: r.get(7)?,
+                    rpm: r.get(8)?,
+                    yaw: r.get(9)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(Some(Lap {
+            id,
+            meta: summary.meta,
+            total_time_ms: summary.total_time_ms,
+            points,
+            markers: serde_json::from_str(&markers)?,
+            index: Default::default(),
+        }))
+    }
+
+    pub fn summary(&self, id: Uuid) -> Result<Option<LapSummary>> {
+        let mut stmt = self.conn.prepare_cached(&format!("{SUMMARY_SELECT} WHERE id = ?1"))?;
+        let summary = stmt.query_row([key(&id)], summary_row).optional()?;
+        summary.map(|s| self.with_tags(s)).transpose()
+    }
+
+    /// Lap metadata matching `q`, fastest first.
+    pub fn query(&self, q: &LapQuery) -> Result<Vec<LapSummary>> {
+        let mut clauses: Vec<String> = Vec::new();
+        let mut args: Vec<Value> = Vec::new();
+        let mut filter = |clause: &str, v: Value| {
+            args.push(v);
+            clauses.push(clause.replace('?', &format!("?{}", args.len())));
+        };
+        if let Some(g) = &q.game {
+            filter("game = ?", Value::Text(g.clone()));
+        }
+        if let Some(c) = &q.car {
+            filter("car = ?", Value::Text(c.clone()));
+        }
+        if let Some(t) = &q.track {
+            filter("track = ?", Value::Text(t.clone()));
+        }
+        if let Some(s) = &q.session_id {
+            filter("session_id = ?", Value::Text(key(s)));
+        }
+        if let Some(t) = &q.tag {
+            filter("id IN (SELECT lap_id FROM tags WHERE tag = ?)", Value::Text(t.clone()));
+        }
+        if let Some(s) = q.since {
+            filter("recorded_at >= ?", Value::Integer(s));
+        }
+        if let Some(u) = q.until {
+            filter("recorded_at < ?", Value::Integer(u));
+        }
+
+        let mut sql = SUMMARY_SELECT.to_string();
+        if !clauses.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&clauses.join(" AND "));
+        }
+        sql.push_str(" ORDER BY total_time_ms, recorded_at");
+        if let Some(n) = q.limit {
+            sql.push_str(&format!(" LIMIT {n}"));
+        }
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt
+            .query_map(params_from_iter(args), summary_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter().map(|s| self.with_tags(s)).collect()
+    }
+
+    /// Every lap matching `q`, points included.
+    pub fn load_laps(&self, q: &LapQuery) -> Result<Vec<Lap>> {
+        self.query(q)?
+            .into_iter()
+            .filter_map(|s| self.lap(s.id).transpose())
+            .collect()
+    }
+
+    /// Change game, car, track, lap number and out-lap and in-lap flags of a
+    /// stored lap without rewriting its points; false if there is no such lap.
+    pub fn update_meta(&self, id: Uuid, meta: &LapMeta) -> Result<bool> {
+        let n = self.conn.execute(
+            "UPDATE laps SET game = ?2, car = ?3, track = ?4, lap_number = ?5, out_lap = ?6, in_lap = ?7 WHERE id = ?1",
+            params![key(&id), meta.game, meta.car, meta.track, meta.lap_number, meta.out_lap, meta.in_lap],
+        )?;
+        Ok(n > 0)
+    }
+
+    pub fn set_driver(&self, id: Uuid, driver: &str) -> Result<bool> {
+        Ok(self.conn.execute("UPDATE laps SET driver = ?2 WHERE id = ?1", params![key(&id), driver])? > 0)
+    }
+
+    pub fn delete_lap(&self, id: Uuid) -> Result<bool> {
+        Ok(self.conn.execute("DELETE FROM laps WHERE id = ?1", [key(&id)])? > 0)
+    }
+
+    pub fn add_tag(&self, id: Uuid, tag: &str) -> Result<()> {
+        self.conn
+            .execute("INSERT OR IGNORE INTO tags (lap_id, tag) VALUES (?1, ?2)", params![key(&id), tag])?;
+        Ok(())
+    }
+
+    pub fn remove_tag(&self, id: Uuid, tag: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM tags WHERE lap_id = ?1 AND tag = ?2", params![key(&id), tag])?;
+        Ok(())
+    }
+
+    /// Replace every tag of a lap with `tags`.
+    pub fn set_tags(&mut self, id: Uuid, tags: &[String]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM tags WHERE lap_id = ?1", [key(&id)])?;
+        for t in tags {
+            tx.execute("INSERT OR IGNORE INTO tags (lap_id, tag) VALUES (?1, ?2)", params![key(&id), t])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn tags(&self, id: Uuid) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare_cached("SELECT tag FROM tags WHERE lap_id = ?1 ORDER BY tag")?;
+        let tags = stmt.query_map([key(&id)], |r| r.get(0))?;
+        Ok(tags.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Save `set` in `workspace` ("" for none), replacing one of the same name.
+    pub fn put_comparison_set(&self, workspace: &str, set: &ComparisonSet) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO comparison_sets (workspace, name, lap_ids, reference_id, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                workspace,
+                set.name,
+                serde_json::to_string(&set.lap_ids)?,
+                set.reference_id.as_ref().map(key),
+                set.created_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Comparison sets of `workspace`, by name.
+    pub fn comparison_sets(&self, workspace: &str) -> Result<Vec<ComparisonSet>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT name, lap_ids, reference_id, created_at FROM comparison_sets WHERE workspace = ?1 ORDER BY name",
+        )?;
+        let rows = stmt.query_map([workspace], |r| {
+            let reference_id = r.get::<_, Option<String>>(2)?.map(|s| parse_uuid(2, &s)).transpose()?;
+            Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, reference_id, r.get::<_, i64>(3)?))
+        })?;
+        rows.map(|r| {
+            let (name, ids, reference_id, created_at) = r?;
+            Ok(ComparisonSet { name, lap_ids: serde_json::from_str(&ids)?, reference_id, created_at })
+        })
+        .collect()
+    }
+
+    pub fn delete_comparison_set(&self, workspace: &str, name: &str) -> Result<bool> {
+        Ok(self
+            .conn
+            .execute("DELETE FROM comparison_sets WHERE workspace = ?1 AND name = ?2", params![workspace, name])?
+            > 0)
+    }
+
+    /// Cached map of `track` in `game`.
+    pub fn track_map(&self, game: &str, track: &str) -> Result<Option<StoredTrackMap>> {
+        self.conn
+            .query_row(
+                "SELECT game, track, lap_id, lap_time_ms, updated_at, map FROM track_maps WHERE game = ?1 AND track = ?2",
+                params![game, track],
+                track_map_row,
+            )
+            .optional()?
+            .transpose()
+    }
+
+    /// Every cached map, most recently updated first.
+    pub fn track_maps(&self) -> Result<Vec<StoredTrackMap>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT game, track, lap_id, lap_time_ms, updated_at, map FROM track_maps ORDER BY updated_at DESC",
+        )?;
+        let rows = stmt.query_map([], track_map_row)?;
+        rows.map(|r| r?).collect()
+    }
+
+    pub fn put_track_map(&self, m: &StoredTrackMap) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO track_maps (game, track, lap_id, lap_time_ms, updated_at, map)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![m.game, m.track, key(&m.lap_id), m.lap_time_ms as i64, m.updated_at, serde_json::to_string(&m.map)?],
+        )?;
+        Ok(())
+    }
+
+    fn with_tags(&self, mut s: LapSummary) -> Result<LapSummary> {
+        s.tags = self.tags(s.id)?;
+        Ok(s)
+    }
+}
+
+const SUMMARY_SELECT: &str =
+    "SELECT id, meta_id, game, car, track, lap_number, total_time_ms, session_id, recorded_at, driver, out_lap, in_lap, samples_hash FROM laps";
+
+fn summary_row(r: &Row) -> rusqlite::Result<LapSummary> {
+    Ok(LapSummary {
+        id: uuid_col(r, 0)?,
+        meta: LapMeta {
+            id: uuid_col(r, 1)?,
+            game: r.get(2)?,
+            car: r.get(3)?,
+            track: r.get(4)?,
+            lap_number: r.get(5)?,
+            out_lap: r.get(10)?,
+            in_lap: r.get(11)?,
+        },
+        total_time_ms: r.get::<_, i64>(6)?.max(0) as u64,
+        session_id: r
+            .get::<_, Option<String>>(7)?
+            .map(|s| parse_uuid(7, &s))
+            .transpose()?,
+        recorded_at: r.get(8)?,
+        driver: r.get(9)?,
+        tags: Vec::new(),
+        samples_hash: r.get::<_, i64>(12)? as u64,
+    })
+}
+
+/// The map column is decoded separately so a bad map is an error of its own
+/// rather than a failed query.
+fn track_map_row(r: &Row) -> rusqlite::Result<Result<StoredTrackMap>> {
+    let (game, track, lap_id, lap_time_ms, updated_at): (String, String, Uuid, i64, i64) =
+        (r.get(0)?, r.get(1)?, uuid_col(r, 2)?, r.get(3)?, r.get(4)?);
+    let map: String = r.get(5)?;
+    Ok(serde_json::from_str(&map)
+        .map(|map| StoredTrackMap { game, track, lap_id, lap_time_ms: lap_time_ms.max(0) as u64, updated_at, map })
+        .map_err(Into::into))
+}
+
+fn key(id: &Uuid) -> String {
+    id.simple().to_string()
+}
+
+fn uuid_col(r: &Row, idx: usize) -> rusqlite::Result<Uuid> {
+    parse_uuid(idx, &r.get::<_, String>(idx)?)
+}
+
+fn parse_uuid(idx: usize, s: &str) -> rusqlite::Result<Uuid> {
+    Uuid::parse_str(s)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lap(n: usize) -> Lap {
+        let points = (0..n)
+            .map(|i| TelemetryPoint {
+                t_ms: i as f64 * 100.0,
+                lap_distance_m: i as f64 * 10.0,
+                x: i as f64 * 10.0,
+                y: 1.5,
+                speed_kph: 120.0 + i as f64,
+                throttle: 0.5,
+                brake: 0.0,
+                gear: 4,
+                rpm: 8000.0,
+                yaw: 0.25,
+            })
+            .collect();
+        Lap {
+            id: Uuid::new_v4(),
+            meta: LapMeta {
+                id: Uuid::new_v4(),
+                game: "F1_2024".into(),
+                car: "car".into(),
+                track: "Monza".into(),
+                lap_number: 3,
+                out_lap: false,
+                in_lap: true,
+            },
+            total_time_ms: n as u64 * 100,
+            points,
+            markers: Vec::new(),
+            index: Default::default(),
+        }
+    }
+
+    #[test]
+    fn lap_round_trip_keeps_points_and_hash() {
+        let mut store = LapStore::open_in_memory().unwrap();
+        let l = lap(25);
+        store.insert_lap(&l, None).unwrap();
+        assert_eq!(store.lap(l.id).unwrap().unwrap(), l);
+
+        let s = store.summary(l.id).unwrap().unwrap();
+        assert_eq!(content_hash(&s.meta, s.samples_hash), l.content_hash());
+    }
+
+    #[test]
+    fn migration_fills_in_samples_hashes() {
+        let path = std::env::temp_dir().join(format!("delta-storage-test-{}.db", Uuid::new_v4().simple()));
+        let l = lap(10);
+        {
+            let mut store = LapStore::open(&path).unwrap();
+            store.insert_lap(&l, None).unwrap();
+            // back to how a v5 database has it
+            store.conn.execute_batch("ALTER TABLE laps DROP COLUMN samples_hash; PRAGMA user_version = 5;").unwrap();
+        }
+        let summary = LapStore::open(&path).and_then(|s| s.summary(l.id));
+        for ext in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{ext}", path.display()));
+        }
+        assert_eq!(summary.unwrap().unwrap().samples_hash, l.samples_hash());
+    }
+}